@@ -0,0 +1,62 @@
+//! Benchmarks for walking generated documents. Run with `cargo bench`.
+
+#[macro_use]
+extern crate criterion;
+extern crate edit_client;
+
+use criterion::Criterion;
+use edit_client::random::{
+    random_doc,
+    RandomDocParams,
+};
+use edit_client::walkers::Walker;
+
+const DOC_SIZES: &[usize] = &[10, 100, 1_000];
+
+fn bench_walker_traversal(c: &mut Criterion) {
+    c.bench_function_over_inputs(
+        "walker_goto_end",
+        |b, &&size| {
+            let doc = random_doc(
+                1,
+                &RandomDocParams {
+                    blocks: size,
+                    ..RandomDocParams::default()
+                },
+            );
+            b.iter(|| {
+                let mut walker = Walker::new(&doc);
+                walker.goto_end();
+                walker.caret_pos()
+            })
+        },
+        DOC_SIZES,
+    );
+}
+
+fn bench_walker_next_block(c: &mut Criterion) {
+    c.bench_function_over_inputs(
+        "walker_next_block",
+        |b, &&size| {
+            let doc = random_doc(
+                1,
+                &RandomDocParams {
+                    blocks: size,
+                    ..RandomDocParams::default()
+                },
+            );
+            b.iter(|| {
+                let mut walker = Walker::new(&doc);
+                let mut count = 0;
+                while walker.next_block() {
+                    count += 1;
+                }
+                count
+            })
+        },
+        DOC_SIZES,
+    );
+}
+
+criterion_group!(benches, bench_walker_traversal, bench_walker_next_block);
+criterion_main!(benches);
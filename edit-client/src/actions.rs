@@ -1,18 +1,32 @@
+use super::positions::PositionIndex;
 use super::walkers::*;
+use super::words::*;
+use edit_common::block_ids::*;
+use edit_common::commands::FormatState;
+use edit_common::embed::embed_src;
+use edit_common::find::find_client_id;
+use edit_common::font::{
+    valid_font_family,
+    valid_font_size,
+};
+use edit_common::link::Link;
+use edit_common::outline::outline_positions;
 use failure::Error;
 use oatie::doc::*;
-use oatie::schema::RtfSchema;
+use oatie::schema::{
+    RtfSchema,
+    RtfTrack,
+};
+use oatie::Schema;
 use oatie::OT;
 
-fn is_boundary_char(c: char) -> bool {
-    c.is_whitespace() || c == '-' || c == '_'
-}
-
 // TODO don't require ActionContext to be owned everywhere
 #[derive(Clone)]
 pub struct ActionContext {
     pub doc: Doc,
     pub client_id: String,
+    // Index of caret positions in `doc`, if it's kept up to date.
+    pub positions: Option<PositionIndex>,
 }
 
 pub fn toggle_list(ctx: ActionContext) -> Result<Op, Error> {
@@ -51,6 +65,299 @@ pub fn toggle_list(ctx: ActionContext) -> Result<Op, Error> {
     Ok(writer.result())
 }
 
+// Whether our focus caret is inside this element.
+fn contains_focus_caret(elem: &DocElement, client_id: &str) -> bool {
+    match elem {
+        DocChars(_) => false,
+        DocGroup(attrs, span) => {
+            (is_own_caret(attrs, client_id) && attrs.get("focus").map(|x| x == "true").unwrap_or(false))
+                || span.iter().any(|child| contains_focus_caret(child, client_id))
+        }
+    }
+}
+
+/// Move the list item containing our caret up a level, after the item it
+/// was nested in. Later items at its old level move with it, as its
+/// children. An item at the top level leaves the list.
+pub fn list_outdent(ctx: ActionContext) -> Result<Op, Error> {
+    let mut item_walker = Walker::to_caret(&ctx.doc, &ctx.client_id, true);
+    assert!(item_walker.back_block());
+    if !item_walker.parent() {
+        return Ok(Op::empty());
+    }
+    let item_attrs = match item_walker.doc().head() {
+        Some(DocGroup(attrs, _)) => attrs,
+        _ => unreachable!(),
+    };
+    if item_attrs["tag"] != "bullet" {
+        return Ok(Op::empty());
+    }
+
+    let mut list_walker = item_walker.clone();
+    let (list_attrs, list_span) = match (list_walker.parent(), list_walker.doc().head()) {
+        (true, Some(DocGroup(attrs, span))) if attrs["tag"] == "bullet" => (attrs, span),
+        _ => return toggle_list(ctx),
+    };
+
+    // List items only contain groups, so each child skips one.
+    let index = list_span
+        .iter()
+        .position(|child| contains_focus_caret(child, &ctx.client_id))
+        .ok_or(format_err!("Expected our caret in the list"))?;
+    let item_len = match list_span[index] {
+        DocGroup(_, ref span) => span.skip_len(),
+        _ => unreachable!(),
+    };
+    let trailing = list_span.len() - index - 1;
+
+    let mut writer = list_walker.to_writer();
+
+    // Unwrap the outer item and the item itself...
+    let mut del = del_span![];
+    if index > 0 {
+        del.push(DelSkip(index));
+    }
+    del.push(DelGroup(del_span![DelSkip(item_len)]));
+    if trailing > 0 {
+        del.push(DelSkip(trailing));
+    }
+    writer.del.place(&DelGroup(del));
+    writer.del.exit_all();
+
+    // ...then rewrap the items before it, and the item with the items after.
+    if index > 0 {
+        writer.add.place(&AddGroup(list_attrs, add_span![AddSkip(index)]));
+    }
+    writer.add.place(&AddGroup(item_attrs, add_span![AddSkip(item_len + trailing)]));
+    writer.add.exit_all();
+
+    Ok(writer.result())
+}
+
+// Indexes of the groups leading from `span` down to the block holding our
+// focus caret. Above blocks, every element is a group.
+pub fn focus_block_path(span: &DocSpan, client_id: &str) -> Option<Vec<usize>> {
+    for (i, elem) in span.iter().enumerate() {
+        if let DocGroup(ref attrs, ref inner) = *elem {
+            if !contains_focus_caret(elem, client_id) {
+                continue;
+            }
+            if RtfSchema::track_type_from_attrs(attrs) == Some(RtfTrack::Blocks) {
+                return Some(vec![i]);
+            }
+            let mut path = focus_block_path(inner, client_id)?;
+            path.insert(0, i);
+            return Some(path);
+        }
+    }
+    None
+}
+
+/// Nest the list item containing our caret in the item before it, as its
+/// last child. The first item of a list can't be indented.
+pub fn list_indent(ctx: ActionContext) -> Result<Op, Error> {
+    let path = focus_block_path(&ctx.doc.0, &ctx.client_id)
+        .ok_or(format_err!("Expected our caret in a block"))?;
+    if path.len() < 2 {
+        return Ok(Op::empty());
+    }
+    let (parents, item_path) = path.split_at(path.len() - 2);
+    let index = item_path[0];
+
+    let span = span_at_path(&ctx.doc.0, parents);
+
+    match span[index] {
+        DocGroup(ref attrs, _) if attrs["tag"] == "bullet" => {}
+        _ => return Ok(Op::empty()),
+    }
+    let (prev_attrs, prev_len) = match index.checked_sub(1).map(|i| &span[i]) {
+        Some(DocGroup(attrs, inner)) if attrs["tag"] == "bullet" => {
+            (attrs.clone(), inner.skip_len())
+        }
+        _ => return Ok(Op::empty()),
+    };
+
+    // Unwrap the previous item, then wrap it again along with this one.
+    let mut del = del_span![];
+    let mut add = add_span![];
+    if index > 1 {
+        del.push(DelSkip(index - 1));
+        add.push(AddSkip(index - 1));
+    }
+    del.push(DelGroup(del_span![DelSkip(prev_len)]));
+    add.push(AddGroup(prev_attrs, add_span![AddSkip(prev_len + 1)]));
+
+    Ok(op_at_path(parents, (del, add)))
+}
+
+// The span found by following `path` down through groups.
+fn span_at_path<'a>(mut span: &'a DocSpan, path: &[usize]) -> &'a DocSpan {
+    for &i in path {
+        span = match span[i] {
+            DocGroup(_, ref inner) => inner,
+            _ => unreachable!(),
+        };
+    }
+    span
+}
+
+// Apply `op` to the span found by following `path` down from the root.
+fn op_at_path(path: &[usize], op: Op) -> Op {
+    let (mut del, mut add) = op;
+    for &i in path.iter().rev() {
+        let mut outer_del = del_span![];
+        let mut outer_add = add_span![];
+        if i > 0 {
+            outer_del.push(DelSkip(i));
+            outer_add.push(AddSkip(i));
+        }
+        outer_del.push(DelWithGroup(del));
+        outer_add.push(AddWithGroup(add));
+        del = outer_del;
+        add = outer_add;
+    }
+    (del, add)
+}
+
+// Whether our caret is in a list item holding only an empty block.
+fn in_empty_list_item(ctx: &ActionContext) -> bool {
+    let mut walker = Walker::to_caret(&ctx.doc, &ctx.client_id, true);
+    assert!(walker.back_block());
+    let block_empty = match walker.doc().head() {
+        Some(DocGroup(_, span)) => span.iter().all(|elem| match elem {
+            DocGroup(attrs, _) => is_any_caret(attrs),
+            DocChars(_) => false,
+        }),
+        _ => false,
+    };
+    block_empty && walker.parent() && match walker.doc().head() {
+        Some(DocGroup(attrs, span)) => attrs["tag"] == "bullet" && span.len() == 1,
+        _ => false,
+    }
+}
+
+fn is_definition_tag(tag: &str) -> bool {
+    tag == "dt" || tag == "dd"
+}
+
+// Whether the caret is in an empty term of a definition list.
+fn in_empty_term(ctx: &ActionContext) -> bool {
+    let mut walker = Walker::to_caret(&ctx.doc, &ctx.client_id, true);
+    assert!(walker.back_block());
+    match walker.doc().head() {
+        Some(DocGroup(attrs, span)) => attrs["tag"] == "dt" && span.iter().all(|elem| match elem {
+            DocGroup(attrs, _) => is_any_caret(attrs),
+            DocChars(_) => false,
+        }),
+        _ => false,
+    }
+}
+
+/// Tab key. In a list this indents the item; in a definition list it moves
+/// on from a term to its definition, or from a definition to the next
+/// term; elsewhere it starts a list.
+pub fn list_tab(ctx: ActionContext) -> Result<Op, Error> {
+    let (tag, in_list) = identify_block(ctx.clone())?;
+    if is_definition_tag(&tag) {
+        caret_block_move(ctx, true)
+    } else if in_list {
+        list_indent(ctx)
+    } else {
+        toggle_list(ctx)
+    }
+}
+
+/// Shift-tab key. In a definition list this moves back to the previous
+/// term or definition; elsewhere it outdents a list item.
+pub fn list_untab(ctx: ActionContext) -> Result<Op, Error> {
+    let (tag, _) = identify_block(ctx.clone())?;
+    if is_definition_tag(&tag) {
+        caret_block_move(ctx, false)
+    } else {
+        list_outdent(ctx)
+    }
+}
+
+/// Enter key. In an empty list item this outdents the item, leaving the
+/// list from its top level, and an empty term ends its definition list.
+/// Otherwise the block is split at the caret.
+pub fn enter_block(ctx: ActionContext) -> Result<Op, Error> {
+    if in_empty_list_item(&ctx) {
+        list_outdent(ctx)
+    } else if in_empty_term(&ctx) {
+        replace_block(ctx, "p")
+    } else {
+        split_block_at_caret(ctx)
+    }
+}
+
+/// Start a definition list at the caret block, which becomes its first term,
+/// followed by an empty definition.
+pub fn insert_definition(ctx: ActionContext) -> Result<Op, Error> {
+    let mut walker = Walker::to_caret(&ctx.doc, &ctx.client_id, true);
+    assert!(walker.back_block());
+
+    let (mut attrs, len) = if let Some(DocGroup(attrs, span)) = walker.doc().head() {
+        (attrs, span.skip_len())
+    } else {
+        unreachable!()
+    };
+    attrs.insert("tag".to_string(), "dt".to_string());
+    attrs.remove("render");
+    attrs.remove("lang");
+
+    let next_id = derive_block_id(&ctx.doc.0, block_id(&attrs).unwrap_or(""), &ctx.client_id);
+
+    let mut writer = walker.to_writer();
+
+    writer.del.place(&DelGroup(del_span![DelSkip(len)]));
+    writer.del.exit_all();
+
+    writer.add.place(&AddGroup(attrs, add_span![AddSkip(len)]));
+    writer.add.place(&AddGroup(
+        hashmap! {
+            "tag".to_string() => "dd".to_string(),
+            "id".to_string() => next_id,
+        },
+        add_span![],
+    ));
+    writer.add.exit_all();
+
+    Ok(writer.result())
+}
+
+/// Formatting at our caret, for highlighting toolbar buttons.
+pub fn format_state(ctx: ActionContext) -> Result<FormatState, Error> {
+    let styles = active_styles(&ctx);
+    let (block, _) = identify_block(ctx.clone())?;
+
+    let mut list_depth = 0;
+    if let Some(path) = focus_block_path(&ctx.doc.0, &ctx.client_id) {
+        let mut span = &ctx.doc.0;
+        for &i in &path[..path.len() - 1] {
+            if let DocGroup(ref attrs, ref inner) = span[i] {
+                if attrs["tag"] == "bullet" {
+                    list_depth += 1;
+                }
+                span = inner;
+            }
+        }
+    }
+
+    Ok(FormatState {
+        link: styles
+            .get(&Style::Link)
+            .cloned()
+            .and_then(|x| x)
+            .map(|x| Link::from_style_value(&x)),
+        font_size: styles.get(&Style::FontSize).cloned().and_then(|x| x),
+        font_family: styles.get(&Style::FontFamily).cloned().and_then(|x| x),
+        styles: styles.keys().cloned().collect(),
+        block,
+        list_depth,
+    })
+}
+
 // Return a "caret state"
 pub fn identify_block(ctx: ActionContext) -> Result<(String, bool), Error> {
     let mut walker = Walker::to_caret_position(&ctx.doc, &ctx.client_id, Pos::Focus)?;
@@ -69,30 +376,132 @@ pub fn identify_block(ctx: ActionContext) -> Result<(String, bool), Error> {
     }
 }
 
+// Id of the block the caret is in, to anchor things to it.
+pub fn caret_block_id(ctx: ActionContext) -> Result<Option<String>, Error> {
+    let mut walker = Walker::to_caret_position(&ctx.doc, &ctx.client_id, Pos::Focus)?;
+    assert!(walker.back_block());
+    if let Some(DocGroup(ref attrs, _)) = walker.doc().head() {
+        Ok(block_id(attrs).map(|id| id.to_string()))
+    } else {
+        bail!("Expected a DocGroup from back_block");
+    }
+}
+
 pub fn replace_block(ctx: ActionContext, tag: &str) -> Result<Op, Error> {
     let mut walker = Walker::to_caret(&ctx.doc, &ctx.client_id, true);
     assert!(walker.back_block());
 
-    let len = if let Some(DocGroup(_, ref span)) = walker.doc().head() {
-        span.skip_len()
+    // Keep the block's other attributes, like its id. Only code blocks have
+    // a render mode or language.
+    let (mut attrs, len) = if let Some(DocGroup(attrs, span)) = walker.doc().head() {
+        (attrs, span.skip_len())
     } else {
         unreachable!()
     };
+    attrs.insert("tag".to_string(), tag.to_string());
+    if tag != "pre" {
+        attrs.remove("render");
+        attrs.remove("lang");
+    }
 
     let mut writer = walker.to_writer();
 
     writer.del.place(&DelGroup(del_span![DelSkip(len)]));
     writer.del.exit_all();
 
-    writer.add.place(&AddGroup(
-        hashmap! { "tag".to_string() => tag.to_string() },
-        add_span![AddSkip(len)],
-    ));
+    writer.add.place(&AddGroup(attrs, add_span![AddSkip(len)]));
+    writer.add.exit_all();
+
+    Ok(writer.result())
+}
+
+/// Set or clear the render mode of the caret block, if it's a code block.
+pub fn set_render_mode(ctx: ActionContext, mode: Option<&str>) -> Result<Op, Error> {
+    set_code_attr(ctx, "render", mode)
+}
+
+/// Set or clear the language of the caret block, if it's a code block.
+pub fn set_code_language(ctx: ActionContext, lang: Option<&str>) -> Result<Op, Error> {
+    set_code_attr(ctx, "lang", lang)
+}
+
+fn set_code_attr(ctx: ActionContext, key: &str, value: Option<&str>) -> Result<Op, Error> {
+    let mut walker = Walker::to_caret(&ctx.doc, &ctx.client_id, true);
+    assert!(walker.back_block());
+
+    let (mut attrs, len) = if let Some(DocGroup(attrs, span)) = walker.doc().head() {
+        (attrs, span.skip_len())
+    } else {
+        unreachable!()
+    };
+    if attrs["tag"] != "pre" {
+        return Ok(Op::empty());
+    }
+    match value {
+        Some(value) => attrs.insert(key.to_string(), value.to_string()),
+        None => attrs.remove(key),
+    };
+
+    let mut writer = walker.to_writer();
+
+    writer.del.place(&DelGroup(del_span![DelSkip(len)]));
+    writer.del.exit_all();
+
+    writer.add.place(&AddGroup(attrs, add_span![AddSkip(len)]));
     writer.add.exit_all();
 
     Ok(writer.result())
 }
 
+/// Text alignments a block can have. Left is the default, and isn't stored.
+pub const ALIGNMENTS: &[&str] = &["left", "center", "right", "justify"];
+
+/// Set the alignment of the caret block. Setting it to left, or to None,
+/// clears the attribute.
+pub fn set_alignment(ctx: ActionContext, align: Option<&str>) -> Result<Op, Error> {
+    if let Some(align) = align {
+        if !ALIGNMENTS.contains(&align) {
+            bail!("Unknown alignment {:?}", align);
+        }
+    }
+
+    let mut walker = Walker::to_caret(&ctx.doc, &ctx.client_id, true);
+    assert!(walker.back_block());
+
+    let (mut attrs, len) = if let Some(DocGroup(attrs, span)) = walker.doc().head() {
+        (attrs, span.skip_len())
+    } else {
+        unreachable!()
+    };
+    match align.filter(|align| *align != "left") {
+        Some(align) => attrs.insert("align".to_string(), align.to_string()),
+        None => attrs.remove("align"),
+    };
+
+    let mut writer = walker.to_writer();
+
+    writer.del.place(&DelGroup(del_span![DelSkip(len)]));
+    writer.del.exit_all();
+
+    writer.add.place(&AddGroup(attrs, add_span![AddSkip(len)]));
+    writer.add.exit_all();
+
+    Ok(writer.result())
+}
+
+/// Rotate the caret block through paragraph, H1, H2, H3, and back to
+/// paragraph. Other blocks become paragraphs.
+pub fn cycle_heading(ctx: ActionContext) -> Result<Op, Error> {
+    let (tag, _) = identify_block(ctx.clone())?;
+    let next = match tag.as_str() {
+        "p" => "h1",
+        "h1" => "h2",
+        "h2" => "h3",
+        _ => "p",
+    };
+    replace_block(ctx, next)
+}
+
 pub fn delete_char(ctx: ActionContext) -> Result<Op, Error> {
     let walker = Walker::to_caret_safe(&ctx.doc, &ctx.client_id, true)
         .ok_or(format_err!("Expected one caret for our client"))?;
@@ -117,6 +526,7 @@ pub fn delete_char(ctx: ActionContext) -> Result<Op, Error> {
                 let ctx2 = ActionContext {
                     doc: Op::apply(&ctx.doc, &op),
                     client_id: ctx.client_id.to_owned(),
+                    positions: None,
                 };
                 let op_next = delete_char(ctx2)?;
                 return Ok(Op::compose(&op, &op_next));
@@ -143,186 +553,230 @@ pub fn delete_char_inner(mut walker: Walker) -> Result<Op, Error> {
     // block_walker.stepper.next(); // re-enter the block to first caret position
     let at_start_of_block = caret_pos == block_walker.caret_pos();
 
-    // See if we can collapse this and the previous block or list item.
+    // At the start of a block, join it with the block before.
     if at_start_of_block {
-        // console_log!("at_start {:?} {:?}", caret_pos, block_walker.caret_pos());
-
-        // Check for first block in a list item.
-        let mut parent_walker = walker.clone();
-        assert!(parent_walker.back_block());
-
-        let mut is_list_item = false;
-        let mut list_item_skip_len = 1;
-        if parent_walker.doc().unhead() == None && parent_walker.parent() {
-            if let Some(DocGroup(ref attrs_2, ref span_2)) = parent_walker.doc().head() {
-                if attrs_2["tag"] == "bullet" {
-                    // We are at the start of a block inside of a list item.
-                    is_list_item = true;
-                    list_item_skip_len = span_2.skip_len();
-                }
-            }
-        }
+        return join_previous(walker);
+    }
 
-        // Check if previous sibling is a list item too.
-        if let Some(DocGroup(ref attrs_1, ref span_1)) = parent_walker.doc().unhead() {
-            if attrs_1["tag"] == "bullet" {
-                // The previous sibling is a list item.
+    walker.back_char();
 
-                parent_walker.stepper.doc.prev();
-                let mut writer = parent_walker.to_writer();
+    // Skip past adjacent carets in between cursor and the next char.
+    // TODO is there a more elegant way to do this:
+    while let Some(DocGroup(ref attrs, _)) = walker.doc().head() {
+        if attrs["tag"] == "caret" {
+            walker.stepper.doc.next();
+        } else {
+            break;
+        }
+    }
 
-                writer.del.begin();
-                if span_1.skip_len() > 0 {
-                    writer.del.place(&DelSkip(span_1.skip_len()));
-                }
-                writer.del.close();
+    // Inline objects are deleted whole.
+    if let Some(DocGroup(ref attrs, _)) = walker.doc().head() {
+        if is_atomic_inline(attrs) {
+            let mut writer = walker.to_writer();
 
-                if is_list_item {
-                    writer.del.begin();
-                }
-                if list_item_skip_len > 0 {
-                    writer.del.place(&DelSkip(list_item_skip_len));
-                }
-                if is_list_item {
-                    writer.del.close();
-                }
-                writer.del.exit_all();
+            writer.del.place(&DelGroup(del_span![]));
+            writer.del.exit_all();
 
-                writer.add.begin();
-                if span_1.skip_len() + list_item_skip_len > 0 {
-                    writer
-                        .add
-                        .place(&AddSkip(span_1.skip_len() + list_item_skip_len));
-                }
-                writer.add.close(attrs_1.clone());
-                writer.add.exit_all();
+            writer.add.exit_all();
 
-                let res = writer.result();
+            return Ok(writer.result());
+        }
+    }
 
-                return Ok(res);
-            }
+    // Check that we precede a character.
+    if let Some(DocChars(..)) = walker.doc().head() {
+        // fallthrough
+    } else {
+        // Check if parent is span, if so move outside span
+        // TODO check that the parent is actually a span
+        // TODO this might not be possible anymore without spans.
+        walker.stepper.next();
+        if let Some(DocChars(..)) = walker.doc().head() {
+            // fallthrough
+        } else {
+            return Ok(op_span!([], []));
         }
+    }
 
-        if is_list_item {
-            // We are a list item, but we want to unindent ourselves.
-            let mut writer = parent_walker.to_writer();
+    let mut writer = walker.to_writer();
 
-            writer.del.begin();
-            if list_item_skip_len > 0 {
-                writer.del.place(&DelSkip(list_item_skip_len));
-            }
-            writer.del.close();
-            writer.del.exit_all();
+    // Delete the character.
+    writer.del.place(&DelChars(1));
+    writer.del.exit_all();
 
-            let res = writer.result();
+    writer.add.exit_all();
 
-            return Ok(res);
-        }
+    Ok(writer.result())
+}
 
-        // Return to block parent.
-        assert!(block_walker.back_block());
-        let span_2 = match block_walker.stepper().head() {
-            Some(DocGroup(.., span)) => span.skip_len(),
-            _ => unreachable!(),
-        };
+/// Join the caret block with the block before it, wherever the caret is in
+/// it. The text of both stays where it is, so it keeps its styles. The
+/// joined block takes the first block's attributes, or the second's if the
+/// first is empty. A block after a block object deletes the object instead,
+/// and the first block of a list item joins it with the item before, or
+/// takes it out of the list.
+pub fn join_with_previous_block(ctx: ActionContext) -> Result<Op, Error> {
+    let walker = Walker::to_caret_safe(&ctx.doc, &ctx.client_id, true)
+        .ok_or(format_err!("Expected one caret for our client"))?;
+    join_previous(walker)
+}
 
-        // TODO what is this? is it needed?
-        let last_doc_stack = block_walker.doc().stack.clone();
+// Join the block `walker` is in with the block before it. Backspace does
+// this at the start of a block.
+fn join_previous(walker: Walker) -> Result<Op, Error> {
+    let mut block_walker = walker.clone();
+    assert!(block_walker.back_block());
+    block_walker.stepper.doc.enter();
 
-        // Move to prior block to join it, or abort.
-        if !block_walker.back_block_or_block_object() {
-            return Ok(op_span!([], []));
+    // Check for first block in a list item.
+    let mut parent_walker = walker.clone();
+    assert!(parent_walker.back_block());
+
+    let mut is_list_item = false;
+    let mut list_item_skip_len = 1;
+    if parent_walker.doc().unhead() == None && parent_walker.parent() {
+        if let Some(DocGroup(ref attrs_2, ref span_2)) = parent_walker.doc().head() {
+            if attrs_2["tag"] == "bullet" {
+                // We are at the start of a block inside of a list item.
+                is_list_item = true;
+                list_item_skip_len = span_2.skip_len();
+            }
         }
+    }
 
-        // TODO what is this? is it needed?
-        let next_doc_stack = block_walker.doc().stack.clone();
-        if last_doc_stack != next_doc_stack {
-            return Ok(op_span!([], []));
-        }
+    // Check if previous sibling is a list item too.
+    if let Some(DocGroup(ref attrs_1, ref span_1)) = parent_walker.doc().unhead() {
+        if attrs_1["tag"] == "bullet" {
+            // The previous sibling is a list item.
+
+            parent_walker.stepper.doc.prev();
+            let mut writer = parent_walker.to_writer();
 
-        // If block is an "hr", delete it.
-        if let Some(DocGroup(ref attrs, _)) = block_walker.doc().head() {
-            if attrs["tag"] == "hr" {
-                let mut writer = block_walker.to_writer();
+            writer.del.begin();
+            if span_1.skip_len() > 0 {
+                writer.del.place(&DelSkip(span_1.skip_len()));
+            }
+            writer.del.close();
 
+            if is_list_item {
                 writer.del.begin();
+            }
+            if list_item_skip_len > 0 {
+                writer.del.place(&DelSkip(list_item_skip_len));
+            }
+            if is_list_item {
                 writer.del.close();
-                writer.del.exit_all();
-
-                writer.add.exit_all();
-
-                return Ok(writer.result());
             }
-        } else {
-            unreachable!();
-        }
+            writer.del.exit_all();
 
-        // Surround block.
-        let (attrs, span_1) = match block_walker.stepper().head() {
-            Some(DocGroup(attrs, span)) => (attrs, span.skip_len()),
-            _ => unreachable!(),
-        };
+            writer.add.begin();
+            if span_1.skip_len() + list_item_skip_len > 0 {
+                writer
+                    .add
+                    .place(&AddSkip(span_1.skip_len() + list_item_skip_len));
+            }
+            writer.add.close(attrs_1.clone());
+            writer.add.exit_all();
 
-        let mut writer = block_walker.to_writer();
+            let res = writer.result();
 
-        writer.del.begin();
-        if span_1 > 0 {
-            writer.del.place(&DelSkip(span_1));
+            return Ok(res);
         }
-        writer.del.close();
+    }
+
+    if is_list_item {
+        // We are a list item, but we want to unindent ourselves.
+        let mut writer = parent_walker.to_writer();
+
         writer.del.begin();
-        if span_2 > 0 {
-            writer.del.place(&DelSkip(span_2));
+        if list_item_skip_len > 0 {
+            writer.del.place(&DelSkip(list_item_skip_len));
         }
         writer.del.close();
         writer.del.exit_all();
 
-        writer.add.begin();
-        if span_1 + span_2 > 0 {
-            writer.add.place(&AddSkip(span_1 + span_2));
-        }
-        writer.add.close(attrs);
-        writer.add.exit_all();
-
         let res = writer.result();
 
         return Ok(res);
     }
 
-    walker.back_char();
+    // Return to block parent.
+    assert!(block_walker.back_block());
+    let (attrs_2, span_2) = match block_walker.stepper().head() {
+        Some(DocGroup(attrs, span)) => (attrs, span.skip_len()),
+        _ => unreachable!(),
+    };
 
-    // Skip past adjacent carets in between cursor and the next char.
-    // TODO is there a more elegant way to do this:
-    while let Some(DocGroup(ref attrs, _)) = walker.doc().head() {
-        if attrs["tag"] == "caret" {
-            walker.stepper.doc.next();
-        }
+    // TODO what is this? is it needed?
+    let last_doc_stack = block_walker.doc().stack.clone();
+
+    // Move to prior block to join it, or abort.
+    if !block_walker.back_block_or_block_object() {
+        return Ok(op_span!([], []));
     }
 
-    // Check that we precede a character.
-    if let Some(DocChars(..)) = walker.doc().head() {
-        // fallthrough
-    } else {
-        // Check if parent is span, if so move outside span
-        // TODO check that the parent is actually a span
-        // TODO this might not be possible anymore without spans.
-        walker.stepper.next();
-        if let Some(DocChars(..)) = walker.doc().head() {
-            // fallthrough
-        } else {
-            return Ok(op_span!([], []));
+    // TODO what is this? is it needed?
+    let next_doc_stack = block_walker.doc().stack.clone();
+    if last_doc_stack != next_doc_stack {
+        return Ok(op_span!([], []));
+    }
+
+    // If block is an "hr" or other block object, delete it whole.
+    if let Some(DocGroup(ref attrs, _)) = block_walker.doc().head() {
+        if RtfSchema::track_type_from_attrs(attrs) == Some(RtfTrack::BlockObjects) {
+            let mut writer = block_walker.to_writer();
+
+            writer.del.begin();
+            writer.del.close();
+            writer.del.exit_all();
+
+            writer.add.exit_all();
+
+            return Ok(writer.result());
         }
+    } else {
+        unreachable!();
     }
 
-    let mut writer = walker.to_writer();
+    // Surround both blocks with the first block's attributes, unless it's
+    // empty, when the second block is what's left and keeps its own: a
+    // heading after an empty paragraph stays a heading.
+    let (attrs, span_1) = match block_walker.stepper().head() {
+        Some(DocGroup(attrs_1, span)) => {
+            let empty = span.iter().all(|elem| match elem {
+                DocGroup(attrs, _) => is_any_caret(attrs),
+                DocChars(_) => false,
+            });
+            (if empty { attrs_2 } else { attrs_1 }, span.skip_len())
+        }
+        _ => unreachable!(),
+    };
 
-    // Delete the character.
-    writer.del.place(&DelChars(1));
+    let mut writer = block_walker.to_writer();
+
+    writer.del.begin();
+    if span_1 > 0 {
+        writer.del.place(&DelSkip(span_1));
+    }
+    writer.del.close();
+    writer.del.begin();
+    if span_2 > 0 {
+        writer.del.place(&DelSkip(span_2));
+    }
+    writer.del.close();
     writer.del.exit_all();
 
+    writer.add.begin();
+    if span_1 + span_2 > 0 {
+        writer.add.place(&AddSkip(span_1 + span_2));
+    }
+    writer.add.close(attrs);
     writer.add.exit_all();
 
-    Ok(writer.result())
+    let res = writer.result();
+
+    Ok(res)
 }
 
 pub fn add_string(ctx: ActionContext, input: &str) -> Result<Op, Error> {
@@ -331,7 +785,7 @@ pub fn add_string(ctx: ActionContext, input: &str) -> Result<Op, Error> {
     let walker = Walker::to_caret_position(&ctx.doc, &ctx.client_id, Pos::Focus)?;
 
     // Style map.
-    let mut styles = hashmap!{ Style::Normie => None };
+    let mut styles = style_map! { Style::Normie => None };
 
     // Identify previous styles.
     let mut char_walker = walker.clone();
@@ -358,40 +812,210 @@ pub fn add_string(ctx: ActionContext, input: &str) -> Result<Op, Error> {
     Ok(writer.result())
 }
 
-// For function reuse
-pub enum StyleOp {
-    AddStyle(Style, Option<String>),
-    RemoveStyle(Style),
+/// Insert an inline math element with the given LaTeX source at our caret.
+pub fn insert_math(ctx: ActionContext, latex: &str) -> Result<Op, Error> {
+    insert_inline_object(
+        ctx,
+        hashmap! {
+            "tag".to_string() => "math".to_string(),
+            "latex".to_string() => latex.to_string(),
+        },
+    )
+}
+
+/// Insert an image of the file at `src` at our caret.
+pub fn insert_image(ctx: ActionContext, src: &str) -> Result<Op, Error> {
+    insert_inline_object(
+        ctx,
+        hashmap! {
+            "tag".to_string() => "img".to_string(),
+            "src".to_string() => src.to_string(),
+            "alt".to_string() => "".to_string(),
+        },
+    )
+}
+
+/// Insert a link to the page `page_id` at our caret.
+pub fn insert_doc_link(ctx: ActionContext, page_id: &str) -> Result<Op, Error> {
+    insert_inline_object(
+        ctx,
+        hashmap! {
+            "tag".to_string() => "doclink".to_string(),
+            "page".to_string() => page_id.to_string(),
+        },
+    )
+}
+
+/// Insert a mention of the user `user_id`, shown as `name`, at our caret.
+pub fn insert_mention(ctx: ActionContext, user_id: &str, name: &str) -> Result<Op, Error> {
+    insert_inline_object(
+        ctx,
+        hashmap! {
+            "tag".to_string() => "mention".to_string(),
+            "user".to_string() => user_id.to_string(),
+            "name".to_string() => name.to_string(),
+        },
+    )
+}
+
+fn insert_inline_object(ctx: ActionContext, attrs: Attrs) -> Result<Op, Error> {
+    let walker = Walker::to_caret_position(&ctx.doc, &ctx.client_id, Pos::Focus)?;
+
+    let mut writer = walker.to_writer();
+
+    writer.del.exit_all();
+
+    writer.add.begin();
+    writer.add.close(attrs);
+    writer.add.exit_all();
+
+    Ok(writer.result())
+}
+
+/// Change the LaTeX source of the math element just before our caret.
+pub fn edit_math(ctx: ActionContext, latex: &str) -> Result<Op, Error> {
+    let mut walker = Walker::to_caret_position(&ctx.doc, &ctx.client_id, Pos::Focus)?;
+    walker.back_char();
+    while let Some(DocGroup(ref attrs, _)) = walker.doc().head() {
+        if is_any_caret(attrs) {
+            walker.stepper.doc.next();
+        } else {
+            break;
+        }
+    }
+
+    let mut attrs = match walker.doc().head() {
+        Some(DocGroup(attrs, _)) if attrs["tag"] == "math" => attrs,
+        _ => bail!("no math element before the caret"),
+    };
+    attrs.insert("latex".to_string(), latex.to_string());
+
+    let mut writer = walker.to_writer();
+
+    writer.del.place(&DelGroup(del_span![]));
+    writer.del.exit_all();
+
+    writer.add.place(&AddGroup(attrs, add_span![]));
+    writer.add.exit_all();
+
+    Ok(writer.result())
+}
+
+// For function reuse
+pub enum StyleOp {
+    AddStyle(Style, Option<String>),
+    RemoveStyle(Style),
+}
+
+/// Add a style to the selection, or remove it if every selected character
+/// already carries it (with the same value, if one is given).
+pub fn toggle_style(ctx: ActionContext, style: Style, value: Option<String>) -> Result<Op, Error> {
+    let active = match active_styles(&ctx).get(&style) {
+        Some(current) => value.is_none() || *current == value,
+        None => false,
+    };
+    if active {
+        restyle(ctx, vec![StyleOp::RemoveStyle(style)])
+    } else {
+        restyle(ctx, vec![StyleOp::AddStyle(style, value)])
+    }
+}
+
+/// Styles carried by every selected character, with their values. Without a
+/// selection, the styles of the character before the caret, which typed
+/// text picks up.
+pub fn active_styles(ctx: &ActionContext) -> StyleMap {
+    let mut styles: Option<StyleMap> = None;
+    if let Some((walker1, walker2)) = selection_walkers(ctx) {
+        let mut doc1 = walker1.doc().to_owned();
+        let doc2 = walker2.doc().to_owned();
+        while doc1 != doc2 {
+            match doc1.head() {
+                Some(DocGroup(..)) => {
+                    doc1.enter();
+                }
+                Some(DocChars(ref text)) => {
+                    let text_styles = text.styles().map(|x| (*x).clone()).unwrap_or_default();
+                    styles = Some(match styles {
+                        None => text_styles,
+                        Some(styles) => styles
+                            .into_iter()
+                            .filter(|(style, value)| text_styles.get(style) == Some(value))
+                            .collect(),
+                    });
+                    doc1.skip(text.char_len());
+                }
+                None => {
+                    doc1.exit();
+                }
+            }
+        }
+    } else if let Some(mut walker) = Walker::to_caret_safe(&ctx.doc, &ctx.client_id, true) {
+        if let Some(DocChars(ref prefix)) = walker.back_char().doc().head() {
+            styles = prefix.styles().map(|x| (*x).clone());
+        }
+    }
+
+    let mut styles = styles.unwrap_or_default();
+    styles.remove(&Style::Normie);
+    styles.remove(&Style::Selected);
+    styles
+}
+
+// TODO consider removing this and just use restyle
+/// Link the selection, replacing the link it had, or unlink it.
+pub fn edit_link(ctx: ActionContext, link: Option<Link>) -> Result<Op, Error> {
+    match link {
+        Some(ref link) if link.href.trim().is_empty() => bail!("Links need a URL"),
+        Some(link) => restyle(ctx, vec![StyleOp::AddStyle(Style::Link, Some(link.to_style_value()))]),
+        None => restyle(ctx, vec![StyleOp::RemoveStyle(Style::Link)]),
+    }
+}
+
+/// Set the font size of the selection, like "14px", or clear it.
+pub fn set_font_size(ctx: ActionContext, size: Option<&str>) -> Result<Op, Error> {
+    match size {
+        Some(size) if !valid_font_size(size) => bail!("Invalid font size {:?}", size),
+        Some(size) => restyle(ctx, vec![StyleOp::AddStyle(Style::FontSize, Some(size.to_string()))]),
+        None => restyle(ctx, vec![StyleOp::RemoveStyle(Style::FontSize)]),
+    }
 }
 
-// TODO consider removing this and just use restyle
-pub fn apply_style(ctx: ActionContext, style: Style, value: Option<String>) -> Result<Op, Error> {
-    restyle(ctx, vec![StyleOp::AddStyle(style, value)])
+/// Set the font family of the selection, like "Georgia, serif", or clear it.
+pub fn set_font_family(ctx: ActionContext, family: Option<&str>) -> Result<Op, Error> {
+    match family {
+        Some(family) if !valid_font_family(family) => bail!("Invalid font family {:?}", family),
+        Some(family) => restyle(ctx, vec![StyleOp::AddStyle(Style::FontFamily, Some(family.to_string()))]),
+        None => restyle(ctx, vec![StyleOp::RemoveStyle(Style::FontFamily)]),
+    }
 }
 
-// TODO consider removing this and just use restyle
 pub fn remove_styles(ctx: ActionContext, mut styles: StyleSet) -> Result<Op, Error> {
     restyle(ctx, styles.drain().map(|style| StyleOp::RemoveStyle(style)).collect())
 }
 
-pub fn restyle(ctx: ActionContext, ops: Vec<StyleOp>) -> Result<Op, Error> {
-    let walker1 = Walker::to_caret_safe(&ctx.doc, &ctx.client_id, false);
-    let walker2 = Walker::to_caret_safe(&ctx.doc, &ctx.client_id, true);
-
-    let (walker1, walker2) = if let (Some(walker1), Some(walker2)) = (walker1, walker2) {
-        if walker1.caret_pos() == walker2.caret_pos() {
-            return Ok(Op::empty());
-        } else if walker1.caret_pos() <= walker2.caret_pos() {
-            (walker1, walker2)
-        } else {
-            (walker2, walker1)
-        }
+// Walkers at the start and end of our selection, if it isn't empty.
+fn selection_walkers(ctx: &ActionContext) -> Option<(Walker, Walker)> {
+    let walker1 = Walker::to_caret_safe(&ctx.doc, &ctx.client_id, false)?;
+    let walker2 = Walker::to_caret_safe(&ctx.doc, &ctx.client_id, true)?;
+
+    if walker1.caret_pos() == walker2.caret_pos() {
+        None
+    } else if walker1.caret_pos() <= walker2.caret_pos() {
+        Some((walker1, walker2))
     } else {
-        return Ok(Op::empty());
+        Some((walker2, walker1))
+    }
+}
+
+pub fn restyle(ctx: ActionContext, ops: Vec<StyleOp>) -> Result<Op, Error> {
+    let (walker1, walker2) = match selection_walkers(&ctx) {
+        Some(walkers) => walkers,
+        None => return Ok(Op::empty()),
     };
 
     // Style map.
-    let mut add_styles = hashmap![];
+    let mut add_styles = StyleMap::new();
     for op in &ops {
         if let &StyleOp::AddStyle(ref style, ref value) = op {
             add_styles.insert(style.to_owned(), value.clone());
@@ -464,32 +1088,138 @@ pub fn restyle(ctx: ActionContext, ops: Vec<StyleOp>) -> Result<Op, Error> {
 }
 
 pub fn split_block(ctx: ActionContext, add_hr: bool) -> Result<Op, Error> {
+    if add_hr {
+        split_block_with(ctx, Some(hashmap! { "tag".into() => "hr".into() }))
+    } else {
+        split_block_at_caret(ctx)
+    }
+}
+
+/// Split the caret block in two at the caret. The text on either side
+/// stays where it is, so it keeps its styles, and a split inside a list
+/// continues it with a new item at the same depth.
+///
+/// Split anywhere but the end, the second half is the same kind of block: a
+/// heading stays a heading of the same level. Split at the end, it's the
+/// block that comes next: a definition after a term, a term after a
+/// definition, and otherwise a paragraph, keeping the alignment.
+pub fn split_block_at_caret(ctx: ActionContext) -> Result<Op, Error> {
+    split_block_with(ctx, None)
+}
+
+// Whether nothing but carets follows the caret in its block.
+fn at_block_end(walker: &Walker) -> bool {
+    let mut doc = walker.doc().clone();
+    loop {
+        match doc.head() {
+            Some(DocGroup(ref attrs, _)) if is_any_caret(attrs) => {
+                doc.next();
+            }
+            Some(_) => return false,
+            None => return true,
+        }
+    }
+}
+
+// Attributes of the second half of a split block. Only what makes it the
+// same kind of block carries over, not classes or other attributes which
+// belong to the first half.
+fn split_attrs(previous_block: &Attrs, at_end: bool, next_id: String) -> Attrs {
+    let tag = if at_end {
+        match previous_block["tag"].as_str() {
+            "dt" => "dd",
+            "dd" => "dt",
+            _ => "p",
+        }
+    } else {
+        previous_block["tag"].as_str()
+    };
+    let mut kept = vec!["align"];
+    if tag == "pre" {
+        kept.extend(&["render", "lang"]);
+    }
+
+    let mut attrs = hashmap! { "tag".to_string() => tag.to_string() };
+    for key in kept {
+        if let Some(value) = previous_block.get(key) {
+            attrs.insert(key.to_string(), value.clone());
+        }
+    }
+    attrs.insert("id".to_string(), next_id);
+    attrs
+}
+
+/// Insert an embed of `url` after the caret block, splitting it at the caret.
+pub fn insert_embed(ctx: ActionContext, url: &str) -> Result<Op, Error> {
+    if embed_src(url).is_none() {
+        bail!("can't embed {:?}", url);
+    }
+    split_block_with(
+        ctx,
+        Some(hashmap! {
+            "tag".into() => "embed".into(),
+            "url".into() => url.trim().to_string(),
+        }),
+    )
+}
+
+/// Insert an attachment block linking to an uploaded file after the caret
+/// block, splitting it at the caret.
+pub fn insert_attachment(
+    ctx: ActionContext,
+    url: &str,
+    filename: &str,
+    size: u64,
+) -> Result<Op, Error> {
+    split_block_with(
+        ctx,
+        Some(hashmap! {
+            "tag".into() => "attachment".into(),
+            "url".into() => url.to_string(),
+            "filename".into() => filename.to_string(),
+            "size".into() => size.to_string(),
+        }),
+    )
+}
+
+// Split the caret block, inserting the given block object between the halves.
+fn split_block_with(ctx: ActionContext, object: Option<Attrs>) -> Result<Op, Error> {
     let walker = Walker::to_caret(&ctx.doc, &ctx.client_id, true);
     let skip = walker.doc().skip_len();
 
-    // Identify the tag of the block we're splitting.
+    // Identify the block we're splitting. The first half keeps its
+    // attributes, including its id.
     let mut prev_walker = walker.clone();
     assert!(prev_walker.back_block());
     let previous_block = if let Some(DocGroup(attrs, _)) = prev_walker.doc().head() {
-        attrs["tag"].to_string()
+        attrs
     } else {
         // Fill in default value.
         // TODO this should be a unreachable!
-        "p".to_string()
+        hashmap! { "tag".into() => "p".into() }
     };
 
-    // Identify if we're nested inside of a bullet.
+    // The second half gets an id derived from the first's.
+    let next_id = derive_block_id(
+        &ctx.doc.0,
+        block_id(&previous_block).unwrap_or(""),
+        &ctx.client_id,
+    );
+    let next_block = split_attrs(&previous_block, at_block_end(&walker), next_id);
+
+    // Identify if we're nested inside of a bullet. The new item copies its
+    // attributes.
     let mut parent_walker = prev_walker.clone();
     let nested_bullet = loop {
         //TODO re-enable once DocGroup aborts when has too few items
         if parent_walker.parent() {
-            if let Some(DocGroup(ref attrs, _)) = parent_walker.doc().head() {
+            if let Some(DocGroup(attrs, _)) = parent_walker.doc().head() {
                 if attrs["tag"] == "bullet" {
-                    break true;
+                    break Some(attrs);
                 }
             }
         }
-        break false;
+        break None;
     };
 
     let mut writer = walker.to_writer();
@@ -498,33 +1228,27 @@ pub fn split_block(ctx: ActionContext, add_hr: bool) -> Result<Op, Error> {
         writer.del.place(&DelSkip(skip));
     }
     writer.del.close();
-    if nested_bullet {
+    if nested_bullet.is_some() {
         writer.del.close();
     }
     writer.del.exit_all();
 
-    writer
-        .add
-        .close(hashmap! { "tag".into() => previous_block });
-    if nested_bullet {
-        writer
-            .add
-            .close(hashmap! { "tag".into() => "bullet".into() });
+    writer.add.close(previous_block);
+    if let Some(ref attrs) = nested_bullet {
+        writer.add.close(attrs.clone());
         writer.add.begin();
     }
-    if add_hr {
+    if let Some(object) = object {
         writer.add.begin();
-        writer.add.close(hashmap! { "tag".into() => "hr".into() });
+        writer.add.close(object);
     }
     writer.add.begin();
     if skip > 0 {
         writer.add.place(&AddSkip(skip));
     }
-    writer.add.close(hashmap! { "tag".into() => "p".into() });
-    if nested_bullet {
-        writer
-            .add
-            .close(hashmap! { "tag".into() => "bullet".into() });
+    writer.add.close(next_block);
+    if let Some(attrs) = nested_bullet {
+        writer.add.close(attrs);
     }
     writer.add.exit_all();
 
@@ -601,15 +1325,25 @@ pub fn caret_word_move(ctx: ActionContext, increase: bool) -> Result<Op, Error>
     let op_1 = writer.result();
 
     // Second operation inserts the new caret.
+    // Class of the last character we stepped over.
+    let mut prev;
     if increase {
         walker.next_char();
+        prev = match walker.doc().unhead() {
+            Some(DocChars(ref text)) => char_class(text.as_str().chars().rev().next().unwrap()),
+            _ => CharClass::Separator,
+        };
         loop {
             match walker.doc().head() {
                 Some(DocChars(ref text)) => {
-                    if is_boundary_char(text.as_str().chars().next().unwrap()) {
+                    let class = char_class(text.as_str().chars().next().unwrap());
+                    if class == CharClass::Separator
+                        || (prev != CharClass::Separator && is_word_boundary(prev, class))
+                    {
                         break;
                     } else {
                         walker.next_char();
+                        prev = class;
                     }
                 }
                 Some(DocGroup(ref attrs, _)) => {
@@ -627,13 +1361,21 @@ pub fn caret_word_move(ctx: ActionContext, increase: bool) -> Result<Op, Error>
     } else {
         println!("skipping WORD");
         walker.back_char();
+        prev = match walker.doc().head() {
+            Some(DocChars(ref text)) => char_class(text.as_str().chars().next().unwrap()),
+            _ => CharClass::Separator,
+        };
         loop {
             match walker.doc().unhead() {
                 Some(DocChars(ref text)) => {
-                    if is_boundary_char(text.as_str().chars().rev().next().unwrap()) {
+                    let class = char_class(text.as_str().chars().rev().next().unwrap());
+                    if class == CharClass::Separator
+                        || (prev != CharClass::Separator && is_word_boundary(class, prev))
+                    {
                         break;
                     } else {
                         walker.back_char();
+                        prev = class;
                     }
                 }
                 Some(DocGroup(ref attrs, _)) => {
@@ -726,6 +1468,380 @@ pub fn caret_select_all(ctx: ActionContext) -> Result<Op, Error> {
     Ok(op_1_2_3_4)
 }
 
+/// Add an anchor caret where our focus caret is, starting a selection.
+pub fn caret_set_anchor(ctx: ActionContext) -> Result<Op, Error> {
+    if has_caret(ctx.clone(), false) {
+        return Ok(Op::empty());
+    }
+
+    let walker = Walker::to_caret(&ctx.doc, &ctx.client_id, true);
+    let mut writer = walker.to_writer();
+
+    writer.del.exit_all();
+
+    writer.add.begin();
+    writer.add.close(hashmap! {
+        "tag".to_string() => "caret".to_string(),
+        "client".to_string() => ctx.client_id.clone(),
+        "focus".to_string() => "false".to_string(),
+    });
+    writer.add.exit_all();
+
+    Ok(writer.result())
+}
+
+fn block_start_pos(doc: &Doc, client_id: &str) -> isize {
+    let mut walker = Walker::to_caret(doc, client_id, true);
+    assert!(walker.back_block());
+    walker.caret_pos()
+}
+
+/// Move our caret to the start or end of its block.
+pub fn caret_block_edge(mut ctx: ActionContext, end: bool, preserve_select: bool) -> Result<Op, Error> {
+    let start = block_start_pos(&ctx.doc, &ctx.client_id);
+
+    // Step one character at a time until we would leave the block.
+    let mut result = Op::empty();
+    loop {
+        let pos = Walker::to_caret(&ctx.doc, &ctx.client_id, true).caret_pos();
+        let op = caret_move(ctx.clone(), end, preserve_select)?;
+        let doc = Op::apply(&ctx.doc, &op);
+        let next_pos = Walker::to_caret(&doc, &ctx.client_id, true).caret_pos();
+        if next_pos == pos || block_start_pos(&doc, &ctx.client_id) != start {
+            break;
+        }
+        result = Op::compose(&result, &op);
+        ctx.doc = doc;
+    }
+
+    Ok(result)
+}
+
+// The character our focus caret would step over, if it isn't at the edge of
+// its block.
+fn char_beside_caret(ctx: &ActionContext, increase: bool) -> Option<char> {
+    let mut walker = Walker::to_caret(&ctx.doc, &ctx.client_id, true);
+    let pos = walker.caret_pos();
+    if increase {
+        walker.next_char();
+        if walker.caret_pos() == pos {
+            return None;
+        }
+        match walker.doc().unhead() {
+            Some(DocChars(ref text)) => text.as_str().chars().rev().next(),
+            _ => None,
+        }
+    } else {
+        walker.back_char();
+        if walker.caret_pos() == pos {
+            return None;
+        }
+        match walker.doc().head() {
+            Some(DocChars(ref text)) => text.as_str().chars().next(),
+            _ => None,
+        }
+    }
+}
+
+/// Move our caret to the start or end of the word it's in.
+pub fn caret_word_edge(mut ctx: ActionContext, end: bool, preserve_select: bool) -> Result<Op, Error> {
+    let mut result = Op::empty();
+    let mut prev: Option<CharClass> = None;
+    while let Some(c) = char_beside_caret(&ctx, end) {
+        let class = char_class(c);
+        let boundary = match prev {
+            Some(prev) if end => is_word_boundary(prev, class),
+            Some(prev) => is_word_boundary(class, prev),
+            None => class == CharClass::Separator,
+        };
+        if boundary {
+            break;
+        }
+
+        let op = caret_move(ctx.clone(), end, preserve_select)?;
+        ctx.doc = Op::apply(&ctx.doc, &op);
+        result = Op::compose(&result, &op);
+        prev = Some(class);
+    }
+
+    Ok(result)
+}
+
+/// Select the word our caret is in.
+pub fn caret_select_word(mut ctx: ActionContext) -> Result<Op, Error> {
+    let op_1 = clear_anchor(ctx.clone())?;
+    ctx.doc = Op::apply(&ctx.doc, &op_1);
+    let op_2 = caret_word_edge(ctx.clone(), false, false)?;
+    ctx.doc = Op::apply(&ctx.doc, &op_2);
+    let op_3 = caret_set_anchor(ctx.clone())?;
+    ctx.doc = Op::apply(&ctx.doc, &op_3);
+    let op_4 = caret_word_edge(ctx.clone(), true, true)?;
+
+    Ok(Op::compose(
+        &Op::compose(&op_1, &op_2),
+        &Op::compose(&op_3, &op_4),
+    ))
+}
+
+/// Remove our anchor caret, if we have one, ending the selection.
+pub fn clear_anchor(ctx: ActionContext) -> Result<Op, Error> {
+    Ok(caret_clear(ctx, Pos::Anchor)
+        .map(|(_, op)| op)
+        .unwrap_or_else(|_| Op::empty()))
+}
+
+fn is_own_caret(attrs: &Attrs, client_id: &str) -> bool {
+    attrs.get("tag").map(|x| x == "caret").unwrap_or(false)
+        && attrs.get("client").map(|x| x == client_id).unwrap_or(false)
+}
+
+pub fn span_text(span: &DocSpan, out: &mut String) {
+    for elem in span {
+        match elem {
+            DocChars(text) => out.push_str(text.as_str()),
+            DocGroup(_, inner) => span_text(inner, out),
+        }
+    }
+}
+
+/// Text between our anchor and focus carets, with blocks on separate lines.
+pub fn selection_text(ctx: &ActionContext) -> String {
+    fn walk(span: &DocSpan, client_id: &str, inside: &mut bool, out: &mut String) {
+        for elem in span {
+            match elem {
+                DocChars(text) => {
+                    if *inside {
+                        out.push_str(text.as_str());
+                    }
+                }
+                DocGroup(attrs, inner) => {
+                    if is_own_caret(attrs, client_id) {
+                        *inside = !*inside;
+                        continue;
+                    }
+                    if *inside
+                        && !out.is_empty()
+                        && RtfSchema::track_type_from_attrs(attrs) == Some(RtfTrack::Blocks)
+                    {
+                        out.push('\n');
+                    }
+                    walk(inner, client_id, inside, out);
+                }
+            }
+        }
+    }
+
+    let mut out = String::new();
+    walk(&ctx.doc.0, &ctx.client_id, &mut false, &mut out);
+    out
+}
+
+/// Select all of the block containing our caret.
+pub fn caret_select_block(mut ctx: ActionContext) -> Result<Op, Error> {
+    let op_1 = clear_anchor(ctx.clone())?;
+    ctx.doc = Op::apply(&ctx.doc, &op_1);
+    let op_2 = caret_block_edge(ctx.clone(), false, false)?;
+    ctx.doc = Op::apply(&ctx.doc, &op_2);
+    let op_3 = caret_set_anchor(ctx.clone())?;
+    ctx.doc = Op::apply(&ctx.doc, &op_3);
+    let op_4 = caret_block_edge(ctx.clone(), true, true)?;
+
+    Ok(Op::compose(
+        &Op::compose(&op_1, &op_2),
+        &Op::compose(&op_3, &op_4),
+    ))
+}
+
+/// Swap the block containing our caret with the block above or below it. A
+/// list item's only block moves with the list item.
+///
+/// Only the moved block is deleted and inserted again on the other side of
+/// its neighbour, so edits made concurrently in the neighbour are kept.
+pub fn move_block(ctx: ActionContext, down: bool) -> Result<Op, Error> {
+    let path = focus_block_path(&ctx.doc.0, &ctx.client_id)
+        .ok_or(format_err!("Expected our caret in a block"))?;
+
+    let mut depth = path.len() - 1;
+    if depth > 0 {
+        let parent = &span_at_path(&ctx.doc.0, &path[..depth - 1])[path[depth - 1]];
+        if let DocGroup(ref attrs, ref inner) = *parent {
+            if attrs["tag"] == "bullet" && inner.len() == 1 {
+                depth -= 1;
+            }
+        }
+    }
+    let span = span_at_path(&ctx.doc.0, &path[..depth]);
+    let index = path[depth];
+
+    let sibling = if down { index + 1 } else { index.wrapping_sub(1) };
+    match span.get(sibling) {
+        Some(DocGroup(..)) => {}
+        _ => return Ok(Op::empty()),
+    }
+    let (attrs, inner) = match span[index] {
+        DocGroup(ref attrs, ref inner) => (attrs.clone(), inner),
+        _ => unreachable!(),
+    };
+
+    // Once deleted, the block goes back in after `sibling` elements: past
+    // its neighbour moving down, and right before it moving up.
+    let mut del = del_span![];
+    let mut add = add_span![];
+    if index > 0 {
+        del.push(DelSkip(index));
+    }
+    del.push(DelGroup(del_all(inner)));
+    if sibling > 0 {
+        add.push(AddSkip(sibling));
+    }
+    add.push(AddGroup(attrs, add_all(inner)));
+
+    Ok(op_at_path(&path[..depth], (del, add)))
+}
+
+/// Move a top-level heading and its section, which runs until the next
+/// heading of the same or a higher level, to before the outline entry now at
+/// `new_index`, or to the end of the document if there's none. The heading
+/// is found by its outline anchor. Dropping a section into itself, or
+/// moving a heading that isn't top-level, does nothing.
+///
+/// There's no operation that moves elements, so the section is deleted and
+/// inserted again where it's dropped, like cutting and pasting it. Only the
+/// section is touched, and the elements it passes over are left alone.
+pub fn move_section(ctx: ActionContext, heading_id: &str, new_index: usize) -> Result<Op, Error> {
+    let doc = &ctx.doc.0;
+    let outline = outline_positions(doc);
+
+    // Whether an entry is a heading at the top level, rather than inside a
+    // list or quote.
+    let top_level = |index: usize, level: usize| match doc[index] {
+        DocGroup(ref attrs, _) => attrs["tag"] == format!("h{}", level),
+        DocChars(_) => false,
+    };
+
+    // The heading may have been deleted since the outline was sent.
+    let (start, level) = match outline.iter().find(|(_, entry)| entry.anchor == heading_id) {
+        Some((index, entry)) if top_level(*index, entry.level) => (*index, entry.level),
+        _ => return Ok(Op::empty()),
+    };
+    let end = outline
+        .iter()
+        .find(|(index, entry)| {
+            *index > start && entry.level <= level && top_level(*index, entry.level)
+        })
+        .map(|(index, _)| *index)
+        .unwrap_or(doc.len());
+    let target = outline
+        .get(new_index)
+        .map(|(index, _)| *index)
+        .unwrap_or(doc.len());
+
+    // Where the section goes back in, among the elements left once it's
+    // deleted.
+    let skip = if target < start {
+        target
+    } else if target > end {
+        target - (end - start)
+    } else {
+        return Ok(Op::empty());
+    };
+
+    let mut del = del_span![];
+    let mut add = add_span![];
+    if start > 0 {
+        del.push(DelSkip(start));
+    }
+    if skip > 0 {
+        add.push(AddSkip(skip));
+    }
+    for elem in &doc[start..end] {
+        if let DocGroup(attrs, inner) = elem {
+            del.push(DelGroup(del_all(inner)));
+            add.push(AddGroup(attrs.clone(), add_all(inner)));
+        }
+    }
+
+    Ok((del, add))
+}
+
+fn add_all(span: &DocSpan) -> AddSpan {
+    span.iter()
+        .map(|elem| match elem {
+            DocChars(text) => AddChars(text.clone()),
+            DocGroup(attrs, inner) => AddGroup(attrs.clone(), add_all(inner)),
+        })
+        .collect()
+}
+
+fn del_all(span: &DocSpan) -> DelSpan {
+    span.iter()
+        .map(|elem| match elem {
+            DocChars(text) => DelChars(text.char_len()),
+            DocGroup(_, inner) => DelGroup(del_all(inner)),
+        })
+        .collect()
+}
+
+/// Delete the block containing our caret, and put the caret at the start of
+/// the block that follows it.
+pub fn delete_block(ctx: ActionContext) -> Result<Op, Error> {
+    let mut walker = Walker::to_caret(&ctx.doc, &ctx.client_id, true);
+    assert!(walker.back_block());
+    let start = walker.caret_pos();
+
+    // A list item's only block goes with the list item.
+    let mut parent_walker = walker.clone();
+    if parent_walker.parent() {
+        if let Some(DocGroup(ref attrs, ref span)) = parent_walker.doc().head() {
+            if attrs["tag"] == "bullet" && span.len() == 1 {
+                walker = parent_walker.clone();
+            }
+        }
+    }
+
+    let span = match walker.doc().head() {
+        Some(DocGroup(_, span)) => span,
+        _ => bail!("Expected a DocGroup from back_block"),
+    };
+
+    // The document must keep at least one block.
+    if ctx.doc.0.len() == 1 && walker.doc().stack.is_empty() {
+        return Ok(Op::empty());
+    }
+
+    let mut writer = walker.to_writer();
+
+    writer.del.place(&DelGroup(del_all(&span)));
+    writer.del.exit_all();
+
+    writer.add.exit_all();
+
+    let op_1 = writer.result();
+
+    // Our caret was deleted along with the block, so insert a new one.
+    let doc = Op::apply(&ctx.doc, &op_1);
+    let mut walker = Walker::new(&doc);
+    if !walker.goto_pos(start) {
+        walker.goto_end();
+    }
+
+    let mut writer = walker.to_writer();
+
+    writer.del.exit_all();
+
+    writer.add.begin();
+    writer.add.close(hashmap! {
+        "tag".to_string() => "caret".to_string(),
+        "client".to_string() => ctx.client_id.clone(),
+        "focus".to_string() => "true".to_string(),
+    });
+    writer.add.exit_all();
+
+    let op_2 = writer.result();
+
+    Ok(Op::compose(&op_1, &op_2))
+}
+
 pub fn has_caret(ctx: ActionContext, focus: bool) -> bool {
     Walker::to_caret_safe(&ctx.doc, &ctx.client_id, focus).is_some()
 }
@@ -795,6 +1911,116 @@ pub fn caret_block_move(ctx: ActionContext, increase: bool) -> Result<Op, Error>
     Ok(Op::transform_advance::<RtfSchema>(&op_1, &op_2))
 }
 
+/// Move our caret to the start of the block with the given id.
+pub fn caret_to_block(ctx: ActionContext, id: &str) -> Result<Op, Error> {
+    if find_block(&ctx.doc.0, id).is_none() {
+        bail!("no block with id {:?}", id);
+    }
+
+    let mut walker = Walker::new(&ctx.doc);
+    assert!(walker.back_block());
+    loop {
+        if let Some(DocGroup(attrs, _)) = walker.doc().head() {
+            if block_id(&attrs) == Some(id) {
+                break;
+            }
+        }
+        if !walker.next_block() {
+            bail!("block {:?} can't hold a caret", id);
+        }
+    }
+
+    // First operation removes the caret, if we have one.
+    let op_1 = caret_clear(ctx.clone(), Pos::Focus)
+        .map(|(_, op)| op)
+        .unwrap_or_else(|_| Op::empty());
+
+    // Second operation inserts the new caret.
+    let mut writer = walker.to_writer();
+
+    writer.del.exit_all();
+
+    writer.add.begin();
+    writer.add.begin();
+    writer.add.close(hashmap! {
+        "tag".to_string() => "caret".to_string(),
+        "client".to_string() => ctx.client_id.clone(),
+        "focus".to_string() => "true".to_string(),
+    });
+    writer.add.exit_all();
+
+    let op_2 = writer.result();
+
+    Ok(Op::transform_advance::<RtfSchema>(&op_1, &op_2))
+}
+
+/// Move our focus or anchor caret to a caret position.
+pub fn caret_to_pos(ctx: ActionContext, pos: isize, focus: bool) -> Result<Op, Error> {
+    let walker = match ctx.positions {
+        Some(ref positions) => Walker::to_pos(&ctx.doc, positions, pos),
+        None => {
+            let mut walker = Walker::new(&ctx.doc);
+            if walker.goto_pos(pos) {
+                Some(walker)
+            } else {
+                None
+            }
+        }
+    };
+    let walker = match walker {
+        Some(walker) => walker,
+        None => bail!("no caret position {}", pos),
+    };
+
+    // First operation removes the caret, if we have one.
+    let op_1 = caret_clear(ctx.clone(), if focus { Pos::Focus } else { Pos::Anchor })
+        .map(|(_, op)| op)
+        .unwrap_or_else(|_| Op::empty());
+
+    // Second operation inserts the new caret.
+    let mut writer = walker.to_writer();
+
+    writer.del.exit_all();
+
+    writer.add.begin();
+    writer.add.close(hashmap! {
+        "tag".to_string() => "caret".to_string(),
+        "client".to_string() => ctx.client_id.clone(),
+        "focus".to_string() => focus.to_string(),
+    });
+    writer.add.exit_all();
+
+    let op_2 = writer.result();
+
+    Ok(Op::transform_advance::<RtfSchema>(&op_1, &op_2))
+}
+
+/// Select from one caret position to another.
+pub fn caret_select_range(mut ctx: ActionContext, anchor: isize, focus: isize) -> Result<Op, Error> {
+    let op_1 = caret_to_pos(ctx.clone(), anchor, false)?;
+    ctx.doc = Op::apply(&ctx.doc, &op_1);
+    if let Some(ref mut positions) = ctx.positions {
+        positions.apply(&ctx.doc, &op_1);
+    }
+    let op_2 = caret_to_pos(ctx.clone(), focus, true)?;
+
+    Ok(Op::compose(&op_1, &op_2))
+}
+
+/// Caret positions of the start and end of each of `count` matches, in a
+/// document marked by `find::find_markers_op`, whose caret positions are
+/// indexed by `positions`.
+pub fn find_positions(doc: &Doc, positions: &PositionIndex, count: usize) -> Vec<(isize, isize)> {
+    (0..count)
+        .filter_map(|n| {
+            let client_id = find_client_id(n);
+            let start = Walker::to_caret_indexed(doc, positions, &client_id, false)?.caret_pos();
+            let end = Walker::to_caret_indexed(doc, positions, &client_id, true)?.caret_pos();
+            Some((start, end))
+        })
+        .collect()
+}
+
 // Returns new caret position
 pub fn caret_clear(ctx: ActionContext, position: Pos) -> Result<(isize, Op), Error> {
     let walker = Walker::to_caret_position(&ctx.doc, &ctx.client_id, position)?;
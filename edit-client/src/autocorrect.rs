@@ -0,0 +1,127 @@
+//! Autocorrect, applied when the Autocorrect input rule is on. Typing the
+//! end of a word fixes a misspelling right before it, like "teh " becoming
+//! "the ". Patterns are matched whole, ignoring case, and the replacement
+//! keeps the capitalization they were typed with.
+//!
+//! A built-in list of common misspellings is always loaded. Each user's own
+//! dictionary is layered over it at setup, and rules can be added or
+//! removed while editing.
+
+use failure::Error;
+
+/// Longest pattern, so patterns always fit in what input rules can see
+/// before the caret.
+pub const MAX_PATTERN_LEN: usize = 24;
+
+const DEFAULT_RULES: &[(&str, &str)] = &[
+    ("adn", "and"),
+    ("alot", "a lot"),
+    ("becuase", "because"),
+    ("definately", "definitely"),
+    ("dont", "don't"),
+    ("occured", "occurred"),
+    ("recieve", "receive"),
+    ("seperate", "separate"),
+    ("teh", "the"),
+    ("thier", "their"),
+    ("untill", "until"),
+    ("wich", "which"),
+];
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '\'' || c == '\u{2019}'
+}
+
+fn same_letter(a: char, b: char) -> bool {
+    a.to_lowercase().eq(b.to_lowercase())
+}
+
+// Give the replacement the capitalization the pattern was typed with:
+// "Teh" becomes "The", and "TEH" becomes "THE".
+fn match_case(typed: &[char], replacement: &str) -> String {
+    let upper = |c: &char| c.is_uppercase();
+    let letters = typed.iter().filter(|c| c.is_alphabetic()).collect::<Vec<_>>();
+    if letters.len() > 1 && letters.iter().all(|c| upper(c)) {
+        replacement.to_uppercase()
+    } else if typed.first().map(upper).unwrap_or(false) {
+        let mut chars = replacement.chars();
+        match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => String::new(),
+        }
+    } else {
+        replacement.to_string()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Autocorrect {
+    // Patterns and their replacements, longest patterns first.
+    rules: Vec<(Vec<char>, String)>,
+}
+
+impl Autocorrect {
+    pub fn new() -> Autocorrect {
+        let mut autocorrect = Autocorrect { rules: vec![] };
+        for (pattern, replacement) in DEFAULT_RULES {
+            autocorrect.set_rule(pattern, Some(*replacement)).unwrap();
+        }
+        autocorrect
+    }
+
+    /// Always replace `pattern` with `replacement`, or stop replacing it
+    /// with None.
+    pub fn set_rule(&mut self, pattern: &str, replacement: Option<&str>) -> Result<(), Error> {
+        let pattern = pattern.trim().chars().collect::<Vec<_>>();
+        if pattern.is_empty() || pattern.len() > MAX_PATTERN_LEN {
+            bail!("autocorrect patterns must be 1 to {} characters", MAX_PATTERN_LEN);
+        }
+        if !is_word_char(pattern[0]) || !is_word_char(pattern[pattern.len() - 1]) {
+            bail!("autocorrect patterns must start and end with a letter or digit");
+        }
+
+        self.rules.retain(|(existing, _)| {
+            existing.len() != pattern.len()
+                || !existing.iter().zip(&pattern).all(|(a, b)| same_letter(*a, *b))
+        });
+        if let Some(replacement) = replacement {
+            self.rules.push((pattern, replacement.to_string()));
+            self.rules.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+        }
+        Ok(())
+    }
+
+    /// Add a user's dictionary over the rules already loaded. Invalid rules
+    /// are skipped.
+    pub fn load_dictionary(&mut self, rules: &[(String, String)]) {
+        for (pattern, replacement) in rules {
+            let _ = self.set_rule(pattern, Some(replacement.as_str()));
+        }
+    }
+
+    /// Correct a pattern ended by the last character of `text`, which
+    /// must not be a word character itself.
+    pub fn correct(&self, text: &mut Vec<char>) {
+        let (last, before) = match text.split_last() {
+            Some((last, before)) if !is_word_char(*last) => (*last, before.to_vec()),
+            _ => return,
+        };
+        for (pattern, replacement) in &self.rules {
+            if pattern.len() > before.len() {
+                continue;
+            }
+            let start = before.len() - pattern.len();
+            if start > 0 && is_word_char(before[start - 1]) {
+                continue;
+            }
+            let typed = &before[start..];
+            if typed.iter().zip(pattern).all(|(a, b)| same_letter(*a, *b)) {
+                let corrected = match_case(typed, replacement);
+                text.truncate(start);
+                text.extend(corrected.chars());
+                text.push(last);
+                return;
+            }
+        }
+    }
+}
@@ -11,6 +11,7 @@ extern crate ws;
 extern crate taken;
 extern crate bus;
 extern crate crossbeam_channel;
+#[macro_use]
 extern crate failure;
 extern crate rand;
 extern crate ron;
@@ -23,17 +24,26 @@ use extern::{
         Sender,
     },
     edit_client::{
+        chaos::*,
         monkey::*,
         proxy::*,
+        recording::*,
         *,
     },
     edit_common::commands::*,
+    edit_common::protocol::PROTOCOL_VERSION,
+    edit_common::signing::{
+        needs_signature,
+        SigningKeys,
+    },
+    edit_common::simple_ws,
     edit_common::simple_ws::*,
     failure::Error,
     std::panic,
     std::process,
     std::sync::atomic::AtomicBool,
     std::sync::atomic::Ordering,
+    std::collections::HashMap,
     std::sync::{
         Arc,
         Mutex,
@@ -55,6 +65,47 @@ struct Opt {
 
     #[structopt(long = "port", help = "Port", default_value = "8002")]
     port: u16,
+
+    #[structopt(long = "record", help = "Record the frames exchanged with sync to a file")]
+    record: Option<String>,
+
+    #[structopt(long = "replay", help = "Replay a recording instead of serving clients")]
+    replay: Option<String>,
+
+    #[structopt(
+        long = "replay-to",
+        help = "Replay what the client sent at \"sync\", or what sync sent at a \"client\"",
+        default_value = "sync"
+    )]
+    replay_to: String,
+
+    #[structopt(long = "replay-page", help = "Page whose frames to replay at a client")]
+    replay_page: Option<String>,
+
+    #[structopt(long = "replay-speed", help = "Speed to replay at", default_value = "1")]
+    replay_speed: f64,
+}
+
+// Replay a recording at the sync server, or at a headless client, printing
+// the document it ends up with.
+fn replay(opt: &Opt, path: &str) -> Result<(), Error> {
+    let frames = read_recording(path)?;
+    match opt.replay_to.as_str() {
+        "sync" => replay_to_sync(&frames, opt.port - 1, opt.replay_speed),
+        "client" => {
+            let page_id = match opt.replay_page {
+                Some(ref page_id) => page_id.to_string(),
+                None => match frames.first() {
+                    Some(frame) => frame.page_id.clone(),
+                    None => return Ok(()),
+                },
+            };
+            let doc = replay_to_client(&frames, &page_id, opt.replay_speed)?;
+            println!("{}", serde_json::to_string(&doc.0)?);
+            Ok(())
+        }
+        other => bail!("can't replay to {:?}, only to sync or client", other),
+    }
 }
 
 pub fn main() {
@@ -71,11 +122,30 @@ pub fn main() {
     let port = opt.port;
     let monkies = opt.monkies;
 
+    if let Some(ref path) = opt.replay {
+        if let Err(err) = replay(&opt, path) {
+            eprintln!("edit-client-proxy: {}", err);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let recorder = match opt.record {
+        Some(ref path) => match Recorder::create(path) {
+            Ok(recorder) => Some(Arc::new(recorder)),
+            Err(err) => {
+                eprintln!("edit-client-proxy: can't record to {:?}: {}", path, err);
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+
     if monkies.is_some() {
         virtual_monkeys();
     }
 
-    start_websocket_server(port);
+    start_websocket_server(port, recorder);
 }
 
 fn spawn_virtual_monkey(port: u16, key: usize) -> JoinHandle<()> {
@@ -128,33 +198,57 @@ fn virtual_monkeys() {
 // #[spawn]
 fn spawn_send_to_client(
     rx_client: Receiver<FrontendCommand>,
-    out: Arc<Mutex<ws::Sender>>,
+    out: simple_ws::Sender,
+    mux_id: Option<String>,
 ) -> JoinHandle<Result<(), Error>> {
     thread::spawn(|| -> Result<(), Error> {
-        take!(rx_client, out);
+        take!(rx_client, out, mux_id);
         while let Ok(req) = rx_client.recv() {
-            let json = serde_json::to_string(&req).unwrap();
-            out.lock().unwrap().send(json)?;
+            // Tag commands with their document on multiplexed connections.
+            let json = match mux_id {
+                Some(ref mux_id) => serde_json::to_string(&MuxFrame(mux_id.clone(), req)),
+                None => serde_json::to_string(&req),
+            }.unwrap();
+            out.send(json)?;
         }
         Ok(())
     })
 }
 
+// Serialize a command for sync, signing commands that change the page if
+// keys are configured, so they can't be altered or replayed on the way.
+fn sync_json(command: &ServerCommand, page_id: &str, signing_keys: &Option<SigningKeys>) -> String {
+    match signing_keys {
+        Some(keys) if needs_signature(command) => {
+            let (key_id, signature, payload) = keys.sign_command(page_id, command);
+            serde_json::to_string(&ServerCommand::Signed(key_id, signature, payload)).unwrap()
+        }
+        _ => serde_json::to_string(command).unwrap(),
+    }
+}
+
 // #[spawn]
 fn spawn_client_to_sync(
     out: ws::Sender,
     rx: Receiver<ServerCommand>,
     sentinel: Arc<AtomicBool>,
+    page_id: String,
+    recorder: Option<Arc<Recorder>>,
 ) -> JoinHandle<()> {
     thread::spawn(move || {
+        let signing_keys = SigningKeys::from_env();
         while let Ok(command) = rx.recv() {
             if let ServerCommand::TerminateProxy = command {
                 let _ = out.close(CloseCode::Away);
                 sentinel.store(false, Ordering::SeqCst);
                 break;
-            } else {
-                out.send(serde_json::to_string(&command).unwrap()).unwrap();
             }
+
+            let json = sync_json(&command, &page_id, &signing_keys);
+            if let Some(ref recorder) = recorder {
+                recorder.record(&page_id, Direction::ToSync, &json);
+            }
+            out.send(json).unwrap();
         }
     })
 }
@@ -165,24 +259,40 @@ fn spawn_sync_connection(
     page_id: String,
     tx_task: Sender<Task>,
     rx: Receiver<ServerCommand>,
+    recorder: Option<Arc<Recorder>>,
 ) -> JoinHandle<()> {
     thread::spawn(move || {
         let sentinel = Arc::new(AtomicBool::new(true));
-        ws::connect(format!("ws://127.0.0.1:{}/$/ws/{}", ws_port, page_id), {
+        let url = format!(
+            "ws://127.0.0.1:{}/$/ws/{}?protocol={}",
+            ws_port, page_id, PROTOCOL_VERSION
+        );
+        ws::connect(url, {
             let sentinel = sentinel.clone();
 
             move |out| {
                 // While we receive packets from the client, send them to sync.
-                spawn_client_to_sync(out, rx.clone(), sentinel.clone());
+                spawn_client_to_sync(
+                    out,
+                    rx.clone(),
+                    sentinel.clone(),
+                    page_id.clone(),
+                    recorder.clone(),
+                );
 
                 // Receive packets from sync and act on them.
                 let tx_task = tx_task.clone();
-                move |msg: ws::Message| {
+                let page_id = page_id.clone();
+                let recorder = recorder.clone();
+                MaybeDeflate::new(move |msg: ws::Message| {
                     // Handle messages received on this connection
                     // println!("wasm got a packet from sync '{}'. ", msg);
 
-                    let req_parse: Result<ClientCommand, _> =
-                        serde_json::from_slice(&msg.into_data());
+                    let data = msg.into_data();
+                    if let Some(ref recorder) = recorder {
+                        recorder.record(&page_id, Direction::ToClient, &String::from_utf8_lossy(&data));
+                    }
+                    let req_parse: Result<ClientCommand, _> = serde_json::from_slice(&data);
                     match req_parse {
                         Err(err) => {
                             println!("Packet error: {:?}", err);
@@ -193,7 +303,7 @@ fn spawn_sync_connection(
                     }
 
                     Ok(())
-                }
+                })
             }
         }).unwrap();
 
@@ -206,17 +316,123 @@ fn spawn_sync_connection(
     })
 }
 
+/// One connection to sync carrying every document of a multiplexed socket.
+#[derive(Clone)]
+struct MuxSync {
+    tx_frames: Sender<String>,
+    // Task queue of each open document.
+    documents: Arc<Mutex<HashMap<String, Sender<Task>>>>,
+    recorder: Option<Arc<Recorder>>,
+}
+
+impl MuxSync {
+    fn spawn(ws_port: u16, recorder: Option<Arc<Recorder>>) -> MuxSync {
+        let (tx_frames, rx_frames) = unbounded::<String>();
+        let mux = MuxSync {
+            tx_frames,
+            documents: Arc::new(Mutex::new(HashMap::new())),
+            recorder,
+        };
+
+        thread::spawn({
+            let mux = mux.clone();
+            move || {
+                let url = format!(
+                    "ws://127.0.0.1:{}/$/ws/$mux?protocol={}",
+                    ws_port, PROTOCOL_VERSION
+                );
+                let _ = ws::connect(url, move |out: ws::Sender| {
+                    // Frames from every document go out over this connection.
+                    let rx_frames = rx_frames.clone();
+                    thread::spawn(move || {
+                        while let Ok(json) = rx_frames.recv() {
+                            if out.send(json).is_err() {
+                                break;
+                            }
+                        }
+                    });
+
+                    let mux = mux.clone();
+                    MaybeDeflate::new(move |msg: ws::Message| {
+                        mux.receive(&msg.into_data());
+                        Ok(())
+                    })
+                });
+            }
+        });
+
+        mux
+    }
+
+    fn send(&self, message: &MuxMessage<()>) {
+        let _ = self.tx_frames.send(serde_json::to_string(message).unwrap());
+    }
+
+    /// Connect a document over this connection. Commands for sync are read
+    /// from `rx_sync`, and commands from sync are queued on `tx_task`.
+    fn attach(&self, page_id: &str, tx_task: Sender<Task>, rx_sync: Receiver<ServerCommand>) {
+        self.documents
+            .lock()
+            .unwrap()
+            .insert(page_id.to_string(), tx_task);
+        self.send(&MuxMessage::Open(page_id.to_string()));
+
+        let mux = self.clone();
+        let page_id = page_id.to_string();
+        thread::spawn(move || {
+            let signing_keys = SigningKeys::from_env();
+            while let Ok(command) = rx_sync.recv() {
+                if let ServerCommand::TerminateProxy = command {
+                    if mux.documents.lock().unwrap().remove(&page_id).is_some() {
+                        mux.send(&MuxMessage::Close(page_id.clone(), None));
+                    }
+                    break;
+                }
+
+                let json = sync_json(&command, &page_id, &signing_keys);
+                if let Some(ref recorder) = mux.recorder {
+                    recorder.record(&page_id, Direction::ToSync, &json);
+                }
+                let _ = mux.tx_frames.send(mux_frame(&page_id, &json));
+            }
+        });
+    }
+
+    // Pass a message from sync to the document it's for.
+    fn receive(&self, data: &[u8]) {
+        match serde_json::from_slice::<MuxMessage<ClientCommand>>(data) {
+            Ok(MuxMessage::Frame(page_id, command)) => {
+                if let Some(ref recorder) = self.recorder {
+                    let json = serde_json::to_string(&command).unwrap();
+                    recorder.record(&page_id, Direction::ToClient, &json);
+                }
+                if let Some(tx_task) = self.documents.lock().unwrap().get(&page_id) {
+                    let _ = tx_task.send(Task::ClientCommand(command));
+                }
+            }
+            // Sync closed a document, which we acknowledge.
+            Ok(MuxMessage::Close(page_id, reason)) => {
+                println!("Sync closed {:?}: {:?}", page_id, reason);
+                if self.documents.lock().unwrap().remove(&page_id).is_some() {
+                    self.send(&MuxMessage::Close(page_id, None));
+                }
+            }
+            Ok(MuxMessage::Open(..)) => {}
+            Err(err) => {
+                println!("Packet error: {:?}", err);
+            }
+        }
+    }
+}
+
 fn setup_client(
     name: &str,
     page_id: &str,
-    out: Arc<Mutex<ws::Sender>>,
+    out: simple_ws::Sender,
     ws_port: u16,
-) -> (
-    Arc<AtomicBool>,
-    Arc<AtomicBool>,
-    Sender<Task>,
-    Sender<ServerCommand>,
-) {
+    mux_sync: Option<&MuxSync>,
+    recorder: Option<Arc<Recorder>>,
+) -> ProxyDocument {
     let (tx_sync, rx_sync) = unbounded();
 
     // Initialize logger.
@@ -226,24 +442,28 @@ fn setup_client(
     let alive = Arc::new(AtomicBool::new(true));
 
     let (tx_client, rx_client) = unbounded();
-    spawn_send_to_client(rx_client, out);
+    spawn_send_to_client(
+        rx_client,
+        out,
+        mux_sync.map(|_| page_id.to_owned()),
+    );
+
+    // Frames to sync go through a chaos link too, when it's configured.
+    let chaos = ChaosConfig::from_env();
+    let tx_client_sync = match chaos {
+        Some(ref config) => chaos_link(config.clone(), monkey.clone(), tx_sync.clone()),
+        None => tx_sync.clone(),
+    };
 
     let mut client = ProxyClient {
-        state: Client {
-            client_id: name.to_owned(),
-            client_doc: ClientDoc::new(),
-
-            monkey: monkey.clone(),
-            alive: alive.clone(),
-            task_count: 0,
-        },
+        state: Client::new(name, monkey.clone(), alive.clone()),
 
         tx_client,
-        tx_sync: tx_sync.clone(),
+        tx_sync: tx_client_sync,
     };
 
     // Send initial controls.
-    client.setup_controls(None);
+    client.setup_controls(Keymap::Default, None);
 
     let (tx_task, rx_task) = unbounded();
 
@@ -254,8 +474,19 @@ fn setup_client(
         monkey.clone(),
     ));
 
-    // Connect to the sync server.
-    spawn_sync_connection(ws_port, page_id.to_owned(), tx_task.clone(), rx_sync);
+    // Connect to the sync server. While the monkey runs, a chaos link
+    // between the two delays and reorders frames, if EDIT_CHAOS configures
+    // one.
+    let tx_sync_task = match chaos {
+        Some(config) => chaos_link(config, monkey.clone(), tx_task.clone()),
+        None => tx_task.clone(),
+    };
+    match mux_sync {
+        Some(mux_sync) => mux_sync.attach(page_id, tx_sync_task, rx_sync),
+        None => {
+            spawn_sync_connection(ws_port, page_id.to_owned(), tx_sync_task, rx_sync, recorder);
+        }
+    }
 
     // Operate on all incoming tasks.
     //TODO possible to delay naming or spawning until init was handled?
@@ -272,58 +503,134 @@ fn setup_client(
             Ok(())
         });
 
-    (alive, monkey, tx_task, tx_sync)
+    ProxyDocument {
+        alive,
+        monkey,
+        tx_task,
+        tx_sync,
+    }
 }
 
-pub struct ProxySocket {
+/// A document hosted by a proxy socket.
+pub struct ProxyDocument {
     alive: Arc<AtomicBool>,
     monkey: Arc<AtomicBool>,
     tx_task: Sender<Task>,
     tx_sync: Sender<ServerCommand>,
 }
 
+impl ProxyDocument {
+    fn close(&self) -> Result<(), Error> {
+        self.monkey.store(false, Ordering::Relaxed);
+        self.alive.store(false, Ordering::Relaxed);
+
+        self.tx_sync.send(ServerCommand::TerminateProxy)?;
+
+        Ok(())
+    }
+}
+
+// Sockets connecting to this path host several documents, and exchange
+// MuxCommand and MuxFrame messages instead of bare commands. Their
+// documents share one connection to sync.
+const MUX_PATH: &str = "/$mux";
+
+pub struct ProxySocket {
+    ws_port: u16,
+    out: simple_ws::Sender,
+    // Set when recording the frames exchanged with sync.
+    recorder: Option<Arc<Recorder>>,
+    // Set for sockets hosting a single document.
+    page_id: Option<String>,
+    // Set for sockets hosting several.
+    mux_sync: Option<MuxSync>,
+    documents: HashMap<String, ProxyDocument>,
+}
+
 impl SimpleSocket for ProxySocket {
-    type Args = u16;
+    type Args = (u16, Option<Arc<Recorder>>);
 
     fn initialize(
-        ws_port: u16,
+        (ws_port, recorder): Self::Args,
         url: &str,
-        out: Arc<Mutex<ws::Sender>>,
+        _remote_addr: Option<String>,
+        _cookie: Option<String>,
+        out: simple_ws::Sender,
     ) -> Result<ProxySocket, Error> {
-        let page_id = url[1..].to_string();
-        let (alive, monkey, tx_task, tx_sync) =
-            setup_client("$$$$$$", &page_id, out.clone(), ws_port);
-
-        Ok(ProxySocket {
-            alive,
-            monkey,
-            tx_task,
-            tx_sync,
-        })
+        let mut socket = ProxySocket {
+            ws_port,
+            out: out.clone(),
+            recorder: recorder.clone(),
+            page_id: None,
+            mux_sync: None,
+            documents: HashMap::new(),
+        };
+
+        if url == MUX_PATH {
+            socket.mux_sync = Some(MuxSync::spawn(ws_port, recorder));
+        } else {
+            let page_id = url[1..].to_string();
+            let document = setup_client("$$$$$$", &page_id, out, ws_port, None, recorder);
+            socket.documents.insert(page_id.clone(), document);
+            socket.page_id = Some(page_id);
+        }
+
+        Ok(socket)
     }
 
     fn handle_message(&mut self, data: &[u8]) -> Result<(), Error> {
-        let msg = serde_json::from_slice(&data)?;
-        Ok(self.tx_task.send(Task::ControllerCommand(msg))?)
+        if let Some(ref page_id) = self.page_id {
+            let msg = serde_json::from_slice(&data)?;
+            return Ok(self.documents[page_id]
+                .tx_task
+                .send(Task::ControllerCommand(msg))?);
+        }
+
+        match serde_json::from_slice(&data)? {
+            MuxCommand::Open(page_id) => {
+                if !self.documents.contains_key(&page_id) {
+                    let document = setup_client(
+                        "$$$$$$",
+                        &page_id,
+                        self.out.clone(),
+                        self.ws_port,
+                        self.mux_sync.as_ref(),
+                        self.recorder.clone(),
+                    );
+                    self.documents.insert(page_id, document);
+                }
+            }
+            MuxCommand::Close(page_id) => {
+                if let Some(document) = self.documents.remove(&page_id) {
+                    document.close()?;
+                }
+            }
+            MuxCommand::Controller(page_id, command) => {
+                let document = self
+                    .documents
+                    .get(&page_id)
+                    .ok_or(format_err!("document {:?} is not open", page_id))?;
+                document.tx_task.send(Task::ControllerCommand(command))?;
+            }
+        }
+        Ok(())
     }
 
     fn cleanup(&mut self) -> Result<(), Error> {
-        self.monkey.store(false, Ordering::Relaxed);
-        self.alive.store(false, Ordering::Relaxed);
-
-        self.tx_sync.send(ServerCommand::TerminateProxy)?;
-
+        for (_, document) in self.documents.drain() {
+            document.close()?;
+        }
         Ok(())
     }
 }
 
-pub fn server(url: &str, ws_port: u16) {
+pub fn server(url: &str, ws_port: u16, recorder: Option<Arc<Recorder>>) {
     ws::listen(url, |out| {
         // Websocket message handler.
-        SocketHandler::<ProxySocket>::new(ws_port, out)
+        MaybeDeflate::new(SocketHandler::<ProxySocket>::new((ws_port, recorder.clone()), out))
     }).unwrap();
 }
 
-pub fn start_websocket_server(port: u16) {
-    server(&format!("0.0.0.0:{}", port), port - 1);
+pub fn start_websocket_server(port: u16, recorder: Option<Arc<Recorder>>) {
+    server(&format!("0.0.0.0:{}", port), port - 1, recorder);
 }
@@ -0,0 +1,345 @@
+//! Load test for the sync server. Connects headless native clients to a
+//! running server, has them type into a few documents at a steady rate, and
+//! reports how many operations were acknowledged, how long acks took, and
+//! whether any two clients saw a different document at the same version.
+//! The server-side complement to the monkeys, which exercise the client.
+
+#![feature(extern_in_paths, crate_in_paths)]
+
+extern crate crossbeam_channel;
+extern crate edit_client;
+extern crate edit_common;
+extern crate failure;
+extern crate libc;
+extern crate rand;
+extern crate serde_json;
+extern crate structopt;
+#[macro_use]
+extern crate structopt_derive;
+extern crate ws;
+
+use extern::{
+    crossbeam_channel::{
+        unbounded,
+        Receiver,
+        Sender,
+    },
+    edit_client::{
+        proxy::*,
+        *,
+    },
+    edit_common::commands::*,
+    edit_common::protocol::PROTOCOL_VERSION,
+    edit_common::simple_ws::*,
+    failure::Error,
+    rand::Rng,
+    std::collections::hash_map::DefaultHasher,
+    std::collections::HashMap,
+    std::fs::{
+        File,
+        OpenOptions,
+    },
+    std::hash::{
+        Hash,
+        Hasher,
+    },
+    std::io::Write,
+    std::os::unix::io::{
+        AsRawFd,
+        FromRawFd,
+    },
+    std::sync::atomic::{
+        AtomicBool,
+        Ordering,
+    },
+    std::sync::{
+        Arc,
+        Mutex,
+    },
+    std::thread,
+    std::time::{
+        Duration,
+        Instant,
+    },
+    structopt::StructOpt,
+};
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "edit-loadtest", about = "Load test a sync server with headless clients.")]
+struct Opt {
+    #[structopt(long = "server", help = "Sync server", default_value = "ws://127.0.0.1:8001")]
+    server: String,
+
+    #[structopt(long = "clients", help = "Number of clients", default_value = "10")]
+    clients: usize,
+
+    #[structopt(long = "docs", help = "Number of documents the clients share", default_value = "1")]
+    docs: usize,
+
+    #[structopt(long = "rate", help = "Keystrokes per second, per client", default_value = "5")]
+    rate: f64,
+
+    #[structopt(long = "duration", help = "Seconds to type for", default_value = "30")]
+    duration: u64,
+
+    #[structopt(long = "prefix", help = "Prefix of the documents' page IDs", default_value = "loadtest")]
+    prefix: String,
+
+    #[structopt(long = "log", help = "File to write client output to", default_value = "/dev/null")]
+    log: String,
+}
+
+// Seconds to wait for outstanding acks once typing stops.
+const DRAIN_SECS: u64 = 3;
+
+/// What the clients observed, shared between them.
+#[derive(Default)]
+struct LoadStats {
+    // Time from sending each acknowledged operation to its ack.
+    ack_latencies: Vec<Duration>,
+    updates: usize,
+    refusals: usize,
+    disconnects: usize,
+    // Hash of each document as of each version, to compare clients by.
+    versions: HashMap<(String, usize), u64>,
+    divergences: usize,
+}
+
+impl LoadStats {
+    // Compare a client's document at a version with what other clients saw.
+    fn observe(&mut self, page_id: &str, client: &Client) {
+        let json = serde_json::to_string(&client.client_doc.original_doc).unwrap();
+        let mut hasher = DefaultHasher::new();
+        json.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        let key = (page_id.to_string(), client.client_doc.version);
+        let seen = *self.versions.entry(key).or_insert(hash);
+        if seen != hash {
+            eprintln!(
+                "(!) {:?} diverged at version {}",
+                page_id, client.client_doc.version
+            );
+            self.divergences += 1;
+        }
+    }
+}
+
+enum Event {
+    Task(Task),
+    Closed,
+}
+
+fn new_client(tx_client: Sender<FrontendCommand>, tx_sync: Sender<ServerCommand>) -> ProxyClient {
+    ProxyClient {
+        state: Client::new(
+            "$$$$$$",
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicBool::new(true)),
+        ),
+
+        tx_client,
+        tx_sync,
+    }
+}
+
+// Connect to sync, timing each operation from when it's sent until its ack.
+fn spawn_sync_connection(
+    url: String,
+    tx_event: Sender<Event>,
+    rx_sync: Receiver<ServerCommand>,
+    stats: Arc<Mutex<LoadStats>>,
+) {
+    thread::spawn(move || {
+        let sent = Arc::new(Mutex::new(HashMap::new()));
+        let _ = ws::connect(url, {
+            let tx_event = tx_event.clone();
+            move |out: ws::Sender| {
+                let rx_sync = rx_sync.clone();
+                let sent_by_us = sent.clone();
+                thread::spawn(move || {
+                    while let Ok(command) = rx_sync.recv() {
+                        if let ServerCommand::CommitOp(_, op_id, ..) = command {
+                            sent_by_us.lock().unwrap().insert(op_id, Instant::now());
+                        }
+                        if out.send(serde_json::to_string(&command).unwrap()).is_err() {
+                            break;
+                        }
+                    }
+                });
+
+                let tx_event = tx_event.clone();
+                let sent = sent.clone();
+                let stats = stats.clone();
+                MaybeDeflate::new(move |msg: ws::Message| {
+                    if let Ok(command) = serde_json::from_slice::<ClientCommand>(&msg.into_data()) {
+                        match command {
+                            ClientCommand::Ack(op_id, _) => {
+                                if let Some(start) = sent.lock().unwrap().remove(&op_id) {
+                                    stats.lock().unwrap().ack_latencies.push(start.elapsed());
+                                }
+                            }
+                            ClientCommand::Update(..) => stats.lock().unwrap().updates += 1,
+                            ClientCommand::Refuse(..) => stats.lock().unwrap().refusals += 1,
+                            _ => {}
+                        }
+                        let _ = tx_event.send(Event::Task(Task::ClientCommand(command)));
+                    }
+                    Ok(())
+                })
+            }
+        });
+
+        let _ = tx_event.send(Event::Closed);
+    });
+}
+
+// Type random letters, and now and then a space, at a steady rate.
+fn spawn_typist(tx_event: Sender<Event>, rate: f64, typing: Arc<AtomicBool>) {
+    thread::spawn(move || {
+        let mut rng = rand::thread_rng();
+        let interval = Duration::from_millis((1000.0 / rate) as u64);
+        while typing.load(Ordering::Relaxed) {
+            thread::sleep(interval);
+            let char_code = if rng.gen_weighted_bool(8) {
+                ' ' as u32
+            } else {
+                rng.gen_range('a' as u32, 'z' as u32 + 1)
+            };
+            let command = ControllerCommand::Character(char_code);
+            if tx_event.send(Event::Task(Task::ControllerCommand(command))).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+fn spawn_load_client(opt: &Opt, index: usize, stats: Arc<Mutex<LoadStats>>, typing: Arc<AtomicBool>) {
+    let page_id = format!("{}-{}", opt.prefix, index % opt.docs.max(1));
+    let url = format!(
+        "{}/$/ws/{}?protocol={}&user={}-{}",
+        opt.server, page_id, PROTOCOL_VERSION, opt.prefix, index
+    );
+    let rate = opt.rate;
+
+    thread::spawn(move || {
+        let (tx_event, rx_event) = unbounded();
+        let (tx_sync, rx_sync) = unbounded();
+        let (tx_client, rx_client) = unbounded();
+        let mut client = new_client(tx_client, tx_sync);
+
+        spawn_sync_connection(url, tx_event.clone(), rx_sync, stats.clone());
+        spawn_typist(tx_event, rate, typing);
+
+        while let Ok(event) = rx_event.recv() {
+            let task = match event {
+                Event::Task(task) => task,
+                Event::Closed => {
+                    stats.lock().unwrap().disconnects += 1;
+                    break;
+                }
+            };
+            let from_sync = match task {
+                Task::ClientCommand(..) => true,
+                _ => false,
+            };
+            if let Err(err) = client.handle_task(task) {
+                eprintln!("(!) client {} failed: {:?}", index, err);
+            }
+            if from_sync && client.state.client_id != "$$$$$$" {
+                stats.lock().unwrap().observe(&page_id, &client.state);
+            }
+
+            // Nothing renders what the client sends the frontend.
+            while let Ok(_) = rx_client.try_recv() {}
+        }
+    });
+}
+
+fn percentile(sorted: &[Duration], fraction: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::from_millis(0);
+    }
+    let index = ((sorted.len() as f64) * fraction).ceil() as usize;
+    sorted[index.max(1).min(sorted.len()) - 1]
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1_000_000.0
+}
+
+fn report(out: &mut impl Write, opt: &Opt, stats: &LoadStats, elapsed: Duration) -> Result<(), Error> {
+    let mut latencies = stats.ack_latencies.clone();
+    latencies.sort();
+    let seconds = millis(elapsed) / 1000.0;
+
+    writeln!(
+        out,
+        "{} clients on {} documents for {:.1}s",
+        opt.clients, opt.docs, seconds
+    )?;
+    writeln!(
+        out,
+        "operations acknowledged: {} ({:.1}/s)",
+        latencies.len(),
+        latencies.len() as f64 / seconds
+    )?;
+    writeln!(
+        out,
+        "ack latency: p50 {:.1}ms, p99 {:.1}ms, max {:.1}ms",
+        millis(percentile(&latencies, 0.5)),
+        millis(percentile(&latencies, 0.99)),
+        millis(percentile(&latencies, 1.0))
+    )?;
+    writeln!(out, "updates received: {}", stats.updates)?;
+    writeln!(out, "refusals: {}", stats.refusals)?;
+    writeln!(out, "disconnects: {}", stats.disconnects)?;
+    writeln!(out, "divergences: {}", stats.divergences)?;
+    Ok(())
+}
+
+// The clients report progress on stdout and stderr, so send those to a log
+// file, and return the original stdout for the report.
+fn redirect_output(path: &str) -> Result<File, Error> {
+    let log = OpenOptions::new().create(true).append(true).open(path)?;
+    let stdout = unsafe {
+        let stdout = libc::dup(libc::STDOUT_FILENO);
+        libc::dup2(log.as_raw_fd(), libc::STDOUT_FILENO);
+        libc::dup2(log.as_raw_fd(), libc::STDERR_FILENO);
+        File::from_raw_fd(stdout)
+    };
+    ::std::mem::forget(log);
+    Ok(stdout)
+}
+
+fn run(opt: Opt) -> Result<bool, Error> {
+    let mut out = redirect_output(&opt.log)?;
+
+    let stats = Arc::new(Mutex::new(LoadStats::default()));
+    let typing = Arc::new(AtomicBool::new(true));
+
+    let start = Instant::now();
+    for index in 0..opt.clients {
+        spawn_load_client(&opt, index, stats.clone(), typing.clone());
+    }
+
+    thread::sleep(Duration::from_secs(opt.duration));
+    typing.store(false, Ordering::Relaxed);
+    thread::sleep(Duration::from_secs(DRAIN_SECS));
+
+    let stats = stats.lock().unwrap();
+    report(&mut out, &opt, &stats, start.elapsed())?;
+    Ok(stats.divergences == 0)
+}
+
+pub fn main() {
+    let opt = Opt::from_args();
+    match run(opt) {
+        Ok(true) => ::std::process::exit(0),
+        Ok(false) => ::std::process::exit(1),
+        Err(err) => {
+            eprintln!("edit-loadtest: {}", err);
+            ::std::process::exit(1);
+        }
+    }
+}
@@ -23,11 +23,11 @@ use crossbeam_channel::{
 use edit_client::{
     log::*,
     proxy::ProxyClient,
-    state::ClientDoc,
     Client,
     ClientImpl,
 };
 use edit_common::commands::*;
+use edit_common::pretty::pretty_doc;
 use failure::Error;
 use std::io::prelude::*;
 use std::sync::{
@@ -46,14 +46,11 @@ fn init_new_client(
     let (tx_client, rx_client) = unbounded();
     let (tx_sync, rx_sync) = unbounded();
     let client = ProxyClient {
-        state: Client {
-            client_id: client_id.to_owned(),
-            client_doc: ClientDoc::new(),
-
-            monkey: Arc::new(AtomicBool::new(false)),
-            alive: Arc::new(AtomicBool::new(true)),
-            task_count: 0,
-        },
+        state: Client::new(
+            client_id,
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicBool::new(true)),
+        ),
 
         tx_client,
         tx_sync,
@@ -65,6 +62,9 @@ fn init_new_client(
 struct Opt {
     #[structopt(long = "filter")]
     filter: Option<String>,
+    // Print each client's document after its tasks.
+    #[structopt(long = "doc")]
+    doc: bool,
 }
 
 main!(|opts: Opt| {
@@ -114,6 +114,9 @@ main!(|opts: Opt| {
                 match clients.get_mut(&client_id) {
                     Some(&mut (ref mut client, _, _)) => {
                         client.handle_task(task)?;
+                        if opts.doc {
+                            println!("{}", pretty_doc(&client.state().client_doc.doc.0));
+                        }
                     }
                     None => {
                         panic!("Client {:?} was not set up.", client_id);
@@ -0,0 +1,419 @@
+//! Terminal frontend for the editor. Runs the native client, connects it to
+//! the sync server, and renders the document in the terminal so it can be
+//! edited over SSH.
+
+#![feature(extern_in_paths, crate_in_paths)]
+
+extern crate crossbeam_channel;
+extern crate edit_client;
+extern crate edit_common;
+extern crate failure;
+extern crate libc;
+extern crate oatie;
+extern crate serde_json;
+extern crate structopt;
+#[macro_use]
+extern crate structopt_derive;
+extern crate termion;
+extern crate ws;
+
+use extern::{
+    crossbeam_channel::{
+        unbounded,
+        Receiver,
+        Sender,
+    },
+    edit_client::{
+        proxy::*,
+        *,
+    },
+    edit_common::attachment::*,
+    edit_common::commands::*,
+    edit_common::protocol::PROTOCOL_VERSION,
+    edit_common::simple_ws::*,
+    failure::Error,
+    oatie::doc::*,
+    std::fs::OpenOptions,
+    std::io::{
+        self,
+        Write,
+    },
+    std::os::unix::io::AsRawFd,
+    std::sync::atomic::AtomicBool,
+    std::sync::Arc,
+    std::thread,
+    structopt::StructOpt,
+    termion::{
+        clear,
+        color,
+        cursor,
+        event::Key,
+        input::TermRead,
+        raw::IntoRawMode,
+        style,
+    },
+};
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "edit-tui", about = "Edit a document in your terminal.")]
+struct Opt {
+    #[structopt(help = "Page ID")]
+    page_id: String,
+
+    #[structopt(long = "server", help = "Sync server", default_value = "ws://127.0.0.1:8001")]
+    server: String,
+
+    #[structopt(long = "user", help = "User ID, to restore your caret when reconnecting")]
+    user: Option<String>,
+
+    #[structopt(long = "log", help = "File to write client output to", default_value = "/dev/null")]
+    log: String,
+
+    #[structopt(long = "emacs", help = "Use Emacs key bindings")]
+    emacs: bool,
+}
+
+enum Event {
+    Task(Task),
+    Quit,
+}
+
+// Lays out a document as terminal lines, soft-wrapping at the terminal width.
+struct Canvas<'a> {
+    client_id: &'a str,
+    width: usize,
+    lines: Vec<String>,
+    col: usize,
+    // Row and column of our focused caret.
+    cursor: Option<(usize, usize)>,
+}
+
+impl<'a> Canvas<'a> {
+    fn new(client_id: &'a str, width: usize) -> Canvas<'a> {
+        Canvas {
+            client_id,
+            width: width.max(1),
+            lines: vec![],
+            col: 0,
+            cursor: None,
+        }
+    }
+
+    fn new_line(&mut self, indent: &str) {
+        self.lines.push(indent.to_string());
+        self.col = indent.chars().count();
+    }
+
+    fn text(&mut self, text: &str, style: &str, indent: &str) {
+        let reset = style::Reset.to_string();
+        self.lines.last_mut().unwrap().push_str(style);
+        for c in text.chars() {
+            if c == '\n' || self.col >= self.width {
+                self.lines.last_mut().unwrap().push_str(&reset);
+                self.new_line(indent);
+                self.lines.last_mut().unwrap().push_str(style);
+                if c == '\n' {
+                    continue;
+                }
+            }
+            self.lines.last_mut().unwrap().push(c);
+            self.col += 1;
+        }
+        self.lines.last_mut().unwrap().push_str(&reset);
+    }
+
+    fn inline(&mut self, span: &DocSpan, base: &str, indent: &str) {
+        for elem in span {
+            match elem {
+                DocChars(text) => {
+                    let mut style = base.to_string();
+                    if let Some(styles) = text.styles() {
+                        if styles.contains_key(&Style::Bold) {
+                            style.push_str(&style::Bold.to_string());
+                        }
+                        if styles.contains_key(&Style::Italic) {
+                            style.push_str(&style::Italic.to_string());
+                        }
+                        if styles.contains_key(&Style::Link) {
+                            style.push_str(&style::Underline.to_string());
+                        }
+                        if styles.contains_key(&Style::Selected) {
+                            style.push_str(&style::Invert.to_string());
+                        }
+                    }
+                    self.text(text.as_str(), &style, indent);
+                }
+                DocGroup(attrs, inner) => {
+                    if attrs.get("tag").map(|x| x.as_str()) == Some("caret") {
+                        if attrs.get("client").map(|x| x.as_str()) == Some(self.client_id)
+                            && attrs.get("focus").map(|x| x.as_str()) == Some("true")
+                        {
+                            self.cursor = Some((self.lines.len() - 1, self.col));
+                        }
+                    } else {
+                        self.inline(inner, base, indent);
+                    }
+                }
+            }
+        }
+    }
+
+    fn block(&mut self, elem: &DocElement, marker: &str, indent: &str) {
+        let (attrs, span) = match elem {
+            DocGroup(attrs, span) => (attrs, span),
+            DocChars(..) => return,
+        };
+        let tag = attrs.get("tag").map(|x| x.as_str()).unwrap_or("");
+
+        if tag == "bullet" {
+            let inner_indent = format!("{}  ", indent);
+            for (i, child) in span.iter().enumerate() {
+                let marker = if i == 0 {
+                    format!("{}• ", marker)
+                } else {
+                    inner_indent.clone()
+                };
+                self.block(child, &marker, &inner_indent);
+            }
+            return;
+        }
+
+        self.new_line(marker);
+        match tag {
+            "hr" => {
+                let rule = "─".repeat(self.width.min(40));
+                self.text(&rule, "", indent);
+            }
+            "embed" => {
+                let url = attrs.get("url").map(|x| x.as_str()).unwrap_or("");
+                let dim = style::Faint.to_string();
+                self.text(&format!("[embed] {}", url), &dim, indent);
+            }
+            "attachment" => {
+                let (_, filename, size) = attachment_info(attrs);
+                let dim = style::Faint.to_string();
+                let label = format!("[attachment] {} ({})", filename, format_size(size));
+                self.text(&label, &dim, indent);
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = tag[1..].parse::<usize>().unwrap_or(1);
+                let prefix = format!("{} ", "#".repeat(level));
+                let bold = style::Bold.to_string();
+                self.text(&prefix, &bold, indent);
+                self.inline(span, &bold, indent);
+            }
+            "pre" | "html" => {
+                let code = color::Fg(color::Cyan).to_string();
+                self.inline(span, &code, indent);
+            }
+            _ => self.inline(span, "", indent),
+        }
+    }
+}
+
+fn render(out: &mut impl Write, client: &Client, page_id: &str) -> Result<(), Error> {
+    let (width, height) = termion::terminal_size()?;
+    let (width, height) = (width as usize, height as usize);
+
+    let mut canvas = Canvas::new(&client.client_id, width);
+    for elem in &client.client_doc.doc.0 {
+        canvas.block(elem, "", "");
+    }
+
+    // Scroll so our caret stays above the status line.
+    let rows = height.saturating_sub(1).max(1);
+    let (cursor_row, cursor_col) = canvas.cursor.unwrap_or((0, 0));
+    let offset = (cursor_row + 1).saturating_sub(rows);
+
+    write!(out, "{}{}", clear::All, cursor::Goto(1, 1))?;
+    for line in canvas.lines.iter().skip(offset).take(rows) {
+        write!(out, "{}\r\n", line)?;
+    }
+
+    let status = format!(
+        " {} · {} · {:?}{} · ^Q quit ",
+        page_id,
+        client.title.as_ref().map(|x| x.as_str()).unwrap_or("(untitled)"),
+        client.sync_status.unwrap_or(SyncStatus::Offline),
+        if client.read_only() { " · read-only" } else { "" },
+    );
+    write!(
+        out,
+        "{}{}{}{}",
+        cursor::Goto(1, height as u16),
+        style::Invert,
+        status.chars().take(width).collect::<String>(),
+        style::Reset,
+    )?;
+
+    write!(
+        out,
+        "{}",
+        cursor::Goto((cursor_col + 1) as u16, (cursor_row - offset + 1) as u16),
+    )?;
+    out.flush()?;
+    Ok(())
+}
+
+// Map terminal keys onto the key codes the client expects from browsers.
+fn key_command(key: Key) -> Option<ControllerCommand> {
+    Some(match key {
+        Key::Char('\n') => ControllerCommand::Keypress(13, false, false, false),
+        Key::Char('\t') => ControllerCommand::Keypress(9, false, false, false),
+        Key::Char(c) => ControllerCommand::Character(c as u32),
+        Key::Backspace => ControllerCommand::Keypress(8, false, false, false),
+        Key::Left => ControllerCommand::Keypress(37, false, false, false),
+        Key::Up => ControllerCommand::Keypress(38, false, false, false),
+        Key::Right => ControllerCommand::Keypress(39, false, false, false),
+        Key::Down => ControllerCommand::Keypress(40, false, false, false),
+        Key::Alt('b') => ControllerCommand::Keypress(37, false, false, true),
+        Key::Alt('f') => ControllerCommand::Keypress(39, false, false, true),
+        Key::Ctrl(c) if c.is_ascii_lowercase() => {
+            ControllerCommand::Keypress(c.to_ascii_uppercase() as u32, true, false, false)
+        }
+        _ => return None,
+    })
+}
+
+fn spawn_keys(tx_event: Sender<Event>) {
+    thread::spawn(move || {
+        for key in io::stdin().keys() {
+            let event = match key {
+                Ok(Key::Ctrl('q')) | Err(_) => Event::Quit,
+                Ok(key) => match key_command(key) {
+                    Some(command) => Event::Task(Task::ControllerCommand(command)),
+                    None => continue,
+                },
+            };
+            let quit = match event {
+                Event::Quit => true,
+                _ => false,
+            };
+            let _ = tx_event.send(event);
+            if quit {
+                break;
+            }
+        }
+    });
+}
+
+fn spawn_sync_connection(url: String, tx_event: Sender<Event>, rx_sync: Receiver<ServerCommand>) {
+    thread::spawn(move || {
+        let _ = ws::connect(url, {
+            let tx_event = tx_event.clone();
+            move |out: ws::Sender| {
+                // While we receive packets from the client, send them to sync.
+                let rx_sync = rx_sync.clone();
+                thread::spawn(move || {
+                    while let Ok(command) = rx_sync.recv() {
+                        if out.send(serde_json::to_string(&command).unwrap()).is_err() {
+                            break;
+                        }
+                    }
+                });
+
+                let tx_event = tx_event.clone();
+                MaybeDeflate::new(move |msg: ws::Message| {
+                    if let Ok(command) = serde_json::from_slice::<ClientCommand>(&msg.into_data()) {
+                        let _ = tx_event.send(Event::Task(Task::ClientCommand(command)));
+                    }
+                    Ok(())
+                })
+            }
+        });
+
+        let _ = tx_event.send(Event::Task(Task::ControllerCommand(
+            ControllerCommand::SyncConnection(false),
+        )));
+    });
+}
+
+// The client reports progress on stdout and stderr, which would corrupt the
+// screen, so send them to a log file and draw on the terminal directly.
+fn redirect_output(path: &str) -> Result<(), Error> {
+    let log = OpenOptions::new().create(true).append(true).open(path)?;
+    unsafe {
+        libc::dup2(log.as_raw_fd(), libc::STDOUT_FILENO);
+        libc::dup2(log.as_raw_fd(), libc::STDERR_FILENO);
+    }
+    ::std::mem::forget(log);
+    Ok(())
+}
+
+fn run(opt: Opt) -> Result<(), Error> {
+    let mut tty = termion::get_tty()?.into_raw_mode()?;
+    redirect_output(&opt.log)?;
+
+    let (tx_event, rx_event) = unbounded();
+    let (tx_sync, rx_sync) = unbounded();
+    let (tx_client, rx_client) = unbounded();
+
+    // Initialize logger.
+    ::edit_client::log::log_init(tx_sync.clone());
+
+    let mut client = ProxyClient {
+        state: Client::new(
+            "$$$$$$",
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicBool::new(true)),
+        ),
+
+        tx_client,
+        tx_sync,
+    };
+
+    if opt.emacs {
+        client.handle_task(Task::ControllerCommand(ControllerCommand::Keymap(Keymap::Emacs)))?;
+    }
+
+    let mut url = format!(
+        "{}/$/ws/{}?protocol={}",
+        opt.server, opt.page_id, PROTOCOL_VERSION
+    );
+    if let Some(ref user) = opt.user {
+        url.push_str(&format!("&user={}", user));
+    }
+    spawn_sync_connection(url, tx_event.clone(), rx_sync);
+    spawn_keys(tx_event);
+
+    while let Ok(event) = rx_event.recv() {
+        let mut error = None;
+        match event {
+            Event::Task(task) => {
+                if let Err(err) = client.handle_task(task) {
+                    error = Some(err.to_string());
+                }
+            }
+            Event::Quit => break,
+        }
+
+        // The terminal draws the document itself, but errors are worth
+        // showing.
+        while let Ok(command) = rx_client.try_recv() {
+            if let FrontendCommand::Error(message) = command {
+                error = Some(message);
+            }
+        }
+
+        render(&mut tty, &client.state, &opt.page_id)?;
+        if let Some(ref message) = error {
+            write!(tty, "{}{}", cursor::Goto(1, 1), message)?;
+            tty.flush()?;
+        }
+    }
+
+    write!(tty, "{}{}", clear::All, cursor::Goto(1, 1))?;
+    tty.flush()?;
+    Ok(())
+}
+
+pub fn main() {
+    let opt = Opt::from_args();
+    if let Err(err) = run(opt) {
+        // stderr may be redirected, so report on the terminal.
+        if let Ok(mut tty) = termion::get_tty() {
+            let _ = write!(tty, "edit-tui: {}\r\n", err);
+        }
+        ::std::process::exit(1);
+    }
+}
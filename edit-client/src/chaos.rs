@@ -0,0 +1,137 @@
+//! Network faults for the monkeys. While a client's monkey is running, the
+//! proxy can pass the frames between the client and sync through a chaos
+//! link, which delays and reorders some of them, so the monkeys exercise how
+//! the protocol copes with a slow network and not only the operations
+//! themselves. Frames are never dropped or duplicated: a websocket doesn't
+//! lose or repeat frames, and the protocol relies on that, so those faults
+//! would only fail the monkeys in ways no real client can.
+//!
+//! Faults are configured with EDIT_CHAOS, as the chance of each per frame:
+//! EDIT_CHAOS=delay=0.05,reorder=0.05. Delays are up to max_delay
+//! milliseconds, 500 unless given.
+
+use extern::{
+    crossbeam_channel::{
+        unbounded,
+        Sender,
+    },
+    failure::Error,
+    rand::{
+        self,
+        Rng,
+    },
+    std::env,
+    std::sync::atomic::{
+        AtomicBool,
+        Ordering,
+    },
+    std::sync::Arc,
+    std::thread,
+    std::time::Duration,
+};
+
+pub const CHAOS_VAR: &str = "EDIT_CHAOS";
+
+const DEFAULT_MAX_DELAY: u64 = 500;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChaosConfig {
+    pub delay: f64,
+    pub max_delay: u64,
+    pub reorder: f64,
+}
+
+impl ChaosConfig {
+    /// Parse a list like "delay=0.05,reorder=0.01".
+    pub fn parse(input: &str) -> Result<ChaosConfig, Error> {
+        let mut config = ChaosConfig {
+            delay: 0.0,
+            max_delay: DEFAULT_MAX_DELAY,
+            reorder: 0.0,
+        };
+        for setting in input.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()) {
+            let mut parts = setting.splitn(2, '=');
+            let (name, value) = match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) => (name.trim(), value.trim()),
+                _ => bail!("expected name=value in {:?}", setting),
+            };
+            if name == "max_delay" {
+                config.max_delay = value.parse()?;
+                continue;
+            }
+            let chance: f64 = value.parse()?;
+            if chance < 0.0 || chance > 1.0 {
+                bail!("chance of {} must be between 0 and 1, not {}", name, chance);
+            }
+            match name {
+                "delay" => config.delay = chance,
+                "reorder" => config.reorder = chance,
+                "drop" | "duplicate" => {
+                    bail!("a websocket can't {} frames, so the link doesn't", name)
+                }
+                _ => bail!("unknown network fault {:?}", name),
+            }
+        }
+        Ok(config)
+    }
+
+    /// The faults configured with EDIT_CHAOS, if any.
+    pub fn from_env() -> Option<ChaosConfig> {
+        let input = env::var(CHAOS_VAR).ok()?;
+        match ChaosConfig::parse(&input) {
+            Ok(config) => Some(config),
+            Err(err) => {
+                eprintln!("(!) ignoring {}: {}", CHAOS_VAR, err);
+                None
+            }
+        }
+    }
+}
+
+/// A sender which passes what it's sent on to `tx`, with faults while
+/// `monkey` is set. Frames held back to be reordered are sent after the
+/// next frame.
+pub fn chaos_link<T: Send + 'static>(
+    config: ChaosConfig,
+    monkey: Arc<AtomicBool>,
+    tx: Sender<T>,
+) -> Sender<T> {
+    let (tx_link, rx_link) = unbounded::<T>();
+    thread::spawn(move || {
+        let mut rng = rand::thread_rng();
+        let mut held: Option<T> = None;
+        while let Ok(frame) = rx_link.recv() {
+            if !monkey.load(Ordering::Relaxed) {
+                if let Some(frame) = held.take() {
+                    let _ = tx.send(frame);
+                }
+                let _ = tx.send(frame);
+                continue;
+            }
+
+            if held.is_none() && rng.next_f64() < config.reorder {
+                held = Some(frame);
+                continue;
+            }
+
+            let mut frames = vec![frame];
+            frames.extend(held.take());
+
+            if rng.next_f64() < config.delay {
+                let delay = rng.gen_range(0, config.max_delay + 1);
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(delay));
+                    for frame in frames {
+                        let _ = tx.send(frame);
+                    }
+                });
+            } else {
+                for frame in frames {
+                    let _ = tx.send(frame);
+                }
+            }
+        }
+    });
+    tx_link
+}
@@ -1,17 +1,43 @@
 use crate::{
     actions::*,
-    random::*,
+    autocorrect::Autocorrect,
+    debug::DebugHistory,
+    e2ee::*,
+    emacs::*,
+    emoji::*,
+    folds::*,
+    peer::*,
     state::*,
+    typography::*,
+    vim::*,
 };
+#[cfg(not(feature = "minimal"))]
+use crate::random::*;
 
 use edit_common::{
     commands::*,
-    doc_as_html,
-    markdown::doc_to_markdown,
+    comments::{
+        visible_threads,
+        CommentAction,
+        CommentThread,
+    },
+    crdt::{
+        initial_peer_doc,
+        CrdtDoc,
+    },
+    e2ee::DocKey,
+    embed::is_video_url,
+    highlight::CodeHighlight,
+    lint::BlockLints,
+    identity::UserIdentity,
+    outline::OutlineEntry,
+    pagination::PageBreak,
+    title::doc_title,
 };
 use failure::Error;
 use oatie::{
     doc::*,
+    intern::intern_op,
     validate::validate_doc,
     OT,
 };
@@ -38,8 +64,77 @@ impl<C: ClientImpl> KeyHandler<C> {
     }
 }
 
-fn key_handlers<C: ClientImpl>() -> Vec<KeyHandler<C>> {
-    vec![
+/// Undo our last edit that's still in the document.
+pub fn undo<C: ClientImpl>(client: &mut C) -> Result<(), Error> {
+    if client.state().read_only() {
+        return Ok(());
+    }
+    match client.state().client_doc.undo_op() {
+        Some(op) => client.client_op(move |_| Ok(op.clone())),
+        None => Ok(()),
+    }
+}
+
+// Bindings of a keymap preset. These are checked before the defaults.
+fn preset_key_handlers<C: ClientImpl>(keymap: Keymap) -> Vec<KeyHandler<C>> {
+    match keymap {
+        Keymap::Default => vec![],
+        Keymap::Emacs => vec![
+            // C-a
+            KeyHandler(
+                65,
+                true,
+                false,
+                false,
+                Box::new(|client| client.client_op(|doc| caret_block_edge(doc, false, false))),
+            ),
+            // C-e
+            KeyHandler(
+                69,
+                true,
+                false,
+                false,
+                Box::new(|client| client.client_op(|doc| caret_block_edge(doc, true, false))),
+            ),
+            // M-f
+            KeyHandler(
+                70,
+                false,
+                false,
+                true,
+                Box::new(|client| client.client_op(|doc| caret_word_move(doc, true))),
+            ),
+            // M-b
+            KeyHandler(
+                66,
+                false,
+                false,
+                true,
+                Box::new(|client| client.client_op(|doc| caret_word_move(doc, false))),
+            ),
+            // C-k
+            KeyHandler(
+                75,
+                true,
+                false,
+                false,
+                Box::new(|client| emacs_kill_line(client)),
+            ),
+            // C-y
+            KeyHandler(
+                89,
+                true,
+                false,
+                false,
+                Box::new(|client| emacs_yank(client)),
+            ),
+        ],
+    }
+}
+
+fn key_handlers<C: ClientImpl>(keymap: Keymap) -> Vec<KeyHandler<C>> {
+    let mut handlers = preset_key_handlers(keymap);
+    handlers.extend(vec![
         // backspace
         KeyHandler(
             8,
@@ -102,7 +197,7 @@ fn key_handlers<C: ClientImpl>() -> Vec<KeyHandler<C>> {
             false,
             false,
             false,
-            Box::new(|client| client.client_op(|doc| split_block(doc, false))),
+            Box::new(|client| client.client_op(|doc| enter_block(doc))),
         ),
         // enter
         KeyHandler(
@@ -118,7 +213,15 @@ fn key_handlers<C: ClientImpl>() -> Vec<KeyHandler<C>> {
             false,
             false,
             false,
-            Box::new(|client| client.client_op(|doc| toggle_list(doc))),
+            Box::new(|client| client.client_op(|doc| list_tab(doc))),
+        ),
+        // shift + tab
+        KeyHandler(
+            9,
+            false,
+            true,
+            false,
+            Box::new(|client| client.client_op(|doc| list_untab(doc))),
         ),
         // OPT-left
         KeyHandler(
@@ -136,6 +239,78 @@ fn key_handlers<C: ClientImpl>() -> Vec<KeyHandler<C>> {
             true,
             Box::new(|client| client.client_op(|doc| caret_word_move(doc, true))),
         ),
+        // OPT-up
+        KeyHandler(
+            38,
+            false,
+            false,
+            true,
+            Box::new(|client| client.client_op(|doc| move_block(doc, false))),
+        ),
+        // OPT-down
+        KeyHandler(
+            40,
+            false,
+            false,
+            true,
+            Box::new(|client| client.client_op(|doc| move_block(doc, true))),
+        ),
+        // CMD-l
+        KeyHandler(
+            76,
+            true,
+            false,
+            false,
+            Box::new(|client| client.client_op(|doc| caret_select_block(doc))),
+        ),
+        // CMD-shift-k
+        KeyHandler(
+            75,
+            true,
+            true,
+            false,
+            Box::new(|client| client.client_op(|doc| delete_block(doc))),
+        ),
+        // CMD-b
+        KeyHandler(
+            66,
+            true,
+            false,
+            false,
+            Box::new(|client| client.client_op(|doc| toggle_style(doc, Style::Bold, None))),
+        ),
+        // CMD-i
+        KeyHandler(
+            73,
+            true,
+            false,
+            false,
+            Box::new(|client| client.client_op(|doc| toggle_style(doc, Style::Italic, None))),
+        ),
+        // CMD-.
+        KeyHandler(
+            190,
+            true,
+            false,
+            false,
+            Box::new(|client| toggle_fold(client)),
+        ),
+        // CMD-shift-j
+        KeyHandler(
+            74,
+            true,
+            true,
+            false,
+            Box::new(|client| client.client_op(|doc| join_with_previous_block(doc))),
+        ),
+        // CMD-shift-h
+        KeyHandler(
+            72,
+            true,
+            true,
+            false,
+            Box::new(|client| client.client_op(|doc| cycle_heading(doc))),
+        ),
         // OPT-a
         KeyHandler(
             65,
@@ -144,10 +319,27 @@ fn key_handlers<C: ClientImpl>() -> Vec<KeyHandler<C>> {
             false,
             Box::new(|client| client.client_op(|doc| caret_select_all(doc))),
         ),
-    ]
+        // CMD-z
+        KeyHandler(
+            90,
+            true,
+            false,
+            false,
+            Box::new(|client| undo(client)),
+        ),
+        // escape
+        KeyHandler(
+            27,
+            false,
+            false,
+            false,
+            Box::new(|client| vim_escape(client)),
+        ),
+    ]);
+    handlers
 }
 
-pub fn button_handlers<C: ClientImpl>(state: Option<(String, bool)>) -> (Vec<Box<Fn(&mut C) -> Result<(), Error>>>, Vec<Ui>) {
+pub fn button_handlers<C: ClientImpl>(state: Option<FormatState>) -> (Vec<Box<Fn(&mut C) -> Result<(), Error>>>, Vec<Ui>) {
     let mut callbacks: Vec<Box<Fn(&mut C) -> Result<(), Error>>> = vec![];
     
     macro_rules! callback {
@@ -164,54 +356,59 @@ pub fn button_handlers<C: ClientImpl>(state: Option<(String, bool)>) -> (Vec<Box
             Ui::Button(
                 "Text".to_string(),
                 callback!(|client| client.client_op(|doc| replace_block(doc, "p"))),
-                state.as_ref().map(|x| x.0 == "p").unwrap_or(false),
+                state.as_ref().map(|x| x.block == "p").unwrap_or(false),
             ),
             Ui::Button(
                 "H1".to_string(),
                 callback!(|client| client.client_op(|doc| replace_block(doc, "h1"))),
                 // TODO i wish we could match on strings, use matches! here
-                state.as_ref().map(|x| x.0 == "h1").unwrap_or(false),
+                state.as_ref().map(|x| x.block == "h1").unwrap_or(false),
             ),
             Ui::Button(
                 "H2".to_string(),
                 callback!(|client| client.client_op(|doc| replace_block(doc, "h2"))),
-                state.as_ref().map(|x| x.0 == "h2").unwrap_or(false),
+                state.as_ref().map(|x| x.block == "h2").unwrap_or(false),
             ),
             Ui::Button(
                 "H3".to_string(),
                 callback!(|client| client.client_op(|doc| replace_block(doc, "h3"))),
-                state.as_ref().map(|x| x.0 == "h3").unwrap_or(false),
+                state.as_ref().map(|x| x.block == "h3").unwrap_or(false),
             ),
             Ui::Button(
                 "H4".to_string(),
                 callback!(|client| client.client_op(|doc| replace_block(doc, "h4"))),
-                state.as_ref().map(|x| x.0 == "h4").unwrap_or(false),
+                state.as_ref().map(|x| x.block == "h4").unwrap_or(false),
             ),
             Ui::Button(
                 "H5".to_string(),
                 callback!(|client| client.client_op(|doc| replace_block(doc, "h5"))),
-                state.as_ref().map(|x| x.0 == "h5").unwrap_or(false),
+                state.as_ref().map(|x| x.block == "h5").unwrap_or(false),
             ),
             Ui::Button(
                 "H6".to_string(),
                 callback!(|client| client.client_op(|doc| replace_block(doc, "h6"))),
-                state.as_ref().map(|x| x.0 == "h6").unwrap_or(false),
+                state.as_ref().map(|x| x.block == "h6").unwrap_or(false),
             ),
             Ui::Button(
                 "Code".to_string(),
                 callback!(|client| client.client_op(|doc| replace_block(doc, "pre"))),
-                state.as_ref().map(|x| x.0 == "pre").unwrap_or(false),
+                state.as_ref().map(|x| x.block == "pre").unwrap_or(false),
             ),
             Ui::Button(
                 "HTML".to_string(),
                 callback!(|client| client.client_op(|doc| replace_block(doc, "html"))),
-                state.as_ref().map(|x| x.0 == "html").unwrap_or(false),
+                state.as_ref().map(|x| x.block == "html").unwrap_or(false),
+            ),
+            Ui::Button(
+                "Definition".to_string(),
+                callback!(|client| client.client_op(|doc| insert_definition(doc))),
+                state.as_ref().map(|x| x.block == "dt" || x.block == "dd").unwrap_or(false),
             ),
         ]),
         Ui::Button(
             "List".to_string(),
             callback!(|client| client.client_op(|doc| toggle_list(doc))),
-            state.as_ref().map(|x| x.1).unwrap_or(false),
+            state.as_ref().map(|x| x.list_depth > 0).unwrap_or(false),
         ),
         Ui::Button(
             "HR".to_string(),
@@ -221,20 +418,18 @@ pub fn button_handlers<C: ClientImpl>(state: Option<(String, bool)>) -> (Vec<Box
         Ui::ButtonGroup(vec![
             Ui::Button(
                 "Bold".to_string(),
-                callback!(|client| client.client_op(|doc| apply_style(doc, Style::Bold, None))),
-                // state.as_ref().map(|x| x.0 == "html").unwrap_or(false),
-                false, // TODO what?
+                callback!(|client| client.client_op(|doc| toggle_style(doc, Style::Bold, None))),
+                state.as_ref().map(|x| x.styles.contains(&Style::Bold)).unwrap_or(false),
             ),
             Ui::Button(
                 "Italic".to_string(),
-                callback!(|client| client.client_op(|doc| apply_style(doc, Style::Italic, None))),
-                // state.as_ref().map(|x| x.0 == "html").unwrap_or(false),
-                false, // TODO what?
+                callback!(|client| client.client_op(|doc| toggle_style(doc, Style::Italic, None))),
+                state.as_ref().map(|x| x.styles.contains(&Style::Italic)).unwrap_or(false),
             ),
             Ui::Button(
                 "Clear".to_string(),
-                callback!(|client| client.client_op(|doc| remove_styles(doc, hashset![Style::Bold, Style::Italic, Style::Link]))),
-                // state.as_ref().map(|x| x.0 == "html").unwrap_or(false),
+                callback!(|client| client.client_op(|doc| remove_styles(doc, hashset![Style::Bold, Style::Italic, Style::Link, Style::FontSize, Style::FontFamily]))),
+                // state.as_ref().map(|x| x.block == "html").unwrap_or(false),
                 false, // TODO what?
             ),
         ]),
@@ -243,6 +438,19 @@ pub fn button_handlers<C: ClientImpl>(state: Option<(String, bool)>) -> (Vec<Box
     (callbacks, ui)
 }
 
+/// Pick the cursor at `pos` (from 0 to 1) among every cursor position.
+#[cfg(not(feature = "minimal"))]
+fn random_target(doc: &Doc, pos: f64) -> Result<CurSpan, Error> {
+    let cursors = random_cursor(doc)?;
+    let idx = (pos * (cursors.len() as f64)) as usize;
+    Ok(cursors[idx].clone())
+}
+
+#[cfg(feature = "minimal")]
+fn random_target(_doc: &Doc, _pos: f64) -> Result<CurSpan, Error> {
+    bail!("random targets aren't available in minimal builds");
+}
+
 fn native_command<C: ClientImpl>(client: &mut C, req: ControllerCommand) -> Result<(), Error> {
     match req {
         ControllerCommand::RenameGroup(tag, _) => {
@@ -260,35 +468,66 @@ fn native_command<C: ClientImpl>(client: &mut C, req: ControllerCommand) -> Resu
                 key_code, meta_key, shift_key, alt_key
             );
 
+            // Outside of Vim's insert mode, backspace and enter are motions.
+            if vim_intercepts(client) && !meta_key && !shift_key && !alt_key {
+                match key_code {
+                    8 => return vim_char(client, 'h'),
+                    13 => return vim_char(client, 'j'),
+                    _ => {}
+                }
+            }
+
             // Find which key handler to process this command.
-            for command in key_handlers() {
+            let keymap = client.state().keymap;
+            for command in key_handlers(keymap) {
                 if command.matches(key_code, meta_key, shift_key, alt_key) {
                     command.invoke(client)?;
                     break;
                 }
             }
+
+            // Keep the caret out of folded sections, moving on in the
+            // direction it was going.
+            let hidden = {
+                let client_doc = &client.state().client_doc;
+                client_doc.folds.hidden(&client_doc.doc)
+            };
+            if hidden.iter().any(|x| *x) {
+                let increase = key_code != 37 && key_code != 38;
+                client.client_op(|doc| caret_skip_hidden(doc, &hidden, increase))?;
+            }
         }
         ControllerCommand::Character(char_code) => {
+            if vim_intercepts(client) {
+                return vim_char(client, from_u32(char_code).unwrap_or('\0'));
+            }
+
+            let rules = client.state().input_rules.clone();
+            let autocorrect = client.state().autocorrect.clone();
             client.client_op(|doc| {
                 let c: char = from_u32(char_code).unwrap_or('?');
                 if c == '\0' {
                     bail!("expected non-null character");
                 }
 
-                add_string(doc, &format!("{}", c))
+                add_char_with_rules(doc, &rules, &autocorrect, c)
             })?;
         }
         ControllerCommand::InsertText(text) => {
-            client.client_op(|doc| add_string(doc, &text))?;
+            // Pasting a link to a video embeds it.
+            if is_video_url(&text) {
+                client.client_op(|doc| insert_embed(doc, &text))?;
+            } else {
+                client.client_op(|doc| add_string(doc, &text))?;
+            }
         }
         ControllerCommand::RandomTarget(pos) => {
             // TODO this should never happen, because we clarify RandomTarget
             // beforehand
 
-            let cursors = random_cursor(&client.state().client_doc.doc)?;
-            let idx = (pos * (cursors.len() as f64)) as usize;
+            let target = random_target(&client.state().client_doc.doc, pos)?;
 
-            client.client_op(|doc| cur_to_caret(doc, &cursors[idx], true))?;
+            client.client_op(|doc| cur_to_caret(doc, &target, true))?;
         }
         ControllerCommand::Cursor(focus, anchor) => {
             match (focus, anchor) {
@@ -313,6 +552,166 @@ fn native_command<C: ClientImpl>(client: &mut C, req: ControllerCommand) -> Resu
             println!("received monkey setting: {:?}", setting);
             client.state().monkey.store(setting, Ordering::Relaxed);
         }
+        ControllerCommand::DebugDump => {
+            let dump = client.state().debug.dump()?;
+            client.send_client(&FrontendCommand::DebugDump(dump))?;
+        }
+        ControllerCommand::SyncConnection(connected) => {
+            client.state().connected = connected;
+        }
+        ControllerCommand::Stats => {
+            let stats = client.state().client_doc.stats.stats();
+            client.send_client(&FrontendCommand::Stats(stats))?;
+        }
+        ControllerCommand::TakeOver => {
+            let client_id = client.state().client_id.clone();
+            client.send_sync(ServerCommand::TakeOver(client_id))?;
+        }
+        ControllerCommand::Duplicate(history) => {
+            let client_id = client.state().client_id.clone();
+            client.send_sync(ServerCommand::Duplicate(client_id, history))?;
+        }
+        ControllerCommand::MergeFork => {
+            let client_id = client.state().client_id.clone();
+            client.send_sync(ServerCommand::MergeFork(client_id))?;
+        }
+        ControllerCommand::Find(query) => {
+            let client_id = client.state().client_id.clone();
+            let version = client.state().client_doc.version;
+            client.send_sync(ServerCommand::Find(client_id, query, version))?;
+        }
+        ControllerCommand::SelectRange(anchor, focus) => {
+            client.client_op(|doc| caret_select_range(doc, anchor, focus))?;
+        }
+        ControllerCommand::Vim(enabled) => {
+            vim_enable(client, enabled)?;
+        }
+        ControllerCommand::InputRule(rule, enabled) => {
+            let rules = &mut client.state().input_rules;
+            rules.retain(|x| *x != rule);
+            if enabled {
+                rules.push(rule);
+            }
+        }
+        ControllerCommand::ToggleFold => {
+            toggle_fold(client)?;
+        }
+        ControllerCommand::MoveSection(heading_id, new_index) => {
+            client.client_op(|doc| move_section(doc, &heading_id, new_index))?;
+        }
+        ControllerCommand::SelectWord => {
+            client.client_op(|doc| caret_select_word(doc))?;
+        }
+        ControllerCommand::EmojiCompletions => {
+            let before = client
+                .with_action_context(|ctx| Ok(text_before_caret(&ctx, MAX_SHORTCODE_LEN + 1)))?;
+            let completions = match partial_shortcode(&before) {
+                Some(code) if !code.is_empty() => emoji_completions(code, EMOJI_COMPLETIONS),
+                _ => vec![],
+            };
+            client.send_client(&FrontendCommand::EmojiCompletions(completions))?;
+        }
+        ControllerCommand::CompleteEmoji(shortcode) => {
+            client.client_op(|doc| complete_emoji(doc, &shortcode))?;
+        }
+        ControllerCommand::InsertMath(latex) => {
+            client.client_op(|doc| insert_math(doc, &latex))?;
+        }
+        ControllerCommand::EditMath(latex) => {
+            client.client_op(|doc| edit_math(doc, &latex))?;
+        }
+        ControllerCommand::InsertImage(src) => {
+            client.client_op(|doc| insert_image(doc, &src))?;
+        }
+        ControllerCommand::InsertDocLink(page_id) => {
+            client.client_op(|doc| insert_doc_link(doc, &page_id))?;
+        }
+        ControllerCommand::InsertMention(user_id, name) => {
+            client.client_op(|doc| insert_mention(doc, &user_id, &name))?;
+        }
+        ControllerCommand::InsertAttachment(url, filename, size) => {
+            client.client_op(|doc| insert_attachment(doc, &url, &filename, size))?;
+        }
+        ControllerCommand::InsertEmbed(url) => {
+            client.client_op(|doc| insert_embed(doc, &url))?;
+        }
+        ControllerCommand::RenderMode(mode) => {
+            client.client_op(|doc| set_render_mode(doc, mode.as_ref().map(|x| x.as_str())))?;
+        }
+        ControllerCommand::CodeLanguage(lang) => {
+            client.client_op(|doc| set_code_language(doc, lang.as_ref().map(|x| x.as_str())))?;
+        }
+        ControllerCommand::Align(align) => {
+            client.client_op(|doc| set_alignment(doc, align.as_ref().map(|x| x.as_str())))?;
+        }
+        ControllerCommand::EditLink(link) => {
+            client.client_op(|doc| edit_link(doc, link.clone()))?;
+        }
+        ControllerCommand::FontSize(size) => {
+            client.client_op(|doc| set_font_size(doc, size.as_ref().map(|x| x.as_str())))?;
+        }
+        ControllerCommand::FontFamily(family) => {
+            client.client_op(|doc| set_font_family(doc, family.as_ref().map(|x| x.as_str())))?;
+        }
+        ControllerCommand::ScrollTo(id) => {
+            client.client_op(|doc| caret_to_block(doc, &id))?;
+            client.send_client(&FrontendCommand::ScrollTo(id))?;
+        }
+        ControllerCommand::Keymap(keymap) => {
+            client.state().keymap = keymap;
+            let state = if client.with_action_context(|doc| Ok(has_caret(doc, true)))? {
+                Some(client.with_action_context(|doc| format_state(doc))?)
+            } else {
+                None
+            };
+            client.setup_controls(keymap, state);
+        }
+        ControllerCommand::EncryptionKey(..) | ControllerCommand::PeerSync => {
+            // Taken by handle_task, since it's needed before we're connected.
+        }
+        ControllerCommand::StartComment(body) => {
+            let anchor = match client.with_action_context(|doc| caret_block_id(doc))? {
+                Some(anchor) => anchor,
+                None => bail!("the caret block has no id to comment on"),
+            };
+            let client_id = client.state().client_id.clone();
+            client.send_sync(ServerCommand::Comment(client_id, CommentAction::Start(anchor, body)))?;
+        }
+        ControllerCommand::Comment(action) => {
+            let client_id = client.state().client_id.clone();
+            client.send_sync(ServerCommand::Comment(client_id, action))?;
+        }
+        ControllerCommand::ShowResolvedComments(show) => {
+            client.state().show_resolved_comments = show;
+            let threads = visible_threads(&client.state().comments, show);
+            client.send_client(&FrontendCommand::Comments(threads))?;
+        }
+        ControllerCommand::Autocorrect(pattern, replacement) => {
+            client
+                .state()
+                .autocorrect
+                .set_rule(&pattern, replacement.as_ref().map(|x| x.as_str()))?;
+        }
+        ControllerCommand::AutocorrectDictionary(rules) => {
+            client.state().autocorrect.load_dictionary(&rules);
+        }
+        ControllerCommand::Paginate(layout) => {
+            // Pages are marked in the rendered document, so render it again.
+            let res = {
+                let client_doc = &mut client.state().client_doc;
+                client_doc.pagination.set_layout(layout, &client_doc.doc);
+                FrontendCommand::Update(client_doc.to_html(), client_doc.to_markdown()?, None)
+            };
+            client.send_client(&res)?;
+            client.update_page_breaks()?;
+        }
+        ControllerCommand::Lint(config) => {
+            {
+                let client_doc = &mut client.state().client_doc;
+                client_doc.lint.set_config(config, &client_doc.doc);
+            }
+            client.update_lints()?;
+        }
     }
     Ok(())
 }
@@ -330,6 +729,107 @@ pub struct Client {
     pub monkey: Arc<AtomicBool>,
     pub alive: Arc<AtomicBool>,
     pub task_count: usize,
+
+    // Recent tasks and the operations they generated, for debugging.
+    pub debug: DebugHistory,
+
+    // Last title announced to the frontend.
+    pub title: Option<String>,
+    // Last outline announced to the frontend.
+    pub outline: Vec<OutlineEntry>,
+    // Last code highlighting announced to the frontend.
+    pub highlights: Vec<CodeHighlight>,
+    // Last readability issues announced to the frontend.
+    pub lints: Vec<BlockLints>,
+    // Last page breaks announced to the frontend.
+    pub page_breaks: Vec<PageBreak>,
+
+    // Whether we're connected to the sync server, and the last sync status
+    // announced to the frontend.
+    pub connected: bool,
+    pub sync_status: Option<SyncStatus>,
+
+    // Whether the server runs in single-writer mode, and which client holds
+    // the write lock.
+    pub single_writer: bool,
+    pub writer: Option<String>,
+
+    // What our share link allows, if we connected with one.
+    pub permission: SharePermission,
+
+    // Client ids and users of everyone editing the document.
+    pub users: Vec<(String, UserIdentity)>,
+
+    // Comment threads of the document, and whether resolved ones are shown.
+    pub comments: Vec<CommentThread>,
+    pub show_resolved_comments: bool,
+
+    // Modal editing state, if Vim mode is on.
+    pub vim: Option<VimState>,
+
+    // Active key binding preset, and text killed with its commands.
+    pub keymap: Keymap,
+    pub kill_ring: Vec<String>,
+
+    // Typing substitutions that are turned on.
+    pub input_rules: Vec<InputRule>,
+    // Misspellings the Autocorrect input rule fixes.
+    pub autocorrect: Autocorrect,
+
+    // Last formatting state announced to the frontend.
+    pub format_state: Option<FormatState>,
+
+    // Key of the page, if it's end-to-end encrypted.
+    pub encryption: Option<DocKey>,
+
+    // Our replica of the page, if it's synced between peers.
+    pub peer: Option<CrdtDoc>,
+}
+
+impl Client {
+    /// A client that hasn't been sent its document yet. `monkey` turns on
+    /// random edits, and clearing `alive` stops them.
+    pub fn new(client_id: &str, monkey: Arc<AtomicBool>, alive: Arc<AtomicBool>) -> Client {
+        Client {
+            client_id: client_id.to_owned(),
+            client_doc: ClientDoc::new(),
+
+            monkey,
+            alive,
+            task_count: 0,
+
+            debug: DebugHistory::new(),
+            title: None,
+            outline: vec![],
+            highlights: vec![],
+            lints: vec![],
+            page_breaks: vec![],
+
+            connected: true,
+            sync_status: None,
+            single_writer: false,
+            writer: None,
+            permission: SharePermission::Edit,
+            users: vec![],
+            comments: vec![],
+            show_resolved_comments: false,
+            vim: None,
+            keymap: Keymap::Default,
+            kill_ring: vec![],
+            input_rules: vec![],
+            autocorrect: Autocorrect::new(),
+            format_state: None,
+            encryption: None,
+            peer: None,
+        }
+    }
+
+    /// Whether another client holds the write lock, or our share link
+    /// doesn't allow editing.
+    pub fn read_only(&self) -> bool {
+        !self.permission.can_edit()
+            || (self.single_writer && self.writer.as_ref() != Some(&self.client_id))
+    }
 }
 
 /// Trait shared by the "wasm" and "client proxy" implementations.
@@ -339,14 +839,14 @@ pub trait ClientImpl {
     fn send_client(&self, req: &FrontendCommand) -> Result<(), Error>;
     fn send_sync(&self, req: ServerCommand) -> Result<(), Error>;
 
-    fn setup_controls(&self, state: Option<(String, bool)>)
+    fn setup_controls(&self, keymap: Keymap, state: Option<FormatState>)
     where
         Self: Sized,
     {
         self.send_client(&FrontendCommand::Controls(Controls{
-            keys: key_handlers::<Self>()
+            keys: key_handlers::<Self>(keymap)
                 .into_iter()
-                .map(|x| (x.0, x.1, x.2))
+                .map(|x| (x.0, x.1, x.2, x.3))
                 .collect(),
             buttons: button_handlers::<Self>(state).1
         })).expect("Could not send initial state");
@@ -376,19 +876,104 @@ pub trait ClientImpl {
                 // Rewrite random targets here.
                 if let Task::ControllerCommand(ControllerCommand::RandomTarget(pos)) = value
                 {
-                    let cursors = random_cursor(&self.state().client_doc.doc)?;
-                    let idx = (pos * (cursors.len() as f64)) as usize;
+                    let target = random_target(&self.state().client_doc.doc, pos)?;
 
                     value = Task::ControllerCommand(ControllerCommand::Cursor(
-                        Some(cursors[idx].clone()),
+                        Some(target),
                         None,
                     ));
                 }
 
+                // The key is needed before we connect, to open the page's
+                // operations.
+                if let Task::ControllerCommand(ControllerCommand::EncryptionKey(ref page_id, ref key)) = value {
+                    self.state().encryption = Some(DocKey::from_base64(key, page_id)?);
+                    return Ok(());
+                }
+                if let Task::ControllerCommand(ControllerCommand::PeerSync) = value {
+                    self.state().peer = Some(CrdtDoc::from_doc(0, &initial_peer_doc()));
+                    return Ok(());
+                }
+
+                // An initial sync arriving in chunks is collected until its
+                // last chunk, then handled as one Init.
+                let chunk = match value {
+                    Task::ClientCommand(ClientCommand::InitChunk(ref client_id, version, index, count, ref span)) => {
+                        let doc = self
+                            .state()
+                            .client_doc
+                            .receive_chunk(version, index, count, span.clone());
+                        Some((client_id.clone(), version, count, doc))
+                    }
+                    Task::ClientCommand(ClientCommand::InitText(ref client_id, version, index, count, ref text)) => {
+                        let doc = self
+                            .state()
+                            .client_doc
+                            .receive_text(version, index, count, text)?;
+                        Some((client_id.clone(), version, count, doc))
+                    }
+                    _ => None,
+                };
+                if let Some((client_id, version, count, doc)) = chunk {
+                    let received = match doc {
+                        Some(_) => count,
+                        None => self
+                            .state()
+                            .client_doc
+                            .resume_point()
+                            .map(|(_, received)| received)
+                            .unwrap_or(0),
+                    };
+                    self.send_client(&FrontendCommand::SyncProgress(version, received, count))?;
+                    match doc {
+                        Some(doc) => {
+                            value = Task::ClientCommand(ClientCommand::Init(client_id, doc, version));
+                        }
+                        None => return Ok(()),
+                    }
+                }
+
+                // Open commands for encrypted pages.
+                if let Some(key) = self.state().encryption.clone() {
+                    value = match value {
+                        Task::ClientCommand(command) => {
+                            let original = &self.state().client_doc.original_doc;
+                            Task::ClientCommand(open_client_command(&key, original, command)?)
+                        }
+                        value => value,
+                    };
+                }
+
+                // Open commands for peer pages.
+                if self.state().peer.is_some() {
+                    value = match value {
+                        Task::ClientCommand(command) => {
+                            let state = self.state();
+                            Task::ClientCommand(open_peer_command(
+                                state.peer.as_mut().unwrap(),
+                                &state.client_doc.original_doc,
+                                state.client_doc.version,
+                                command,
+                            ))
+                        }
+                        value => value,
+                    };
+                }
+
                 if !delay_log {
                     log_wasm!(Task(self.state().client_id.clone(), value.clone()));
                 }
 
+                // Don't record dump requests, so they don't push out the
+                // tasks being inspected.
+                let record = match value {
+                    Task::ControllerCommand(ControllerCommand::DebugDump) => false,
+                    _ => true,
+                };
+                if record {
+                    self.state().debug.begin(&value);
+                }
+
                 match value.clone() {
                     // Handle commands from Native.
                     Task::ControllerCommand(command) => {
@@ -407,7 +992,18 @@ pub trait ClientImpl {
                         version,
                     )) => {
                         self.state().client_id = new_client_id.clone();
-                        self.state().client_doc.init(&Doc(doc_span), version);
+                        self.state().connected = true;
+                        let doc = Doc(doc_span);
+
+                        // After a refusal, roll back the refused operation and
+                        // keep the ones we made since.
+                        let correcting = self.state().client_doc.awaiting_correction;
+                        if correcting {
+                            self.state().client_doc.rollback(&doc, version);
+                        } else if !self.state().client_doc.init(&doc, version) {
+                            eprintln!("(!) dropped edits that didn't fit the synced document");
+                        }
+                        let resend = self.state().client_doc.pending_op.clone();
 
                         // Announce.
                         println!("inital version is {:?}", version);
@@ -427,14 +1023,41 @@ pub trait ClientImpl {
                         let res = FrontendCommand::Init(new_client_id);
                         self.send_client(&res).unwrap();
 
+                        if self.state().single_writer || !self.state().permission.can_edit() {
+                            let read_only = self.state().read_only();
+                            self.send_client(&FrontendCommand::ReadOnly(read_only))?;
+                        }
+
                         // Native drives client state.
                         let state = self.state();
                         let res = FrontendCommand::Update(
-                            doc_as_html(&state.client_doc.doc.0),
-                            doc_to_markdown(&state.client_doc.doc.0).unwrap(),
+                            state.client_doc.to_html(),
+                            state.client_doc.to_markdown().unwrap(),
                             None,
                         );
                         self.send_client(&res).unwrap();
+
+                        self.update_title()?;
+
+                        self.update_outline()?;
+
+                        self.update_highlights()?;
+
+                        self.update_lints()?;
+
+                        self.update_page_breaks()?;
+
+                        if correcting {
+                            self.send_client(&FrontendCommand::Correcting(false))?;
+                        }
+
+                        // Our operations sync hasn't confirmed are sent again,
+                        // the pending one with the id it was first sent with.
+                        if let Some(pending_op) = resend {
+                            self.upload(pending_op)?;
+                        } else if let Some(local_op) = self.state().client_doc.next_payload() {
+                            self.upload(local_op)?;
+                        }
                     }
 
                     // Sync sent us an Update command with a new document version.
@@ -447,11 +1070,15 @@ pub trait ClientImpl {
                             return Ok(());
                         }
 
+                        // Its text shares the styles already in our documents.
+                        let input_op = intern_op(&input_op, &mut self.state().client_doc.interner);
+
                         // Generated from original_doc transformed with input_op
                         let doc = Op::apply(&self.state().client_doc.original_doc, &input_op);
 
-                        // If this operation is an acknowledgment...
-                        if self.state().client_id == client_id {
+                        // Operations we gave an id are acknowledged with Ack,
+                        // so only encrypted pages recognize our own here.
+                        if self.state().encryption.is_some() && self.state().client_id == client_id {
                             if let Some(local_op) = self
                                 .state()
                                 .client_doc
@@ -484,11 +1111,155 @@ pub trait ClientImpl {
                         // Native drives client state.
                         let state = self.state();
                         let res = FrontendCommand::Update(
-                            doc_as_html(&state.client_doc.doc.0),
-                            doc_to_markdown(&state.client_doc.doc.0).unwrap(),
+                            state.client_doc.to_html(),
+                            state.client_doc.to_markdown().unwrap(),
                             None,
                         );
                         self.send_client(&res).unwrap();
+
+                        self.update_title()?;
+
+                        self.update_outline()?;
+
+                        self.update_highlights()?;
+
+                        self.update_lints()?;
+
+                        self.update_page_breaks()?;
+                    }
+
+                    // Sync committed our pending operation.
+                    Task::ClientCommand(ClientCommand::Ack(op_id, version)) => {
+                        if self.state().client_id == "$$$$$$" {
+                            return Ok(());
+                        }
+
+                        if let Some(local_op) = self
+                            .state()
+                            .client_doc
+                            .sync_acknowledged(op_id, version)
+                        {
+                            // Send our next operation.
+                            self.upload(local_op)?;
+                        }
+                    }
+
+                    // Sync refused our last operation. It resends the document
+                    // with an Init command right after this one.
+                    Task::ClientCommand(ClientCommand::Refuse(reason)) => {
+                        println!("sync refused our operation: {:?}", reason);
+
+                        // Encrypted pages refuse operations that fell behind,
+                        // which by now are transformed against what we missed.
+                        if self.state().encryption.is_some() {
+                            if let Some(pending_op) = self.state().client_doc.pending_op.clone() {
+                                self.upload(pending_op)?;
+                            }
+                            return Ok(());
+                        }
+
+                        // Our document is speculative until the Init that
+                        // follows, which it's rolled back to.
+                        self.state().client_doc.awaiting_correction = true;
+                        self.send_client(&FrontendCommand::Correcting(true))?;
+
+                        let message = match reason {
+                            RefusalReason::TooLarge(limit, max) => format!(
+                                "The edit would make the document too large ({:?} is limited to {}), so it was undone.",
+                                limit, max
+                            ),
+                            reason => format!(
+                                "The server refused an edit and is resynchronizing the document: {:?}",
+                                reason
+                            ),
+                        };
+                        self.send_client(&FrontendCommand::Error(message))?;
+                    }
+
+                    Task::ClientCommand(ClientCommand::Mentions(mentions)) => {
+                        self.send_client(&FrontendCommand::Mentions(mentions))?;
+                    }
+
+                    Task::ClientCommand(ClientCommand::Comments(threads)) => {
+                        let visible = visible_threads(&threads, self.state().show_resolved_comments);
+                        self.state().comments = threads;
+                        self.send_client(&FrontendCommand::Comments(visible))?;
+                    }
+
+                    Task::ClientCommand(ClientCommand::Users(users)) => {
+                        self.state().users = users.clone();
+                        self.send_client(&FrontendCommand::Users(users))?;
+                    }
+
+                    // Our share link's permission, announced with Init.
+                    Task::ClientCommand(ClientCommand::Permission(permission)) => {
+                        self.state().permission = permission;
+                    }
+
+                    // The write lock changed hands. This is sent before Init
+                    // when connecting, so we know whether to add a caret.
+                    Task::ClientCommand(ClientCommand::Writer(writer)) => {
+                        self.state().single_writer = true;
+                        self.state().writer = writer;
+
+                        if self.state().client_id == "$$$$$$" {
+                            return Ok(());
+                        }
+
+                        let read_only = self.state().read_only();
+                        self.send_client(&FrontendCommand::ReadOnly(read_only))?;
+
+                        // Having taken over, we may need a caret to edit with.
+                        if !read_only
+                            && !self
+                                .with_action_context(|ctx| Ok(has_caret(ctx, true)))
+                                .ok()
+                                .unwrap_or(true)
+                        {
+                            self.client_op(|doc| init_caret(doc))?;
+                        }
+                    }
+
+                    Task::ClientCommand(ClientCommand::Duplicated(page_id)) => {
+                        self.send_client(&FrontendCommand::Duplicated(page_id))?;
+                    }
+
+                    Task::ClientCommand(ClientCommand::PageTitles(titles)) => {
+                        self.send_client(&FrontendCommand::PageTitles(titles))?;
+                    }
+
+                    // These are opened into Init and Update when we have the
+                    // page's key.
+                    Task::ClientCommand(ClientCommand::EncryptedInit(..))
+                    | Task::ClientCommand(ClientCommand::EncryptedUpdate(..)) => {
+                        bail!("received an encrypted page without its key");
+                    }
+
+                    // These are opened into Init and Update on peer pages.
+                    Task::ClientCommand(ClientCommand::PeerInit(..))
+                    | Task::ClientCommand(ClientCommand::PeerOps(..)) => {
+                        bail!("received a peer page without peer sync");
+                    }
+
+                    // Chunks were collected into an Init above.
+                    Task::ClientCommand(ClientCommand::InitChunk(..))
+                    | Task::ClientCommand(ClientCommand::InitText(..)) => {}
+
+                    // Matches are marked against the last version we got from
+                    // sync, and carried over our own edits since.
+                    Task::ClientCommand(ClientCommand::FindResults(query, version, count, op)) => {
+                        let positions = {
+                            let client_doc = &self.state().client_doc;
+                            if client_doc.version != version {
+                                return Ok(());
+                            }
+                            let op = client_doc.transform_to_local(&op);
+                            let doc = Op::apply(&client_doc.doc, &op);
+                            let mut positions = client_doc.positions.clone();
+                            positions.apply(&doc, &op);
+                            find_positions(&doc, &positions, count)
+                        };
+                        self.send_client(&FrontendCommand::FindResults(query, positions))?;
                     }
                 }
 
@@ -508,6 +1279,13 @@ pub trait ClientImpl {
                     log_wasm!(Task(self.state().client_id.clone(), value.clone()));
                 }
 
+                if record {
+                    let state = self.state();
+                    state.debug.finish(&state.client_doc.doc);
+                }
+
+                self.update_sync_status()?;
+
                 Ok(())
             },
         ));
@@ -523,11 +1301,134 @@ pub trait ClientImpl {
         }
     }
 
+    /// Announce the document title to the frontend if it has changed.
+    fn update_title(&mut self) -> Result<(), Error> {
+        let title = doc_title(&self.state().client_doc.doc.0);
+        if title != self.state().title {
+            self.state().title = title.clone();
+            self.send_client(&FrontendCommand::Title(title))?;
+        }
+        Ok(())
+    }
+
+    /// Announce the document's headings to the frontend if they have
+    /// changed.
+    fn update_outline(&mut self) -> Result<(), Error> {
+        let outline = {
+            let client_doc = &mut self.state().client_doc;
+            client_doc.outline.outline(&client_doc.doc)
+        };
+        if outline != self.state().outline {
+            self.state().outline = outline.clone();
+            self.send_client(&FrontendCommand::Outline(outline))?;
+        }
+        Ok(())
+    }
+
+    /// Announce the tokens of highlighted code blocks to the frontend if they
+    /// have changed.
+    fn update_highlights(&mut self) -> Result<(), Error> {
+        let highlights = {
+            let client_doc = &mut self.state().client_doc;
+            client_doc.highlight.highlights(&client_doc.doc)
+        };
+        if highlights != self.state().highlights {
+            self.state().highlights = highlights.clone();
+            self.send_client(&FrontendCommand::Highlight(highlights))?;
+        }
+        Ok(())
+    }
+
+    /// Announce the readability issues of prose blocks to the frontend if
+    /// they have changed.
+    fn update_lints(&mut self) -> Result<(), Error> {
+        let lints = {
+            let client_doc = &mut self.state().client_doc;
+            client_doc.lint.lints(&client_doc.doc)
+        };
+        if lints != self.state().lints {
+            self.state().lints = lints.clone();
+            self.send_client(&FrontendCommand::Lints(lints))?;
+        }
+        Ok(())
+    }
+
+    /// Announce where the document's pages break to the frontend if that
+    /// has changed.
+    fn update_page_breaks(&mut self) -> Result<(), Error> {
+        let page_breaks = {
+            let client_doc = &mut self.state().client_doc;
+            client_doc.pagination.breaks(&client_doc.doc)
+        };
+        if page_breaks != self.state().page_breaks {
+            self.state().page_breaks = page_breaks.clone();
+            self.send_client(&FrontendCommand::PageBreaks(page_breaks))?;
+        }
+        Ok(())
+    }
+
+    /// Announce whether local edits are safely on the server, if that has
+    /// changed.
+    fn update_sync_status(&mut self) -> Result<(), Error> {
+        let status = {
+            let state = self.state();
+            if !state.connected || state.client_id == "$$$$$$" {
+                SyncStatus::Offline
+            } else if state.client_doc.is_dirty() {
+                SyncStatus::Pending
+            } else {
+                SyncStatus::Synced
+            }
+        };
+        if Some(status) != self.state().sync_status {
+            self.state().sync_status = Some(status);
+            self.send_client(&FrontendCommand::SyncStatus(status))?;
+        }
+        Ok(())
+    }
+
     fn upload(&mut self, local_op: Op) -> Result<(), Error> {
         log_wasm!(Debug("CLIENTOP".to_string()));
         let client_id = self.state().client_id.clone();
         let version = self.state().client_doc.version;
-        Ok(self.send_sync(ServerCommand::Commit(client_id, local_op, version))?)
+        if let Some(key) = self.state().encryption.clone() {
+            let sealed = seal_op(&key, &local_op)?;
+            return Ok(self.send_sync(ServerCommand::EncryptedCommit(client_id, sealed, version))?);
+        }
+        if self.state().peer.is_some() {
+            return self.upload_peer(local_op);
+        }
+        let op_id = self
+            .state()
+            .client_doc
+            .pending_id()
+            .ok_or_else(|| format_err!("no pending operation to upload"))?;
+        Ok(self.send_sync(ServerCommand::CommitOp(client_id, op_id, local_op, version))?)
+    }
+
+    // Peer pages take our operation as soon as it's sent, as the CrdtOps
+    // which make the same change for the other peers.
+    fn upload_peer(&mut self, local_op: Op) -> Result<(), Error> {
+        let client_id = self.state().client_id.clone();
+        let doc = Op::apply(&self.state().client_doc.original_doc, &local_op);
+        let ops = match self.state().peer {
+            Some(ref mut replica) => replica.edit(&doc),
+            None => bail!("not a peer page"),
+        };
+        if !ops.is_empty() {
+            self.send_sync(ServerCommand::PeerOps(client_id, ops))?;
+        }
+
+        let op_id = self
+            .state()
+            .client_doc
+            .pending_id()
+            .ok_or_else(|| format_err!("no pending operation to upload"))?;
+        let version = self.state().client_doc.version + 1;
+        if let Some(local_op) = self.state().client_doc.sync_acknowledged(op_id, version) {
+            self.upload(local_op)?;
+        }
+        Ok(())
     }
 
     // TODO combine with client_op?
@@ -538,6 +1439,7 @@ pub trait ClientImpl {
         callback(ActionContext {
             doc: self.state().client_doc.doc.clone(),
             client_id: self.state().client_id.clone(),
+            positions: Some(self.state().client_doc.positions.clone()),
         })
     }
 
@@ -546,12 +1448,18 @@ pub trait ClientImpl {
         C: Fn(ActionContext) -> Result<Op, Error>,
         Self: Sized,
     {
+        // Read-only clients can't change the document, not even their carets.
+        if self.state().read_only() {
+            return Ok(());
+        }
+
         // Apply operation.
         let op = self.with_action_context(callback)?;
 
         // Apply new operation.
         // eprintln!("apply to (d) {:?}", self.state().client_doc.doc);
         self.state().client_doc.apply_local_op(&op);
+        self.state().debug.push_op(&op);
 
         eprintln!("-----> {:?}", op);
 
@@ -579,11 +1487,16 @@ pub trait ClientImpl {
         // Render the update.
         let state = self.state();
         let res = FrontendCommand::Update(
-            doc_as_html(&state.client_doc.doc.0),
-            doc_to_markdown(&state.client_doc.doc.0).unwrap(),
+            state.client_doc.to_html(),
+            state.client_doc.to_markdown().unwrap(),
             Some(op),
         );
         self.send_client(&res)?;
+        self.update_title()?;
+        self.update_outline()?;
+        self.update_highlights()?;
+        self.update_lints()?;
+        self.update_page_breaks()?;
 
         // Send any queued payloads.
         if let Some(local_op) = self.state().client_doc.next_payload() {
@@ -593,10 +1506,13 @@ pub trait ClientImpl {
         // Update the controls state.
         // TODO should optimize this to not always send this out.
         // console_log!("CUR DOC {:?}", doc);
-        let (cur_block, in_list) = self.with_action_context(|doc| identify_block(doc))?;
-        println!("current block: {:?}", cur_block);
-        println!("in list: {:?}", in_list);
-        self.setup_controls(Some((cur_block, in_list)));
+        let state = self.with_action_context(|doc| format_state(doc))?;
+        if Some(&state) != self.state().format_state.as_ref() {
+            self.state().format_state = Some(state.clone());
+            self.send_client(&FrontendCommand::FormatState(state.clone()))?;
+        }
+        let keymap = self.state().keymap;
+        self.setup_controls(keymap, Some(state));
 
         Ok(())
     }
@@ -0,0 +1,101 @@
+//! Bounded history of recent client tasks, so a confused client can dump
+//! exactly what it did in its last few steps.
+
+use crate::client::Task;
+
+use failure::Error;
+use oatie::doc::*;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::{
+    Hash,
+    Hasher,
+};
+
+const DEBUG_HISTORY_LEN: usize = 64;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskRecord {
+    pub task: Task,
+    // Operations generated locally while handling the task.
+    pub ops: Vec<Op>,
+    // Hash of the client document after the task. None if the task
+    // failed or panicked before completing.
+    pub doc_hash: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DebugHistory {
+    records: VecDeque<TaskRecord>,
+}
+
+#[cfg(not(feature = "minimal"))]
+impl DebugHistory {
+    pub fn new() -> DebugHistory {
+        DebugHistory {
+            records: VecDeque::with_capacity(DEBUG_HISTORY_LEN),
+        }
+    }
+
+    /// Start recording a new task, evicting the oldest one if full.
+    pub fn begin(&mut self, task: &Task) {
+        if self.records.len() == DEBUG_HISTORY_LEN {
+            self.records.pop_front();
+        }
+        self.records.push_back(TaskRecord {
+            task: task.clone(),
+            ops: vec![],
+            doc_hash: None,
+        });
+    }
+
+    /// Attach an operation to the task currently being recorded.
+    pub fn push_op(&mut self, op: &Op) {
+        if let Some(record) = self.records.back_mut() {
+            record.ops.push(op.clone());
+        }
+    }
+
+    /// Mark the current task as complete with the resulting document.
+    pub fn finish(&mut self, doc: &Doc) {
+        let hash = doc_hash(doc);
+        if let Some(record) = self.records.back_mut() {
+            record.doc_hash = Some(hash);
+        }
+    }
+
+    /// Serialize all recorded tasks, oldest first.
+    pub fn dump(&self) -> Result<String, Error> {
+        let records = self.records.iter().collect::<Vec<_>>();
+        Ok(::ron::ser::to_string(&records)?)
+    }
+}
+
+// Minimal builds don't keep any history.
+#[cfg(feature = "minimal")]
+impl DebugHistory {
+    pub fn new() -> DebugHistory {
+        DebugHistory {
+            records: VecDeque::new(),
+        }
+    }
+
+    pub fn begin(&mut self, _task: &Task) {}
+
+    pub fn push_op(&mut self, _op: &Op) {}
+
+    pub fn finish(&mut self, _doc: &Doc) {}
+
+    pub fn dump(&self) -> Result<String, Error> {
+        bail!("debug history isn't available in minimal builds");
+    }
+}
+
+#[cfg(not(feature = "minimal"))]
+pub fn doc_hash(doc: &Doc) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    ::ron::ser::to_string(&doc.0)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,70 @@
+//! Client side of end-to-end encrypted pages. Operations are sealed before
+//! they're sent to the server and opened as they arrive, and the document is
+//! rebuilt by replaying every operation of the page.
+
+use edit_common::commands::*;
+use edit_common::e2ee::*;
+use failure::Error;
+use oatie::doc::*;
+use oatie::validate::validate_op;
+use oatie::OT;
+
+#[cfg(target_arch = "wasm32")]
+fn nonce() -> [u8; 12] {
+    use extern::wbg_rand::{
+        wasm_rng,
+        Rng,
+    };
+    let mut nonce = [0; 12];
+    wasm_rng().fill_bytes(&mut nonce);
+    nonce
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn nonce() -> [u8; 12] {
+    use extern::rand::{
+        thread_rng,
+        Rng,
+    };
+    let mut nonce = [0; 12];
+    thread_rng().fill_bytes(&mut nonce);
+    nonce
+}
+
+pub fn seal_op(key: &DocKey, op: &Op) -> Result<String, Error> {
+    key.seal(op, nonce())
+}
+
+// Open an operation and check it fits the document it applies to. Ones that
+// don't, sealed with another key or by a broken client, are taken as empty
+// operations, so every client skips them alike and the page still loads.
+fn open_op(key: &DocKey, doc: &Doc, sealed: &str) -> Op {
+    key.open::<Op>(sealed)
+        .ok()
+        .filter(|op| validate_op(doc, op).is_ok())
+        .unwrap_or_else(|| Op::empty())
+}
+
+/// Rewrite a command for an encrypted page as the command for a plain page:
+/// Init with the document its operations replay to, or Update with its
+/// operation opened against `original`, the last document the server sent.
+pub fn open_client_command(
+    key: &DocKey,
+    original: &Doc,
+    command: ClientCommand,
+) -> Result<ClientCommand, Error> {
+    Ok(match command {
+        ClientCommand::EncryptedInit(client_id, ops, version) => {
+            let mut doc = initial_encrypted_doc();
+            for (_, sealed) in ops {
+                let op = open_op(key, &doc, &sealed);
+                doc = Op::apply(&doc, &op);
+            }
+            ClientCommand::Init(client_id, doc.0, version)
+        }
+        ClientCommand::EncryptedUpdate(version, client_id, sealed) => {
+            ClientCommand::Update(version, client_id, open_op(key, original, &sealed))
+        }
+        command => command,
+    })
+}
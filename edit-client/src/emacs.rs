@@ -0,0 +1,59 @@
+//! Emacs-style killing and yanking, used by the Emacs keymap.
+
+use crate::{
+    actions::*,
+    client::ClientImpl,
+    walkers::*,
+};
+
+use failure::Error;
+
+// Kills older than this many are forgotten.
+const KILL_RING_SIZE: usize = 16;
+
+fn focus_pos<C: ClientImpl>(client: &mut C) -> Result<isize, Error> {
+    client.with_action_context(|ctx| Ok(Walker::to_caret(&ctx.doc, &ctx.client_id, true).caret_pos()))
+}
+
+fn push_kill<C: ClientImpl>(client: &mut C, text: String) {
+    let ring = &mut client.state().kill_ring;
+    ring.push(text);
+    if ring.len() > KILL_RING_SIZE {
+        ring.remove(0);
+    }
+}
+
+/// Kill the text from the caret to the end of its block. At the end of a
+/// block, join it with the next one instead.
+pub fn emacs_kill_line<C: ClientImpl>(client: &mut C) -> Result<(), Error> {
+    client.client_op(|ctx| clear_anchor(ctx))?;
+    client.client_op(|ctx| caret_set_anchor(ctx))?;
+    client.client_op(|ctx| caret_block_edge(ctx, true, true))?;
+
+    let text = client.with_action_context(|ctx| Ok(selection_text(&ctx)))?;
+    if !text.is_empty() {
+        client.client_op(|ctx| delete_char(ctx))?;
+        client.client_op(|ctx| clear_anchor(ctx))?;
+        push_kill(client, text);
+        return Ok(());
+    }
+
+    // Already at the end of the block. Step into the next block and delete
+    // backward to join them, unless this is the last block.
+    client.client_op(|ctx| clear_anchor(ctx))?;
+    let pos = focus_pos(client)?;
+    client.client_op(|ctx| caret_move(ctx, true, false))?;
+    if focus_pos(client)? != pos {
+        client.client_op(|ctx| delete_char(ctx))?;
+    }
+    Ok(())
+}
+
+/// Insert the most recently killed text.
+pub fn emacs_yank<C: ClientImpl>(client: &mut C) -> Result<(), Error> {
+    let text = match client.state().kill_ring.last() {
+        Some(text) => text.clone(),
+        None => return Ok(()),
+    };
+    client.client_op(|ctx| add_string(ctx, &text))
+}
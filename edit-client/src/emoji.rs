@@ -0,0 +1,182 @@
+//! Emoji shortcodes, like ":smile:". Typing the closing colon of a known
+//! shortcode replaces it with its emoji, when the Emoji input rule is on, and
+//! a partial shortcode before the caret can be completed from the table.
+
+use crate::{
+    actions::*,
+    typography::text_before_caret,
+};
+
+use failure::Error;
+use oatie::doc::*;
+use oatie::OT;
+
+/// Longest shortcode in the table, not counting its colons.
+pub const MAX_SHORTCODE_LEN: usize = 24;
+
+/// Most completions offered for a partial shortcode.
+pub const EMOJI_COMPLETIONS: usize = 8;
+
+// Sorted by shortcode, for binary search.
+const EMOJI: &[(&str, &str)] = &[
+    ("+1", "\u{1F44D}"),
+    ("-1", "\u{1F44E}"),
+    ("100", "\u{1F4AF}"),
+    ("angry", "\u{1F620}"),
+    ("apple", "\u{1F34E}"),
+    ("arrow_down", "\u{2B07}\u{FE0F}"),
+    ("arrow_left", "\u{2B05}\u{FE0F}"),
+    ("arrow_right", "\u{27A1}\u{FE0F}"),
+    ("arrow_up", "\u{2B06}\u{FE0F}"),
+    ("beer", "\u{1F37A}"),
+    ("bell", "\u{1F514}"),
+    ("blush", "\u{1F60A}"),
+    ("book", "\u{1F4D6}"),
+    ("bookmark", "\u{1F516}"),
+    ("broken_heart", "\u{1F494}"),
+    ("bug", "\u{1F41B}"),
+    ("bulb", "\u{1F4A1}"),
+    ("calendar", "\u{1F4C6}"),
+    ("cat", "\u{1F431}"),
+    ("check", "\u{2714}\u{FE0F}"),
+    ("clap", "\u{1F44F}"),
+    ("clock", "\u{1F552}"),
+    ("cloud", "\u{2601}\u{FE0F}"),
+    ("coffee", "\u{2615}"),
+    ("confused", "\u{1F615}"),
+    ("cool", "\u{1F192}"),
+    ("cry", "\u{1F622}"),
+    ("dog", "\u{1F436}"),
+    ("eyes", "\u{1F440}"),
+    ("fire", "\u{1F525}"),
+    ("flag", "\u{1F6A9}"),
+    ("gift", "\u{1F381}"),
+    ("grin", "\u{1F601}"),
+    ("grinning", "\u{1F600}"),
+    ("hammer", "\u{1F528}"),
+    ("heart", "\u{2764}\u{FE0F}"),
+    ("heart_eyes", "\u{1F60D}"),
+    ("hourglass", "\u{231B}"),
+    ("hundred", "\u{1F4AF}"),
+    ("info", "\u{2139}\u{FE0F}"),
+    ("joy", "\u{1F602}"),
+    ("key", "\u{1F511}"),
+    ("laughing", "\u{1F606}"),
+    ("link", "\u{1F517}"),
+    ("lock", "\u{1F512}"),
+    ("mag", "\u{1F50D}"),
+    ("memo", "\u{1F4DD}"),
+    ("moon", "\u{1F319}"),
+    ("muscle", "\u{1F4AA}"),
+    ("no_entry", "\u{26D4}"),
+    ("ok", "\u{1F197}"),
+    ("ok_hand", "\u{1F44C}"),
+    ("party", "\u{1F389}"),
+    ("pencil", "\u{270F}\u{FE0F}"),
+    ("point_down", "\u{1F447}"),
+    ("point_left", "\u{1F448}"),
+    ("point_right", "\u{1F449}"),
+    ("point_up", "\u{1F446}"),
+    ("pray", "\u{1F64F}"),
+    ("pushpin", "\u{1F4CC}"),
+    ("question", "\u{2753}"),
+    ("rage", "\u{1F621}"),
+    ("rainbow", "\u{1F308}"),
+    ("raised_hands", "\u{1F64C}"),
+    ("recycle", "\u{267B}\u{FE0F}"),
+    ("rocket", "\u{1F680}"),
+    ("rofl", "\u{1F923}"),
+    ("sad", "\u{1F61E}"),
+    ("scream", "\u{1F631}"),
+    ("see_no_evil", "\u{1F648}"),
+    ("shrug", "\u{1F937}"),
+    ("skull", "\u{1F480}"),
+    ("sleeping", "\u{1F634}"),
+    ("slightly_smiling_face", "\u{1F642}"),
+    ("smile", "\u{1F604}"),
+    ("smiley", "\u{1F603}"),
+    ("smirk", "\u{1F60F}"),
+    ("snowflake", "\u{2744}\u{FE0F}"),
+    ("sob", "\u{1F62D}"),
+    ("sparkles", "\u{2728}"),
+    ("star", "\u{2B50}"),
+    ("stuck_out_tongue", "\u{1F61B}"),
+    ("sun", "\u{2600}\u{FE0F}"),
+    ("sunglasses", "\u{1F60E}"),
+    ("sweat_smile", "\u{1F605}"),
+    ("tada", "\u{1F389}"),
+    ("thinking", "\u{1F914}"),
+    ("thumbsdown", "\u{1F44E}"),
+    ("thumbsup", "\u{1F44D}"),
+    ("tired_face", "\u{1F62B}"),
+    ("trophy", "\u{1F3C6}"),
+    ("umbrella", "\u{2602}\u{FE0F}"),
+    ("unlock", "\u{1F513}"),
+    ("warning", "\u{26A0}\u{FE0F}"),
+    ("wave", "\u{1F44B}"),
+    ("white_check_mark", "\u{2705}"),
+    ("wink", "\u{1F609}"),
+    ("wrench", "\u{1F527}"),
+    ("x", "\u{274C}"),
+    ("yum", "\u{1F60B}"),
+    ("zap", "\u{26A1}"),
+    ("zzz", "\u{1F4A4}"),
+];
+
+fn is_shortcode_char(c: char) -> bool {
+    c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '+' || c == '-'
+}
+
+/// The emoji for a shortcode, given without its colons.
+pub fn emoji_for(shortcode: &str) -> Option<&'static str> {
+    EMOJI
+        .binary_search_by(|(code, _)| (*code).cmp(shortcode))
+        .ok()
+        .map(|i| EMOJI[i].1)
+}
+
+/// Shortcodes starting with `prefix` and their emoji, in order.
+pub fn emoji_completions(prefix: &str, limit: usize) -> Vec<(String, String)> {
+    EMOJI
+        .iter()
+        .filter(|(code, _)| code.starts_with(prefix))
+        .take(limit)
+        .map(|(code, emoji)| (code.to_string(), emoji.to_string()))
+        .collect()
+}
+
+/// The shortcode being typed at the end of `text`: the characters after an
+/// opening colon, which must start the text or follow whitespace.
+pub fn partial_shortcode(text: &str) -> Option<&str> {
+    let start = text.rfind(':')?;
+    let code = &text[start + 1..];
+    let opens = text[..start].chars().next_back().map(|c| c.is_whitespace()).unwrap_or(true);
+    if opens && code.len() <= MAX_SHORTCODE_LEN && code.chars().all(is_shortcode_char) {
+        Some(code)
+    } else {
+        None
+    }
+}
+
+/// Replace the partial shortcode before our caret with the emoji for
+/// `shortcode`.
+pub fn complete_emoji(mut ctx: ActionContext, shortcode: &str) -> Result<Op, Error> {
+    let emoji = match emoji_for(shortcode) {
+        Some(emoji) => emoji,
+        None => bail!("unknown emoji shortcode {:?}", shortcode),
+    };
+    let before = text_before_caret(&ctx, MAX_SHORTCODE_LEN + 1);
+    let delete = match partial_shortcode(&before) {
+        Some(code) => code.chars().count() + 1,
+        None => bail!("no shortcode before the caret"),
+    };
+
+    let mut result = Op::empty();
+    for _ in 0..delete {
+        let op = delete_char(ctx.clone())?;
+        ctx.doc = Op::apply(&ctx.doc, &op);
+        result = Op::compose(&result, &op);
+    }
+    let op = add_string(ctx, emoji)?;
+    Ok(Op::compose(&result, &op))
+}
@@ -0,0 +1,182 @@
+//! Folding of the sections under headings. Folds are local to this client:
+//! they never enter the shared document, and only change how it's rendered
+//! and how the caret moves through it.
+
+use crate::{
+    actions::*,
+    client::ClientImpl,
+    walkers::*,
+};
+
+use edit_common::blocks::follow_top_level;
+use edit_common::commands::FrontendCommand;
+use failure::Error;
+use oatie::doc::*;
+use oatie::OT;
+
+fn heading_level(elem: &DocElement) -> Option<usize> {
+    match elem {
+        DocGroup(attrs, _) => {
+            let tag = attrs.get("tag")?;
+            if tag.len() == 2 && tag.starts_with('h') {
+                tag[1..].parse::<usize>().ok()
+            } else {
+                None
+            }
+        }
+        DocChars(_) => None,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Folds {
+    // Whether each top-level element is a collapsed heading. None for
+    // elements that were never collapsed.
+    collapsed: Vec<Option<bool>>,
+}
+
+impl Folds {
+    pub fn new() -> Folds {
+        Folds { collapsed: vec![] }
+    }
+
+    /// Expand everything.
+    pub fn reset(&mut self, doc: &Doc) {
+        self.collapsed = vec![None; doc.0.len()];
+    }
+
+    /// Follow headings through `op`, which produced `doc`. Operations that
+    /// split, join, or rewrap top-level elements expand everything.
+    pub fn apply(&mut self, doc: &Doc, op: &Op) {
+        match follow_top_level(&self.collapsed, op) {
+            Some(collapsed) => {
+                if collapsed.len() == doc.0.len() {
+                    self.collapsed = collapsed;
+                } else {
+                    self.reset(doc);
+                }
+            }
+            None => self.reset(doc),
+        }
+    }
+
+    fn is_collapsed(&self, index: usize) -> bool {
+        self.collapsed.get(index).cloned().and_then(|x| x).unwrap_or(false)
+    }
+
+    /// Collapse or expand the section under the heading at `index`.
+    /// Returns false if there's no top-level heading there.
+    pub fn toggle(&mut self, doc: &Doc, index: usize) -> bool {
+        if doc.0.get(index).and_then(heading_level).is_none() {
+            return false;
+        }
+        if self.collapsed.len() != doc.0.len() {
+            self.reset(doc);
+        }
+        self.collapsed[index] = Some(!self.is_collapsed(index));
+        true
+    }
+
+    /// Whether each top-level element is hidden inside a collapsed section.
+    /// A section runs until the next heading of the same or a higher level.
+    pub fn hidden(&self, doc: &Doc) -> Vec<bool> {
+        let mut hidden = vec![false; doc.0.len()];
+        let mut fold_level = None;
+        for (i, elem) in doc.0.iter().enumerate() {
+            let level = heading_level(elem);
+            if let Some(fold) = fold_level {
+                if level.map(|level| level <= fold).unwrap_or(false) {
+                    fold_level = None;
+                } else {
+                    hidden[i] = true;
+                    continue;
+                }
+            }
+            if level.is_some() && self.is_collapsed(i) {
+                fold_level = level;
+            }
+        }
+        hidden
+    }
+
+    /// A copy of `doc` for rendering, with hidden elements and collapsed
+    /// headings marked by class.
+    pub fn render(&self, doc: &Doc) -> Doc {
+        let hidden = self.hidden(doc);
+        Doc(doc
+            .0
+            .iter()
+            .enumerate()
+            .map(|(i, elem)| match elem {
+                DocGroup(attrs, span) => {
+                    let mut attrs = attrs.clone();
+                    if hidden[i] {
+                        attrs.insert("class".to_string(), "folded".to_string());
+                    } else if self.is_collapsed(i) && heading_level(elem).is_some() {
+                        attrs.insert("class".to_string(), "fold-collapsed".to_string());
+                    }
+                    DocGroup(attrs, span.clone())
+                }
+                DocChars(_) => elem.clone(),
+            })
+            .collect())
+    }
+}
+
+/// Collapse or expand the section our caret is in, under the nearest
+/// heading at or above it.
+pub fn toggle_fold<C: ClientImpl>(client: &mut C) -> Result<(), Error> {
+    let ctx = client.with_action_context(|ctx| Ok(ctx))?;
+    let index = match caret_top_level_index(&ctx) {
+        Some(index) => index,
+        None => return Ok(()),
+    };
+    let heading = match (0..index + 1).rev().find(|&i| heading_level(&ctx.doc.0[i]).is_some()) {
+        Some(heading) => heading,
+        None => return Ok(()),
+    };
+
+    let hidden = {
+        let folds = &mut client.state().client_doc.folds;
+        folds.toggle(&ctx.doc, heading);
+        folds.hidden(&ctx.doc)
+    };
+    client.client_op(|ctx| caret_skip_hidden(ctx, &hidden, false))?;
+
+    // Folding doesn't change the document, so render it here.
+    let res = {
+        let client_doc = &mut client.state().client_doc;
+        FrontendCommand::Update(client_doc.to_html(), client_doc.to_markdown()?, None)
+    };
+    client.send_client(&res)
+}
+
+// Index of the top-level element holding our focus caret.
+pub fn caret_top_level_index(ctx: &ActionContext) -> Option<usize> {
+    focus_block_path(&ctx.doc.0, &ctx.client_id).map(|path| path[0])
+}
+
+/// Move our caret out of hidden elements, by blocks in the given direction.
+/// At the end of the document it turns back, toward the collapsed heading.
+pub fn caret_skip_hidden(mut ctx: ActionContext, hidden: &[bool], mut increase: bool) -> Result<Op, Error> {
+    let mut result = Op::empty();
+    while let Some(index) = caret_top_level_index(&ctx) {
+        if !hidden.get(index).cloned().unwrap_or(false) {
+            break;
+        }
+
+        let pos = Walker::to_caret(&ctx.doc, &ctx.client_id, true).caret_pos();
+        let op = caret_block_move(ctx.clone(), increase)?;
+        let doc = Op::apply(&ctx.doc, &op);
+        if Walker::to_caret(&doc, &ctx.client_id, true).caret_pos() == pos {
+            if !increase {
+                break;
+            }
+            increase = false;
+            continue;
+        }
+        result = Op::compose(&result, &op);
+        ctx.doc = doc;
+    }
+    Ok(result)
+}
@@ -37,16 +37,35 @@ pub mod log;
 pub mod wasm;
 
 pub mod actions;
+pub mod autocorrect;
 pub mod client;
+pub mod debug;
+pub mod e2ee;
+pub mod emacs;
+pub mod emoji;
+pub mod folds;
+pub mod peer;
+#[cfg(not(feature = "minimal"))]
 pub mod monkey;
+pub mod positions;
+#[cfg(not(feature = "minimal"))]
 pub mod random;
 pub mod state;
+pub mod stats;
+pub mod typography;
+pub mod vim;
 pub mod walkers;
+pub mod words;
 
+#[cfg(not(target_arch = "wasm32"))]
+pub mod chaos;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod proxy;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod recording;
 
 pub use self::actions::*;
 pub use self::client::*;
+#[cfg(not(feature = "minimal"))]
 pub use self::random::*;
 pub use self::state::*;
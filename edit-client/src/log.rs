@@ -40,7 +40,13 @@ pub enum LogWasm {
 
 // TODO switch on a debug flag/feature or something
 #[macro_export]
-#[cfg(target_arch = "wasm32")]
+#[cfg(feature = "minimal")]
+macro_rules! log_wasm {
+    ($x:expr) => {{}};
+}
+
+#[macro_export]
+#[cfg(all(target_arch = "wasm32", not(feature = "minimal")))]
 macro_rules! log_wasm {
     ($x:expr) => {{
         // Load the logging enum variants locally.
@@ -61,7 +67,7 @@ macro_rules! log_wasm {
 }
 
 #[macro_export]
-#[cfg(not(target_arch = "wasm32"))]
+#[cfg(all(not(target_arch = "wasm32"), not(feature = "minimal")))]
 macro_rules! log_wasm {
     ($x:expr) => {{
         // Load the logging enum variants locally.
@@ -0,0 +1,45 @@
+//! Client side of peer pages, which are synced between clients as a CRDT
+//! (see `edit_common::crdt`) instead of through the server's transforms.
+//! Commands for them are rewritten as the commands for a plain page, and
+//! our operations are turned into CrdtOps as they're sent.
+
+use crate::state::without_carets;
+use edit_common::commands::*;
+use edit_common::crdt::*;
+use oatie::diff::diff;
+use oatie::doc::*;
+use oatie::schema::RtfSchema;
+use oatie::OT;
+
+// The operation from `original` to the content of `target`, which has no
+// carets, leaving the carets of `original` where they are.
+fn peer_op(original: &Doc, target: &Doc) -> Op {
+    let bare = Doc(without_carets(&original.0));
+    let carets = diff(&bare, original);
+    Op::transform::<RtfSchema>(&carets, &diff(&bare, target)).0
+}
+
+/// Rewrite a command for a peer page as the command for a plain page: Init
+/// with the document the page's operations make, or Update with what other
+/// clients' operations changed in `original`, the last document we synced.
+/// Peer pages have no versions, so each Update counts up from `version`.
+pub fn open_peer_command(
+    replica: &mut CrdtDoc,
+    original: &Doc,
+    version: usize,
+    command: ClientCommand,
+) -> ClientCommand {
+    match command {
+        ClientCommand::PeerInit(client_id, ops) => {
+            *replica = CrdtDoc::from_doc(site_for_client(&client_id), &initial_peer_doc());
+            replica.apply_remote_ops(ops);
+            ClientCommand::Init(client_id, replica.to_doc().0, 0)
+        }
+        ClientCommand::PeerOps(client_id, ops) => {
+            replica.apply_remote_ops(ops);
+            let op = peer_op(original, &replica.to_doc());
+            ClientCommand::Update(version + 1, client_id, op)
+        }
+        command => command,
+    }
+}
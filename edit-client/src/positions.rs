@@ -0,0 +1,202 @@
+//! An index of caret positions, so a position can be found without walking
+//! every element before it. The number of caret positions in each top-level
+//! element is kept in a Fenwick tree: finding the element holding a position
+//! takes O(log n), and applying an operation only recounts the elements it
+//! edited. Inside that element, as in a list holding many blocks, walkers
+//! skip over the children before the position by their counts.
+
+use crate::walkers::is_any_caret;
+use edit_common::blocks::map_top_level;
+use oatie::doc::*;
+use oatie::schema::{
+    RtfSchema,
+    RtfTrack,
+};
+use oatie::Schema;
+
+/// Caret positions in an element, counted the way CaretStepper counts them:
+/// one at the start of each block, one after each character, and one after
+/// each inline object other than a caret.
+pub fn count_positions(elem: &DocElement) -> usize {
+    match elem {
+        DocChars(text) => text.char_len(),
+        DocGroup(attrs, span) => match RtfSchema::track_type_from_attrs(attrs) {
+            Some(RtfTrack::Blocks) => 1 + span.iter().map(count_positions).sum::<usize>(),
+            Some(RtfTrack::InlineObjects) => if is_any_caret(attrs) {
+                0
+            } else {
+                1
+            },
+            _ => span.iter().map(count_positions).sum(),
+        },
+    }
+}
+
+// Lowest set bit, the size of the range a Fenwick tree node sums.
+fn lowbit(index: usize) -> usize {
+    index & index.wrapping_neg()
+}
+
+// Top-level elements an operation edits, if it only edits inside them.
+fn edited_top_level(op: &Op) -> Option<Vec<usize>> {
+    let (ref del, ref add) = *op;
+    let mut edited = vec![];
+
+    let mut index = 0;
+    for elem in del {
+        match elem {
+            DelSkip(n) => index += n,
+            DelWithGroup(_) => {
+                edited.push(index);
+                index += 1;
+            }
+            _ => return None,
+        }
+    }
+
+    let mut index = 0;
+    for elem in add {
+        match elem {
+            AddSkip(n) => index += n,
+            AddWithGroup(_) => {
+                edited.push(index);
+                index += 1;
+            }
+            _ => return None,
+        }
+    }
+
+    edited.sort();
+    edited.dedup();
+    Some(edited)
+}
+
+#[derive(Debug, Clone)]
+pub struct PositionIndex {
+    // Caret positions in each top-level element.
+    counts: Vec<usize>,
+    // Fenwick tree over `counts`, indexed from 1.
+    tree: Vec<usize>,
+}
+
+impl PositionIndex {
+    pub fn new() -> PositionIndex {
+        PositionIndex {
+            counts: vec![],
+            tree: vec![0],
+        }
+    }
+
+    /// Recount the whole document.
+    pub fn reset(&mut self, doc: &Doc) {
+        let counts = doc.0.iter().map(count_positions).collect();
+        self.rebuild(counts);
+    }
+
+    fn rebuild(&mut self, counts: Vec<usize>) {
+        let mut tree = vec![0; counts.len() + 1];
+        for index in 1..tree.len() {
+            tree[index] += counts[index - 1];
+            let parent = index + lowbit(index);
+            if parent < tree.len() {
+                tree[parent] += tree[index];
+            }
+        }
+        self.counts = counts;
+        self.tree = tree;
+    }
+
+    fn set(&mut self, element: usize, count: usize) {
+        let (old, new) = (self.counts[element], count);
+        if old == new {
+            return;
+        }
+        self.counts[element] = new;
+        let mut index = element + 1;
+        while index < self.tree.len() {
+            self.tree[index] = self.tree[index] - old + new;
+            index += lowbit(index);
+        }
+    }
+
+    // Caret positions in the first `elements` top-level elements.
+    fn prefix(&self, elements: usize) -> usize {
+        let mut sum = 0;
+        let mut index = elements;
+        while index > 0 {
+            sum += self.tree[index];
+            index -= lowbit(index);
+        }
+        sum
+    }
+
+    /// Update the index after `op` was applied, producing `doc`.
+    pub fn apply(&mut self, doc: &Doc, op: &Op) {
+        if let Some(edited) = edited_top_level(op) {
+            if self.counts.len() == doc.0.len() {
+                for element in edited {
+                    self.set(element, count_positions(&doc.0[element]));
+                }
+                return;
+            }
+        }
+
+        let entries = self.counts.iter().cloned().map(Some).collect::<Vec<_>>();
+        match map_top_level(&entries, op) {
+            Some(ref entries) if entries.len() == doc.0.len() => {
+                let counts = entries
+                    .iter()
+                    .zip(&doc.0)
+                    .map(|(count, elem)| count.unwrap_or_else(|| count_positions(elem)))
+                    .collect();
+                self.rebuild(counts);
+            }
+            // Operations which split or join top-level elements in ways we
+            // can't follow fall back to recounting everything.
+            _ => self.reset(doc),
+        }
+    }
+
+    /// Number of caret positions in the document.
+    pub fn len(&self) -> usize {
+        self.prefix(self.counts.len())
+    }
+
+    /// Number of top-level elements.
+    pub fn elements(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Caret position at the start of a top-level element.
+    pub fn start_of(&self, element: usize) -> isize {
+        self.prefix(element) as isize
+    }
+
+    /// The top-level element holding a caret position, and the caret
+    /// position at its start.
+    pub fn element_at(&self, pos: isize) -> Option<(usize, isize)> {
+        if pos < 0 {
+            return None;
+        }
+
+        // Descend the tree for the most elements whose positions all come
+        // before `pos`; the next element holds it.
+        let mut remaining = pos as usize;
+        let mut element = 0;
+        let mut step = (self.tree.len() - 1).next_power_of_two();
+        while step > 0 {
+            let next = element + step;
+            if next < self.tree.len() && self.tree[next] <= remaining {
+                element = next;
+                remaining -= self.tree[next];
+            }
+            step >>= 1;
+        }
+
+        if element < self.counts.len() {
+            Some((element, pos - remaining as isize))
+        } else {
+            None
+        }
+    }
+}
@@ -1,6 +1,14 @@
 use failure::Error;
 use oatie::doc::*;
-use oatie::writer::CurWriter;
+use oatie::writer::{
+    CurWriter,
+    DocWriter,
+};
+use rand::{
+    Rng,
+    SeedableRng,
+    XorShiftRng,
+};
 
 pub struct RandomCursorContext {
     cur: CurWriter,
@@ -55,3 +63,109 @@ pub fn random_cursor(doc: &Doc) -> Result<Vec<CurSpan>, Error> {
     random_cursor_span(&mut ctx, &doc.0)?;
     Ok(ctx.history)
 }
+
+// Random documents
+
+const RANDOM_WORDS: &[&str] = &[
+    "lorem", "ipsum", "dolor", "sit", "amet", "consectetur", "adipiscing", "elit", "sed", "do",
+    "eiusmod", "tempor", "incididunt", "ut", "labore", "et", "dolore", "magna", "aliqua",
+];
+const RANDOM_BLOCKS: &[&str] = &["p", "p", "p", "h1", "h2", "h3", "h4", "h5", "h6", "pre"];
+
+/// Shape of a document built by `random_doc`.
+#[derive(Debug, Clone)]
+pub struct RandomDocParams {
+    // Number of top-level blocks and lists.
+    pub blocks: usize,
+    // Deepest nesting of lists.
+    pub max_depth: usize,
+    // Most words in a single text block.
+    pub max_words: usize,
+}
+
+impl Default for RandomDocParams {
+    fn default() -> Self {
+        RandomDocParams {
+            blocks: 100,
+            max_depth: 4,
+            max_words: 40,
+        }
+    }
+}
+
+fn random_styles<R: Rng>(rng: &mut R) -> StyleMap {
+    let mut styles = style_map! { Style::Normie => None };
+    if rng.gen_weighted_bool(4) {
+        styles.insert(Style::Bold, None);
+    }
+    if rng.gen_weighted_bool(4) {
+        styles.insert(Style::Italic, None);
+    }
+    if rng.gen_weighted_bool(8) {
+        styles.insert(Style::Link, Some("https://example.com/".to_string()));
+    }
+    styles
+}
+
+fn random_text_block<R: Rng>(rng: &mut R, writer: &mut DocWriter, params: &RandomDocParams) {
+    let tag = *rng.choose(RANDOM_BLOCKS).unwrap();
+
+    writer.begin();
+    let words = rng.gen_range(1, params.max_words.max(1) + 1);
+    let mut word = 0;
+    while word < words {
+        // Split the block into runs of words that share styles.
+        let run = rng.gen_range(1, 6).min(words - word);
+        let text = (0..run)
+            .map(|_| *rng.choose(RANDOM_WORDS).unwrap())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let text = if word + run < words {
+            format!("{} ", text)
+        } else {
+            text
+        };
+        let styles = if tag == "pre" {
+            style_map! { Style::Normie => None }
+        } else {
+            random_styles(rng)
+        };
+        writer.place(&DocChars(DocString::from_string_styled(text, styles)));
+        word += run;
+    }
+    writer.close(hashmap! { "tag".to_string() => tag.to_string() });
+}
+
+fn random_block<R: Rng>(
+    rng: &mut R,
+    writer: &mut DocWriter,
+    params: &RandomDocParams,
+    depth: usize,
+) {
+    if depth < params.max_depth && rng.gen_weighted_bool(4) {
+        // A list item containing at least one block.
+        writer.begin();
+        for _ in 0..rng.gen_range(1, 4) {
+            random_block(rng, writer, params, depth + 1);
+        }
+        writer.close(hashmap! { "tag".to_string() => "bullet".to_string() });
+    } else if rng.gen_weighted_bool(20) {
+        writer.begin();
+        writer.close(hashmap! { "tag".to_string() => "hr".to_string() });
+    } else {
+        random_text_block(rng, writer, params);
+    }
+}
+
+/// Builds a document of headings, paragraphs, nested lists, and styled
+/// text. The same seed and parameters always produce the same document.
+pub fn random_doc(seed: u32, params: &RandomDocParams) -> Doc {
+    // XorShift can't be seeded with all zeroes.
+    let mut rng = XorShiftRng::from_seed([seed, 0x193a_6754, 0xa8a7_d469, 0x9783_0e05]);
+
+    let mut writer = DocWriter::new();
+    for _ in 0..params.blocks {
+        random_block(&mut rng, &mut writer, params, 0);
+    }
+    Doc(writer.result().unwrap())
+}
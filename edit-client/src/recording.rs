@@ -0,0 +1,225 @@
+//! Recordings of the frames a proxied client exchanges with sync, to make
+//! what happened in production reproducible locally. A recording is a file
+//! of JSON lines, one per frame, with the time since recording started.
+//!
+//! A recording can be replayed in either direction: the frames the client
+//! sent, at a sync server, or the frames sync sent, at a headless client.
+
+use crate::{
+    proxy::ProxyClient,
+    Client,
+    ClientImpl,
+    Task,
+};
+
+use extern::{
+    crossbeam_channel::unbounded,
+    edit_common::commands::*,
+    edit_common::protocol::PROTOCOL_VERSION,
+    failure::Error,
+    oatie::doc::*,
+    serde_json,
+    std::collections::HashMap,
+    std::fs::{
+        File,
+        OpenOptions,
+    },
+    std::io::{
+        BufRead,
+        BufReader,
+        Write,
+    },
+    std::sync::atomic::AtomicBool,
+    std::sync::{
+        Arc,
+        Mutex,
+    },
+    std::thread,
+    std::time::{
+        Duration,
+        Instant,
+    },
+    ws,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    ToSync,
+    ToClient,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Frame {
+    // Milliseconds since the recording started.
+    pub time: u64,
+    pub page_id: String,
+    pub direction: Direction,
+    // The frame as sent, a serialized ServerCommand or ClientCommand.
+    pub data: String,
+}
+
+/// Appends frames to a recording, from any thread.
+pub struct Recorder {
+    file: Mutex<File>,
+    start: Instant,
+}
+
+impl Recorder {
+    pub fn create(path: &str) -> Result<Recorder, Error> {
+        Ok(Recorder {
+            file: Mutex::new(OpenOptions::new().create(true).append(true).open(path)?),
+            start: Instant::now(),
+        })
+    }
+
+    pub fn record(&self, page_id: &str, direction: Direction, data: &str) {
+        let elapsed = self.start.elapsed();
+        let frame = Frame {
+            time: elapsed.as_secs() * 1000 + (elapsed.subsec_nanos() / 1_000_000) as u64,
+            page_id: page_id.to_string(),
+            direction,
+            data: data.to_string(),
+        };
+        let line = serde_json::to_string(&frame).unwrap();
+        if let Err(err) = writeln!(self.file.lock().unwrap(), "{}", line) {
+            eprintln!("(!) could not record frame: {:?}", err);
+        }
+    }
+}
+
+pub fn read_recording(path: &str) -> Result<Vec<Frame>, Error> {
+    let mut frames = vec![];
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            frames.push(serde_json::from_str(&line)?);
+        }
+    }
+    Ok(frames)
+}
+
+// Wait until a frame's time comes, at a speed relative to the recording.
+fn wait_for(frame: &Frame, start: Instant, speed: f64) {
+    let due = Duration::from_millis((frame.time as f64 / speed) as u64);
+    let elapsed = start.elapsed();
+    if due > elapsed {
+        thread::sleep(due - elapsed);
+    }
+}
+
+// The client id sync assigned in a page's first Init, recorded or live.
+fn init_client_id(data: &str) -> Option<String> {
+    match serde_json::from_str(data).ok()? {
+        ClientCommand::Init(client_id, ..)
+        | ClientCommand::InitChunk(client_id, ..)
+        | ClientCommand::InitText(client_id, ..) => Some(client_id),
+        _ => None,
+    }
+}
+
+/// Send the frames the client sent to sync at a sync server, one
+/// connection per page. Sync assigns new client ids, so the recorded ids in
+/// each frame are rewritten to the new ones. The server's documents should
+/// start as they were when the recording was made.
+pub fn replay_to_sync(frames: &[Frame], ws_port: u16, speed: f64) -> Result<(), Error> {
+    let mut pages: HashMap<String, Vec<Frame>> = HashMap::new();
+    for frame in frames {
+        pages
+            .entry(frame.page_id.clone())
+            .or_insert_with(Vec::new)
+            .push(frame.clone());
+    }
+
+    let start = Instant::now();
+    let threads = pages
+        .into_iter()
+        .map(|(page_id, frames)| {
+            thread::spawn(move || {
+                let recorded_id = frames
+                    .iter()
+                    .filter(|frame| frame.direction == Direction::ToClient)
+                    .filter_map(|frame| init_client_id(&frame.data))
+                    .next();
+                let url = format!(
+                    "ws://127.0.0.1:{}/$/ws/{}?protocol={}",
+                    ws_port, page_id, PROTOCOL_VERSION
+                );
+                let result = ws::connect(url, move |out: ws::Sender| {
+                    let frames = frames.clone();
+                    let recorded_id = recorded_id.clone();
+                    let mut started = false;
+                    move |msg: ws::Message| {
+                        // Start sending once sync tells us our client id.
+                        if started {
+                            return Ok(());
+                        }
+                        let live_id = match init_client_id(&msg.into_text()?) {
+                            Some(live_id) => live_id,
+                            None => return Ok(()),
+                        };
+                        started = true;
+
+                        let out = out.clone();
+                        let frames = frames.clone();
+                        let recorded_id = recorded_id.clone();
+                        thread::spawn(move || {
+                            for frame in frames.iter().filter(|frame| frame.direction == Direction::ToSync) {
+                                wait_for(frame, start, speed);
+                                let data = match recorded_id {
+                                    Some(ref recorded_id) => frame.data.replace(
+                                        &format!("\"{}\"", recorded_id),
+                                        &format!("\"{}\"", live_id),
+                                    ),
+                                    None => frame.data.clone(),
+                                };
+                                if out.send(data).is_err() {
+                                    return;
+                                }
+                            }
+                            let _ = out.close(ws::CloseCode::Normal);
+                        });
+                        Ok(())
+                    }
+                });
+                if let Err(err) = result {
+                    eprintln!("(!) replay of {:?} failed: {:?}", page_id, err);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for thread in threads {
+        let _ = thread.join();
+    }
+    Ok(())
+}
+
+/// Feed the frames sync sent for a page to a headless client, as they
+/// arrived, and return the document it ends up with. What the client sends
+/// back is dropped.
+pub fn replay_to_client(frames: &[Frame], page_id: &str, speed: f64) -> Result<Doc, Error> {
+    let (tx_client, _rx_client) = unbounded();
+    let (tx_sync, _rx_sync) = unbounded();
+    let mut client = ProxyClient {
+        state: Client::new(
+            "$$$$$$",
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicBool::new(true)),
+        ),
+
+        tx_client,
+        tx_sync,
+    };
+
+    let start = Instant::now();
+    for frame in frames {
+        if frame.page_id != page_id || frame.direction != Direction::ToClient {
+            continue;
+        }
+        wait_for(frame, start, speed);
+        let command: ClientCommand = serde_json::from_str(&frame.data)?;
+        client.handle_task(Task::ClientCommand(command))?;
+    }
+
+    Ok(client.state().client_doc.doc.clone())
+}
@@ -1,11 +1,128 @@
 //! Document + versioning state that talks to a synchronization server.
 
+use crate::folds::Folds;
+use crate::positions::PositionIndex;
+use crate::stats::StatsCache;
+use edit_common::doc_as_html;
+use edit_common::highlight::HighlightCache;
+use edit_common::lint::LintCache;
+use edit_common::markdown::MarkdownCache;
+use edit_common::outline::OutlineCache;
+use edit_common::pagination::{
+    mark_page_breaks,
+    PaginationCache,
+};
+use failure::Error;
+use oatie::diff::{
+    diff,
+    untouched_edges,
+};
 use oatie::doc::*;
+use oatie::intern::{
+    intern_doc,
+    intern_op,
+    Interner,
+};
+use oatie::prelude::invert;
 use oatie::schema::RtfSchema;
-use oatie::validate::validate_doc;
+use oatie::validate::{
+    validate_doc,
+    validate_op,
+};
 use oatie::OT;
+use serde_json;
 use std::mem;
 
+// Version of the format of serialized client state. Bump it whenever
+// ClientSnapshot changes, so older blobs are refused instead of misread.
+pub const CLIENT_SNAPSHOT_FORMAT: usize = 2;
+
+// Most of our edits that can be undone.
+const UNDO_LIMIT: usize = 100;
+
+/// Client state in a form that can be stored and restored, for restoring a
+/// tab, moving a client to another worker, or editing offline: the document
+/// as the server last confirmed it, our operations it hasn't, and the count
+/// of operations sent, so the pending one is resent with its id. Carets are
+/// part of the document, so they come along, as does the undo stack.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ClientSnapshot {
+    pub format: usize,
+    pub client_id: String,
+    pub version: usize,
+    pub original_doc: Doc,
+    pub pending_op: Option<Op>,
+    pub local_op: Op,
+    pub sent_ops: u64,
+    pub undo_stack: Vec<Op>,
+}
+
+// An initial sync arriving in chunks, collected until the last arrives.
+// Chunks are of elements, or of the document's JSON as text.
+#[derive(Debug, Clone)]
+struct PartialSync {
+    version: usize,
+    count: usize,
+    received: usize,
+    elements: DocSpan,
+    text: String,
+}
+
+// A span without the carets in it.
+pub(crate) fn without_carets(span: &DocSpan) -> DocSpan {
+    let mut out: DocSpan = vec![];
+    for elem in span {
+        match elem {
+            DocGroup(attrs, inner) => {
+                if attrs["tag"] != "caret" {
+                    out.place(&DocGroup(attrs.clone(), without_carets(inner)));
+                }
+            }
+            DocChars(text) => out.place(&DocChars(text.clone())),
+        }
+    }
+    out
+}
+
+// An operation undoing `op`, which turned `before` into `after`, leaving the
+// carets of `after` where they are, or None if only carets changed. Only the
+// blocks `op` touched are compared; the top level of a document is all
+// blocks, so they're found by index.
+fn content_undo(before: &Doc, op: &Op, after: &Doc) -> Option<Op> {
+    let (head, tail) = untouched_edges(before, after, op);
+    let touched = |doc: &Doc| Doc(doc.0[head..doc.0.len() - tail].to_vec());
+    let (before, after) = (touched(before), touched(after));
+
+    let bare_before = Doc(without_carets(&before.0));
+    let bare_after = Doc(without_carets(&after.0));
+    let undo = diff(&bare_after, &bare_before);
+    if undo == Op::empty() {
+        return None;
+    }
+    // The undo is written without carets, so carry it past adding them.
+    let carets = diff(&bare_after, &after);
+    let (del, add) = Op::transform::<RtfSchema>(&carets, &undo).0;
+
+    // Then skip the blocks before the touched ones.
+    let mut skipped_del = vec![];
+    if head > 0 && !del.is_empty() {
+        skipped_del.place(&DelSkip(head));
+    }
+    skipped_del.place_all(&del);
+    let mut skipped_add = vec![];
+    if head > 0 && !add.is_empty() {
+        skipped_add.place(&AddSkip(head));
+    }
+    skipped_add.place_all(&add);
+    Some((skipped_del, skipped_add))
+}
+
+/// The document as we show it, and how it got there. Our edits are applied
+/// to `doc` right away, speculatively: `original_doc` is the version sync
+/// last confirmed, and `pending_op` (sent, awaiting its ack) and `local_op`
+/// (not yet sent) are the buffer of our operations on top of it. Operations
+/// from sync are rebased under that buffer, and when sync refuses one of
+/// ours, `rollback` takes it out and rebases the rest on sync's document.
 #[derive(Debug)]
 pub struct ClientDoc {
     pub doc: Doc,
@@ -14,6 +131,32 @@ pub struct ClientDoc {
     pub original_doc: Doc,
     pub pending_op: Option<Op>,
     pub local_op: Op,
+    // Operations sent to sync so far, which numbers them: the pending
+    // operation's id is this count.
+    sent_ops: u64,
+    // Set when sync refused our pending operation, until the document it
+    // resynchronizes us with arrives.
+    pub awaiting_correction: bool,
+    // Operations undoing our recent edits, newest last. Each applies to
+    // `doc`, and is transformed past every operation applied to it. They
+    // leave carets alone, so undoing doesn't move anyone's caret.
+    pub undo_stack: Vec<Op>,
+    // Set while the operation from `undo_op` is applied, so it isn't
+    // recorded as an edit to undo.
+    undoing: bool,
+    // Styles shared by the text of our documents and the operations on them.
+    pub interner: Interner,
+
+    pub stats: StatsCache,
+    pub markdown: MarkdownCache,
+    pub outline: OutlineCache,
+    pub highlight: HighlightCache,
+    pub lint: LintCache,
+    pub pagination: PaginationCache,
+    pub folds: Folds,
+    pub positions: PositionIndex,
+
+    partial_sync: Option<PartialSync>,
 }
 
 impl ClientDoc {
@@ -26,17 +169,259 @@ impl ClientDoc {
             original_doc: Doc(vec![]),
             pending_op: None,
             local_op: Op::empty(),
+            sent_ops: 0,
+            awaiting_correction: false,
+            undo_stack: vec![],
+            undoing: false,
+            interner: Interner::new(),
+
+            stats: StatsCache::new(),
+            markdown: MarkdownCache::new(),
+            outline: OutlineCache::new(),
+            highlight: HighlightCache::new(),
+            lint: LintCache::new(),
+            pagination: PaginationCache::new(),
+            folds: Folds::new(),
+            positions: PositionIndex::new(),
+
+            partial_sync: None,
+        }
+    }
+
+    /// Collect a chunk of an initial sync, returning the whole document once
+    /// its last chunk arrives. A chunk which doesn't follow the ones we have,
+    /// or is of another version, starts over unless it's the first chunk.
+    pub fn receive_chunk(
+        &mut self,
+        version: usize,
+        index: usize,
+        count: usize,
+        span: DocSpan,
+    ) -> Option<DocSpan> {
+        self.continue_sync(version, index, count)?.elements.extend(span);
+        self.finish_sync().map(|partial| partial.elements)
+    }
+
+    /// Like `receive_chunk`, for a piece of the document's JSON. The pieces
+    /// are parsed once the last arrives.
+    pub fn receive_text(
+        &mut self,
+        version: usize,
+        index: usize,
+        count: usize,
+        text: &str,
+    ) -> Result<Option<DocSpan>, Error> {
+        match self.continue_sync(version, index, count) {
+            Some(partial) => partial.text.push_str(text),
+            None => return Ok(None),
         }
+        match self.finish_sync() {
+            Some(partial) => Ok(Some(from_json_shared(partial.text)?)),
+            None => Ok(None),
+        }
+    }
+
+    // The unfinished sync a chunk belongs to, having counted the chunk, or
+    // None if it doesn't follow the chunks we have.
+    fn continue_sync(
+        &mut self,
+        version: usize,
+        index: usize,
+        count: usize,
+    ) -> Option<&mut PartialSync> {
+        let continues = match self.partial_sync {
+            Some(ref partial) => {
+                partial.version == version && partial.count == count && partial.received == index
+            }
+            None => false,
+        };
+        if index == 0 {
+            self.partial_sync = Some(PartialSync {
+                version,
+                count,
+                received: 0,
+                elements: vec![],
+                text: String::new(),
+            });
+        } else if !continues {
+            self.partial_sync = None;
+            return None;
+        }
+
+        let partial = self.partial_sync.as_mut().unwrap();
+        partial.received += 1;
+        Some(partial)
     }
 
-    /// Overwrite current state
-    pub fn init(&mut self, new_doc: &Doc, version: usize) {
+    // The unfinished sync, once it has all its chunks.
+    fn finish_sync(&mut self) -> Option<PartialSync> {
+        match self.partial_sync {
+            Some(ref partial) if partial.received == partial.count => {}
+            _ => return None,
+        }
+        self.partial_sync.take()
+    }
+
+    /// The version of an unfinished initial sync, and the index of the next
+    /// chunk it needs.
+    pub fn resume_point(&self) -> Option<(usize, usize)> {
+        self.partial_sync
+            .as_ref()
+            .map(|partial| (partial.version, partial.received))
+    }
+
+    /// Take a document from sync. Operations sync hasn't confirmed, as
+    /// after reconnecting or restoring a snapshot, are carried over to it,
+    /// and the pending one keeps its id to be resent. Returns whether they
+    /// survived; if they can't be carried over, we start over from sync's
+    /// document.
+    pub fn init(&mut self, new_doc: &Doc, version: usize) -> bool {
+        if !self.is_dirty() {
+            self.reset(new_doc, version);
+            return true;
+        }
+        match self.rebase_unconfirmed(new_doc) {
+            Some((doc, pending_op, local_op, changes)) => {
+                let undo_stack = self
+                    .undo_stack
+                    .iter()
+                    .map(|undo| Op::transform::<RtfSchema>(&changes, undo).0)
+                    .collect();
+                let pending = self.pending_op.is_some();
+                self.reset(new_doc, version);
+                self.doc = doc;
+                self.pending_op = if pending { Some(pending_op) } else { None };
+                self.local_op = local_op;
+                self.undo_stack = undo_stack;
+                self.intern_docs();
+                self.reset_caches();
+                true
+            }
+            None => {
+                self.reset(new_doc, version);
+                false
+            }
+        }
+    }
+
+    // Overwrite our state with a document from sync.
+    fn reset(&mut self, new_doc: &Doc, version: usize) {
         self.doc = new_doc.clone();
         self.version = version;
+        self.awaiting_correction = false;
 
         self.original_doc = new_doc.clone();
         self.pending_op = None;
         self.local_op = Op::empty();
+        self.undo_stack = vec![];
+        self.undoing = false;
+
+        self.intern_docs();
+        self.reset_caches();
+    }
+
+    // Share the styles of our documents' text through the interner, and
+    // forget the styles neither has anymore.
+    fn intern_docs(&mut self) {
+        self.doc = intern_doc(&self.doc, &mut self.interner);
+        self.original_doc = intern_doc(&self.original_doc, &mut self.interner);
+        self.interner.prune();
+    }
+
+    fn reset_caches(&mut self) {
+        self.stats.reset(&self.doc);
+        self.markdown.reset(&self.doc);
+        self.outline.reset(&self.doc);
+        self.highlight.reset(&self.doc);
+        self.lint.reset(&self.doc);
+        self.pagination.reset(&self.doc);
+        self.folds.reset(&self.doc);
+        self.positions.reset(&self.doc);
+    }
+
+    // Our pending and local operations, rebased from the version sync last
+    // confirmed to a document from sync, along with the document they make
+    // and the change that rebasing made to `doc`.
+    fn rebase_unconfirmed(&self, server_doc: &Doc) -> Option<(Doc, Op, Op, Op)> {
+        let pending_op = self.pending_op.clone().unwrap_or_else(Op::empty);
+        let changes = diff(&self.original_doc, server_doc);
+
+        let (pending_op, changes) = Op::transform::<RtfSchema>(&changes, &pending_op);
+        validate_op(server_doc, &pending_op).ok()?;
+        let pending_doc = Op::apply(server_doc, &pending_op);
+
+        let (local_op, changes) = Op::transform::<RtfSchema>(&changes, &self.local_op);
+        validate_op(&pending_doc, &local_op).ok()?;
+        let doc = Op::apply(&pending_doc, &local_op);
+        validate_doc(&doc).ok()?;
+
+        Some((doc, pending_op, local_op, changes))
+    }
+
+    /// Serialize the document and our unconfirmed operations, as a
+    /// versioned ClientSnapshot.
+    pub fn serialize(&self, client_id: &str) -> Result<String, Error> {
+        Ok(serde_json::to_string(&ClientSnapshot {
+            format: CLIENT_SNAPSHOT_FORMAT,
+            client_id: client_id.to_string(),
+            version: self.version,
+            original_doc: self.original_doc.clone(),
+            pending_op: self.pending_op.clone(),
+            local_op: self.local_op.clone(),
+            sent_ops: self.sent_ops,
+            undo_stack: self.undo_stack.clone(),
+        })?)
+    }
+
+    /// Replace our state with a serialized ClientSnapshot, returning the
+    /// client id it was taken as.
+    pub fn restore(&mut self, input: &str) -> Result<String, Error> {
+        let snapshot: ClientSnapshot = serde_json::from_str(input)?;
+        if snapshot.format != CLIENT_SNAPSHOT_FORMAT {
+            bail!(
+                "client state is in format {}, expected {}",
+                snapshot.format,
+                CLIENT_SNAPSHOT_FORMAT
+            );
+        }
+
+        // Check the operations still reassemble into a document, before
+        // applying them.
+        validate_doc(&snapshot.original_doc)?;
+        let pending_op = snapshot.pending_op.clone().unwrap_or_else(Op::empty);
+        validate_op(&snapshot.original_doc, &pending_op)?;
+        let pending_doc = Op::apply(&snapshot.original_doc, &pending_op);
+        validate_op(&pending_doc, &snapshot.local_op)?;
+        let doc = Op::apply(&pending_doc, &snapshot.local_op);
+        validate_doc(&doc)?;
+
+        // Undo entries that don't fit the document are dropped.
+        let undo_stack = snapshot
+            .undo_stack
+            .into_iter()
+            .filter(|undo| validate_op(&doc, undo).is_ok())
+            .collect();
+
+        self.reset(&doc, snapshot.version);
+        self.original_doc = snapshot.original_doc;
+        self.pending_op = snapshot.pending_op;
+        self.local_op = snapshot.local_op;
+        self.sent_ops = snapshot.sent_ops;
+        self.undo_stack = undo_stack;
+        self.intern_docs();
+        self.assert_compose_correctness(None);
+
+        Ok(snapshot.client_id)
+    }
+
+    /// Carry an operation against the last version from sync over our
+    /// unconfirmed operations, so that it applies to `doc`.
+    pub fn transform_to_local(&self, op: &Op) -> Op {
+        let mut op = op.clone();
+        if let Some(ref pending_op) = self.pending_op {
+            op = Op::transform::<RtfSchema>(pending_op, &op).0;
+        }
+        Op::transform::<RtfSchema>(&self.local_op, &op).0
     }
 
     /// Sync ACK'd our pending operation.
@@ -70,6 +455,72 @@ impl ClientDoc {
         self.next_payload()
     }
 
+    /// Sync acknowledged our operation with this id. Returns the next op to
+    /// send to sync, if any. Acks of an operation that isn't pending, like
+    /// a second ack of one we resent, are ignored.
+    pub fn sync_acknowledged(&mut self, op_id: u64, version: usize) -> Option<Op> {
+        if self.pending_id() != Some(op_id) {
+            eprintln!("(!) ignoring ack of operation {:?}", op_id);
+            return None;
+        }
+        let new_doc = Op::apply(&self.original_doc, self.pending_op.as_ref().unwrap());
+        self.sync_confirmed_pending_op(&new_doc, version)
+    }
+
+    /// Id of the operation waiting for an ack from sync, if there is one.
+    pub fn pending_id(&self) -> Option<u64> {
+        self.pending_op.as_ref().map(|_| self.sent_ops)
+    }
+
+    /// Sync refused our pending operation and sent its own document. Undo
+    /// the pending operation and carry our later operations over to sync's
+    /// document, changing every part of our state at once, so the document
+    /// is shown either as it was or as corrected and never in between.
+    /// Returns whether our later operations survived; if they can't be
+    /// carried over, we start over from sync's document.
+    pub fn rollback(&mut self, server_doc: &Doc, version: usize) -> bool {
+        let rebased = self.rebase_local(server_doc);
+        self.reset(server_doc, version);
+        match rebased {
+            Some((doc, local_op)) => {
+                self.doc = doc;
+                self.local_op = local_op;
+                self.intern_docs();
+                self.reset_caches();
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Our local operations without the pending one, rebased on a document
+    // from sync, and the document they make.
+    fn rebase_local(&self, server_doc: &Doc) -> Option<(Doc, Op)> {
+        if self.local_op == Op::empty() {
+            return None;
+        }
+        // L follows P, so rewrite it to follow P's undo instead, leaving it
+        // on top of the version sync last confirmed...
+        let local_op = match self.pending_op {
+            Some(ref pending_op) => {
+                let undo = invert(&self.original_doc, pending_op);
+                Op::transform::<RtfSchema>(&undo, &self.local_op).0
+            }
+            None => self.local_op.clone(),
+        };
+        validate_op(&self.original_doc, &local_op).ok()?;
+
+        // ...and then to follow whatever changed between it and sync's
+        // document.
+        let changes = diff(&self.original_doc, server_doc);
+        let local_op = Op::transform::<RtfSchema>(&changes, &local_op).0;
+        validate_op(server_doc, &local_op).ok()?;
+        let doc = Op::apply(server_doc, &local_op);
+        validate_doc(&doc).ok()?;
+
+        Some((doc, local_op))
+    }
+
     /// Sync gave us an operation not originating from us.
     // TODO we can determine new_doc without needing it passed in
     pub fn sync_sent_new_version(&mut self, new_doc: &Doc, version: usize, input_op: &Op) {
@@ -82,6 +533,15 @@ impl ClientDoc {
             self.doc = new_doc.clone();
             self.version = version;
             self.original_doc = new_doc.clone();
+            self.stats.apply(&self.doc, input_op);
+            self.markdown.apply(&self.doc, input_op);
+            self.outline.apply(&self.doc, input_op);
+            self.highlight.apply(&self.doc, input_op);
+            self.lint.apply(&self.doc, input_op);
+            self.pagination.apply(&self.doc, input_op);
+            self.folds.apply(&self.doc, input_op);
+            self.positions.apply(&self.doc, input_op);
+            self.transform_undo_stack(input_op);
             return;
         }
 
@@ -119,7 +579,7 @@ impl ClientDoc {
         // let input_final = Op::compose(&input_transform, &correction);
 
         // P' x L -> P'', L'
-        let (local_transform, _) = Op::transform::<RtfSchema>(&input_transform, &local_op);
+        let (local_transform, input_local) = Op::transform::<RtfSchema>(&input_transform, &local_op);
 
         // let correction = correct_op(&local_transform).unwrap();
         // let input_correction = correct_op(&input_transform).unwrap();
@@ -186,17 +646,48 @@ impl ClientDoc {
         self.version = version;
         self.original_doc = new_doc.clone();
 
+        // Caches, folds, and undo follow the server's operation as it applies
+        // to our document, updating only the blocks it touched.
+        self.stats.apply(&self.doc, &input_local);
+        self.markdown.apply(&self.doc, &input_local);
+        self.outline.apply(&self.doc, &input_local);
+        self.highlight.apply(&self.doc, &input_local);
+        self.lint.apply(&self.doc, &input_local);
+        self.pagination.apply(&self.doc, &input_local);
+        self.folds.apply(&self.doc, &input_local);
+        self.positions.apply(&self.doc, &input_local);
+        self.transform_undo_stack(&input_local);
+
         // println!("{}", format!("\n----> result {:?}\n{:?}\n{:?}\n\n{:?}\n\n", self.original_doc, self.pending_op, self.local_op, self.doc).red());
 
         self.assert_compose_correctness(None);
     }
 
+    /// Export the current document as markdown, re-rendering only the
+    /// blocks changed since the last export.
+    pub fn to_markdown(&mut self) -> Result<String, Error> {
+        self.markdown.markdown(&self.doc)
+    }
+
+    /// Render the current document as HTML, with folded sections hidden
+    /// and the blocks that start pages marked.
+    pub fn to_html(&mut self) -> String {
+        let breaks = self.pagination.breaks(&self.doc);
+        doc_as_html(&mark_page_breaks(&self.folds.render(&self.doc), &breaks).0)
+    }
+
+    /// Whether there are local operations the server hasn't acknowledged.
+    pub fn is_dirty(&self) -> bool {
+        self.pending_op.is_some() || self.local_op != Op::empty()
+    }
+
     /// When there are no payloads queued, queue a next one.
     pub fn next_payload(&mut self) -> Option<Op> {
         log_wasm!(Debug(format!("NEXT_PAYLOAD: {:?}", self.local_op)));
         if self.pending_op.is_none() && self.local_op != Op::empty() {
             // Take the contents of local_op.
             self.pending_op = Some(mem::replace(&mut self.local_op, Op::empty()));
+            self.sent_ops += 1;
             println!("~~~~~~~> {:?} \n {:?}\n\n", self.pending_op, self.local_op);
             self.pending_op.clone()
         } else {
@@ -257,8 +748,30 @@ impl ClientDoc {
         }
     }
 
+    // Carry the undo stack past an operation applied to `doc`.
+    fn transform_undo_stack(&mut self, op: &Op) {
+        for undo in self.undo_stack.iter_mut() {
+            *undo = Op::transform::<RtfSchema>(op, undo).0;
+        }
+    }
+
+    /// Take the operation undoing our last edit, to be applied with
+    /// `apply_local_op` next. Undo entries that no longer fit the document
+    /// or change nothing, as when others deleted what they'd restore, are
+    /// skipped.
+    pub fn undo_op(&mut self) -> Option<Op> {
+        while let Some(undo) = self.undo_stack.pop() {
+            if undo != Op::empty() && validate_op(&self.doc, &undo).is_ok() {
+                self.undoing = true;
+                return Some(undo);
+            }
+        }
+        None
+    }
+
     /// An operation was applied to the document locally.
     pub fn apply_local_op(&mut self, op: &Op) {
+        let op = &intern_op(op, &mut self.interner);
         self.assert_compose_correctness(Some(op.clone()));
 
         // TODO pending op should be none, but it's actually a value here.
@@ -269,10 +782,28 @@ impl ClientDoc {
         validate_doc(&self.doc).expect("Validation error BEFORE op application");
 
         // Apply the new operation.
-        self.doc = Op::apply(&self.doc, op);
-
-        // TODO Generate an "undo" version of the operation and store it.
-        // This should come from the Op::apply above.
+        let after = Op::apply(&self.doc, op);
+        let before = mem::replace(&mut self.doc, after);
+        self.stats.apply(&self.doc, op);
+        self.markdown.apply(&self.doc, op);
+        self.outline.apply(&self.doc, op);
+        self.highlight.apply(&self.doc, op);
+        self.lint.apply(&self.doc, op);
+        self.pagination.apply(&self.doc, op);
+        self.folds.apply(&self.doc, op);
+        self.positions.apply(&self.doc, op);
+
+        // Remember how to undo the operation, unless it's an undo itself or
+        // only moved carets.
+        self.transform_undo_stack(op);
+        if !mem::replace(&mut self.undoing, false) {
+            if let Some(undo) = content_undo(&before, op, &self.doc) {
+                self.undo_stack.push(undo);
+                if self.undo_stack.len() > UNDO_LIMIT {
+                    self.undo_stack.remove(0);
+                }
+            }
+        }
 
         // Combine operation with previous queued operations.
         self.local_op = Op::compose(&self.local_op, &op);
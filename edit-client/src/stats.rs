@@ -0,0 +1,116 @@
+//! Document statistics, cached per top-level element so that applying an
+//! operation only recounts the elements it touched.
+
+use edit_common::blocks::map_top_level;
+use edit_common::commands::DocStats;
+use oatie::doc::*;
+use oatie::schema::{
+    RtfSchema,
+    RtfTrack,
+};
+use oatie::Schema;
+
+const WORDS_PER_MINUTE: usize = 200;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct ElementStats {
+    chars: usize,
+    words: usize,
+    blocks: usize,
+}
+
+impl ElementStats {
+    fn count(elem: &DocElement) -> ElementStats {
+        let mut stats = ElementStats::default();
+        let mut in_word = false;
+        count_element(elem, &mut stats, &mut in_word);
+        stats
+    }
+}
+
+fn count_element(elem: &DocElement, stats: &mut ElementStats, in_word: &mut bool) {
+    match elem {
+        DocChars(text) => {
+            for c in text.as_str().chars() {
+                stats.chars += 1;
+                if c.is_whitespace() {
+                    *in_word = false;
+                } else if !*in_word {
+                    *in_word = true;
+                    stats.words += 1;
+                }
+            }
+        }
+        DocGroup(attrs, span) => {
+            let track = RtfSchema::track_type_from_attrs(attrs);
+            if track == Some(RtfTrack::Blocks) {
+                stats.blocks += 1;
+            }
+            // Words don't continue across block boundaries, but do continue
+            // across inline objects like carets.
+            if track != Some(RtfTrack::InlineObjects) {
+                *in_word = false;
+            }
+            for child in span {
+                count_element(child, stats, in_word);
+            }
+            if track != Some(RtfTrack::InlineObjects) {
+                *in_word = false;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StatsCache {
+    // Stats for each top-level element of the document, or None if the
+    // element changed and needs to be recounted.
+    elements: Vec<Option<ElementStats>>,
+}
+
+impl StatsCache {
+    pub fn new() -> StatsCache {
+        StatsCache { elements: vec![] }
+    }
+
+    /// Recount the whole document.
+    pub fn reset(&mut self, doc: &Doc) {
+        self.elements = doc.0.iter().map(|elem| Some(ElementStats::count(elem))).collect();
+    }
+
+    /// Update the cache after `op` was applied, producing `doc`.
+    pub fn apply(&mut self, doc: &Doc, op: &Op) {
+        match map_top_level(&self.elements, op) {
+            Some(ref elements) if elements.len() == doc.0.len() => {
+                self.elements = elements
+                    .iter()
+                    .zip(&doc.0)
+                    .map(|(stats, elem)| Some(stats.unwrap_or_else(|| ElementStats::count(elem))))
+                    .collect();
+            }
+            // Operations which split or join top-level elements in ways we
+            // can't follow fall back to recounting everything.
+            _ => self.reset(doc),
+        }
+    }
+
+    pub fn stats(&self) -> DocStats {
+        let total = self
+            .elements
+            .iter()
+            .fold(ElementStats::default(), |acc, stats| {
+                let stats = stats.unwrap_or_default();
+                ElementStats {
+                    chars: acc.chars + stats.chars,
+                    words: acc.words + stats.words,
+                    blocks: acc.blocks + stats.blocks,
+                }
+            });
+        DocStats {
+            chars: total.chars,
+            words: total.words,
+            blocks: total.blocks,
+            reading_time: (total.words * 60 + WORDS_PER_MINUTE - 1) / WORDS_PER_MINUTE,
+        }
+    }
+}
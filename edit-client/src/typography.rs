@@ -0,0 +1,144 @@
+//! Smart typography, applied to characters as they're typed. Each input rule
+//! rewrites the end of the text before the caret, so rules compose: every
+//! enabled rule sees the text as the rules before it left it.
+
+use crate::{
+    actions::*,
+    autocorrect::*,
+    emoji::*,
+    walkers::*,
+};
+
+use edit_common::commands::InputRule;
+use failure::Error;
+use oatie::doc::*;
+use oatie::OT;
+
+// Characters before the caret that rules look at: enough for the longest
+// emoji shortcode, its opening colon, and the character before that. The
+// longest autocorrect pattern and the character before it fit too.
+const LOOKBEHIND: usize = MAX_SHORTCODE_LEN + 2;
+
+// A double or single quote that follows these opens a quotation.
+fn opens_quote(prev: Option<char>) -> bool {
+    match prev {
+        None => true,
+        Some(c) => c.is_whitespace() || "([{\u{201C}\u{2018}\u{2013}\u{2014}".contains(c),
+    }
+}
+
+// Replace the last `len` characters of `text` with `with`.
+fn replace_end(text: &mut Vec<char>, len: usize, with: &str) {
+    let start = text.len() - len;
+    text.truncate(start);
+    text.extend(with.chars());
+}
+
+// A shortcode closed by the last character, as the length of the shortcode
+// with its colons and the emoji replacing it.
+fn closed_shortcode(text: &[char]) -> Option<(usize, &'static str)> {
+    let (last, rest) = text.split_last()?;
+    if *last != ':' {
+        return None;
+    }
+    let rest = rest.iter().collect::<String>();
+    let code = partial_shortcode(&rest)?;
+    let emoji = emoji_for(code)?;
+    Some((code.chars().count() + 2, emoji))
+}
+
+fn apply_rule(rule: InputRule, autocorrect: &Autocorrect, text: &mut Vec<char>) {
+    let rewrite = {
+        // The character `i` places before the end.
+        let last = |i: usize| text.iter().rev().nth(i).cloned();
+        match rule {
+            InputRule::SmartQuotes => match last(0) {
+                Some('"') => Some((1, if opens_quote(last(1)) { "\u{201C}" } else { "\u{201D}" })),
+                Some('\'') => Some((1, if opens_quote(last(1)) { "\u{2018}" } else { "\u{2019}" })),
+                _ => None,
+            },
+            // "--" becomes an en dash, and a third hyphen makes it an em dash.
+            InputRule::Dashes => match (last(1), last(0)) {
+                (Some('-'), Some('-')) => Some((2, "\u{2013}")),
+                (Some('\u{2013}'), Some('-')) => Some((2, "\u{2014}")),
+                _ => None,
+            },
+            InputRule::Ellipses => match (last(2), last(1), last(0)) {
+                (Some('.'), Some('.'), Some('.')) => Some((3, "\u{2026}")),
+                _ => None,
+            },
+            InputRule::Emoji => closed_shortcode(text),
+            InputRule::Autocorrect => return autocorrect.correct(text),
+        }
+    };
+    if let Some((len, with)) = rewrite {
+        replace_end(text, len, with);
+    }
+}
+
+/// Apply `rules` to `input` typed after `before`. Returns how many characters
+/// of `before` to delete and the text to insert in their place.
+pub fn apply_input_rules(
+    rules: &[InputRule],
+    autocorrect: &Autocorrect,
+    before: &str,
+    input: char,
+) -> (usize, String) {
+    let before = before.chars().collect::<Vec<_>>();
+    let mut text = before.clone();
+    text.push(input);
+    for rule in rules {
+        apply_rule(*rule, autocorrect, &mut text);
+    }
+
+    let common = before
+        .iter()
+        .zip(&text)
+        .take_while(|(a, b)| a == b)
+        .count();
+    (before.len() - common, text[common..].iter().collect())
+}
+
+/// Up to `count` characters before our caret, stopping at the start of its
+/// block.
+pub fn text_before_caret(ctx: &ActionContext, count: usize) -> String {
+    let mut walker = Walker::to_caret(&ctx.doc, &ctx.client_id, true);
+    let mut out = vec![];
+    while out.len() < count {
+        let pos = walker.caret_pos();
+        walker.back_char();
+        if walker.caret_pos() == pos {
+            break;
+        }
+        match walker.doc().head() {
+            Some(DocChars(ref text)) => out.push(text.as_str().chars().next().unwrap()),
+            _ => break,
+        }
+    }
+    out.into_iter().rev().collect()
+}
+
+/// Type a character, rewritten by any enabled input rules.
+pub fn add_char_with_rules(
+    mut ctx: ActionContext,
+    rules: &[InputRule],
+    autocorrect: &Autocorrect,
+    input: char,
+) -> Result<Op, Error> {
+    // Typing over a selection is left alone.
+    if rules.is_empty() || has_bounding_carets(ctx.clone()) {
+        return add_string(ctx, &input.to_string());
+    }
+
+    let before = text_before_caret(&ctx, LOOKBEHIND);
+    let (delete, insert) = apply_input_rules(rules, autocorrect, &before, input);
+
+    let mut result = Op::empty();
+    for _ in 0..delete {
+        let op = delete_char(ctx.clone())?;
+        ctx.doc = Op::apply(&ctx.doc, &op);
+        result = Op::compose(&result, &op);
+    }
+    let op = add_string(ctx, &insert)?;
+    Ok(Op::compose(&result, &op))
+}
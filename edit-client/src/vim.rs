@@ -0,0 +1,192 @@
+//! Modal, Vim-style editing. Outside of insert mode, characters are motions
+//! and operators rather than text.
+
+use crate::{
+    actions::*,
+    client::ClientImpl,
+    walkers::*,
+};
+
+use edit_common::commands::*;
+use failure::Error;
+use oatie::doc::*;
+
+#[derive(Debug, Clone)]
+pub struct VimState {
+    pub mode: VimMode,
+    // First key of a two-key command like "dd".
+    pending: Option<char>,
+    // Most recently yanked or deleted text.
+    register: String,
+}
+
+impl VimState {
+    pub fn new() -> VimState {
+        VimState {
+            mode: VimMode::Normal,
+            pending: None,
+            register: String::new(),
+        }
+    }
+}
+
+// Text of the block containing our caret.
+fn block_text(ctx: &ActionContext) -> String {
+    let mut walker = Walker::to_caret(&ctx.doc, &ctx.client_id, true);
+    assert!(walker.back_block());
+    let mut out = String::new();
+    if let Some(DocGroup(_, span)) = walker.doc().head() {
+        span_text(&span, &mut out);
+    }
+    out
+}
+
+fn set_mode<C: ClientImpl>(client: &mut C, mode: VimMode) -> Result<(), Error> {
+    if let Some(ref mut vim) = client.state().vim {
+        vim.mode = mode;
+        vim.pending = None;
+    }
+    client.send_client(&FrontendCommand::VimMode(Some(mode)))
+}
+
+fn yank<C: ClientImpl>(client: &mut C, text: String) {
+    if let Some(ref mut vim) = client.state().vim {
+        vim.register = text;
+    }
+}
+
+/// Turn modal editing on or off. It starts in normal mode.
+pub fn vim_enable<C: ClientImpl>(client: &mut C, enabled: bool) -> Result<(), Error> {
+    client.state().vim = if enabled { Some(VimState::new()) } else { None };
+    let mode = client.state().vim.as_ref().map(|vim| vim.mode);
+    client.send_client(&FrontendCommand::VimMode(mode))
+}
+
+/// Whether input should be treated as Vim commands rather than text.
+pub fn vim_intercepts<C: ClientImpl>(client: &mut C) -> bool {
+    client
+        .state()
+        .vim
+        .as_ref()
+        .map(|vim| vim.mode != VimMode::Insert)
+        .unwrap_or(false)
+}
+
+/// Return to normal mode, dropping any selection.
+pub fn vim_escape<C: ClientImpl>(client: &mut C) -> Result<(), Error> {
+    let mode = match client.state().vim {
+        Some(ref vim) => vim.mode,
+        None => return Ok(()),
+    };
+    if mode == VimMode::Visual {
+        client.client_op(|ctx| clear_anchor(ctx))?;
+    }
+    set_mode(client, VimMode::Normal)
+}
+
+/// Handle a character typed in normal or visual mode.
+pub fn vim_char<C: ClientImpl>(client: &mut C, c: char) -> Result<(), Error> {
+    let (mode, pending) = match client.state().vim {
+        Some(ref mut vim) => (vim.mode, vim.pending.take()),
+        None => return Ok(()),
+    };
+    let visual = mode == VimMode::Visual;
+
+    // Second key of a two-key command.
+    if let Some(first) = pending {
+        match (first, c) {
+            ('d', 'd') => {
+                let text = client.with_action_context(|ctx| Ok(block_text(&ctx)))?;
+                yank(client, text);
+                client.client_op(|ctx| delete_block(ctx))?;
+            }
+            ('y', 'y') => {
+                let text = client.with_action_context(|ctx| Ok(block_text(&ctx)))?;
+                yank(client, text);
+            }
+            _ => {}
+        }
+        return Ok(());
+    }
+
+    match c {
+        // Motions. In visual mode, these extend the selection.
+        'h' => client.client_op(|ctx| caret_move(ctx, false, visual))?,
+        'l' => client.client_op(|ctx| caret_move(ctx, true, visual))?,
+        'j' => client.client_op(|ctx| caret_block_move(ctx, true))?,
+        'k' => client.client_op(|ctx| caret_block_move(ctx, false))?,
+        'w' => {
+            client.client_op(|ctx| caret_word_move(ctx, true))?;
+            client.client_op(|ctx| caret_move(ctx, true, visual))?;
+        }
+        'e' => client.client_op(|ctx| caret_word_move(ctx, true))?,
+        'b' => client.client_op(|ctx| caret_word_move(ctx, false))?,
+        '0' => client.client_op(|ctx| caret_block_edge(ctx, false, visual))?,
+        '$' => client.client_op(|ctx| caret_block_edge(ctx, true, visual))?,
+
+        // Entering insert mode.
+        'i' if !visual => set_mode(client, VimMode::Insert)?,
+        'a' if !visual => {
+            client.client_op(|ctx| caret_move(ctx, true, false))?;
+            set_mode(client, VimMode::Insert)?;
+        }
+        'I' if !visual => {
+            client.client_op(|ctx| caret_block_edge(ctx, false, false))?;
+            set_mode(client, VimMode::Insert)?;
+        }
+        'A' if !visual => {
+            client.client_op(|ctx| caret_block_edge(ctx, true, false))?;
+            set_mode(client, VimMode::Insert)?;
+        }
+        'o' if !visual => {
+            client.client_op(|ctx| caret_block_edge(ctx, true, false))?;
+            client.client_op(|ctx| split_block(ctx, false))?;
+            set_mode(client, VimMode::Insert)?;
+        }
+
+        // Operators.
+        'x' | 'd' if visual => {
+            let text = client.with_action_context(|ctx| Ok(selection_text(&ctx)))?;
+            yank(client, text);
+            client.client_op(|ctx| delete_char(ctx))?;
+            client.client_op(|ctx| clear_anchor(ctx))?;
+            set_mode(client, VimMode::Normal)?;
+        }
+        'y' if visual => {
+            let text = client.with_action_context(|ctx| Ok(selection_text(&ctx)))?;
+            yank(client, text);
+            vim_escape(client)?;
+        }
+        'x' => {
+            client.client_op(|ctx| caret_move(ctx, true, false))?;
+            client.client_op(|ctx| delete_char(ctx))?;
+        }
+        'd' | 'y' => {
+            if let Some(ref mut vim) = client.state().vim {
+                vim.pending = Some(c);
+            }
+        }
+        'p' => {
+            let text = client
+                .state()
+                .vim
+                .as_ref()
+                .map(|vim| vim.register.clone())
+                .unwrap_or_default();
+            if !text.is_empty() {
+                client.client_op(|ctx| add_string(ctx, &text))?;
+            }
+        }
+
+        // Visual mode.
+        'v' if visual => vim_escape(client)?,
+        'v' => {
+            client.client_op(|ctx| caret_set_anchor(ctx))?;
+            set_mode(client, VimMode::Visual)?;
+        }
+
+        _ => {}
+    }
+
+    Ok(())
+}
@@ -1,3 +1,7 @@
+use crate::positions::{
+    count_positions,
+    PositionIndex,
+};
 use oatie::doc::*;
 use oatie::stepper::*;
 use oatie::transform::Schema;
@@ -24,11 +28,29 @@ fn is_caret(attrs: &Attrs, client_id: Option<&str>, focus: bool) -> bool {
             .unwrap_or(false) == focus
 }
 
+// Whether an element is, or holds, a client's caret.
+fn has_caret(elem: &DocElement, client_id: &str, focus: bool) -> bool {
+    match elem {
+        DocGroup(attrs, span) => {
+            is_caret(attrs, Some(client_id), focus)
+                || span.iter().any(|elem| has_caret(elem, client_id, focus))
+        }
+        DocChars(..) => false,
+    }
+}
+
 // Is any caret
 pub fn is_any_caret(attrs: &Attrs) -> bool {
     attrs["tag"] == "caret"
 }
 
+// Inline objects other than carets, like math, take up one caret position,
+// which the caret steps over whole.
+pub fn is_atomic_inline(attrs: &Attrs) -> bool {
+    use oatie::schema::*;
+    RtfSchema::track_type_from_attrs(attrs) == Some(RtfTrack::InlineObjects) && !is_any_caret(attrs)
+}
+
 #[derive(Clone, Debug)]
 pub enum Pos {
     Start,
@@ -65,6 +87,8 @@ impl CaretStepper {
     pub fn is_valid_caret_pos(&self) -> bool {
         if let Some(DocChars(..)) = self.doc.unhead() {
             return true;
+        } else if let Some(DocGroup(ref attrs, _)) = self.doc.unhead() {
+            return is_atomic_inline(attrs);
         } else if self.doc.unhead().is_none() && !self.doc.is_back_done() {
             if let Some(DocGroup(ref attrs, _)) = self.doc.clone().unenter().head() {
                 if is_block(attrs) {
@@ -159,6 +183,8 @@ impl ReverseCaretStepper {
 
         if let Some(DocChars(..)) = doc2.unhead() {
             return true;
+        } else if let Some(DocGroup(ref attrs, _)) = doc2.unhead() {
+            return is_atomic_inline(attrs);
         } else if doc2.unhead().is_none() {
             if doc2.stack.is_empty() {
                 // end of document, bail
@@ -265,6 +291,88 @@ impl Walker {
         matched
     }
 
+    // A walker before a top-level element, whose first caret position is
+    // `start`. Entering the element steps onto it.
+    fn before_element(doc: &Doc, element: usize, start: isize) -> Walker {
+        let mut doc_stepper = DocStepper::new(&doc.0);
+        doc_stepper.skip(element);
+        Walker {
+            original_doc: doc.clone(),
+            stepper: CaretStepper {
+                doc: doc_stepper,
+                caret_pos: start - 1,
+            },
+        }
+    }
+
+    /// A walker at a caret position, found by skipping straight to the
+    /// top-level element that holds it, then over the elements inside it
+    /// which come before the position.
+    pub fn to_pos(doc: &Doc, positions: &PositionIndex, pos: isize) -> Option<Walker> {
+        let (element, start) = positions.element_at(pos)?;
+        let mut walker = Walker::before_element(doc, element, start);
+
+        loop {
+            let stepper = &mut walker.stepper;
+            let head = match stepper.doc.head() {
+                Some(head) => head,
+                None => break,
+            };
+            let count = count_positions(&head) as isize;
+            let (len, enter) = match head {
+                DocChars(ref text) => (text.char_len(), false),
+                DocGroup(ref attrs, _) => (1, !is_atomic_inline(attrs)),
+            };
+            if stepper.caret_pos + count < pos {
+                // Skipping an element passes over all of its caret positions.
+                stepper.doc.skip(len);
+                stepper.caret_pos += count;
+            } else if enter {
+                stepper.next();
+            } else {
+                break;
+            }
+        }
+
+        if walker.goto_pos(pos) {
+            Some(walker)
+        } else {
+            None
+        }
+    }
+
+    /// A walker at one of a client's carets, found by skipping straight to
+    /// the top-level element that holds it.
+    pub fn to_caret_indexed(
+        doc: &Doc,
+        positions: &PositionIndex,
+        client_id: &str,
+        focus: bool,
+    ) -> Option<Walker> {
+        if positions.elements() != doc.0.len() {
+            return Walker::to_caret_safe(doc, client_id, focus);
+        }
+        let element = doc.0.iter().position(|elem| has_caret(elem, client_id, focus))?;
+        let mut walker = Walker::before_element(doc, element, positions.start_of(element));
+
+        let matched = loop {
+            if let Some(DocGroup(attrs, _)) = walker.stepper.doc.head() {
+                if is_caret(&attrs, Some(client_id), focus) {
+                    break true;
+                }
+            }
+            if walker.stepper.next().is_none() {
+                break false;
+            }
+        };
+
+        if matched {
+            Some(walker)
+        } else {
+            None
+        }
+    }
+
     pub fn goto_end(&mut self) {
         take_mut::take(&mut self.stepper, |prev_stepper| {
             let mut stepper = prev_stepper.clone();
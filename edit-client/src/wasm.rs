@@ -3,6 +3,7 @@
 extern crate console_error_panic_hook;
 extern crate edit_common;
 extern crate failure;
+extern crate js_sys;
 extern crate maplit;
 extern crate oatie;
 extern crate rand;
@@ -12,6 +13,8 @@ extern crate take_mut;
 extern crate wbg_rand;
 
 use super::client::*;
+use super::debug::*;
+#[cfg(not(feature = "minimal"))]
 use super::monkey::*;
 use super::state::*;
 use edit_common::{
@@ -20,12 +23,12 @@ use edit_common::{
     markdown::markdown_to_doc,
 };
 use failure::Error;
+use std::cell::RefCell;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use wasm_bindgen::prelude::*;
 use serde_json::Value;
-use edit_common::markdown::doc_to_markdown;
 
 lazy_static! {
     static ref WASM_ALIVE: Arc<AtomicBool> = Arc::new(AtomicBool::new(true));
@@ -34,9 +37,9 @@ lazy_static! {
 
 // JS imports
 
-#[wasm_bindgen(module = "./../editor/wasm")]
+#[wasm_bindgen(module = "./../editor/bridge")]
 extern "C" {
-    /// Send a command *from* the client *to* the frontend.
+    /// Send a log command *from* the client *to* the frontend.
     pub fn sendCommandToJS(input: &str) -> u32;
 
     pub fn forwardWasmTask(input: &str);
@@ -78,19 +81,48 @@ pub fn convertMarkdownToDoc(input: &str) -> String {
 
 // WebAssembly client.
 
+/// Snapshot of the editor's state, returned by `Editor.state()`.
+#[derive(Serialize)]
+struct EditorState {
+    client_id: String,
+    version: usize,
+    title: Option<String>,
+    sync_status: Option<SyncStatus>,
+    read_only: bool,
+}
+
+/// The editor exported to JavaScript. Commands are exchanged as JS objects
+/// rather than JSON strings.
 #[wasm_bindgen]
-pub struct WasmClient {
+pub struct Editor {
     state: Client,
+    // Document id to tag outgoing commands with, when this editor is one of
+    // several documents sharing the wasm instance.
+    mux_id: Option<String>,
+    // Receives commands for the frontend. Commands sent before it is
+    // registered are queued.
+    callback: Option<js_sys::Function>,
+    queue: RefCell<Vec<JsValue>>,
 }
 
-impl ClientImpl for WasmClient {
+impl ClientImpl for Editor {
     fn state(&mut self) -> &mut Client {
         &mut self.state
     }
 
     fn send_client(&self, req: &FrontendCommand) -> Result<(), Error> {
-        let data = serde_json::to_string(&req)?;
-        let _ = sendCommandToJS(&data);
+        let value = match self.mux_id {
+            Some(ref mux_id) => JsValue::from_serde(&MuxFrame(mux_id.clone(), req.clone()))?,
+            None => JsValue::from_serde(&req)?,
+        };
+        match self.callback {
+            Some(ref callback) => {
+                if let Err(err) = callback.call1(&JsValue::NULL, &value) {
+                    bail!("native command callback failed: {:?}", err);
+                }
+            }
+            None => self.queue.borrow_mut().push(value),
+        }
 
         Ok(())
     }
@@ -100,38 +132,25 @@ impl ClientImpl for WasmClient {
     }
 }
 
-// Entry point.
-
 #[wasm_bindgen]
-pub fn wasm_setup() -> WasmClient {
-    // Set the panic hook to log to console.error.
-    console_error_panic_hook::set_once();
-
-    let editor_id = "$$$$$$".to_string();
-
-    // Setup monkey tasks.
-    // setup_monkey::<WasmClient>(Scheduler::new(WASM_ALIVE.clone(), WASM_MONKEY.clone()));
-
-    let client = WasmClient {
-        state: Client {
-            client_id: editor_id,
-            client_doc: ClientDoc::new(),
-
-            monkey: WASM_MONKEY.clone(),
-            alive: WASM_ALIVE.clone(),
-            task_count: 0,
-        },
-    };
-
-    client.setup_controls(None);
+impl Editor {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Editor {
+        Editor::setup(None)
+    }
 
-    client
-}
+    /// Create an editor for one of several documents open in this instance.
+    /// Its commands to the frontend are tagged with `doc_id`.
+    pub fn mux(doc_id: String) -> Editor {
+        Editor::setup(Some(doc_id))
+    }
 
-#[wasm_bindgen]
-impl WasmClient {
     /// Send a command *from* the frontend *to* the client.
-    fn client_task(&mut self, input: Task) -> Result<(), Error> {
+    pub fn command(&mut self, input: JsValue) -> Result<(), JsValue> {
+        let task: Task = input
+            .into_serde()
+            .map_err(|err| JsValue::from_str(&format!("error parsing task: {:?}", err)))?;
+
         // Do a random roll to see how we react when panicking.
         // use wbg_rand::Rng;
         // let mut rng = wbg_rand::wasm_rng();
@@ -139,42 +158,112 @@ impl WasmClient {
         //     panic!("{} encountered a Panic Monkey!!!!!!!!!!!!", self.state().client_id);
         // }
 
-        match self.handle_task(input.clone()) {
-            Ok(_) => {
-            }
-            Err(err) => {
-                // We could panic here, but some errors are resumable
-                console_error!("Error handling task: {:?}\n{:?}", input, err);
-                return Err(err);
-            }
+        if let Err(err) = self.handle_task(task.clone()) {
+            // We could panic here, but some errors are resumable
+            #[cfg(not(feature = "minimal"))]
+            console_error!("Error handling task: {:?}\n{:?}", task, err);
+            return Err(JsValue::from_str(&err.to_string()));
         }
-
-        // Default status
         Ok(())
     }
 
-    /// Send a command *from* the frontend *to* the client.
-    pub fn command(&mut self, input: &str) -> u32 {
-        let req_parse: Result<Task, _> = serde_json::from_slice(&input.as_bytes());
+    /// Send a command from the sync server, as the JSON text it arrived as.
+    /// The text of documents in a large command, like an initial sync, is
+    /// left in the received buffer instead of copied.
+    #[wasm_bindgen(js_name = serverCommand)]
+    pub fn server_command(&mut self, input: String) -> Result<(), JsValue> {
+        let command: ClientCommand = oatie::doc::from_json_shared(input)
+            .map_err(|err| JsValue::from_str(&format!("error parsing command: {:?}", err)))?;
+
+        if let Err(err) = self.handle_task(Task::ClientCommand(command)) {
+            #[cfg(not(feature = "minimal"))]
+            console_error!("Error handling server command: {:?}", err);
+            return Err(JsValue::from_str(&err.to_string()));
+        }
+        Ok(())
+    }
 
-        match req_parse {
-            Ok(task) => {
-                if self.client_task(task).is_err() {
-                    return 1;
-                }
-            }
-            Err(err) => {
-                console_log!("error parsing task: {:?}", err);
-                return 1;
-            }
+    /// Register the callback receiving commands *from* the client *to* the
+    /// frontend, and flush any commands sent before now.
+    #[wasm_bindgen(js_name = onNativeCommand)]
+    pub fn on_native_command(&mut self, callback: js_sys::Function) {
+        for value in self.queue.borrow_mut().drain(..) {
+            let _ = callback.call1(&JsValue::NULL, &value);
         }
+        self.callback = Some(callback);
+    }
 
-        // Default status
-        0
+    #[wasm_bindgen(js_name = exportMarkdown)]
+    pub fn export_markdown(&mut self) -> Result<String, JsValue> {
+        self.state()
+            .client_doc
+            .to_markdown()
+            .map_err(|err| JsValue::from_str(&err.to_string()))
     }
 
-    pub fn asMarkdown(&mut self) -> String {
-        doc_to_markdown(&self.state().client_doc.doc.0).unwrap()
+    /// Move the caret to the block with the given id, and have the frontend
+    /// scroll to it.
+    #[wasm_bindgen(js_name = scrollTo)]
+    pub fn scroll_to(&mut self, id: String) -> Result<(), JsValue> {
+        self.handle_task(Task::ControllerCommand(ControllerCommand::ScrollTo(id)))
+            .map_err(|err| JsValue::from_str(&err.to_string()))
+    }
+
+    /// Serialize the document and our unconfirmed edits, to restore later.
+    #[wasm_bindgen(js_name = saveState)]
+    pub fn save_state(&self) -> Result<String, JsValue> {
+        self.state
+            .client_doc
+            .serialize(&self.state.client_id)
+            .map_err(|err| JsValue::from_str(&err.to_string()))
+    }
+
+    /// Restore the state saved by saveState.
+    #[wasm_bindgen(js_name = restoreState)]
+    pub fn restore_state(&mut self, input: &str) -> Result<(), JsValue> {
+        let client_id = self
+            .state
+            .client_doc
+            .restore(input)
+            .map_err(|err| JsValue::from_str(&err.to_string()))?;
+        self.state.client_id = client_id;
+        Ok(())
+    }
+
+    pub fn state(&self) -> JsValue {
+        let state = &self.state;
+        JsValue::from_serde(&EditorState {
+            client_id: state.client_id.clone(),
+            version: state.client_doc.version,
+            title: state.title.clone(),
+            sync_status: state.sync_status,
+            read_only: state.read_only(),
+        }).unwrap_or(JsValue::NULL)
+    }
+}
+
+impl Editor {
+    fn setup(mux_id: Option<String>) -> Editor {
+        // Set the panic hook to log to console.error. Minimal builds abort
+        // without formatting the panic.
+        #[cfg(not(feature = "minimal"))]
+        console_error_panic_hook::set_once();
+
+        let editor_id = "$$$$$$".to_string();
+
+        // Setup monkey tasks.
+        // setup_monkey::<Editor>(Scheduler::new(WASM_ALIVE.clone(), WASM_MONKEY.clone()));
+
+        let editor = Editor {
+            state: Client::new(&editor_id, WASM_MONKEY.clone(), WASM_ALIVE.clone()),
+            mux_id,
+            callback: None,
+            queue: RefCell::new(vec![]),
+        };
+
+        editor.setup_controls(Keymap::Default, None);
+
+        editor
     }
 }
 
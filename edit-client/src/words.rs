@@ -0,0 +1,52 @@
+//! Word boundaries for caret movement and word selection. Scripts written
+//! without spaces are segmented heuristically, without a dictionary: a word
+//! ends where the script changes, and each Chinese character is a word.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CharClass {
+    // Whitespace and punctuation between words.
+    Separator,
+    // Latin, digits, and anything else written with spaces.
+    Word,
+    Han,
+    Hiragana,
+    Katakana,
+    Hangul,
+}
+
+pub fn char_class(c: char) -> CharClass {
+    match c {
+        c if c.is_whitespace() || c == '-' || c == '_' => CharClass::Separator,
+        // Ideographic iteration mark, which repeats the preceding ideograph.
+        '\u{3005}' => CharClass::Han,
+        // CJK punctuation, and fullwidth forms of ASCII punctuation.
+        '\u{3000}'..='\u{303F}'
+        | '\u{FF01}'..='\u{FF0F}'
+        | '\u{FF1A}'..='\u{FF20}'
+        | '\u{FF3B}'..='\u{FF40}'
+        | '\u{FF5B}'..='\u{FF65}' => CharClass::Separator,
+        '\u{3040}'..='\u{309F}' => CharClass::Hiragana,
+        '\u{30A0}'..='\u{30FF}' | '\u{31F0}'..='\u{31FF}' | '\u{FF66}'..='\u{FF9F}' => {
+            CharClass::Katakana
+        }
+        '\u{1100}'..='\u{11FF}' | '\u{3130}'..='\u{318F}' | '\u{AC00}'..='\u{D7AF}' => {
+            CharClass::Hangul
+        }
+        '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{20000}'..='\u{2FA1F}' => CharClass::Han,
+        _ => CharClass::Word,
+    }
+}
+
+/// Whether a word ends between two adjacent characters, given their classes.
+pub fn is_word_boundary(left: CharClass, right: CharClass) -> bool {
+    match (left, right) {
+        (CharClass::Separator, _) | (_, CharClass::Separator) => true,
+        // Kana endings written after a Japanese kanji belong to its word.
+        (CharClass::Han, CharClass::Hiragana) => false,
+        (CharClass::Han, _) | (_, CharClass::Han) => true,
+        (left, right) => left != right,
+    }
+}
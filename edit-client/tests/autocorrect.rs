@@ -0,0 +1,75 @@
+extern crate edit_client;
+extern crate edit_common;
+extern crate failure;
+extern crate oatie;
+
+mod support;
+
+use edit_client::autocorrect::*;
+use edit_common::commands::*;
+use support::*;
+
+fn correct(autocorrect: &Autocorrect, text: &str) -> String {
+    let mut chars = text.chars().collect::<Vec<_>>();
+    autocorrect.correct(&mut chars);
+    chars.into_iter().collect()
+}
+
+#[test]
+fn corrects_whole_words() {
+    let autocorrect = Autocorrect::new();
+    assert_eq!(correct(&autocorrect, "see teh "), "see the ");
+    assert_eq!(correct(&autocorrect, "teh."), "the.");
+
+    // Only patterns ended by the last character, and not inside a word.
+    assert_eq!(correct(&autocorrect, "teh"), "teh");
+    assert_eq!(correct(&autocorrect, "steh "), "steh ");
+    assert_eq!(correct(&autocorrect, "teh see "), "teh see ");
+}
+
+#[test]
+fn keeps_capitalization() {
+    let autocorrect = Autocorrect::new();
+    assert_eq!(correct(&autocorrect, "Teh "), "The ");
+    assert_eq!(correct(&autocorrect, "TEH "), "THE ");
+    assert_eq!(correct(&autocorrect, "Alot "), "A lot ");
+}
+
+#[test]
+fn rules_can_be_added_and_removed() {
+    let mut autocorrect = Autocorrect::new();
+    autocorrect.set_rule("wrk", Some("work")).unwrap();
+    assert_eq!(correct(&autocorrect, "wrk "), "work ");
+
+    // Patterns are matched ignoring case, so this removes the rule.
+    autocorrect.set_rule("TEH", None).unwrap();
+    assert_eq!(correct(&autocorrect, "teh "), "teh ");
+
+    assert!(autocorrect.set_rule("", Some("x")).is_err());
+    assert!(autocorrect.set_rule(" wrk!", Some("work")).is_err());
+    let long = "a".repeat(MAX_PATTERN_LEN + 1);
+    assert!(autocorrect.set_rule(&long, Some("x")).is_err());
+}
+
+#[test]
+fn dictionaries_skip_invalid_rules() {
+    let mut autocorrect = Autocorrect::new();
+    autocorrect.load_dictionary(&[
+        ("!!".to_string(), "bang".to_string()),
+        ("wrk".to_string(), "work".to_string()),
+    ]);
+    assert_eq!(correct(&autocorrect, "wrk "), "work ");
+    assert_eq!(correct(&autocorrect, "!! "), "!! ");
+}
+
+#[test]
+fn corrects_while_typing() {
+    let mut client = TestClient::new("x");
+    client.command(ControllerCommand::InputRule(InputRule::Autocorrect, true));
+    client.command(ControllerCommand::Autocorrect(
+        "wrk".to_string(),
+        Some("work".to_string()),
+    ));
+    client.type_text("teh wrk ");
+    assert_eq!(client.blocks(), vec!["the work x"]);
+}
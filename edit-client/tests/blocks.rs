@@ -0,0 +1,264 @@
+#[macro_use]
+extern crate oatie;
+extern crate edit_client;
+extern crate edit_common;
+extern crate failure;
+
+mod support;
+
+use edit_client::*;
+use edit_common::commands::*;
+use oatie::doc::*;
+use support::*;
+
+// Blocks of `client` once `op` is applied, without applying it.
+fn blocks_after(client: &TestClient, op: &Op) -> Vec<String> {
+    block_texts(&Op::apply(&client.client.client_doc.doc, op))
+}
+
+// Attributes of each top-level block of `client`.
+fn block_attrs(client: &TestClient) -> Vec<Attrs> {
+    client
+        .client
+        .client_doc
+        .doc
+        .0
+        .iter()
+        .map(|elem| match *elem {
+            DocGroup(ref attrs, _) => attrs.clone(),
+            DocChars(_) => panic!("expected a block"),
+        })
+        .collect()
+}
+
+fn block_tags(client: &TestClient) -> Vec<String> {
+    block_attrs(client).into_iter().map(|x| x["tag"].clone()).collect()
+}
+
+fn press(client: &mut TestClient, key: u32) {
+    client.command(ControllerCommand::Keypress(key, false, false, false));
+}
+
+#[test]
+fn move_block_down_and_up() {
+    let mut client = TestClient::new("one\n\ntwo\n\nthree");
+    client.client_op(|ctx| move_block(ctx, true)).unwrap();
+    assert_eq!(client.blocks(), vec!["two", "one", "three"]);
+
+    // The caret moves with its block.
+    client.client_op(|ctx| move_block(ctx, true)).unwrap();
+    assert_eq!(client.blocks(), vec!["two", "three", "one"]);
+    client.client_op(|ctx| add_string(ctx, "X")).unwrap();
+    assert_eq!(client.blocks(), vec!["two", "three", "Xone"]);
+
+    client.client_op(|ctx| move_block(ctx, false)).unwrap();
+    assert_eq!(client.blocks(), vec!["two", "Xone", "three"]);
+}
+
+#[test]
+fn move_block_at_edges() {
+    let mut client = TestClient::new("one\n\ntwo");
+    let op = client.action_op(|ctx| move_block(ctx, false));
+    assert_eq!(op, Op::empty());
+
+    client.client_op(|ctx| caret_block_move(ctx, true)).unwrap();
+    let op = client.action_op(|ctx| move_block(ctx, true));
+    assert_eq!(op, Op::empty());
+}
+
+#[test]
+fn move_block_only_touches_moved_block() {
+    let mut client = TestClient::new("one\n\ntwo\n\nthree");
+    let op = client.action_op(|ctx| move_block(ctx, true));
+    assert_eq!(blocks_after(&client, &op), vec!["two", "one", "three"]);
+
+    // The neighbour and the block after it are skipped over, not deleted.
+    let (ref del, ref add) = op;
+    assert_eq!(del.len(), 1);
+    match del[0] {
+        DelGroup(..) => {}
+        ref other => panic!("expected the moved block deleted, got {:?}", other),
+    }
+    assert_eq!(add[0], AddSkip(1));
+}
+
+#[test]
+fn move_block_down_with_concurrent_edit_in_neighbour() {
+    let mut client = TestClient::new("one\n\ntwo\n\nthree");
+    let doc = client.client.client_doc.doc.clone();
+    let a = client.action_op(|ctx| move_block(ctx, true));
+    // Someone else types at the start of "two".
+    let b = op_span!([], [AddSkip(1), AddWithGroup([AddChars("X")])]);
+
+    let result = converge(&doc, &a, &b);
+    assert_eq!(block_texts(&result), vec!["Xtwo", "one", "three"]);
+}
+
+#[test]
+fn move_block_up_with_concurrent_edit_in_neighbour() {
+    let mut client = TestClient::new("one\n\ntwo\n\nthree");
+    client.client_op(|ctx| caret_block_move(ctx, true)).unwrap();
+    let doc = client.client.client_doc.doc.clone();
+    let a = client.action_op(|ctx| move_block(ctx, false));
+    // Someone else replaces the "e" of "one" with an "s".
+    let b = op_span!(
+        [DelWithGroup([DelSkip(2), DelChars(1)])],
+        [AddWithGroup([AddSkip(2), AddChars("s")])],
+    );
+
+    let result = converge(&doc, &a, &b);
+    assert_eq!(block_texts(&result), vec!["two", "ons", "three"]);
+}
+
+#[test]
+fn move_block_with_concurrent_edit_elsewhere() {
+    let mut client = TestClient::new("one\n\ntwo\n\nthree");
+    let doc = client.client.client_doc.doc.clone();
+    let a = client.action_op(|ctx| move_block(ctx, true));
+    let b = op_span!([], [AddSkip(2), AddWithGroup([AddChars("X")])]);
+
+    let result = converge(&doc, &a, &b);
+    assert_eq!(block_texts(&result), vec!["two", "one", "Xthree"]);
+}
+
+// Headings A, B and C, with B's section holding the subheading B2.
+const SECTIONS: &str = "# A\n\na1\n\n# B\n\nb1\n\n## B2\n\nb2\n\n# C\n\nc1";
+
+#[test]
+fn move_section_up_and_to_the_end() {
+    let mut client = TestClient::new(SECTIONS);
+    client.client_op(|ctx| move_section(ctx, "b", 0)).unwrap();
+    assert_eq!(client.blocks(), vec!["B", "b1", "B2", "b2", "A", "a1", "C", "c1"]);
+
+    // Past the last entry is the end of the document.
+    client.client_op(|ctx| move_section(ctx, "a", 4)).unwrap();
+    assert_eq!(client.blocks(), vec!["B", "b1", "B2", "b2", "C", "c1", "A", "a1"]);
+}
+
+#[test]
+fn move_section_into_itself_does_nothing() {
+    let mut client = TestClient::new(SECTIONS);
+    assert_eq!(client.action_op(|ctx| move_section(ctx, "b", 2)), Op::empty());
+    assert_eq!(client.action_op(|ctx| move_section(ctx, "b", 1)), Op::empty());
+    assert_eq!(client.action_op(|ctx| move_section(ctx, "missing", 0)), Op::empty());
+}
+
+#[test]
+fn move_section_only_touches_the_section() {
+    let mut client = TestClient::new(SECTIONS);
+    let op = client.action_op(|ctx| move_section(ctx, "a", 3));
+    assert_eq!(
+        blocks_after(&client, &op),
+        vec!["B", "b1", "B2", "b2", "A", "a1", "C", "c1"]
+    );
+
+    // The sections it passes over are skipped, not deleted.
+    let (ref del, ref add) = op;
+    assert_eq!(del.len(), 2);
+    assert_eq!(add[0], AddSkip(4));
+    assert_eq!(add.len(), 3);
+}
+
+#[test]
+fn move_section_with_concurrent_edit_passed_over() {
+    let mut client = TestClient::new(SECTIONS);
+    let doc = client.client.client_doc.doc.clone();
+    let a = client.action_op(|ctx| move_section(ctx, "a", 4));
+    // Someone else types at the start of "b1".
+    let b = op_span!([], [AddSkip(3), AddWithGroup([AddChars("X")])]);
+
+    let result = converge(&doc, &a, &b);
+    assert_eq!(
+        block_texts(&result),
+        vec!["B", "Xb1", "B2", "b2", "C", "c1", "A", "a1"]
+    );
+}
+
+#[test]
+fn split_heading_in_the_middle() {
+    let mut client = TestClient::new("# Heading");
+    client.command(ControllerCommand::SelectRange(3, 3));
+    press(&mut client, 13);
+    assert_eq!(client.blocks(), vec!["Hea", "ding"]);
+    assert_eq!(block_tags(&client), vec!["h1", "h1"]);
+
+    let attrs = block_attrs(&client);
+    assert!(attrs[1].contains_key("id"));
+    assert_ne!(attrs[0].get("id"), attrs[1].get("id"));
+}
+
+#[test]
+fn split_term_at_the_end() {
+    let mut client = TestClient::new("term");
+    client.client_op(|ctx| replace_block(ctx, "dt")).unwrap();
+    client.command(ControllerCommand::SelectRange(4, 4));
+    press(&mut client, 13);
+    assert_eq!(client.blocks(), vec!["term", ""]);
+    assert_eq!(block_tags(&client), vec!["dt", "dd"]);
+}
+
+#[test]
+fn split_keeps_only_the_kind_of_block() {
+    let mut client = TestClient::new("abcd");
+    client.client_op(|ctx| set_alignment(ctx, Some("center"))).unwrap();
+    // Give the paragraph a class and a language. Its caret and characters
+    // are five elements.
+    client
+        .client_op(|ctx| {
+            let mut attrs = match ctx.doc.0[0] {
+                DocGroup(ref attrs, _) => attrs.clone(),
+                DocChars(_) => unreachable!(),
+            };
+            attrs.insert("class".to_string(), "note".to_string());
+            attrs.insert("lang".to_string(), "fr".to_string());
+            Ok((
+                vec![DelGroup(vec![DelSkip(5)])],
+                vec![AddGroup(attrs, vec![AddSkip(5)])],
+            ))
+        })
+        .unwrap();
+
+    client.command(ControllerCommand::SelectRange(2, 2));
+    press(&mut client, 13);
+    assert_eq!(client.blocks(), vec!["ab", "cd"]);
+    let attrs = block_attrs(&client);
+    assert_eq!(attrs[0]["class"], "note");
+    assert_eq!(attrs[1]["align"], "center");
+    assert!(!attrs[1].contains_key("class"));
+    assert!(!attrs[1].contains_key("lang"));
+}
+
+#[test]
+fn join_heading_after_empty_paragraph() {
+    let mut client = TestClient::new("x\n\n# Head");
+    client.command(ControllerCommand::SelectRange(1, 1));
+    press(&mut client, 8);
+    assert_eq!(client.blocks(), vec!["", "Head"]);
+
+    // The empty paragraph gives way to the heading.
+    client.command(ControllerCommand::SelectRange(1, 1));
+    press(&mut client, 8);
+    assert_eq!(client.blocks(), vec!["Head"]);
+    assert_eq!(block_tags(&client), vec!["h1"]);
+}
+
+#[test]
+fn join_with_previous_block_from_the_middle() {
+    let mut client = TestClient::new("# Head\n\n**bold** tail");
+    client.command(ControllerCommand::SelectRange(11, 11));
+    client.command(ControllerCommand::Keypress(74, true, true, false));
+    assert_eq!(client.blocks(), vec!["Headbold tail"]);
+    assert_eq!(block_tags(&client), vec!["h1"]);
+
+    // The joined text keeps its styles.
+    match client.client.client_doc.doc.0[0] {
+        DocGroup(_, ref span) => assert!(span.iter().any(|elem| match *elem {
+            DocChars(ref text) => {
+                text.as_str() == "bold"
+                    && text.styles().map(|x| x.contains_key(&Style::Bold)).unwrap_or(false)
+            }
+            _ => false,
+        })),
+        _ => panic!("expected a block"),
+    }
+}
@@ -0,0 +1,51 @@
+extern crate edit_client;
+
+use edit_client::chaos::*;
+use std::env;
+
+#[test]
+fn parse_faults() {
+    let config = ChaosConfig::parse("delay=0.05, reorder=0.5,max_delay=100").unwrap();
+    assert_eq!(
+        config,
+        ChaosConfig {
+            delay: 0.05,
+            max_delay: 100,
+            reorder: 0.5,
+        }
+    );
+
+    // Faults not given never happen.
+    let config = ChaosConfig::parse("reorder=1").unwrap();
+    assert_eq!(config.delay, 0.0);
+    assert_eq!(config.max_delay, 500);
+    assert_eq!(ChaosConfig::parse("").unwrap().reorder, 0.0);
+}
+
+#[test]
+fn parse_refuses_bad_faults() {
+    assert!(ChaosConfig::parse("delay").is_err());
+    assert!(ChaosConfig::parse("delay=often").is_err());
+    assert!(ChaosConfig::parse("delay=1.5").is_err());
+    assert!(ChaosConfig::parse("delay=-0.1").is_err());
+    assert!(ChaosConfig::parse("max_delay=-1").is_err());
+    assert!(ChaosConfig::parse("jitter=0.1").is_err());
+
+    // A websocket never drops or duplicates frames, so neither does the
+    // link.
+    assert!(ChaosConfig::parse("drop=0.01").is_err());
+    assert!(ChaosConfig::parse("delay=0.1,duplicate=0.01").is_err());
+}
+
+#[test]
+fn faults_from_env() {
+    env::set_var(CHAOS_VAR, "delay=0.25");
+    assert_eq!(ChaosConfig::from_env().map(|config| config.delay), Some(0.25));
+
+    // A bad setting is ignored rather than failing the proxy.
+    env::set_var(CHAOS_VAR, "drop=0.25");
+    assert_eq!(ChaosConfig::from_env(), None);
+
+    env::remove_var(CHAOS_VAR);
+    assert_eq!(ChaosConfig::from_env(), None);
+}
@@ -0,0 +1,88 @@
+#[macro_use]
+extern crate oatie;
+extern crate edit_client;
+extern crate edit_common;
+extern crate failure;
+
+mod support;
+
+use edit_client::*;
+use edit_common::commands::*;
+use edit_common::comments::*;
+use oatie::doc::*;
+use support::*;
+
+fn thread(id: &str, resolved: bool) -> CommentThread {
+    CommentThread {
+        id: id.to_string(),
+        anchor: "intro".to_string(),
+        resolved,
+        comments: vec![],
+    }
+}
+
+fn shown_threads(client: &TestClient) -> Vec<String> {
+    match client.frontend.borrow().last() {
+        Some(FrontendCommand::Comments(threads)) => threads.iter().map(|x| x.id.clone()).collect(),
+        other => panic!("expected comments, got {:?}", other),
+    }
+}
+
+#[test]
+fn resolved_comments_shown_on_request() {
+    let mut client = TestClient::new("text");
+    client.task(Task::ClientCommand(ClientCommand::Comments(vec![
+        thread("open", false),
+        thread("done", true),
+    ])));
+    assert_eq!(shown_threads(&client), vec!["open"]);
+
+    client.command(ControllerCommand::ShowResolvedComments(true));
+    assert_eq!(shown_threads(&client), vec!["open", "done"]);
+
+    // Later threads are shown the same way.
+    client.task(Task::ClientCommand(ClientCommand::Comments(vec![thread("done", true)])));
+    assert_eq!(shown_threads(&client), vec!["done"]);
+
+    client.command(ControllerCommand::ShowResolvedComments(false));
+    assert!(shown_threads(&client).is_empty());
+}
+
+#[test]
+fn start_comment_on_caret_block() {
+    let mut client = TestClient::new("text");
+    client.task(Task::ClientCommand(ClientCommand::Init(
+        "test".to_string(),
+        doc_span![
+            DocGroup({"tag": "p", "id": "intro"}, [DocChars("hello")]),
+        ],
+        100,
+    )));
+
+    client.command(ControllerCommand::StartComment("nice".to_string()));
+    match client.sync.borrow().last() {
+        Some(ServerCommand::Comment(_, action)) => assert_eq!(
+            *action,
+            CommentAction::Start("intro".to_string(), "nice".to_string())
+        ),
+        other => panic!("expected a comment, got {:?}", other),
+    }
+
+    let action = CommentAction::Resolve("thread".to_string());
+    client.command(ControllerCommand::Comment(action.clone()));
+    match client.sync.borrow().last() {
+        Some(ServerCommand::Comment(_, sent)) => assert_eq!(*sent, action),
+        other => panic!("expected a comment, got {:?}", other),
+    }
+}
+
+#[test]
+fn start_comment_needs_block_id() {
+    let mut client = TestClient::new("text");
+    let sent = client.sync.borrow().len();
+    let res = client.handle_task(Task::ControllerCommand(ControllerCommand::StartComment(
+        "nice".to_string(),
+    )));
+    assert!(res.is_err());
+    assert_eq!(client.sync.borrow().len(), sent);
+}
@@ -0,0 +1,85 @@
+#[macro_use]
+extern crate oatie;
+extern crate edit_client;
+extern crate edit_common;
+
+use edit_client::e2ee::*;
+use edit_common::commands::*;
+use edit_common::e2ee::*;
+use oatie::doc::*;
+use oatie::OT;
+
+const KEY: &str = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
+
+fn append(index: usize, text: &str) -> Op {
+    op_span!([], [AddSkip(index), AddGroup({"tag": "p"}, [AddChars(text)])])
+}
+
+#[test]
+fn init_skips_ops_that_dont_open() {
+    let key = DocKey::from_base64(KEY, "secret").unwrap();
+    // The same key for another page, whose operations don't open here.
+    let other = DocKey::from_base64(KEY, "elsewhere").unwrap();
+
+    let ops = vec![
+        seal_op(&key, &append(1, "one")).unwrap(),
+        seal_op(&other, &append(2, "elsewhere")).unwrap(),
+        "not sealed".to_string(),
+        // Opens, but doesn't fit the document.
+        seal_op(&key, &op_span!([DelWithGroup([DelChars(100)])], [])).unwrap(),
+        seal_op(&key, &append(2, "two")).unwrap(),
+    ];
+    let ops = ops
+        .into_iter()
+        .map(|sealed| ("client".to_string(), sealed))
+        .collect::<Vec<_>>();
+
+    let command = ClientCommand::EncryptedInit("me".to_string(), ops, 5);
+    match open_client_command(&key, &initial_encrypted_doc(), command).unwrap() {
+        ClientCommand::Init(client_id, doc, version) => {
+            assert_eq!(client_id, "me");
+            assert_eq!(version, 5);
+            assert_eq!(
+                doc,
+                doc_span![
+                    DocGroup({"tag": "h1"}, [DocChars("Untitled")]),
+                    DocGroup({"tag": "p"}, [DocChars("one")]),
+                    DocGroup({"tag": "p"}, [DocChars("two")]),
+                ]
+            );
+        }
+        other => panic!("expected Init, got {:?}", other),
+    }
+}
+
+#[test]
+fn update_opens_against_original() {
+    let key = DocKey::from_base64(KEY, "secret").unwrap();
+    let original = initial_encrypted_doc();
+
+    let sealed = seal_op(&key, &append(1, "one")).unwrap();
+    let command = ClientCommand::EncryptedUpdate(1, "them".to_string(), sealed);
+    match open_client_command(&key, &original, command).unwrap() {
+        ClientCommand::Update(1, ref client_id, ref op) if client_id == "them" => {
+            assert_eq!(*op, append(1, "one"));
+        }
+        other => panic!("expected Update, got {:?}", other),
+    }
+
+    // Operations that don't fit the original are taken as empty, so the
+    // version still advances.
+    let sealed = seal_op(&key, &append(5, "far")).unwrap();
+    let command = ClientCommand::EncryptedUpdate(2, "them".to_string(), sealed);
+    match open_client_command(&key, &original, command).unwrap() {
+        ClientCommand::Update(2, _, op) => assert_eq!(op, Op::empty()),
+        other => panic!("expected Update, got {:?}", other),
+    }
+}
+
+#[test]
+fn sealed_values_are_recognized() {
+    let key = DocKey::from_base64(KEY, "secret").unwrap();
+    assert!(is_sealed(&seal_op(&key, &append(1, "one")).unwrap()));
+    assert!(!is_sealed("not sealed"));
+    assert!(!is_sealed("AAAA"));
+}
@@ -0,0 +1,137 @@
+#[macro_use]
+extern crate oatie;
+extern crate edit_client;
+extern crate edit_common;
+extern crate failure;
+
+mod support;
+
+use edit_client::folds::*;
+use edit_client::*;
+use edit_common::commands::*;
+use edit_common::markdown::markdown_to_doc;
+use oatie::doc::*;
+use support::*;
+
+const SECTIONS: &str = "# One\n\nfirst\n\n## Two\n\nsecond\n\n# Three";
+
+fn folds(doc: &Doc) -> Folds {
+    let mut folds = Folds::new();
+    folds.reset(doc);
+    folds
+}
+
+#[test]
+fn fold_hides_section() {
+    let doc = Doc(markdown_to_doc(SECTIONS).unwrap());
+    let mut folds = folds(&doc);
+    assert_eq!(folds.hidden(&doc), vec![false; 5]);
+
+    // A section runs to the next heading of its level, over subsections.
+    assert!(folds.toggle(&doc, 0));
+    assert_eq!(folds.hidden(&doc), vec![false, true, true, true, false]);
+
+    assert!(folds.toggle(&doc, 0));
+    assert_eq!(folds.hidden(&doc), vec![false; 5]);
+}
+
+#[test]
+fn fold_subsection() {
+    let doc = Doc(markdown_to_doc(SECTIONS).unwrap());
+    let mut folds = folds(&doc);
+    assert!(folds.toggle(&doc, 2));
+    assert_eq!(folds.hidden(&doc), vec![false, false, false, true, false]);
+
+    // Folding the outer section too leaves the inner one folded inside it.
+    assert!(folds.toggle(&doc, 0));
+    assert!(folds.toggle(&doc, 0));
+    assert_eq!(folds.hidden(&doc), vec![false, false, false, true, false]);
+}
+
+#[test]
+fn fold_only_headings() {
+    let doc = Doc(markdown_to_doc(SECTIONS).unwrap());
+    let mut folds = folds(&doc);
+    assert!(!folds.toggle(&doc, 1));
+    assert!(!folds.toggle(&doc, 10));
+    assert_eq!(folds.hidden(&doc), vec![false; 5]);
+}
+
+#[test]
+fn fold_render_classes() {
+    let doc = Doc(markdown_to_doc(SECTIONS).unwrap());
+    let mut folds = folds(&doc);
+    folds.toggle(&doc, 2);
+    let classes = folds
+        .render(&doc)
+        .0
+        .iter()
+        .map(|elem| match elem {
+            DocGroup(attrs, _) => attrs.get("class").cloned(),
+            DocChars(_) => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        classes,
+        vec![
+            None,
+            None,
+            Some("fold-collapsed".to_string()),
+            Some("folded".to_string()),
+            None,
+        ]
+    );
+}
+
+#[test]
+fn fold_follows_edits() {
+    let doc = Doc(markdown_to_doc(SECTIONS).unwrap());
+    let mut folds = folds(&doc);
+    folds.toggle(&doc, 2);
+
+    // A block inserted before the folded heading shifts it along.
+    let op = op_span!([], [AddGroup({"tag": "p"}, [AddChars("new")])]);
+    let doc = Op::apply(&doc, &op);
+    folds.apply(&doc, &op);
+    assert_eq!(
+        folds.hidden(&doc),
+        vec![false, false, false, false, true, false]
+    );
+
+    // Text edits inside a block keep the fold.
+    let op = op_span!([], [AddSkip(1), AddWithGroup([AddChars("x")])]);
+    let doc = Op::apply(&doc, &op);
+    folds.apply(&doc, &op);
+    assert_eq!(
+        folds.hidden(&doc),
+        vec![false, false, false, false, true, false]
+    );
+}
+
+#[test]
+fn toggle_fold_moves_caret_past_hidden_blocks() {
+    let mut client = TestClient::new("# One\n\nfirst\n\n# Two");
+    client.command(ControllerCommand::ToggleFold);
+    {
+        let client_doc = &client.client.client_doc;
+        assert_eq!(
+            client_doc.folds.hidden(&client_doc.doc),
+            vec![false, true, false]
+        );
+    }
+    match client.frontend.borrow().last() {
+        Some(FrontendCommand::Update(html, ..)) => assert!(html.contains("folded")),
+        other => panic!("expected an update, got {:?}", other),
+    }
+
+    // Moving down skips the folded paragraph.
+    client.command(ControllerCommand::Keypress(40, false, false, false));
+    client.type_text("X");
+    assert_eq!(client.blocks(), vec!["One", "first", "XTwo"]);
+
+    // Unfolding shows it again.
+    client.command(ControllerCommand::Keypress(38, false, false, false));
+    client.command(ControllerCommand::ToggleFold);
+    let client_doc = &client.client.client_doc;
+    assert_eq!(client_doc.folds.hidden(&client_doc.doc), vec![false; 3]);
+}
@@ -0,0 +1,98 @@
+extern crate edit_client;
+extern crate edit_common;
+extern crate failure;
+extern crate oatie;
+
+mod support;
+
+use edit_client::*;
+use edit_common::commands::*;
+use edit_common::crdt::CrdtOp;
+use std::cell::RefCell;
+use std::sync::{
+    atomic::AtomicBool,
+    Arc,
+};
+use support::*;
+
+// A client of a peer page, sent the page's operations so far.
+fn peer_client(client_id: &str, ops: Vec<CrdtOp>) -> TestClient {
+    let mut client = TestClient {
+        client: Client::new(
+            "$$$$$$",
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicBool::new(true)),
+        ),
+        frontend: RefCell::new(vec![]),
+        sync: RefCell::new(vec![]),
+    };
+    client.command(ControllerCommand::PeerSync);
+    client.task(Task::ClientCommand(ClientCommand::PeerInit(
+        client_id.to_string(),
+        ops,
+    )));
+    client
+}
+
+// The operations a client sent to the relay since we last looked.
+fn sent_ops(client: &TestClient) -> Vec<CrdtOp> {
+    client
+        .sync
+        .borrow_mut()
+        .drain(..)
+        .flat_map(|command| match command {
+            ServerCommand::PeerOps(_, ops) => ops,
+            other => panic!("expected PeerOps, got {:?}", other),
+        })
+        .collect()
+}
+
+fn relay(from: &TestClient, from_id: &str, to: &mut [&mut TestClient]) -> Vec<CrdtOp> {
+    let ops = sent_ops(from);
+    for client in to {
+        client.task(Task::ClientCommand(ClientCommand::PeerOps(
+            from_id.to_string(),
+            ops.clone(),
+        )));
+    }
+    ops
+}
+
+#[test]
+fn typing_reaches_other_peers() {
+    let mut a = peer_client("a", vec![]);
+    let mut b = peer_client("b", vec![]);
+    assert_eq!(a.blocks(), vec![""]);
+
+    // Adding our caret isn't sent, and nothing waits on the relay.
+    assert_eq!(sent_ops(&a), vec![]);
+    a.type_text("hi");
+    assert!(a.client.client_doc.pending_op.is_none());
+    relay(&a, "a", &mut [&mut b]);
+    assert_eq!(b.blocks(), vec!["hi"]);
+
+    // Our caret stays put through their edits.
+    b.type_text("X");
+    relay(&b, "b", &mut [&mut a]);
+    assert_eq!(a.blocks(), b.blocks());
+    assert_eq!(b.blocks()[0].len(), 3);
+}
+
+#[test]
+fn concurrent_typing_converges() {
+    let mut a = peer_client("a", vec![]);
+    let mut b = peer_client("b", vec![]);
+    a.type_text("one");
+    a.command(ControllerCommand::Keypress(13, false, false, false));
+    a.type_text("two");
+    b.type_text("three");
+
+    let mut log = relay(&a, "a", &mut [&mut b]);
+    log.extend(relay(&b, "b", &mut [&mut a]));
+    assert_eq!(a.blocks(), b.blocks());
+    assert_eq!(a.blocks().len(), 2);
+
+    // A client joining later replays everything.
+    let c = peer_client("c", log);
+    assert_eq!(c.blocks(), a.blocks());
+}
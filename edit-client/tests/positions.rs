@@ -0,0 +1,123 @@
+extern crate edit_client;
+extern crate edit_common;
+extern crate failure;
+#[macro_use]
+extern crate oatie;
+
+mod support;
+
+use edit_client::positions::*;
+use edit_client::walkers::*;
+use edit_common::commands::*;
+use edit_common::markdown::markdown_to_doc;
+use oatie::doc::*;
+use oatie::OT;
+use support::*;
+
+// A list item holding a nested list is one top-level element.
+const NESTED: &str = "# Title\n\n- one\n- two\n    - three\n    - four\n\nlast *styled* text\n";
+
+fn index(doc: &Doc) -> PositionIndex {
+    let mut positions = PositionIndex::new();
+    positions.reset(doc);
+    positions
+}
+
+// Whether the index agrees with a fresh count of `doc`.
+fn assert_fresh(positions: &PositionIndex, doc: &Doc) {
+    let fresh = index(doc);
+    assert_eq!(positions.elements(), fresh.elements());
+    assert_eq!(positions.len(), fresh.len());
+    for element in 0..fresh.elements() {
+        assert_eq!(positions.start_of(element), fresh.start_of(element));
+    }
+}
+
+#[test]
+fn to_pos_matches_walking() {
+    let doc = Doc(markdown_to_doc(NESTED).unwrap());
+    let positions = index(&doc);
+    assert_eq!(positions.elements(), 4);
+
+    for pos in 0..(positions.len() as isize) {
+        let indexed = Walker::to_pos(&doc, &positions, pos).unwrap();
+        let mut walked = Walker::new(&doc);
+        assert!(walked.goto_pos(pos));
+        assert_eq!(indexed.caret_pos(), pos);
+        assert_eq!(indexed.doc().head(), walked.doc().head());
+        assert_eq!(indexed.doc().unhead(), walked.doc().unhead());
+    }
+
+    // There's no caret position past the end.
+    assert!(Walker::to_pos(&doc, &positions, positions.len() as isize).is_none());
+    assert!(Walker::to_pos(&doc, &positions, -1).is_none());
+}
+
+#[test]
+fn element_at_finds_top_level_elements() {
+    let doc = Doc(markdown_to_doc("# ab\n\n- c\n- d\n\ne").unwrap());
+    let positions = index(&doc);
+
+    // Each block has a position at its start and one after each character.
+    assert_eq!(positions.len(), 9);
+    assert_eq!(positions.element_at(0), Some((0, 0)));
+    assert_eq!(positions.element_at(2), Some((0, 0)));
+    assert_eq!(positions.element_at(3), Some((1, 3)));
+    assert_eq!(positions.element_at(4), Some((1, 3)));
+    assert_eq!(positions.element_at(5), Some((2, 5)));
+    assert_eq!(positions.element_at(7), Some((3, 7)));
+    assert_eq!(positions.element_at(9), None);
+}
+
+#[test]
+fn edits_keep_the_index_up_to_date() {
+    let mut client = TestClient::new(NESTED);
+    client.type_text("abc");
+    assert_fresh(&client.client.client_doc.positions, &client.client.client_doc.doc);
+
+    // Splitting and joining blocks changes the top-level elements.
+    client.command(ControllerCommand::Keypress(13, false, false, false));
+    assert_fresh(&client.client.client_doc.positions, &client.client.client_doc.doc);
+    client.command(ControllerCommand::Keypress(8, false, false, false));
+    assert_fresh(&client.client.client_doc.positions, &client.client.client_doc.doc);
+}
+
+#[test]
+fn remote_edits_keep_the_index_up_to_date() {
+    let mut client = TestClient::new(NESTED);
+    client.type_text("a");
+    client.client.client_doc.next_payload();
+    client.type_text("b");
+    assert!(client.client.client_doc.pending_op.is_some());
+
+    // Another client adds a block at the end while ours are unconfirmed.
+    let input = op_span!([], [AddSkip(4), AddGroup({"tag": "p"}, [AddChars("remote")])]);
+    let new_doc = Op::apply(&client.client.client_doc.original_doc, &input);
+    let version = client.client.client_doc.version + 1;
+    client.client.client_doc.sync_sent_new_version(&new_doc, version, &input);
+
+    assert_eq!(client.blocks().last().unwrap(), "remote");
+    assert_fresh(&client.client.client_doc.positions, &client.client.client_doc.doc);
+}
+
+#[test]
+fn to_caret_indexed_matches_walking() {
+    let mut client = TestClient::new(NESTED);
+    client.command(ControllerCommand::SelectRange(12, 16));
+    let client_id = client.client.client_id.clone();
+    let client_doc = &client.client.client_doc;
+
+    for &focus in &[false, true] {
+        let indexed =
+            Walker::to_caret_indexed(&client_doc.doc, &client_doc.positions, &client_id, focus)
+                .unwrap();
+        let walked = Walker::to_caret_safe(&client_doc.doc, &client_id, focus).unwrap();
+        assert_eq!(indexed.caret_pos(), walked.caret_pos());
+    }
+    assert_eq!(
+        Walker::to_caret_indexed(&client_doc.doc, &client_doc.positions, &client_id, true)
+            .unwrap()
+            .caret_pos(),
+        16
+    );
+}
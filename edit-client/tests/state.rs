@@ -0,0 +1,300 @@
+#[macro_use]
+extern crate oatie;
+extern crate edit_client;
+extern crate edit_common;
+extern crate failure;
+extern crate serde_json;
+
+mod support;
+
+use edit_client::*;
+use edit_common::commands::*;
+use edit_common::markdown::markdown_to_doc;
+use oatie::doc::*;
+use oatie::OT;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use support::*;
+
+fn undo(client: &mut TestClient) {
+    client.command(ControllerCommand::Keypress(90, true, false, false));
+}
+
+// The last operation sent to sync, with its id and version.
+fn last_commit(client: &TestClient) -> Option<(u64, Op, usize)> {
+    client
+        .sync
+        .borrow()
+        .iter()
+        .rev()
+        .filter_map(|command| match command {
+            ServerCommand::CommitOp(_, op_id, op, version) => Some((*op_id, op.clone(), *version)),
+            _ => None,
+        })
+        .next()
+}
+
+// A client that hasn't been sent a document.
+fn new_client() -> Client {
+    Client::new(
+        "$$$$$$",
+        Arc::new(AtomicBool::new(false)),
+        Arc::new(AtomicBool::new(true)),
+    )
+}
+
+#[test]
+fn undo_typing() {
+    let mut client = TestClient::new("one");
+    client.type_text("ab");
+    assert_eq!(client.blocks(), vec!["abone"]);
+
+    undo(&mut client);
+    assert_eq!(client.blocks(), vec!["aone"]);
+    undo(&mut client);
+    assert_eq!(client.blocks(), vec!["one"]);
+
+    // Nothing left to undo.
+    undo(&mut client);
+    assert_eq!(client.blocks(), vec!["one"]);
+}
+
+#[test]
+fn undo_skips_caret_moves() {
+    let mut client = TestClient::new("one");
+    client.type_text("x");
+    client.command(ControllerCommand::Keypress(39, false, false, false));
+    client.command(ControllerCommand::Keypress(39, false, false, false));
+    assert_eq!(client.client.client_doc.undo_stack.len(), 1);
+
+    undo(&mut client);
+    assert_eq!(client.blocks(), vec!["one"]);
+    assert!(client.client.client_doc.undo_stack.is_empty());
+}
+
+#[test]
+fn undo_after_remote_edit() {
+    let mut client = TestClient::new("one");
+    client.type_text("x");
+
+    // Someone else adds a block before ours.
+    let op = op_span!([], [AddGroup({"tag": "p"}, [AddChars("two")])]);
+    client.task(Task::ClientCommand(ClientCommand::Update(
+        101,
+        "other".to_string(),
+        op,
+    )));
+    assert_eq!(client.blocks(), vec!["two", "xone"]);
+
+    undo(&mut client);
+    assert_eq!(client.blocks(), vec!["two", "one"]);
+}
+
+#[test]
+fn snapshot_roundtrip() {
+    let mut client = TestClient::new("one");
+    client.type_text("xy");
+    undo(&mut client);
+    let blob = client.client.client_doc.serialize("test").unwrap();
+
+    let mut restored = new_client();
+    assert_eq!(restored.client_doc.restore(&blob).unwrap(), "test");
+
+    let (before, after) = (&client.client.client_doc, &restored.client_doc);
+    assert_eq!(after.doc, before.doc);
+    assert_eq!(after.original_doc, before.original_doc);
+    assert_eq!(after.pending_op, before.pending_op);
+    assert_eq!(after.local_op, before.local_op);
+    assert_eq!(after.pending_id(), before.pending_id());
+    assert_eq!(after.undo_stack, before.undo_stack);
+}
+
+#[test]
+fn snapshot_refuses_bad_blobs() {
+    let mut client = TestClient::new("one");
+    client.type_text("x");
+    let blob = client.client.client_doc.serialize("test").unwrap();
+    let mut restored = new_client();
+
+    // Another format.
+    let mut snapshot: ClientSnapshot = serde_json::from_str(&blob).unwrap();
+    snapshot.format = 1;
+    assert!(restored
+        .client_doc
+        .restore(&serde_json::to_string(&snapshot).unwrap())
+        .is_err());
+
+    // Operations that don't fit the document they're on.
+    let mut snapshot: ClientSnapshot = serde_json::from_str(&blob).unwrap();
+    snapshot.original_doc = Doc(vec![]);
+    assert!(restored
+        .client_doc
+        .restore(&serde_json::to_string(&snapshot).unwrap())
+        .is_err());
+}
+
+#[test]
+fn init_keeps_unconfirmed_ops_and_resends_pending() {
+    let mut client = TestClient::new("one");
+    client.type_text("x");
+    let (pending_id, _, _) = last_commit(&client).unwrap();
+    assert_eq!(client.client.client_doc.pending_id(), Some(pending_id));
+
+    // We reconnect, and the document has changed since.
+    let doc = markdown_to_doc("two\n\none").unwrap();
+    client.task(Task::ClientCommand(ClientCommand::Init("test".to_string(), doc, 105)));
+    assert_eq!(client.blocks(), vec!["two", "xone"]);
+
+    // The pending operation is sent again, with its id, against the new
+    // version.
+    let (op_id, op, version) = last_commit(&client).unwrap();
+    assert_eq!((op_id, version), (pending_id, 105));
+    assert_eq!(client.client.client_doc.pending_op, Some(op));
+    assert_ne!(client.client.client_doc.local_op, Op::empty());
+}
+
+#[test]
+fn init_without_unconfirmed_ops_takes_document() {
+    let mut client = TestClient::new("one");
+    client.task(Task::ClientCommand(ClientCommand::Ack(1, 101)));
+    assert!(!client.client.client_doc.is_dirty());
+
+    let doc = markdown_to_doc("two").unwrap();
+    let sent = client.sync.borrow().len();
+    client.task(Task::ClientCommand(ClientCommand::Init("test".to_string(), doc, 105)));
+    assert_eq!(client.blocks(), vec!["two"]);
+    // Only a new caret is sent.
+    assert_eq!(client.sync.borrow().len(), sent + 1);
+}
+
+#[test]
+fn receive_chunk_collects_chunks_in_order() {
+    let mut client = new_client();
+    let doc = markdown_to_doc("one\n\ntwo\n\nthree").unwrap();
+    let chunks: Vec<DocSpan> = doc.iter().map(|elem| vec![elem.clone()]).collect();
+
+    let client_doc = &mut client.client_doc;
+    assert_eq!(client_doc.resume_point(), None);
+    assert_eq!(client_doc.receive_chunk(100, 0, 3, chunks[0].clone()), None);
+    assert_eq!(client_doc.receive_chunk(100, 1, 3, chunks[1].clone()), None);
+    assert_eq!(client_doc.resume_point(), Some((100, 2)));
+    assert_eq!(client_doc.receive_chunk(100, 2, 3, chunks[2].clone()), Some(doc));
+    assert_eq!(client_doc.resume_point(), None);
+}
+
+#[test]
+fn receive_chunk_starts_over() {
+    let mut client = new_client();
+    let doc = markdown_to_doc("one\n\ntwo").unwrap();
+    let first = vec![doc[0].clone()];
+    let second = vec![doc[1].clone()];
+
+    // A chunk that skips one is dropped, along with what we had.
+    let client_doc = &mut client.client_doc;
+    client_doc.receive_chunk(100, 0, 3, first.clone());
+    assert_eq!(client_doc.receive_chunk(100, 2, 3, second.clone()), None);
+    assert_eq!(client_doc.resume_point(), None);
+
+    // As is one of another version.
+    client_doc.receive_chunk(100, 0, 2, first.clone());
+    assert_eq!(client_doc.receive_chunk(101, 1, 2, second.clone()), None);
+    assert_eq!(client_doc.resume_point(), None);
+
+    // The first chunk of a version starts it over.
+    client_doc.receive_chunk(100, 0, 2, second.clone());
+    client_doc.receive_chunk(101, 0, 2, first.clone());
+    assert_eq!(client_doc.resume_point(), Some((101, 1)));
+    assert_eq!(client_doc.receive_chunk(101, 1, 2, second), Some(doc));
+}
+
+#[test]
+fn receive_text_parses_the_joined_pieces() {
+    let mut client = new_client();
+    let doc = markdown_to_doc("one\n\n**two** three").unwrap();
+    let json = serde_json::to_string(&doc).unwrap();
+    let (first, rest) = json.split_at(5);
+    let (second, third) = rest.split_at(rest.len() / 2);
+
+    let client_doc = &mut client.client_doc;
+    assert_eq!(client_doc.receive_text(100, 0, 3, first).unwrap(), None);
+    assert_eq!(client_doc.receive_text(100, 1, 3, second).unwrap(), None);
+    assert_eq!(client_doc.resume_point(), Some((100, 2)));
+    assert_eq!(client_doc.receive_text(100, 2, 3, third).unwrap(), Some(doc));
+
+    // Pieces that don't make a document are an error.
+    client_doc.receive_text(100, 0, 2, first).unwrap();
+    assert!(client_doc.receive_text(100, 1, 2, third).is_err());
+}
+
+#[test]
+fn init_text_initializes_the_client() {
+    let mut client = TestClient::new("one");
+    let doc = markdown_to_doc("two\n\nthree").unwrap();
+    let json = serde_json::to_string(&doc).unwrap();
+    let (first, second) = json.split_at(json.len() / 2);
+
+    for (index, text) in [first, second].iter().enumerate() {
+        let command = ClientCommand::InitText("test".to_string(), 105, index, 2, text.to_string());
+        client.task(Task::ClientCommand(command));
+    }
+    assert_eq!(client.blocks(), vec!["two", "three"]);
+}
+
+#[test]
+fn reconnect_after_sync_applied_the_pending_operation() {
+    let mut client = TestClient::new("one");
+    client.task(Task::ClientCommand(ClientCommand::Ack(1, 101)));
+    client.type_text("x");
+    let (pending_id, op, version) = last_commit(&client).unwrap();
+    let client_id = client.client.client_id.clone();
+    let doc = Op::apply(&client.client.client_doc.original_doc, &op);
+    let sent = client.sync.borrow().len();
+
+    // The connection dropped before sync's ack arrived. Reconnecting with
+    // our id, we're sent the ack before the document it made.
+    client.task(Task::ClientCommand(ClientCommand::Ack(pending_id, version + 1)));
+    client.task(Task::ClientCommand(ClientCommand::Init(client_id.clone(), doc.0, version + 1)));
+    assert_eq!(client.client.client_id, client_id);
+    assert_eq!(client.blocks(), vec!["xone"]);
+    assert_eq!(client.client.client_doc.pending_op, None);
+
+    // So the operation isn't sent again.
+    let resent = client.sync.borrow()[sent..].iter().any(|command| match command {
+        ServerCommand::CommitOp(_, op_id, ..) => *op_id == pending_id,
+        _ => false,
+    });
+    assert!(!resent);
+}
+
+#[test]
+fn refused_operation_is_rolled_back_keeping_later_ones() {
+    let mut client = TestClient::new("one");
+    client.task(Task::ClientCommand(ClientCommand::Ack(1, 101)));
+    let synced = client.client.client_doc.original_doc.clone();
+
+    // The first edit is sent and refused; the second waits for it.
+    client.type_text("x");
+    client.type_text("y");
+    assert_eq!(client.blocks(), vec!["xyone"]);
+
+    client.task(Task::ClientCommand(ClientCommand::Refuse(RefusalReason::ReadOnly)));
+    assert!(client.client.client_doc.awaiting_correction);
+    client.task(Task::ClientCommand(ClientCommand::Init("test".to_string(), synced.0, 101)));
+    assert_eq!(client.blocks(), vec!["yone"]);
+    assert!(!client.client.client_doc.awaiting_correction);
+    assert_eq!(client.client.client_doc.pending_op, None);
+    assert_ne!(client.client.client_doc.local_op, Op::empty());
+}
+
+#[test]
+fn refused_operation_is_rolled_back_alone() {
+    let mut client = TestClient::new("one");
+    client.task(Task::ClientCommand(ClientCommand::Ack(1, 101)));
+    let synced = client.client.client_doc.original_doc.clone();
+    client.type_text("x");
+
+    client.task(Task::ClientCommand(ClientCommand::Refuse(RefusalReason::ReadOnly)));
+    client.task(Task::ClientCommand(ClientCommand::Init("test".to_string(), synced.0, 101)));
+    assert_eq!(client.blocks(), vec!["one"]);
+    assert!(!client.client.client_doc.is_dirty());
+}
@@ -0,0 +1,123 @@
+//! A client for tests, which keeps what it would send to the frontend and to
+//! sync instead of sending it.
+
+// Not every test uses every helper.
+#![allow(dead_code)]
+
+use edit_client::{
+    span_text,
+    ActionContext,
+    Client,
+    ClientImpl,
+    Task,
+};
+use edit_common::commands::*;
+use edit_common::markdown::markdown_to_doc;
+use failure::Error;
+use oatie::doc::*;
+use oatie::schema::RtfSchema;
+use oatie::validate::validate_doc;
+use oatie::OT;
+use std::cell::RefCell;
+use std::sync::{
+    atomic::AtomicBool,
+    Arc,
+};
+
+pub struct TestClient {
+    pub client: Client,
+    pub frontend: RefCell<Vec<FrontendCommand>>,
+    pub sync: RefCell<Vec<ServerCommand>>,
+}
+
+impl ClientImpl for TestClient {
+    fn state(&mut self) -> &mut Client {
+        &mut self.client
+    }
+
+    fn send_client(&self, req: &FrontendCommand) -> Result<(), Error> {
+        self.frontend.borrow_mut().push(req.clone());
+        Ok(())
+    }
+
+    fn send_sync(&self, req: ServerCommand) -> Result<(), Error> {
+        self.sync.borrow_mut().push(req);
+        Ok(())
+    }
+}
+
+impl TestClient {
+    /// A client that's been sent a document of this Markdown, with its
+    /// caret at the start.
+    pub fn new(markdown: &str) -> TestClient {
+        let mut client = TestClient {
+            client: Client::new(
+                "$$$$$$",
+                Arc::new(AtomicBool::new(false)),
+                Arc::new(AtomicBool::new(true)),
+            ),
+            frontend: RefCell::new(vec![]),
+            sync: RefCell::new(vec![]),
+        };
+        let doc = markdown_to_doc(markdown).unwrap();
+        client.task(Task::ClientCommand(ClientCommand::Init(
+            "test".to_string(),
+            doc,
+            100,
+        )));
+        client
+    }
+
+    pub fn task(&mut self, task: Task) {
+        self.handle_task(task).unwrap();
+    }
+
+    pub fn command(&mut self, command: ControllerCommand) {
+        self.task(Task::ControllerCommand(command));
+    }
+
+    /// Type each character of `text`.
+    pub fn type_text(&mut self, text: &str) {
+        for c in text.chars() {
+            self.command(ControllerCommand::Character(c as u32));
+        }
+    }
+
+    /// Text of each top-level block.
+    pub fn blocks(&self) -> Vec<String> {
+        block_texts(&self.client.client_doc.doc)
+    }
+
+    /// The operation an action makes on our document, without applying it.
+    pub fn action_op<C>(&mut self, action: C) -> Op
+    where
+        C: Fn(ActionContext) -> Result<Op, Error>,
+    {
+        self.with_action_context(action).unwrap()
+    }
+}
+
+/// Apply `a` and `b`, made concurrently on `doc`, in either order, and check
+/// that both orders give the same valid document, which is returned.
+pub fn converge(doc: &Doc, a: &Op, b: &Op) -> Doc {
+    let (a_, b_) = Op::transform::<RtfSchema>(a, b);
+    let doc_a = Op::apply(&Op::apply(doc, a), &a_);
+    let doc_b = Op::apply(&Op::apply(doc, b), &b_);
+    assert_eq!(doc_a, doc_b);
+    validate_doc(&doc_a).unwrap();
+    doc_a
+}
+
+/// Text of each top-level block of `doc`.
+pub fn block_texts(doc: &Doc) -> Vec<String> {
+    doc.0
+        .iter()
+        .map(|elem| {
+            let mut out = String::new();
+            if let DocGroup(_, ref span) = *elem {
+                span_text(span, &mut out);
+            }
+            out
+        })
+        .collect()
+}
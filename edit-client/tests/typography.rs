@@ -0,0 +1,83 @@
+extern crate edit_client;
+extern crate edit_common;
+extern crate failure;
+extern crate oatie;
+
+mod support;
+
+use edit_client::autocorrect::Autocorrect;
+use edit_client::typography::*;
+use edit_common::commands::*;
+use support::*;
+
+fn rewrite(rules: &[InputRule], before: &str, input: char) -> (usize, String) {
+    apply_input_rules(rules, &Autocorrect::new(), before, input)
+}
+
+#[test]
+fn smart_quotes() {
+    let rules = &[InputRule::SmartQuotes];
+    assert_eq!(rewrite(rules, "", '"'), (0, "\u{201C}".to_string()));
+    assert_eq!(rewrite(rules, "say ", '"'), (0, "\u{201C}".to_string()));
+    assert_eq!(rewrite(rules, "(", '"'), (0, "\u{201C}".to_string()));
+    assert_eq!(rewrite(rules, "said", '"'), (0, "\u{201D}".to_string()));
+    assert_eq!(rewrite(rules, " ", '\''), (0, "\u{2018}".to_string()));
+    assert_eq!(rewrite(rules, "don", '\''), (0, "\u{2019}".to_string()));
+    // A quote just opened opens another.
+    assert_eq!(rewrite(rules, "\u{201C}", '\''), (0, "\u{2018}".to_string()));
+}
+
+#[test]
+fn dashes() {
+    let rules = &[InputRule::Dashes];
+    assert_eq!(rewrite(rules, "a", '-'), (0, "-".to_string()));
+    assert_eq!(rewrite(rules, "a-", '-'), (1, "\u{2013}".to_string()));
+    assert_eq!(rewrite(rules, "a\u{2013}", '-'), (1, "\u{2014}".to_string()));
+    assert_eq!(rewrite(rules, "a\u{2014}", '-'), (0, "-".to_string()));
+}
+
+#[test]
+fn ellipses() {
+    let rules = &[InputRule::Ellipses];
+    assert_eq!(rewrite(rules, "wait.", '.'), (0, ".".to_string()));
+    assert_eq!(rewrite(rules, "wait..", '.'), (2, "\u{2026}".to_string()));
+}
+
+#[test]
+fn emoji_shortcodes() {
+    let rules = &[InputRule::Emoji];
+    assert_eq!(rewrite(rules, "hi :smile", ':'), (6, "\u{1F604}".to_string()));
+    assert_eq!(rewrite(rules, "hi :nonsense", ':'), (0, ":".to_string()));
+    assert_eq!(rewrite(rules, "10", ':'), (0, ":".to_string()));
+}
+
+#[test]
+fn rules_off() {
+    assert_eq!(rewrite(&[], "a-", '-'), (0, "-".to_string()));
+    assert_eq!(rewrite(&[InputRule::Dashes], "said", '"'), (0, "\"".to_string()));
+}
+
+#[test]
+fn rules_compose() {
+    // Ellipses sees the dash Dashes left, and leaves it alone.
+    let rules = &[InputRule::Dashes, InputRule::Ellipses];
+    assert_eq!(rewrite(rules, "a-", '-'), (1, "\u{2013}".to_string()));
+    assert_eq!(rewrite(rules, "..", '.'), (2, "\u{2026}".to_string()));
+
+    // A quote after an en dash opens, whichever rule ran first.
+    let rules = &[InputRule::SmartQuotes, InputRule::Dashes];
+    assert_eq!(rewrite(rules, "a\u{2013}", '"'), (0, "\u{201C}".to_string()));
+}
+
+#[test]
+fn rules_apply_while_typing() {
+    let mut client = TestClient::new("x");
+    client.command(ControllerCommand::InputRule(InputRule::Dashes, true));
+    client.command(ControllerCommand::InputRule(InputRule::SmartQuotes, true));
+    client.type_text("a--b \"c\"");
+    assert_eq!(client.blocks(), vec!["a\u{2013}b \u{201C}c\u{201D}x"]);
+
+    client.command(ControllerCommand::InputRule(InputRule::Dashes, false));
+    client.type_text("--");
+    assert_eq!(client.blocks(), vec!["a\u{2013}b \u{201C}c\u{201D}--x"]);
+}
@@ -0,0 +1,114 @@
+extern crate edit_client;
+extern crate edit_common;
+extern crate failure;
+extern crate oatie;
+
+mod support;
+
+use edit_client::vim::*;
+use edit_common::commands::*;
+use support::*;
+
+fn vim_client(markdown: &str) -> TestClient {
+    let mut client = TestClient::new(markdown);
+    client.command(ControllerCommand::Vim(true));
+    client
+}
+
+fn last_mode(client: &TestClient) -> Option<Option<VimMode>> {
+    client
+        .frontend
+        .borrow()
+        .iter()
+        .filter_map(|command| match command {
+            FrontendCommand::VimMode(mode) => Some(*mode),
+            _ => None,
+        })
+        .last()
+}
+
+#[test]
+fn vim_starts_in_normal_mode() {
+    let mut client = vim_client("hello");
+    assert_eq!(last_mode(&client), Some(Some(VimMode::Normal)));
+
+    // Characters are commands, not text.
+    client.type_text("q");
+    assert_eq!(client.blocks(), vec!["hello"]);
+
+    client.command(ControllerCommand::Vim(false));
+    assert_eq!(last_mode(&client), Some(None));
+    client.type_text("q");
+    assert_eq!(client.blocks(), vec!["qhello"]);
+}
+
+#[test]
+fn vim_x_deletes_under_caret() {
+    let mut client = vim_client("hello");
+    client.type_text("x");
+    assert_eq!(client.blocks(), vec!["ello"]);
+    client.type_text("lx");
+    assert_eq!(client.blocks(), vec!["elo"]);
+}
+
+#[test]
+fn vim_insert_and_escape() {
+    let mut client = vim_client("hello");
+    client.type_text("a");
+    assert_eq!(last_mode(&client), Some(Some(VimMode::Insert)));
+    client.type_text("X");
+    assert_eq!(client.blocks(), vec!["hXello"]);
+
+    vim_escape(&mut client).unwrap();
+    assert_eq!(last_mode(&client), Some(Some(VimMode::Normal)));
+    client.type_text("A");
+    client.type_text("!");
+    assert_eq!(client.blocks(), vec!["hXello!"]);
+}
+
+#[test]
+fn vim_dd_and_p() {
+    let mut client = vim_client("one\n\ntwo");
+    client.type_text("dd");
+    assert_eq!(client.blocks(), vec!["two"]);
+    client.type_text("p");
+    assert_eq!(client.blocks(), vec!["onetwo"]);
+}
+
+#[test]
+fn vim_yy_keeps_block() {
+    let mut client = vim_client("one\n\ntwo");
+    client.type_text("yyjp");
+    assert_eq!(client.blocks(), vec!["one", "onetwo"]);
+}
+
+#[test]
+fn vim_pending_operator_is_cancelled() {
+    let mut client = vim_client("hello");
+    // "d" followed by a motion it doesn't know does nothing.
+    client.type_text("dlx");
+    assert_eq!(client.blocks(), vec!["ello"]);
+}
+
+#[test]
+fn vim_visual_delete_and_paste() {
+    let mut client = vim_client("hello");
+    client.type_text("v");
+    assert_eq!(last_mode(&client), Some(Some(VimMode::Visual)));
+    client.type_text("ll");
+    client.type_text("d");
+    assert_eq!(last_mode(&client), Some(Some(VimMode::Normal)));
+    assert_eq!(client.blocks(), vec!["llo"]);
+
+    client.type_text("$p");
+    assert_eq!(client.blocks(), vec!["llohe"]);
+}
+
+#[test]
+fn vim_o_opens_block() {
+    let mut client = vim_client("one\n\ntwo");
+    client.type_text("o");
+    assert_eq!(last_mode(&client), Some(Some(VimMode::Insert)));
+    client.type_text("new");
+    assert_eq!(client.blocks(), vec!["one", "new", "two"]);
+}
@@ -0,0 +1,100 @@
+extern crate edit_client;
+extern crate edit_common;
+extern crate failure;
+extern crate oatie;
+
+mod support;
+
+use edit_client::words::*;
+use edit_client::*;
+use edit_common::commands::*;
+use support::*;
+
+// Move our caret by a word, and mark where it lands with "|".
+fn word_move(markdown: &str, from_end: bool, increase: bool) -> String {
+    let mut client = TestClient::new(markdown);
+    if from_end {
+        client
+            .client_op(|ctx| caret_block_edge(ctx, true, false))
+            .unwrap();
+    }
+    client
+        .client_op(|ctx| caret_word_move(ctx, increase))
+        .unwrap();
+    client.client_op(|ctx| add_string(ctx, "|")).unwrap();
+    client.blocks().remove(0)
+}
+
+// Select the word under a caret after the `offset`th character.
+fn select_word(markdown: &str, offset: usize) -> String {
+    let mut client = TestClient::new(markdown);
+    for _ in 0..offset {
+        client
+            .client_op(|ctx| caret_move(ctx, true, false))
+            .unwrap();
+    }
+    client.command(ControllerCommand::SelectWord);
+    client
+        .with_action_context(|ctx| Ok(selection_text(&ctx)))
+        .unwrap()
+}
+
+#[test]
+fn char_classes() {
+    assert_eq!(char_class('a'), CharClass::Word);
+    assert_eq!(char_class('9'), CharClass::Word);
+    assert_eq!(char_class(' '), CharClass::Separator);
+    assert_eq!(char_class('\u{3000}'), CharClass::Separator);
+    assert_eq!(char_class('。'), CharClass::Separator);
+    assert_eq!(char_class('！'), CharClass::Separator);
+    assert_eq!(char_class('漢'), CharClass::Han);
+    assert_eq!(char_class('々'), CharClass::Han);
+    assert_eq!(char_class('の'), CharClass::Hiragana);
+    assert_eq!(char_class('カ'), CharClass::Katakana);
+    assert_eq!(char_class('ｶ'), CharClass::Katakana);
+    assert_eq!(char_class('한'), CharClass::Hangul);
+}
+
+#[test]
+fn word_boundaries() {
+    use edit_client::words::CharClass::*;
+
+    // Each Chinese character is a word.
+    assert!(is_word_boundary(Han, Han));
+    // Kana endings stay with the kanji before them, but not after.
+    assert!(!is_word_boundary(Han, Hiragana));
+    assert!(is_word_boundary(Hiragana, Han));
+    // Script changes end words.
+    assert!(is_word_boundary(Katakana, Hiragana));
+    assert!(is_word_boundary(Word, Han));
+    assert!(is_word_boundary(Hangul, Word));
+    // Runs of one script without spaces don't.
+    assert!(!is_word_boundary(Katakana, Katakana));
+    assert!(!is_word_boundary(Hangul, Hangul));
+    assert!(!is_word_boundary(Word, Word));
+}
+
+#[test]
+fn word_move_latin() {
+    assert_eq!(word_move("hello world", false, true), "hello| world");
+    assert_eq!(word_move("hello world", true, false), "hello |world");
+}
+
+#[test]
+fn word_move_cjk() {
+    assert_eq!(word_move("中文字", false, true), "中|文字");
+    assert_eq!(word_move("テストです", false, true), "テスト|です");
+    assert_eq!(word_move("書きます", false, true), "書きます|");
+    assert_eq!(word_move("안녕 세상", false, true), "안녕| 세상");
+    assert_eq!(word_move("abc漢字", false, true), "abc|漢字");
+
+    assert_eq!(word_move("テストです", true, false), "テスト|です");
+    assert_eq!(word_move("日本語の", true, false), "日本|語の");
+}
+
+#[test]
+fn select_word_cjk() {
+    assert_eq!(select_word("テストです", 1), "テスト");
+    assert_eq!(select_word("hello 안녕하세요", 7), "안녕하세요");
+    assert_eq!(select_word("hello world", 2), "hello");
+}
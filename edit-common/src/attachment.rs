@@ -0,0 +1,36 @@
+//! Attachment blocks, which link to a file uploaded to the server. They keep
+//! the file's URL, its original filename, and its size in bytes.
+
+use crate::is_safe_url;
+use oatie::doc::*;
+
+/// Size in bytes, as in "512 B", "1.4 KB", or "12.0 MB".
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KB", "MB", "GB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// URL, filename, and size of an attachment block. URLs that aren't to an
+/// uploaded file or an http(s) URL are left empty.
+pub fn attachment_info(attrs: &Attrs) -> (&str, &str, u64) {
+    let url = attrs
+        .get("url")
+        .map(|x| x.as_str())
+        .filter(|x| is_safe_url(x))
+        .unwrap_or("");
+    let filename = attrs.get("filename").map(|x| x.as_str()).unwrap_or("");
+    let size = attrs
+        .get("size")
+        .and_then(|x| x.parse::<u64>().ok())
+        .unwrap_or(0);
+    (url, filename, size)
+}
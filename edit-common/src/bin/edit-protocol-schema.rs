@@ -0,0 +1,14 @@
+//! Print the JSON Schema of the wire protocol, for validating payloads and
+//! generating types for other clients.
+
+extern crate edit_common;
+extern crate serde_json;
+
+use edit_common::protocol_schema::protocol_schema;
+
+fn main() {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&protocol_schema()).unwrap()
+    );
+}
@@ -0,0 +1,99 @@
+//! Stable ids for blocks, stored in their "id" attribute, for deep linking
+//! and anchoring. Ids survive edits inside a block. Splitting a block keeps
+//! its id on the first half and derives a new one for the second; joining
+//! keeps the id of the first block, unless it was empty.
+
+use oatie::doc::*;
+use oatie::schema::{
+    RtfSchema,
+    RtfTrack,
+};
+use oatie::Schema;
+use std::collections::HashSet;
+
+fn is_block(attrs: &Attrs) -> bool {
+    RtfSchema::track_type_from_attrs(attrs) == Some(RtfTrack::Blocks)
+}
+
+pub fn block_id(attrs: &Attrs) -> Option<&str> {
+    attrs.get("id").map(|id| id.as_str())
+}
+
+fn collect_ids(span: &DocSpan, out: &mut HashSet<String>) {
+    for elem in span {
+        if let DocGroup(attrs, inner) = elem {
+            if let Some(id) = block_id(attrs) {
+                out.insert(id.to_string());
+            }
+            collect_ids(inner, out);
+        }
+    }
+}
+
+// FNV-1a, which unlike the standard library's hasher is guaranteed to give
+// every client the same result.
+fn fnv1a(input: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in input.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+fn derive_unused(existing: &HashSet<String>, parent: &str, salt: &str) -> String {
+    (0..)
+        .map(|n| format!("{:08x}", fnv1a(&format!("{}/{}/{}", parent, salt, n)) as u32))
+        .find(|id| !existing.contains(id))
+        .unwrap()
+}
+
+/// The id for a block split off from the block `parent`. Derived only from
+/// its inputs and the ids already in `doc`, so it's the same wherever it's
+/// computed, and distinct from every id in `doc`.
+pub fn derive_block_id(doc: &DocSpan, parent: &str, salt: &str) -> String {
+    let mut existing = HashSet::new();
+    collect_ids(doc, &mut existing);
+    derive_unused(&existing, parent, salt)
+}
+
+fn assign_inner(span: &DocSpan, existing: &mut HashSet<String>, counter: &mut usize) -> DocSpan {
+    span.iter()
+        .map(|elem| match elem {
+            DocGroup(attrs, inner) => {
+                let mut attrs = attrs.clone();
+                if is_block(&attrs) && block_id(&attrs).is_none() {
+                    let id = derive_unused(existing, "", &counter.to_string());
+                    existing.insert(id.clone());
+                    attrs.insert("id".to_string(), id);
+                }
+                *counter += 1;
+                DocGroup(attrs, assign_inner(inner, existing, counter))
+            }
+            DocChars(_) => elem.clone(),
+        })
+        .collect()
+}
+
+/// A copy of `doc` where every block without an id is given one.
+pub fn assign_block_ids(doc: &DocSpan) -> DocSpan {
+    let mut existing = HashSet::new();
+    collect_ids(doc, &mut existing);
+    assign_inner(doc, &mut existing, &mut 0)
+}
+
+/// Path to the block with the given id.
+pub fn find_block(span: &DocSpan, id: &str) -> Option<Vec<usize>> {
+    for (i, elem) in span.iter().enumerate() {
+        if let DocGroup(attrs, inner) = elem {
+            if block_id(attrs) == Some(id) {
+                return Some(vec![i]);
+            }
+            if let Some(mut path) = find_block(inner, id) {
+                path.insert(0, i);
+                return Some(path);
+            }
+        }
+    }
+    None
+}
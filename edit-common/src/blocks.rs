@@ -0,0 +1,82 @@
+//! Follows top-level document elements through an operation, so caches
+//! keyed by top-level element only recompute what the operation touched.
+
+use oatie::doc::*;
+
+/// Number of top-level elements an add span consumes from the document
+/// it's applied to.
+fn add_span_consumed(span: &AddSpan) -> Option<usize> {
+    let mut count = 0;
+    for elem in span {
+        count += match elem {
+            AddSkip(n) => *n,
+            AddWithGroup(_) => 1,
+            AddGroup(_, inner) => add_span_consumed(inner)?,
+            AddChars(_) | AddStyles(..) => return None,
+        };
+    }
+    Some(count)
+}
+
+/// Maps per-element cache entries through an operation. Entries for
+/// elements the operation touched or created become None. Returns None when
+/// the operation splits or joins top-level elements in ways that can't be
+/// followed, in which case the cache should be rebuilt.
+pub fn map_top_level<T: Clone>(entries: &[Option<T>], op: &Op) -> Option<Vec<Option<T>>> {
+    map_entries(entries, op, false)
+}
+
+/// Like `map_top_level`, but elements the operation only edited inside keep
+/// their entries. For state that belongs to an element rather than being
+/// derived from its contents.
+pub fn follow_top_level<T: Clone>(entries: &[Option<T>], op: &Op) -> Option<Vec<Option<T>>> {
+    map_entries(entries, op, true)
+}
+
+fn map_entries<T: Clone>(entries: &[Option<T>], op: &Op, keep_edited: bool) -> Option<Vec<Option<T>>> {
+    let (ref del, ref add) = *op;
+
+    let mut source = entries.iter().cloned();
+    let mut deleted = vec![];
+    for elem in del {
+        match elem {
+            DelSkip(n) => {
+                for _ in 0..*n {
+                    deleted.push(source.next()?);
+                }
+            }
+            DelWithGroup(_) => {
+                let entry = source.next()?;
+                deleted.push(if keep_edited { entry } else { None });
+            }
+            DelChars(_) | DelGroup(_) | DelStyles(..) => return None,
+        }
+    }
+    deleted.extend(source);
+
+    let mut source = deleted.into_iter();
+    let mut added = vec![];
+    for elem in add {
+        match elem {
+            AddSkip(n) => {
+                for _ in 0..*n {
+                    added.push(source.next()?);
+                }
+            }
+            AddWithGroup(_) => {
+                let entry = source.next()?;
+                added.push(if keep_edited { entry } else { None });
+            }
+            AddGroup(_, inner) => {
+                for _ in 0..add_span_consumed(inner)? {
+                    source.next()?;
+                }
+                added.push(None);
+            }
+            AddChars(_) | AddStyles(..) => return None,
+        }
+    }
+    added.extend(source);
+
+    Some(added)
+}
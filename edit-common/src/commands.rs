@@ -1,23 +1,35 @@
+use crate::comments::{
+    CommentAction,
+    CommentThread,
+};
+use crate::highlight::CodeHighlight;
+use crate::identity::UserIdentity;
+use crate::link::Link;
+use crate::lint::{
+    BlockLints,
+    LintConfig,
+};
+use crate::mention::Mention;
+use crate::outline::OutlineEntry;
+use crate::pagination::{
+    PageBreak,
+    PageLayout,
+};
 use oatie::doc::*;
 
-// The server is the synchronization server.
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub enum ServerCommand {
-    // Connect(String),
-    Commit(String, Op, usize),
-    Log(String),
-    TerminateProxy,
-}
-
-// Client is an individual user / machine.
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub enum ClientCommand {
-    // Client id assignment, initial doc, initial version
-    Init(String, DocSpan, usize),
-
-    // New document, version, client-id, operation
-    Update(usize, String, Op),
-}
+// Commands between the sync server and its clients are defined per version
+// of the protocol, in `protocol`. These are the current version's.
+pub use crate::protocol::close::{
+    CloseReason,
+    Recovery,
+};
+pub use crate::protocol::v5::{
+    ClientCommand,
+    RefusalReason,
+    ServerCommand,
+    SharePermission,
+    SizeLimit,
+};
 
 // Controller is the client interface that is exposed to the frnontend.
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -33,6 +45,87 @@ pub enum ControllerCommand {
     // Target(CurSpan),
     RandomTarget(f64),
     Monkey(bool),
+    // Request a dump of the client's recent task history.
+    DebugDump,
+    // Request the current document statistics.
+    Stats,
+    // The frontend's connection to the sync server opened or closed.
+    SyncConnection(bool),
+    // Take over editing from the client holding the write lock.
+    TakeOver,
+    // Turn Vim-style modal editing on or off.
+    Vim(bool),
+    // Switch to another set of key bindings.
+    Keymap(Keymap),
+    // Select the word under the caret, as on a double click.
+    SelectWord,
+    // Turn a typing substitution on or off.
+    InputRule(InputRule, bool),
+    // Collapse or expand the section the caret is in.
+    ToggleFold,
+    // Move the caret to the block with this id.
+    ScrollTo(String),
+    // Move the section under the heading with this outline anchor to before
+    // the outline entry at this index.
+    MoveSection(String, usize),
+    // Request completions for the emoji shortcode before the caret.
+    EmojiCompletions,
+    // Replace the shortcode before the caret with this one's emoji.
+    CompleteEmoji(String),
+    // Insert inline math with this LaTeX source at the caret.
+    InsertMath(String),
+    // Change the LaTeX source of the math before the caret.
+    EditMath(String),
+    // Insert an image of the uploaded file at this URL at the caret.
+    InsertImage(String),
+    // Insert a link to the page with this id at the caret.
+    InsertDocLink(String),
+    // Insert a mention of a user at the caret, given their id and name.
+    InsertMention(String, String),
+    // Insert a block after the caret linking to an uploaded file, given its
+    // URL, filename, and size in bytes.
+    InsertAttachment(String, String, u64),
+    // Embed the page at this URL in a block after the caret.
+    InsertEmbed(String),
+    // Set or clear the render mode of the caret code block, like "mermaid".
+    RenderMode(Option<String>),
+    // Set or clear the language of the caret code block, for highlighting.
+    CodeLanguage(Option<String>),
+    // Set the text alignment of the caret block, or clear it with None.
+    Align(Option<String>),
+    // Link the selection, or update the link it has, or unlink it with None.
+    EditLink(Option<Link>),
+    // Set the font size of the selection, like "14px", or clear it with None.
+    FontSize(Option<String>),
+    // Set the font family of the selection, or clear it with None.
+    FontFamily(Option<String>),
+    // Copy the document into a new one, optionally with its history.
+    Duplicate(bool),
+    // Merge this copy's changes back into the document it was copied from.
+    MergeFork,
+    // Find text in the document, on the server.
+    Find(String),
+    // Select between two caret positions, as of a found match.
+    SelectRange(isize, isize),
+    // Edit a page end-to-end encrypted, given its id and its key in base64.
+    EncryptionKey(String, String),
+    // Edit a page synced between peers as a CRDT.
+    PeerSync,
+    // Start a comment thread on the caret block.
+    StartComment(String),
+    // Reply to, resolve, or reopen a comment thread.
+    Comment(CommentAction),
+    // Show resolved comment threads, or hide them.
+    ShowResolvedComments(bool),
+    // Always autocorrect a pattern to a replacement, or stop with None.
+    Autocorrect(String, Option<String>),
+    // Add the user's autocorrect dictionary, as pattern and replacement
+    // pairs, over the built-in one.
+    AutocorrectDictionary(Vec<(String, String)>),
+    // Change which readability checks run.
+    Lint(LintConfig),
+    // Break the document into pages of a layout, or stop with None.
+    Paginate(Option<PageLayout>),
 }
 
 // Frontend is the editor components in JavaScript.
@@ -44,6 +137,120 @@ pub enum FrontendCommand {
     Update(String, String, Option<Op>),
     Error(String),
     ServerCommand(ServerCommand),
+    // Serialized history of the client's recent tasks.
+    DebugDump(String),
+    Stats(DocStats),
+    // The document title changed.
+    Title(Option<String>),
+    SyncStatus(SyncStatus),
+    // Progress of an initial sync arriving in chunks: the version being
+    // synced, chunks received, and the number of chunks.
+    SyncProgress(usize, usize, usize),
+    // Sync refused one of our edits, and the document shown until this is
+    // false again will be rolled back. Updates in between can be held back
+    // to avoid showing the edit disappear and reappear.
+    Correcting(bool),
+    // Whether the client is read-only, in single-writer mode.
+    ReadOnly(bool),
+    // The current Vim mode, or None if modal editing is off.
+    VimMode(Option<VimMode>),
+    // Formatting at the caret changed.
+    FormatState(FormatState),
+    // The document's headings changed.
+    Outline(Vec<OutlineEntry>),
+    // Scroll the block with this id into view.
+    ScrollTo(String),
+    // Syntax highlighting of the document's code blocks changed.
+    Highlight(Vec<CodeHighlight>),
+    // Readability issues of the document's prose blocks changed.
+    Lints(Vec<BlockLints>),
+    // Where the document's pages break changed.
+    PageBreaks(Vec<PageBreak>),
+    // Shortcodes and their emoji completing the shortcode before the caret.
+    EmojiCompletions(Vec<(String, String)>),
+    // The document was copied into the one with this id.
+    Duplicated(String),
+    // Titles of linked pages changed, or None for pages that don't exist.
+    PageTitles(Vec<(String, Option<String>)>),
+    // Caret positions of the start and end of each match of a query.
+    FindResults(String, Vec<(isize, isize)>),
+    // Client ids and users of everyone editing the document.
+    Users(Vec<(String, UserIdentity)>),
+    // Unread mentions of our user.
+    Mentions(Vec<Mention>),
+    // Comment threads to show, oldest first.
+    Comments(Vec<CommentThread>),
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub enum VimMode {
+    Normal,
+    Insert,
+    Visual,
+}
+
+// Key binding presets, layered over the default bindings.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub enum Keymap {
+    Default,
+    Emacs,
+}
+
+// Substitutions applied to text as it's typed.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub enum InputRule {
+    // Straight quotes become curly quotes.
+    SmartQuotes,
+    // "--" becomes an en dash, "---" an em dash.
+    Dashes,
+    // "..." becomes an ellipsis.
+    Ellipses,
+    // Emoji shortcodes like ":smile:" become their emoji.
+    Emoji,
+    // Misspellings like "teh" are fixed when the word ends.
+    Autocorrect,
+}
+
+// Whether local edits have safely reached the sync server.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub enum SyncStatus {
+    // Every local operation was acknowledged by the server.
+    Synced,
+    // Some local operations haven't been acknowledged yet.
+    Pending,
+    // There is no connection to the server.
+    Offline,
+}
+
+// Commands from the frontend on a multiplexed connection, which hosts
+// several documents at once. Each is tagged with a document id.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub enum MuxCommand {
+    // Open a document, connecting it to the sync server.
+    Open(String),
+    // Close a document.
+    Close(String),
+    // Forward a command to an open document.
+    Controller(String, ControllerCommand),
+}
+
+// Document id, command for the frontend of that document.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct MuxFrame(pub String, pub FrontendCommand);
+
+// Messages on a multiplexed connection to the sync server, which carries
+// several documents at once. The client opens and closes documents, and
+// both sides exchange commands tagged with a document id.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub enum MuxMessage<T> {
+    // Connect to a document.
+    Open(String),
+    // A command for or from a document.
+    Frame(String, T),
+    // Disconnect from a document. When the server closes one, it gives the
+    // close code and reason it would have closed a connection with, and
+    // the client answers with a Close of its own.
+    Close(String, Option<(u16, String)>),
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -53,8 +260,33 @@ pub enum Ui {
     ButtonGroup(Vec<Ui>),
 }
 
+// Formatting at the caret or selection, for highlighting toolbar buttons.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct FormatState {
+    // Styles carried by the whole selection, or the text before the caret.
+    pub styles: StyleSet,
+    // Tag of the caret block.
+    pub block: String,
+    // Number of lists the caret block is nested in.
+    pub list_depth: usize,
+    pub link: Option<Link>,
+    // Font size and family of the selection, if it has just one of each.
+    pub font_size: Option<String>,
+    pub font_family: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct DocStats {
+    pub chars: usize,
+    pub words: usize,
+    pub blocks: usize,
+    // Estimated reading time, in seconds.
+    pub reading_time: usize,
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Controls {
-    pub keys: Vec<(u32, bool, bool)>,
+    // code, meta, shift, alt
+    pub keys: Vec<(u32, bool, bool, bool)>,
     pub buttons: Vec<Ui>,
 }
@@ -0,0 +1,68 @@
+//! Comment threads, anchored to a block by its id. Threads are stored by the
+//! sync server next to the page they're on, and every client of the page is
+//! sent the page's threads whenever one changes.
+
+/// Longest comment kept, in characters.
+pub const MAX_COMMENT_LEN: usize = 4000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Comment {
+    // Name of the user who wrote the comment.
+    pub author: String,
+    pub body: String,
+    // Unix time in milliseconds.
+    pub created: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CommentThread {
+    pub id: String,
+    // Id of the block the thread is about.
+    pub anchor: String,
+    pub resolved: bool,
+    // The comment that started the thread, then its replies, oldest first.
+    pub comments: Vec<Comment>,
+}
+
+// A change to a page's comment threads.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum CommentAction {
+    // Start a thread on the block with this id, with a first comment.
+    Start(String, String),
+    // Reply to the thread with this id.
+    Reply(String, String),
+    // Mark a thread as resolved.
+    Resolve(String),
+    // Reopen a resolved thread.
+    Reopen(String),
+}
+
+impl CommentAction {
+    /// The comment body the action adds, if any.
+    pub fn body(&self) -> Option<&str> {
+        match self {
+            CommentAction::Start(_, body) | CommentAction::Reply(_, body) => Some(body),
+            CommentAction::Resolve(_) | CommentAction::Reopen(_) => None,
+        }
+    }
+}
+
+/// Comment bodies are trimmed and cut to MAX_COMMENT_LEN. Returns None for
+/// comments that are empty.
+pub fn clean_comment(body: &str) -> Option<String> {
+    let body = body.trim().chars().take(MAX_COMMENT_LEN).collect::<String>();
+    if body.is_empty() {
+        None
+    } else {
+        Some(body)
+    }
+}
+
+/// The threads to show, leaving out resolved ones unless asked for.
+pub fn visible_threads(threads: &[CommentThread], show_resolved: bool) -> Vec<CommentThread> {
+    threads
+        .iter()
+        .filter(|thread| show_resolved || !thread.resolved)
+        .cloned()
+        .collect()
+}
@@ -0,0 +1,442 @@
+//! An RGA-style sequence CRDT, for syncing a document between peers when
+//! there's no server to transform their operations. The document is a
+//! sequence of blocks, and each block a sequence of characters, where every
+//! block and character has an id no other replica will reuse. Insertions
+//! name what they follow, and deletions leave tombstones, so replicas that
+//! apply the same operations in any order end up with the same document.
+//!
+//! Only block tags and plain text are modeled: styles, nested groups, and
+//! carets are dropped when converting a document into a CrdtDoc. A nested
+//! block, like a bullet, is kept as the block it holds.
+//!
+//! The client edits peer pages, at /$/ws/{page}/peer, through a CrdtDoc:
+//! `edit` turns the editor's changes into CrdtOps for the other peers, and
+//! their CrdtOps are turned back into editor operations by diffing.
+
+use oatie::doc::*;
+use std::cmp;
+use std::collections::HashMap;
+
+/// Unique id of a block or character. Ids are ordered by their Lamport
+/// clock, then by site, which decides between concurrent insertions at the
+/// same place.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CrdtId {
+    pub clock: u64,
+    pub site: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum CrdtOp {
+    InsertBlock {
+        id: CrdtId,
+        after: Option<CrdtId>,
+        tag: String,
+    },
+    DeleteBlock {
+        id: CrdtId,
+    },
+    InsertChar {
+        block: CrdtId,
+        id: CrdtId,
+        after: Option<CrdtId>,
+        value: char,
+    },
+    DeleteChar {
+        block: CrdtId,
+        id: CrdtId,
+    },
+}
+
+impl CrdtOp {
+    fn id(&self) -> CrdtId {
+        match self {
+            CrdtOp::InsertBlock { id, .. }
+            | CrdtOp::DeleteBlock { id }
+            | CrdtOp::InsertChar { id, .. }
+            | CrdtOp::DeleteChar { id, .. } => *id,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Node<T> {
+    id: CrdtId,
+    value: T,
+    deleted: bool,
+}
+
+/// A replicated sequence. Deleted elements stay as tombstones, so later
+/// insertions can still name them.
+#[derive(Debug, Clone, PartialEq)]
+struct Rga<T> {
+    nodes: Vec<Node<T>>,
+}
+
+impl<T> Rga<T> {
+    fn new() -> Rga<T> {
+        Rga { nodes: vec![] }
+    }
+
+    fn position(&self, id: CrdtId) -> Option<usize> {
+        self.nodes.iter().position(|node| node.id == id)
+    }
+
+    fn contains(&self, id: CrdtId) -> bool {
+        self.position(id).is_some()
+    }
+
+    // Returns false if the element it follows isn't known yet.
+    fn insert(&mut self, after: Option<CrdtId>, id: CrdtId, value: T) -> bool {
+        if self.contains(id) {
+            return true;
+        }
+        let mut index = match after {
+            Some(after) => match self.position(after) {
+                Some(index) => index + 1,
+                None => return false,
+            },
+            None => 0,
+        };
+        // Concurrent insertions after the same element, and what follows
+        // them, come first if their ids are greater.
+        while index < self.nodes.len() && self.nodes[index].id > id {
+            index += 1;
+        }
+        self.nodes.insert(
+            index,
+            Node {
+                id,
+                value,
+                deleted: false,
+            },
+        );
+        true
+    }
+
+    fn delete(&mut self, id: CrdtId) -> bool {
+        match self.position(id) {
+            Some(index) => {
+                self.nodes[index].deleted = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn visible(&self) -> impl Iterator<Item = &Node<T>> {
+        self.nodes.iter().filter(|node| !node.deleted)
+    }
+
+    // Id of the visible element before the given visible index.
+    fn id_before(&self, index: usize) -> Option<CrdtId> {
+        if index == 0 {
+            None
+        } else {
+            self.visible().nth(index - 1).map(|node| node.id)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Block {
+    tag: String,
+    text: Rga<char>,
+}
+
+/// A document replica on one site.
+#[derive(Debug, Clone)]
+pub struct CrdtDoc {
+    site: u32,
+    clock: u64,
+    blocks: Rga<Block>,
+    // Remote operations waiting on what they refer to.
+    pending: Vec<CrdtOp>,
+}
+
+fn plain_text(span: &DocSpan, out: &mut String) {
+    for elem in span {
+        match elem {
+            DocChars(text) => out.push_str(text.as_str()),
+            DocGroup(_, inner) => plain_text(inner, out),
+        }
+    }
+}
+
+// The tag and text of each block of a document, as a CrdtDoc models them.
+fn plain_blocks(doc: &Doc) -> Vec<(String, String)> {
+    doc.0
+        .iter()
+        .filter_map(|elem| match elem {
+            DocGroup(attrs, inner) => {
+                // A bullet is kept as the first block it holds.
+                let is_bullet = attrs.get("tag").map(|x| x == "bullet").unwrap_or(false);
+                let attrs = match inner.first() {
+                    Some(DocGroup(inner_attrs, _)) if is_bullet => inner_attrs,
+                    _ => attrs,
+                };
+                let tag = attrs.get("tag").cloned().unwrap_or_else(|| "p".to_string());
+                let mut text = String::new();
+                plain_text(inner, &mut text);
+                Some((tag, text))
+            }
+            DocChars(_) => None,
+        })
+        .collect()
+}
+
+/// The initial document of every peer page, which each replica starts from.
+pub fn initial_peer_doc() -> Doc {
+    let mut attrs = HashMap::new();
+    attrs.insert("tag".to_string(), "p".to_string());
+    Doc(vec![DocGroup(attrs, vec![])])
+}
+
+/// A site for a client, from its id. Site 0 is left for the contents every
+/// replica starts with.
+pub fn site_for_client(client_id: &str) -> u32 {
+    // FNV-1a, which is the same on every platform.
+    let hash = client_id.bytes().fold(0x811c_9dc5u32, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    });
+    cmp::max(hash, 1)
+}
+
+impl CrdtDoc {
+    /// A replica starting from a document. Every replica of the same
+    /// document must start from it, as the ids of its initial contents are
+    /// given out in order from site 0.
+    pub fn from_doc(site: u32, doc: &Doc) -> CrdtDoc {
+        let mut replica = CrdtDoc {
+            site: 0,
+            clock: 0,
+            blocks: Rga::new(),
+            pending: vec![],
+        };
+        for (tag, text) in plain_blocks(doc) {
+            let block = replica.blocks.visible().count();
+            replica.insert_block_with_text(block, &tag, &text);
+        }
+        replica.site = site;
+        replica
+    }
+
+    /// Bring the replica in line with a document the editor changed it to,
+    /// returning the operations that make the same change on other replicas.
+    /// Blocks are matched up by skipping those that are unchanged at either
+    /// end, and the rest are edited in place where their tags agree.
+    pub fn edit(&mut self, doc: &Doc) -> Vec<CrdtOp> {
+        let current = plain_blocks(&self.to_doc());
+        let target = plain_blocks(doc);
+
+        let prefix = current
+            .iter()
+            .zip(&target)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let suffix = current[prefix..]
+            .iter()
+            .rev()
+            .zip(target[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let old_blocks = &current[prefix..current.len() - suffix];
+        let new_blocks = &target[prefix..target.len() - suffix];
+
+        let mut ops = vec![];
+        let mut block = prefix;
+        for index in 0..cmp::max(old_blocks.len(), new_blocks.len()) {
+            match (old_blocks.get(index), new_blocks.get(index)) {
+                (Some((old_tag, old_text)), Some((tag, text))) if old_tag == tag => {
+                    ops.extend(self.edit_text(block, old_text, text));
+                    block += 1;
+                }
+                (Some(_), Some((tag, text))) => {
+                    ops.extend(self.delete_block(block));
+                    ops.extend(self.insert_block_with_text(block, tag, text));
+                    block += 1;
+                }
+                (Some(_), None) => ops.extend(self.delete_block(block)),
+                (None, Some((tag, text))) => {
+                    ops.extend(self.insert_block_with_text(block, tag, text));
+                    block += 1;
+                }
+                (None, None) => unreachable!(),
+            }
+        }
+        ops
+    }
+
+    // Change the text of a block from `old` to `new`, skipping what's the
+    // same at either end.
+    fn edit_text(&mut self, block: usize, old: &str, new: &str) -> Vec<CrdtOp> {
+        let old = old.chars().collect::<Vec<_>>();
+        let new = new.chars().collect::<Vec<_>>();
+        let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+        let suffix = old[prefix..]
+            .iter()
+            .rev()
+            .zip(new[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        let mut ops = vec![];
+        for _ in prefix..old.len() - suffix {
+            ops.extend(self.delete_char(block, prefix));
+        }
+        for (offset, value) in new[prefix..new.len() - suffix].iter().enumerate() {
+            ops.extend(self.insert_char(block, prefix + offset, *value));
+        }
+        ops
+    }
+
+    fn insert_block_with_text(&mut self, block: usize, tag: &str, text: &str) -> Vec<CrdtOp> {
+        let mut ops = vec![self.insert_block(block, tag)];
+        for (offset, value) in text.chars().enumerate() {
+            ops.extend(self.insert_char(block, offset, value));
+        }
+        ops
+    }
+
+    fn next_id(&mut self) -> CrdtId {
+        self.clock += 1;
+        CrdtId {
+            clock: self.clock,
+            site: self.site,
+        }
+    }
+
+    fn block_id(&self, block: usize) -> Option<CrdtId> {
+        self.blocks.visible().nth(block).map(|node| node.id)
+    }
+
+    /// Insert a block with a tag before the visible block at `block`.
+    pub fn insert_block(&mut self, block: usize, tag: &str) -> CrdtOp {
+        let op = CrdtOp::InsertBlock {
+            id: self.next_id(),
+            after: self.blocks.id_before(block),
+            tag: tag.to_string(),
+        };
+        self.apply_op(&op);
+        op
+    }
+
+    pub fn delete_block(&mut self, block: usize) -> Option<CrdtOp> {
+        let op = CrdtOp::DeleteBlock {
+            id: self.block_id(block)?,
+        };
+        self.apply_op(&op);
+        Some(op)
+    }
+
+    /// Insert a character into a block, at an offset among its visible
+    /// characters.
+    pub fn insert_char(&mut self, block: usize, offset: usize, value: char) -> Option<CrdtOp> {
+        let id = self.next_id();
+        let op = {
+            let block_node = self.blocks.visible().nth(block)?;
+            CrdtOp::InsertChar {
+                block: block_node.id,
+                id,
+                after: block_node.value.text.id_before(offset),
+                value,
+            }
+        };
+        self.apply_op(&op);
+        Some(op)
+    }
+
+    pub fn delete_char(&mut self, block: usize, offset: usize) -> Option<CrdtOp> {
+        let op = {
+            let block_node = self.blocks.visible().nth(block)?;
+            CrdtOp::DeleteChar {
+                block: block_node.id,
+                id: block_node.value.text.visible().nth(offset)?.id,
+            }
+        };
+        self.apply_op(&op);
+        Some(op)
+    }
+
+    // Returns false if the operation refers to something not known yet.
+    fn apply_op(&mut self, op: &CrdtOp) -> bool {
+        match op.clone() {
+            CrdtOp::InsertBlock { id, after, tag } => self.blocks.insert(
+                after,
+                id,
+                Block {
+                    tag,
+                    text: Rga::new(),
+                },
+            ),
+            CrdtOp::DeleteBlock { id } => self.blocks.delete(id),
+            CrdtOp::InsertChar {
+                block,
+                id,
+                after,
+                value,
+            } => match self.blocks.nodes.iter_mut().find(|node| node.id == block) {
+                Some(node) => node.value.text.insert(after, id, value),
+                None => false,
+            },
+            CrdtOp::DeleteChar { block, id } => {
+                match self.blocks.nodes.iter_mut().find(|node| node.id == block) {
+                    Some(node) => node.value.text.delete(id),
+                    None => false,
+                }
+            }
+        }
+    }
+
+    /// Apply an operation from another replica. Operations that arrive
+    /// before what they refer to are held until it arrives.
+    pub fn apply_remote(&mut self, op: CrdtOp) {
+        self.clock = self.clock.max(op.id().clock);
+        self.pending.push(op);
+        loop {
+            let pending = ::std::mem::replace(&mut self.pending, vec![]);
+            let count = pending.len();
+            for op in pending {
+                if !self.apply_op(&op) {
+                    self.pending.push(op);
+                }
+            }
+            if self.pending.len() == count {
+                break;
+            }
+        }
+    }
+
+    /// Apply operations from another replica, in the order they were made.
+    pub fn apply_remote_ops(&mut self, ops: Vec<CrdtOp>) {
+        for op in ops {
+            self.apply_remote(op);
+        }
+    }
+
+    /// How many remote operations are waiting on others.
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// The document as the editor displays it.
+    pub fn to_doc(&self) -> Doc {
+        Doc(self
+            .blocks
+            .visible()
+            .map(|node| {
+                let text = node.value.text.visible().map(|node| node.value).collect::<String>();
+                let mut attrs = HashMap::new();
+                attrs.insert("tag".to_string(), node.value.tag.clone());
+                DocGroup(
+                    attrs,
+                    if text.is_empty() {
+                        vec![]
+                    } else {
+                        vec![DocChars(DocString::from_string(text))]
+                    },
+                )
+            })
+            .collect())
+    }
+}
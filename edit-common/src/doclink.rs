@@ -0,0 +1,37 @@
+//! Links to other documents, as inline objects holding the id of the page
+//! they point at. They're shown with the target page's current title, which
+//! the sync server resolves and pushes to clients as it changes.
+
+use oatie::doc::*;
+use std::collections::BTreeSet;
+
+fn is_doc_link(attrs: &Attrs) -> bool {
+    attrs.get("tag").map(|tag| tag == "doclink").unwrap_or(false)
+}
+
+/// The page a document link points at.
+pub fn doc_link_target(attrs: &Attrs) -> Option<&str> {
+    if is_doc_link(attrs) {
+        attrs.get("page").map(|x| x.as_str())
+    } else {
+        None
+    }
+}
+
+fn collect_targets(span: &DocSpan, out: &mut BTreeSet<String>) {
+    for elem in span {
+        if let DocGroup(attrs, inner) = elem {
+            if let Some(page_id) = doc_link_target(attrs) {
+                out.insert(page_id.to_string());
+            }
+            collect_targets(inner, out);
+        }
+    }
+}
+
+/// Ids of every page the document links to.
+pub fn linked_pages(span: &DocSpan) -> BTreeSet<String> {
+    let mut out = BTreeSet::new();
+    collect_targets(span, &mut out);
+    out
+}
@@ -0,0 +1,90 @@
+//! End-to-end encryption of documents. Operations are sealed with
+//! ChaCha20-Poly1305 under a per-document key that stays with the clients,
+//! bound to the page id so they can't be replayed into another page.
+
+use base64;
+use chacha20_poly1305_aead::{
+    decrypt,
+    encrypt,
+};
+use failure::{
+    err_msg,
+    Error,
+};
+use oatie::doc::*;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json;
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+/// The document an encrypted page starts out as, before its first
+/// operation. The server never sees it, so every client has to agree on it.
+pub fn initial_encrypted_doc() -> Doc {
+    let text = DocString::from_str_styled("Untitled", style_map! { Style::Normie => None });
+    Doc(vec![DocGroup(
+        hashmap! { "tag".to_string() => "h1".to_string() },
+        vec![DocChars(text)],
+    )])
+}
+
+/// Whether a value is shaped like one `seal` produces. Only clients with the
+/// key can tell if it opens, but the server can refuse what never could.
+pub fn is_sealed(sealed: &str) -> bool {
+    match base64::decode_config(sealed, base64::URL_SAFE_NO_PAD) {
+        Ok(bytes) => bytes.len() >= NONCE_LEN + TAG_LEN,
+        Err(_) => false,
+    }
+}
+
+#[derive(Clone)]
+pub struct DocKey {
+    key: [u8; KEY_LEN],
+    page_id: String,
+}
+
+impl DocKey {
+    /// A key from its base64 encoding, for the page `page_id`.
+    pub fn from_base64(input: &str, page_id: &str) -> Result<DocKey, Error> {
+        let bytes = base64::decode_config(input, base64::URL_SAFE_NO_PAD)?;
+        if bytes.len() != KEY_LEN {
+            return Err(err_msg(format!(
+                "encryption keys are {} bytes, not {}",
+                KEY_LEN,
+                bytes.len()
+            )));
+        }
+        let mut key = [0; KEY_LEN];
+        key.copy_from_slice(&bytes);
+        Ok(DocKey {
+            key,
+            page_id: page_id.to_string(),
+        })
+    }
+
+    /// Encrypt a value, given a nonce never used with this key before.
+    pub fn seal<T: Serialize>(&self, value: &T, nonce: [u8; NONCE_LEN]) -> Result<String, Error> {
+        let plaintext = serde_json::to_vec(value)?;
+        let mut out = nonce.to_vec();
+        let tag = encrypt(&self.key, &nonce, self.page_id.as_bytes(), &plaintext, &mut out)?;
+        out.extend_from_slice(&tag);
+        Ok(base64::encode_config(&out, base64::URL_SAFE_NO_PAD))
+    }
+
+    /// Decrypt a value sealed by `seal`.
+    pub fn open<T: DeserializeOwned>(&self, sealed: &str) -> Result<T, Error> {
+        let bytes = base64::decode_config(sealed, base64::URL_SAFE_NO_PAD)?;
+        if bytes.len() < NONCE_LEN + TAG_LEN {
+            return Err(err_msg("sealed value is too short"));
+        }
+        let (nonce, rest) = bytes.split_at(NONCE_LEN);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LEN);
+
+        let mut plaintext = vec![];
+        decrypt(&self.key, nonce, self.page_id.as_bytes(), ciphertext, tag, &mut plaintext)
+            .map_err(|_| err_msg("could not decrypt a sealed value"))?;
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+}
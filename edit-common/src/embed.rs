@@ -0,0 +1,65 @@
+//! Embedded media blocks, which show the page at a URL in an iframe. Links
+//! to videos on known sites are rewritten to those sites' embeddable players.
+
+/// The iframe source for embedding `url`, or None if it isn't an http(s)
+/// URL, or is a video link without a valid video id.
+pub fn embed_src(url: &str) -> Option<String> {
+    let url = url.trim();
+    let rest = if url.starts_with("https://") {
+        &url[8..]
+    } else if url.starts_with("http://") {
+        &url[7..]
+    } else {
+        return None;
+    };
+    if rest.is_empty() || rest.contains(char::is_whitespace) {
+        return None;
+    }
+    let rest = if rest.starts_with("www.") { &rest[4..] } else { rest };
+
+    // Video ids end at the next query parameter or fragment, and are only
+    // letters, digits, dashes, and underscores.
+    let video_id = |id: &str| {
+        let id = id.split(|c| c == '&' || c == '?' || c == '#').next().unwrap_or("");
+        if !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            Some(id.to_string())
+        } else {
+            None
+        }
+    };
+
+    if rest.starts_with("youtube.com/watch?") {
+        let query = &rest["youtube.com/watch?".len()..];
+        if let Some(param) = query.split('&').find(|param| param.starts_with("v=")) {
+            return video_id(&param[2..]).map(|id| format!("https://www.youtube.com/embed/{}", id));
+        }
+    } else if rest.starts_with("youtu.be/") {
+        return video_id(&rest[9..]).map(|id| format!("https://www.youtube.com/embed/{}", id));
+    } else if rest.starts_with("vimeo.com/") {
+        return video_id(&rest[10..]).map(|id| format!("https://player.vimeo.com/video/{}", id));
+    }
+    Some(url.to_string())
+}
+
+/// Whether `url` links to a video on a site we know how to embed, so that
+/// pasting it should embed the video rather than insert the link.
+pub fn is_video_url(url: &str) -> bool {
+    match embed_src(url) {
+        Some(src) => src != url.trim(),
+        None => false,
+    }
+}
+
+/// The iframe sandbox for an embed with this source. Video players need
+/// their own origin for storage; other pages get scripts but stay in a
+/// unique origin, so a page served from our own host can't reach into the
+/// editor.
+pub fn embed_sandbox(src: &str) -> &'static str {
+    if src.starts_with("https://www.youtube.com/embed/")
+        || src.starts_with("https://player.vimeo.com/video/")
+    {
+        "allow-scripts allow-same-origin allow-presentation allow-popups"
+    } else {
+        "allow-scripts allow-popups"
+    }
+}
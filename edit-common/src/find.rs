@@ -0,0 +1,120 @@
+//! Finding text in a document. Matches are returned as an operation that
+//! inserts a pair of marker carets around each one, so that like any other
+//! operation they can be transformed to a later version of the document.
+//! Match `n` is marked by the carets of client "$find-n".
+
+use oatie::doc::*;
+use oatie::OT;
+
+/// Prefix of the client ids of match markers.
+pub const FIND_CLIENT: &str = "$find";
+
+/// Client id of the carets marking match `n`.
+pub fn find_client_id(n: usize) -> String {
+    format!("{}-{}", FIND_CLIENT, n)
+}
+
+fn is_caret(attrs: &Attrs) -> bool {
+    attrs.get("tag").map(|tag| tag == "caret").unwrap_or(false)
+}
+
+fn lower(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+fn marker(n: usize, focus: bool) -> Attrs {
+    hashmap! {
+        "tag".to_string() => "caret".to_string(),
+        "client".to_string() => find_client_id(n),
+        "focus".to_string() => focus.to_string(),
+    }
+}
+
+fn find_span(span: &DocSpan, query: &[char], n: &mut usize) -> AddSpan {
+    // Characters directly inside this span. Other groups can't be part of a
+    // match, so each stands in as a character nothing matches, except for
+    // carets, which are left out.
+    let mut text = vec![];
+    for elem in span {
+        match elem {
+            DocChars(chars) => text.extend(chars.as_str().chars().map(lower)),
+            DocGroup(attrs, _) => {
+                if !is_caret(attrs) {
+                    text.push('\0');
+                }
+            }
+        }
+    }
+
+    let mut markers = vec![];
+    let mut i = 0;
+    while i + query.len() <= text.len() {
+        if text[i..].starts_with(query) {
+            markers.push((i, marker(*n, false)));
+            markers.push((i + query.len(), marker(*n, true)));
+            *n += 1;
+            i += query.len();
+        } else {
+            i += 1;
+        }
+    }
+    let mut markers = markers.into_iter().peekable();
+
+    let mut add = vec![];
+    let mut pos = 0;
+    for elem in span {
+        match elem {
+            DocChars(chars) => {
+                let len = chars.char_len();
+                let mut done = 0;
+                while markers.peek().map(|(at, _)| *at < pos + len).unwrap_or(false) {
+                    let (at, attrs) = markers.next().unwrap();
+                    if at - pos > done {
+                        add.place(&AddSkip(at - pos - done));
+                        done = at - pos;
+                    }
+                    add.place(&AddGroup(attrs, vec![]));
+                }
+                if len > done {
+                    add.place(&AddSkip(len - done));
+                }
+                pos += len;
+            }
+            DocGroup(attrs, inner) => {
+                if !is_caret(attrs) {
+                    while markers.peek().map(|(at, _)| *at == pos).unwrap_or(false) {
+                        let (_, attrs) = markers.next().unwrap();
+                        add.place(&AddGroup(attrs, vec![]));
+                    }
+                    pos += 1;
+                }
+                let inner_add = find_span(inner, query, n);
+                if inner_add.is_empty() {
+                    add.place(&AddSkip(1));
+                } else {
+                    add.place(&AddWithGroup(inner_add));
+                }
+            }
+        }
+    }
+    for (_, attrs) in markers {
+        add.place(&AddGroup(attrs, vec![]));
+    }
+
+    if let Some(AddSkip(_)) = add.last() {
+        add.pop();
+    }
+    add
+}
+
+/// An operation marking every match of `query` in `doc`, ignoring case,
+/// along with the number of matches.
+pub fn find_markers_op(doc: &Doc, query: &str) -> (Op, usize) {
+    let query = query.chars().map(lower).collect::<Vec<_>>();
+    if query.is_empty() {
+        return (Op::empty(), 0);
+    }
+    let mut n = 0;
+    let add = find_span(&doc.0, &query, &mut n);
+    ((vec![], add), n)
+}
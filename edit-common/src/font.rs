@@ -0,0 +1,36 @@
+//! Font size and family styles. Their values end up in the style attribute
+//! of exported HTML, so only values that can't escape it are allowed.
+
+/// Whether `size` is a CSS length like "14px", "1.5em", or "120%".
+pub fn valid_font_size(size: &str) -> bool {
+    let units = ["px", "pt", "em", "rem", "%"];
+    units.iter().any(|unit| {
+        size.ends_with(unit) && {
+            let number = &size[..size.len() - unit.len()];
+            !number.is_empty()
+                && number.chars().all(|c| c.is_ascii_digit() || c == '.')
+                && number.parse::<f64>().map(|x| x > 0.0).unwrap_or(false)
+        }
+    })
+}
+
+/// Whether `family` is a list of font names like "Georgia, serif".
+pub fn valid_font_family(family: &str) -> bool {
+    !family.trim().is_empty()
+        && family.len() <= 100
+        && family
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == ' ' || c == ',' || c == '-')
+}
+
+/// The CSS declarations for a span's font styles, skipping invalid values.
+pub fn font_css(size: Option<&str>, family: Option<&str>) -> String {
+    let mut css = String::new();
+    if let Some(size) = size.filter(|size| valid_font_size(size)) {
+        css.push_str(&format!("font-size:{};", size));
+    }
+    if let Some(family) = family.filter(|family| valid_font_family(family)) {
+        css.push_str(&format!("font-family:{};", family));
+    }
+    css
+}
@@ -0,0 +1,254 @@
+//! Syntax highlighting for code blocks. Each code block with a language is
+//! split into token spans, which are shipped to the frontend alongside the
+//! document so it can color code without a highlighter of its own. Tokens
+//! are cached per top-level element, like document statistics.
+
+use crate::blocks::map_top_level;
+use oatie::doc::*;
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub enum TokenKind {
+    Keyword,
+    String,
+    Number,
+    Comment,
+}
+
+// A token, as a range of characters in its code block.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Token {
+    pub start: usize,
+    pub end: usize,
+    pub kind: TokenKind,
+}
+
+// Tokens of one code block, which is identified by its position among the
+// document's code blocks, and by its id if it has one.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct CodeHighlight {
+    pub block: usize,
+    pub id: Option<String>,
+    pub tokens: Vec<Token>,
+}
+
+struct Language {
+    keywords: &'static [&'static str],
+    line_comment: &'static str,
+    block_comment: Option<(&'static str, &'static str)>,
+}
+
+const RUST: Language = Language {
+    keywords: &[
+        "as", "break", "const", "continue", "crate", "else", "enum", "extern", "false", "fn",
+        "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+        "return", "self", "Self", "static", "struct", "super", "trait", "true", "type", "unsafe",
+        "use", "where", "while",
+    ],
+    line_comment: "//",
+    block_comment: Some(("/*", "*/")),
+};
+
+const JAVASCRIPT: Language = Language {
+    keywords: &[
+        "async", "await", "break", "case", "catch", "class", "const", "continue", "default",
+        "delete", "do", "else", "export", "extends", "false", "finally", "for", "function", "if",
+        "import", "in", "instanceof", "let", "new", "null", "return", "switch", "this", "throw",
+        "true", "try", "typeof", "undefined", "var", "void", "while", "yield",
+    ],
+    line_comment: "//",
+    block_comment: Some(("/*", "*/")),
+};
+
+const PYTHON: Language = Language {
+    keywords: &[
+        "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class",
+        "continue", "def", "del", "elif", "else", "except", "finally", "for", "from", "global",
+        "if", "import", "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise",
+        "return", "try", "while", "with", "yield",
+    ],
+    line_comment: "#",
+    block_comment: None,
+};
+
+const SHELL: Language = Language {
+    keywords: &[
+        "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function", "if",
+        "in", "local", "return", "then", "until", "while",
+    ],
+    line_comment: "#",
+    block_comment: None,
+};
+
+fn language(name: &str) -> Option<&'static Language> {
+    match name.trim().to_lowercase().as_str() {
+        "rust" | "rs" => Some(&RUST),
+        "javascript" | "js" | "typescript" | "ts" | "jsx" | "tsx" => Some(&JAVASCRIPT),
+        "python" | "py" => Some(&PYTHON),
+        "sh" | "bash" | "shell" => Some(&SHELL),
+        _ => None,
+    }
+}
+
+fn starts_with_at(chars: &[char], i: usize, pat: &str) -> bool {
+    let mut j = i;
+    for c in pat.chars() {
+        if chars.get(j) != Some(&c) {
+            return false;
+        }
+        j += 1;
+    }
+    true
+}
+
+/// Split `code` into tokens of the named language. Unknown languages have
+/// no tokens.
+pub fn highlight(lang: &str, code: &str) -> Vec<Token> {
+    let lang = match language(lang) {
+        Some(lang) => lang,
+        None => return vec![],
+    };
+
+    let chars = code.chars().collect::<Vec<_>>();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        let kind = if starts_with_at(&chars, i, lang.line_comment) {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            TokenKind::Comment
+        } else if let Some((open, close)) = lang
+            .block_comment
+            .filter(|(open, _)| starts_with_at(&chars, i, open))
+        {
+            i += open.chars().count();
+            while i < chars.len() && !starts_with_at(&chars, i, close) {
+                i += 1;
+            }
+            i = (i + close.chars().count()).min(chars.len());
+            TokenKind::Comment
+        } else if c == '"' || c == '\'' || c == '`' {
+            i += 1;
+            while i < chars.len() && chars[i] != c {
+                if chars[i] == '\\' {
+                    i += 1;
+                }
+                i += 1;
+            }
+            i = (i + 1).min(chars.len());
+            TokenKind::String
+        } else if c.is_ascii_digit() {
+            let in_number = |c: char| c.is_ascii_alphanumeric() || c == '.' || c == '_';
+            while i < chars.len() && in_number(chars[i]) {
+                i += 1;
+            }
+            TokenKind::Number
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let word = chars[start..i].iter().collect::<String>();
+            if lang.keywords.contains(&word.as_str()) {
+                TokenKind::Keyword
+            } else {
+                continue;
+            }
+        } else {
+            i += 1;
+            continue;
+        };
+        tokens.push(Token {
+            start,
+            end: i,
+            kind,
+        });
+    }
+    tokens
+}
+
+fn block_text(span: &DocSpan, out: &mut String) {
+    for elem in span {
+        match elem {
+            DocChars(text) => out.push_str(text.as_str()),
+            DocGroup(_, span) => block_text(span, out),
+        }
+    }
+}
+
+// Id and tokens of each code block in `span`, in order.
+fn collect_code(span: &DocSpan, out: &mut Vec<(Option<String>, Vec<Token>)>) {
+    for elem in span {
+        if let DocGroup(attrs, inner) = elem {
+            if attrs["tag"] == "pre" {
+                let mut code = String::new();
+                block_text(inner, &mut code);
+                let tokens = attrs
+                    .get("lang")
+                    .map(|lang| highlight(lang, &code))
+                    .unwrap_or_default();
+                out.push((attrs.get("id").cloned(), tokens));
+            } else {
+                collect_code(inner, out);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HighlightCache {
+    // Code blocks inside each top-level element, or None if the element
+    // changed and needs to be highlighted again.
+    elements: Vec<Option<Vec<(Option<String>, Vec<Token>)>>>,
+}
+
+impl HighlightCache {
+    pub fn new() -> HighlightCache {
+        HighlightCache { elements: vec![] }
+    }
+
+    /// Highlight the whole document again.
+    pub fn reset(&mut self, doc: &Doc) {
+        self.elements = doc.0.iter().map(|_| None).collect();
+    }
+
+    /// Update the cache after `op` was applied, producing `doc`.
+    pub fn apply(&mut self, doc: &Doc, op: &Op) {
+        match map_top_level(&self.elements, op) {
+            Some(elements) if elements.len() == doc.0.len() => {
+                self.elements = elements;
+            }
+            _ => self.reset(doc),
+        }
+    }
+
+    /// Tokens of every highlighted code block of `doc`. `doc` must be the
+    /// document the cache was last updated with.
+    pub fn highlights(&mut self, doc: &Doc) -> Vec<CodeHighlight> {
+        if self.elements.len() != doc.0.len() {
+            self.reset(doc);
+        }
+
+        let mut out = vec![];
+        let mut block = 0;
+        for (entry, elem) in self.elements.iter_mut().zip(&doc.0) {
+            if entry.is_none() {
+                let mut found = vec![];
+                collect_code(&vec![elem.clone()], &mut found);
+                *entry = Some(found);
+            }
+            for (id, tokens) in entry.as_ref().unwrap() {
+                if !tokens.is_empty() {
+                    out.push(CodeHighlight {
+                        block,
+                        id: id.clone(),
+                        tokens: tokens.clone(),
+                    });
+                }
+                block += 1;
+            }
+        }
+        out
+    }
+}
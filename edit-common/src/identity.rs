@@ -0,0 +1,122 @@
+//! Who is behind a client. Clients describe their user when they connect,
+//! and the server tells every client of a page who else is editing it, so
+//! carets can be shown with a name and a color.
+//!
+//! Users who don't give a name are guests, with a name like "Amber Fox"
+//! picked from their session cookie, so it stays the same across pages and
+//! reconnects.
+
+/// Cookie identifying a browser's session, set when it loads a page.
+pub const SESSION_COOKIE: &str = "edit-session";
+
+/// Longest display name kept, in characters.
+pub const MAX_NAME_LEN: usize = 64;
+
+// Caret colors, chosen to read well against the page background.
+const USER_COLORS: &[&str] = &[
+    "#e6194b", "#3cb44b", "#4363d8", "#f58231", "#911eb4", "#46a0a0", "#f032e6", "#808000",
+    "#9a6324", "#800000", "#008080", "#000075",
+];
+
+const GUEST_ADJECTIVES: &[&str] = &[
+    "Amber", "Azure", "Brave", "Calm", "Coral", "Crimson", "Dapper", "Eager", "Gentle", "Golden",
+    "Indigo", "Jade", "Lively", "Lucky", "Misty", "Nimble", "Olive", "Quiet", "Rustic", "Scarlet",
+    "Silver", "Sunny", "Swift", "Violet",
+];
+
+const GUEST_ANIMALS: &[&str] = &[
+    "Badger", "Crane", "Dolphin", "Falcon", "Fox", "Gecko", "Heron", "Ibis", "Koala", "Lynx",
+    "Marten", "Newt", "Otter", "Owl", "Panda", "Quail", "Raven", "Seal", "Stoat", "Tapir",
+    "Turtle", "Walrus", "Wren", "Yak",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserIdentity {
+    pub id: String,
+    pub name: String,
+    // CSS color of the user's carets, as "#rrggbb".
+    pub color: String,
+    pub avatar_url: Option<String>,
+    // Whether the name was made up for a user who didn't give one.
+    #[serde(default)]
+    pub guest: bool,
+}
+
+fn hash(seed: &str) -> u32 {
+    seed.bytes()
+        .fold(0u32, |hash, byte| hash.wrapping_mul(31).wrapping_add(byte as u32))
+}
+
+/// A color for a user picked from their id, so they're the same color
+/// wherever they show up.
+pub fn default_user_color(id: &str) -> String {
+    USER_COLORS[hash(id) as usize % USER_COLORS.len()].to_string()
+}
+
+/// A guest name, like "Amber Fox", that's always the same for a seed.
+pub fn guest_name(seed: &str) -> String {
+    let hash = hash(seed) as usize;
+    format!(
+        "{} {}",
+        GUEST_ADJECTIVES[hash % GUEST_ADJECTIVES.len()],
+        GUEST_ANIMALS[(hash / GUEST_ADJECTIVES.len()) % GUEST_ANIMALS.len()],
+    )
+}
+
+/// The session id in a Cookie header, if there is one.
+pub fn session_id(cookie: &str) -> Option<String> {
+    cookie
+        .split(';')
+        .filter_map(|pair| {
+            let mut parts = pair.trim().splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) if name == SESSION_COOKIE && !value.is_empty() => {
+                    Some(value.to_string())
+                }
+                _ => None,
+            }
+        })
+        .next()
+}
+
+fn valid_color(color: &str) -> bool {
+    color.len() == 7
+        && color.starts_with('#')
+        && color[1..].chars().all(|c| c.is_digit(16))
+}
+
+fn valid_avatar_url(url: &str) -> bool {
+    (url.starts_with("https://") || url.starts_with("http://")) && !url.contains('"')
+}
+
+impl UserIdentity {
+    /// An identity from what a client sent, filling in what it left out or
+    /// got wrong. Users without a name are named as guests from
+    /// `guest_seed`, or else their id.
+    pub fn new(
+        id: &str,
+        name: Option<&str>,
+        color: Option<&str>,
+        avatar_url: Option<&str>,
+        guest_seed: Option<&str>,
+    ) -> UserIdentity {
+        let name = name
+            .map(|name| name.trim().chars().take(MAX_NAME_LEN).collect::<String>())
+            .filter(|name| !name.is_empty());
+        let guest = name.is_none();
+        let name = name.unwrap_or_else(|| guest_name(guest_seed.unwrap_or(id)));
+        let color = color
+            .filter(|color| valid_color(color))
+            .map(|color| color.to_lowercase())
+            .unwrap_or_else(|| default_user_color(id));
+        UserIdentity {
+            id: id.to_string(),
+            name,
+            color,
+            avatar_url: avatar_url
+                .filter(|url| valid_avatar_url(url))
+                .map(|url| url.to_string()),
+            guest,
+        }
+    }
+}
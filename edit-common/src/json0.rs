@@ -0,0 +1,393 @@
+//! Conversion between operations and ShareDB's json0 and text OT types, to
+//! bridge clients that still speak them. Only text edits within blocks are
+//! supported: operations that add, remove, or restyle groups can't be
+//! expressed in either type and are refused.
+//!
+//! A json0 snapshot of a document is an array holding the text of each
+//! block, in the order blocks appear, including those nested in lists and
+//! quotes. A text snapshot joins those with "\n". Inline objects, like math
+//! and images, appear as U+FFFC and can't be edited, and carets are left out.
+//! Offsets count UTF-16 code units, as JavaScript does.
+
+use failure::{
+    err_msg,
+    Error,
+};
+use oatie::apply::normalize;
+use oatie::doc::*;
+use oatie::schema::{
+    RtfSchema,
+    RtfTrack,
+};
+use oatie::{
+    Schema,
+    OT,
+};
+use std::collections::HashMap;
+
+/// Stands in for inline objects in the text of a block.
+pub const OBJECT_CHAR: char = '\u{fffc}';
+
+/// A component of a json0 operation, inserting or deleting text at
+/// `[block, offset]`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Json0Component {
+    pub p: Vec<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub si: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sd: Option<String>,
+}
+
+/// A component of a text operation: skip, insert, or delete.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum TextComponent {
+    Skip(usize),
+    Insert(String),
+    Delete { d: usize },
+}
+
+pub type TextOp = Vec<TextComponent>;
+
+fn is_block(attrs: &Attrs) -> bool {
+    RtfSchema::track_type_from_attrs(attrs) == Some(RtfTrack::Blocks)
+}
+
+fn is_container(attrs: &Attrs) -> bool {
+    match attrs.get("tag").map(|x| x.as_str()) {
+        Some("bullet") | Some("blockquote") => true,
+        _ => false,
+    }
+}
+
+fn is_caret(attrs: &Attrs) -> bool {
+    attrs.get("tag").map(|x| x == "caret").unwrap_or(false)
+}
+
+fn block_text(span: &DocSpan) -> String {
+    let mut text = String::new();
+    for elem in span {
+        match elem {
+            DocChars(chars) => text.push_str(chars.as_str()),
+            DocGroup(attrs, _) if is_caret(attrs) => {}
+            DocGroup(..) => text.push(OBJECT_CHAR),
+        }
+    }
+    text
+}
+
+fn collect_blocks(span: &DocSpan, out: &mut Vec<String>) {
+    for elem in span {
+        if let DocGroup(attrs, inner) = elem {
+            if is_block(attrs) {
+                out.push(block_text(inner));
+            } else if is_container(attrs) {
+                collect_blocks(inner, out);
+            }
+        }
+    }
+}
+
+/// The json0 snapshot of a document: the text of each of its blocks.
+pub fn doc_to_json0(doc: &DocSpan) -> Vec<String> {
+    let mut blocks = vec![];
+    collect_blocks(doc, &mut blocks);
+    blocks
+}
+
+/// The text snapshot of a document. Fails if a block's text has a newline,
+/// as in code blocks, since those separate blocks.
+pub fn doc_to_text(doc: &DocSpan) -> Result<String, Error> {
+    let blocks = doc_to_json0(doc);
+    if blocks.iter().any(|block| block.contains('\n')) {
+        return Err(err_msg("blocks with line breaks have no text snapshot"));
+    }
+    Ok(blocks.join("\n"))
+}
+
+fn utf16_len(text: &str) -> usize {
+    text.chars().map(|c| c.len_utf16()).sum()
+}
+
+// Char index of a UTF-16 offset.
+fn char_index(text: &str, offset: usize) -> Result<usize, Error> {
+    let mut units = 0;
+    for (i, c) in text.chars().enumerate() {
+        if units == offset {
+            return Ok(i);
+        }
+        units += c.len_utf16();
+    }
+    if units == offset {
+        Ok(text.chars().count())
+    } else {
+        Err(err_msg(format!("offset {} is outside of its text", offset)))
+    }
+}
+
+// The chars of `before` replaced to make `after`: where the change starts,
+// how many chars it deletes, and what it inserts.
+fn text_change(before: &str, after: &str) -> Option<(usize, usize, String)> {
+    let before = before.chars().collect::<Vec<_>>();
+    let after = after.chars().collect::<Vec<_>>();
+    let prefix = before
+        .iter()
+        .zip(after.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let max_suffix = before.len().min(after.len()) - prefix;
+    let suffix = before
+        .iter()
+        .rev()
+        .zip(after.iter().rev())
+        .take(max_suffix)
+        .take_while(|(a, b)| a == b)
+        .count();
+    if prefix + suffix == before.len() && prefix + suffix == after.len() {
+        return None;
+    }
+    Some((
+        prefix,
+        before.len() - prefix - suffix,
+        after[prefix..after.len() - suffix].iter().collect(),
+    ))
+}
+
+// The edit of a block's contents replacing `deleted` chars of its text at
+// `start` with `inserted`.
+fn block_edit(
+    span: &DocSpan,
+    start: usize,
+    deleted: usize,
+    inserted: &str,
+) -> Result<(DelSpan, AddSpan), Error> {
+    if inserted.contains(OBJECT_CHAR) {
+        return Err(err_msg("inline objects can't be inserted as text"));
+    }
+
+    // Carets are skipped over without counting.
+    let mut units = vec![];
+    for elem in span {
+        match elem {
+            DocChars(text) => units.extend(text.as_str().chars().map(|_| None)),
+            DocGroup(attrs, inner) => units.push(Some((is_caret(attrs), inner.is_empty()))),
+        }
+    }
+
+    let mut del = vec![];
+    let mut index = 0;
+    let mut position = 0;
+    while position < start {
+        match units.get(index) {
+            Some(Some((true, _))) => {}
+            Some(_) => position += 1,
+            None => return Err(err_msg("edit starts past the end of its block")),
+        }
+        del.place(&DelSkip(1));
+        index += 1;
+    }
+    let prefix = index;
+
+    let mut remaining = deleted;
+    while remaining > 0 {
+        match units.get(index) {
+            Some(None) => del.place(&DelChars(1)),
+            Some(Some((true, _))) => {
+                del.place(&DelSkip(1));
+                index += 1;
+                continue;
+            }
+            Some(Some((false, true))) => del.place(&DelGroup(vec![])),
+            Some(Some((false, false))) => return Err(err_msg("inline objects with contents can't be deleted as text")),
+            None => return Err(err_msg("edit deletes past the end of its block")),
+        }
+        remaining -= 1;
+        index += 1;
+    }
+
+    let mut add = vec![];
+    if prefix > 0 {
+        add.place(&AddSkip(prefix));
+    }
+    if !inserted.is_empty() {
+        add.place(&AddChars(DocString::from_str_styled(
+            inserted,
+            style_map! { Style::Normie => None },
+        )));
+    }
+    Ok((del, add))
+}
+
+// The edits of each block changed, by the index of the block.
+type BlockEdits = HashMap<usize, (usize, usize, String)>;
+
+fn build_span(span: &DocSpan, edits: &BlockEdits, block: &mut usize) -> Result<Op, Error> {
+    let mut del = vec![];
+    let mut add = vec![];
+    for elem in span {
+        let (attrs, inner) = match elem {
+            DocGroup(attrs, inner) => (attrs, inner),
+            DocChars(text) => {
+                del.place(&DelSkip(text.char_len()));
+                add.place(&AddSkip(text.char_len()));
+                continue;
+            }
+        };
+        let (inner_del, inner_add) = if is_block(attrs) {
+            let index = *block;
+            *block += 1;
+            match edits.get(&index) {
+                Some((start, deleted, inserted)) => block_edit(inner, *start, *deleted, inserted)?,
+                None => (vec![], vec![]),
+            }
+        } else if is_container(attrs) {
+            build_span(inner, edits, block)?
+        } else {
+            (vec![], vec![])
+        };
+        if inner_del.is_empty() && inner_add.is_empty() {
+            del.place(&DelSkip(1));
+            add.place(&AddSkip(1));
+        } else {
+            del.place(&DelWithGroup(inner_del));
+            add.place(&AddWithGroup(inner_add));
+        }
+    }
+    Ok((del, add))
+}
+
+// The operation that changes the text of each block of `doc` into `blocks`.
+fn blocks_to_op(doc: &Doc, blocks: &[String]) -> Result<Op, Error> {
+    let before = doc_to_json0(&doc.0);
+    if before.len() != blocks.len() {
+        return Err(err_msg("blocks can't be added or removed as text"));
+    }
+    let edits = before
+        .iter()
+        .zip(blocks)
+        .enumerate()
+        .filter_map(|(i, (before, after))| text_change(before, after).map(|change| (i, change)))
+        .collect::<BlockEdits>();
+    Ok(normalize(build_span(&doc.0, &edits, &mut 0)?))
+}
+
+// The text of each block after an operation, if it only edits text.
+fn op_blocks(doc: &Doc, op: &Op) -> Result<(Vec<String>, Vec<String>), Error> {
+    let before = doc_to_json0(&doc.0);
+    let after = doc_to_json0(&Op::apply(doc, op).0);
+    if before.len() != after.len() {
+        return Err(err_msg("operations adding or removing blocks have no text equivalent"));
+    }
+    Ok((before, after))
+}
+
+/// The json0 components equivalent to an operation on a document.
+pub fn op_to_json0(doc: &Doc, op: &Op) -> Result<Vec<Json0Component>, Error> {
+    let (before, after) = op_blocks(doc, op)?;
+    let mut components = vec![];
+    for (i, (before, after)) in before.iter().zip(&after).enumerate() {
+        if let Some((start, deleted, inserted)) = text_change(before, after) {
+            let chars = before.chars().collect::<Vec<_>>();
+            let offset = utf16_len(&chars[..start].iter().collect::<String>());
+            if deleted > 0 {
+                components.push(Json0Component {
+                    p: vec![i, offset],
+                    si: None,
+                    sd: Some(chars[start..start + deleted].iter().collect()),
+                });
+            }
+            if !inserted.is_empty() {
+                components.push(Json0Component {
+                    p: vec![i, offset],
+                    si: Some(inserted),
+                    sd: None,
+                });
+            }
+        }
+    }
+    Ok(components)
+}
+
+/// The operation on a document equivalent to json0 components.
+pub fn json0_to_op(doc: &Doc, components: &[Json0Component]) -> Result<Op, Error> {
+    let mut blocks = doc_to_json0(&doc.0);
+    for component in components {
+        let (block, offset) = match component.p.as_slice() {
+            [block, offset] => (*block, *offset),
+            _ => return Err(err_msg("json0 paths must be [block, offset]")),
+        };
+        let text = blocks
+            .get_mut(block)
+            .ok_or_else(|| err_msg(format!("no block at {}", block)))?;
+        let index = char_index(text, offset)?;
+        let mut chars = text.chars().collect::<Vec<_>>();
+        if let Some(ref deleted) = component.sd {
+            let count = deleted.chars().count();
+            if index + count > chars.len() || chars[index..index + count].iter().collect::<String>() != *deleted {
+                return Err(err_msg("json0 deletion doesn't match the document"));
+            }
+            chars.drain(index..index + count);
+        }
+        if let Some(ref inserted) = component.si {
+            let tail = chars.split_off(index);
+            chars.extend(inserted.chars());
+            chars.extend(tail);
+        }
+        *text = chars.into_iter().collect();
+    }
+    blocks_to_op(doc, &blocks)
+}
+
+/// The text operation equivalent to an operation on a document.
+pub fn op_to_text_op(doc: &Doc, op: &Op) -> Result<TextOp, Error> {
+    let (before, after) = op_blocks(doc, op)?;
+    let (before, after) = (before.join("\n"), after.join("\n"));
+    let mut text_op = vec![];
+    if let Some((start, deleted, inserted)) = text_change(&before, &after) {
+        let chars = before.chars().collect::<Vec<_>>();
+        let skip = utf16_len(&chars[..start].iter().collect::<String>());
+        if skip > 0 {
+            text_op.push(TextComponent::Skip(skip));
+        }
+        if deleted > 0 {
+            text_op.push(TextComponent::Delete {
+                d: utf16_len(&chars[start..start + deleted].iter().collect::<String>()),
+            });
+        }
+        if !inserted.is_empty() {
+            text_op.push(TextComponent::Insert(inserted));
+        }
+    }
+    Ok(text_op)
+}
+
+/// The operation on a document equivalent to a text operation.
+pub fn text_op_to_op(doc: &Doc, text_op: &TextOp) -> Result<Op, Error> {
+    let before = doc_to_text(&doc.0)?.encode_utf16().collect::<Vec<_>>();
+    let mut after = vec![];
+    let mut index = 0;
+    for component in text_op {
+        match component {
+            TextComponent::Skip(count) => {
+                let end = index + count;
+                if end > before.len() {
+                    return Err(err_msg("text operation skips past the end of the document"));
+                }
+                after.extend_from_slice(&before[index..end]);
+                index = end;
+            }
+            TextComponent::Insert(text) => after.extend(text.encode_utf16()),
+            TextComponent::Delete { d } => {
+                if index + d > before.len() {
+                    return Err(err_msg("text operation deletes past the end of the document"));
+                }
+                index += d;
+            }
+        }
+    }
+    after.extend_from_slice(&before[index..]);
+    let after = String::from_utf16(&after)?;
+    let blocks = after.split('\n').map(|x| x.to_string()).collect::<Vec<_>>();
+    blocks_to_op(doc, &blocks)
+}
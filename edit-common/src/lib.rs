@@ -1,8 +1,12 @@
 #![feature(crate_in_paths)]
 
+extern crate base64;
+extern crate chacha20_poly1305_aead;
 extern crate failure;
+extern crate hmac;
 #[macro_use]
 extern crate maplit;
+#[macro_use]
 extern crate oatie;
 extern crate rand;
 extern crate regex;
@@ -15,17 +19,50 @@ extern crate htmlescape;
 extern crate pulldown_cmark;
 extern crate pulldown_cmark_to_cmark;
 extern crate ron;
+#[macro_use]
 extern crate serde_json;
+extern crate sha2;
 extern crate take_mut;
 #[cfg(not(target_arch = "wasm32"))]
+extern crate url;
+#[cfg(not(target_arch = "wasm32"))]
 extern crate ws;
 
+pub mod attachment;
+pub mod block_ids;
+pub mod blocks;
 pub mod commands;
+pub mod comments;
+pub mod crdt;
+pub mod doclink;
+pub mod e2ee;
+pub mod embed;
+pub mod find;
+pub mod font;
+pub mod highlight;
+pub mod identity;
+pub mod json0;
+pub mod link;
+pub mod lint;
 pub mod markdown;
+pub mod mention;
+pub mod outline;
+pub mod pagination;
+pub mod pretty;
+pub mod prosemirror;
+pub mod protocol;
+pub mod protocol_schema;
+pub mod quill;
+pub mod signing;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod simple_ws;
+pub mod title;
+pub mod yjs;
 
-use htmlescape::encode_minimal;
+use htmlescape::{
+    encode_attribute,
+    encode_minimal,
+};
 use oatie::doc::*;
 use std::collections::{
     HashMap,
@@ -41,6 +78,18 @@ fn is_caret(attrs: &Attrs, client_id: Option<&str>) -> bool {
     // && attrs.get("focus").unwrap_or(&"false".to_string()).parse::<bool>().map(|x| x == focus).unwrap_or(false)
 }
 
+// An HTML attribute value, quoted and escaped so it can't end the attribute.
+fn attr_value(value: &str) -> String {
+    format!("\"{}\"", encode_attribute(value))
+}
+
+/// Whether `url` can be loaded from a page: a file uploaded to the server,
+/// or an http(s) URL. Others, such as javascript: URLs, are left out.
+pub fn is_safe_url(url: &str) -> bool {
+    let url = url.trim();
+    url.starts_with("/$/assets/") || url.starts_with("https://") || url.starts_with("http://")
+}
+
 // TODO move this to a different module
 /// Converts a DocSpan to an HTML string.
 pub fn doc_as_html(doc: &DocSpan) -> String {
@@ -84,19 +133,36 @@ pub fn doc_as_html_inner(
     for elem in doc {
         match elem {
             &DocGroup(ref attrs, ref span) => {
+                let attr = |key: &str| attr_value(attrs.get(key).map(|x| x.as_str()).unwrap_or(""));
+
+                // Blocks with ids can be linked to, as from the outline.
+                let id = attrs
+                    .get("id")
+                    .map(|id| format!("id={}", attr_value(id)))
+                    .unwrap_or_default();
                 out.push_str(&format!(
                     r#"<div
+                        {}
                         data-tag={}
                         data-client={}
                         data-anchor={}
                         data-focus={}
+                        data-latex={}
+                        data-render={}
+                        data-lang={}
+                        data-align={}
                         class={}
                     >"#,
-                    serde_json::to_string(attrs.get("tag").unwrap_or(&"".to_string())).unwrap(),
-                    serde_json::to_string(attrs.get("client").unwrap_or(&"".to_string())).unwrap(),
-                    serde_json::to_string(attrs.get("anchor").unwrap_or(&"".to_string())).unwrap(),
-                    serde_json::to_string(attrs.get("focus").unwrap_or(&"".to_string())).unwrap(),
-                    serde_json::to_string(attrs.get("class").unwrap_or(&"".to_string())).unwrap(),
+                    id,
+                    attr("tag"),
+                    attr("client"),
+                    attr("anchor"),
+                    attr("focus"),
+                    attr("latex"),
+                    attr("render"),
+                    attr("lang"),
+                    attr("align"),
+                    attr("class"),
                 ));
 
                 if attrs.get("tag") == Some(&"caret".to_string()) {
@@ -110,6 +176,58 @@ pub fn doc_as_html_inner(
                     }
                 }
 
+                // Embeds show their page in an iframe.
+                if attrs.get("tag").map(|tag| tag == "embed").unwrap_or(false) {
+                    if let Some(src) = attrs.get("url").and_then(|url| embed::embed_src(url)) {
+                        out.push_str(&format!(
+                            r#"<iframe src={} sandbox="{}" {}></iframe>"#,
+                            attr_value(&src),
+                            embed::embed_sandbox(&src),
+                            r#"frameborder="0" allowfullscreen"#,
+                        ));
+                    }
+                }
+
+                // Attachments link to their file, to be downloaded under its
+                // original name.
+                if attrs.get("tag").map(|tag| tag == "attachment").unwrap_or(false) {
+                    let (url, filename, size) = attachment::attachment_info(attrs);
+                    out.push_str(&format!(
+                        r#"<a href={} download={}>{}</a> <span class="attachment-size">{}</span>"#,
+                        attr_value(url),
+                        attr_value(filename),
+                        encode_minimal(filename),
+                        attachment::format_size(size),
+                    ));
+                }
+
+                // Document links start out labelled with their target's id,
+                // until the server tells us its title.
+                if let Some(page_id) = doclink::doc_link_target(attrs) {
+                    out.push_str(&format!(
+                        r#"<a href={} data-page={}>{}</a>"#,
+                        attr_value(&format!("/{}", page_id)),
+                        attr_value(page_id),
+                        encode_minimal(page_id),
+                    ));
+                }
+
+                if mention::mention_target(attrs).is_some() {
+                    out.push_str(&format!(
+                        r#"<span class="mention-name">@{}</span>"#,
+                        encode_minimal(mention::mention_name(attrs)),
+                    ));
+                }
+
+                if attrs.get("tag").map(|tag| tag == "img").unwrap_or(false) {
+                    let src = attrs.get("src").filter(|src| is_safe_url(src));
+                    out.push_str(&format!(
+                        r#"<img src={} alt={}>"#,
+                        attr_value(src.map(|x| x.as_str()).unwrap_or("")),
+                        attr("alt"),
+                    ));
+                }
+
                 out.push_str(&doc_as_html_inner(span, caret_index, remote_select_active));
                 out.push_str(r"</div>");
             }
@@ -121,8 +239,31 @@ pub fn doc_as_html_inner(
                         classes.push(Style::Selected);
                     }
 
+                    let css = font::font_css(
+                        styles.get(&Style::FontSize).and_then(|x| x.as_ref()).map(|x| x.as_str()),
+                        styles.get(&Style::FontFamily).and_then(|x| x.as_ref()).map(|x| x.as_str()),
+                    );
+
+                    // Links show their title on hover, and mark whether they
+                    // open in a new tab.
+                    let link_attrs = styles
+                        .get(&Style::Link)
+                        .and_then(|x| x.as_ref())
+                        .map(|value| {
+                            let link = link::Link::from_style_value(value);
+                            let mut attrs = String::new();
+                            if let Some(title) = link.title {
+                                attrs.push_str(&format!("title={} ", attr_value(&title)));
+                            }
+                            if link.new_tab {
+                                attrs.push_str(r#"data-link-target="_blank""#);
+                            }
+                            attrs
+                        })
+                        .unwrap_or_default();
+
                     out.push_str(&format!(
-                        r#"<span class="{}" {}>"#,
+                        r#"<span class="{}" {} {} style="{}">"#,
                         classes
                             .into_iter()
                             .map(|e| e.to_string())
@@ -131,13 +272,20 @@ pub fn doc_as_html_inner(
                         styles
                             .iter()
                             .filter(|(_, v)| v.is_some())
-                            .map(|(k, v)| format!(
-                                "data-style-{k}={v}",
-                                k = k,
-                                v = serde_json::to_string(&v).unwrap()
-                            ))
+                            .map(|(k, v)| {
+                                // Links carry just their URL, to be followed.
+                                let v = v.as_ref().map(|v| v.as_str()).unwrap_or("");
+                                let v = if *k == Style::Link {
+                                    link::link_href(v)
+                                } else {
+                                    v.to_string()
+                                };
+                                format!("data-style-{k}={v}", k = k, v = attr_value(&v))
+                            })
                             .collect::<Vec<String>>()
                             .join(" "),
+                        link_attrs,
+                        css,
                     ));
                 } else {
                     out.push_str(r"<span>");
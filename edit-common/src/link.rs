@@ -0,0 +1,48 @@
+//! Links, as carried by the value of the Link style. A link with only a URL
+//! is stored as the URL itself, as links always were, and one with a title
+//! or that opens in a new tab is stored as JSON.
+
+use serde_json;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Link {
+    pub href: String,
+    #[serde(default)]
+    pub title: Option<String>,
+    // Whether following the link opens a new tab.
+    #[serde(default)]
+    pub new_tab: bool,
+}
+
+impl Link {
+    pub fn new(href: &str) -> Link {
+        Link {
+            href: href.to_string(),
+            ..Link::default()
+        }
+    }
+
+    /// Read a link from a Link style's value.
+    pub fn from_style_value(value: &str) -> Link {
+        if value.starts_with('{') {
+            if let Ok(link) = serde_json::from_str(value) {
+                return link;
+            }
+        }
+        Link::new(value)
+    }
+
+    /// The value of a Link style for this link.
+    pub fn to_style_value(&self) -> String {
+        if self.title.is_none() && !self.new_tab {
+            self.href.clone()
+        } else {
+            serde_json::to_string(self).unwrap()
+        }
+    }
+}
+
+/// The URL a Link style's value points to.
+pub fn link_href(value: &str) -> String {
+    Link::from_style_value(value).href
+}
@@ -0,0 +1,297 @@
+//! Readability checks for prose: very long sentences, the passive voice,
+//! and words repeated by mistake. Issues are ranges of characters in their
+//! block, shipped to the frontend like syntax highlighting. They're cached
+//! per top-level element, so an edit only checks the elements it touched
+//! again, and the issues of the rest follow the edit unchanged.
+
+use crate::blocks::map_top_level;
+use oatie::doc::*;
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub enum LintKind {
+    LongSentence,
+    PassiveVoice,
+    RepeatedWord,
+}
+
+// An issue, as a range of characters in its block.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct LintIssue {
+    pub start: usize,
+    pub end: usize,
+    pub kind: LintKind,
+    pub message: String,
+}
+
+// Issues of one block, which is identified by its position among the
+// document's prose blocks, and by its id if it has one.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct BlockLints {
+    pub block: usize,
+    pub id: Option<String>,
+    pub issues: Vec<LintIssue>,
+}
+
+// Which checks run.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct LintConfig {
+    // Sentences with more words than this are too long, or None to allow
+    // any length.
+    pub max_sentence_words: Option<usize>,
+    pub passive_voice: bool,
+    pub repeated_words: bool,
+}
+
+// Checks are off until the user turns them on.
+impl Default for LintConfig {
+    fn default() -> LintConfig {
+        LintConfig {
+            max_sentence_words: None,
+            passive_voice: false,
+            repeated_words: false,
+        }
+    }
+}
+
+impl LintConfig {
+    /// Whether any check runs.
+    pub fn is_enabled(&self) -> bool {
+        self.max_sentence_words.is_some() || self.passive_voice || self.repeated_words
+    }
+}
+
+const PROSE_TAGS: &[&str] = &["p", "h1", "h2", "h3", "h4", "h5", "h6"];
+
+const BE_VERBS: &[&str] = &["am", "are", "be", "been", "being", "is", "was", "were"];
+
+// Past participles which don't end in "-ed" or "-en".
+const IRREGULAR_PARTICIPLES: &[&str] = &[
+    "begun", "bought", "brought", "built", "caught", "done", "dealt", "felt", "found", "held",
+    "kept", "known", "laid", "led", "left", "lost", "made", "meant", "met", "paid", "put",
+    "read", "run", "said", "seen", "sent", "set", "shown", "sold", "sought", "spent", "taught",
+    "thought", "told", "understood", "won",
+];
+
+// Participles ending in "-ed" or "-en" are common adjectives and nouns too,
+// like "open" or "ten", so only longer words count.
+fn is_participle(word: &str) -> bool {
+    IRREGULAR_PARTICIPLES.contains(&word)
+        || (word.chars().count() > 4 && (word.ends_with("ed") || word.ends_with("en")))
+}
+
+// A word, as its range of characters and its lowercase text.
+struct Word {
+    start: usize,
+    end: usize,
+    text: String,
+}
+
+fn words(chars: &[char]) -> Vec<Word> {
+    let in_word = |c: char| c.is_alphanumeric() || c == '\'' || c == '\u{2019}';
+    let mut words = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        if !in_word(chars[i]) {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < chars.len() && in_word(chars[i]) {
+            i += 1;
+        }
+        words.push(Word {
+            start,
+            end: i,
+            text: chars[start..i].iter().collect::<String>().to_lowercase(),
+        });
+    }
+    words
+}
+
+/// Ranges of the sentences in `chars`, without surrounding whitespace.
+pub fn sentences(chars: &[char]) -> Vec<(usize, usize)> {
+    let mut sentences = vec![];
+    let mut start = 0;
+    for i in 0..chars.len() {
+        let ends = ".!?".contains(chars[i])
+            && chars.get(i + 1).map(|c| c.is_whitespace()).unwrap_or(true);
+        if ends || i + 1 == chars.len() {
+            sentences.push((start, i + 1));
+            start = i + 1;
+        }
+    }
+    sentences
+        .into_iter()
+        .filter_map(|(mut start, mut end)| {
+            while start < end && chars[start].is_whitespace() {
+                start += 1;
+            }
+            while end > start && chars[end - 1].is_whitespace() {
+                end -= 1;
+            }
+            if start < end {
+                Some((start, end))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Check the text of a block.
+pub fn lint(config: &LintConfig, text: &str) -> Vec<LintIssue> {
+    let chars = text.chars().collect::<Vec<_>>();
+    let words = words(&chars);
+    let mut issues = vec![];
+
+    if let Some(max) = config.max_sentence_words {
+        for (start, end) in sentences(&chars) {
+            let count = words
+                .iter()
+                .filter(|word| word.start >= start && word.end <= end)
+                .count();
+            if count > max {
+                issues.push(LintIssue {
+                    start,
+                    end,
+                    kind: LintKind::LongSentence,
+                    message: format!("This sentence has {} words. Consider splitting it.", count),
+                });
+            }
+        }
+    }
+
+    if config.passive_voice {
+        for (i, word) in words.iter().enumerate() {
+            if !BE_VERBS.contains(&word.text.as_str()) {
+                continue;
+            }
+            // Allow an adverb in between, as in "was quickly written".
+            let mut next = words.get(i + 1);
+            if next.map(|x| x.text.ends_with("ly")).unwrap_or(false) {
+                next = words.get(i + 2);
+            }
+            if let Some(participle) = next.filter(|x| is_participle(&x.text)) {
+                issues.push(LintIssue {
+                    start: word.start,
+                    end: participle.end,
+                    kind: LintKind::PassiveVoice,
+                    message: "This may be in the passive voice.".to_string(),
+                });
+            }
+        }
+    }
+
+    if config.repeated_words {
+        for pair in words.windows(2) {
+            // Only words separated by nothing but whitespace, so "that, that"
+            // is left alone.
+            let between = &chars[pair[0].end..pair[1].start];
+            if pair[0].text == pair[1].text && between.iter().all(|c| c.is_whitespace()) {
+                issues.push(LintIssue {
+                    start: pair[0].start,
+                    end: pair[1].end,
+                    kind: LintKind::RepeatedWord,
+                    message: format!("\"{}\" is repeated.", pair[1].text),
+                });
+            }
+        }
+    }
+
+    issues.sort_by_key(|issue| (issue.start, issue.end));
+    issues
+}
+
+fn block_text(span: &DocSpan, out: &mut String) {
+    for elem in span {
+        match elem {
+            DocChars(text) => out.push_str(text.as_str()),
+            DocGroup(_, span) => block_text(span, out),
+        }
+    }
+}
+
+// Id and issues of each prose block in `span`, in order.
+fn collect_prose(config: &LintConfig, span: &DocSpan, out: &mut Vec<(Option<String>, Vec<LintIssue>)>) {
+    for elem in span {
+        if let DocGroup(attrs, inner) = elem {
+            if PROSE_TAGS.contains(&attrs["tag"].as_str()) {
+                let mut text = String::new();
+                block_text(inner, &mut text);
+                out.push((attrs.get("id").cloned(), lint(config, &text)));
+            } else if attrs["tag"] != "pre" {
+                collect_prose(config, inner, out);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LintCache {
+    config: LintConfig,
+    // Prose blocks inside each top-level element, or None if the element
+    // changed and needs to be checked again.
+    elements: Vec<Option<Vec<(Option<String>, Vec<LintIssue>)>>>,
+}
+
+impl LintCache {
+    pub fn new() -> LintCache {
+        LintCache {
+            config: LintConfig::default(),
+            elements: vec![],
+        }
+    }
+
+    /// Change which checks run, and check the whole document again.
+    pub fn set_config(&mut self, config: LintConfig, doc: &Doc) {
+        self.config = config;
+        self.reset(doc);
+    }
+
+    /// Check the whole document again.
+    pub fn reset(&mut self, doc: &Doc) {
+        self.elements = doc.0.iter().map(|_| None).collect();
+    }
+
+    /// Update the cache after `op` was applied, producing `doc`.
+    pub fn apply(&mut self, doc: &Doc, op: &Op) {
+        match map_top_level(&self.elements, op) {
+            Some(elements) if elements.len() == doc.0.len() => {
+                self.elements = elements;
+            }
+            _ => self.reset(doc),
+        }
+    }
+
+    /// Issues of every prose block of `doc` that has any. `doc` must be the
+    /// document the cache was last updated with.
+    pub fn lints(&mut self, doc: &Doc) -> Vec<BlockLints> {
+        if !self.config.is_enabled() {
+            return vec![];
+        }
+        if self.elements.len() != doc.0.len() {
+            self.reset(doc);
+        }
+
+        let mut out = vec![];
+        let mut block = 0;
+        for (entry, elem) in self.elements.iter_mut().zip(&doc.0) {
+            if entry.is_none() {
+                let mut found = vec![];
+                collect_prose(&self.config, &vec![elem.clone()], &mut found);
+                *entry = Some(found);
+            }
+            for (id, issues) in entry.as_ref().unwrap() {
+                if !issues.is_empty() {
+                    out.push(BlockLints {
+                        block,
+                        id: id.clone(),
+                        issues: issues.clone(),
+                    });
+                }
+                block += 1;
+            }
+        }
+        out
+    }
+}
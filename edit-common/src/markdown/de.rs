@@ -1,4 +1,6 @@
+use crate::link::Link;
 use failure::Error;
+use htmlescape::decode_html;
 use oatie::doc::*;
 use oatie::writer::DocWriter;
 use pulldown_cmark::{
@@ -16,11 +18,80 @@ use pulldown_cmark::{
     Parser, Tag,
 };
 
+/// Fenced code info strings that give code blocks a render mode, flagging
+/// them for the frontend to render as diagrams.
+pub const RENDER_MODES: &[&str] = &["mermaid"];
+
 struct Ctx<'b, I> {
     iter: I,
     body: &'b mut DocWriter,
     styles: StyleMap,
     bare_text: bool,
+    in_code: bool,
+    // The alignment of the div blocks are read in, if any.
+    align: Option<String>,
+}
+
+// Splits text around its first `$...$` span, into the text before it, its
+// LaTeX source, and the text after. As in Pandoc, the source can't start or
+// end with whitespace.
+fn split_math(text: &str) -> Option<(&str, &str, &str)> {
+    let start = text.find('$')?;
+    let len = text[start + 1..].find('$')?;
+    let latex = &text[start + 1..start + 1 + len];
+    if latex.is_empty()
+        || latex.starts_with(char::is_whitespace)
+        || latex.ends_with(char::is_whitespace)
+    {
+        return None;
+    }
+    Some((&text[..start], latex, &text[start + 2 + len..]))
+}
+
+// The alignment of an alignment div's opening tag, as blocks are exported in.
+fn align_div(html: &str) -> Option<&str> {
+    let html = html.trim();
+    if html.starts_with("<div align=\"") && html.ends_with("\">") {
+        Some(&html["<div align=\"".len()..html.len() - "\">".len()])
+            .filter(|align| !align.contains('"'))
+    } else {
+        None
+    }
+}
+
+// The contents of `<tag>...</tag>` on a line of its own.
+fn html_element<'a>(line: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    if line.len() >= open.len() + close.len() && line.starts_with(&open) && line.ends_with(&close) {
+        Some(&line[open.len()..line.len() - close.len()]).filter(|inner| !inner.contains('<'))
+    } else {
+        None
+    }
+}
+
+// The terms and definitions of definition list HTML, as they're exported,
+// or None if the HTML is anything else.
+fn definitions(html: &str) -> Option<Vec<(&'static str, String)>> {
+    let mut blocks = vec![];
+    let mut empty = true;
+    for line in html.lines().map(|line| line.trim()).filter(|line| !line.is_empty()) {
+        empty = false;
+        if line == "<dl>" || line == "</dl>" {
+            continue;
+        }
+        let (tag, inner) = match (html_element(line, "dt"), html_element(line, "dd")) {
+            (Some(inner), _) => ("dt", inner),
+            (_, Some(inner)) => ("dd", inner),
+            _ => return None,
+        };
+        blocks.push((tag, decode_html(inner).ok()?));
+    }
+    if empty {
+        None
+    } else {
+        Some(blocks)
+    }
 }
 
 impl<'a, 'b, I: Iterator<Item = Event<'a>>> Ctx<'b, I> {
@@ -42,10 +113,7 @@ impl<'a, 'b, I: Iterator<Item = Event<'a>>> Ctx<'b, I> {
                     if self.bare_text {
                         self.body.begin();
                     }
-                    self.body.place(&DocChars(DocString::from_str_styled(
-                        text.as_ref(),
-                        self.styles.clone(),
-                    )));
+                    self.place_text(text.as_ref());
                     if self.bare_text {
                         self.body.close(hashmap! { "tag".into() => "p".into() });
                     }
@@ -73,10 +141,26 @@ impl<'a, 'b, I: Iterator<Item = Event<'a>>> Ctx<'b, I> {
                     )));
                 }
                 Html(html) => {
+                    if let Some(align) = align_div(&html) {
+                        self.align = Some(align.to_string());
+                        continue;
+                    }
+                    if html.trim() == "</div>" && self.align.is_some() {
+                        self.align = None;
+                        continue;
+                    }
+                    if let Some(blocks) = definitions(&html) {
+                        for (tag, text) in blocks {
+                            self.body.begin();
+                            self.place_chars(&text);
+                            self.body.close(hashmap! { "tag".into() => tag.into() });
+                        }
+                        continue;
+                    }
                     self.body.begin();
                     self.body.place(&DocChars(DocString::from_str_styled(
                         &html,
-                        hashmap!{ Style::Normie => None },
+                        style_map! { Style::Normie => None },
                     )));
                     self.body.close(hashmap! { "tag".into() => "html".into() });
                 }
@@ -86,6 +170,41 @@ impl<'a, 'b, I: Iterator<Item = Event<'a>>> Ctx<'b, I> {
         }
     }
 
+    // Place text, turning `$...$` spans outside of code into math elements.
+    fn place_text(&mut self, text: &str) {
+        let mut rest = text;
+        if !self.in_code {
+            while let Some((before, latex, after)) = split_math(rest) {
+                self.place_chars(before);
+                self.body.begin();
+                self.body.close(hashmap! {
+                    "tag".into() => "math".into(),
+                    "latex".into() => latex.into(),
+                });
+                rest = after;
+            }
+        }
+        self.place_chars(rest);
+    }
+
+    fn place_chars(&mut self, text: &str) {
+        if !text.is_empty() {
+            self.body.place(&DocChars(DocString::from_str_styled(
+                text,
+                self.styles.clone(),
+            )));
+        }
+    }
+
+    // Attributes of a block, aligned if it's in an alignment div.
+    fn block_attrs(&self, tag: String) -> Attrs {
+        let mut attrs = hashmap! { "tag".into() => tag };
+        if let Some(ref align) = self.align {
+            attrs.insert("align".into(), align.clone());
+        }
+        attrs
+    }
+
     fn start_tag(&mut self, tag: Tag<'a>) {
         match tag {
             // Blocks
@@ -100,6 +219,7 @@ impl<'a, 'b, I: Iterator<Item = Event<'a>>> Ctx<'b, I> {
             Tag::CodeBlock(_info) => {
                 self.body.begin();
                 self.bare_text = false;
+                self.in_code = true;
             }
 
             // List items
@@ -113,9 +233,33 @@ impl<'a, 'b, I: Iterator<Item = Event<'a>>> Ctx<'b, I> {
                 self.body.begin();
             }
 
+            // Inline objects. The image's content is its alt text, which we
+            // keep as an attribute.
+            Tag::Image(src, _title) => {
+                let mut alt = String::new();
+                while let Some(event) = self.iter.next() {
+                    match event {
+                        Text(text) => alt.push_str(&text),
+                        End(Tag::Image(..)) => break,
+                        _ => {}
+                    }
+                }
+                self.body.begin();
+                self.body.close(hashmap! {
+                    "tag".into() => "img".into(),
+                    "src".into() => src.to_string(),
+                    "alt".into() => alt,
+                });
+            }
+
             // Spans
-            Tag::Link(dest, _title) => {
-                self.styles.insert(Style::Link, Some(dest.to_string()));
+            Tag::Link(dest, title) => {
+                let link = Link {
+                    href: dest.to_string(),
+                    title: Some(title.to_string()).filter(|title| !title.is_empty()),
+                    new_tab: false,
+                };
+                self.styles.insert(Style::Link, Some(link.to_style_value()));
             }
             Tag::Strong => {
                 self.styles.insert(Style::Bold, None);
@@ -123,15 +267,16 @@ impl<'a, 'b, I: Iterator<Item = Event<'a>>> Ctx<'b, I> {
             Tag::Emphasis => {
                 self.styles.insert(Style::Italic, None);
             }
+            Tag::Code => {
+                self.in_code = true;
+            }
 
             Tag::Table(..)
             | Tag::TableHead
             | Tag::TableRow
             | Tag::TableCell
             | Tag::BlockQuote
-            | Tag::Code
             | Tag::List(_)
-            | Tag::Image(..)
             | Tag::FootnoteDefinition(_) => {}
         }
     }
@@ -140,17 +285,26 @@ impl<'a, 'b, I: Iterator<Item = Event<'a>>> Ctx<'b, I> {
         match tag {
             // Blocks
             Tag::Paragraph => {
-                self.body.close(hashmap! { "tag".into() => "p".into() });
+                let attrs = self.block_attrs("p".into());
+                self.body.close(attrs);
                 self.bare_text = true;
             }
             Tag::Header(level) => {
-                let tag = format!("h{}", level);
-                self.body.close(hashmap! { "tag".into() => tag });
+                let attrs = self.block_attrs(format!("h{}", level));
+                self.body.close(attrs);
                 self.bare_text = true;
             }
-            Tag::CodeBlock(_) => {
-                self.body.close(hashmap! { "tag".into() => "pre".into() });
+            Tag::CodeBlock(info) => {
+                let mut attrs = self.block_attrs("pre".into());
+                let info = info.trim();
+                if RENDER_MODES.contains(&info) {
+                    attrs.insert("render".into(), info.to_string());
+                } else if let Some(lang) = info.split_whitespace().next() {
+                    attrs.insert("lang".into(), lang.to_string());
+                }
+                self.body.close(attrs);
                 self.bare_text = true;
+                self.in_code = false;
             }
 
             // List items
@@ -176,9 +330,11 @@ impl<'a, 'b, I: Iterator<Item = Event<'a>>> Ctx<'b, I> {
             Tag::Emphasis => {
                 self.styles.remove(&Style::Italic);
             }
+            Tag::Code => {
+                self.in_code = false;
+            }
 
             Tag::FootnoteDefinition(_)
-            | Tag::Code
             | Tag::TableCell
             | Tag::Table(_)
             | Tag::TableHead
@@ -196,8 +352,10 @@ pub fn markdown_to_doc(input: &str) -> Result<DocSpan, Error> {
         let mut ctx = Ctx {
             iter: parser,
             body: &mut doc_writer,
-            styles: hashmap!{ Style::Normie => None },
+            styles: style_map! { Style::Normie => None },
             bare_text: true,
+            in_code: false,
+            align: None,
         };
         ctx.run();
     }
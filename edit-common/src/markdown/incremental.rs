@@ -0,0 +1,80 @@
+//! Markdown export that caches the output of each top-level element, and
+//! only re-renders the elements an operation touched.
+
+use super::ser::doc_to_markdown;
+use crate::blocks::map_top_level;
+use failure::Error;
+use oatie::doc::*;
+
+fn is_bullet(elem: &DocElement) -> bool {
+    match elem {
+        DocGroup(attrs, _) => attrs.get("tag").map(|tag| tag == "bullet").unwrap_or(false),
+        _ => false,
+    }
+}
+
+fn render_element(elem: &DocElement) -> Result<String, Error> {
+    doc_to_markdown(&vec![elem.clone()])
+}
+
+#[derive(Debug, Clone)]
+struct CachedElement {
+    bullet: bool,
+    markdown: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct MarkdownCache {
+    elements: Vec<Option<CachedElement>>,
+}
+
+impl MarkdownCache {
+    pub fn new() -> MarkdownCache {
+        MarkdownCache { elements: vec![] }
+    }
+
+    /// Re-render the whole document.
+    pub fn reset(&mut self, doc: &Doc) {
+        self.elements = doc.0.iter().map(|_| None).collect();
+    }
+
+    /// Update the cache after `op` was applied, producing `doc`.
+    pub fn apply(&mut self, doc: &Doc, op: &Op) {
+        match map_top_level(&self.elements, op) {
+            Some(elements) if elements.len() == doc.0.len() => {
+                self.elements = elements;
+            }
+            _ => self.reset(doc),
+        }
+    }
+
+    /// Export `doc`, rendering only elements not already cached. `doc` must
+    /// be the document the cache was last updated with.
+    pub fn markdown(&mut self, doc: &Doc) -> Result<String, Error> {
+        if self.elements.len() != doc.0.len() {
+            self.reset(doc);
+        }
+
+        let mut out = String::new();
+        let mut prev_bullet = None;
+        for (entry, elem) in self.elements.iter_mut().zip(&doc.0) {
+            if entry.is_none() {
+                *entry = Some(CachedElement {
+                    bullet: is_bullet(elem),
+                    markdown: render_element(elem)?,
+                });
+            }
+            let cached = entry.as_ref().unwrap();
+
+            // Consecutive bullets belong to the same list.
+            match prev_bullet {
+                Some(true) if cached.bullet => out.push_str("\n"),
+                Some(_) => out.push_str("\n\n"),
+                None => {}
+            }
+            out.push_str(&cached.markdown);
+            prev_bullet = Some(cached.bullet);
+        }
+        Ok(out)
+    }
+}
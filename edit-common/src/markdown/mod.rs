@@ -1,5 +1,7 @@
 pub mod de;
+pub mod incremental;
 pub mod ser;
 
 pub use self::de::markdown_to_doc;
+pub use self::incremental::MarkdownCache;
 pub use self::ser::doc_to_markdown;
@@ -1,4 +1,7 @@
+use crate::attachment::*;
+use crate::mention::mention_name;
 use failure::Error;
+use htmlescape::encode_minimal;
 use oatie::doc::*;
 use oatie::stepper::DocStepper;
 use pulldown_cmark::{
@@ -10,6 +13,8 @@ use pulldown_cmark_to_cmark::fmt::cmark;
 struct DocToMarkdown<'a> {
     doc_stepper: DocStepper,
     queue: Vec<Event<'a>>,
+    // Whether we're in a code block, whose text is exported as is.
+    in_code: bool,
 }
 
 impl<'a> DocToMarkdown<'a> {
@@ -17,6 +22,7 @@ impl<'a> DocToMarkdown<'a> {
         DocToMarkdown {
             doc_stepper: DocStepper::new(doc),
             queue: vec![],
+            in_code: false,
         }
     }
 }
@@ -37,7 +43,19 @@ impl<'a> Iterator for DocToMarkdown<'a> {
                         let level = attrs["tag"][1..].parse::<i32>().unwrap_or(1);
                         Event::Start(Tag::Header(level))
                     }
-                    "pre" => Event::Start(Tag::CodeBlock("".into())),
+                    // Render modes, like "mermaid", or else languages become
+                    // the info string of the fence.
+                    "pre" => {
+                        self.in_code = true;
+                        Event::Start(Tag::CodeBlock(
+                            attrs
+                                .get("render")
+                                .or_else(|| attrs.get("lang"))
+                                .cloned()
+                                .unwrap_or_default()
+                                .into(),
+                        ))
+                    }
                     "html" => {
                         let mut out = String::new();
                         for child in body {
@@ -51,6 +69,24 @@ impl<'a> Iterator for DocToMarkdown<'a> {
                         self.doc_stepper.next();
                         return Some(Event::Html(out.into()));
                     }
+                    // Markdown has no definition lists, so they export as
+                    // HTML, which renderers pass through.
+                    "dt" | "dd" => {
+                        let mut out = String::new();
+                        if !is_definition(self.doc_stepper.unhead()) {
+                            out.push_str("<dl>\n");
+                        }
+                        out.push_str(&format!(
+                            "<{tag}>{}</{tag}>\n",
+                            encode_minimal(&plain_text(body)),
+                            tag = attrs["tag"],
+                        ));
+                        self.doc_stepper.next();
+                        if !is_definition(self.doc_stepper.head()) {
+                            out.push_str("</dl>\n");
+                        }
+                        return Some(Event::Html(out.into()));
+                    }
                     "bullet" => {
                         if let Some(DocGroup(ref pre_attrs, _)) = self.doc_stepper.unhead() {
                             if pre_attrs["tag"] == "bullet" {
@@ -65,7 +101,57 @@ impl<'a> Iterator for DocToMarkdown<'a> {
                         self.doc_stepper.next();
                         return self.next();
                     }
+                    "math" => {
+                        let latex = attrs.get("latex").cloned().unwrap_or_default();
+                        self.doc_stepper.next();
+                        return Some(Event::Text(format!("${}$", latex).into()));
+                    }
+                    "img" => {
+                        let src = attrs.get("src").cloned().unwrap_or_default();
+                        let alt = attrs.get("alt").cloned().unwrap_or_default();
+                        self.doc_stepper.next();
+                        if !alt.is_empty() {
+                            self.queue.push(Event::Text(alt.into()));
+                        }
+                        self.queue.push(Event::End(Tag::Image("".into(), "".into())));
+                        return Some(Event::Start(Tag::Image(src.into(), "".into())));
+                    }
+                    // Mentions export as the name they're shown with.
+                    "mention" => {
+                        let name = mention_name(attrs).to_string();
+                        self.doc_stepper.next();
+                        return Some(Event::Text(format!("@{}", name).into()));
+                    }
+                    // Document links export as a link to the page's URL.
+                    "doclink" => {
+                        let page_id = attrs.get("page").cloned().unwrap_or_default();
+                        self.doc_stepper.next();
+                        self.queue.push(Event::Text(page_id.clone().into()));
+                        self.queue.push(Event::End(Tag::Link("".into(), "".into())));
+                        return Some(Event::Start(Tag::Link(format!("/{}", page_id).into(), "".into())));
+                    }
                     "hr" => Event::Start(Tag::Rule),
+                    // Attachments export as a paragraph linking to the file.
+                    "attachment" => {
+                        let (url, filename, size) = attachment_info(attrs);
+                        let label = format!("{} ({})", filename, format_size(size));
+                        self.queue.push(Event::Start(Tag::Link(url.to_string().into(), "".into())));
+                        self.queue.push(Event::Text(label.into()));
+                        self.queue.push(Event::End(Tag::Link("".into(), "".into())));
+                        self.queue.push(Event::End(Tag::Paragraph));
+                        self.doc_stepper.next();
+                        return Some(Event::Start(Tag::Paragraph));
+                    }
+                    // Embeds export as a paragraph holding a link.
+                    "embed" => {
+                        let url = attrs.get("url").cloned().unwrap_or_default();
+                        self.doc_stepper.next();
+                        self.queue.push(Event::Start(Tag::Link(url.clone().into(), "".into())));
+                        self.queue.push(Event::Text(url.into()));
+                        self.queue.push(Event::End(Tag::Link("".into(), "".into())));
+                        self.queue.push(Event::End(Tag::Paragraph));
+                        return Some(Event::Start(Tag::Paragraph));
+                    }
                     _ => {
                         eprintln!("Unexpected tag {:?}!", attrs["tag"]);
                         self.doc_stepper.next();
@@ -73,13 +159,25 @@ impl<'a> Iterator for DocToMarkdown<'a> {
                     }
                 });
                 self.doc_stepper.enter();
+                // Markdown has no alignment, so aligned blocks are wrapped in
+                // an HTML div, which most renderers honor.
+                if let Some(align) = block_alignment(attrs) {
+                    self.queue.insert(0, res.unwrap());
+                    return Some(Event::Html(format!("<div align=\"{}\">\n\n", align).into()));
+                }
                 res
             }
             Some(DocChars(ref text)) => {
                 self.doc_stepper.next();
 
+                // Dollar signs are escaped, so they aren't read back as math.
+                let mut text_str = text.to_string();
+                if !self.in_code {
+                    text_str = text_str.replace('$', "\\$");
+                }
+
                 // Styling.
-                let text_event = Event::Text(text.to_string().replace("\n", "  \n").into());
+                let text_event = Event::Text(text_str.replace("\n", "  \n").into());
                 if let Some(styles) = text.styles() {
                     if styles.contains_key(&Style::Bold) {
                         self.queue.push(text_event);
@@ -103,13 +201,14 @@ impl<'a> Iterator for DocToMarkdown<'a> {
                         _ => unreachable!(),
                     };
                     self.doc_stepper.exit();
-                    Some(match attrs["tag"].as_ref() {
+                    let res = Some(match attrs["tag"].as_ref() {
                         "p" => Event::End(Tag::Paragraph),
                         "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
                             let level = attrs["tag"][1..].parse::<i32>().unwrap_or(1);
                             Event::End(Tag::Header(level))
                         }
                         "pre" => {
+                            self.in_code = false;
                             self.queue.push(Event::End(Tag::CodeBlock("".into())));
                             Event::Text("\n".to_string().into())
                         }
@@ -123,13 +222,40 @@ impl<'a> Iterator for DocToMarkdown<'a> {
                         }
                         "hr" => Event::End(Tag::Rule),
                         _ => unimplemented!(),
-                    })
+                    });
+                    if block_alignment(&attrs).is_some() {
+                        self.queue.push(Event::Html("\n</div>\n\n".into()));
+                    }
+                    res
                 }
             }
         }
     }
 }
 
+fn is_definition(elem: Option<DocElement>) -> bool {
+    match elem {
+        Some(DocGroup(ref attrs, _)) => attrs["tag"] == "dt" || attrs["tag"] == "dd",
+        _ => false,
+    }
+}
+
+// The text of a block, without its styles or inline objects.
+fn plain_text(span: &DocSpan) -> String {
+    let mut out = String::new();
+    for elem in span {
+        if let DocChars(ref text) = *elem {
+            out.push_str(text.as_str());
+        }
+    }
+    out
+}
+
+// The alignment of a block, if it isn't the default.
+fn block_alignment(attrs: &Attrs) -> Option<String> {
+    attrs.get("align").filter(|align| *align != "left").cloned()
+}
+
 pub fn doc_to_markdown(doc: &DocSpan) -> Result<String, Error> {
     let to_mark = DocToMarkdown::new(&doc);
     let mut buf = String::new();
@@ -0,0 +1,65 @@
+//! Mentions of users, as inline objects holding the id of the user they
+//! mention and the name they had when mentioned. The sync server notifies
+//! users when a mention of them is added to a document.
+
+use crate::block_ids::block_id;
+use oatie::doc::*;
+use std::collections::BTreeSet;
+
+/// A notification that a user was mentioned.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Mention {
+    pub id: i32,
+    pub page_id: String,
+    // Id of the block the mention is in, to link to it.
+    pub anchor: Option<String>,
+    // Name of the user who added the mention.
+    pub author: String,
+    // Unix time in milliseconds.
+    pub created: i64,
+}
+
+fn is_mention(attrs: &Attrs) -> bool {
+    attrs.get("tag").map(|tag| tag == "mention").unwrap_or(false)
+}
+
+/// The user a mention refers to.
+pub fn mention_target(attrs: &Attrs) -> Option<&str> {
+    if is_mention(attrs) {
+        attrs.get("user").map(|x| x.as_str())
+    } else {
+        None
+    }
+}
+
+/// The name a mention is shown with.
+pub fn mention_name(attrs: &Attrs) -> &str {
+    attrs
+        .get("name")
+        .or_else(|| attrs.get("user"))
+        .map(|x| x.as_str())
+        .unwrap_or("")
+}
+
+fn collect_mentions(
+    span: &DocSpan,
+    anchor: Option<&str>,
+    out: &mut BTreeSet<(String, Option<String>)>,
+) {
+    for elem in span {
+        if let DocGroup(attrs, inner) = elem {
+            if let Some(user_id) = mention_target(attrs) {
+                out.insert((user_id.to_string(), anchor.map(|x| x.to_string())));
+            }
+            collect_mentions(inner, block_id(attrs).or(anchor), out);
+        }
+    }
+}
+
+/// Every user mentioned in the document, with the id of the block they're
+/// mentioned in.
+pub fn mentioned_users(span: &DocSpan) -> BTreeSet<(String, Option<String>)> {
+    let mut out = BTreeSet::new();
+    collect_mentions(span, None, &mut out);
+    out
+}
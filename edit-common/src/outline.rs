@@ -0,0 +1,167 @@
+//! The heading tree of a document, for rendering a table of contents.
+//! Headings are cached per top-level element, so applying an operation only
+//! rescans the elements it touched.
+
+use crate::block_ids::block_id;
+use crate::blocks::map_top_level;
+use oatie::doc::*;
+use std::collections::HashMap;
+
+// Level, text, and block id of a heading.
+type Heading = (usize, String, Option<String>);
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct OutlineEntry {
+    pub text: String,
+    // Heading level, 1 through 6.
+    pub level: usize,
+    // Fragment id for linking to the heading: its block id, or failing that
+    // a slug of its text unique within the document.
+    pub anchor: String,
+}
+
+fn heading_level(attrs: &Attrs) -> Option<usize> {
+    let tag = attrs.get("tag")?;
+    if tag.len() == 2 && tag.starts_with('h') {
+        tag[1..].parse::<usize>().ok().filter(|level| *level >= 1 && *level <= 6)
+    } else {
+        None
+    }
+}
+
+fn block_text(span: &DocSpan, out: &mut String) {
+    for elem in span {
+        match elem {
+            DocChars(text) => out.push_str(text.as_str()),
+            DocGroup(_, span) => block_text(span, out),
+        }
+    }
+}
+
+fn collect_headings(span: &DocSpan, out: &mut Vec<Heading>) {
+    for elem in span {
+        if let DocGroup(attrs, inner) = elem {
+            if let Some(level) = heading_level(attrs) {
+                let mut text = String::new();
+                block_text(inner, &mut text);
+                out.push((
+                    level,
+                    text.split_whitespace().collect::<Vec<_>>().join(" "),
+                    block_id(attrs).map(|id| id.to_string()),
+                ));
+            } else {
+                collect_headings(inner, out);
+            }
+        }
+    }
+}
+
+/// Lowercase alphanumerics joined by dashes, as in "Getting started" to
+/// "getting-started". Falls back to "section" for headings without any.
+pub fn slugify(text: &str) -> String {
+    let slug = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>()
+        .join("-");
+    if slug.is_empty() {
+        "section".to_string()
+    } else {
+        slug
+    }
+}
+
+// Headings without block ids number repeated slugs in document order:
+// "intro", "intro-1", "intro-2".
+fn assign_anchors(headings: Vec<Heading>) -> Vec<OutlineEntry> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    headings
+        .into_iter()
+        .map(|(level, text, id)| {
+            let anchor = id.unwrap_or_else(|| {
+                let slug = slugify(&text);
+                let count = seen.entry(slug.clone()).or_insert(0);
+                let anchor = if *count == 0 {
+                    slug
+                } else {
+                    format!("{}-{}", slug, count)
+                };
+                *count += 1;
+                anchor
+            });
+            OutlineEntry {
+                text,
+                level,
+                anchor,
+            }
+        })
+        .collect()
+}
+
+/// The headings of a document in order, with their anchors.
+pub fn doc_outline(doc: &DocSpan) -> Vec<OutlineEntry> {
+    let mut headings = vec![];
+    collect_headings(doc, &mut headings);
+    assign_anchors(headings)
+}
+
+/// The outline of a document, with the index of the top-level element
+/// holding each heading.
+pub fn outline_positions(doc: &DocSpan) -> Vec<(usize, OutlineEntry)> {
+    let mut headings = vec![];
+    let mut indices = vec![];
+    for (index, elem) in doc.iter().enumerate() {
+        let count = headings.len();
+        collect_headings(&vec![elem.clone()], &mut headings);
+        indices.extend((count..headings.len()).map(|_| index));
+    }
+    indices.into_iter().zip(assign_anchors(headings)).collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct OutlineCache {
+    // Headings inside each top-level element, or None if the element
+    // changed and needs to be rescanned.
+    elements: Vec<Option<Vec<Heading>>>,
+}
+
+impl OutlineCache {
+    pub fn new() -> OutlineCache {
+        OutlineCache { elements: vec![] }
+    }
+
+    /// Rescan the whole document.
+    pub fn reset(&mut self, doc: &Doc) {
+        self.elements = doc.0.iter().map(|_| None).collect();
+    }
+
+    /// Update the cache after `op` was applied, producing `doc`.
+    pub fn apply(&mut self, doc: &Doc, op: &Op) {
+        match map_top_level(&self.elements, op) {
+            Some(elements) if elements.len() == doc.0.len() => {
+                self.elements = elements;
+            }
+            _ => self.reset(doc),
+        }
+    }
+
+    /// The outline of `doc`, scanning only elements not already cached.
+    /// `doc` must be the document the cache was last updated with.
+    pub fn outline(&mut self, doc: &Doc) -> Vec<OutlineEntry> {
+        if self.elements.len() != doc.0.len() {
+            self.reset(doc);
+        }
+
+        let mut headings = vec![];
+        for (entry, elem) in self.elements.iter_mut().zip(&doc.0) {
+            if entry.is_none() {
+                let mut found = vec![];
+                collect_headings(&vec![elem.clone()], &mut found);
+                *entry = Some(found);
+            }
+            headings.extend(entry.as_ref().unwrap().iter().cloned());
+        }
+        assign_anchors(headings)
+    }
+}
@@ -0,0 +1,313 @@
+//! Page breaks for printing. Blocks are laid out on pages of a fixed number
+//! of lines, measured roughly from their text: a block takes as many lines
+//! as its words wrap to, with headings set larger, and a blank line after.
+//! Breaks fall between blocks, and a block taller than a page runs on into
+//! the next. The editor and the PDF export place breaks from the same
+//! layout, so exported pages match the page indicators shown while editing.
+//!
+//! Block heights are cached per top-level element, like syntax highlighting.
+
+use crate::blocks::map_top_level;
+use oatie::doc::*;
+use oatie::schema::{
+    RtfSchema,
+    RtfTrack,
+};
+use oatie::Schema;
+
+// Class of blocks that start a page, when rendered.
+pub const PAGE_BREAK_CLASS: &str = "page-break";
+
+/// Lines a block takes on the page besides its text.
+pub const BLOCK_SPACING: usize = 1;
+/// Characters of indentation of each level of lists and quotes.
+pub const INDENT_CHARS: usize = 4;
+// Lines taken by embedded pages.
+const EMBED_LINES: usize = 12;
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct PageLayout {
+    // Lines of body text that fit on a page.
+    pub lines_per_page: usize,
+    // Characters of body text that fit on a line.
+    pub chars_per_line: usize,
+}
+
+// A Letter or A4 page with 12pt text and an inch of margin.
+impl Default for PageLayout {
+    fn default() -> PageLayout {
+        PageLayout {
+            lines_per_page: 46,
+            chars_per_line: 80,
+        }
+    }
+}
+
+// A page break before a block, which is identified by its position among
+// the document's blocks, and by its id if it has one.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct PageBreak {
+    // The page the block starts, counting from 1.
+    pub page: usize,
+    pub block: usize,
+    pub id: Option<String>,
+}
+
+fn block_text(span: &DocSpan, out: &mut String) {
+    for elem in span {
+        match elem {
+            DocChars(text) => out.push_str(text.as_str()),
+            DocGroup(_, span) => block_text(span, out),
+        }
+    }
+}
+
+/// Wrap text to lines of at most `width` characters, breaking between words
+/// where possible. There's always at least one line.
+pub fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = vec![];
+    let mut line = String::new();
+    let mut line_len = 0;
+    for word in text.split_whitespace() {
+        let mut word = word.chars().collect::<Vec<_>>();
+        if line_len > 0 && line_len + 1 + word.len() > width {
+            lines.push(line);
+            line = String::new();
+            line_len = 0;
+        }
+        // Words longer than a line are broken up.
+        while word.len() > width {
+            lines.push(word.drain(..width).collect());
+        }
+        if line_len > 0 {
+            line.push(' ');
+            line_len += 1;
+        }
+        line.extend(&word);
+        line_len += word.len();
+    }
+    if line_len > 0 || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+// A block as it's set on the page.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockLayout {
+    pub id: Option<String>,
+    pub tag: String,
+    // Levels of lists and quotes the block is inside.
+    pub indent: usize,
+    // Size the block is set at, as a percent of body text.
+    pub scale: usize,
+    // Lines of text the block wraps to.
+    pub lines: Vec<String>,
+}
+
+impl BlockLayout {
+    /// Lines of body text the block takes on the page, with the space
+    /// after it.
+    pub fn height(&self) -> usize {
+        (self.lines.len() * self.scale + 99) / 100 + BLOCK_SPACING
+    }
+}
+
+// Set a block in the characters that fit on its lines.
+fn set_block(attrs: &Attrs, span: &DocSpan, indent: usize, layout: &PageLayout) -> BlockLayout {
+    let width = layout
+        .chars_per_line
+        .saturating_sub(indent * INDENT_CHARS)
+        .max(1);
+    let mut text = String::new();
+    block_text(span, &mut text);
+
+    // Headings are set larger, so fewer characters fit on their lines, and
+    // each line is taller.
+    let tag = attrs["tag"].clone();
+    let scale = match tag.as_str() {
+        "h1" => 200,
+        "h2" => 150,
+        "h3" => 125,
+        _ => 100,
+    };
+    let lines = match tag.as_str() {
+        // Code keeps its lines, and long ones are broken anywhere.
+        "pre" => text
+            .split('\n')
+            .flat_map(|line| {
+                let chars = line.chars().collect::<Vec<_>>();
+                if chars.is_empty() {
+                    vec![String::new()]
+                } else {
+                    chars.chunks(width).map(|x| x.iter().collect()).collect()
+                }
+            })
+            .collect(),
+        "embed" => vec![String::new(); EMBED_LINES],
+        "hr" => vec![String::new()],
+        _ => wrap_text(&text, width * 100 / scale),
+    };
+    BlockLayout {
+        id: attrs.get("id").cloned(),
+        tag,
+        indent,
+        scale,
+        lines,
+    }
+}
+
+// Each block in `span`, in order.
+fn collect_blocks(layout: &PageLayout, span: &DocSpan, indent: usize, out: &mut Vec<BlockLayout>) {
+    for elem in span {
+        if let DocGroup(attrs, inner) = elem {
+            match RtfSchema::track_type_from_attrs(attrs) {
+                Some(RtfTrack::Blocks) | Some(RtfTrack::BlockObjects) => {
+                    out.push(set_block(attrs, inner, indent, layout));
+                }
+                Some(RtfTrack::ListItems) | Some(RtfTrack::BlockQuotes) => {
+                    collect_blocks(layout, inner, indent + 1, out);
+                }
+                _ => collect_blocks(layout, inner, indent, out),
+            }
+        }
+    }
+}
+
+/// Place blocks of the given ids and heights in lines on pages, returning
+/// where pages break.
+pub fn place_blocks(layout: &PageLayout, blocks: &[(Option<String>, usize)]) -> Vec<PageBreak> {
+    let page_lines = layout.lines_per_page.max(1);
+    let mut breaks = vec![];
+    let mut page = 1;
+    let mut used = 0;
+    for (block, (id, lines)) in blocks.iter().enumerate() {
+        if used > 0 && used + lines > page_lines {
+            page += 1;
+            used = 0;
+            breaks.push(PageBreak {
+                page,
+                block,
+                id: id.clone(),
+            });
+        }
+        used += lines;
+        // Blocks taller than a page run on into the next ones.
+        while used > page_lines {
+            page += 1;
+            used -= page_lines;
+        }
+    }
+    breaks
+}
+
+/// Every block of `doc` as it's set in a layout.
+pub fn layout_blocks(doc: &Doc, layout: &PageLayout) -> Vec<BlockLayout> {
+    let mut blocks = vec![];
+    collect_blocks(layout, &doc.0, 0, &mut blocks);
+    blocks
+}
+
+/// Where the pages of `doc` break in a layout.
+pub fn paginate(doc: &Doc, layout: &PageLayout) -> Vec<PageBreak> {
+    let blocks = layout_blocks(doc, layout)
+        .into_iter()
+        .map(|block| (block.id.clone(), block.height()))
+        .collect::<Vec<_>>();
+    place_blocks(layout, &blocks)
+}
+
+fn mark_span(span: &DocSpan, breaks: &[PageBreak], block: &mut usize) -> DocSpan {
+    span.iter()
+        .map(|elem| match elem {
+            DocGroup(attrs, inner) => match RtfSchema::track_type_from_attrs(attrs) {
+                Some(RtfTrack::Blocks) | Some(RtfTrack::BlockObjects) => {
+                    let mut attrs = attrs.clone();
+                    if breaks.iter().any(|x| x.block == *block) {
+                        let class = match attrs.get("class") {
+                            Some(class) => format!("{} {}", class, PAGE_BREAK_CLASS),
+                            None => PAGE_BREAK_CLASS.to_string(),
+                        };
+                        attrs.insert("class".to_string(), class);
+                    }
+                    *block += 1;
+                    DocGroup(attrs, inner.clone())
+                }
+                _ => DocGroup(attrs.clone(), mark_span(inner, breaks, block)),
+            },
+            DocChars(_) => elem.clone(),
+        })
+        .collect()
+}
+
+/// A copy of `doc` for rendering, with blocks that start a page marked by
+/// class.
+pub fn mark_page_breaks(doc: &Doc, breaks: &[PageBreak]) -> Doc {
+    if breaks.is_empty() {
+        return doc.clone();
+    }
+    Doc(mark_span(&doc.0, breaks, &mut 0))
+}
+
+#[derive(Debug, Clone)]
+pub struct PaginationCache {
+    // The layout, if the document is paginated.
+    layout: Option<PageLayout>,
+    // Blocks inside each top-level element, or None if the element changed
+    // and needs to be measured again.
+    elements: Vec<Option<Vec<(Option<String>, usize)>>>,
+}
+
+impl PaginationCache {
+    pub fn new() -> PaginationCache {
+        PaginationCache {
+            layout: None,
+            elements: vec![],
+        }
+    }
+
+    /// Paginate in a layout from now on, or stop with None.
+    pub fn set_layout(&mut self, layout: Option<PageLayout>, doc: &Doc) {
+        self.layout = layout;
+        self.reset(doc);
+    }
+
+    /// Measure the whole document again.
+    pub fn reset(&mut self, doc: &Doc) {
+        self.elements = doc.0.iter().map(|_| None).collect();
+    }
+
+    /// Update the cache after `op` was applied, producing `doc`.
+    pub fn apply(&mut self, doc: &Doc, op: &Op) {
+        match map_top_level(&self.elements, op) {
+            Some(elements) if elements.len() == doc.0.len() => {
+                self.elements = elements;
+            }
+            _ => self.reset(doc),
+        }
+    }
+
+    /// Where the pages of `doc` break, or nothing if it isn't paginated.
+    /// `doc` must be the document the cache was last updated with.
+    pub fn breaks(&mut self, doc: &Doc) -> Vec<PageBreak> {
+        let layout = match self.layout {
+            Some(ref layout) => layout.clone(),
+            None => return vec![],
+        };
+        if self.elements.len() != doc.0.len() {
+            self.reset(doc);
+        }
+
+        let mut blocks = vec![];
+        for (entry, elem) in self.elements.iter_mut().zip(&doc.0) {
+            if entry.is_none() {
+                let mut found = vec![];
+                collect_blocks(&layout, &vec![elem.clone()], 0, &mut found);
+                *entry = Some(found.into_iter().map(|x| (x.id.clone(), x.height())).collect());
+            }
+            blocks.extend(entry.as_ref().unwrap().iter().cloned());
+        }
+        place_blocks(&layout, &blocks)
+    }
+}
@@ -0,0 +1,76 @@
+//! Colored, indented rendering of documents for terminals, to read a
+//! document's structure at a glance: one line per group, with its
+//! attributes, text with its styles, and carets marked with their client.
+
+use colored::Colorize;
+use oatie::doc::*;
+
+const INDENT: &str = "  ";
+
+fn is_caret(attrs: &Attrs) -> bool {
+    attrs.get("tag").map(|tag| tag == "caret").unwrap_or(false)
+}
+
+fn format_attrs(attrs: &Attrs) -> String {
+    let mut attrs = attrs
+        .iter()
+        .filter(|(key, _)| *key != "tag")
+        .map(|(key, value)| format!(" {}={:?}", key, value))
+        .collect::<Vec<_>>();
+    attrs.sort();
+    attrs.concat()
+}
+
+fn format_styles(text: &DocString) -> String {
+    let styles = match text.styles() {
+        Some(styles) => styles,
+        None => return String::new(),
+    };
+    let mut styles = styles
+        .iter()
+        .filter(|(style, _)| **style != Style::Normie)
+        .map(|(style, value)| match value {
+            Some(value) => format!("{}={:?}", style, value),
+            None => style.to_string(),
+        })
+        .collect::<Vec<_>>();
+    if styles.is_empty() {
+        return String::new();
+    }
+    styles.sort();
+    format!(" [{}]", styles.join(", "))
+}
+
+fn write_span(out: &mut String, span: &DocSpan, depth: usize) {
+    for elem in span {
+        out.push_str(&INDENT.repeat(depth));
+        match elem {
+            DocChars(text) => {
+                out.push_str(&format!("{:?}", text.as_str()).green().to_string());
+                out.push_str(&format_styles(text).yellow().to_string());
+                out.push('\n');
+            }
+            DocGroup(attrs, _) if is_caret(attrs) => {
+                let client = attrs.get("client").map(|x| x.as_str()).unwrap_or("?");
+                let focus = attrs.get("focus").map(|x| x == "true").unwrap_or(false);
+                let marker = if focus { "| caret" } else { "| anchor" };
+                out.push_str(&format!("{} {}", marker, client).magenta().bold().to_string());
+                out.push('\n');
+            }
+            DocGroup(attrs, inner) => {
+                let tag = attrs.get("tag").map(|x| x.as_str()).unwrap_or("?");
+                out.push_str(&tag.cyan().bold().to_string());
+                out.push_str(&format_attrs(attrs).dimmed().to_string());
+                out.push('\n');
+                write_span(out, inner, depth + 1);
+            }
+        }
+    }
+}
+
+/// Render a document as an indented tree, colored for a terminal.
+pub fn pretty_doc(span: &DocSpan) -> String {
+    let mut out = String::new();
+    write_span(&mut out, span, 0);
+    out
+}
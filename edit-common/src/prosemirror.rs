@@ -0,0 +1,428 @@
+//! Conversion between documents and ProseMirror's document JSON, in the
+//! node types of prosemirror-schema-basic and prosemirror-schema-list:
+//! paragraphs, headings, code blocks, blockquotes, rules, bullet lists, and
+//! images, with the strong, em, and link marks.
+//!
+//! Lists are flat in documents, where an item's depth is how many bullets
+//! wrap it, and nested in ProseMirror, where a deeper list sits inside the
+//! item before it.
+
+use crate::attachment::attachment_info;
+use crate::link::Link;
+use crate::mention::mention_name;
+use failure::{
+    err_msg,
+    Error,
+};
+use oatie::doc::*;
+use serde_json::{
+    Map,
+    Value,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Node {
+    #[serde(rename = "type")]
+    pub node_type: String,
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub attrs: Map<String, Value>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub content: Vec<Node>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub marks: Vec<Mark>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Mark {
+    #[serde(rename = "type")]
+    pub mark_type: String,
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub attrs: Map<String, Value>,
+}
+
+impl Node {
+    fn new(node_type: &str, content: Vec<Node>) -> Node {
+        Node {
+            node_type: node_type.to_string(),
+            content,
+            ..Node::default()
+        }
+    }
+
+    fn text(text: &str, marks: Vec<Mark>) -> Node {
+        Node {
+            node_type: "text".to_string(),
+            text: Some(text.to_string()),
+            marks,
+            ..Node::default()
+        }
+    }
+
+    fn with_attr(mut self, key: &str, value: Value) -> Node {
+        self.attrs.insert(key.to_string(), value);
+        self
+    }
+
+    fn attr_str(&self, key: &str) -> Option<&str> {
+        self.attrs.get(key).and_then(|x| x.as_str())
+    }
+}
+
+fn mark(mark_type: &str) -> Mark {
+    Mark {
+        mark_type: mark_type.to_string(),
+        attrs: Map::new(),
+    }
+}
+
+fn plain_text(span: &DocSpan, out: &mut String) {
+    for elem in span {
+        match elem {
+            DocChars(text) => out.push_str(text.as_str()),
+            DocGroup(_, inner) => plain_text(inner, out),
+        }
+    }
+}
+
+fn text_marks(text: &DocString) -> Vec<Mark> {
+    let mut marks = vec![];
+    if let Some(styles) = text.styles() {
+        if styles.contains_key(&Style::Bold) {
+            marks.push(mark("strong"));
+        }
+        if styles.contains_key(&Style::Italic) {
+            marks.push(mark("em"));
+        }
+        if let Some(Some(value)) = styles.get(&Style::Link) {
+            let value = Link::from_style_value(value);
+            let mut link = mark("link");
+            link.attrs.insert("href".to_string(), Value::String(value.href));
+            if let Some(title) = value.title {
+                link.attrs.insert("title".to_string(), Value::String(title));
+            }
+            if value.new_tab {
+                link.attrs.insert("target".to_string(), Value::String("_blank".to_string()));
+            }
+            marks.push(link);
+        }
+    }
+    marks
+}
+
+fn link_text(text: &str, href: &str) -> Node {
+    let mut link = mark("link");
+    link.attrs.insert("href".to_string(), Value::String(href.to_string()));
+    Node::text(text, vec![link])
+}
+
+// Text nodes can't be empty, and line breaks are nodes of their own.
+fn push_text(out: &mut Vec<Node>, text: &str, marks: Vec<Mark>) {
+    for (i, line) in text.split('\n').enumerate() {
+        if i > 0 {
+            out.push(Node::new("hard_break", vec![]));
+        }
+        if !line.is_empty() {
+            out.push(Node::text(line, marks.clone()));
+        }
+    }
+}
+
+fn inline_nodes(span: &DocSpan, out: &mut Vec<Node>) {
+    for elem in span {
+        match elem {
+            DocChars(text) => push_text(out, text.as_str(), text_marks(text)),
+            DocGroup(attrs, inner) => match attrs.get("tag").map(|x| x.as_str()) {
+                Some("caret") => {}
+                Some("img") => out.push(
+                    Node::new("image", vec![])
+                        .with_attr("src", Value::String(attrs.get("src").cloned().unwrap_or_default()))
+                        .with_attr("alt", Value::String(attrs.get("alt").cloned().unwrap_or_default())),
+                ),
+                // As in prosemirror-math.
+                Some("math") => {
+                    let latex = attrs.get("latex").cloned().unwrap_or_default();
+                    let content = if latex.is_empty() {
+                        vec![]
+                    } else {
+                        vec![Node::text(&latex, vec![])]
+                    };
+                    out.push(Node::new("math_inline", content));
+                }
+                Some("mention") => out.push(Node::text(&format!("@{}", mention_name(attrs)), vec![])),
+                Some("doclink") => {
+                    let page_id = attrs.get("page").cloned().unwrap_or_default();
+                    out.push(link_text(&page_id, &format!("/{}", page_id)));
+                }
+                _ => inline_nodes(inner, out),
+            },
+        }
+    }
+}
+
+fn block_node(attrs: &Attrs, inner: &DocSpan) -> Node {
+    let tag = attrs.get("tag").map(|x| x.as_str()).unwrap_or("");
+    let mut inlines = vec![];
+    match tag {
+        "hr" => Node::new("horizontal_rule", vec![]),
+        "blockquote" => Node::new("blockquote", block_nodes(inner)),
+        "pre" | "html" => {
+            let mut text = String::new();
+            plain_text(inner, &mut text);
+            let content = if text.is_empty() {
+                vec![]
+            } else {
+                vec![Node::text(&text, vec![])]
+            };
+            let node = Node::new("code_block", content);
+            match attrs.get("lang") {
+                Some(lang) => node.with_attr("params", Value::String(lang.clone())),
+                None => node,
+            }
+        }
+        "attachment" => {
+            let (url, filename, _) = attachment_info(attrs);
+            Node::new("paragraph", vec![link_text(filename, url)])
+        }
+        "embed" => {
+            let url = attrs.get("url").cloned().unwrap_or_default();
+            Node::new("paragraph", vec![link_text(&url, &url)])
+        }
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+            inline_nodes(inner, &mut inlines);
+            let level = tag[1..].parse::<u64>().unwrap_or(1);
+            Node::new("heading", inlines).with_attr("level", Value::from(level))
+        }
+        _ => {
+            inline_nodes(inner, &mut inlines);
+            Node::new("paragraph", inlines)
+        }
+    }
+}
+
+// Flatten a bullet into items, each with its depth and blocks.
+fn collect_items(span: &DocSpan, depth: usize, items: &mut Vec<(usize, Vec<Node>)>) {
+    let mut started = false;
+    for elem in span {
+        match elem {
+            DocGroup(attrs, inner) if attrs.get("tag").map(|x| x == "bullet").unwrap_or(false) => {
+                collect_items(inner, depth + 1, items);
+                started = false;
+            }
+            DocGroup(attrs, inner) => {
+                let node = block_node(attrs, inner);
+                if started {
+                    if let Some(item) = items.last_mut() {
+                        item.1.push(node);
+                    }
+                } else {
+                    items.push((depth, vec![node]));
+                    started = true;
+                }
+            }
+            DocChars(_) => {}
+        }
+    }
+}
+
+fn build_list(items: &[(usize, Vec<Node>)], depth: usize) -> Node {
+    let mut list_items: Vec<Node> = vec![];
+    let mut i = 0;
+    while i < items.len() {
+        if items[i].0 <= depth {
+            list_items.push(Node::new("list_item", items[i].1.clone()));
+            i += 1;
+            continue;
+        }
+
+        // Deeper items nest in the item before them.
+        let end = items[i..]
+            .iter()
+            .position(|item| item.0 <= depth)
+            .map(|x| i + x)
+            .unwrap_or(items.len());
+        let nested = build_list(&items[i..end], depth + 1);
+        if list_items.is_empty() {
+            list_items.push(Node::new("list_item", vec![Node::new("paragraph", vec![])]));
+        }
+        list_items.last_mut().unwrap().content.push(nested);
+        i = end;
+    }
+    Node::new("bullet_list", list_items)
+}
+
+fn block_nodes(span: &DocSpan) -> Vec<Node> {
+    let mut out = vec![];
+    let mut items = vec![];
+    for elem in span {
+        match elem {
+            DocGroup(attrs, inner) if attrs.get("tag").map(|x| x == "bullet").unwrap_or(false) => {
+                collect_items(inner, 0, &mut items);
+            }
+            DocGroup(attrs, inner) => {
+                if !items.is_empty() {
+                    out.push(build_list(&items, 0));
+                    items.clear();
+                }
+                out.push(block_node(attrs, inner));
+            }
+            DocChars(_) => {}
+        }
+    }
+    if !items.is_empty() {
+        out.push(build_list(&items, 0));
+    }
+    out
+}
+
+/// A ProseMirror document node for a document.
+pub fn doc_to_prosemirror(doc: &DocSpan) -> Node {
+    Node::new("doc", block_nodes(doc))
+}
+
+fn group(tag: &str, inner: DocSpan) -> DocElement {
+    DocGroup(hashmap! { "tag".to_string() => tag.to_string() }, inner)
+}
+
+fn node_text(node: &Node) -> String {
+    let mut text = node.text.clone().unwrap_or_default();
+    for child in &node.content {
+        text.push_str(&node_text(child));
+    }
+    text
+}
+
+fn inline_span(nodes: &[Node]) -> Result<DocSpan, Error> {
+    let mut span = vec![];
+    for node in nodes {
+        match node.node_type.as_str() {
+            "text" => {
+                let mut styles = style_map! { Style::Normie => None };
+                for mark in &node.marks {
+                    match mark.mark_type.as_str() {
+                        "strong" => {
+                            styles.insert(Style::Bold, None);
+                        }
+                        "em" => {
+                            styles.insert(Style::Italic, None);
+                        }
+                        "link" => {
+                            let href = mark.attrs.get("href").and_then(|x| x.as_str()).unwrap_or("");
+                            let link = Link {
+                                href: href.to_string(),
+                                title: mark.attrs.get("title").and_then(|x| x.as_str()).map(|x| x.to_string()),
+                                new_tab: mark.attrs.get("target").and_then(|x| x.as_str()) == Some("_blank"),
+                            };
+                            styles.insert(Style::Link, Some(link.to_style_value()));
+                        }
+                        // Other marks, like code, have no counterpart.
+                        _ => {}
+                    }
+                }
+                let text = node.text.clone().unwrap_or_default();
+                if !text.is_empty() {
+                    span.push(DocChars(DocString::from_string_styled(text, styles)));
+                }
+            }
+            "hard_break" => {
+                span.push(DocChars(DocString::from_str_styled(
+                    "\n",
+                    style_map! { Style::Normie => None },
+                )));
+            }
+            "image" => span.push(DocGroup(
+                hashmap! {
+                    "tag".to_string() => "img".to_string(),
+                    "src".to_string() => node.attr_str("src").unwrap_or("").to_string(),
+                    "alt".to_string() => node.attr_str("alt").unwrap_or("").to_string(),
+                },
+                vec![],
+            )),
+            "math_inline" => span.push(DocGroup(
+                hashmap! {
+                    "tag".to_string() => "math".to_string(),
+                    "latex".to_string() => node_text(node),
+                },
+                vec![],
+            )),
+            other => return Err(err_msg(format!("unsupported inline node {:?}", other))),
+        }
+    }
+    Ok(span)
+}
+
+fn wrap_bullets(block: DocElement, depth: usize) -> DocElement {
+    let mut block = group("bullet", vec![block]);
+    for _ in 0..depth {
+        block = group("bullet", vec![block]);
+    }
+    block
+}
+
+fn list_blocks(items: &[Node], depth: usize, out: &mut DocSpan) -> Result<(), Error> {
+    for item in items {
+        for child in &item.content {
+            match child.node_type.as_str() {
+                "bullet_list" | "ordered_list" => list_blocks(&child.content, depth + 1, out)?,
+                _ => {
+                    let mut blocks = vec![];
+                    doc_blocks(::std::slice::from_ref(child), &mut blocks)?;
+                    out.extend(blocks.into_iter().map(|block| wrap_bullets(block, depth)));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn doc_blocks(nodes: &[Node], out: &mut DocSpan) -> Result<(), Error> {
+    for node in nodes {
+        match node.node_type.as_str() {
+            "paragraph" => out.push(group("p", inline_span(&node.content)?)),
+            "heading" => {
+                let level = node.attrs.get("level").and_then(|x| x.as_u64()).unwrap_or(1);
+                let tag = format!("h{}", level.max(1).min(6));
+                out.push(group(&tag, inline_span(&node.content)?));
+            }
+            "code_block" => {
+                let mut attrs = hashmap! { "tag".to_string() => "pre".to_string() };
+                let lang = node.attr_str("params").or_else(|| node.attr_str("language"));
+                if let Some(lang) = lang.filter(|lang| !lang.is_empty()) {
+                    attrs.insert("lang".to_string(), lang.to_string());
+                }
+                let text = node_text(node);
+                let content = if text.is_empty() {
+                    vec![]
+                } else {
+                    vec![DocChars(DocString::from_string_styled(
+                        text,
+                        style_map! { Style::Normie => None },
+                    ))]
+                };
+                out.push(DocGroup(attrs, content));
+            }
+            "blockquote" => {
+                let mut inner = vec![];
+                doc_blocks(&node.content, &mut inner)?;
+                out.push(group("blockquote", inner));
+            }
+            "horizontal_rule" => out.push(group("hr", vec![])),
+            "bullet_list" | "ordered_list" => list_blocks(&node.content, 0, out)?,
+            // An image outside a paragraph.
+            "image" => out.push(group("p", inline_span(::std::slice::from_ref(node))?)),
+            other => return Err(err_msg(format!("unsupported block node {:?}", other))),
+        }
+    }
+    Ok(())
+}
+
+/// Convert a ProseMirror document node into a document.
+pub fn prosemirror_to_doc(node: &Node) -> Result<DocSpan, Error> {
+    if node.node_type != "doc" {
+        return Err(err_msg(format!("expected a doc node, not {:?}", node.node_type)));
+    }
+    let mut doc = vec![];
+    doc_blocks(&node.content, &mut doc)?;
+    Ok(doc)
+}
@@ -0,0 +1,96 @@
+//! Why the sync server closes a connection. Close codes are sent outside of
+//! any command, so they aren't versioned like commands are: a client that
+//! doesn't know a code treats it as a dropped connection, and reconnects.
+
+// Why the sync server closed a client's connection. Each reason is sent as
+// its own close code, from the range left to applications, with a message
+// for the user.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum CloseReason {
+    // The document was replaced, as by restoring a revision, and must be
+    // loaded again.
+    Reloaded,
+    // The server unloaded the document.
+    Unloaded,
+    // The client sent a message the server couldn't handle.
+    ProtocolError,
+    // The client speaks a version of the protocol the server doesn't.
+    UnsupportedVersion,
+    // The client's share link is missing, expired, or was revoked.
+    Unauthorized,
+    // The client's user or address is banned.
+    Banned,
+    // An administrator disconnected the client.
+    Kicked,
+    // The client sent more messages than the server allows.
+    RateLimited,
+    // The server is shutting down, as when it's restarted.
+    ShuttingDown,
+    // The server couldn't handle a message for now, as when its database
+    // is unavailable.
+    Unavailable,
+}
+
+// What a client should do after the server closed its connection.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Recovery {
+    // Connect again, which loads the document afresh.
+    Reconnect,
+    // Wait a while, then connect again.
+    ReconnectLater,
+    // Ask the user for a new share link before connecting again.
+    Reauthenticate,
+    // Show the error. Connecting again won't help.
+    Stop,
+}
+
+impl CloseReason {
+    const ALL: &'static [CloseReason] = &[
+        CloseReason::Reloaded,
+        CloseReason::Unloaded,
+        CloseReason::ProtocolError,
+        CloseReason::UnsupportedVersion,
+        CloseReason::Unauthorized,
+        CloseReason::Banned,
+        CloseReason::Kicked,
+        CloseReason::RateLimited,
+        CloseReason::ShuttingDown,
+        CloseReason::Unavailable,
+    ];
+
+    pub fn code(&self) -> u16 {
+        match self {
+            CloseReason::Reloaded => 4000,
+            CloseReason::Unloaded => 4001,
+            CloseReason::ProtocolError => 4002,
+            CloseReason::UnsupportedVersion => 4003,
+            CloseReason::Unauthorized => 4004,
+            CloseReason::Banned => 4005,
+            CloseReason::Kicked => 4006,
+            CloseReason::RateLimited => 4007,
+            CloseReason::ShuttingDown => 4008,
+            CloseReason::Unavailable => 4009,
+        }
+    }
+
+    pub fn from_code(code: u16) -> Option<CloseReason> {
+        CloseReason::ALL
+            .iter()
+            .find(|reason| reason.code() == code)
+            .cloned()
+    }
+
+    pub fn recovery(&self) -> Recovery {
+        match self {
+            CloseReason::Reloaded | CloseReason::Unloaded => Recovery::Reconnect,
+            CloseReason::RateLimited | CloseReason::ShuttingDown | CloseReason::Unavailable => {
+                Recovery::ReconnectLater
+            }
+            CloseReason::Unauthorized => Recovery::Reauthenticate,
+            CloseReason::ProtocolError
+            | CloseReason::UnsupportedVersion
+            | CloseReason::Banned
+            | CloseReason::Kicked => Recovery::Stop,
+        }
+    }
+}
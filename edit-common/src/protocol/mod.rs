@@ -0,0 +1,146 @@
+//! Versions of the protocol between the sync server and its clients. Each
+//! version's commands live in their own module, which is frozen once a
+//! newer version replaces it: a change to the commands, even one that only
+//! adds some, is a new version, with conversions from the one before it, so
+//! a server and client built at different times can't disagree about a
+//! command's shape without noticing. A version's module re-exports the
+//! types it shares with the version before it.
+//!
+//! Clients give the version they speak when connecting, as
+//! /$/ws/{page}?protocol={version}, and the server refuses versions it
+//! doesn't support. Clients which don't give one speak version 1. A client
+//! reconnecting after its connection dropped gives the id it was assigned,
+//! as ?client={id}, and is sent an Ack of the last of its operations the
+//! server applied before its Init.
+//!
+//! Versions:
+//!
+//! 1. The commands of `v1`.
+//! 2. Adds ClientCommand::InitChunk, sent instead of Init. A client that
+//!    lost its connection partway through can continue from the chunk after
+//!    the last it received, by connecting with ?resume={version}:{chunk}.
+//! 3. Adds ServerCommand::CommitOp, sent instead of Commit, which gives
+//!    each operation an id. Its sender is answered with ClientCommand::Ack
+//!    and not sent the operation back as an Update.
+//! 4. Adds peer pages, edited at /$/ws/{page}/peer as a CRDT: clients send
+//!    ServerCommand::PeerOps, and are sent ClientCommand::PeerInit and
+//!    ClientCommand::PeerOps.
+//! 5. Adds ClientCommand::InitText, sent instead of InitChunk, which splits
+//!    the initial document's JSON into pieces of text, so that a document
+//!    with one long element isn't sent as one long message. It resumes
+//!    like InitChunk, from the piece after the last received.
+
+use serde_json;
+
+// Conversions between a version's commands and the version before it's,
+// given the variants they share. Every command of the version before is a
+// command of this one, and `downgrade` gives a command of this version as
+// the version before has it, if it does.
+macro_rules! previous_version {
+    ($previous:ident :: $name:ident {
+        $( $variant:ident $( ( $( $field:ident ),* ) )* ),* $(,)*
+    }) => {
+        impl From<super::$previous::$name> for $name {
+            fn from(command: super::$previous::$name) -> $name {
+                match command {
+                    $(
+                        super::$previous::$name::$variant $( ( $( $field ),* ) )* => {
+                            $name::$variant $( ( $( $field ),* ) )*
+                        }
+                    )*
+                }
+            }
+        }
+
+        impl $name {
+            /// This command as the version before this one has it, if it
+            /// does.
+            pub fn downgrade(self) -> Option<super::$previous::$name> {
+                match self {
+                    $(
+                        $name::$variant $( ( $( $field ),* ) )* => {
+                            Some(super::$previous::$name::$variant $( ( $( $field ),* ) )*)
+                        }
+                    )*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+pub mod close;
+pub mod v1;
+pub mod v2;
+pub mod v3;
+pub mod v4;
+pub mod v5;
+
+/// Version of the protocol `commands` re-exports, which this build speaks.
+pub const PROTOCOL_VERSION: u32 = 5;
+
+/// First version of the protocol whose clients are sent InitChunk.
+pub const CHUNKED_SYNC_VERSION: u32 = 2;
+
+/// First version of the protocol whose clients can edit peer pages.
+pub const PEER_SYNC_VERSION: u32 = 4;
+
+/// First version of the protocol whose clients are sent InitText.
+pub const TEXT_SYNC_VERSION: u32 = 5;
+
+/// Oldest version of the protocol the server still accepts.
+pub const OLDEST_PROTOCOL_VERSION: u32 = 1;
+
+/// Whether a client speaking this version of the protocol can connect.
+pub fn is_supported(version: u32) -> bool {
+    version >= OLDEST_PROTOCOL_VERSION && version <= PROTOCOL_VERSION
+}
+
+/// The version a client asked for with the `protocol` query parameter.
+pub fn requested_version(param: Option<&str>) -> Option<u32> {
+    match param {
+        Some(value) => value.parse().ok(),
+        None => Some(1),
+    }
+}
+
+/// The version and chunk a client asked to continue its initial sync from,
+/// given with the `resume` query parameter as "{version}:{chunk}".
+pub fn requested_resume(param: Option<&str>) -> Option<(usize, usize)> {
+    let mut parts = param?.splitn(2, ':');
+    let version = parts.next()?.parse().ok()?;
+    let chunk = parts.next()?.parse().ok()?;
+    Some((version, chunk))
+}
+
+/// Parse a command sent by a client speaking `version` of the protocol, as
+/// the command of the version this build speaks. Commands the client's
+/// version doesn't have are refused like any other malformed command.
+pub fn parse_server_command(
+    version: u32,
+    json: &[u8],
+) -> Result<v5::ServerCommand, serde_json::Error> {
+    Ok(match version {
+        1 | 2 => {
+            let command: v2::ServerCommand = serde_json::from_slice(json)?;
+            v3::ServerCommand::from(command).into()
+        }
+        3 => serde_json::from_slice::<v3::ServerCommand>(json)?.into(),
+        _ => serde_json::from_slice(json)?,
+    })
+}
+
+/// A command of the version this build speaks as JSON for a client
+/// speaking `version` of the protocol, or None if its version doesn't
+/// have the command.
+pub fn client_command_json(version: u32, command: &v5::ClientCommand) -> Option<String> {
+    let command = command.clone();
+    let json = match version {
+        1 => serde_json::to_string(&command.downgrade()?.downgrade()?.downgrade()?.downgrade()?),
+        2 => serde_json::to_string(&command.downgrade()?.downgrade()?.downgrade()?),
+        3 => serde_json::to_string(&command.downgrade()?.downgrade()?),
+        4 => serde_json::to_string(&command.downgrade()?),
+        _ => serde_json::to_string(&command),
+    };
+    json.ok()
+}
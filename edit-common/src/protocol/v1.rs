@@ -0,0 +1,154 @@
+//! Version 1 of the protocol, which clients that don't give a version
+//! speak. The types commands carry are defined here, and shared by the
+//! versions after it.
+
+use crate::comments::{
+    CommentAction,
+    CommentThread,
+};
+use crate::identity::UserIdentity;
+use crate::mention::Mention;
+use oatie::doc::*;
+
+// The server is the synchronization server.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ServerCommand {
+    // Connect(String),
+    Commit(String, Op, usize),
+    Log(String),
+    TerminateProxy,
+    // Take the write lock from whoever holds it, in single-writer mode.
+    TakeOver(String),
+    // Copy the document into a new one, along with its recorded history if
+    // the flag is set. Answered with Duplicated.
+    Duplicate(String, bool),
+    // Merge this document, a copy made by Duplicate, back into the original.
+    MergeFork(String),
+    // Find a query in the document as of a version. Answered with
+    // FindResults.
+    Find(String, String, usize),
+    // Like Commit, for an end-to-end encrypted page, with the operation
+    // sealed by `e2ee::DocKey`. Refused unless it's for the latest version.
+    EncryptedCommit(String, String, usize),
+    // A command in JSON, signed by `signing::SigningKeys`, with the id of
+    // the key and the signature.
+    Signed(String, String, String),
+    // Start, reply to, resolve, or reopen a comment thread. Answered by
+    // Comments to every client of the page.
+    Comment(String, CommentAction),
+}
+
+// Client is an individual user / machine.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ClientCommand {
+    // Client id assignment, initial doc, initial version
+    Init(String, DocSpan, usize),
+
+    // New document, version, client-id, operation
+    Update(usize, String, Op),
+
+    // The server refused to commit our last operation. It's followed by an
+    // Init command to resynchronize the client.
+    Refuse(RefusalReason),
+
+    // In single-writer mode, the client id allowed to edit the document.
+    // Every other client is read-only.
+    Writer(Option<String>),
+
+    // Id of the copy made in response to our Duplicate command.
+    Duplicated(String),
+
+    // Current titles of pages the document links to, or None for pages
+    // that don't exist.
+    PageTitles(Vec<(String, Option<String>)>),
+
+    // Query, version, match count, and an operation marking the matches in
+    // that version of the document. See `find::find_markers_op`.
+    FindResults(String, usize, usize, Op),
+
+    // Like Init, for an end-to-end encrypted page: client id, the id of the
+    // client behind each sealed operation of the page, and the version.
+    EncryptedInit(String, Vec<(String, String)>, usize),
+
+    // Like Update, with the operation sealed.
+    EncryptedUpdate(usize, String, String),
+
+    // What the client may do, when it connected with a share link. Sent
+    // before Init, like Writer.
+    Permission(SharePermission),
+
+    // Client ids and users of everyone editing the document, sent after
+    // Init and whenever someone joins or leaves.
+    Users(Vec<(String, UserIdentity)>),
+
+    // Unread mentions of our user, sent after Init and as we're mentioned.
+    Mentions(Vec<Mention>),
+
+    // Every comment thread of the document, sent after Init and whenever
+    // one changes.
+    Comments(Vec<CommentThread>),
+}
+
+// Access granted by a share link.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum SharePermission {
+    View,
+    Comment,
+    Edit,
+}
+
+impl SharePermission {
+    pub fn from_str(input: &str) -> Option<SharePermission> {
+        match input {
+            "view" => Some(SharePermission::View),
+            "comment" => Some(SharePermission::Comment),
+            "edit" => Some(SharePermission::Edit),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SharePermission::View => "view",
+            SharePermission::Comment => "comment",
+            SharePermission::Edit => "edit",
+        }
+    }
+
+    pub fn can_edit(&self) -> bool {
+        *self == SharePermission::Edit
+    }
+
+    pub fn can_comment(&self) -> bool {
+        *self != SharePermission::View
+    }
+}
+
+// Reason the sync server quarantined an operation instead of committing it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum RefusalReason {
+    // The operation's version is newer than the server's, or too old for
+    // the server to transform it.
+    UnknownVersion(usize),
+    // The operation doesn't fit the document it was written against.
+    MalformedOp(String),
+    // Applying the operation produces a document that fails validation.
+    InvalidDocument(String),
+    // The client doesn't hold the write lock, or its share link doesn't
+    // allow editing.
+    ReadOnly,
+    // The operation would take the document past one of its size limits,
+    // given with the limit's value.
+    TooLarge(SizeLimit, usize),
+}
+
+// Size limits the sync server enforces on documents.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum SizeLimit {
+    // Characters in the document.
+    Chars,
+    // Blocks in the document.
+    Blocks,
+    // Characters and groups added by a single operation.
+    OpSize,
+}
@@ -0,0 +1,53 @@
+//! Version 2 of the protocol, which sends the initial document in chunks.
+
+use crate::comments::CommentThread;
+use crate::identity::UserIdentity;
+use crate::mention::Mention;
+use oatie::doc::*;
+
+pub use super::v1::{
+    RefusalReason,
+    ServerCommand,
+    SharePermission,
+    SizeLimit,
+};
+
+// The commands of version 1, and InitChunk.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ClientCommand {
+    Init(String, DocSpan, usize),
+
+    // Sent instead of Init, with the initial doc split into chunks of
+    // top-level elements: client id, version, index of the chunk, number
+    // of chunks, and its elements.
+    InitChunk(String, usize, usize, usize, DocSpan),
+
+    Update(usize, String, Op),
+    Refuse(RefusalReason),
+    Writer(Option<String>),
+    Duplicated(String),
+    PageTitles(Vec<(String, Option<String>)>),
+    FindResults(String, usize, usize, Op),
+    EncryptedInit(String, Vec<(String, String)>, usize),
+    EncryptedUpdate(usize, String, String),
+    Permission(SharePermission),
+    Users(Vec<(String, UserIdentity)>),
+    Mentions(Vec<Mention>),
+    Comments(Vec<CommentThread>),
+}
+
+previous_version!(v1::ClientCommand {
+    Init(a, b, c),
+    Update(a, b, c),
+    Refuse(a),
+    Writer(a),
+    Duplicated(a),
+    PageTitles(a),
+    FindResults(a, b, c, d),
+    EncryptedInit(a, b, c),
+    EncryptedUpdate(a, b, c),
+    Permission(a),
+    Users(a),
+    Mentions(a),
+    Comments(a),
+});
@@ -0,0 +1,89 @@
+//! Version 3 of the protocol, which gives each operation an id so its
+//! sender can be answered with an Ack.
+
+use crate::comments::{
+    CommentAction,
+    CommentThread,
+};
+use crate::identity::UserIdentity;
+use crate::mention::Mention;
+use oatie::doc::*;
+
+pub use super::v2::{
+    RefusalReason,
+    SharePermission,
+    SizeLimit,
+};
+
+// The commands of version 2, and CommitOp.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ServerCommand {
+    Commit(String, Op, usize),
+    Log(String),
+    TerminateProxy,
+    TakeOver(String),
+    Duplicate(String, bool),
+    MergeFork(String),
+    Find(String, String, usize),
+    EncryptedCommit(String, String, usize),
+    Signed(String, String, String),
+    Comment(String, CommentAction),
+    // Commit with an id for the operation, unique among this client's
+    // operations: client id, operation id, operation, version. Answered
+    // with Ack instead of an Update of the operation. Sent instead of
+    // Commit.
+    CommitOp(String, u64, Op, usize),
+}
+
+previous_version!(v2::ServerCommand {
+    Commit(a, b, c),
+    Log(a),
+    TerminateProxy,
+    TakeOver(a),
+    Duplicate(a, b),
+    MergeFork(a),
+    Find(a, b, c),
+    EncryptedCommit(a, b, c),
+    Signed(a, b, c),
+    Comment(a, b),
+});
+
+// The commands of version 2, and Ack.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ClientCommand {
+    Init(String, DocSpan, usize),
+    InitChunk(String, usize, usize, usize, DocSpan),
+    Update(usize, String, Op),
+
+    // Our operation with this id was committed, making this version.
+    Ack(u64, usize),
+
+    Refuse(RefusalReason),
+    Writer(Option<String>),
+    Duplicated(String),
+    PageTitles(Vec<(String, Option<String>)>),
+    FindResults(String, usize, usize, Op),
+    EncryptedInit(String, Vec<(String, String)>, usize),
+    EncryptedUpdate(usize, String, String),
+    Permission(SharePermission),
+    Users(Vec<(String, UserIdentity)>),
+    Mentions(Vec<Mention>),
+    Comments(Vec<CommentThread>),
+}
+
+previous_version!(v2::ClientCommand {
+    Init(a, b, c),
+    InitChunk(a, b, c, d, e),
+    Update(a, b, c),
+    Refuse(a),
+    Writer(a),
+    Duplicated(a),
+    PageTitles(a),
+    FindResults(a, b, c, d),
+    EncryptedInit(a, b, c),
+    EncryptedUpdate(a, b, c),
+    Permission(a),
+    Users(a),
+    Mentions(a),
+    Comments(a),
+});
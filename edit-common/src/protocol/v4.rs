@@ -0,0 +1,151 @@
+//! Version 4 of the protocol, which adds peer pages, edited as a CRDT.
+
+use crate::comments::{
+    CommentAction,
+    CommentThread,
+};
+use crate::crdt::CrdtOp;
+use crate::identity::UserIdentity;
+use crate::mention::Mention;
+use oatie::doc::*;
+
+pub use super::v3::{
+    RefusalReason,
+    SharePermission,
+    SizeLimit,
+};
+
+// The server is the synchronization server.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ServerCommand {
+    // Connect(String),
+    Commit(String, Op, usize),
+    Log(String),
+    TerminateProxy,
+    // Take the write lock from whoever holds it, in single-writer mode.
+    TakeOver(String),
+    // Copy the document into a new one, along with its recorded history if
+    // the flag is set. Answered with Duplicated.
+    Duplicate(String, bool),
+    // Merge this document, a copy made by Duplicate, back into the original.
+    MergeFork(String),
+    // Find a query in the document as of a version. Answered with
+    // FindResults.
+    Find(String, String, usize),
+    // Like Commit, for an end-to-end encrypted page, with the operation
+    // sealed by `e2ee::DocKey`. Refused unless it's for the latest version.
+    EncryptedCommit(String, String, usize),
+    // A command in JSON, signed by `signing::SigningKeys`, with the id of
+    // the key and the signature.
+    Signed(String, String, String),
+    // Start, reply to, resolve, or reopen a comment thread. Answered by
+    // Comments to every client of the page.
+    Comment(String, CommentAction),
+    // Commit with an id for the operation, unique among this client's
+    // operations: client id, operation id, operation, version. Answered
+    // with Ack instead of an Update of the operation. Sent instead of
+    // Commit since protocol version 3.
+    CommitOp(String, u64, Op, usize),
+    // Operations on a peer page, made by the client with this id. Relayed
+    // to the page's other clients as PeerOps, in whatever order.
+    PeerOps(String, Vec<CrdtOp>),
+}
+
+previous_version!(v3::ServerCommand {
+    Commit(a, b, c),
+    Log(a),
+    TerminateProxy,
+    TakeOver(a),
+    Duplicate(a, b),
+    MergeFork(a),
+    Find(a, b, c),
+    EncryptedCommit(a, b, c),
+    Signed(a, b, c),
+    Comment(a, b),
+    CommitOp(a, b, c, d),
+});
+
+// Client is an individual user / machine.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ClientCommand {
+    // Client id assignment, initial doc, initial version
+    Init(String, DocSpan, usize),
+
+    // Sent instead of Init since protocol version 2, with the initial doc
+    // split into chunks of top-level elements: client id, version, index
+    // of the chunk, number of chunks, and its elements.
+    InitChunk(String, usize, usize, usize, DocSpan),
+
+    // New document, version, client-id, operation
+    Update(usize, String, Op),
+
+    // Our operation with this id was committed, making this version.
+    Ack(u64, usize),
+
+    // The server refused to commit our last operation. It's followed by an
+    // Init command to resynchronize the client.
+    Refuse(RefusalReason),
+
+    // In single-writer mode, the client id allowed to edit the document.
+    // Every other client is read-only.
+    Writer(Option<String>),
+
+    // Id of the copy made in response to our Duplicate command.
+    Duplicated(String),
+
+    // Current titles of pages the document links to, or None for pages
+    // that don't exist.
+    PageTitles(Vec<(String, Option<String>)>),
+
+    // Query, version, match count, and an operation marking the matches in
+    // that version of the document. See `find::find_markers_op`.
+    FindResults(String, usize, usize, Op),
+
+    // Like Init, for an end-to-end encrypted page: client id, the id of the
+    // client behind each sealed operation of the page, and the version.
+    EncryptedInit(String, Vec<(String, String)>, usize),
+
+    // Like Update, with the operation sealed.
+    EncryptedUpdate(usize, String, String),
+
+    // Like Init, for a peer page: client id, and every operation made on
+    // the page so far, to replay on its initial document.
+    PeerInit(String, Vec<CrdtOp>),
+
+    // Operations another client made on a peer page: its client id, and
+    // the operations.
+    PeerOps(String, Vec<CrdtOp>),
+
+    // What the client may do, when it connected with a share link. Sent
+    // before Init, like Writer.
+    Permission(SharePermission),
+
+    // Client ids and users of everyone editing the document, sent after
+    // Init and whenever someone joins or leaves.
+    Users(Vec<(String, UserIdentity)>),
+
+    // Unread mentions of our user, sent after Init and as we're mentioned.
+    Mentions(Vec<Mention>),
+
+    // Every comment thread of the document, sent after Init and whenever
+    // one changes.
+    Comments(Vec<CommentThread>),
+}
+
+previous_version!(v3::ClientCommand {
+    Init(a, b, c),
+    InitChunk(a, b, c, d, e),
+    Update(a, b, c),
+    Ack(a, b),
+    Refuse(a),
+    Writer(a),
+    Duplicated(a),
+    PageTitles(a),
+    FindResults(a, b, c, d),
+    EncryptedInit(a, b, c),
+    EncryptedUpdate(a, b, c),
+    Permission(a),
+    Users(a),
+    Mentions(a),
+    Comments(a),
+});
@@ -0,0 +1,109 @@
+//! Version 5 of the protocol, which sends the initial document as pieces of
+//! text, so that no element is too long to send. This is the version
+//! `commands` re-exports, which this build speaks.
+
+use crate::comments::CommentThread;
+use crate::crdt::CrdtOp;
+use crate::identity::UserIdentity;
+use crate::mention::Mention;
+use oatie::doc::*;
+
+pub use super::v4::{
+    RefusalReason,
+    ServerCommand,
+    SharePermission,
+    SizeLimit,
+};
+
+// Client is an individual user / machine.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ClientCommand {
+    // Client id assignment, initial doc, initial version
+    Init(String, DocSpan, usize),
+
+    // Sent instead of Init in protocol versions 2 to 4, with the initial
+    // doc split into chunks of top-level elements: client id, version,
+    // index of the chunk, number of chunks, and its elements.
+    InitChunk(String, usize, usize, usize, DocSpan),
+
+    // Sent instead of InitChunk since protocol version 5, with the initial
+    // doc as JSON, split into pieces of text however long its elements
+    // are: client id, version, index of the piece, number of pieces, and
+    // its text. The doc is the pieces joined in order.
+    InitText(String, usize, usize, usize, String),
+
+    // New document, version, client-id, operation
+    Update(usize, String, Op),
+
+    // Our operation with this id was committed, making this version.
+    Ack(u64, usize),
+
+    // The server refused to commit our last operation. It's followed by an
+    // Init command to resynchronize the client.
+    Refuse(RefusalReason),
+
+    // In single-writer mode, the client id allowed to edit the document.
+    // Every other client is read-only.
+    Writer(Option<String>),
+
+    // Id of the copy made in response to our Duplicate command.
+    Duplicated(String),
+
+    // Current titles of pages the document links to, or None for pages
+    // that don't exist.
+    PageTitles(Vec<(String, Option<String>)>),
+
+    // Query, version, match count, and an operation marking the matches in
+    // that version of the document. See `find::find_markers_op`.
+    FindResults(String, usize, usize, Op),
+
+    // Like Init, for an end-to-end encrypted page: client id, the id of the
+    // client behind each sealed operation of the page, and the version.
+    EncryptedInit(String, Vec<(String, String)>, usize),
+
+    // Like Update, with the operation sealed.
+    EncryptedUpdate(usize, String, String),
+
+    // Like Init, for a peer page: client id, and every operation made on
+    // the page so far, to replay on its initial document.
+    PeerInit(String, Vec<CrdtOp>),
+
+    // Operations another client made on a peer page: its client id, and
+    // the operations.
+    PeerOps(String, Vec<CrdtOp>),
+
+    // What the client may do, when it connected with a share link. Sent
+    // before Init, like Writer.
+    Permission(SharePermission),
+
+    // Client ids and users of everyone editing the document, sent after
+    // Init and whenever someone joins or leaves.
+    Users(Vec<(String, UserIdentity)>),
+
+    // Unread mentions of our user, sent after Init and as we're mentioned.
+    Mentions(Vec<Mention>),
+
+    // Every comment thread of the document, sent after Init and whenever
+    // one changes.
+    Comments(Vec<CommentThread>),
+}
+
+previous_version!(v4::ClientCommand {
+    Init(a, b, c),
+    InitChunk(a, b, c, d, e),
+    Update(a, b, c),
+    Ack(a, b),
+    Refuse(a),
+    Writer(a),
+    Duplicated(a),
+    PageTitles(a),
+    FindResults(a, b, c, d),
+    EncryptedInit(a, b, c),
+    EncryptedUpdate(a, b, c),
+    Permission(a),
+    Users(a),
+    Mentions(a),
+    Comments(a),
+    PeerInit(a, b),
+    PeerOps(a, b),
+});
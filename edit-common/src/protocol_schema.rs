@@ -0,0 +1,445 @@
+//! JSON Schema (draft-07) for the wire protocol: documents, operations, and
+//! the commands exchanged between the sync server, the client, and the
+//! frontend, as serde_json serializes them. Enums are externally tagged, so
+//! a unit variant is its name as a string, and any other variant an object
+//! with its name as the only key. Tuple variants hold an array.
+//!
+//! The schema is written out by hand beside the types it describes, so a
+//! change to a command has to be made here too; the protocol_schema test
+//! checks serialized commands against it.
+
+use serde_json::Value;
+
+fn reference(name: &str) -> Value {
+    json!({ "$ref": format!("#/definitions/{}", name) })
+}
+
+fn string() -> Value {
+    json!({ "type": "string" })
+}
+
+fn boolean() -> Value {
+    json!({ "type": "boolean" })
+}
+
+fn integer() -> Value {
+    json!({ "type": "integer" })
+}
+
+fn unsigned() -> Value {
+    json!({ "type": "integer", "minimum": 0 })
+}
+
+fn number() -> Value {
+    json!({ "type": "number" })
+}
+
+fn optional(schema: Value) -> Value {
+    json!({ "anyOf": [schema, { "type": "null" }] })
+}
+
+fn array(items: Value) -> Value {
+    json!({ "type": "array", "items": items })
+}
+
+fn tuple(items: Vec<Value>) -> Value {
+    let len = items.len();
+    json!({
+        "type": "array",
+        "items": items,
+        "minItems": len,
+        "maxItems": len,
+    })
+}
+
+// A struct, given its fields which must be present and those which may be
+// left out.
+fn object(required_fields: Vec<(&str, Value)>, optional_fields: Vec<(&str, Value)>) -> Value {
+    let names = required_fields.iter().map(|&(name, _)| json!(name)).collect::<Vec<_>>();
+    let mut properties = json!({});
+    for (name, schema) in required_fields.into_iter().chain(optional_fields) {
+        properties[name] = schema;
+    }
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": names,
+        "additionalProperties": false,
+    })
+}
+
+// An enum whose variants are all unit variants.
+fn names(variants: &[&str]) -> Value {
+    json!({ "enum": variants })
+}
+
+fn unit(name: &str) -> Value {
+    json!({ "const": name })
+}
+
+// A newtype or tuple variant, given the schema of what it holds.
+fn variant(name: &str, contents: Value) -> Value {
+    object(vec![(name, contents)], vec![])
+}
+
+fn one_of(variants: Vec<Value>) -> Value {
+    json!({ "oneOf": variants })
+}
+
+fn document_definitions() -> Vec<(&'static str, Value)> {
+    vec![
+        ("Doc", reference("DocSpan")),
+        ("DocSpan", array(reference("DocElement"))),
+        (
+            "DocElement",
+            one_of(vec![
+                variant("DocChars", reference("DocString")),
+                variant("DocGroup", tuple(vec![reference("Attrs"), reference("DocSpan")])),
+            ]),
+        ),
+        // Text, or text and its styles.
+        (
+            "DocString",
+            one_of(vec![
+                string(),
+                tuple(vec![string(), reference("StyleMap")]),
+            ]),
+        ),
+        (
+            "Attrs",
+            json!({ "type": "object", "additionalProperties": string() }),
+        ),
+        (
+            "Style",
+            names(&[
+                "Normie",
+                "Selected",
+                "Bold",
+                "Italic",
+                "Link",
+                "FontSize",
+                "FontFamily",
+            ]),
+        ),
+        (
+            "StyleMap",
+            json!({
+                "type": "object",
+                "propertyNames": reference("Style"),
+                "additionalProperties": optional(string()),
+            }),
+        ),
+        (
+            "StyleSet",
+            json!({ "type": "array", "items": reference("Style"), "uniqueItems": true }),
+        ),
+        ("Op", tuple(vec![reference("DelSpan"), reference("AddSpan")])),
+        ("DelSpan", array(reference("DelElement"))),
+        (
+            "DelElement",
+            one_of(vec![
+                variant("DelSkip", unsigned()),
+                variant("DelWithGroup", reference("DelSpan")),
+                variant("DelChars", unsigned()),
+                variant("DelGroup", reference("DelSpan")),
+                variant("DelStyles", tuple(vec![unsigned(), reference("StyleSet")])),
+            ]),
+        ),
+        ("AddSpan", array(reference("AddElement"))),
+        (
+            "AddElement",
+            one_of(vec![
+                variant("AddSkip", unsigned()),
+                variant("AddWithGroup", reference("AddSpan")),
+                variant("AddChars", reference("DocString")),
+                variant("AddGroup", tuple(vec![reference("Attrs"), reference("AddSpan")])),
+                variant("AddStyles", tuple(vec![unsigned(), reference("StyleMap")])),
+            ]),
+        ),
+        ("CurSpan", array(reference("CurElement"))),
+        (
+            "CurElement",
+            one_of(vec![
+                variant("CurSkip", unsigned()),
+                variant("CurWithGroup", reference("CurSpan")),
+                unit("CurGroup"),
+                unit("CurChar"),
+            ]),
+        ),
+    ]
+}
+
+fn command_definitions() -> Vec<(&'static str, Value)> {
+    vec![
+        (
+            "ServerCommand",
+            one_of(vec![
+                variant("Commit", tuple(vec![string(), reference("Op"), unsigned()])),
+                variant("Log", string()),
+                unit("TerminateProxy"),
+                variant("TakeOver", string()),
+                variant("Duplicate", tuple(vec![string(), boolean()])),
+                variant("MergeFork", string()),
+                variant("Find", tuple(vec![string(), string(), unsigned()])),
+                variant("EncryptedCommit", tuple(vec![string(), string(), unsigned()])),
+                variant("Signed", tuple(vec![string(), string(), string()])),
+                variant("Comment", tuple(vec![string(), reference("CommentAction")])),
+                variant(
+                    "CommitOp",
+                    tuple(vec![string(), unsigned(), reference("Op"), unsigned()]),
+                ),
+                variant("PeerOps", tuple(vec![string(), array(reference("CrdtOp"))])),
+            ]),
+        ),
+        (
+            "ClientCommand",
+            one_of(vec![
+                variant("Init", tuple(vec![string(), reference("DocSpan"), unsigned()])),
+                variant(
+                    "InitChunk",
+                    tuple(vec![
+                        string(),
+                        unsigned(),
+                        unsigned(),
+                        unsigned(),
+                        reference("DocSpan"),
+                    ]),
+                ),
+                variant(
+                    "InitText",
+                    tuple(vec![string(), unsigned(), unsigned(), unsigned(), string()]),
+                ),
+                variant("Update", tuple(vec![unsigned(), string(), reference("Op")])),
+                variant("Ack", tuple(vec![unsigned(), unsigned()])),
+                variant("Refuse", reference("RefusalReason")),
+                variant("Writer", optional(string())),
+                variant("Duplicated", string()),
+                variant(
+                    "PageTitles",
+                    array(tuple(vec![string(), optional(string())])),
+                ),
+                variant(
+                    "FindResults",
+                    tuple(vec![string(), unsigned(), unsigned(), reference("Op")]),
+                ),
+                variant(
+                    "EncryptedInit",
+                    tuple(vec![string(), array(tuple(vec![string(), string()])), unsigned()]),
+                ),
+                variant("EncryptedUpdate", tuple(vec![unsigned(), string(), string()])),
+                variant("PeerInit", tuple(vec![string(), array(reference("CrdtOp"))])),
+                variant("PeerOps", tuple(vec![string(), array(reference("CrdtOp"))])),
+                variant("Permission", reference("SharePermission")),
+                variant(
+                    "Users",
+                    array(tuple(vec![string(), reference("UserIdentity")])),
+                ),
+                variant("Mentions", array(reference("Mention"))),
+                variant("Comments", array(reference("CommentThread"))),
+            ]),
+        ),
+        (
+            "ControllerCommand",
+            one_of(vec![
+                variant(
+                    "Keypress",
+                    tuple(vec![unsigned(), boolean(), boolean(), boolean()]),
+                ),
+                variant("Button", unsigned()),
+                variant("Character", unsigned()),
+                variant("InsertText", string()),
+                variant("RenameGroup", tuple(vec![string(), reference("CurSpan")])),
+                variant(
+                    "Cursor",
+                    tuple(vec![optional(reference("CurSpan")), optional(reference("CurSpan"))]),
+                ),
+                variant("RandomTarget", number()),
+                variant("Monkey", boolean()),
+                unit("DebugDump"),
+                unit("Stats"),
+                variant("SyncConnection", boolean()),
+                unit("TakeOver"),
+                variant("Vim", boolean()),
+                variant("Keymap", reference("Keymap")),
+                unit("SelectWord"),
+                variant("InputRule", tuple(vec![reference("InputRule"), boolean()])),
+                unit("ToggleFold"),
+                variant("ScrollTo", string()),
+                variant("MoveSection", tuple(vec![string(), unsigned()])),
+                unit("EmojiCompletions"),
+                variant("CompleteEmoji", string()),
+                variant("InsertMath", string()),
+                variant("EditMath", string()),
+                variant("InsertImage", string()),
+                variant("InsertDocLink", string()),
+                variant("InsertMention", tuple(vec![string(), string()])),
+                variant(
+                    "InsertAttachment",
+                    tuple(vec![string(), string(), unsigned()]),
+                ),
+                variant("InsertEmbed", string()),
+                variant("RenderMode", optional(string())),
+                variant("CodeLanguage", optional(string())),
+                variant("Align", optional(string())),
+                variant("EditLink", optional(reference("Link"))),
+                variant("FontSize", optional(string())),
+                variant("FontFamily", optional(string())),
+                variant("Duplicate", boolean()),
+                unit("MergeFork"),
+                variant("Find", string()),
+                variant("SelectRange", tuple(vec![integer(), integer()])),
+                variant("EncryptionKey", tuple(vec![string(), string()])),
+                unit("PeerSync"),
+                variant("StartComment", string()),
+                variant("Comment", reference("CommentAction")),
+                variant("ShowResolvedComments", boolean()),
+                variant("Autocorrect", tuple(vec![string(), optional(string())])),
+                variant(
+                    "AutocorrectDictionary",
+                    array(tuple(vec![string(), string()])),
+                ),
+                variant("Lint", reference("LintConfig")),
+                variant("Paginate", optional(reference("PageLayout"))),
+            ]),
+        ),
+        (
+            "RefusalReason",
+            one_of(vec![
+                variant("UnknownVersion", unsigned()),
+                variant("MalformedOp", string()),
+                variant("InvalidDocument", string()),
+                unit("ReadOnly"),
+                variant("TooLarge", tuple(vec![reference("SizeLimit"), unsigned()])),
+            ]),
+        ),
+        ("SizeLimit", names(&["Chars", "Blocks", "OpSize"])),
+        ("SharePermission", names(&["View", "Comment", "Edit"])),
+        ("Keymap", names(&["Default", "Emacs"])),
+        (
+            "InputRule",
+            names(&["SmartQuotes", "Dashes", "Ellipses", "Emoji", "Autocorrect"]),
+        ),
+        (
+            "LintConfig",
+            object(
+                vec![("passive_voice", boolean()), ("repeated_words", boolean())],
+                vec![("max_sentence_words", optional(unsigned()))],
+            ),
+        ),
+        (
+            "PageLayout",
+            object(
+                vec![("lines_per_page", unsigned()), ("chars_per_line", unsigned())],
+                vec![],
+            ),
+        ),
+        (
+            "UserIdentity",
+            object(
+                vec![("id", string()), ("name", string()), ("color", string())],
+                vec![("avatar_url", optional(string())), ("guest", boolean())],
+            ),
+        ),
+        (
+            "Mention",
+            object(
+                vec![
+                    ("id", integer()),
+                    ("page_id", string()),
+                    ("author", string()),
+                    ("created", integer()),
+                ],
+                vec![("anchor", optional(string()))],
+            ),
+        ),
+        (
+            "Comment",
+            object(
+                vec![("author", string()), ("body", string()), ("created", integer())],
+                vec![],
+            ),
+        ),
+        (
+            "CommentThread",
+            object(
+                vec![
+                    ("id", string()),
+                    ("anchor", string()),
+                    ("resolved", boolean()),
+                    ("comments", array(reference("Comment"))),
+                ],
+                vec![],
+            ),
+        ),
+        (
+            "CommentAction",
+            one_of(vec![
+                variant("Start", tuple(vec![string(), string()])),
+                variant("Reply", tuple(vec![string(), string()])),
+                variant("Resolve", string()),
+                variant("Reopen", string()),
+            ]),
+        ),
+        (
+            "CrdtId",
+            object(vec![("clock", unsigned()), ("site", unsigned())], vec![]),
+        ),
+        (
+            "CrdtOp",
+            one_of(vec![
+                variant(
+                    "InsertBlock",
+                    object(
+                        vec![
+                            ("id", reference("CrdtId")),
+                            ("after", optional(reference("CrdtId"))),
+                            ("tag", string()),
+                        ],
+                        vec![],
+                    ),
+                ),
+                variant("DeleteBlock", object(vec![("id", reference("CrdtId"))], vec![])),
+                variant(
+                    "InsertChar",
+                    object(
+                        vec![
+                            ("block", reference("CrdtId")),
+                            ("id", reference("CrdtId")),
+                            ("after", optional(reference("CrdtId"))),
+                            ("value", json!({ "type": "string", "minLength": 1, "maxLength": 1 })),
+                        ],
+                        vec![],
+                    ),
+                ),
+                variant(
+                    "DeleteChar",
+                    object(
+                        vec![("block", reference("CrdtId")), ("id", reference("CrdtId"))],
+                        vec![],
+                    ),
+                ),
+            ]),
+        ),
+        (
+            "Link",
+            object(
+                vec![("href", string())],
+                vec![("title", optional(string())), ("new_tab", boolean())],
+            ),
+        ),
+    ]
+}
+
+/// The schema of the protocol, with a definition for each type, to refer
+/// to as "#/definitions/ClientCommand" and so on.
+pub fn protocol_schema() -> Value {
+    let mut definitions = json!({});
+    for (name, schema) in document_definitions().into_iter().chain(command_definitions()) {
+        definitions[name] = schema;
+    }
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "edit-text protocol",
+        "definitions": definitions,
+    })
+}
@@ -0,0 +1,453 @@
+//! Conversion between documents and Quill's Delta format, to move content
+//! to and from Quill-based editors.
+//!
+//! A document becomes a Delta of inserts, where each line ends with a "\n"
+//! whose attributes describe its block: `header`, `list`, `blockquote`, or
+//! `code-block`. Bold, italic, and links become `bold`, `italic`, and `link`
+//! attributes on text, and images, math, and rules become `image`,
+//! `formula`, and `divider` embeds. Deltas that change a document, with
+//! retain and delete operations, can be applied with `apply_delta`.
+
+use crate::attachment::attachment_info;
+use crate::link::link_href;
+use crate::mention::mention_name;
+use failure::{
+    err_msg,
+    Error,
+};
+use oatie::doc::*;
+use serde_json::{
+    Map,
+    Value,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Delta {
+    pub ops: Vec<DeltaOp>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct DeltaOp {
+    // A string, or an object for embeds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub insert: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retain: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delete: Option<usize>,
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub attributes: Map<String, Value>,
+}
+
+// A character or an embed, the unit Deltas count in.
+#[derive(Clone, Debug, PartialEq)]
+enum Unit {
+    Char(char),
+    Embed(Value),
+}
+
+impl Unit {
+    // Quill counts lengths in UTF-16 code units, as JavaScript does.
+    fn len(&self) -> usize {
+        match self {
+            Unit::Char(c) => c.len_utf16(),
+            Unit::Embed(_) => 1,
+        }
+    }
+}
+
+impl Delta {
+    /// Append an insert, merging it into the last one if they're both text
+    /// with the same attributes.
+    pub fn push_insert(&mut self, insert: Value, attributes: Map<String, Value>) {
+        if let Value::String(ref text) = insert {
+            if let Some(last) = self.ops.last_mut() {
+                if last.attributes == attributes {
+                    if let Some(Value::String(ref mut last_text)) = last.insert {
+                        last_text.push_str(text);
+                        return;
+                    }
+                }
+            }
+        }
+        self.ops.push(DeltaOp {
+            insert: Some(insert),
+            attributes,
+            ..DeltaOp::default()
+        });
+    }
+
+    pub fn push_text(&mut self, text: &str, attributes: Map<String, Value>) {
+        if !text.is_empty() {
+            self.push_insert(Value::String(text.to_string()), attributes);
+        }
+    }
+
+    fn push_unit(&mut self, unit: Unit, attributes: Map<String, Value>) {
+        match unit {
+            Unit::Char(c) => self.push_text(&c.to_string(), attributes),
+            Unit::Embed(embed) => self.push_insert(embed, attributes),
+        }
+    }
+
+    fn units(&self) -> Result<Vec<(Unit, Map<String, Value>)>, Error> {
+        let mut units = vec![];
+        for op in &self.ops {
+            match op.insert {
+                Some(Value::String(ref text)) => {
+                    units.extend(text.chars().map(|c| (Unit::Char(c), op.attributes.clone())));
+                }
+                Some(ref embed) => units.push((Unit::Embed(embed.clone()), op.attributes.clone())),
+                None => return Err(err_msg("a document's Delta can only contain inserts")),
+            }
+        }
+        Ok(units)
+    }
+}
+
+fn embed(key: &str, value: Value) -> Value {
+    let mut map = Map::new();
+    map.insert(key.to_string(), value);
+    Value::Object(map)
+}
+
+fn text_attributes(text: &DocString) -> Map<String, Value> {
+    let mut attributes = Map::new();
+    if let Some(styles) = text.styles() {
+        if styles.contains_key(&Style::Bold) {
+            attributes.insert("bold".to_string(), Value::Bool(true));
+        }
+        if styles.contains_key(&Style::Italic) {
+            attributes.insert("italic".to_string(), Value::Bool(true));
+        }
+        if let Some(Some(link)) = styles.get(&Style::Link) {
+            attributes.insert("link".to_string(), Value::String(link_href(link)));
+        }
+    }
+    attributes
+}
+
+fn plain_text(span: &DocSpan, out: &mut String) {
+    for elem in span {
+        match elem {
+            DocChars(text) => out.push_str(text.as_str()),
+            DocGroup(_, inner) => plain_text(inner, out),
+        }
+    }
+}
+
+fn write_inlines(delta: &mut Delta, span: &DocSpan) {
+    for elem in span {
+        match elem {
+            DocChars(text) => delta.push_text(text.as_str(), text_attributes(text)),
+            DocGroup(attrs, inner) => match attrs.get("tag").map(|x| x.as_str()) {
+                Some("caret") => {}
+                Some("img") => {
+                    let mut attributes = Map::new();
+                    if let Some(alt) = attrs.get("alt").filter(|alt| !alt.is_empty()) {
+                        attributes.insert("alt".to_string(), Value::String(alt.clone()));
+                    }
+                    let src = attrs.get("src").cloned().unwrap_or_default();
+                    delta.push_insert(embed("image", Value::String(src)), attributes);
+                }
+                Some("math") => {
+                    let latex = attrs.get("latex").cloned().unwrap_or_default();
+                    delta.push_insert(embed("formula", Value::String(latex)), Map::new());
+                }
+                Some("mention") => {
+                    delta.push_text(&format!("@{}", mention_name(attrs)), Map::new());
+                }
+                Some("doclink") => {
+                    let page_id = attrs.get("page").cloned().unwrap_or_default();
+                    let mut attributes = Map::new();
+                    attributes.insert("link".to_string(), Value::String(format!("/{}", page_id)));
+                    delta.push_text(&page_id, attributes);
+                }
+                _ => write_inlines(delta, inner),
+            },
+        }
+    }
+}
+
+// The attributes of the newline ending a block inside lists and quotes.
+fn line_attributes(list_depth: Option<usize>, quote: bool) -> Map<String, Value> {
+    let mut attributes = Map::new();
+    if let Some(depth) = list_depth {
+        attributes.insert("list".to_string(), Value::String("bullet".to_string()));
+        if depth > 0 {
+            attributes.insert("indent".to_string(), Value::from(depth));
+        }
+    }
+    if quote {
+        attributes.insert("blockquote".to_string(), Value::Bool(true));
+    }
+    attributes
+}
+
+fn write_blocks(delta: &mut Delta, span: &DocSpan, list_depth: Option<usize>, quote: bool) {
+    for elem in span {
+        let (attrs, inner) = match elem {
+            DocGroup(attrs, inner) => (attrs, inner),
+            DocChars(text) => {
+                delta.push_text(text.as_str(), text_attributes(text));
+                continue;
+            }
+        };
+        let tag = attrs.get("tag").map(|x| x.as_str()).unwrap_or("");
+        match tag {
+            "bullet" => {
+                let depth = list_depth.map(|depth| depth + 1).unwrap_or(0);
+                write_blocks(delta, inner, Some(depth), quote);
+            }
+            "blockquote" => write_blocks(delta, inner, list_depth, true),
+            "hr" => delta.push_insert(embed("divider", Value::Bool(true)), Map::new()),
+            "embed" => {
+                let url = attrs.get("url").cloned().unwrap_or_default();
+                delta.push_insert(embed("video", Value::String(url)), Map::new());
+            }
+            "attachment" => {
+                let (url, filename, _) = attachment_info(attrs);
+                let mut attributes = Map::new();
+                attributes.insert("link".to_string(), Value::String(url.to_string()));
+                delta.push_text(filename, attributes);
+                delta.push_text("\n", line_attributes(list_depth, quote));
+            }
+            "pre" | "html" => {
+                let mut text = String::new();
+                plain_text(inner, &mut text);
+                let language = attrs
+                    .get("lang")
+                    .map(|lang| Value::String(lang.clone()))
+                    .unwrap_or(Value::Bool(true));
+                for line in text.trim_right_matches('\n').split('\n') {
+                    delta.push_text(line, Map::new());
+                    let mut attributes = line_attributes(list_depth, quote);
+                    attributes.insert("code-block".to_string(), language.clone());
+                    delta.push_text("\n", attributes);
+                }
+            }
+            _ => {
+                write_inlines(delta, inner);
+                let mut attributes = line_attributes(list_depth, quote);
+                if tag.len() == 2 && tag.starts_with('h') {
+                    if let Ok(level) = tag[1..].parse::<u64>() {
+                        attributes.insert("header".to_string(), Value::from(level));
+                    }
+                }
+                delta.push_text("\n", attributes);
+            }
+        }
+    }
+}
+
+/// A Delta of inserts that recreates a document.
+pub fn doc_to_delta(doc: &DocSpan) -> Delta {
+    let mut delta = Delta::default();
+    write_blocks(&mut delta, doc, None, false);
+    delta
+}
+
+fn doc_styles(attributes: &Map<String, Value>) -> StyleMap {
+    let mut styles = style_map! { Style::Normie => None };
+    if attributes.get("bold").and_then(|x| x.as_bool()) == Some(true) {
+        styles.insert(Style::Bold, None);
+    }
+    if attributes.get("italic").and_then(|x| x.as_bool()) == Some(true) {
+        styles.insert(Style::Italic, None);
+    }
+    if let Some(link) = attributes.get("link").and_then(|x| x.as_str()) {
+        styles.insert(Style::Link, Some(link.to_string()));
+    }
+    styles
+}
+
+fn group(tag: &str, inner: DocSpan) -> DocElement {
+    DocGroup(hashmap! { "tag".to_string() => tag.to_string() }, inner)
+}
+
+#[derive(Default)]
+struct DocBuilder {
+    blocks: DocSpan,
+    line: DocSpan,
+    // Lines of the code block being read, and its language.
+    code: Option<(Option<String>, Vec<String>)>,
+}
+
+impl DocBuilder {
+    fn flush_code(&mut self) {
+        if let Some((lang, lines)) = self.code.take() {
+            let mut attrs = hashmap! { "tag".to_string() => "pre".to_string() };
+            if let Some(lang) = lang {
+                attrs.insert("lang".to_string(), lang);
+            }
+            self.blocks.push(DocGroup(
+                attrs,
+                vec![DocChars(DocString::from_string_styled(
+                    lines.join("\n"),
+                    style_map! { Style::Normie => None },
+                ))],
+            ));
+        }
+    }
+
+    fn push_block(&mut self, mut block: DocElement, attributes: &Map<String, Value>) {
+        self.flush_code();
+        if attributes.get("list").is_some() {
+            let indent = attributes.get("indent").and_then(|x| x.as_u64()).unwrap_or(0);
+            block = group("bullet", vec![block]);
+            for _ in 0..indent {
+                block = group("bullet", vec![block]);
+            }
+        }
+        if attributes.get("blockquote").is_some() {
+            block = group("blockquote", vec![block]);
+        }
+        self.blocks.push(block);
+    }
+
+    fn end_line(&mut self, attributes: &Map<String, Value>) {
+        let line = ::std::mem::replace(&mut self.line, vec![]);
+        if let Some(language) = attributes.get("code-block") {
+            let mut text = String::new();
+            plain_text(&line, &mut text);
+            let lang = language.as_str().map(|x| x.to_string());
+            let same_block = match self.code {
+                Some((ref code_lang, _)) => *code_lang == lang,
+                None => false,
+            };
+            if same_block {
+                if let Some((_, ref mut lines)) = self.code {
+                    lines.push(text);
+                }
+            } else {
+                self.flush_code();
+                self.code = Some((lang, vec![text]));
+            }
+            return;
+        }
+
+        let tag = match attributes.get("header").and_then(|x| x.as_u64()) {
+            Some(level) if level >= 1 && level <= 6 => format!("h{}", level),
+            _ => "p".to_string(),
+        };
+        self.push_block(group(&tag, line), attributes);
+    }
+
+    fn push_embed(&mut self, embed: &Map<String, Value>, attributes: &Map<String, Value>) {
+        if let Some(src) = embed.get("image").and_then(|x| x.as_str()) {
+            let alt = attributes.get("alt").and_then(|x| x.as_str()).unwrap_or("");
+            self.line.push(DocGroup(
+                hashmap! {
+                    "tag".to_string() => "img".to_string(),
+                    "src".to_string() => src.to_string(),
+                    "alt".to_string() => alt.to_string(),
+                },
+                vec![],
+            ));
+        } else if let Some(latex) = embed.get("formula").and_then(|x| x.as_str()) {
+            self.line.push(DocGroup(
+                hashmap! {
+                    "tag".to_string() => "math".to_string(),
+                    "latex".to_string() => latex.to_string(),
+                },
+                vec![],
+            ));
+        } else if embed.contains_key("divider") {
+            self.push_block(group("hr", vec![]), &Map::new());
+        } else if let Some(url) = embed.get("video").and_then(|x| x.as_str()) {
+            self.push_block(
+                DocGroup(
+                    hashmap! {
+                        "tag".to_string() => "embed".to_string(),
+                        "url".to_string() => url.to_string(),
+                    },
+                    vec![],
+                ),
+                &Map::new(),
+            );
+        }
+        // Other embeds have no counterpart in documents.
+    }
+
+    fn finish(mut self) -> DocSpan {
+        if !self.line.is_empty() {
+            self.end_line(&Map::new());
+        }
+        self.flush_code();
+        self.blocks
+    }
+}
+
+/// Convert a Delta of inserts into a document.
+pub fn delta_to_doc(delta: &Delta) -> Result<DocSpan, Error> {
+    let mut builder = DocBuilder::default();
+    for op in &delta.ops {
+        match op.insert {
+            Some(Value::String(ref text)) => {
+                for (i, part) in text.split('\n').enumerate() {
+                    if i > 0 {
+                        builder.end_line(&op.attributes);
+                    }
+                    if !part.is_empty() {
+                        builder.line.push(DocChars(DocString::from_str_styled(
+                            part,
+                            doc_styles(&op.attributes),
+                        )));
+                    }
+                }
+            }
+            Some(Value::Object(ref embed)) => builder.push_embed(embed, &op.attributes),
+            Some(_) => return Err(err_msg("inserts must be strings or embeds")),
+            None => return Err(err_msg("a document's Delta can only contain inserts")),
+        }
+    }
+    Ok(builder.finish())
+}
+
+// Set or, with null, remove attributes.
+fn format_attributes(attributes: &mut Map<String, Value>, changes: &Map<String, Value>) {
+    for (key, value) in changes {
+        if value.is_null() {
+            attributes.remove(key);
+        } else {
+            attributes.insert(key.clone(), value.clone());
+        }
+    }
+}
+
+/// Apply a Delta with retain, delete, and insert operations to a document,
+/// as Quill would to the document's own Delta.
+pub fn apply_delta(doc: &DocSpan, change: &Delta) -> Result<DocSpan, Error> {
+    let units = doc_to_delta(doc).units()?;
+    let mut index = 0;
+    let mut result = Delta::default();
+    for op in &change.ops {
+        if let Some(ref insert) = op.insert {
+            let mut attributes = Map::new();
+            format_attributes(&mut attributes, &op.attributes);
+            result.push_insert(insert.clone(), attributes);
+            continue;
+        }
+        let (mut count, retain) = match (op.retain, op.delete) {
+            (Some(count), _) => (count, true),
+            (_, Some(count)) => (count, false),
+            _ => return Err(err_msg("empty Delta operation")),
+        };
+        while count > 0 {
+            let (unit, mut attributes) = units
+                .get(index)
+                .cloned()
+                .ok_or_else(|| err_msg("Delta reaches past the end of the document"))?;
+            count = count.saturating_sub(unit.len());
+            index += 1;
+            if retain {
+                format_attributes(&mut attributes, &op.attributes);
+                result.push_unit(unit, attributes);
+            }
+        }
+    }
+    for (unit, attributes) in units.into_iter().skip(index) {
+        result.push_unit(unit, attributes);
+    }
+    delta_to_doc(&result)
+}
@@ -0,0 +1,233 @@
+//! HMAC signing of commands between trusted components, like the client
+//! proxy and the sync server, so whatever sits between them can't forge or
+//! alter operations attributed to a client.
+//!
+//! Keys are configured as comma-separated `id:secret` pairs in
+//! EDIT_OP_SIGNING_KEYS. The first key signs, and every key listed verifies,
+//! so keys are rotated by adding the new key to the front of the list on the
+//! server first, then on the proxies, before removing the old one.
+//!
+//! A signature covers the page and version a command is for, when it was
+//! signed, and a random nonce along with the command, so it can't be sent to
+//! another page, or sent again. Servers refuse commands signed before they
+//! started or before the oldest nonce they've forgotten, so restarting a
+//! server or filling its memory of nonces doesn't let old commands back in.
+
+use crate::commands::ServerCommand;
+use base64;
+use failure::{
+    err_msg,
+    Error,
+};
+use hmac::{
+    Hmac,
+    Mac,
+};
+use rand::random;
+use serde_json;
+use sha2::Sha256;
+use std::cmp;
+use std::collections::{
+    HashSet,
+    VecDeque,
+};
+use std::env;
+use std::time::{
+    Duration,
+    SystemTime,
+    UNIX_EPOCH,
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Milliseconds since the Unix epoch, which is what payloads are stamped
+/// with when they're signed.
+pub fn signing_time() -> u64 {
+    let elapsed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::from_secs(0));
+    elapsed.as_secs() * 1_000 + (elapsed.subsec_nanos() / 1_000_000) as u64
+}
+
+pub const SIGNING_KEYS_VAR: &str = "EDIT_OP_SIGNING_KEYS";
+
+/// Commands that change a page, which are signed when keys are configured,
+/// and refused unsigned when signing is required.
+pub fn needs_signature(command: &ServerCommand) -> bool {
+    match command {
+        ServerCommand::Commit(..)
+        | ServerCommand::CommitOp(..)
+        | ServerCommand::EncryptedCommit(..)
+        | ServerCommand::PeerOps(..)
+        | ServerCommand::Duplicate(..)
+        | ServerCommand::MergeFork(..) => true,
+        _ => false,
+    }
+}
+
+/// The version a command was written against, if it has one.
+pub fn command_version(command: &ServerCommand) -> Option<usize> {
+    match command {
+        ServerCommand::Commit(_, _, version)
+        | ServerCommand::CommitOp(_, _, _, version)
+        | ServerCommand::EncryptedCommit(_, _, version) => Some(*version),
+        _ => None,
+    }
+}
+
+/// The payload of a signed command: the command's JSON, and what it's
+/// bound to.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct SignedPayload {
+    pub page_id: String,
+    pub version: Option<usize>,
+    pub nonce: String,
+    pub signed_at: u64,
+    pub command: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct SigningKeys {
+    keys: Vec<(String, Vec<u8>)>,
+}
+
+impl SigningKeys {
+    /// Parse keys in the format of EDIT_OP_SIGNING_KEYS.
+    pub fn parse(config: &str) -> Result<SigningKeys, Error> {
+        let mut keys = vec![];
+        for entry in config.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()) {
+            let mut parts = entry.splitn(2, ':');
+            let id = parts.next().unwrap_or("");
+            let secret = parts.next().unwrap_or("");
+            if id.is_empty() || secret.is_empty() {
+                return Err(err_msg(format!("signing key {:?} isn't in the form id:secret", entry)));
+            }
+            keys.push((id.to_string(), secret.as_bytes().to_vec()));
+        }
+        if keys.is_empty() {
+            return Err(err_msg("no signing keys were given"));
+        }
+        Ok(SigningKeys { keys })
+    }
+
+    /// Keys from EDIT_OP_SIGNING_KEYS, if it's set.
+    pub fn from_env() -> Option<SigningKeys> {
+        let config = env::var(SIGNING_KEYS_VAR).ok()?;
+        match SigningKeys::parse(&config) {
+            Ok(keys) => Some(keys),
+            Err(err) => {
+                eprintln!("(!) ignoring {}: {}", SIGNING_KEYS_VAR, err);
+                None
+            }
+        }
+    }
+
+    fn mac(secret: &[u8], payload: &str) -> HmacSha256 {
+        // HMAC takes keys of any length.
+        let mut mac = HmacSha256::new_varkey(secret).unwrap();
+        mac.input(payload.as_bytes());
+        mac
+    }
+
+    /// Sign a payload with the current key, returning the key's id and the
+    /// signature.
+    pub fn sign(&self, payload: &str) -> (String, String) {
+        let (ref id, ref secret) = self.keys[0];
+        let code = SigningKeys::mac(secret, payload).result().code();
+        (id.clone(), base64::encode_config(&code, base64::URL_SAFE_NO_PAD))
+    }
+
+    /// Check a payload was signed by the key `key_id`.
+    pub fn verify(&self, key_id: &str, signature: &str, payload: &str) -> Result<(), Error> {
+        let secret = self
+            .keys
+            .iter()
+            .find(|(id, _)| id == key_id)
+            .map(|(_, secret)| secret)
+            .ok_or_else(|| err_msg(format!("unknown signing key {:?}", key_id)))?;
+        let code = base64::decode_config(signature, base64::URL_SAFE_NO_PAD)?;
+        SigningKeys::mac(secret, payload)
+            .verify(&code)
+            .map_err(|_| err_msg("signature doesn't match"))
+    }
+
+    /// Sign a command for the page `page_id`, returning the key's id, the
+    /// signature, and the payload that was signed.
+    pub fn sign_command(&self, page_id: &str, command: &ServerCommand) -> (String, String, String) {
+        let payload = SignedPayload {
+            page_id: page_id.to_string(),
+            version: command_version(command),
+            nonce: base64::encode_config(&random::<[u8; 16]>(), base64::URL_SAFE_NO_PAD),
+            signed_at: signing_time(),
+            command: serde_json::to_string(command).unwrap(),
+        };
+        let payload = serde_json::to_string(&payload).unwrap();
+        let (key_id, signature) = self.sign(&payload);
+        (key_id, signature, payload)
+    }
+
+    /// Check a signed command's payload, and that the command is one that's
+    /// signed, for the page `page_id` and the version the payload names.
+    /// Returns the command and its payload, whose nonce the caller checks is
+    /// new.
+    pub fn verify_command(
+        &self,
+        key_id: &str,
+        signature: &str,
+        payload: &str,
+        page_id: &str,
+    ) -> Result<(ServerCommand, SignedPayload), Error> {
+        self.verify(key_id, signature, payload)?;
+        let payload: SignedPayload = serde_json::from_str(payload)?;
+        let command: ServerCommand = serde_json::from_str(&payload.command)?;
+        if !needs_signature(&command) {
+            return Err(err_msg("only commands that change a page can be signed"));
+        }
+        if payload.page_id != page_id {
+            return Err(err_msg(format!("command was signed for page {:?}", payload.page_id)));
+        }
+        if payload.version != command_version(&command) {
+            return Err(err_msg("command was signed for another version"));
+        }
+        Ok((command, payload))
+    }
+}
+
+/// Nonces of recent signed commands, to refuse them if they're sent again.
+/// Only the latest `capacity` are remembered, so commands signed no later
+/// than one that was forgotten are refused too, as are commands signed
+/// before `since`, when nothing was remembered yet.
+#[derive(Debug)]
+pub struct SeenNonces {
+    seen: HashSet<String>,
+    order: VecDeque<(String, u64)>,
+    capacity: usize,
+    since: u64,
+}
+
+impl SeenNonces {
+    pub fn new(capacity: usize, since: u64) -> SeenNonces {
+        SeenNonces {
+            seen: HashSet::new(),
+            order: VecDeque::new(),
+            capacity,
+            since,
+        }
+    }
+
+    /// Remember the nonce of a command signed at `signed_at`, returning
+    /// false if it was already seen or might have been.
+    pub fn insert(&mut self, nonce: &str, signed_at: u64) -> bool {
+        if signed_at < self.since || !self.seen.insert(nonce.to_string()) {
+            return false;
+        }
+        self.order.push_back((nonce.to_string(), signed_at));
+        while self.order.len() > self.capacity {
+            if let Some((oldest, oldest_signed_at)) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+                self.since = cmp::max(self.since, oldest_signed_at + 1);
+            }
+        }
+        true
+    }
+}
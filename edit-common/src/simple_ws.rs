@@ -2,21 +2,37 @@
 
 #![allow(deprecated)]
 
+use crate::commands::MuxMessage;
+use crate::protocol::close::CloseReason;
 use ws;
-use failure::Error;
+use failure::{
+    Error,
+    Fail,
+};
+use serde_json;
+use std::env;
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering;
 use std::sync::{
     Arc,
     Mutex,
 };
+use std::time::{
+    Duration,
+    Instant,
+};
 use ws::util::{
     Timeout,
     Token,
 };
+use ws::deflate::DeflateHandler;
 use ws::{
     CloseCode,
     Frame,
+    Handler,
 };
 
 const PING_INTERVAL: u64 = 5_000;
@@ -24,13 +40,149 @@ const TIMEOUT_INTERVAL: u64 = 30_000;
 
 static TOKEN_COUNTER: AtomicUsize = AtomicUsize::new(1);
 
-pub type Sender = Arc<Mutex<ws::Sender>>;
+/// Sends messages over a connection. A sender for one of the documents on
+/// a multiplexed connection tags them with the document's id, as
+/// `MuxMessage`s.
+#[derive(Clone)]
+pub struct Sender {
+    out: Arc<Mutex<ws::Sender>>,
+    mux_id: Option<String>,
+}
+
+impl Sender {
+    pub fn new(out: Arc<Mutex<ws::Sender>>) -> Sender {
+        Sender { out, mux_id: None }
+    }
+
+    /// A sender for the document with this id, on the same connection.
+    pub fn muxed(&self, mux_id: &str) -> Sender {
+        Sender {
+            out: self.out.clone(),
+            mux_id: Some(mux_id.to_string()),
+        }
+    }
+
+    pub fn send(&self, json: String) -> ws::Result<()> {
+        let out = self.out.lock().unwrap();
+        match self.mux_id {
+            Some(ref mux_id) => out.send(mux_frame(mux_id, &json)),
+            None => out.send(json),
+        }
+    }
+
+    /// Close the connection, or only the document on a multiplexed one.
+    pub fn close(&self, reason: CloseReason, message: &str) -> ws::Result<()> {
+        match self.mux_id {
+            Some(ref mux_id) => {
+                let close = MuxMessage::<()>::Close(
+                    mux_id.to_string(),
+                    Some((reason.code(), message.to_string())),
+                );
+                self.out.lock().unwrap().send(serde_json::to_string(&close).unwrap())
+            }
+            None => self
+                .out
+                .lock()
+                .unwrap()
+                .close_with_reason(CloseCode::from(reason.code()), message.to_string()),
+        }
+    }
+}
+
+/// A `MuxMessage::Frame` tagging an already serialized command with the id
+/// of its document.
+pub fn mux_frame(mux_id: &str, json: &str) -> String {
+    format!("{{\"Frame\":[{},{}]}}", serde_json::to_string(mux_id).unwrap(), json)
+}
+
+/// An error handling a message, which closes the connection with this
+/// reason. Other errors close it as a protocol error, except for I/O
+/// errors, which close it as unavailable for now.
+#[derive(Debug)]
+pub struct CloseError {
+    pub reason: CloseReason,
+    pub message: String,
+}
+
+impl CloseError {
+    /// An error the client can retry after a while, as when the database
+    /// can't be reached.
+    pub fn unavailable<E: fmt::Display>(err: E) -> CloseError {
+        CloseError {
+            reason: CloseReason::Unavailable,
+            message: format!("The server is unavailable ({}). Try again shortly.", err),
+        }
+    }
+}
+
+impl fmt::Display for CloseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Fail for CloseError {}
+
+/// The reason and message to close a connection with after an error
+/// handling one of its messages.
+pub fn close_reason_for(err: &Error) -> (CloseReason, String) {
+    if let Some(err) = err.downcast_ref::<CloseError>() {
+        (err.reason, err.message.clone())
+    } else if let Some(err) = err.downcast_ref::<io::Error>() {
+        let err = CloseError::unavailable(err);
+        (err.reason, err.message)
+    } else {
+        (
+            CloseReason::ProtocolError,
+            "The server couldn't handle a message from this editor.".to_string(),
+        )
+    }
+}
+
+/// Most messages a connection may send per second, from
+/// EDIT_WS_MESSAGE_LIMIT. Unlimited if it isn't set.
+fn message_limit() -> Option<usize> {
+    env::var("EDIT_WS_MESSAGE_LIMIT").ok()?.parse().ok()
+}
+
+/// Counts the messages a connection sent in the current second.
+pub struct MessageRate {
+    limit: Option<usize>,
+    window: Instant,
+    count: usize,
+}
+
+impl MessageRate {
+    pub fn new(limit: Option<usize>) -> MessageRate {
+        MessageRate {
+            limit,
+            window: Instant::now(),
+            count: 0,
+        }
+    }
+
+    /// Count a message received at `now`, returning whether it's within
+    /// the limit.
+    pub fn allow(&mut self, now: Instant) -> bool {
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None => return true,
+        };
+        if now.duration_since(self.window) >= Duration::from_secs(1) {
+            self.window = now;
+            self.count = 0;
+        }
+        self.count += 1;
+        self.count <= limit
+    }
+}
 
 pub struct SocketHandler<S: SimpleSocket> {
     args: Option<S::Args>,
 
     out: Arc<Mutex<ws::Sender>>,
     obj: Option<S>,
+    rate: MessageRate,
 
     timeout: Option<Timeout>,
     ping_event: Token,
@@ -44,6 +196,7 @@ impl<S: SimpleSocket> SocketHandler<S> {
 
             out: Arc::new(Mutex::new(out)),
             obj: None,
+            rate: MessageRate::new(message_limit()),
 
             timeout: None,
             ping_event: Token(TOKEN_COUNTER.fetch_add(1, Ordering::SeqCst)),
@@ -52,9 +205,53 @@ impl<S: SimpleSocket> SocketHandler<S> {
     }
 }
 
+/// Close a connection, with a code telling the other side why and a
+/// message for the user.
+pub fn close_client(out: &Sender, reason: CloseReason, message: &str) {
+    let _ = out.close(reason, message);
+}
+
+/// Address of a reverse proxy whose X-Forwarded-For header is trusted, from
+/// EDIT_TRUSTED_PROXY.
+fn trusted_proxy() -> Option<IpAddr> {
+    env::var("EDIT_TRUSTED_PROXY").ok()?.parse().ok()
+}
+
+/// Address of the other end of a connection. Connections through the
+/// trusted proxy are from the last address it added to X-Forwarded-For.
+/// Anyone else's X-Forwarded-For is ignored, since they could say anything.
+fn remote_addr(shake: &ws::Handshake) -> Option<String> {
+    let peer = shake.peer_addr?.ip();
+    if Some(peer) == trusted_proxy() {
+        let forwarded = shake
+            .request
+            .header("X-Forwarded-For")
+            .map(|value| String::from_utf8_lossy(value).to_string());
+        let client = forwarded.as_ref().and_then(|value| {
+            value
+                .split(',')
+                .map(|addr| addr.trim())
+                .filter(|addr| !addr.is_empty())
+                .last()
+                .map(|addr| addr.to_string())
+        });
+        if client.is_some() {
+            return client;
+        }
+    }
+    Some(peer.to_string())
+}
+
 pub trait SimpleSocket: Sized {
     type Args;
-    fn initialize(args: Self::Args, url: &str, out: Arc<Mutex<ws::Sender>>) -> Result<Self, Error>;
+    fn initialize(
+        args: Self::Args,
+        url: &str,
+        remote_addr: Option<String>,
+        // The handshake's Cookie header.
+        cookie: Option<String>,
+        out: Sender,
+    ) -> Result<Self, Error>;
     fn handle_message(&mut self, data: &[u8]) -> Result<(), Error>;
     fn cleanup(&mut self) -> Result<(), Error>;
 }
@@ -65,7 +262,12 @@ impl<S: SimpleSocket> ws::Handler for SocketHandler<S> {
             S::initialize(
                 self.args.take().unwrap(),
                 shake.request.resource(),
-                self.out.clone(),
+                remote_addr(&shake),
+                shake
+                    .request
+                    .header("Cookie")
+                    .map(|cookie| String::from_utf8_lossy(cookie).to_string()),
+                Sender::new(self.out.clone()),
             ).expect("Failed to start socket handler due to error"),
         );
 
@@ -81,10 +283,28 @@ impl<S: SimpleSocket> ws::Handler for SocketHandler<S> {
     }
 
     fn on_message(&mut self, msg: ws::Message) -> Result<(), ws::Error> {
-        self.obj.as_mut().map(|obj| {
-            obj.handle_message(&msg.into_data())
-                .expect("Could not handle native command.");
-        });
+        if !self.rate.allow(Instant::now()) {
+            eprintln!("(!) Closing socket sending too many messages");
+            close_client(
+                &Sender::new(self.out.clone()),
+                CloseReason::RateLimited,
+                "This editor sent too many changes at once. Reconnecting shortly.",
+            );
+            return Ok(());
+        }
+
+        let result = match self.obj.as_mut() {
+            Some(obj) => obj.handle_message(&msg.into_data()),
+            None => Ok(()),
+        };
+        if let Err(err) = result {
+            let (reason, message) = close_reason_for(&err);
+            eprintln!(
+                "(!) Closing socket after a message it couldn't handle ({:?}): {:?}",
+                reason, err
+            );
+            close_client(&Sender::new(self.out.clone()), reason, &message);
+        }
 
         Ok(())
     }
@@ -105,6 +325,11 @@ impl<S: SimpleSocket> ws::Handler for SocketHandler<S> {
 
     fn on_shutdown(&mut self) {
         println!("Killing after shutdown");
+        close_client(
+            &Sender::new(self.out.clone()),
+            CloseReason::ShuttingDown,
+            "The server is restarting. Reconnecting shortly.",
+        );
         self.obj
             .take()
             .map(|mut x| x.cleanup().expect("Failed to clean up socket"));
@@ -142,3 +367,87 @@ impl<S: SimpleSocket> ws::Handler for SocketHandler<S> {
         Ok(Some(frame))
     }
 }
+
+/// Whether websockets should negotiate permessage-deflate compression.
+/// Set EDIT_WS_DEFLATE=0 to disable it.
+pub fn deflate_enabled() -> bool {
+    env::var("EDIT_WS_DEFLATE")
+        .map(|x| x != "0" && x != "false")
+        .unwrap_or(true)
+}
+
+/// Wraps a handler with permessage-deflate compression, unless it was
+/// disabled with `deflate_enabled()`.
+pub enum MaybeDeflate<H: Handler> {
+    Deflate(DeflateHandler<H>),
+    Plain(H),
+}
+
+impl<H: Handler> MaybeDeflate<H> {
+    pub fn new(handler: H) -> MaybeDeflate<H> {
+        if deflate_enabled() {
+            MaybeDeflate::Deflate(DeflateHandler::new(handler))
+        } else {
+            MaybeDeflate::Plain(handler)
+        }
+    }
+}
+
+macro_rules! delegate {
+    ($this:ident, $method:ident ( $( $arg:expr ),* )) => {
+        match *$this {
+            MaybeDeflate::Deflate(ref mut handler) => handler.$method($( $arg ),*),
+            MaybeDeflate::Plain(ref mut handler) => handler.$method($( $arg ),*),
+        }
+    };
+}
+
+impl<H: Handler> Handler for MaybeDeflate<H> {
+    fn on_shutdown(&mut self) {
+        delegate!(self, on_shutdown())
+    }
+
+    fn on_open(&mut self, shake: ws::Handshake) -> ws::Result<()> {
+        delegate!(self, on_open(shake))
+    }
+
+    fn on_message(&mut self, msg: ws::Message) -> ws::Result<()> {
+        delegate!(self, on_message(msg))
+    }
+
+    fn on_close(&mut self, code: CloseCode, reason: &str) {
+        delegate!(self, on_close(code, reason))
+    }
+
+    fn on_error(&mut self, err: ws::Error) {
+        delegate!(self, on_error(err))
+    }
+
+    fn on_request(&mut self, req: &ws::Request) -> ws::Result<ws::Response> {
+        delegate!(self, on_request(req))
+    }
+
+    fn on_response(&mut self, res: &ws::Response) -> ws::Result<()> {
+        delegate!(self, on_response(res))
+    }
+
+    fn on_timeout(&mut self, event: Token) -> ws::Result<()> {
+        delegate!(self, on_timeout(event))
+    }
+
+    fn on_new_timeout(&mut self, event: Token, timeout: Timeout) -> ws::Result<()> {
+        delegate!(self, on_new_timeout(event, timeout))
+    }
+
+    fn on_frame(&mut self, frame: Frame) -> ws::Result<Option<Frame>> {
+        delegate!(self, on_frame(frame))
+    }
+
+    fn on_send_frame(&mut self, frame: Frame) -> ws::Result<Option<Frame>> {
+        delegate!(self, on_send_frame(frame))
+    }
+
+    fn build_request(&mut self, url: &::url::Url) -> ws::Result<ws::Request> {
+        delegate!(self, build_request(url))
+    }
+}
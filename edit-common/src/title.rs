@@ -0,0 +1,82 @@
+//! Derives a document's title from its contents.
+
+use oatie::doc::*;
+use oatie::schema::{
+    RtfSchema,
+    RtfTrack,
+};
+use oatie::Schema;
+
+const TITLE_MAX_CHARS: usize = 120;
+
+fn is_heading(attrs: &Attrs) -> bool {
+    match attrs.get("tag").map(|tag| tag.as_str()) {
+        Some("h1") | Some("h2") | Some("h3") | Some("h4") | Some("h5") | Some("h6") => true,
+        _ => false,
+    }
+}
+
+fn block_text(span: &DocSpan, out: &mut String) {
+    for elem in span {
+        match elem {
+            DocChars(text) => out.push_str(text.as_str()),
+            DocGroup(_, span) => block_text(span, out),
+        }
+    }
+}
+
+// Collapse whitespace and truncate, returning None for blank text.
+fn clean_title(text: &str) -> Option<String> {
+    let title = text
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(TITLE_MAX_CHARS)
+        .collect::<String>();
+    if title.is_empty() {
+        None
+    } else {
+        Some(title)
+    }
+}
+
+fn first_heading(span: &DocSpan) -> Option<String> {
+    for elem in span {
+        if let DocGroup(attrs, inner) = elem {
+            if is_heading(attrs) {
+                let mut text = String::new();
+                block_text(inner, &mut text);
+                if let Some(title) = clean_title(&text) {
+                    return Some(title);
+                }
+            } else if let Some(title) = first_heading(inner) {
+                return Some(title);
+            }
+        }
+    }
+    None
+}
+
+fn first_line(span: &DocSpan) -> Option<String> {
+    for elem in span {
+        if let DocGroup(attrs, inner) = elem {
+            if RtfSchema::track_type_from_attrs(attrs) == Some(RtfTrack::Blocks) {
+                let mut text = String::new();
+                block_text(inner, &mut text);
+                if let Some(title) = text.lines().filter_map(clean_title).next() {
+                    return Some(title);
+                }
+            } else if let Some(title) = first_line(inner) {
+                return Some(title);
+            }
+        }
+    }
+    None
+}
+
+/// The title of a document: the text of its first non-empty heading, or
+/// failing that its first non-empty line. None if the document is blank.
+pub fn doc_title(doc: &DocSpan) -> Option<String> {
+    first_heading(doc).or_else(|| first_line(doc))
+}
@@ -0,0 +1,580 @@
+//! A bridge to Yjs, reading and writing Yjs updates (in the v1 encoding)
+//! for a document held in a Y.Text, as y-quill keeps it. Its contents and
+//! formatting attributes are those of a Quill Delta, so documents go through
+//! the conversions in `quill`.
+//!
+//! Other shared types in an update are read past and ignored. Updates from
+//! several sources should be merged, as with Y.mergeUpdates, before they're
+//! read here.
+
+use crate::quill::{
+    delta_to_doc,
+    doc_to_delta,
+    Delta,
+};
+use failure::{
+    err_msg,
+    Error,
+};
+use oatie::doc::*;
+use serde_json::{
+    self,
+    Map,
+    Number,
+    Value,
+};
+use std::collections::{
+    HashMap,
+    HashSet,
+};
+
+// Content types, from the low bits of an item's info byte.
+const CONTENT_DELETED: u8 = 1;
+const CONTENT_JSON: u8 = 2;
+const CONTENT_BINARY: u8 = 3;
+const CONTENT_STRING: u8 = 4;
+const CONTENT_EMBED: u8 = 5;
+const CONTENT_FORMAT: u8 = 6;
+const CONTENT_TYPE: u8 = 7;
+const CONTENT_ANY: u8 = 8;
+const CONTENT_DOC: u8 = 9;
+const STRUCT_GC: u8 = 0;
+const STRUCT_SKIP: u8 = 10;
+
+const HAS_ORIGIN: u8 = 0x80;
+const HAS_RIGHT_ORIGIN: u8 = 0x40;
+const HAS_PARENT_SUB: u8 = 0x20;
+
+// Type refs of XML types, which are followed by a node name.
+const TYPE_XML_ELEMENT: u64 = 3;
+const TYPE_XML_HOOK: u64 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Id {
+    client: u64,
+    clock: u64,
+}
+
+fn write_var_uint(out: &mut Vec<u8>, mut value: u64) {
+    while value > 0x7f {
+        out.push(0x80 | (value & 0x7f) as u8);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_var_string(out: &mut Vec<u8>, value: &str) {
+    write_var_uint(out, value.len() as u64);
+    out.extend_from_slice(value.as_bytes());
+}
+
+struct Encoder {
+    out: Vec<u8>,
+    structs: usize,
+    client: u64,
+    clock: u64,
+    type_name: String,
+}
+
+impl Encoder {
+    // Append an item after the last one.
+    fn item(&mut self, content: u8, length: u64, write_content: &Fn(&mut Vec<u8>)) {
+        if self.clock == 0 {
+            self.out.push(content);
+            // The parent is a root type, named by its key.
+            write_var_uint(&mut self.out, 1);
+            write_var_string(&mut self.out, &self.type_name);
+        } else {
+            self.out.push(content | HAS_ORIGIN);
+            write_var_uint(&mut self.out, self.client);
+            write_var_uint(&mut self.out, self.clock - 1);
+        }
+        write_content(&mut self.out);
+        self.clock += length;
+        self.structs += 1;
+    }
+
+    fn format(&mut self, key: &str, value: &Value) {
+        let value = value.to_string();
+        self.item(CONTENT_FORMAT, 1, &|out| {
+            write_var_string(out, key);
+            write_var_string(out, &value);
+        });
+    }
+}
+
+/// A Yjs update creating a Y.Text named `type_name` with a document's
+/// contents, written by the Yjs client `client`.
+pub fn doc_to_yjs_update(doc: &DocSpan, client: u64, type_name: &str) -> Vec<u8> {
+    let mut encoder = Encoder {
+        out: vec![],
+        structs: 0,
+        client,
+        clock: 0,
+        type_name: type_name.to_string(),
+    };
+    for op in doc_to_delta(doc).ops {
+        // Formatting attributes surround the content they apply to.
+        let mut keys = op.attributes.keys().cloned().collect::<Vec<_>>();
+        keys.sort();
+        for key in &keys {
+            encoder.format(key, &op.attributes[key]);
+        }
+        match op.insert {
+            Some(Value::String(ref text)) => {
+                let length = text.encode_utf16().count() as u64;
+                encoder.item(CONTENT_STRING, length, &|out| write_var_string(out, text));
+            }
+            Some(ref embed) => {
+                let embed = embed.to_string();
+                encoder.item(CONTENT_EMBED, 1, &|out| write_var_string(out, &embed));
+            }
+            None => {}
+        }
+        for key in &keys {
+            encoder.format(key, &Value::Null);
+        }
+    }
+
+    let mut update = vec![];
+    if encoder.structs > 0 {
+        write_var_uint(&mut update, 1);
+        write_var_uint(&mut update, encoder.structs as u64);
+        write_var_uint(&mut update, client);
+        write_var_uint(&mut update, 0);
+        update.extend(encoder.out);
+    } else {
+        write_var_uint(&mut update, 0);
+    }
+    // No deletions.
+    write_var_uint(&mut update, 0);
+    update
+}
+
+struct Decoder<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn u8(&mut self) -> Result<u8, Error> {
+        let value = *self
+            .input
+            .get(self.pos)
+            .ok_or_else(|| err_msg("unexpected end of the update"))?;
+        self.pos += 1;
+        Ok(value)
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.pos + len > self.input.len() {
+            return Err(err_msg("unexpected end of the update"));
+        }
+        let bytes = &self.input[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn var_uint(&mut self) -> Result<u64, Error> {
+        let mut value = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte < 0x80 {
+                return Ok(value);
+            }
+            shift += 7;
+            if shift > 63 {
+                return Err(err_msg("variable length integer is too long"));
+            }
+        }
+    }
+
+    // The first byte holds a sign bit and six bits of the value.
+    fn var_int(&mut self) -> Result<i64, Error> {
+        let byte = self.u8()?;
+        let negative = byte & 0x40 != 0;
+        let mut value = i64::from(byte & 0x3f);
+        let mut shift = 6;
+        let mut more = byte & 0x80 != 0;
+        while more {
+            let byte = self.u8()?;
+            if shift > 62 {
+                return Err(err_msg("variable length integer is too long"));
+            }
+            value |= i64::from(byte & 0x7f) << shift;
+            shift += 7;
+            more = byte & 0x80 != 0;
+        }
+        Ok(if negative { -value } else { value })
+    }
+
+    fn var_string(&mut self) -> Result<String, Error> {
+        let len = self.var_uint()? as usize;
+        Ok(String::from_utf8(self.bytes(len)?.to_vec())?)
+    }
+
+    fn json(&mut self) -> Result<Value, Error> {
+        Ok(serde_json::from_str(&self.var_string()?)?)
+    }
+
+    fn be_bytes(&mut self, len: usize) -> Result<u64, Error> {
+        Ok(self
+            .bytes(len)?
+            .iter()
+            .fold(0u64, |value, byte| (value << 8) | u64::from(*byte)))
+    }
+
+    // A value in lib0's encoding of JavaScript values.
+    fn any(&mut self) -> Result<Value, Error> {
+        Ok(match self.u8()? {
+            127 | 126 => Value::Null,
+            125 => Value::from(self.var_int()?),
+            124 => {
+                let value = f32::from_bits(self.be_bytes(4)? as u32);
+                Number::from_f64(f64::from(value)).map(Value::Number).unwrap_or(Value::Null)
+            }
+            123 => {
+                let value = f64::from_bits(self.be_bytes(8)?);
+                Number::from_f64(value).map(Value::Number).unwrap_or(Value::Null)
+            }
+            122 => Value::from(self.be_bytes(8)? as i64),
+            121 => Value::Bool(false),
+            120 => Value::Bool(true),
+            119 => Value::String(self.var_string()?),
+            118 => {
+                let len = self.var_uint()?;
+                let mut map = Map::new();
+                for _ in 0..len {
+                    let key = self.var_string()?;
+                    map.insert(key, self.any()?);
+                }
+                Value::Object(map)
+            }
+            117 => {
+                let len = self.var_uint()?;
+                let mut values = vec![];
+                for _ in 0..len {
+                    values.push(self.any()?);
+                }
+                Value::Array(values)
+            }
+            116 => {
+                let len = self.var_uint()? as usize;
+                Value::Array(self.bytes(len)?.iter().map(|x| Value::from(*x)).collect())
+            }
+            tag => return Err(err_msg(format!("unknown value type {}", tag))),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Content {
+    Char(char),
+    Format(String, Value),
+    Embed(Value),
+    // Content no Y.Text is made of.
+    Other,
+}
+
+#[derive(Debug, Clone)]
+enum Parent {
+    Root(String),
+    // A nested type, which the bridge doesn't read.
+    Nested,
+}
+
+#[derive(Debug, Clone)]
+struct Item {
+    id: Id,
+    origin: Option<Id>,
+    right_origin: Option<Id>,
+    parent: Option<Parent>,
+    // Each takes one clock tick, except characters outside of the BMP,
+    // which take two, as Yjs counts in UTF-16 code units.
+    contents: Vec<Content>,
+}
+
+// The contents of an item, and how many clock ticks it takes.
+fn read_contents(decoder: &mut Decoder, content: u8) -> Result<(Vec<Content>, u64), Error> {
+    Ok(match content {
+        CONTENT_DELETED => {
+            let len = decoder.var_uint()?;
+            ((0..len).map(|_| Content::Other).collect(), len)
+        }
+        CONTENT_JSON => {
+            let len = decoder.var_uint()?;
+            for _ in 0..len {
+                decoder.var_string()?;
+            }
+            ((0..len).map(|_| Content::Other).collect(), len)
+        }
+        CONTENT_BINARY => {
+            let len = decoder.var_uint()? as usize;
+            decoder.bytes(len)?;
+            (vec![Content::Other], 1)
+        }
+        CONTENT_STRING => {
+            let text = decoder.var_string()?;
+            let len = text.encode_utf16().count() as u64;
+            (text.chars().map(Content::Char).collect(), len)
+        }
+        CONTENT_EMBED => (vec![Content::Embed(decoder.json()?)], 1),
+        CONTENT_FORMAT => {
+            let key = decoder.var_string()?;
+            (vec![Content::Format(key, decoder.json()?)], 1)
+        }
+        CONTENT_TYPE => {
+            let type_ref = decoder.var_uint()?;
+            if type_ref == TYPE_XML_ELEMENT || type_ref == TYPE_XML_HOOK {
+                decoder.var_string()?;
+            }
+            (vec![Content::Other], 1)
+        }
+        CONTENT_ANY => {
+            let len = decoder.var_uint()?;
+            for _ in 0..len {
+                decoder.any()?;
+            }
+            ((0..len).map(|_| Content::Other).collect(), len)
+        }
+        CONTENT_DOC => {
+            decoder.var_string()?;
+            decoder.any()?;
+            (vec![Content::Other], 1)
+        }
+        other => return Err(err_msg(format!("unknown content type {}", other))),
+    })
+}
+
+struct Update {
+    items: Vec<Item>,
+    // Ranges of clocks that were garbage collected, by client.
+    gc: Vec<(u64, u64, u64)>,
+    deleted: Vec<(u64, u64, u64)>,
+}
+
+fn read_update(input: &[u8]) -> Result<Update, Error> {
+    let mut decoder = Decoder { input, pos: 0 };
+    let mut update = Update {
+        items: vec![],
+        gc: vec![],
+        deleted: vec![],
+    };
+
+    for _ in 0..decoder.var_uint()? {
+        let structs = decoder.var_uint()?;
+        let client = decoder.var_uint()?;
+        let mut clock = decoder.var_uint()?;
+        for _ in 0..structs {
+            let info = decoder.u8()?;
+            match info & 0x1f {
+                STRUCT_GC => {
+                    let len = decoder.var_uint()?;
+                    update.gc.push((client, clock, len));
+                    clock += len;
+                }
+                STRUCT_SKIP => clock += decoder.var_uint()?,
+                content => {
+                    let origin = if info & HAS_ORIGIN != 0 {
+                        Some(Id {
+                            client: decoder.var_uint()?,
+                            clock: decoder.var_uint()?,
+                        })
+                    } else {
+                        None
+                    };
+                    let right_origin = if info & HAS_RIGHT_ORIGIN != 0 {
+                        Some(Id {
+                            client: decoder.var_uint()?,
+                            clock: decoder.var_uint()?,
+                        })
+                    } else {
+                        None
+                    };
+                    let parent = if origin.is_none() && right_origin.is_none() {
+                        let parent = if decoder.var_uint()? == 1 {
+                            Parent::Root(decoder.var_string()?)
+                        } else {
+                            decoder.var_uint()?;
+                            decoder.var_uint()?;
+                            Parent::Nested
+                        };
+                        // Entries of maps name their key.
+                        if info & HAS_PARENT_SUB != 0 {
+                            decoder.var_string()?;
+                            Some(Parent::Nested)
+                        } else {
+                            Some(parent)
+                        }
+                    } else {
+                        None
+                    };
+                    let (contents, len) = read_contents(&mut decoder, content)?;
+                    update.items.push(Item {
+                        id: Id { client, clock },
+                        origin,
+                        right_origin,
+                        parent,
+                        contents,
+                    });
+                    clock += len;
+                }
+            }
+        }
+    }
+
+    for _ in 0..decoder.var_uint()? {
+        let client = decoder.var_uint()?;
+        for _ in 0..decoder.var_uint()? {
+            let clock = decoder.var_uint()?;
+            let len = decoder.var_uint()?;
+            update.deleted.push((client, clock, len));
+        }
+    }
+    Ok(update)
+}
+
+fn in_ranges(ranges: &[(u64, u64, u64)], id: Id) -> bool {
+    ranges
+        .iter()
+        .any(|(client, clock, len)| *client == id.client && id.clock >= *clock && id.clock < clock + len)
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    id: Id,
+    origin: Option<Id>,
+    right_origin: Option<Id>,
+    content: Content,
+}
+
+// Insert a node where Yjs would, between its origins, ordering it among
+// concurrent insertions at the same place.
+fn integrate(nodes: &mut Vec<Node>, node: Node) {
+    let position = |nodes: &Vec<Node>, id: Option<Id>| id.and_then(|id| nodes.iter().position(|x| x.id == id));
+    let mut left = position(nodes, node.origin);
+    let right = position(nodes, node.right_origin).unwrap_or(nodes.len());
+
+    let mut before_origin = HashSet::new();
+    let mut conflicting = HashSet::new();
+    let mut index = left.map(|x| x + 1).unwrap_or(0);
+    while index < right {
+        let other = &nodes[index];
+        before_origin.insert(other.id);
+        conflicting.insert(other.id);
+        if other.origin == node.origin {
+            if other.id.client < node.id.client {
+                left = Some(index);
+                conflicting.clear();
+            } else if other.right_origin == node.right_origin {
+                break;
+            }
+        } else if let Some(origin) = other.origin.filter(|x| before_origin.contains(x)) {
+            if !conflicting.contains(&origin) {
+                left = Some(index);
+                conflicting.clear();
+            }
+        } else {
+            break;
+        }
+        index += 1;
+    }
+    nodes.insert(left.map(|x| x + 1).unwrap_or(0), node);
+}
+
+/// Read the Y.Text named `type_name` in a Yjs update as a Delta.
+pub fn yjs_update_to_delta(input: &[u8], type_name: &str) -> Result<Delta, Error> {
+    let update = read_update(input)?;
+
+    // Whether the unit with an id is in the text, for every unit read.
+    let mut in_text: HashMap<Id, bool> = HashMap::new();
+    let mut nodes: Vec<Node> = vec![];
+    let mut pending = update.items;
+    loop {
+        let count = pending.len();
+        for item in ::std::mem::replace(&mut pending, vec![]) {
+            // Items go where their origins are, once those are read.
+            let (origin, right_origin) = {
+                let membership = |id: Option<Id>| -> Option<Option<bool>> {
+                    match id {
+                        None => Some(None),
+                        Some(id) if in_ranges(&update.gc, id) => Some(Some(false)),
+                        Some(id) => in_text.get(&id).map(|x| Some(*x)),
+                    }
+                };
+                match (membership(item.origin), membership(item.right_origin)) {
+                    (Some(origin), Some(right_origin)) => (origin, right_origin),
+                    _ => {
+                        pending.push(item);
+                        continue;
+                    }
+                }
+            };
+            let text = match item.parent {
+                Some(Parent::Root(ref name)) => name == type_name,
+                Some(Parent::Nested) => false,
+                None => origin.or(right_origin).unwrap_or(false),
+            };
+
+            let mut previous = item.origin;
+            let mut clock = item.id.clock;
+            for content in item.contents {
+                let id = Id {
+                    client: item.id.client,
+                    clock,
+                };
+                clock += match content {
+                    Content::Char(c) => c.len_utf16() as u64,
+                    _ => 1,
+                };
+                in_text.insert(id, text);
+                if text {
+                    integrate(
+                        &mut nodes,
+                        Node {
+                            id,
+                            origin: previous,
+                            right_origin: item.right_origin,
+                            content,
+                        },
+                    );
+                }
+                previous = Some(id);
+            }
+        }
+        if pending.is_empty() {
+            break;
+        }
+        if pending.len() == count {
+            return Err(err_msg("the update refers to content it doesn't include"));
+        }
+    }
+
+    // Deleted formatting attributes don't apply.
+    let mut delta = Delta::default();
+    let mut attributes = Map::new();
+    for node in nodes {
+        if in_ranges(&update.deleted, node.id) {
+            continue;
+        }
+        match node.content {
+            Content::Char(c) => delta.push_text(&c.to_string(), attributes.clone()),
+            Content::Embed(embed) => delta.push_insert(embed, attributes.clone()),
+            Content::Format(key, Value::Null) => {
+                attributes.remove(&key);
+            }
+            Content::Format(key, value) => {
+                attributes.insert(key, value);
+            }
+            Content::Other => {}
+        }
+    }
+    Ok(delta)
+}
+
+/// Read the Y.Text named `type_name` in a Yjs update as a document.
+pub fn yjs_update_to_doc(input: &[u8], type_name: &str) -> Result<DocSpan, Error> {
+    delta_to_doc(&yjs_update_to_delta(input, type_name)?)
+}
@@ -0,0 +1,43 @@
+extern crate edit_common;
+
+use edit_common::comments::*;
+
+fn thread(id: &str, resolved: bool) -> CommentThread {
+    CommentThread {
+        id: id.to_string(),
+        anchor: "block".to_string(),
+        resolved,
+        comments: vec![Comment {
+            author: "someone".to_string(),
+            body: "a comment".to_string(),
+            created: 0,
+        }],
+    }
+}
+
+#[test]
+fn comments_are_trimmed_and_cut() {
+    assert_eq!(clean_comment("  hello \n"), Some("hello".to_string()));
+    assert_eq!(clean_comment(" \n\t"), None);
+
+    let long = "x".repeat(MAX_COMMENT_LEN + 10);
+    assert_eq!(clean_comment(&long).unwrap().chars().count(), MAX_COMMENT_LEN);
+}
+
+#[test]
+fn resolved_threads_are_hidden_unless_asked_for() {
+    let threads = vec![thread("a", false), thread("b", true), thread("c", false)];
+    let ids = |threads: Vec<CommentThread>| threads.into_iter().map(|x| x.id).collect::<Vec<_>>();
+    assert_eq!(ids(visible_threads(&threads, false)), vec!["a", "c"]);
+    assert_eq!(ids(visible_threads(&threads, true)), vec!["a", "b", "c"]);
+}
+
+#[test]
+fn only_starting_and_replying_add_comments() {
+    let start = CommentAction::Start("block".to_string(), "first".to_string());
+    let reply = CommentAction::Reply("thread".to_string(), "second".to_string());
+    assert_eq!(start.body(), Some("first"));
+    assert_eq!(reply.body(), Some("second"));
+    assert_eq!(CommentAction::Resolve("thread".to_string()).body(), None);
+    assert_eq!(CommentAction::Reopen("thread".to_string()).body(), None);
+}
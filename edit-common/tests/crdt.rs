@@ -0,0 +1,219 @@
+//! Replicas of a CrdtDoc converge whatever order they're sent operations in.
+
+extern crate edit_common;
+#[macro_use]
+extern crate oatie;
+
+use edit_common::crdt::*;
+use oatie::doc::*;
+
+fn paragraphs(texts: &[&str]) -> Doc {
+    Doc(texts
+        .iter()
+        .flat_map(|text| doc_span![DocGroup({"tag": "p"}, [DocChars(text)])])
+        .collect())
+}
+
+fn texts(replica: &CrdtDoc) -> Vec<String> {
+    replica
+        .to_doc()
+        .0
+        .iter()
+        .map(|elem| match elem {
+            DocGroup(_, inner) => match inner.first() {
+                Some(DocChars(text)) => text.to_string(),
+                _ => String::new(),
+            },
+            DocChars(_) => panic!("text outside a block"),
+        })
+        .collect()
+}
+
+// Two replicas of the same document.
+fn replicas(texts: &[&str]) -> (CrdtDoc, CrdtDoc) {
+    let doc = paragraphs(texts);
+    (CrdtDoc::from_doc(1, &doc), CrdtDoc::from_doc(2, &doc))
+}
+
+#[test]
+fn from_doc_roundtrip() {
+    let doc = doc_span![
+        DocGroup({"tag": "h1"}, [DocChars("Title")]),
+        DocGroup({"tag": "p"}, [
+            DocChars("one "),
+            DocGroup({"tag": "caret", "client": "a"}, []),
+            DocChars("two"),
+        ]),
+        DocGroup({"tag": "bullet"}, [DocGroup({"tag": "p"}, [DocChars("item")])]),
+        DocGroup({"tag": "p"}, []),
+    ];
+    let replica = CrdtDoc::from_doc(1, &Doc(doc));
+    assert_eq!(
+        replica.to_doc().0,
+        doc_span![
+            DocGroup({"tag": "h1"}, [DocChars("Title")]),
+            DocGroup({"tag": "p"}, [DocChars("one two")]),
+            DocGroup({"tag": "p"}, [DocChars("item")]),
+            DocGroup({"tag": "p"}, []),
+        ]
+    );
+}
+
+#[test]
+fn concurrent_inserts_at_the_same_place() {
+    let (mut a, mut b) = replicas(&["ac"]);
+    let op_a = a.insert_char(0, 1, 'x').unwrap();
+    let op_b = b.insert_char(0, 1, 'y').unwrap();
+    a.apply_remote(op_b);
+    b.apply_remote(op_a);
+    assert_eq!(texts(&a), texts(&b));
+    assert_eq!(texts(&a)[0].len(), 4);
+}
+
+#[test]
+fn concurrent_insert_and_delete() {
+    let (mut a, mut b) = replicas(&["abc"]);
+    // One replica deletes the "b" the other types after.
+    let delete = a.delete_char(0, 1).unwrap();
+    let insert = b.insert_char(0, 2, 'x').unwrap();
+    a.apply_remote(insert);
+    b.apply_remote(delete);
+    assert_eq!(texts(&a), vec!["axc"]);
+    assert_eq!(texts(&b), vec!["axc"]);
+}
+
+#[test]
+fn typing_into_a_deleted_block() {
+    let (mut a, mut b) = replicas(&["one", "two"]);
+    let delete = a.delete_block(1).unwrap();
+    let insert = b.insert_char(1, 3, 's').unwrap();
+    a.apply_remote(insert);
+    b.apply_remote(delete);
+    assert_eq!(texts(&a), vec!["one"]);
+    assert_eq!(texts(&b), vec!["one"]);
+}
+
+#[test]
+fn operations_wait_for_what_they_refer_to() {
+    let (mut a, mut b) = replicas(&["one"]);
+    let block = a.insert_block(1, "h2");
+    let first = a.insert_char(1, 0, 'h').unwrap();
+    let second = a.insert_char(1, 1, 'i').unwrap();
+
+    // Sent backwards, the characters wait for their block.
+    b.apply_remote(second.clone());
+    b.apply_remote(first.clone());
+    assert_eq!(b.pending_count(), 2);
+    assert_eq!(texts(&b), vec!["one"]);
+    b.apply_remote(block.clone());
+    assert_eq!(b.pending_count(), 0);
+    assert_eq!(b.to_doc(), a.to_doc());
+
+    // Operations sent again change nothing.
+    b.apply_remote_ops(vec![block, first, second]);
+    assert_eq!(b.to_doc(), a.to_doc());
+}
+
+#[test]
+fn edit_matches_the_editor() {
+    let (mut a, mut b) = replicas(&["one", "two", "three"]);
+    let edited = Doc(doc_span![
+        DocGroup({"tag": "p"}, [DocChars("one")]),
+        DocGroup({"tag": "h1"}, [DocChars("two")]),
+        DocGroup({"tag": "p"}, [DocChars("thirty")]),
+        DocGroup({"tag": "p"}, [DocChars("four")]),
+    ]);
+    let ops = a.edit(&edited);
+    assert_eq!(a.to_doc(), edited);
+
+    // Unchanged blocks and characters are left alone.
+    assert!(ops.len() < 20, "{:?}", ops);
+    assert_eq!(a.edit(&edited), vec![]);
+
+    b.apply_remote_ops(ops);
+    assert_eq!(b.to_doc(), edited);
+}
+
+#[test]
+fn concurrent_edits_converge() {
+    let (mut a, mut b) = replicas(&["hello world"]);
+    let ops_a = a.edit(&paragraphs(&["hello there world"]));
+    let ops_b = b.edit(&paragraphs(&["hello world", "again"]));
+    a.apply_remote_ops(ops_b);
+    b.apply_remote_ops(ops_a);
+    assert_eq!(texts(&a), vec!["hello there world", "again"]);
+    assert_eq!(texts(&b), texts(&a));
+}
+
+// A small random number generator, so failures can be reproduced.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self, max: usize) -> usize {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        ((self.0 >> 33) as usize) % max
+    }
+}
+
+// Make a random edit on a replica.
+fn random_edit(rng: &mut Lcg, replica: &mut CrdtDoc) -> Vec<CrdtOp> {
+    let blocks = texts(replica);
+    if blocks.is_empty() {
+        return vec![replica.insert_block(0, "p")];
+    }
+    let block = rng.next(blocks.len());
+    let len = blocks[block].chars().count();
+    match rng.next(10) {
+        0 => vec![replica.insert_block(rng.next(blocks.len() + 1), "p")],
+        1 if blocks.len() > 1 => replica.delete_block(block).into_iter().collect(),
+        2 | 3 if len > 0 => replica.delete_char(block, rng.next(len)).into_iter().collect(),
+        _ => {
+            let value = (b'a' + rng.next(26) as u8) as char;
+            replica.insert_char(block, rng.next(len + 1), value).into_iter().collect()
+        }
+    }
+}
+
+#[test]
+fn random_edits_converge_in_any_order() {
+    for seed in 0..20 {
+        let mut rng = Lcg(seed);
+        let doc = paragraphs(&["one", "two"]);
+        let mut sites = (1..4).map(|site| CrdtDoc::from_doc(site, &doc)).collect::<Vec<_>>();
+
+        // Each replica edits on its own for a while, sharing what it's done
+        // with a random other replica now and then.
+        let mut made: Vec<Vec<CrdtOp>> = vec![vec![]; sites.len()];
+        for _ in 0..60 {
+            let site = rng.next(sites.len());
+            let ops = random_edit(&mut rng, &mut sites[site]);
+            made[site].extend(ops);
+            if rng.next(4) == 0 {
+                let from = rng.next(sites.len());
+                let to = rng.next(sites.len());
+                if from != to {
+                    let ops = made[from].clone();
+                    sites[to].apply_remote_ops(ops);
+                }
+            }
+        }
+
+        // Then everyone is sent everything, shuffled.
+        for (to, replica) in sites.iter_mut().enumerate() {
+            let mut ops = made
+                .iter()
+                .enumerate()
+                .filter(|(from, _)| *from != to)
+                .flat_map(|(_, ops)| ops.clone())
+                .collect::<Vec<_>>();
+            for index in (1..ops.len()).rev() {
+                ops.swap(index, rng.next(index + 1));
+            }
+            replica.apply_remote_ops(ops);
+            assert_eq!(replica.pending_count(), 0);
+        }
+
+        assert_eq!(sites[0].to_doc(), sites[1].to_doc(), "seed {}", seed);
+        assert_eq!(sites[1].to_doc(), sites[2].to_doc(), "seed {}", seed);
+    }
+}
@@ -0,0 +1,89 @@
+//! Documents rendered to the HTML the editor shows.
+
+extern crate edit_common;
+#[macro_use]
+extern crate oatie;
+
+use edit_common::doc_as_html;
+use edit_common::embed::embed_src;
+use edit_common::link::Link;
+use oatie::doc::*;
+
+// Whether a quote in a value ended its attribute, so the handler after it
+// became an attribute of its own.
+fn injected(html: &str) -> bool {
+    html.contains("onmouseover=\"")
+}
+
+#[test]
+fn link_titles_stay_in_their_attribute() {
+    let link = Link {
+        href: "https://example.com".to_string(),
+        title: Some("x\" onmouseover=\"alert(1)".to_string()),
+        new_tab: false,
+    };
+    let doc = doc_span![DocGroup({"tag": "p"}, [
+        DocChars("link", { Style::Link => Some(link.to_style_value()) }),
+    ])];
+    let html = doc_as_html(&doc);
+    assert!(html.contains("title=\"x&quot;"));
+    assert!(!injected(&html));
+}
+
+#[test]
+fn block_attributes_are_escaped() {
+    let doc = doc_span![DocGroup({"tag": "p", "class": "a\" onmouseover=\"b", "id": "<x>"}, [])];
+    let html = doc_as_html(&doc);
+    assert!(!injected(&html));
+    assert!(!html.contains("<x>"));
+}
+
+#[test]
+fn embeds_need_valid_video_ids() {
+    assert_eq!(
+        embed_src("https://youtu.be/dQw4w9WgXcQ?t=10"),
+        Some("https://www.youtube.com/embed/dQw4w9WgXcQ".to_string())
+    );
+    assert_eq!(embed_src("https://www.youtube.com/watch?v=a\"b"), None);
+    assert_eq!(embed_src("https://vimeo.com/"), None);
+
+    let url = "https://example.com/\"onmouseover=\"x";
+    let doc = doc_span![DocGroup({"tag": "embed", "url": url}, [])];
+    let html = doc_as_html(&doc);
+    assert!(html.contains("<iframe src=\"https"));
+    assert!(!injected(&html));
+}
+
+#[test]
+fn images_load_only_web_and_asset_urls() {
+    let doc = doc_span![
+        DocGroup({"tag": "img", "src": "/$/assets/a.png", "alt": "a\" onmouseover=\"b"}, []),
+        DocGroup({"tag": "img", "src": "javascript:alert(1)", "alt": ""}, []),
+    ];
+    let html = doc_as_html(&doc);
+    assert!(html.contains("assets"));
+    assert!(html.contains("<img src=\"\" alt=\"\">"));
+    assert!(!injected(&html));
+}
+
+#[test]
+fn attachments_link_only_to_files_and_the_web() {
+    let name = "b\" onmouseover=\"c";
+    let doc = doc_span![
+        DocGroup({"tag": "attachment", "url": "javascript:alert(1)", "filename": "a.txt"}, []),
+        DocGroup({"tag": "attachment", "url": "/$/assets/b", "filename": name}, []),
+    ];
+    let html = doc_as_html(&doc);
+    assert!(html.contains("<a href=\"\""));
+    assert!(!html.contains("javascript"));
+    assert!(!injected(&html));
+}
+
+#[test]
+fn latex_source_is_escaped() {
+    let doc = doc_span![DocGroup({"tag": "math", "latex": "\\text{\" onmouseover=\"x}"}, [])];
+    let html = doc_as_html(&doc);
+    // Backslashes are kept as they are, not escaped as in JSON.
+    assert!(!html.contains("\\\\"));
+    assert!(!injected(&html));
+}
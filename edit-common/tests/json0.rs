@@ -0,0 +1,119 @@
+//! Text edits converted to json0 and text operations and back.
+
+extern crate edit_common;
+#[macro_use]
+extern crate oatie;
+#[macro_use]
+extern crate serde_json;
+
+use edit_common::json0::*;
+use oatie::doc::*;
+use oatie::OT;
+
+fn doc() -> Doc {
+    Doc(doc_span![
+        DocGroup({"tag": "p"}, [DocChars("hello")]),
+        DocGroup({"tag": "bullet"}, [DocGroup({"tag": "p"}, [DocChars("item")])]),
+        DocGroup({"tag": "p"}, [DocChars("x"), DocGroup({"tag": "math", "latex": "y"}, [])]),
+    ])
+}
+
+fn insert(block: usize, offset: usize, text: &str) -> Json0Component {
+    Json0Component {
+        p: vec![block, offset],
+        si: Some(text.to_string()),
+        sd: None,
+    }
+}
+
+fn delete(block: usize, offset: usize, text: &str) -> Json0Component {
+    Json0Component {
+        p: vec![block, offset],
+        si: None,
+        sd: Some(text.to_string()),
+    }
+}
+
+#[test]
+fn snapshots() {
+    let doc = doc();
+    assert_eq!(doc_to_json0(&doc.0), vec!["hello", "item", "x\u{fffc}"]);
+    assert_eq!(doc_to_text(&doc.0).unwrap(), "hello\nitem\nx\u{fffc}");
+
+    let code = doc_span![DocGroup({"tag": "pre"}, [DocChars("a\nb")])];
+    assert!(doc_to_text(&code).is_err());
+}
+
+#[test]
+fn json0_roundtrip() {
+    let doc = doc();
+    let components = vec![insert(1, 4, "s"), delete(0, 0, "he")];
+    let op = json0_to_op(&doc, &components).unwrap();
+    let after = Op::apply(&doc, &op);
+    assert_eq!(doc_to_json0(&after.0), vec!["llo", "items", "x\u{fffc}"]);
+
+    // Components come back in block order.
+    assert_eq!(
+        op_to_json0(&doc, &op).unwrap(),
+        vec![delete(0, 0, "he"), insert(1, 4, "s")]
+    );
+}
+
+#[test]
+fn json0_deletes_inline_objects() {
+    let doc = doc();
+    let op = json0_to_op(&doc, &[delete(2, 1, "\u{fffc}")]).unwrap();
+    assert_eq!(doc_to_json0(&Op::apply(&doc, &op).0), vec!["hello", "item", "x"]);
+}
+
+#[test]
+fn json0_counts_utf16() {
+    let doc = Doc(doc_span![DocGroup({"tag": "p"}, [DocChars("😀b")])]);
+    let op = json0_to_op(&doc, &[insert(0, 2, "a")]).unwrap();
+    assert_eq!(doc_to_json0(&Op::apply(&doc, &op).0), vec!["😀ab"]);
+    assert_eq!(op_to_json0(&doc, &op).unwrap(), vec![insert(0, 2, "a")]);
+
+    // Offsets inside a surrogate pair are refused.
+    assert!(json0_to_op(&doc, &[insert(0, 1, "a")]).is_err());
+}
+
+#[test]
+fn json0_refusals() {
+    let doc = doc();
+    assert!(json0_to_op(&doc, &[delete(0, 0, "xx")]).is_err());
+    assert!(json0_to_op(&doc, &[insert(5, 0, "a")]).is_err());
+    assert!(json0_to_op(&doc, &[insert(0, 9, "a")]).is_err());
+    assert!(json0_to_op(&doc, &[insert(0, 0, "\u{fffc}")]).is_err());
+    let component = Json0Component {
+        p: vec![0],
+        si: Some("a".to_string()),
+        sd: None,
+    };
+    assert!(json0_to_op(&doc, &[component]).is_err());
+
+    // Adding a block has no json0 equivalent.
+    let op = op_span!([], [AddGroup({"tag": "p"}, [AddChars("new")])]);
+    assert!(op_to_json0(&doc, &op).is_err());
+    assert!(op_to_text_op(&doc, &op).is_err());
+}
+
+#[test]
+fn text_op_roundtrip() {
+    let doc = doc();
+    let text_op: TextOp = serde_json::from_value(json!([7, {"d": 2}, "o"])).unwrap();
+    let op = text_op_to_op(&doc, &text_op).unwrap();
+    assert_eq!(doc_to_json0(&Op::apply(&doc, &op).0), vec!["hello", "iom", "x\u{fffc}"]);
+    assert_eq!(
+        serde_json::to_value(op_to_text_op(&doc, &op).unwrap()).unwrap(),
+        json!([7, {"d": 2}, "o"])
+    );
+}
+
+#[test]
+fn text_op_refusals() {
+    let doc = doc();
+    // Line breaks would split blocks.
+    assert!(text_op_to_op(&doc, &vec![TextComponent::Insert("\n".to_string())]).is_err());
+    assert!(text_op_to_op(&doc, &vec![TextComponent::Skip(100)]).is_err());
+    assert!(text_op_to_op(&doc, &vec![TextComponent::Delete { d: 100 }]).is_err());
+}
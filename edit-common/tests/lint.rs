@@ -0,0 +1,86 @@
+#[macro_use]
+extern crate oatie;
+extern crate edit_common;
+
+use edit_common::lint::*;
+use oatie::doc::*;
+
+fn all_checks(max_sentence_words: usize) -> LintConfig {
+    LintConfig {
+        max_sentence_words: Some(max_sentence_words),
+        passive_voice: true,
+        repeated_words: true,
+    }
+}
+
+fn ranges(issues: &[LintIssue]) -> Vec<(usize, usize, LintKind)> {
+    issues.iter().map(|x| (x.start, x.end, x.kind)).collect()
+}
+
+fn chars(text: &str) -> Vec<char> {
+    text.chars().collect()
+}
+
+#[test]
+fn sentences_are_trimmed() {
+    assert_eq!(
+        sentences(&chars("One two.  Three? four")),
+        vec![(0, 8), (10, 16), (17, 21)]
+    );
+
+    // A period inside a number doesn't end a sentence.
+    assert_eq!(sentences(&chars("It costs 3.50 now.")), vec![(0, 18)]);
+    assert!(sentences(&chars("   ")).is_empty());
+}
+
+#[test]
+fn long_sentences() {
+    let issues = lint(&all_checks(3), "One two three four. Short.");
+    assert_eq!(ranges(&issues), vec![(0, 19, LintKind::LongSentence)]);
+    assert!(issues[0].message.contains("4 words"));
+}
+
+#[test]
+fn passive_voice() {
+    let issues = lint(&all_checks(30), "The cake was quickly eaten.");
+    assert_eq!(ranges(&issues), vec![(9, 26, LintKind::PassiveVoice)]);
+
+    // Short words ending in "-en" are usually not participles.
+    assert!(lint(&all_checks(30), "The door is open.").is_empty());
+}
+
+#[test]
+fn repeated_words() {
+    let issues = lint(&all_checks(30), "The the cat.");
+    assert_eq!(ranges(&issues), vec![(0, 7, LintKind::RepeatedWord)]);
+    assert!(lint(&all_checks(30), "I said that, that is all.").is_empty());
+}
+
+#[test]
+fn checks_are_off_by_default() {
+    let config = LintConfig::default();
+    assert!(!config.is_enabled());
+    assert!(lint(&config, "The the cake was eaten.").is_empty());
+}
+
+#[test]
+fn cache_reports_prose_blocks() {
+    let doc = Doc(doc_span![
+        DocGroup({"tag": "p"}, [DocChars("the the")]),
+        DocGroup({"tag": "pre"}, [DocChars("a a")]),
+        DocGroup({"tag": "p"}, [DocChars("fine")]),
+        DocGroup({"tag": "h1", "id": "title"}, [DocChars("b b")]),
+    ]);
+
+    let mut cache = LintCache::new();
+    cache.reset(&doc);
+    assert!(cache.lints(&doc).is_empty());
+
+    // Code blocks aren't prose, and don't count toward positions.
+    cache.set_config(all_checks(30), &doc);
+    let lints = cache.lints(&doc);
+    assert_eq!(
+        lints.iter().map(|x| (x.block, x.id.clone())).collect::<Vec<_>>(),
+        vec![(0, None), (2, Some("title".to_string()))]
+    );
+}
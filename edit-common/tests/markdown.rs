@@ -0,0 +1,104 @@
+//! Documents exported to Markdown and imported back.
+
+extern crate edit_common;
+#[macro_use]
+extern crate oatie;
+
+use edit_common::markdown::{
+    doc_to_markdown,
+    markdown_to_doc,
+};
+use oatie::doc::*;
+
+fn roundtrip(doc: &DocSpan) -> DocSpan {
+    let markdown = doc_to_markdown(doc).unwrap();
+    markdown_to_doc(&markdown).unwrap()
+}
+
+#[test]
+fn math_roundtrip() {
+    let doc = markdown_to_doc("Area is $\\pi r^2$ here").unwrap();
+    assert_eq!(
+        doc,
+        doc_span![
+            DocGroup({"tag": "p"}, [
+                DocChars("Area is "),
+                DocGroup({"tag": "math", "latex": "\\pi r^2"}, []),
+                DocChars(" here"),
+            ]),
+        ]
+    );
+    assert_eq!(roundtrip(&doc), doc);
+}
+
+#[test]
+fn dollar_signs_stay_text() {
+    let doc = doc_span![
+        DocGroup({"tag": "p"}, [DocChars("costs $5 or $10")]),
+    ];
+    assert_eq!(roundtrip(&doc), doc);
+
+    let doc = doc_span![
+        DocGroup({"tag": "p"}, [
+            DocChars("$x$ is "),
+            DocGroup({"tag": "math", "latex": "x"}, []),
+        ]),
+    ];
+    assert_eq!(roundtrip(&doc), doc);
+}
+
+#[test]
+fn dollar_signs_in_code_stay_unescaped() {
+    let doc = markdown_to_doc("```\nlet $x = 1;\n```").unwrap();
+    let markdown = doc_to_markdown(&doc).unwrap();
+    assert!(markdown.contains("let $x = 1;"));
+    assert!(!markdown.contains("\\$"));
+}
+
+#[test]
+fn alignment_roundtrip() {
+    let doc = doc_span![
+        DocGroup({"tag": "h1", "align": "center"}, [DocChars("Title")]),
+        DocGroup({"tag": "p"}, [DocChars("left")]),
+        DocGroup({"tag": "p", "align": "right"}, [DocChars("right")]),
+    ];
+    let markdown = doc_to_markdown(&doc).unwrap();
+    assert!(markdown.contains("<div align=\"center\">"));
+    assert_eq!(markdown_to_doc(&markdown).unwrap(), doc);
+}
+
+#[test]
+fn other_html_stays_html() {
+    let doc = markdown_to_doc("<div class=\"note\">\n\nhi\n\n</div>").unwrap();
+    let tags = doc
+        .iter()
+        .map(|elem| match elem {
+            DocGroup(attrs, _) => attrs["tag"].clone(),
+            DocChars(_) => String::new(),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(tags, vec!["html", "p", "html"]);
+}
+
+#[test]
+fn definitions_roundtrip() {
+    let doc = doc_span![
+        DocGroup({"tag": "p"}, [DocChars("before")]),
+        DocGroup({"tag": "dt"}, [DocChars("Term & <more>")]),
+        DocGroup({"tag": "dd"}, [DocChars("Its definition")]),
+        DocGroup({"tag": "dd"}, []),
+        DocGroup({"tag": "p"}, [DocChars("after")]),
+    ];
+    let markdown = doc_to_markdown(&doc).unwrap();
+    assert!(markdown.contains("<dt>Term &amp; &lt;more&gt;</dt>"));
+    assert_eq!(markdown_to_doc(&markdown).unwrap(), doc);
+}
+
+#[test]
+fn definition_like_html_stays_html() {
+    let doc = markdown_to_doc("<dl>\n<dt>Term <b>bold</b></dt>\n</dl>").unwrap();
+    match doc[0] {
+        DocGroup(ref attrs, _) => assert_eq!(attrs["tag"], "html"),
+        ref other => panic!("expected an html block, got {:?}", other),
+    }
+}
@@ -0,0 +1,94 @@
+#[macro_use]
+extern crate oatie;
+extern crate edit_common;
+
+use edit_common::pagination::*;
+use oatie::doc::*;
+
+fn layout(lines_per_page: usize) -> PageLayout {
+    PageLayout {
+        lines_per_page,
+        chars_per_line: 80,
+    }
+}
+
+// Pages and blocks of each break.
+fn placed(lines_per_page: usize, heights: &[usize]) -> Vec<(usize, usize)> {
+    let blocks = heights.iter().map(|x| (None, *x)).collect::<Vec<_>>();
+    place_blocks(&layout(lines_per_page), &blocks)
+        .into_iter()
+        .map(|x| (x.page, x.block))
+        .collect()
+}
+
+#[test]
+fn blocks_break_to_the_next_page() {
+    assert_eq!(placed(10, &[4, 4, 4]), vec![(2, 2)]);
+    // Blocks filling a page exactly fit on it.
+    assert_eq!(placed(10, &[5, 5, 5]), vec![(2, 2)]);
+    assert_eq!(placed(10, &[1, 1, 1]), vec![]);
+    assert_eq!(placed(10, &[]), vec![]);
+}
+
+#[test]
+fn tall_blocks_run_on() {
+    // The tall block starts a page, and runs on through two more.
+    assert_eq!(placed(10, &[3, 25, 2, 4]), vec![(2, 1), (5, 3)]);
+
+    // A page is never broken before the first block.
+    assert_eq!(placed(10, &[12, 1]), vec![]);
+
+    // Pages of no lines hold a line.
+    assert_eq!(placed(0, &[1, 1]), vec![(2, 1)]);
+}
+
+#[test]
+fn wrap_text_breaks_between_words() {
+    assert_eq!(wrap_text("one two three", 7), vec!["one two", "three"]);
+    assert_eq!(wrap_text("one   two", 80), vec!["one two"]);
+    assert_eq!(wrap_text("", 5), vec![""]);
+
+    // Words longer than a line are broken up.
+    assert_eq!(wrap_text("abcdefghij", 4), vec!["abcd", "efgh", "ij"]);
+    assert_eq!(wrap_text("a abcdefgh", 4), vec!["a", "abcd", "efgh"]);
+}
+
+#[test]
+fn blocks_are_measured_by_their_text() {
+    let doc = Doc(doc_span![
+        DocGroup({"tag": "h1"}, [DocChars("Title")]),
+        DocGroup({"tag": "p"}, [DocChars(&"x".repeat(100))]),
+        DocGroup({"tag": "bullet"}, [
+            DocGroup({"tag": "p", "id": "item"}, [DocChars(&"y".repeat(78))]),
+        ]),
+    ]);
+    let blocks = layout_blocks(&doc, &layout(46));
+    assert_eq!(
+        blocks.iter().map(|x| (x.indent, x.scale, x.lines.len())).collect::<Vec<_>>(),
+        vec![(0, 200, 1), (0, 100, 2), (1, 100, 2)]
+    );
+    assert_eq!(
+        blocks.iter().map(|x| x.height()).collect::<Vec<_>>(),
+        vec![3, 3, 3]
+    );
+
+    // The list item is the block that doesn't fit.
+    let breaks = paginate(&doc, &layout(7));
+    assert_eq!(
+        breaks,
+        vec![PageBreak {
+            page: 2,
+            block: 2,
+            id: Some("item".to_string()),
+        }]
+    );
+
+    let marked = mark_page_breaks(&doc, &breaks);
+    match marked.0[2] {
+        DocGroup(_, ref inner) => match inner[0] {
+            DocGroup(ref attrs, _) => assert_eq!(attrs["class"], PAGE_BREAK_CLASS),
+            _ => panic!("expected the list item's block"),
+        },
+        _ => panic!("expected the list item"),
+    }
+}
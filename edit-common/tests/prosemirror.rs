@@ -0,0 +1,125 @@
+//! Documents converted to ProseMirror document JSON and back.
+
+extern crate edit_common;
+#[macro_use]
+extern crate oatie;
+#[macro_use]
+extern crate serde_json;
+
+use edit_common::link::Link;
+use edit_common::prosemirror::*;
+use oatie::doc::*;
+use serde_json::Value;
+
+fn to_json(doc: &DocSpan) -> Value {
+    serde_json::to_value(doc_to_prosemirror(doc)).unwrap()
+}
+
+fn from_json(value: Value) -> DocSpan {
+    prosemirror_to_doc(&serde_json::from_value(value).unwrap()).unwrap()
+}
+
+#[test]
+fn export_blocks() {
+    let doc = doc_span![
+        DocGroup({"tag": "h2"}, [DocChars("Title")]),
+        DocGroup({"tag": "p"}, [DocChars("one\ntwo")]),
+        DocGroup({"tag": "pre", "lang": "rust"}, [DocChars("fn main() {}")]),
+        DocGroup({"tag": "hr"}, []),
+    ];
+    assert_eq!(
+        to_json(&doc),
+        json!({"type": "doc", "content": [
+            {"type": "heading", "attrs": {"level": 2}, "content": [
+                {"type": "text", "text": "Title"},
+            ]},
+            {"type": "paragraph", "content": [
+                {"type": "text", "text": "one"},
+                {"type": "hard_break"},
+                {"type": "text", "text": "two"},
+            ]},
+            {"type": "code_block", "attrs": {"params": "rust"}, "content": [
+                {"type": "text", "text": "fn main() {}"},
+            ]},
+            {"type": "horizontal_rule"},
+        ]})
+    );
+}
+
+#[test]
+fn nested_lists_roundtrip() {
+    let doc = doc_span![
+        DocGroup({"tag": "bullet"}, [DocGroup({"tag": "p"}, [DocChars("a")])]),
+        DocGroup({"tag": "bullet"}, [
+            DocGroup({"tag": "bullet"}, [DocGroup({"tag": "p"}, [DocChars("b")])]),
+        ]),
+        DocGroup({"tag": "bullet"}, [DocGroup({"tag": "p"}, [DocChars("c")])]),
+    ];
+    let json = to_json(&doc);
+    assert_eq!(
+        json,
+        json!({"type": "doc", "content": [
+            {"type": "bullet_list", "content": [
+                {"type": "list_item", "content": [
+                    {"type": "paragraph", "content": [{"type": "text", "text": "a"}]},
+                    {"type": "bullet_list", "content": [
+                        {"type": "list_item", "content": [
+                            {"type": "paragraph", "content": [{"type": "text", "text": "b"}]},
+                        ]},
+                    ]},
+                ]},
+                {"type": "list_item", "content": [
+                    {"type": "paragraph", "content": [{"type": "text", "text": "c"}]},
+                ]},
+            ]},
+        ]})
+    );
+    assert_eq!(from_json(json), doc);
+}
+
+#[test]
+fn marks_roundtrip() {
+    let link = Link {
+        href: "https://example.com/".to_string(),
+        title: Some("Example".to_string()),
+        new_tab: true,
+    };
+    let json = json!({"type": "doc", "content": [
+        {"type": "paragraph", "content": [
+            {"type": "text", "text": "bold", "marks": [{"type": "strong"}]},
+            {"type": "text", "text": " and "},
+            {"type": "text", "text": "link", "marks": [{"type": "link", "attrs": {
+                "href": "https://example.com/",
+                "title": "Example",
+                "target": "_blank",
+            }}]},
+        ]},
+    ]});
+    let doc = from_json(json.clone());
+    match doc[0] {
+        DocGroup(_, ref inner) => match inner[2] {
+            DocChars(ref text) => {
+                let styles = text.styles().unwrap();
+                assert_eq!(styles.get(&Style::Link), Some(&Some(link.to_style_value())));
+            }
+            ref other => panic!("expected link text, got {:?}", other),
+        },
+        ref other => panic!("expected a paragraph, got {:?}", other),
+    }
+    assert_eq!(to_json(&doc), json);
+}
+
+#[test]
+fn import_refuses_unknown_nodes() {
+    let node = serde_json::from_value(json!({"type": "doc", "content": [
+        {"type": "table", "content": []},
+    ]}))
+    .unwrap();
+    assert!(prosemirror_to_doc(&node).is_err());
+
+    let node = serde_json::from_value(json!({"type": "doc", "content": [
+        {"type": "paragraph", "content": [{"type": "footnote"}]},
+    ]}))
+    .unwrap();
+    assert!(prosemirror_to_doc(&node).is_err());
+}
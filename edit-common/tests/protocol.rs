@@ -0,0 +1,101 @@
+extern crate edit_common;
+extern crate oatie;
+extern crate serde;
+extern crate serde_json;
+
+use edit_common::commands::*;
+use edit_common::protocol::*;
+use oatie::doc::*;
+use oatie::OT;
+
+fn json<T: serde::Serialize>(value: &T) -> Vec<u8> {
+    serde_json::to_vec(value).unwrap()
+}
+
+fn commit() -> ServerCommand {
+    ServerCommand::Commit("client".to_string(), Op::empty(), 3)
+}
+
+fn commit_op() -> ServerCommand {
+    ServerCommand::CommitOp("client".to_string(), 7, Op::empty(), 3)
+}
+
+fn peer_ops() -> ServerCommand {
+    ServerCommand::PeerOps("client".to_string(), vec![])
+}
+
+#[test]
+fn versions_parse_their_own_commands() {
+    for version in 1..(PROTOCOL_VERSION + 1) {
+        match parse_server_command(version, &json(&commit())).unwrap() {
+            ServerCommand::Commit(client_id, _, 3) => assert_eq!(client_id, "client"),
+            other => panic!("expected a commit, got {:?}", other),
+        }
+    }
+
+    // Commands newer than the client's version are refused.
+    assert!(parse_server_command(2, &json(&commit_op())).is_err());
+    match parse_server_command(3, &json(&commit_op())).unwrap() {
+        ServerCommand::CommitOp(_, 7, _, 3) => {}
+        other => panic!("expected a commit with an id, got {:?}", other),
+    }
+    assert!(parse_server_command(3, &json(&peer_ops())).is_err());
+    assert!(parse_server_command(4, &json(&peer_ops())).is_ok());
+}
+
+#[test]
+fn client_commands_for_older_versions() {
+    let init = ClientCommand::Init("client".to_string(), vec![], 100);
+    let init_chunk = ClientCommand::InitChunk("client".to_string(), 100, 0, 1, vec![]);
+    let ack = ClientCommand::Ack(7, 101);
+    let peer_init = ClientCommand::PeerInit("client".to_string(), vec![]);
+    let init_text = ClientCommand::InitText("client".to_string(), 100, 0, 1, "[]".to_string());
+
+    // Commands every version has are serialized the same for each.
+    let expected = serde_json::to_string(&init).unwrap();
+    for version in 1..(PROTOCOL_VERSION + 1) {
+        assert_eq!(client_command_json(version, &init), Some(expected.clone()));
+    }
+
+    assert_eq!(client_command_json(1, &init_chunk), None);
+    assert!(client_command_json(2, &init_chunk).is_some());
+    assert_eq!(client_command_json(2, &ack), None);
+    assert!(client_command_json(3, &ack).is_some());
+    assert_eq!(client_command_json(3, &peer_init), None);
+    assert!(client_command_json(4, &peer_init).is_some());
+    assert_eq!(client_command_json(4, &init_text), None);
+    assert!(client_command_json(5, &init_text).is_some());
+}
+
+#[test]
+fn conversions_between_versions() {
+    let command = v1::ClientCommand::Writer(Some("client".to_string()));
+    let upgraded = v2::ClientCommand::from(command);
+    match upgraded.downgrade() {
+        Some(v1::ClientCommand::Writer(Some(client_id))) => assert_eq!(client_id, "client"),
+        other => panic!("expected the writer back, got {:?}", other),
+    }
+
+    let upgraded = v3::ServerCommand::from(v2::ServerCommand::TerminateProxy);
+    let upgraded = v4::ServerCommand::from(upgraded);
+    match upgraded.downgrade() {
+        Some(v3::ServerCommand::TerminateProxy) => {}
+        other => panic!("expected the command back, got {:?}", other),
+    }
+    assert!(v4::ServerCommand::PeerOps("client".to_string(), vec![]).downgrade().is_none());
+}
+
+#[test]
+fn requested_versions() {
+    assert_eq!(requested_version(None), Some(1));
+    assert_eq!(requested_version(Some("3")), Some(3));
+    assert_eq!(requested_version(Some("three")), None);
+    assert!(is_supported(1));
+    assert!(is_supported(PROTOCOL_VERSION));
+    assert!(!is_supported(0));
+    assert!(!is_supported(PROTOCOL_VERSION + 1));
+
+    assert_eq!(requested_resume(Some("104:2")), Some((104, 2)));
+    assert_eq!(requested_resume(Some("104")), None);
+    assert_eq!(requested_resume(None), None);
+}
@@ -0,0 +1,326 @@
+//! Serialized commands must match the protocol's JSON Schema. The checker
+//! here handles just the keywords the schema uses.
+
+extern crate edit_common;
+#[macro_use]
+extern crate oatie;
+extern crate serde;
+extern crate serde_json;
+
+use edit_common::commands::*;
+use edit_common::comments::CommentAction;
+use edit_common::crdt::*;
+use edit_common::identity::UserIdentity;
+use edit_common::link::Link;
+use edit_common::lint::LintConfig;
+use edit_common::pagination::PageLayout;
+use edit_common::protocol_schema::protocol_schema;
+use oatie::doc::*;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+fn matches_type(value: &Value, name: &str) -> bool {
+    match name {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        _ => panic!("unknown type {:?}", name),
+    }
+}
+
+fn check(root: &Value, schema: &Value, value: &Value) -> bool {
+    if let Some(path) = schema.get("$ref").and_then(|x| x.as_str()) {
+        let name = path.trim_left_matches("#/definitions/");
+        return check(root, &root["definitions"][name], value);
+    }
+    if let Some(variants) = schema.get("oneOf").and_then(|x| x.as_array()) {
+        let count = variants.iter().filter(|schema| check(root, schema, value)).count();
+        if count != 1 {
+            return false;
+        }
+    }
+    if let Some(variants) = schema.get("anyOf").and_then(|x| x.as_array()) {
+        if !variants.iter().any(|schema| check(root, schema, value)) {
+            return false;
+        }
+    }
+    if let Some(name) = schema.get("type").and_then(|x| x.as_str()) {
+        if !matches_type(value, name) {
+            return false;
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if value != expected {
+            return false;
+        }
+    }
+    if let Some(options) = schema.get("enum").and_then(|x| x.as_array()) {
+        if !options.contains(value) {
+            return false;
+        }
+    }
+    if let Some(minimum) = schema.get("minimum").and_then(|x| x.as_f64()) {
+        if value.as_f64().map(|x| x < minimum).unwrap_or(false) {
+            return false;
+        }
+    }
+
+    if let Some(items) = value.as_array() {
+        match schema.get("items") {
+            Some(Value::Array(schemas)) => {
+                if items.iter().zip(schemas).any(|(item, schema)| !check(root, schema, item)) {
+                    return false;
+                }
+            }
+            Some(item_schema) => {
+                if items.iter().any(|item| !check(root, item_schema, item)) {
+                    return false;
+                }
+            }
+            None => {}
+        }
+        let len = items.len() as u64;
+        if schema.get("minItems").and_then(|x| x.as_u64()).map(|x| len < x).unwrap_or(false)
+            || schema.get("maxItems").and_then(|x| x.as_u64()).map(|x| len > x).unwrap_or(false)
+        {
+            return false;
+        }
+    }
+
+    if let Some(fields) = value.as_object() {
+        if let Some(required) = schema.get("required").and_then(|x| x.as_array()) {
+            if required.iter().any(|name| !fields.contains_key(name.as_str().unwrap())) {
+                return false;
+            }
+        }
+        for (name, field) in fields {
+            if let Some(names) = schema.get("propertyNames") {
+                if !check(root, names, &Value::String(name.clone())) {
+                    return false;
+                }
+            }
+            let field_schema = schema
+                .get("properties")
+                .and_then(|x| x.get(name))
+                .or_else(|| schema.get("additionalProperties"));
+            match field_schema {
+                Some(Value::Bool(false)) => return false,
+                Some(field_schema) => {
+                    if !check(root, field_schema, field) {
+                        return false;
+                    }
+                }
+                None => {}
+            }
+        }
+    }
+
+    true
+}
+
+fn assert_valid<T: Serialize>(definition: &str, value: &T) {
+    let root = protocol_schema();
+    let value = serde_json::to_value(value).unwrap();
+    let schema = json_ref(definition);
+    assert!(
+        check(&root, &schema, &value),
+        "{} doesn't match its schema: {}",
+        definition,
+        value
+    );
+}
+
+fn json_ref(definition: &str) -> Value {
+    let mut schema = serde_json::Map::new();
+    schema.insert(
+        "$ref".to_string(),
+        Value::String(format!("#/definitions/{}", definition)),
+    );
+    Value::Object(schema)
+}
+
+fn attrs(tag: &str) -> Attrs {
+    let mut attrs = HashMap::new();
+    attrs.insert("tag".to_string(), tag.to_string());
+    attrs
+}
+
+fn sample_doc() -> Doc {
+    Doc(vec![
+        DocGroup(
+            attrs("h1"),
+            vec![DocChars(DocString::from_str("Title"))],
+        ),
+        DocGroup(
+            attrs("p"),
+            vec![
+                DocChars(DocString::from_str("plain ")),
+                DocChars(DocString::from_str_styled(
+                    "bold",
+                    style_map! { Style::Normie => None, Style::Bold => None },
+                )),
+                DocChars(DocString::from_str_styled(
+                    "link",
+                    style_map! { Style::Link => Some("https://example.com".to_string()) },
+                )),
+            ],
+        ),
+    ])
+}
+
+fn sample_op() -> Op {
+    let mut styles = ::std::collections::HashSet::new();
+    styles.insert(Style::Bold);
+    (
+        vec![
+            DelSkip(1),
+            DelWithGroup(vec![DelChars(2), DelStyles(3, styles)]),
+            DelGroup(vec![]),
+        ],
+        vec![
+            AddSkip(1),
+            AddWithGroup(vec![
+                AddChars(DocString::from_str("x")),
+                AddStyles(2, style_map! { Style::Italic => None }),
+            ]),
+            AddGroup(attrs("p"), vec![AddSkip(1)]),
+        ],
+    )
+}
+
+fn sample_crdt_ops() -> Vec<CrdtOp> {
+    let mut replica = CrdtDoc::from_doc(1, &initial_peer_doc());
+    let mut ops = vec![replica.insert_block(1, "h1")];
+    ops.extend(replica.insert_char(1, 0, 'x'));
+    ops.extend(replica.delete_char(1, 0));
+    ops.extend(replica.delete_block(1));
+    ops
+}
+
+#[test]
+fn documents_and_operations() {
+    assert_valid("Doc", &sample_doc());
+    assert_valid("Op", &sample_op());
+    assert_valid("CurSpan", &vec![CurSkip(2), CurWithGroup(vec![CurChar]), CurGroup]);
+}
+
+#[test]
+fn commands() {
+    assert_valid(
+        "ServerCommand",
+        &ServerCommand::Commit("client".to_string(), sample_op(), 3),
+    );
+    assert_valid(
+        "ServerCommand",
+        &ServerCommand::CommitOp("client".to_string(), 7, sample_op(), 3),
+    );
+    assert_valid("ServerCommand", &ServerCommand::TerminateProxy);
+    assert_valid(
+        "ServerCommand",
+        &ServerCommand::PeerOps("client".to_string(), sample_crdt_ops()),
+    );
+    assert_valid(
+        "ServerCommand",
+        &ServerCommand::Comment(
+            "client".to_string(),
+            CommentAction::Start("block".to_string(), "Hi".to_string()),
+        ),
+    );
+
+    assert_valid(
+        "ClientCommand",
+        &ClientCommand::Init("client".to_string(), sample_doc().0, 100),
+    );
+    assert_valid(
+        "ClientCommand",
+        &ClientCommand::InitChunk("client".to_string(), 100, 0, 2, sample_doc().0),
+    );
+    assert_valid(
+        "ClientCommand",
+        &ClientCommand::InitText("client".to_string(), 100, 0, 2, "[".to_string()),
+    );
+    assert_valid(
+        "ClientCommand",
+        &ClientCommand::Refuse(RefusalReason::TooLarge(SizeLimit::Chars, 10)),
+    );
+    assert_valid("ClientCommand", &ClientCommand::Refuse(RefusalReason::ReadOnly));
+    assert_valid("ClientCommand", &ClientCommand::Writer(None));
+    assert_valid(
+        "ClientCommand",
+        &ClientCommand::PeerInit("client".to_string(), sample_crdt_ops()),
+    );
+    assert_valid(
+        "ClientCommand",
+        &ClientCommand::Users(vec![(
+            "client".to_string(),
+            UserIdentity {
+                id: "user".to_string(),
+                name: "Ada".to_string(),
+                color: "#336699".to_string(),
+                avatar_url: None,
+                guest: false,
+            },
+        )]),
+    );
+
+    assert_valid("ControllerCommand", &ControllerCommand::Keypress(13, false, true, false));
+    assert_valid("ControllerCommand", &ControllerCommand::Stats);
+    assert_valid("ControllerCommand", &ControllerCommand::PeerSync);
+    assert_valid(
+        "ControllerCommand",
+        &ControllerCommand::Cursor(Some(vec![CurSkip(1), CurChar]), None),
+    );
+    assert_valid(
+        "ControllerCommand",
+        &ControllerCommand::EditLink(Some(Link::new("https://example.com"))),
+    );
+    assert_valid("ControllerCommand", &ControllerCommand::SelectRange(-1, 4));
+    assert_valid(
+        "ControllerCommand",
+        &ControllerCommand::MoveSection("intro".to_string(), 2),
+    );
+    assert_valid(
+        "ControllerCommand",
+        &ControllerCommand::InputRule(InputRule::SmartQuotes, true),
+    );
+    assert_valid(
+        "ControllerCommand",
+        &ControllerCommand::Autocorrect("teh".to_string(), Some("the".to_string())),
+    );
+    assert_valid(
+        "ControllerCommand",
+        &ControllerCommand::AutocorrectDictionary(vec![("adn".to_string(), "and".to_string())]),
+    );
+    assert_valid("ControllerCommand", &ControllerCommand::Lint(LintConfig::default()));
+    assert_valid(
+        "ControllerCommand",
+        &ControllerCommand::Paginate(Some(PageLayout::default())),
+    );
+    assert_valid("ControllerCommand", &ControllerCommand::Paginate(None));
+}
+
+#[test]
+fn rejects_malformed_payloads() {
+    let root = protocol_schema();
+    let malformed = vec![
+        ("Op", "[[{\"DelSkip\": -1}], []]"),
+        ("DocString", "[\"text\", {\"Underline\": null}]"),
+        ("ClientCommand", "{\"Init\": [\"client\", []]}"),
+        ("ServerCommand", "{\"Log\": \"a\", \"TakeOver\": \"b\"}"),
+        ("ControllerCommand", "\"Keypress\""),
+    ];
+    for (definition, input) in malformed {
+        let value: Value = serde_json::from_str(input).unwrap();
+        assert!(
+            !check(&root, &json_ref(definition), &value),
+            "{} accepted {}",
+            definition,
+            input
+        );
+    }
+}
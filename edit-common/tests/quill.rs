@@ -0,0 +1,127 @@
+//! Documents converted to Quill Deltas and back.
+
+extern crate edit_common;
+#[macro_use]
+extern crate oatie;
+#[macro_use]
+extern crate serde_json;
+
+use edit_common::quill::*;
+use oatie::doc::*;
+use serde_json::Value;
+
+fn delta(value: Value) -> Delta {
+    serde_json::from_value(value).unwrap()
+}
+
+#[test]
+fn export_blocks() {
+    let doc = doc_span![
+        DocGroup({"tag": "h1"}, [DocChars("Title")]),
+        DocGroup({"tag": "p"}, [DocChars("Some text")]),
+        DocGroup({"tag": "bullet"}, [DocGroup({"tag": "p"}, [DocChars("item")])]),
+        DocGroup({"tag": "hr"}, []),
+    ];
+    assert_eq!(
+        doc_to_delta(&doc),
+        delta(json!({"ops": [
+            {"insert": "Title"},
+            {"insert": "\n", "attributes": {"header": 1}},
+            {"insert": "Some text\nitem"},
+            {"insert": "\n", "attributes": {"list": "bullet"}},
+            {"insert": {"divider": true}},
+        ]}))
+    );
+}
+
+#[test]
+fn import_blocks() {
+    let doc = delta_to_doc(&delta(json!({"ops": [
+        {"insert": "Title"},
+        {"insert": "\n", "attributes": {"header": 2}},
+        {"insert": "one\n"},
+        {"insert": "nested"},
+        {"insert": "\n", "attributes": {"list": "bullet", "indent": 1}},
+        {"insert": "fn main() {}"},
+        {"insert": "\n", "attributes": {"code-block": "rust"}},
+    ]})))
+    .unwrap();
+    assert_eq!(
+        doc,
+        doc_span![
+            DocGroup({"tag": "h2"}, [DocChars("Title")]),
+            DocGroup({"tag": "p"}, [DocChars("one")]),
+            DocGroup({"tag": "bullet"}, [
+                DocGroup({"tag": "bullet"}, [DocGroup({"tag": "p"}, [DocChars("nested")])]),
+            ]),
+            DocGroup({"tag": "pre", "lang": "rust"}, [DocChars("fn main() {}")]),
+        ]
+    );
+}
+
+#[test]
+fn styles_roundtrip() {
+    let input = delta(json!({"ops": [
+        {"insert": "plain "},
+        {"insert": "bold", "attributes": {"bold": true}},
+        {"insert": " "},
+        {"insert": "link", "attributes": {"link": "https://example.com/"}},
+        {"insert": {"formula": "x^2"}},
+        {"insert": "\n"},
+    ]}));
+    let doc = delta_to_doc(&input).unwrap();
+    assert_eq!(doc_to_delta(&doc), input);
+}
+
+#[test]
+fn apply_retain_delete_insert() {
+    let doc = doc_span![
+        DocGroup({"tag": "p"}, [DocChars("hello world")]),
+    ];
+    let change = delta(json!({"ops": [
+        {"retain": 5, "attributes": {"bold": true}},
+        {"delete": 6},
+        {"insert": "!"},
+    ]}));
+    let doc = apply_delta(&doc, &change).unwrap();
+    assert_eq!(
+        doc_to_delta(&doc),
+        delta(json!({"ops": [
+            {"insert": "hello", "attributes": {"bold": true}},
+            {"insert": "!\n"},
+        ]}))
+    );
+
+    // A null attribute removes the format again.
+    let change = delta(json!({"ops": [{"retain": 2, "attributes": {"bold": null}}]}));
+    let doc = apply_delta(&doc, &change).unwrap();
+    assert_eq!(
+        doc_to_delta(&doc),
+        delta(json!({"ops": [
+            {"insert": "he"},
+            {"insert": "llo", "attributes": {"bold": true}},
+            {"insert": "!\n"},
+        ]}))
+    );
+}
+
+#[test]
+fn apply_counts_utf16() {
+    let doc = doc_span![
+        DocGroup({"tag": "p"}, [DocChars("😀a")]),
+    ];
+    let change = delta(json!({"ops": [{"retain": 2}, {"delete": 1}]}));
+    assert_eq!(
+        apply_delta(&doc, &change).unwrap(),
+        doc_span![DocGroup({"tag": "p"}, [DocChars("😀")])]
+    );
+}
+
+#[test]
+fn apply_refuses_bad_deltas() {
+    let doc = doc_span![DocGroup({"tag": "p"}, [DocChars("hi")])];
+    assert!(apply_delta(&doc, &delta(json!({"ops": [{"retain": 10}]}))).is_err());
+    assert!(apply_delta(&doc, &delta(json!({"ops": [{}]}))).is_err());
+    assert!(delta_to_doc(&delta(json!({"ops": [{"retain": 1}]}))).is_err());
+    assert!(delta_to_doc(&delta(json!({"ops": [{"insert": 5}]}))).is_err());
+}
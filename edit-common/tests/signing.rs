@@ -0,0 +1,113 @@
+extern crate edit_common;
+extern crate oatie;
+extern crate serde_json;
+
+use edit_common::commands::*;
+use edit_common::signing::*;
+use oatie::doc::*;
+use oatie::OT;
+
+fn commit(client_id: &str, version: usize) -> ServerCommand {
+    ServerCommand::Commit(client_id.to_string(), Op::empty(), version)
+}
+
+#[test]
+fn signed_commands_verify() {
+    let keys = SigningKeys::parse("new:secret2, old:secret1").unwrap();
+    let (key_id, signature, payload) = keys.sign_command("page", &commit("client", 3));
+    assert_eq!(key_id, "new");
+
+    match keys.verify_command(&key_id, &signature, &payload, "page").unwrap() {
+        (ServerCommand::Commit(client_id, _, 3), _) => assert_eq!(client_id, "client"),
+        other => panic!("expected the commit back, got {:?}", other),
+    }
+
+    // Servers still knowing the old key verify commands signed with it.
+    let old = SigningKeys::parse("old:secret1").unwrap();
+    let (key_id, signature, payload) = old.sign_command("page", &commit("client", 3));
+    assert!(keys.verify_command(&key_id, &signature, &payload, "page").is_ok());
+    assert!(SigningKeys::parse("other:secret3")
+        .unwrap()
+        .verify_command(&key_id, &signature, &payload, "page")
+        .is_err());
+}
+
+#[test]
+fn signed_commands_are_bound_to_their_page_and_version() {
+    let keys = SigningKeys::parse("key:secret").unwrap();
+    let (key_id, signature, payload) = keys.sign_command("page", &commit("client", 3));
+    assert!(keys.verify_command(&key_id, &signature, &payload, "other").is_err());
+
+    // Changing the version, or anything else, breaks the signature.
+    let mut signed: SignedPayload = serde_json::from_str(&payload).unwrap();
+    signed.command = serde_json::to_string(&commit("client", 4)).unwrap();
+    signed.version = Some(4);
+    let altered = serde_json::to_string(&signed).unwrap();
+    assert!(keys.verify_command(&key_id, &signature, &altered, "page").is_err());
+
+    // A payload whose version doesn't match its command isn't accepted
+    // even when it's signed.
+    signed.version = Some(3);
+    let mismatched = serde_json::to_string(&signed).unwrap();
+    let (key_id, signature) = keys.sign(&mismatched);
+    assert!(keys.verify_command(&key_id, &signature, &mismatched, "page").is_err());
+}
+
+#[test]
+fn signing_covers_commands_that_change_a_page() {
+    assert!(needs_signature(&commit("client", 0)));
+    assert!(needs_signature(&ServerCommand::CommitOp("client".to_string(), 1, Op::empty(), 0)));
+    assert!(needs_signature(&ServerCommand::EncryptedCommit(
+        "client".to_string(),
+        "sealed".to_string(),
+        0
+    )));
+    assert!(needs_signature(&ServerCommand::Duplicate("client".to_string(), true)));
+    assert!(needs_signature(&ServerCommand::MergeFork("client".to_string())));
+    assert!(!needs_signature(&ServerCommand::TerminateProxy));
+
+    assert_eq!(command_version(&commit("client", 7)), Some(7));
+    assert_eq!(command_version(&ServerCommand::MergeFork("client".to_string())), None);
+
+    // Other commands can't be signed at all.
+    let keys = SigningKeys::parse("key:secret").unwrap();
+    let (key_id, signature, payload) = keys.sign_command("page", &ServerCommand::TerminateProxy);
+    assert!(keys.verify_command(&key_id, &signature, &payload, "page").is_err());
+}
+
+#[test]
+fn nonces_are_fresh_and_remembered() {
+    let keys = SigningKeys::parse("key:secret").unwrap();
+    let (_, _, a) = keys.sign_command("page", &commit("client", 3));
+    let (_, _, b) = keys.sign_command("page", &commit("client", 3));
+    let a: SignedPayload = serde_json::from_str(&a).unwrap();
+    let b: SignedPayload = serde_json::from_str(&b).unwrap();
+    assert_ne!(a.nonce, b.nonce);
+
+    let mut seen = SeenNonces::new(2, 10);
+    assert!(seen.insert("one", 10));
+    assert!(!seen.insert("one", 10));
+    assert!(seen.insert("two", 11));
+    assert!(seen.insert("three", 12));
+    assert!(!seen.insert("three", 12));
+}
+
+#[test]
+fn old_signatures_are_refused_once_their_nonces_could_be_forgotten() {
+    let keys = SigningKeys::parse("key:secret").unwrap();
+    let (key_id, signature, payload) = keys.sign_command("page", &commit("client", 3));
+    let (_, signed) = keys.verify_command(&key_id, &signature, &payload, "page").unwrap();
+
+    // A server started since the command was signed doesn't know its nonce.
+    let mut restarted = SeenNonces::new(2, signed.signed_at + 1);
+    assert!(!restarted.insert(&signed.nonce, signed.signed_at));
+
+    // Nor does one that has forgotten it, or any signed before it.
+    let mut seen = SeenNonces::new(2, 0);
+    assert!(seen.insert("one", 10));
+    assert!(seen.insert("two", 20));
+    assert!(seen.insert("three", 30));
+    assert!(!seen.insert("one", 10));
+    assert!(!seen.insert("earlier", 5));
+    assert!(seen.insert("later", 25));
+}
@@ -0,0 +1,65 @@
+extern crate edit_common;
+extern crate failure;
+
+use edit_common::commands::*;
+use edit_common::simple_ws::*;
+use failure::Error;
+use std::io;
+use std::time::{
+    Duration,
+    Instant,
+};
+
+#[test]
+fn close_codes_roundtrip() {
+    for code in 4000..4010 {
+        let reason = CloseReason::from_code(code).unwrap();
+        assert_eq!(reason.code(), code);
+    }
+    assert_eq!(CloseReason::from_code(1000), None);
+    assert_eq!(CloseReason::from_code(4010), None);
+}
+
+#[test]
+fn retryable_reasons() {
+    for reason in &[
+        CloseReason::RateLimited,
+        CloseReason::ShuttingDown,
+        CloseReason::Unavailable,
+    ] {
+        assert_eq!(reason.recovery(), Recovery::ReconnectLater);
+    }
+    assert_eq!(CloseReason::Unloaded.recovery(), Recovery::Reconnect);
+    assert_eq!(CloseReason::ProtocolError.recovery(), Recovery::Stop);
+}
+
+#[test]
+fn errors_close_with_their_reasons() {
+    let err: Error = CloseError::unavailable("database is locked").into();
+    let (reason, message) = close_reason_for(&err);
+    assert_eq!(reason, CloseReason::Unavailable);
+    assert!(message.contains("database is locked"));
+
+    // I/O errors are worth retrying.
+    let err: Error = io::Error::new(io::ErrorKind::TimedOut, "timed out").into();
+    assert_eq!(close_reason_for(&err).0, CloseReason::Unavailable);
+
+    // Anything else is the client's fault, and isn't.
+    let err: Error = failure::err_msg("unknown command");
+    assert_eq!(close_reason_for(&err).0, CloseReason::ProtocolError);
+}
+
+#[test]
+fn message_rate() {
+    let start = Instant::now();
+    let mut rate = MessageRate::new(Some(2));
+    assert!(rate.allow(start));
+    assert!(rate.allow(start));
+    assert!(!rate.allow(start + Duration::from_millis(500)));
+
+    // The count starts over each second.
+    assert!(rate.allow(start + Duration::from_millis(1000)));
+
+    let mut unlimited = MessageRate::new(None);
+    assert!((0..1000).all(|_| unlimited.allow(start)));
+}
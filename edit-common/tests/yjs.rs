@@ -0,0 +1,89 @@
+//! Documents written as Yjs updates and read back.
+
+extern crate edit_common;
+#[macro_use]
+extern crate oatie;
+#[macro_use]
+extern crate serde_json;
+
+use edit_common::quill::*;
+use edit_common::yjs::*;
+use oatie::doc::*;
+
+// A Y.Text named "text" at the root, inserted by `client` with no origins.
+fn root_insert(client: u8, text: &str) -> Vec<u8> {
+    let mut out = vec![1, client, 0, 4, 1, 4];
+    out.extend_from_slice(b"text");
+    out.push(text.len() as u8);
+    out.extend_from_slice(text.as_bytes());
+    out
+}
+
+#[test]
+fn write_update() {
+    let doc = doc_span![DocGroup({"tag": "p"}, [DocChars("hi")])];
+    let mut expected = vec![1];
+    expected.extend(root_insert(5, "hi\n"));
+    expected.push(0);
+    assert_eq!(doc_to_yjs_update(&doc, 5, "text"), expected);
+
+    // An empty document has no structs.
+    assert_eq!(doc_to_yjs_update(&vec![], 5, "text"), vec![0, 0]);
+}
+
+#[test]
+fn update_roundtrip() {
+    let doc = doc_span![
+        DocGroup({"tag": "h1"}, [DocChars("Title")]),
+        DocGroup({"tag": "p"}, [
+            DocChars("plain "),
+            DocChars("bold", {Style::Bold => None}),
+            DocGroup({"tag": "math", "latex": "x"}, []),
+        ]),
+        DocGroup({"tag": "hr"}, []),
+    ];
+    let update = doc_to_yjs_update(&doc, 7, "text");
+    assert_eq!(yjs_update_to_delta(&update, "text").unwrap(), doc_to_delta(&doc));
+    assert_eq!(yjs_update_to_doc(&update, "text").unwrap(), doc);
+
+    // Other types are ignored.
+    assert_eq!(yjs_update_to_doc(&update, "other").unwrap(), vec![]);
+}
+
+#[test]
+fn read_deletions() {
+    let mut update = vec![1];
+    update.extend(root_insert(5, "hi\n"));
+    // Client 5 deleted the clock range [0, 1).
+    update.extend_from_slice(&[1, 5, 1, 0, 1]);
+    assert_eq!(
+        yjs_update_to_doc(&update, "text").unwrap(),
+        doc_span![DocGroup({"tag": "p"}, [DocChars("i")])]
+    );
+}
+
+#[test]
+fn concurrent_inserts_order_by_client() {
+    let delta = Delta {
+        ops: vec![serde_json::from_value(json!({"insert": "ab"})).unwrap()],
+    };
+    for &(first, second) in &[(1, 2), (2, 1)] {
+        let text = |client: u8| if client == 1 { "a" } else { "b" };
+        let mut update = vec![2];
+        update.extend(root_insert(first, text(first)));
+        update.extend(root_insert(second, text(second)));
+        update.push(0);
+        assert_eq!(yjs_update_to_delta(&update, "text").unwrap(), delta);
+    }
+}
+
+#[test]
+fn refuses_broken_updates() {
+    let doc = doc_span![DocGroup({"tag": "p"}, [DocChars("hi")])];
+    let update = doc_to_yjs_update(&doc, 5, "text");
+    assert!(yjs_update_to_delta(&update[..update.len() - 3], "text").is_err());
+
+    // An item after content the update doesn't have.
+    let update = vec![1, 1, 5, 0, 0x84, 9, 0, 1, b'x', 0];
+    assert!(yjs_update_to_delta(&update, "text").is_err());
+}
@@ -1,8 +1,8 @@
-import { WasmClient as WasmClientModule } from './bindgen/edit_client';
+import { Editor } from './bindgen/edit_client';
 
 declare var window: any;
 
-let globalClientBindings: WasmClientModule | null = null;
+let globalClientBindings: Editor | null = null;
 
 let POEM = `The Telegraphers Valentine, by J.C. Maxwell, 1860
 
@@ -88,11 +88,11 @@ const DEBUG = {
             throw new Error('Bindings not assigned');
         }
 
-        return globalClientBindings.asMarkdown();
+        return globalClientBindings.exportMarkdown();
     },
 
     setGlobalClientBindings: (
-        bindings: WasmClientModule,
+        bindings: Editor,
     ) => {
         globalClientBindings = bindings;
     },
@@ -0,0 +1,30 @@
+// Functions imported by the wasm client. This module is kept free of DOM
+// dependencies so the client can also run inside a Web Worker.
+
+let sendCommandToJSList: Array<(value: any) => void> = [];
+
+export function addSendCommandToJSHandler(handler: (value: any) => void) {
+  sendCommandToJSList.push(handler);
+}
+
+// Only used by the client's logging, which doesn't belong to an editor.
+export function sendCommandToJS(msg: any) {
+  let parse = JSON.parse(msg);
+  sendCommandToJSList.forEach(handler => handler(parse));
+}
+
+let forwardWasmTaskCallback: any = null;
+
+export function getForwardWasmTaskCallback(): any {
+  return forwardWasmTaskCallback;
+}
+
+export function setForwardWasmTaskCallback(value: any) {
+  forwardWasmTaskCallback = value;
+}
+
+export function forwardWasmTask(msg: any) {
+  if (forwardWasmTaskCallback) {
+    forwardWasmTaskCallback(msg);
+  }
+}
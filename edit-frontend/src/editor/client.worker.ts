@@ -0,0 +1,50 @@
+// Runs the wasm client off the main thread. Tasks arrive as messages, and
+// commands for the frontend are posted back.
+
+import {addSendCommandToJSHandler, setForwardWasmTaskCallback} from './bridge';
+
+const ctx: Worker = self as any;
+
+import('../bindgen/edit_client')
+.then(Module => {
+  let editor = new Module.Editor();
+
+  let post = (msg: any) => ctx.postMessage({NativeCommand: msg});
+  editor.onNativeCommand(post);
+  addSendCommandToJSHandler(post);
+
+  let guard = (run: () => void) => {
+    try {
+      run();
+    } catch (e) {
+      setForwardWasmTaskCallback(null);
+      Module.wasm_close();
+      ctx.postMessage({WorkerError: `${e.message || e}`});
+    }
+  };
+  let command = (task: any) => guard(() => editor.command(task));
+
+  // Tasks from the monkey arrive as JSON strings.
+  setForwardWasmTaskCallback((msg: string) => command(JSON.parse(msg)));
+
+  ctx.onmessage = (event: MessageEvent) => {
+    if (event.data.ExportMarkdown) {
+      ctx.postMessage({Markdown: editor.exportMarkdown()});
+    } else if (event.data.SaveState) {
+      ctx.postMessage({State: editor.saveState()});
+    } else if (typeof event.data.RestoreState == 'string') {
+      try {
+        editor.restoreState(event.data.RestoreState);
+        ctx.postMessage({Restored: null});
+      } catch (e) {
+        ctx.postMessage({Restored: `${e.message || e}`});
+      }
+    } else if (typeof event.data.ServerCommand == 'string') {
+      guard(() => editor.serverCommand(event.data.ServerCommand));
+    } else {
+      command(event.data);
+    }
+  };
+
+  ctx.postMessage({WorkerReady: true});
+});
@@ -85,6 +85,310 @@ export function Monkey(
   };
 }
 
+export function SyncConnection(
+  connected: boolean,
+) {
+  return {
+    tag: 'SyncConnection' as 'SyncConnection',
+    'SyncConnection': connected,
+  };
+}
+
+export function DebugDump() {
+  return {
+    tag: 'DebugDump' as 'DebugDump',
+    'DebugDump': null,
+  };
+}
+
+export function Stats() {
+  return {
+    tag: 'Stats' as 'Stats',
+    'Stats': null,
+  };
+}
+
+export function TakeOver() {
+  return {
+    tag: 'TakeOver' as 'TakeOver',
+    'TakeOver': null,
+  };
+}
+
+export function Vim(
+  enabled: boolean,
+) {
+  return {
+    tag: 'Vim' as 'Vim',
+    'Vim': enabled,
+  };
+}
+
+export function SelectWord() {
+  return {
+    tag: 'SelectWord' as 'SelectWord',
+    'SelectWord': null,
+  };
+}
+
+export function ToggleFold() {
+  return {
+    tag: 'ToggleFold' as 'ToggleFold',
+    'ToggleFold': null,
+  };
+}
+
+export function MoveSection(headingId: string, newIndex: number) {
+  return {
+    tag: 'MoveSection' as 'MoveSection',
+    'MoveSection': [headingId, newIndex],
+  };
+}
+
+export function ScrollTo(id: string) {
+  return {
+    tag: 'ScrollTo' as 'ScrollTo',
+    'ScrollTo': id,
+  };
+}
+
+export function EmojiCompletions() {
+  return {
+    tag: 'EmojiCompletions' as 'EmojiCompletions',
+    'EmojiCompletions': null,
+  };
+}
+
+export function CompleteEmoji(shortcode: string) {
+  return {
+    tag: 'CompleteEmoji' as 'CompleteEmoji',
+    'CompleteEmoji': shortcode,
+  };
+}
+
+export function InsertMath(latex: string) {
+  return {
+    tag: 'InsertMath' as 'InsertMath',
+    'InsertMath': latex,
+  };
+}
+
+export function EditMath(latex: string) {
+  return {
+    tag: 'EditMath' as 'EditMath',
+    'EditMath': latex,
+  };
+}
+
+export function InsertImage(src: string) {
+  return {
+    tag: 'InsertImage' as 'InsertImage',
+    'InsertImage': src,
+  };
+}
+
+export function InsertDocLink(pageId: string) {
+  return {
+    tag: 'InsertDocLink' as 'InsertDocLink',
+    'InsertDocLink': pageId,
+  };
+}
+
+export function InsertMention(userId: string, name: string) {
+  return {
+    tag: 'InsertMention' as 'InsertMention',
+    'InsertMention': [userId, name],
+  };
+}
+
+export function InsertAttachment(url: string, filename: string, size: number) {
+  return {
+    tag: 'InsertAttachment' as 'InsertAttachment',
+    'InsertAttachment': [url, filename, size],
+  };
+}
+
+export function InsertEmbed(url: string) {
+  return {
+    tag: 'InsertEmbed' as 'InsertEmbed',
+    'InsertEmbed': url,
+  };
+}
+
+export function RenderMode(mode: string | null) {
+  return {
+    tag: 'RenderMode' as 'RenderMode',
+    'RenderMode': mode,
+  };
+}
+
+export function CodeLanguage(lang: string | null) {
+  return {
+    tag: 'CodeLanguage' as 'CodeLanguage',
+    'CodeLanguage': lang,
+  };
+}
+
+export function Align(align: string | null) {
+  return {
+    tag: 'Align' as 'Align',
+    'Align': align,
+  };
+}
+
+export type Link = {href: string, title: string | null, new_tab: boolean};
+
+export function EditLink(link: Link | null) {
+  return {
+    tag: 'EditLink' as 'EditLink',
+    'EditLink': link,
+  };
+}
+
+export function FontSize(size: string | null) {
+  return {
+    tag: 'FontSize' as 'FontSize',
+    'FontSize': size,
+  };
+}
+
+export function FontFamily(family: string | null) {
+  return {
+    tag: 'FontFamily' as 'FontFamily',
+    'FontFamily': family,
+  };
+}
+
+export function Duplicate(history: boolean) {
+  return {
+    tag: 'Duplicate' as 'Duplicate',
+    'Duplicate': history,
+  };
+}
+
+export function MergeFork() {
+  return {
+    tag: 'MergeFork' as 'MergeFork',
+    'MergeFork': null,
+  };
+}
+
+export function Find(query: string) {
+  return {
+    tag: 'Find' as 'Find',
+    'Find': query,
+  };
+}
+
+export function SelectRange(anchor: number, focus: number) {
+  return {
+    tag: 'SelectRange' as 'SelectRange',
+    'SelectRange': [anchor, focus],
+  };
+}
+
+export function EncryptionKey(pageId: string, key: string) {
+  return {
+    tag: 'EncryptionKey' as 'EncryptionKey',
+    'EncryptionKey': [pageId, key],
+  };
+}
+
+export function PeerSync() {
+  return {
+    tag: 'PeerSync' as 'PeerSync',
+    'PeerSync': null,
+  };
+}
+
+export function StartComment(body: string) {
+  return {
+    tag: 'StartComment' as 'StartComment',
+    'StartComment': body,
+  };
+}
+
+export type CommentAction =
+  {'Reply': [string, string]}
+  | {'Resolve': string}
+  | {'Reopen': string};
+
+export function Comment(action: CommentAction) {
+  return {
+    tag: 'Comment' as 'Comment',
+    'Comment': action,
+  };
+}
+
+export function ShowResolvedComments(show: boolean) {
+  return {
+    tag: 'ShowResolvedComments' as 'ShowResolvedComments',
+    'ShowResolvedComments': show,
+  };
+}
+
+export type InputRule = 'SmartQuotes' | 'Dashes' | 'Ellipses' | 'Emoji' | 'Autocorrect';
+
+export function InputRule(
+  rule: InputRule,
+  enabled: boolean,
+) {
+  return {
+    tag: 'InputRule' as 'InputRule',
+    'InputRule': [rule, enabled],
+  };
+}
+
+export function Autocorrect(pattern: string, replacement: string | null) {
+  return {
+    tag: 'Autocorrect' as 'Autocorrect',
+    'Autocorrect': [pattern, replacement],
+  };
+}
+
+export function AutocorrectDictionary(rules: Array<[string, string]>) {
+  return {
+    tag: 'AutocorrectDictionary' as 'AutocorrectDictionary',
+    'AutocorrectDictionary': rules,
+  };
+}
+
+export type LintConfig = {
+  max_sentence_words: number | null,
+  passive_voice: boolean,
+  repeated_words: boolean,
+};
+
+export function Lint(config: LintConfig) {
+  return {
+    tag: 'Lint' as 'Lint',
+    'Lint': config,
+  };
+}
+
+export type PageLayout = {
+  lines_per_page: number,
+  chars_per_line: number,
+};
+
+export function Paginate(layout: PageLayout | null) {
+  return {
+    tag: 'Paginate' as 'Paginate',
+    'Paginate': layout,
+  };
+}
+
+export type Keymap = 'Default' | 'Emacs';
+
+export function Keymap(
+  keymap: Keymap,
+) {
+  return {
+    tag: 'Keymap' as 'Keymap',
+    'Keymap': keymap,
+  };
+}
+
 export function Connect(
   client: string,
 ) {
@@ -104,4 +408,43 @@ export type Command
   | ReturnType<typeof Load>
   | ReturnType<typeof Connect>
   | ReturnType<typeof InsertText>
+  | ReturnType<typeof SyncConnection>
+  | ReturnType<typeof DebugDump>
+  | ReturnType<typeof Stats>
+  | ReturnType<typeof TakeOver>
+  | ReturnType<typeof Vim>
+  | ReturnType<typeof Keymap>
+  | ReturnType<typeof SelectWord>
+  | ReturnType<typeof InputRule>
+  | ReturnType<typeof ToggleFold>
+  | ReturnType<typeof ScrollTo>
+  | ReturnType<typeof MoveSection>
+  | ReturnType<typeof EmojiCompletions>
+  | ReturnType<typeof CompleteEmoji>
+  | ReturnType<typeof InsertMath>
+  | ReturnType<typeof EditMath>
+  | ReturnType<typeof InsertImage>
+  | ReturnType<typeof InsertDocLink>
+  | ReturnType<typeof InsertMention>
+  | ReturnType<typeof InsertAttachment>
+  | ReturnType<typeof InsertEmbed>
+  | ReturnType<typeof RenderMode>
+  | ReturnType<typeof CodeLanguage>
+  | ReturnType<typeof Align>
+  | ReturnType<typeof EditLink>
+  | ReturnType<typeof FontSize>
+  | ReturnType<typeof FontFamily>
+  | ReturnType<typeof Duplicate>
+  | ReturnType<typeof MergeFork>
+  | ReturnType<typeof Find>
+  | ReturnType<typeof SelectRange>
+  | ReturnType<typeof EncryptionKey>
+  | ReturnType<typeof PeerSync>
+  | ReturnType<typeof StartComment>
+  | ReturnType<typeof Comment>
+  | ReturnType<typeof ShowResolvedComments>
+  | ReturnType<typeof Autocorrect>
+  | ReturnType<typeof AutocorrectDictionary>
+  | ReturnType<typeof Lint>
+  | ReturnType<typeof Paginate>
   ;
@@ -1,6 +1,7 @@
 import * as React from 'react';
 
 import * as commands from './commands';
+import * as route from '../ui/route';
 import * as util from './util';
 import { ControllerImpl } from './network';
 
@@ -61,6 +62,218 @@ function charLength(
   }
 }
 
+// Titles of linked pages, as last sent by the server. Pages that don't
+// exist have a null title.
+export const PAGE_TITLES: {[pageId: string]: string | null} = {};
+
+// Label document links with their target's title, and flag links to pages
+// that don't exist.
+export function renderDocLinks() {
+  document.querySelectorAll('div[data-tag="doclink"] a[data-page]').forEach(link => {
+    const pageId = link.getAttribute('data-page') || '';
+    if (!(pageId in PAGE_TITLES)) {
+      return;
+    }
+    const title = PAGE_TITLES[pageId];
+    link.textContent = title || pageId;
+    link.classList.toggle('broken', title === null);
+  });
+}
+
+export type UserIdentity = {
+  id: string,
+  name: string,
+  color: string,
+  avatar_url: string | null,
+};
+
+// Users editing the document, by client id.
+export const USERS: {[clientId: string]: UserIdentity} = {};
+
+// Show other users' carets in their color, labelled with their name.
+export function renderUsers() {
+  document.querySelectorAll('div[data-tag="caret"]').forEach(caret => {
+    const user = USERS[caret.getAttribute('data-client') || ''];
+    if (!user) {
+      return;
+    }
+    (caret as HTMLElement).style.setProperty('--caret-color', user.color);
+    caret.setAttribute('data-name', user.name);
+  });
+}
+
+export type CommentThread = {
+  id: string,
+  anchor: string,
+  resolved: boolean,
+  comments: Array<{author: string, body: string, created: number}>,
+};
+
+// Comment threads to show, as last sent by the client.
+export const COMMENTS: Array<CommentThread> = [];
+
+// Mark blocks that have comment threads, with the threads as a tooltip.
+export function renderComments() {
+  document.querySelectorAll('div[data-comments]').forEach(block => {
+    block.removeAttribute('data-comments');
+    block.removeAttribute('title');
+  });
+  const byAnchor: {[anchor: string]: Array<CommentThread>} = {};
+  COMMENTS.forEach(thread => {
+    (byAnchor[thread.anchor] = byAnchor[thread.anchor] || []).push(thread);
+  });
+  Object.keys(byAnchor).forEach(anchor => {
+    const block = document.getElementById(anchor);
+    if (!block) {
+      return;
+    }
+    const threads = byAnchor[anchor];
+    block.setAttribute('data-comments', String(threads.length));
+    block.setAttribute('title', threads.map(thread =>
+      (thread.resolved ? '(resolved) ' : '') +
+      thread.comments.map(comment => `${comment.author}: ${comment.body}`).join('\n'),
+    ).join('\n\n'));
+  });
+}
+
+// A function finding the DOM range of characters `start` to `end` of a
+// block, counting only its text and leaving out carets.
+function textRanges(block: Element): (start: number, end: number) => Range | null {
+  const texts: Array<{node: Text, start: number}> = [];
+  const walker = document.createTreeWalker(block, NodeFilter.SHOW_TEXT);
+  let offset = 0;
+  while (walker.nextNode()) {
+    const node = walker.currentNode as Text;
+    if (node.parentElement && node.parentElement.closest('div[data-tag="caret"]')) {
+      continue;
+    }
+    texts.push({node, start: offset});
+    offset += node.data.length;
+  }
+  const locate = (pos: number) => {
+    const text = texts.find(text => pos <= text.start + text.node.data.length);
+    return text ? {node: text.node, offset: pos - text.start} : null;
+  };
+
+  return (start, end) => {
+    const from = locate(start);
+    const to = locate(end);
+    if (!from || !to) {
+      return null;
+    }
+    const range = document.createRange();
+    range.setStart(from.node, from.offset);
+    range.setEnd(to.node, to.offset);
+    return range;
+  };
+}
+
+export type BlockLints = {
+  block: number,
+  id: string | null,
+  issues: Array<{start: number, end: number, kind: string, message: string}>,
+};
+
+// Readability issues of prose blocks, as last sent by the client.
+export const LINTS: Array<BlockLints> = [];
+
+// Underline readability issues with the CSS Custom Highlight API, like
+// code tokens, and list a block's issues in a tooltip. Blocks without an id
+// are found by their position among prose blocks.
+export function renderLints() {
+  document.querySelectorAll('div[data-lints]').forEach(block => {
+    block.removeAttribute('data-lints');
+  });
+  const prose = document.querySelectorAll(
+    ['p', 'h1', 'h2', 'h3', 'h4', 'h5', 'h6']
+      .map(tag => `${ROOT_SELECTOR} div[data-tag="${tag}"]`)
+      .join(', '),
+  );
+  const ranges: Array<Range> = [];
+  LINTS.forEach(lints => {
+    const block = (lints.id && document.getElementById(lints.id)) || prose[lints.block];
+    if (!block) {
+      return;
+    }
+    block.setAttribute('data-lints', lints.issues.map(issue => issue.message).join('\n'));
+    const rangeOf = textRanges(block);
+    lints.issues.forEach(issue => {
+      const range = rangeOf(issue.start, issue.end);
+      if (range) {
+        ranges.push(range);
+      }
+    });
+  });
+
+  const registry = (window as any).CSS && (CSS as any).highlights;
+  const Highlight = (window as any).Highlight;
+  if (registry && Highlight) {
+    registry.set('lint', new Highlight(...ranges));
+  }
+}
+
+export type PageBreak = {
+  page: number,
+  block: number,
+  id: string | null,
+};
+
+// Blocks that start a page, as last sent by the client.
+export const PAGE_BREAKS: Array<PageBreak> = [];
+
+// Number the blocks the client marked as starting a page, for the page
+// indicators. Marked blocks are in the same order as the breaks.
+export function renderPageBreaks() {
+  document.querySelectorAll(`${ROOT_SELECTOR} div.page-break`).forEach((block, i) => {
+    const pageBreak = PAGE_BREAKS[i];
+    if (pageBreak) {
+      block.setAttribute('data-page', String(pageBreak.page));
+    }
+  });
+}
+
+export type CodeHighlight = {
+  block: number,
+  id: string | null,
+  tokens: Array<{start: number, end: number, kind: string}>,
+};
+
+// Syntax highlighting of code blocks, as last sent by the client.
+export const HIGHLIGHTS: Array<CodeHighlight> = [];
+
+// Color code tokens with the CSS Custom Highlight API, which styles ranges
+// of text without adding elements that cursor positions would have to skip.
+// Browsers without it show code uncolored. Blocks without an id are found
+// by their position among code blocks.
+export function renderHighlights() {
+  const registry = (window as any).CSS && (CSS as any).highlights;
+  const Highlight = (window as any).Highlight;
+  if (!registry || !Highlight) {
+    return;
+  }
+
+  const ranges: {[kind: string]: Array<Range>} = {};
+  const code = document.querySelectorAll(`${ROOT_SELECTOR} div[data-tag="pre"]`);
+  HIGHLIGHTS.forEach(highlight => {
+    const block = (highlight.id && document.getElementById(highlight.id)) || code[highlight.block];
+    if (!block) {
+      return;
+    }
+
+    const rangeOf = textRanges(block);
+    highlight.tokens.forEach(token => {
+      const range = rangeOf(token.start, token.end);
+      if (range) {
+        (ranges[token.kind] = ranges[token.kind] || []).push(range);
+      }
+    });
+  });
+
+  ['Keyword', 'String', 'Number', 'Comment'].forEach(kind => {
+    registry.set(`code-${kind.toLowerCase()}`, new Highlight(...(ranges[kind] || [])));
+  });
+}
+
 export type CurElement = any;
 
 export type CurSpan = Array<CurElement>;
@@ -318,6 +531,12 @@ export class Editor extends React.Component {
     this.mouseDown = false;
   }
 
+  onDoubleClick(e: MouseEvent) {
+    // The first click of the pair already moved the caret.
+    this.props.controller.sendCommand(commands.SelectWord());
+    e.preventDefault();
+  }
+
   onMouseMove(e: MouseEvent, dropAnchor: boolean = false) {
     // Only enable dragging while the mouse is down.
     if (!this.mouseDown) {
@@ -388,6 +607,8 @@ export class Editor extends React.Component {
     }
 
     this.props.controller.sendCommand(commands.Character(e.charCode));
+    // Offer emoji for a shortcode being typed.
+    this.props.controller.sendCommand(commands.EmojiCompletions());
 
     e.preventDefault();
   }
@@ -397,11 +618,70 @@ export class Editor extends React.Component {
       return;
     }
 
+    // Pasted files are uploaded to the server and inserted once we know
+    // their URL. Images, like screenshots, are shown inline; other files
+    // become attachments.
+    const files = Array.from(e.clipboardData.files || []);
+    if (files.length > 0) {
+      e.preventDefault();
+      files.forEach(file => {
+        if (file.type.startsWith('image/')) {
+          this.uploadImage(file);
+        } else {
+          this.uploadAttachment(file);
+        }
+      });
+      return;
+    }
+
     const text = e.clipboardData.getData('text/plain');
     console.info('(c) got pasted text: ', text);
     this.props.controller.sendCommand(commands.InsertText(text));
   }
 
+  uploadImage(file: File) {
+    fetch(route.uploadUrl(), {
+      method: 'POST',
+      credentials: 'same-origin',
+      headers: {'Content-Type': file.type},
+      body: file,
+    })
+    .then(res => {
+      if (!res.ok) {
+        throw new Error(`upload failed with status ${res.status}`);
+      }
+      return res.json();
+    })
+    .then(data => {
+      this.props.controller.sendCommand(commands.InsertImage(data.url));
+    })
+    .catch(err => {
+      console.error('(c) image upload failed:', err);
+    });
+  }
+
+  uploadAttachment(file: File) {
+    fetch(route.uploadUrl(file.name), {
+      method: 'POST',
+      credentials: 'same-origin',
+      headers: {'Content-Type': file.type || 'application/octet-stream'},
+      body: file,
+    })
+    .then(res => {
+      if (!res.ok) {
+        throw new Error(`upload failed with status ${res.status}`);
+      }
+      return res.json();
+    })
+    .then(data => {
+      this.props.controller.sendCommand(
+        commands.InsertAttachment(data.url, file.name, data.size));
+    })
+    .catch(err => {
+      console.error('(c) attachment upload failed:', err);
+    });
+  }
+
   onGlobalKeydown(e: KeyboardEvent) {
     let current = document.querySelector('div.current[data-tag="caret"]');
 
@@ -423,9 +703,18 @@ export class Editor extends React.Component {
       return;
     }
 
+    // Control is treated as the meta key, so bindings like C-a work on
+    // every platform.
+    let keys: any = {
+      keyCode: e.keyCode,
+      metaKey: e.metaKey || e.ctrlKey,
+      shiftKey: e.shiftKey,
+      altKey: e.altKey,
+    };
+
     // Check if this event exists in the list of whitelisted key combinations.
     let isWhitelisted = this.props.KEY_WHITELIST
-      .some((x: any) => Object.keys(x).every((key: any) => (e as any)[key] == (x as any)[key]));
+      .some((x: any) => Object.keys(x).every((key: any) => keys[key] == (x as any)[key]));
     if (!isWhitelisted) {
       return;
     }
@@ -433,8 +722,9 @@ export class Editor extends React.Component {
     // Up and down cursor navigation to find text in the same visual column.
     // This requires we perform this from JavaScript, since we need to interact with
     // the client box model.
-    let UP = e.keyCode == 38;
-    let DOWN = e.keyCode == 40;
+    let plain = !(keys.metaKey || keys.altKey);
+    let UP = plain && e.keyCode == 38;
+    let DOWN = plain && e.keyCode == 40;
     if (UP || DOWN) {
       let current = document.querySelector('div.current[data-tag="caret"][data-focus="true"]');
       if (current !== null) {
@@ -458,10 +748,10 @@ export class Editor extends React.Component {
 
     // Forward the keypress to the controller.
     this.props.controller.sendCommand(commands.Keypress(
-      e.keyCode,
-      e.metaKey,
-      e.shiftKey,
-      e.altKey,
+      keys.keyCode,
+      keys.metaKey,
+      keys.shiftKey,
+      keys.altKey,
     ));
 
     e.preventDefault();
@@ -495,6 +785,44 @@ export class Editor extends React.Component {
     ).forEach(caret => {
       caret.classList.add("current");
     });
+    renderUsers();
+    renderComments();
+    renderLints();
+    renderPageBreaks();
+    renderHighlights();
+
+    // Render inline math from its LaTeX source, with KaTeX if the page
+    // loaded it, or else as the source itself.
+    document.querySelectorAll('div[data-tag="math"]').forEach(math => {
+      const latex = math.getAttribute('data-latex') || '';
+      const katex = (window as any).katex;
+      if (katex) {
+        katex.render(latex, math, {throwOnError: false});
+      } else {
+        math.textContent = latex;
+      }
+    });
+
+    renderDocLinks();
+
+    // Code blocks flagged for diagram rendering stay editable as text, with
+    // the rendered diagram shown after them, if the page loaded Mermaid.
+    const mermaid = (window as any).mermaid;
+    if (mermaid) {
+      document.querySelectorAll('div[data-tag="pre"][data-render="mermaid"]').forEach((block, i) => {
+        const preview = document.createElement('div');
+        preview.className = 'diagram-preview';
+        preview.contentEditable = 'false';
+        try {
+          mermaid.render(`diagram-${i}`, block.textContent || '', (svg: string) => {
+            preview.innerHTML = svg;
+          });
+        } catch (e) {
+          preview.textContent = String(e);
+        }
+        block.parentNode!.insertBefore(preview, block.nextSibling);
+      });
+    }
   }
 
   componentDidMount() {
@@ -519,6 +847,7 @@ export class Editor extends React.Component {
         onClick={this.onClick.bind(this)}
         onMouseDown={this.onMouseDown.bind(this)}
         onMouseUp={this.onMouseUp.bind(this)}
+        onDoubleClick={this.onDoubleClick.bind(this)}
         onMouseMove={this.onMouseMove.bind(this)}
         dangerouslySetInnerHTML={{
           __html: this.props.content,
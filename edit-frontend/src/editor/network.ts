@@ -12,10 +12,19 @@ export interface ControllerImpl {
   sendCommand(command: Command): void;
 }
 
+// Where an unfinished initial sync can continue from: the version being
+// synced, and the next chunk of it.
+export interface SyncResume {
+  version: number;
+  chunk: number;
+}
+
 export interface ServerImpl {
   onClose: () => void | null;
   connect(onError: (message: React.ReactNode) => void): Promise<void>;
   sendCommand(command: any): Promise<void>;
+  resumeFrom(resume: SyncResume | null): void;
+  rejoinAs(clientId: string): void;
 }
 
 export class NullServer implements ServerImpl {
@@ -30,4 +39,11 @@ export class NullServer implements ServerImpl {
   sendCommand(command: any): Promise<void> {
     return Promise.resolve();
   }
+
+  // The native server (the client proxy) handles sync traffic directly
+  resumeFrom(resume: SyncResume | null) {
+  }
+
+  rejoinAs(clientId: string) {
+  }
 }
@@ -2,13 +2,22 @@
 import 'react';
 
 import * as index from '..';
-import { WasmClient as WasmClientModule } from '../bindgen/edit_client';
+import { Editor } from '../bindgen/edit_client';
 import { getWasmModule } from '../index';
 
+import {
+  addSendCommandToJSHandler,
+  getForwardWasmTaskCallback,
+  setForwardWasmTaskCallback,
+} from './bridge';
 import {Command} from './commands';
 import {ControllerImpl, ServerImpl} from './network';
 import DEBUG from '../debug';
 
+declare var require: any;
+
+const ClientWorker = require('worker-loader!./client.worker');
+
 let _convertMarkdownToDoc: ((x: string) => any) | null = null;
 let _convertMarkdownToHtml: ((x: string) => any) | null = null;
 getWasmModule()
@@ -28,31 +37,7 @@ export function convertMarkdownToHtml(input: string): string {
 
 
 
-// TODO what are all of these things vvv
-
-let sendCommandToJSList: Array<(value: any) => void> = [];
-
-export function sendCommandToJS(msg: any) {
-  sendCommandToJSList.forEach(handler => handler(msg));
-}
-
-let forwardWasmTaskCallback: any = null;
-
-export function getForwardWasmTaskCallback(): any {
-  return forwardWasmTaskCallback;
-}
-
-export function setForwardWasmTaskCallback(value: any) {
-  forwardWasmTaskCallback = value;
-}
-
-export function forwardWasmTask(msg: any) {
-  if (forwardWasmTaskCallback) {
-    forwardWasmTaskCallback(msg);
-  }
-}
-
-// ^^^^^
+export {getForwardWasmTaskCallback, setForwardWasmTaskCallback};
 
 
 export class WasmError extends Error {
@@ -79,30 +64,59 @@ export class WasmClient implements ControllerImpl {
 
   // TODO refactor wasmClient, remove Module
   Module: any;
-  clientBindings: WasmClientModule;
+  clientBindings: Editor;
 
   sendCommand(command: Command) {
     delete command.tag;
-    if (forwardWasmTaskCallback != null) {
-      this.clientBindings.command(JSON.stringify({
+    if (getForwardWasmTaskCallback() != null) {
+      this.clientBindings.command({
         ControllerCommand: command,
-      }));
+      });
+    }
+  }
+
+  // Send a task, such as a ClientCommand from the sync server.
+  sendTask(task: any) {
+    this.clientBindings.command(task);
+  }
+
+  // Send a command from the sync server as the text it arrived as, so the
+  // client can parse it without copying its documents' text.
+  sendServerCommand(data: string) {
+    this.clientBindings.serverCommand(data);
+  }
+
+  exportMarkdown(): Promise<string> {
+    return Promise.resolve(this.clientBindings.exportMarkdown());
+  }
+
+  // The document and our edits sync hasn't confirmed, to restore later.
+  saveState(): Promise<string> {
+    return Promise.resolve(this.clientBindings.saveState());
+  }
+
+  restoreState(state: string): Promise<void> {
+    try {
+      this.clientBindings.restoreState(state);
+      return Promise.resolve();
+    } catch (e) {
+      return Promise.reject(e);
     }
   }
 
+  close() {
+    this.Module.wasm_close();
+  }
+
   // Wasm connector.
   connect(onError: () => void): Promise<void> {
     const client = this;
     return new Promise((resolve, reject) => {
-      sendCommandToJSList.push((data) => {
-        
-        // console.log('----> js_command:', data);
+      let onNativeCommand = (parse: any) => {
+        // console.log('----> js_command:', parse);
 
         // Make this async so we don't have deeply nested call stacks from Rust<->JS interop.
         setImmediate(() => {
-          // Parse the packet.
-          let parse = JSON.parse(data);
-
           if (parse.ServerCommand && client.server != null) {
             client.server.sendCommand(parse.ServerCommand);
           } else {
@@ -111,11 +125,13 @@ export class WasmClient implements ControllerImpl {
             }
           }
         });
-      });
+      };
+      addSendCommandToJSHandler(onNativeCommand);
 
       index.getWasmModule()
       .then(Module => {
-        let clientBindings = Module.wasm_setup();
+        let clientBindings = new Module.Editor();
+        clientBindings.onNativeCommand(onNativeCommand);
         DEBUG.setGlobalClientBindings(clientBindings);
   
         setImmediate(() => {
@@ -123,17 +139,18 @@ export class WasmClient implements ControllerImpl {
           client.Module = Module;
           client.clientBindings = clientBindings;
 
-          forwardWasmTaskCallback = (msg: any) => {
+          // Tasks from the monkey arrive as JSON strings.
+          setForwardWasmTaskCallback((msg: any) => {
             try {
-              clientBindings.command(msg);
+              clientBindings.command(typeof msg == 'string' ? JSON.parse(msg) : msg);
             } catch (e) {
-              forwardWasmTaskCallback = null;
+              setForwardWasmTaskCallback(null);
 
               onError();
 
               throw new WasmError(e, `Error during client command: ${e.message}`);
             }
-          };
+          });
 
           resolve();
         });
@@ -141,3 +158,98 @@ export class WasmClient implements ControllerImpl {
     });
   }
 }
+
+// Runs the wasm client in a Web Worker, so transforming operations doesn't
+// block the UI. All traffic with the client goes over postMessage.
+export class WorkerClient implements ControllerImpl {
+  // public
+  server: ServerImpl | null;
+  onMessage: (msg: any) => void | null;
+  onClose: () => void | null; // unused
+
+  private worker: Worker;
+  private markdownQueue: Array<(markdown: string) => void> = [];
+  private stateQueue: Array<(state: string) => void> = [];
+  private restoreQueue: Array<(error: string | null) => void> = [];
+
+  sendCommand(command: Command) {
+    delete command.tag;
+    this.sendTask({
+      ControllerCommand: command,
+    });
+  }
+
+  // Send a task, such as a ClientCommand from the sync server.
+  sendTask(task: any) {
+    this.worker.postMessage(task);
+  }
+
+  // Send a command from the sync server as the text it arrived as.
+  sendServerCommand(data: string) {
+    this.worker.postMessage({ServerCommand: data});
+  }
+
+  exportMarkdown(): Promise<string> {
+    return new Promise((resolve) => {
+      this.markdownQueue.push(resolve);
+      this.worker.postMessage({ExportMarkdown: true});
+    });
+  }
+
+  // The document and our edits sync hasn't confirmed, to restore later.
+  saveState(): Promise<string> {
+    return new Promise((resolve) => {
+      this.stateQueue.push(resolve);
+      this.worker.postMessage({SaveState: true});
+    });
+  }
+
+  restoreState(state: string): Promise<void> {
+    return new Promise((resolve, reject) => {
+      this.restoreQueue.push((error) => error == null ? resolve() : reject(new Error(error)));
+      this.worker.postMessage({RestoreState: state});
+    });
+  }
+
+  close() {
+    this.worker.terminate();
+  }
+
+  connect(onError: () => void): Promise<void> {
+    const client = this;
+    return new Promise((resolve, reject) => {
+      client.worker = new ClientWorker();
+      client.worker.onmessage = (event: MessageEvent) => {
+        let data = event.data;
+        if (data.WorkerReady) {
+          resolve();
+        } else if (data.WorkerError) {
+          onError();
+          console.error(new WasmError(new Error(data.WorkerError), `Error during client command: ${data.WorkerError}`));
+        } else if ('Markdown' in data) {
+          let callback = client.markdownQueue.shift();
+          if (callback) {
+            callback(data.Markdown);
+          }
+        } else if ('State' in data) {
+          let callback = client.stateQueue.shift();
+          if (callback) {
+            callback(data.State);
+          }
+        } else if ('Restored' in data) {
+          let callback = client.restoreQueue.shift();
+          if (callback) {
+            callback(data.Restored);
+          }
+        } else if (data.NativeCommand) {
+          let parse = data.NativeCommand;
+          if (parse.ServerCommand && client.server != null) {
+            client.server.sendCommand(parse.ServerCommand);
+          } else if (client.onMessage != null) {
+            client.onMessage(parse);
+          }
+        }
+      };
+    });
+  }
+}
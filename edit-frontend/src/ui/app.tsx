@@ -9,10 +9,26 @@ import * as Raven from 'raven-js';
 
 import * as commands from '../editor/commands';
 import * as route from './route';
-import { Editor } from '../editor/editor';
+import {
+  COMMENTS,
+  CommentThread,
+  Editor,
+  HIGHLIGHTS,
+  LINTS,
+  PAGE_BREAKS,
+  PAGE_TITLES,
+  USERS,
+  UserIdentity,
+  renderComments,
+  renderDocLinks,
+  renderHighlights,
+  renderLints,
+  renderPageBreaks,
+  renderUsers,
+} from '../editor/editor';
 import { AppServer, ProxyClient } from './sync';
 import { NullServer, ControllerImpl, ServerImpl } from '../editor/network';
-import { WasmClient, convertMarkdownToHtml, convertMarkdownToDoc } from '../editor/wasm';
+import { WasmClient, WorkerClient, convertMarkdownToHtml, convertMarkdownToDoc } from '../editor/wasm';
 import * as index from '../index';
 
 import DEBUG from '../debug';
@@ -24,6 +40,24 @@ if (!CONFIG.configured) {
   alert('The window.CONFIG variable was not configured by the server!')
 }
 
+// Readability checks the Lint button turns on, and off.
+const LINT_CHECKS: commands.LintConfig = {
+  max_sentence_words: 30,
+  passive_voice: true,
+  repeated_words: true,
+};
+const LINT_OFF: commands.LintConfig = {
+  max_sentence_words: null,
+  passive_voice: false,
+  repeated_words: false,
+};
+
+// Page layout the Pages button turns on: a Letter or A4 page with 12pt text.
+const PAGE_LAYOUT: commands.PageLayout = {
+  lines_per_page: 46,
+  chars_per_line: 80,
+};
+
 function UiElement(
   props: {
     editor: EditorFrame,
@@ -101,6 +135,23 @@ mutation ($id: String!, $markdown: String!) { createPage(id: $id, markdown: $mar
   );
 }
 
+// Mark mentions as seen once they've been shown, so they aren't shown again.
+export function graphqlMarkMentionsSeen(user: string, ids: Array<number>) {
+  return axios.post(
+    route.graphqlUrl(),
+    {
+      query:
+`
+mutation ($user: String!, $ids: [Int!]!) { markMentionsSeen(user: $user, ids: $ids) }
+`,
+      variables: {
+        user,
+        ids,
+      },
+    }
+  );
+}
+
 class MarkdownModal extends React.Component {
   props: {
     markdown: string,
@@ -149,6 +200,23 @@ class MarkdownModal extends React.Component {
   }
 }
 
+// The client's recent task history, for attaching to bug reports.
+function DebugDumpModal(props: {
+  dump: string,
+  onModal: (modal: React.ReactNode) => void,
+}) {
+  return (
+    <Modal>
+      <h1>Debug Dump</h1>
+      <p>The client's recent history is shown below. Copy it into a bug report to help reproduce a problem.</p>
+      <textarea value={props.dump} readOnly />
+      <div className="modal-buttons">
+        <button className="dismiss" onClick={() => props.onModal(null)}>Back</button>
+      </div>
+    </Modal>
+  );
+}
+
 class LocalButtons extends React.Component {
   props: {
     editorID: string,
@@ -177,6 +245,103 @@ class LocalButtons extends React.Component {
     })
   }
 
+  // Copy the document into a new page, which opens once it's made.
+  onDuplicateClick() {
+    const history = confirm('Copy the document with its history? Cancel copies just its content.');
+    this.props.editor.client.sendCommand(commands.Duplicate(history));
+  }
+
+  // Merge the changes made in this copy back into the page it was copied from.
+  onMergeForkClick() {
+    if (confirm('Merge this copy\'s changes back into the original document?')) {
+      this.props.editor.client.sendCommand(commands.MergeFork());
+    }
+  }
+
+  // Search the document, selecting the first match.
+  onFindClick() {
+    const query = prompt('Find:');
+    if (query) {
+      this.props.editor.client.sendCommand(commands.Find(query));
+    }
+  }
+
+  // Start a comment thread on the block the caret is in.
+  onCommentClick() {
+    const body = prompt('Comment:');
+    if (body) {
+      this.props.editor.client.sendCommand(commands.StartComment(body));
+    }
+  }
+
+  toggleResolvedComments() {
+    const show = !this.props.editor.state.showResolvedComments;
+    this.props.editor.setState({showResolvedComments: show});
+    this.props.editor.client.sendCommand(commands.ShowResolvedComments(show));
+  }
+
+  // Mention a user connected to the page by name, or anyone by their id.
+  onMentionClick() {
+    const query = prompt('Mention:');
+    if (!query) {
+      return;
+    }
+    const user = Object.keys(USERS)
+      .map(clientId => USERS[clientId])
+      .find(user => user.name.toLowerCase() == query.toLowerCase() || user.id == query);
+    this.props.editor.client.sendCommand(user ?
+      commands.InsertMention(user.id, user.name) :
+      commands.InsertMention(query, query));
+  }
+
+  // Link to another page by its id, or its path.
+  onDocLinkClick() {
+    const pageId = prompt('Link to page:');
+    if (pageId) {
+      this.props.editor.client.sendCommand(commands.InsertDocLink(pageId.replace(/^\//, '')));
+    }
+  }
+
+  // Always autocorrect a pattern to a replacement, which this browser
+  // remembers. An empty replacement stops autocorrecting the pattern.
+  onAutocorrectClick() {
+    const pattern = prompt('Autocorrect:');
+    if (!pattern) {
+      return;
+    }
+    const replacement = prompt(`Replace "${pattern}" with (empty to stop):`);
+    if (replacement == null) {
+      return;
+    }
+    route.saveAutocorrect(pattern, replacement || null);
+    this.props.editor.client.sendCommand(commands.Autocorrect(pattern, replacement || null));
+  }
+
+  // Turn the readability checks on or off, which this browser remembers.
+  toggleLints() {
+    const config = this.props.editor.state.lint ? null : LINT_CHECKS;
+    route.saveLintConfig(config);
+    this.props.editor.setState({lint: config != null});
+    this.props.editor.client.sendCommand(commands.Lint(config || LINT_OFF));
+  }
+
+  // Show where pages break, which this browser remembers.
+  togglePages() {
+    const layout = this.props.editor.state.paginated ? null : PAGE_LAYOUT;
+    route.savePageLayout(layout);
+    this.props.editor.setState({paginated: layout != null});
+    this.props.editor.client.sendCommand(commands.Paginate(layout));
+  }
+
+  // Open the document as a PDF, with its pages broken as they're shown.
+  onPdfClick() {
+    const layout = route.pageLayout() || PAGE_LAYOUT;
+    const share = route.shareToken();
+    const params = `lines=${layout.lines_per_page}&chars=${layout.chars_per_line}` +
+      (share != null ? `&share=${share}` : '');
+    window.open(`/${route.pageId()}/pdf?${params}`);
+  }
+
   toggleWidth() {
     document.body.classList.toggle('theme-column');
     if (!document.body.classList.contains('theme-column')) {
@@ -193,6 +358,46 @@ class LocalButtons extends React.Component {
 
         <button id="width" onClick={() => this.toggleWidth()}>Page Width</button>
 
+        <button
+          className={this.props.editor.state.paginated ? 'active' : ''}
+          onClick={() => this.togglePages()}
+        >Pages</button>
+
+        <button onClick={() => this.onPdfClick()}>PDF</button>
+
+        <button onClick={() => this.onFindClick()}>Find</button>
+
+        <button
+          className={this.props.editor.state.lint ? 'active' : ''}
+          onClick={() => this.toggleLints()}
+        >Lint</button>
+
+        <button onClick={() => this.onDocLinkClick()}>Link Page</button>
+
+        <button onClick={() => this.onMentionClick()}>Mention</button>
+
+        <button onClick={() => this.onCommentClick()}>Comment</button>
+
+        <button onClick={() => this.onAutocorrectClick()}>Autocorrect</button>
+
+        <button
+          className={this.props.editor.state.showResolvedComments ? 'active' : ''}
+          onClick={() => this.toggleResolvedComments()}
+        >Resolved</button>
+
+        <button onClick={() => this.onDuplicateClick()}>Duplicate</button>
+
+        <button onClick={() => this.onMergeForkClick()}>Merge Back</button>
+
+        <button
+          className={this.props.editor.state.vimMode ? 'active' : ''}
+          onClick={() => this.props.editor.client.sendCommand(commands.Vim(!this.props.editor.state.vimMode))}
+        >Vim</button>
+
+        <button onClick={() => this.props.editor.client.sendCommand(commands.Stats())}>Stats</button>
+
+        <button onClick={() => this.props.editor.client.sendCommand(commands.DebugDump())}>Debug</button>
+
         <b style={{marginLeft: 10, whiteSpace: 'nowrap'}}>
           Client: <kbd tabIndex={0}>{this.props.editorID}</kbd>
         </b>
@@ -201,6 +406,174 @@ class LocalButtons extends React.Component {
   }
 }
 
+export type FormatState = {
+  styles: Array<string>,
+  block: string,
+  list_depth: number,
+  link: {href: string, title: string | null, new_tab: boolean} | null,
+  font_size: string | null,
+  font_family: string | null,
+};
+
+// Describe the formatting at the caret, like "h1, Bold, in list".
+function formatDescription(format: FormatState): string {
+  let parts = [format.block];
+  parts = parts.concat(format.styles.filter(style => style !== 'Link' && style !== 'Selected').sort());
+  if (format.font_size) {
+    parts.push(format.font_size);
+  }
+  if (format.font_family) {
+    parts.push(format.font_family);
+  }
+  if (format.list_depth > 0) {
+    parts.push(format.list_depth > 1 ? `in list (${format.list_depth} deep)` : 'in list');
+  }
+  if (format.link) {
+    parts.push(`link to ${format.link.href}`);
+  }
+  return parts.join(', ');
+}
+
+// What the client reports about the editor, shown next to the toolbar.
+function StatusBar(props: {
+  editor: EditorFrame,
+  syncStatus: string | null,
+  readOnly: boolean,
+  vimMode: string | null,
+  format: FormatState | null,
+}) {
+  return (
+    <div id="status">
+      {props.format ?
+        <span className="format-state">{formatDescription(props.format)}</span>
+        : null}
+      {props.vimMode ?
+        <span className="vim-mode">-- {props.vimMode.toUpperCase()} --</span>
+        : null}
+      {props.syncStatus ?
+        <span className={`sync-status ${props.syncStatus.toLowerCase()}`}>{props.syncStatus}</span>
+        : null}
+      {props.readOnly ?
+        <span>
+          Someone else is editing.
+          <button onClick={() => props.editor.client.sendCommand(commands.TakeOver())}>Take Over</button>
+        </span>
+        : null}
+    </div>
+  );
+}
+
+export type OutlineEntry = {
+  text: string,
+  level: number,
+  anchor: string,
+};
+
+// The document's headings, indented by level. Picking one moves the caret
+// to it, and the client scrolls it into view. Dragging one moves its section
+// to before the heading it's dropped on, or to the end of the document when
+// it's dropped below them.
+function Outline(props: {
+  editor: EditorFrame,
+  outline: Array<OutlineEntry>,
+}) {
+  if (!props.outline.length) {
+    return null;
+  }
+  const draggable = !props.editor.state.readOnly;
+  const moveTo = (e: React.DragEvent<HTMLElement>, index: number) => {
+    const anchor = e.dataTransfer.getData('text/x-outline-anchor');
+    if (anchor) {
+      e.preventDefault();
+      e.stopPropagation();
+      props.editor.client.sendCommand(commands.MoveSection(anchor, index));
+    }
+  };
+  return (
+    <nav
+      id="outline"
+      onDragOver={(e) => e.preventDefault()}
+      onDrop={(e) => moveTo(e, props.outline.length)}
+    >{
+      props.outline.map((entry, i) => (
+        <a
+          key={i}
+          href={`#${entry.anchor}`}
+          style={{paddingLeft: (entry.level - 1) * 12}}
+          draggable={draggable}
+          onDragStart={(e) => e.dataTransfer.setData('text/x-outline-anchor', entry.anchor)}
+          onDrop={(e) => moveTo(e, i)}
+          onClick={(e) => {
+            e.preventDefault();
+            props.editor.client.sendCommand(commands.ScrollTo(entry.anchor));
+          }}
+        >{entry.text || '(untitled)'}</a>
+      ))
+    }</nav>
+  );
+}
+
+// Emoji completing the shortcode before the caret.
+// Comment threads, with buttons to reply to, resolve, or reopen them.
+// Picking a thread scrolls to the block it's on.
+function Comments(props: {
+  editor: EditorFrame,
+  comments: Array<CommentThread>,
+}) {
+  if (!props.comments.length) {
+    return null;
+  }
+  const send = (command: commands.Command) => props.editor.client.sendCommand(command);
+  return (
+    <aside id="comments">{
+      props.comments.map(thread => (
+        <div key={thread.id} className={thread.resolved ? 'thread resolved' : 'thread'}>
+          <a
+            href={`#${thread.anchor}`}
+            onClick={(e) => {
+              e.preventDefault();
+              send(commands.ScrollTo(thread.anchor));
+            }}
+          >{thread.comments.map((comment, i) => (
+            <p key={i}><b>{comment.author}</b> {comment.body}</p>
+          ))}</a>
+          <button
+            onClick={() => {
+              const body = prompt('Reply:');
+              if (body) {
+                send(commands.Comment({'Reply': [thread.id, body]}));
+              }
+            }}
+          >Reply</button>
+          {thread.resolved ?
+            <button onClick={() => send(commands.Comment({'Reopen': thread.id}))}>Reopen</button> :
+            <button onClick={() => send(commands.Comment({'Resolve': thread.id}))}>Resolve</button>}
+        </div>
+      ))
+    }</aside>
+  );
+}
+
+function EmojiCompletions(props: {
+  editor: EditorFrame,
+  completions: Array<[string, string]>,
+}) {
+  if (!props.completions.length) {
+    return null;
+  }
+  return (
+    <div id="emoji-completions">{
+      props.completions.map(([shortcode, emoji]) => (
+        <button
+          key={shortcode}
+          title={`:${shortcode}:`}
+          onClick={() => props.editor.client.sendCommand(commands.CompleteEmoji(shortcode))}
+        >{emoji} <span>{shortcode}</span></button>
+      ))
+    }</div>
+  );
+}
+
 function Modal(props: any) {
   return (
     <div id="modal">
@@ -246,6 +619,16 @@ export class EditorFrame extends React.Component {
     editorID: string,
     modal: React.ReactNode,
     notices: Array<NoticeProps>,
+    syncStatus: string | null,
+    readOnly: boolean,
+    vimMode: string | null,
+    format: FormatState | null,
+    outline: Array<OutlineEntry>,
+    emojiCompletions: Array<[string, string]>,
+    comments: Array<CommentThread>,
+    showResolvedComments: boolean,
+    lint: boolean,
+    paginated: boolean,
   };
 
   KEY_WHITELIST: any;
@@ -253,6 +636,11 @@ export class EditorFrame extends React.Component {
   client: ControllerImpl;
   markdown: string;
 
+  // While the client rolls back an edit the server refused, the latest
+  // page content, shown once the rollback is done.
+  correcting: boolean = false;
+  heldBody: string | null = null;
+
   constructor(
     props: EditorFrameProps,
   ) {
@@ -271,9 +659,11 @@ export class EditorFrame extends React.Component {
       document.body.style.background = 'red';
       console.error('!!! client close');
     };
+    let client = this.client;
     this.network.onClose = function () {
       document.body.style.background = 'red';
       console.error('!!! server close');
+      client.sendCommand(commands.SyncConnection(false));
     };
 
     this.state = {
@@ -282,6 +672,16 @@ export class EditorFrame extends React.Component {
       editorID: '$$$$$$',
       modal: null,
       notices: [],
+      syncStatus: null,
+      readOnly: false,
+      vimMode: null,
+      format: null,
+      outline: [],
+      emojiCompletions: [],
+      comments: [],
+      showResolvedComments: false,
+      lint: route.lintConfig() != null,
+      paginated: route.pageLayout() != null,
     };
   }
 
@@ -314,9 +714,24 @@ export class EditorFrame extends React.Component {
                 });
               }}
             />
+            <StatusBar
+              editor={this}
+              syncStatus={this.state.syncStatus}
+              readOnly={this.state.readOnly}
+              vimMode={this.state.vimMode}
+              format={this.state.format}
+            />
           </div>
 
           <div id="edit-layout">
+            <Outline
+              editor={this}
+              outline={this.state.outline}
+            />
+            <Comments
+              editor={this}
+              comments={this.state.comments}
+            />
             <div
               id="edit-outer"
               ref={r => editBoundary = r}
@@ -335,12 +750,16 @@ export class EditorFrame extends React.Component {
                 KEY_WHITELIST={this.KEY_WHITELIST}
                 content={this.state.body}
                 editorID={this.state.editorID}
-                disabled={!!this.state.modal}
+                disabled={!!this.state.modal || this.state.readOnly}
                 ref={r => editor = r}
               />
             </div>
           </div>
         </div>
+        <EmojiCompletions
+          editor={this}
+          completions={this.state.emojiCompletions}
+        />
         <div id="footer">{
           this.state.notices.map((x, key) => {
             return (
@@ -377,6 +796,10 @@ export class EditorFrame extends React.Component {
 
       console.info('Editor "%s" connected.', editorID);
 
+      // Reconnect as the same editor, so sync recognizes edits we send
+      // again.
+      this.network.rejoinAs(editorID);
+
       // Log the editor ID.
       Raven.setExtraContext({
         editor_id: editorID,
@@ -387,9 +810,25 @@ export class EditorFrame extends React.Component {
       DEBUG.measureTime('first-update');
 
       // Update page content
-      this.setState({
-        body: parse.Update[0],
-      });
+      if (this.correcting) {
+        this.heldBody = parse.Update[0];
+      } else {
+        this.setState({
+          body: parse.Update[0],
+        });
+      }
+    }
+
+    // Hold back content during a rollback, so the refused edit doesn't
+    // flicker out and back in with the edits made after it.
+    else if ('Correcting' in parse) {
+      this.correcting = parse.Correcting;
+      if (!this.correcting && this.heldBody !== null) {
+        this.setState({
+          body: this.heldBody,
+        });
+        this.heldBody = null;
+      }
     }
 
     else if (parse.Controls) {
@@ -397,10 +836,11 @@ export class EditorFrame extends React.Component {
 
       // Update the key list in-place.
       editor.KEY_WHITELIST.splice.apply(editor.KEY_WHITELIST,
-        [0, 0].concat(parse.Controls.keys.map((x: any) => ({
+        [0, editor.KEY_WHITELIST.length].concat(parse.Controls.keys.map((x: any) => ({
           keyCode: x[0],
           metaKey: x[1],
           shiftKey: x[2],
+          altKey: x[3],
         })))
       );
 
@@ -412,6 +852,154 @@ export class EditorFrame extends React.Component {
       DEBUG.measureTime('interactive');
     }
 
+    // Open the copy we asked the server to make.
+    else if (parse.Duplicated) {
+      window.open(`/${parse.Duplicated}`, '_blank');
+    }
+
+    else if (parse.PageTitles) {
+      parse.PageTitles.forEach(([pageId, title]: [string, string | null]) => {
+        PAGE_TITLES[pageId] = title;
+      });
+      renderDocLinks();
+    }
+
+    else if (parse.Users) {
+      Object.keys(USERS).forEach(clientId => delete USERS[clientId]);
+      parse.Users.forEach(([clientId, user]: [string, UserIdentity]) => {
+        USERS[clientId] = user;
+      });
+      renderUsers();
+    }
+
+    else if (parse.Comments) {
+      COMMENTS.splice(0, COMMENTS.length, ...(parse.Comments as Array<CommentThread>));
+      renderComments();
+      this.setState({
+        comments: parse.Comments,
+      });
+    }
+
+    else if (parse.Lints) {
+      LINTS.splice(0, LINTS.length, ...parse.Lints);
+      renderLints();
+    }
+
+    else if (parse.Highlight) {
+      HIGHLIGHTS.splice(0, HIGHLIGHTS.length, ...parse.Highlight);
+      renderHighlights();
+    }
+
+    else if (parse.PageBreaks) {
+      PAGE_BREAKS.splice(0, PAGE_BREAKS.length, ...parse.PageBreaks);
+      renderPageBreaks();
+    }
+
+    // Tell the user where they've been mentioned.
+    else if (parse.Mentions) {
+      parse.Mentions.forEach((mention: any) => {
+        let href = `/${mention.page_id}` + (mention.anchor ? `#${mention.anchor}` : '');
+        this.showNotification({
+          element: (<div>
+            {mention.author} mentioned you in <a href={href}>{PAGE_TITLES[mention.page_id] || mention.page_id}</a>.
+          </div>),
+          level: 'notice',
+        });
+      });
+      graphqlMarkMentionsSeen(route.userId(), parse.Mentions.map((mention: any) => mention.id))
+      .catch(err => {
+        console.error('markMentionsSeen:', err);
+      });
+    }
+
+    // Select the first match of a search.
+    else if (parse.FindResults) {
+      const [query, matches] = parse.FindResults;
+      console.info('Found %d matches for %o.', matches.length, query);
+      if (matches.length > 0) {
+        this.client.sendCommand(commands.SelectRange(matches[0][0], matches[0][1]));
+      }
+    }
+
+    // Name the tab after the document.
+    else if ('Title' in parse) {
+      document.title = parse.Title ? `${parse.Title} - ${CONFIG.title}` : CONFIG.title;
+    }
+
+    else if (parse.SyncStatus) {
+      this.setState({
+        syncStatus: parse.SyncStatus,
+      });
+    }
+
+    // Another client holds the write lock.
+    else if ('ReadOnly' in parse) {
+      this.setState({
+        readOnly: parse.ReadOnly,
+      });
+    }
+
+    // The Vim mode, or null once modal editing is off.
+    else if ('VimMode' in parse) {
+      this.setState({
+        vimMode: parse.VimMode,
+      });
+    }
+
+    else if (parse.FormatState) {
+      this.setState({
+        format: parse.FormatState,
+      });
+    }
+
+    else if (parse.Outline) {
+      this.setState({
+        outline: parse.Outline,
+      });
+    }
+
+    else if (parse.ScrollTo) {
+      const block = document.getElementById(parse.ScrollTo);
+      if (block) {
+        block.scrollIntoView({block: 'center'});
+      }
+    }
+
+    else if (parse.EmojiCompletions) {
+      this.setState({
+        emojiCompletions: parse.EmojiCompletions,
+      });
+    }
+
+    else if (parse.Stats) {
+      const stats = parse.Stats;
+      const minutes = Math.max(1, Math.round(stats.reading_time / 60));
+      this.showNotification({
+        element: (<div>
+          {stats.words} words, {stats.chars} characters, {stats.blocks} blocks. About {minutes} min to read.
+        </div>),
+        level: 'notice',
+      });
+    }
+
+    else if ('DebugDump' in parse) {
+      this.setState({
+        modal: (
+          <DebugDumpModal
+            dump={parse.DebugDump}
+            onModal={(modal) => this.setState({modal})}
+          />
+        ),
+      });
+    }
+
+    // An initial sync arriving in chunks. Until its last chunk, a dropped
+    // connection can pick up where it left off.
+    else if (parse.SyncProgress) {
+      const [version, received, count] = parse.SyncProgress;
+      this.network.resumeFrom(received < count ? {version, chunk: received} : null);
+    }
+
     else {
       console.error('Unknown packet:', parse);
     }
@@ -508,6 +1096,7 @@ class EditText extends React.Component {
             keyCode: x[0],
             metaKey: x[1],
             shiftKey: x[2],
+            altKey: x[3],
           })),
         });
       }
@@ -517,11 +1106,11 @@ class EditText extends React.Component {
       .connect(() => {})
       .then(() => {
         console.log('Loading static editor.');
-        this.props.client.clientBindings.command(JSON.stringify({
+        this.props.client.clientBindings.command({
           ClientCommand: {
             Init: ["$local", convertMarkdownToDoc(this.props.markdown), 100],
           } 
-        }));
+        });
       });
   }
 }
@@ -555,14 +1144,33 @@ export function start_standalone() {
   });
 }
 
+// Milliseconds between saves of edits the server hasn't confirmed.
+const CLIENT_STATE_INTERVAL = 2000;
+
+// Save our state while the server hasn't confirmed our edits, and forget it
+// once it has.
+function keepClientState(client: WasmClient | WorkerClient, editorFrame: EditorFrame) {
+  setInterval(() => {
+    let status = editorFrame.state.syncStatus;
+    if (status == 'Synced') {
+      route.saveClientState(null);
+    } else if (status != null) {
+      client.saveState().then((state) => route.saveClientState(state));
+    }
+  }, CLIENT_STATE_INTERVAL);
+}
+
 export function start() {
 // export function start_app() {
   let server: ServerImpl;
   let client: ControllerImpl;
+  // The wasm client, whose state is kept across page loads.
+  let persistent: WasmClient | WorkerClient | null = null;
 
   // Wasm and Proxy implementations
   if (CONFIG.wasm) {
-    let wasmClient = new WasmClient();
+    // Optionally run the client off the main thread.
+    let wasmClient = CONFIG.worker ? new WorkerClient() : new WasmClient();
     let wasmServer = new AppServer();
 
     // Link them.
@@ -571,6 +1179,7 @@ export function start() {
 
     client = wasmClient;
     server = wasmServer;
+    persistent = wasmClient;
   } else {
     client = new ProxyClient();
     server = new NullServer();
@@ -627,11 +1236,56 @@ export function start() {
           // TODO
         })
         .then(() => {
-          server.connect((message: React.ReactNode) => {
-            editorFrame!.showNotification({
-              element: message,
-              level: 'error',
+          let dictionary = route.autocorrectDictionary();
+          if (dictionary.length) {
+            client.sendCommand(commands.AutocorrectDictionary(dictionary));
+          }
+
+          let lint = route.lintConfig();
+          if (lint != null) {
+            client.sendCommand(commands.Lint(lint));
+          }
+
+          let layout = route.pageLayout();
+          if (layout != null) {
+            client.sendCommand(commands.Paginate(layout));
+          }
+
+          // Encrypted pages need their key before anything is synced.
+          let key = route.encryptionKey();
+          if (CONFIG.wasm && key != null) {
+            client.sendCommand(commands.EncryptionKey(route.pageId(), key));
+          }
+
+          // Peer pages are synced as a CRDT, which is also set up first.
+          let peer = route.peerSync();
+          if (CONFIG.wasm && peer) {
+            client.sendCommand(commands.PeerSync());
+          }
+
+          // Edits the server hadn't confirmed when the page was last open
+          // are restored, to be sent once we're synced. Encrypted pages
+          // aren't kept, so their text never rests unencrypted, and neither
+          // are peer pages, whose edits the other peers keep.
+          let saved = key == null && !peer ? route.savedClientState() : null;
+          let restored = persistent != null && saved != null ?
+            persistent.restoreState(saved).catch((e: any) => {
+              console.warn('Could not restore saved edits:', e);
+              route.saveClientState(null);
+            }) :
+            Promise.resolve();
+
+          restored.then(() => {
+            server.connect((message: React.ReactNode) => {
+              editorFrame!.showNotification({
+                element: message,
+                level: 'error',
+              });
             });
+
+            if (persistent != null && key == null && !peer) {
+              keepClientState(persistent, editorFrame!);
+            }
           });
         });
     }
@@ -1,7 +1,139 @@
+import * as commands from '../editor/commands';
+
 export function pageId(): string {
   return window.location.pathname.match(/^\/?([^\/]+)/)![1] || '';
 }
 
+// Stable identifier for this browser, so the server can restore our caret
+// when we reconnect.
+export function userId(): string {
+  let id = localStorage.getItem('edit-text:user-id');
+  if (id == null) {
+    id = Math.random().toString(36).slice(2, 14);
+    localStorage.setItem('edit-text:user-id', id);
+  }
+  return id;
+}
+
+// How we're shown to other users, if set: a display name, a caret color
+// like "#4363d8", and an avatar URL.
+function userProfileParams(): string {
+  let params = '';
+  for (let [key, param] of [['user-name', 'name'], ['user-color', 'color'], ['user-avatar', 'avatar']]) {
+    let value = localStorage.getItem('edit-text:' + key);
+    if (value) {
+      params += '&' + param + '=' + encodeURIComponent(value);
+    }
+  }
+  return params;
+}
+
+// This browser's autocorrect dictionary, as pattern and replacement pairs.
+export function autocorrectDictionary(): Array<[string, string]> {
+  try {
+    let rules = JSON.parse(localStorage.getItem('edit-text:autocorrect') || '[]');
+    return Array.isArray(rules) ? rules : [];
+  } catch (e) {
+    return [];
+  }
+}
+
+// Always autocorrect a pattern to a replacement from now on, or stop with
+// null.
+export function saveAutocorrect(pattern: string, replacement: string | null) {
+  let rules = autocorrectDictionary().filter(([existing]) => existing.toLowerCase() != pattern.toLowerCase());
+  if (replacement != null) {
+    rules.push([pattern, replacement]);
+  }
+  localStorage.setItem('edit-text:autocorrect', JSON.stringify(rules));
+}
+
+// The page layout this browser paginates documents in, if it does.
+export function pageLayout(): commands.PageLayout | null {
+  try {
+    let layout = JSON.parse(localStorage.getItem('edit-text:page-layout') || 'null');
+    return layout && layout.lines_per_page > 0 && layout.chars_per_line > 0 ? layout : null;
+  } catch (e) {
+    return null;
+  }
+}
+
+export function savePageLayout(layout: commands.PageLayout | null) {
+  if (layout == null) {
+    localStorage.removeItem('edit-text:page-layout');
+  } else {
+    localStorage.setItem('edit-text:page-layout', JSON.stringify(layout));
+  }
+}
+
+// This browser's readability checks, or null if they're off.
+export function lintConfig(): commands.LintConfig | null {
+  try {
+    return JSON.parse(localStorage.getItem('edit-text:lint') || 'null');
+  } catch (e) {
+    return null;
+  }
+}
+
+export function saveLintConfig(config: commands.LintConfig | null) {
+  if (config == null) {
+    localStorage.removeItem('edit-text:lint');
+  } else {
+    localStorage.setItem('edit-text:lint', JSON.stringify(config));
+  }
+}
+
+// Our saved state for this page, with edits the server hadn't confirmed
+// when it was saved.
+export function savedClientState(): string | null {
+  return localStorage.getItem('edit-text:client-state:' + pageId());
+}
+
+// Save our state for this page, or forget it with null once the server has
+// confirmed our edits.
+export function saveClientState(state: string | null) {
+  if (state == null) {
+    localStorage.removeItem('edit-text:client-state:' + pageId());
+  } else {
+    localStorage.setItem('edit-text:client-state:' + pageId(), state);
+  }
+}
+
+// Key of an end-to-end encrypted page, which is kept in the URL fragment so
+// it's never sent to the server. Opening a page at "#e2ee" creates a new key.
+export function encryptionKey(): string | null {
+  if (window.location.hash == '#e2ee') {
+    let bytes = new Uint8Array(32);
+    window.crypto.getRandomValues(bytes);
+    let key = btoa(String.fromCharCode.apply(null, Array.from(bytes)))
+      .replace(/\+/g, '-')
+      .replace(/\//g, '_')
+      .replace(/=+$/, '');
+    window.location.hash = 'key=' + key;
+  }
+  let match = window.location.hash.match(/key=([A-Za-z0-9_-]+)/);
+  return match ? match[1] : null;
+}
+
+// Whether this page is synced between peers as a CRDT, which it is when
+// opened at "#peer".
+export function peerSync(): boolean {
+  return window.location.hash == '#peer';
+}
+
+// Token of the share link this page was opened with, as ?share=...
+export function shareToken(): string | null {
+  let match = window.location.search.match(/[?&]share=([A-Za-z0-9]+)/);
+  return match ? match[1] : null;
+}
+
+// The URL to upload a file to this page at, named if it isn't an image.
+export function uploadUrl(filename?: string): string {
+  return '/$/upload?page=' + encodeURIComponent(pageId()) +
+    (shareToken() != null ? '&share=' + shareToken() : '') +
+    (filename != null ? '&filename=' + encodeURIComponent(filename) : '');
+}
+
 export function clientProxyUrl(): string {
   return '' +
     (window.location.protocol.match(/^https/) ? 'wss://' : 'ws://') +
@@ -10,12 +142,28 @@ export function clientProxyUrl(): string {
     pageId();
 }
 
-export function syncUrl(): string {
+// Version of the protocol this frontend's client speaks to the sync server.
+// Keep it in step with PROTOCOL_VERSION in edit-common/src/protocol.
+export const PROTOCOL_VERSION = 5;
+
+// The URL of the sync server, resuming an unfinished initial sync if given
+// where to resume it from, and reconnecting as a client id if given one.
+export function syncUrl(
+  resume?: {version: number, chunk: number} | null,
+  clientId?: string | null,
+): string {
   return '' +
     (window.location.protocol.match(/^https/) ? 'wss://' : 'ws://') +
     (window.location.host.match(/localhost|0.0.0.0/) ?
       window.location.host.replace(/:\d+$|$/, ':8001') + '/$/ws/' + pageId() :
-      window.location.host + '/$/ws/' + pageId());
+      window.location.host + '/$/ws/' + pageId()) +
+    (encryptionKey() != null ? '/encrypted' : peerSync() ? '/peer' : '') +
+    '?user=' + encodeURIComponent(userId()) +
+    '&protocol=' + PROTOCOL_VERSION +
+    userProfileParams() +
+    (shareToken() != null ? '&share=' + shareToken() : '') +
+    (resume ? '&resume=' + resume.version + ':' + resume.chunk : '') +
+    (clientId ? '&client=' + encodeURIComponent(clientId) : '');
 }
 
 export function graphqlUrl(): string {
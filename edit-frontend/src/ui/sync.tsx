@@ -6,10 +6,34 @@ import * as route from './route';
 import * as app from './app';
 import {EditorFrame} from './app';
 import * as commands from '../editor/commands';
-import {ServerImpl, ControllerImpl } from '../editor/network';
-import {WasmClient, WasmError, getForwardWasmTaskCallback, setForwardWasmTaskCallback} from '../editor/wasm';
+import {ServerImpl, ControllerImpl, SyncResume} from '../editor/network';
+import {WasmClient, WorkerClient, WasmError, getForwardWasmTaskCallback, setForwardWasmTaskCallback} from '../editor/wasm';
 import DEBUG from '../debug';
 
+// What to do after the sync server closes the connection, by close code.
+// Keep these in step with CloseReason in edit-common/src/protocol/close.rs.
+// Other codes, as when the connection drops, mean reconnecting.
+type Recovery = 'reconnect' | 'reconnect-later' | 'reauthenticate' | 'stop';
+const CLOSE_RECOVERY: {[code: number]: Recovery} = {
+  4000: 'reconnect', // Reloaded
+  4001: 'reconnect', // Unloaded
+  4002: 'stop', // ProtocolError
+  4003: 'stop', // UnsupportedVersion
+  4004: 'reauthenticate', // Unauthorized
+  4005: 'stop', // Banned
+  4006: 'stop', // Kicked
+  4007: 'reconnect-later', // RateLimited
+  4008: 'reconnect-later', // ShuttingDown
+  4009: 'reconnect-later', // Unavailable
+};
+
+// How long to wait before checking on the server again, after it asked us
+// to reconnect later.
+const RECONNECT_LATER_DELAY = 15000;
+
+// How long to wait before reconnecting after the connection dropped.
+const RECONNECT_DELAY = 3000;
+
 class DeferredSocket {
   socket: WebSocket;
 
@@ -23,9 +47,7 @@ class DeferredSocket {
     this.closeQueue = [];
 
     let self = this;
-    this.socket = new WebSocket(
-      route.syncUrl()
-    );
+    this.socket = new WebSocket(url);
     this.socket.onopen = function () {
       DEBUG.measureTime('websocket-defer-open');
       self.openQueue.push(arguments);
@@ -63,7 +85,7 @@ let syncSocket = new DeferredSocket(
 );
 
 export class AppServer implements ServerImpl {
-  client: WasmClient | null;
+  client: WasmClient | WorkerClient | null;
   
   onClose: () => void;
 
@@ -76,6 +98,13 @@ export class AppServer implements ServerImpl {
 
   private editorFrame: EditorFrame | null;
 
+  // Where to continue an unfinished initial sync from, if the connection
+  // drops before it's done.
+  private resume: SyncResume | null = null;
+
+  // Our client id, once sync assigned one, to reconnect as.
+  private clientId: string | null = null;
+
   constructor() {
     this.deferSync = new Promise((resolve, reject: any) => {
       this.deferSyncResolve = resolve;
@@ -88,6 +117,14 @@ export class AppServer implements ServerImpl {
     });
   }
 
+  resumeFrom(resume: SyncResume | null) {
+    this.resume = resume;
+  }
+
+  rejoinAs(clientId: string) {
+    this.clientId = clientId;
+  }
+
   connect(onError: (message: React.ReactNode) => void): Promise<void> {
     let server = this;
 
@@ -95,7 +132,7 @@ export class AppServer implements ServerImpl {
     .then(() => {
       DEBUG.measureTime('connect-server');
 
-      syncSocket.handle({
+      let handlers = {
         onopen: (event: any) => {
           console.debug('server socket opened.');
           DEBUG.measureTime('connect-ready');
@@ -103,18 +140,17 @@ export class AppServer implements ServerImpl {
         onmessage: (event: any) => {
           // console.log('Got message from sync:', event.data);
           try {
-            if (getForwardWasmTaskCallback() != null) {
+            // A worker client reports its own errors.
+            if (server.client instanceof WorkerClient || getForwardWasmTaskCallback() != null) {
               if (server.client != null) {
-                server.client.clientBindings.command(JSON.stringify({
-                  ClientCommand: JSON.parse(event.data),
-                }));
+                server.client.sendServerCommand(event.data);
               }
             }
           } catch (e) {
             // Kill the current process, we triggered an exception.
             setForwardWasmTaskCallback(null);
             if (server.client != null) {
-              server.client.Module.wasm_close();
+              server.client.close();
             }
             // syncSocket.close();
 
@@ -130,25 +166,70 @@ export class AppServer implements ServerImpl {
             throw new WasmError(e, `Error during sync command: ${e.message}`);
           }
         },
-        onclose: () => {
-          if (server.editorFrame) { 
-            onError(
-              <div>The editor has disconnected from the server. We're sorry. You can <a href="?">refresh your browser</a>, or we'll refresh once the server is reachable.</div>
-            );
+        onclose: (event: CloseEvent) => {
+          let recovery = CLOSE_RECOVERY[event.code] || 'reconnect';
+
+          // A connection which dropped during the initial sync reconnects
+          // right away, asking for the chunks we don't have yet.
+          if (recovery == 'reconnect' && server.resume !== null) {
+            console.info('Resuming initial sync from', server.resume);
+            syncSocket = new DeferredSocket(route.syncUrl(server.resume));
+            syncSocket.handle(handlers);
+            server.deferSync = Promise.resolve(syncSocket.socket);
+            return;
           }
 
-          setTimeout(() => {
-            setInterval(() => {
-              app.graphqlPage('home').then(() => {
-                // Can access server, continue
-                window.location.reload();
-              });
-            }, 2000);
-          }, 3000);
+          // Otherwise a client that was initialized reconnects as itself,
+          // keeping its edits. Ones sync didn't acknowledge are sent again,
+          // and sync applies each at most once.
+          if (recovery == 'reconnect' && server.clientId !== null) {
+            console.info('Reconnecting as', server.clientId);
+            if (server.client != null) {
+              server.client.sendCommand(commands.SyncConnection(false));
+            }
+            setTimeout(() => {
+              syncSocket = new DeferredSocket(route.syncUrl(null, server.clientId));
+              syncSocket.handle(handlers);
+              server.deferSync = Promise.resolve(syncSocket.socket);
+            }, RECONNECT_DELAY);
+            return;
+          }
+
+          if (server.editorFrame) {
+            if (recovery == 'stop') {
+              onError(
+                <div>{event.reason || 'The server closed the connection.'}</div>
+              );
+            } else if (recovery == 'reauthenticate') {
+              onError(
+                <div>{event.reason} Ask for a new share link to keep editing.</div>
+              );
+            } else if (recovery == 'reconnect-later') {
+              onError(
+                <div>{event.reason} We'll refresh once the server is ready.</div>
+              );
+            } else {
+              onError(
+                <div>The editor has disconnected from the server. We're sorry. You can <a href="?">refresh your browser</a>, or we'll refresh once the server is reachable.</div>
+              );
+            }
+          }
+
+          if (recovery == 'reconnect' || recovery == 'reconnect-later') {
+            setTimeout(() => {
+              setInterval(() => {
+                app.graphqlPage('home').then(() => {
+                  // Can access server, continue
+                  window.location.reload();
+                });
+              }, 2000);
+            }, recovery == 'reconnect-later' ? RECONNECT_LATER_DELAY : 3000);
+          }
 
           server.onClose();
         },
-      });
+      };
+      syncSocket.handle(handlers);
 
       if (this.deferSyncResolve !== null) {
         this.deferSyncResolve(syncSocket.socket);
@@ -0,0 +1 @@
+DROP TABLE page_records
\ No newline at end of file
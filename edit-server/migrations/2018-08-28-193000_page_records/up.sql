@@ -0,0 +1,6 @@
+CREATE TABLE page_records (
+  rowid INTEGER NOT NULL PRIMARY KEY,
+  page_id VARCHAR NOT NULL,
+  created BIGINT NOT NULL,
+  body TEXT NOT NULL
+)
\ No newline at end of file
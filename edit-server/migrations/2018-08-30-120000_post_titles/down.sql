@@ -0,0 +1,7 @@
+CREATE TABLE posts_without_titles (
+  id VARCHAR NOT NULL PRIMARY KEY,
+  body TEXT NOT NULL
+);
+INSERT INTO posts_without_titles (id, body) SELECT id, body FROM posts;
+DROP TABLE posts;
+ALTER TABLE posts_without_titles RENAME TO posts
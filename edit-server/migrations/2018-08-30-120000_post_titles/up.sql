@@ -0,0 +1 @@
+ALTER TABLE posts ADD COLUMN title TEXT NOT NULL DEFAULT ''
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE saved_carets
\ No newline at end of file
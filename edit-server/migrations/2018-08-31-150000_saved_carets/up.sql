@@ -0,0 +1,6 @@
+CREATE TABLE saved_carets (
+  page_id VARCHAR NOT NULL,
+  user_id VARCHAR NOT NULL,
+  body TEXT NOT NULL,
+  PRIMARY KEY (page_id, user_id)
+)
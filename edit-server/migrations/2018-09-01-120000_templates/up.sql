@@ -0,0 +1,3 @@
+CREATE TABLE templates (
+  page_id VARCHAR NOT NULL PRIMARY KEY
+)
@@ -0,0 +1,5 @@
+CREATE TABLE forks (
+  page_id VARCHAR NOT NULL PRIMARY KEY,
+  source_id VARCHAR NOT NULL,
+  ancestor TEXT NOT NULL
+)
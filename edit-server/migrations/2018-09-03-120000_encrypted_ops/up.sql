@@ -0,0 +1,7 @@
+CREATE TABLE encrypted_ops (
+  page_id VARCHAR NOT NULL,
+  version INTEGER NOT NULL,
+  client_id VARCHAR NOT NULL,
+  body TEXT NOT NULL,
+  PRIMARY KEY (page_id, version)
+)
@@ -0,0 +1,6 @@
+CREATE TABLE share_tokens (
+  token VARCHAR NOT NULL PRIMARY KEY,
+  page_id VARCHAR NOT NULL,
+  permission VARCHAR NOT NULL,
+  expires BIGINT
+)
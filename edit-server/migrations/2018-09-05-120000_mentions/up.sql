@@ -0,0 +1,9 @@
+CREATE TABLE mentions (
+  rowid INTEGER NOT NULL PRIMARY KEY,
+  user_id VARCHAR NOT NULL,
+  page_id VARCHAR NOT NULL,
+  anchor VARCHAR,
+  author VARCHAR NOT NULL,
+  created BIGINT NOT NULL,
+  seen BOOLEAN NOT NULL DEFAULT 0
+)
@@ -0,0 +1,15 @@
+CREATE TABLE comment_threads (
+  id VARCHAR NOT NULL PRIMARY KEY,
+  page_id VARCHAR NOT NULL,
+  anchor VARCHAR NOT NULL,
+  resolved BOOLEAN NOT NULL DEFAULT 0,
+  created BIGINT NOT NULL
+);
+
+CREATE TABLE comments (
+  rowid INTEGER NOT NULL PRIMARY KEY,
+  thread_id VARCHAR NOT NULL,
+  author VARCHAR NOT NULL,
+  body VARCHAR NOT NULL,
+  created BIGINT NOT NULL
+)
@@ -0,0 +1,7 @@
+CREATE TABLE peer_ops (
+  page_id VARCHAR NOT NULL,
+  seq INTEGER NOT NULL,
+  client_id VARCHAR NOT NULL,
+  body TEXT NOT NULL,
+  PRIMARY KEY (page_id, seq)
+)
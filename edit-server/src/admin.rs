@@ -0,0 +1,412 @@
+//! Admin API for inspecting and managing the pages the sync server holds in
+//! memory. Requests must carry the token from EDIT_ADMIN_TOKEN.
+
+use crate::{
+    db::{
+        get_single_page,
+        DbPool,
+    },
+    export::export_zip,
+    import::import_zip,
+    retention::*,
+    share::*,
+    sync::{
+        ClientNotify,
+        ClientUpdate,
+    },
+};
+
+use extern::{
+    crossbeam_channel::Sender as CCSender,
+    edit_common::commands::SharePermission,
+    rouille::{
+        self,
+        Request,
+        Response,
+    },
+    std::{
+        collections::{
+            HashMap,
+            VecDeque,
+        },
+        env,
+        io::Read,
+        sync::{
+            Arc,
+            Mutex,
+        },
+        time::{
+            Duration,
+            Instant,
+        },
+    },
+};
+
+// Errors older than this aren't reported as recent.
+const RECENT_ERROR_WINDOW: u64 = 10 * 60;
+
+// Longest ban, ten years, so the time it ends at can't overflow.
+const MAX_BAN_SECS: u64 = 10 * 365 * 24 * 60 * 60;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ClientStatus {
+    pub id: String,
+    pub user_id: Option<String>,
+    // Display name of the user.
+    pub name: Option<String>,
+    pub remote_addr: Option<String>,
+    // Session cookie and share token the client connected with, which the
+    // server handed out, unlike the user id. Bans are keyed on them.
+    #[serde(skip)]
+    pub session: Option<String>,
+    #[serde(skip)]
+    pub share_token: Option<String>,
+}
+
+/// Live state of a page's sync thread, as reported to the admin API.
+pub struct PageStatus {
+    pub version: usize,
+    pub clients: Vec<ClientStatus>,
+    // Number of sockets watching the page's events.
+    pub watchers: usize,
+    // Time of each refused operation within the error window.
+    pub errors: VecDeque<Instant>,
+    // The page's notification queue.
+    pub queue: CCSender<ClientUpdate>,
+    // Estimated bytes held by the page's sync thread.
+    pub memory: usize,
+    // When the page last handled a notification.
+    pub last_active: Instant,
+}
+
+impl PageStatus {
+    pub fn new(queue: CCSender<ClientUpdate>) -> PageStatus {
+        PageStatus {
+            version: 0,
+            clients: vec![],
+            watchers: 0,
+            errors: VecDeque::new(),
+            queue,
+            memory: 0,
+            last_active: Instant::now(),
+        }
+    }
+
+    pub fn record_error(&mut self) {
+        self.errors.push_back(Instant::now());
+        self.prune_errors();
+    }
+
+    fn prune_errors(&mut self) {
+        let window = Duration::from_secs(RECENT_ERROR_WINDOW);
+        while self
+            .errors
+            .front()
+            .map(|time| time.elapsed() > window)
+            .unwrap_or(false)
+        {
+            self.errors.pop_front();
+        }
+    }
+}
+
+/// Status of every page held in memory, keyed by page ID.
+pub type PageRegistry = Arc<Mutex<HashMap<String, PageStatus>>>;
+
+pub fn page_registry_create() -> PageRegistry {
+    Arc::new(Mutex::new(HashMap::new()))
+}
+
+/// Sessions, share tokens, and remote addresses that may not connect, and
+/// until when.
+pub type BanList = Arc<Mutex<HashMap<String, Instant>>>;
+
+pub fn ban_list_create() -> BanList {
+    Arc::new(Mutex::new(HashMap::new()))
+}
+
+/// Whether any of the given session, share token, or address is banned.
+pub fn is_banned(bans: &BanList, keys: &[Option<&str>]) -> bool {
+    let mut bans = bans.lock().unwrap();
+    let now = Instant::now();
+    bans.retain(|_, until| *until > now);
+    keys.iter()
+        .filter_map(|key| *key)
+        .any(|key| bans.contains_key(key))
+}
+
+fn find_client(registry: &PageRegistry, page_id: &str, client_id: &str) -> Option<ClientStatus> {
+    registry
+        .lock()
+        .unwrap()
+        .get(page_id)?
+        .clients
+        .iter()
+        .find(|client| client.id == client_id)
+        .cloned()
+}
+
+#[derive(Serialize)]
+struct PageReport {
+    id: String,
+    version: usize,
+    clients: Vec<ClientStatus>,
+    queue_depth: usize,
+    recent_errors: usize,
+    memory: usize,
+    idle_secs: u64,
+}
+
+const NO_RETENTION_POLICY: &str =
+    "No retention policy: set EDIT_OPLOG_RETENTION_DAYS or EDIT_OPLOG_RETENTION_REVISIONS, or pass ?days= or ?revisions=";
+
+/// The retention policy, with what the request overrides.
+fn request_retention_policy(request: &Request) -> Option<RetentionPolicy> {
+    let days = request.get_param("days").and_then(|x| x.parse().ok());
+    let revisions = request.get_param("revisions").and_then(|x| x.parse().ok());
+    if days.is_none() && revisions.is_none() {
+        return RetentionPolicy::from_env();
+    }
+    Some(RetentionPolicy {
+        max_age_days: days,
+        min_revisions: revisions,
+    })
+}
+
+// Compare without stopping at the first difference, so how long it takes
+// doesn't tell how much of a guess was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn authorized(request: &Request) -> bool {
+    let token = match env::var("EDIT_ADMIN_TOKEN") {
+        Ok(ref token) if !token.is_empty() => token.clone(),
+        // The admin API is disabled without a token.
+        _ => return false,
+    };
+    request
+        .header("Authorization")
+        .map(|value| constant_time_eq(value.as_bytes(), format!("Bearer {}", token).as_bytes()))
+        .unwrap_or(false)
+}
+
+fn report_pages(registry: &PageRegistry) -> Vec<PageReport> {
+    let mut registry = registry.lock().unwrap();
+    let mut pages = registry
+        .iter_mut()
+        .map(|(id, status)| {
+            status.prune_errors();
+            PageReport {
+                id: id.clone(),
+                version: status.version,
+                clients: status.clients.clone(),
+                queue_depth: status.queue.len(),
+                recent_errors: status.errors.len(),
+                memory: status.memory,
+                idle_secs: status.last_active.elapsed().as_secs(),
+            }
+        })
+        .collect::<Vec<_>>();
+    pages.sort_by(|a, b| a.id.cmp(&b.id));
+    pages
+}
+
+/// Handles requests to /admin/. Returns None for other URLs.
+pub fn admin_request(
+    request: &Request,
+    registry: &PageRegistry,
+    bans: &BanList,
+    tx_master: &CCSender<ClientNotify>,
+    db_pool: &DbPool,
+) -> Option<Response> {
+    if !request.url().starts_with("/admin/") {
+        return None;
+    }
+
+    if !authorized(request) {
+        return Some(Response::text("Unauthorized").with_status_code(401));
+    }
+
+    Some(router!(request,
+        (GET) (/admin/pages) => {
+            Response::json(&json!({
+                "pages": report_pages(registry),
+            }))
+        },
+
+        // Download pages as a zip of markdown files: every page, or those
+        // listed in ?ids=a,b,c.
+        (GET) (/admin/export.zip) => {
+            let ids = request.get_param("ids").map(|ids| {
+                ids.split(',')
+                    .filter(|id| !id.is_empty())
+                    .map(|id| id.to_string())
+                    .collect::<Vec<_>>()
+            });
+            let conn = db_pool.get().unwrap();
+            match export_zip(&conn, ids.as_ref().map(|ids| ids.as_slice())) {
+                Ok(data) => Response::from_data("application/zip", data)
+                    .with_unique_header("Content-Disposition", r#"attachment; filename="export.zip""#),
+                Err(err) => Response::text(err.to_string()).with_status_code(500),
+            }
+        },
+
+        // Create pages from a zip of markdown files in the request body.
+        // With ?overwrite=1, pages with the same ids are replaced.
+        (POST) (/admin/import) => {
+            let overwrite = request.get_param("overwrite").map(|x| x == "1").unwrap_or(false);
+            let mut data = vec![];
+            if let Some(mut body) = request.data() {
+                if body.read_to_end(&mut data).is_err() {
+                    return Some(Response::text("Failed to read body").with_status_code(400));
+                }
+            }
+
+            let conn = db_pool.get().unwrap();
+            match import_zip(&conn, &data, overwrite) {
+                Ok(imported) => {
+                    // Pages already loaded restart with their new contents.
+                    for (_, id) in &imported {
+                        if let Some(doc) = get_single_page(&conn, id) {
+                            if registry.lock().unwrap().contains_key(id) {
+                                let _ = tx_master.send(ClientNotify(id.clone(), ClientUpdate::Overwrite {
+                                    doc,
+                                }));
+                            }
+                        }
+                    }
+                    Response::json(&json!({
+                        "pages": imported
+                            .into_iter()
+                            .map(|(path, id)| json!({ "path": path, "id": id }))
+                            .collect::<Vec<_>>(),
+                    }))
+                }
+                Err(err) => Response::text(err.to_string()).with_status_code(400),
+            }
+        },
+
+        (POST) (/admin/pages/{id: String}/save) => {
+            let _ = tx_master.send(ClientNotify(id, ClientUpdate::Save));
+            Response::json(&json!({ "ok": true }))
+        },
+
+        (POST) (/admin/pages/{id: String}/evict) => {
+            let _ = tx_master.send(ClientNotify(id, ClientUpdate::Evict));
+            Response::json(&json!({ "ok": true }))
+        },
+
+        // Compact recordings to the retention policy now: a page's, or
+        // every page's. ?days= and ?revisions= override the policy.
+        (POST) (/admin/pages/{id: String}/gc) => {
+            let policy = match request_retention_policy(request) {
+                Some(policy) => policy,
+                None => return Some(Response::text(NO_RETENTION_POLICY).with_status_code(400)),
+            };
+            let conn = db_pool.get().unwrap();
+            match compact_page_records(&conn, &id, &policy) {
+                Ok(removed) => Response::json(&json!({ "removed": removed })),
+                Err(err) => Response::text(err.to_string()).with_status_code(500),
+            }
+        },
+
+        (POST) (/admin/gc) => {
+            let policy = match request_retention_policy(request) {
+                Some(policy) => policy,
+                None => return Some(Response::text(NO_RETENTION_POLICY).with_status_code(400)),
+            };
+            let conn = db_pool.get().unwrap();
+            match compact_all_page_records(&conn, &policy) {
+                Ok(removed) => Response::json(&json!({ "removed": removed })),
+                Err(err) => Response::text(err.to_string()).with_status_code(500),
+            }
+        },
+
+        // Disconnect a client with an explanatory reason. With ?ban_secs=N,
+        // its session, share link, and address can't reconnect for N seconds,
+        // up to ten years.
+        (POST) (/admin/pages/{id: String}/clients/{client_id: String}/disconnect) => {
+            let client = match find_client(registry, &id, &client_id) {
+                Some(client) => client,
+                None => return Some(Response::empty_404()),
+            };
+
+            let reason = request
+                .get_param("reason")
+                .unwrap_or_else(|| "Disconnected by an administrator.".to_string());
+
+            let ban_secs = request.get_param("ban_secs").and_then(|x| x.parse::<u64>().ok());
+            if ban_secs.map(|x| x > MAX_BAN_SECS).unwrap_or(false) {
+                return Some(
+                    Response::text(format!("ban_secs can be at most {}.", MAX_BAN_SECS))
+                        .with_status_code(400),
+                );
+            }
+            if let Some(ban_secs) = ban_secs {
+                let until = Instant::now() + Duration::from_secs(ban_secs);
+                let mut bans = bans.lock().unwrap();
+                let keys = client
+                    .session
+                    .iter()
+                    .chain(client.share_token.iter())
+                    .chain(client.remote_addr.iter());
+                for key in keys {
+                    bans.insert(key.clone(), until);
+                }
+            }
+
+            let _ = tx_master.send(ClientNotify(id, ClientUpdate::Kick {
+                client_id,
+                reason,
+            }));
+            Response::json(&json!({ "ok": true }))
+        },
+
+        (GET) (/admin/pages/{id: String}/shares) => {
+            let conn = db_pool.get().unwrap();
+            match page_share_links(&conn, &id) {
+                Ok(links) => Response::json(&json!({ "shares": links })),
+                Err(err) => Response::text(err.to_string()).with_status_code(500),
+            }
+        },
+
+        // Create a share link with ?permission=view|comment|edit, expiring
+        // after ?ttl_secs=N if given.
+        (POST) (/admin/pages/{id: String}/shares) => {
+            let permission = request
+                .get_param("permission")
+                .and_then(|x| SharePermission::from_str(&x));
+            let permission = match permission {
+                Some(permission) => permission,
+                None => return Some(Response::text("Expected ?permission=view|comment|edit").with_status_code(400)),
+            };
+            let ttl_secs = request.get_param("ttl_secs").and_then(|x| x.parse::<u64>().ok());
+
+            let conn = db_pool.get().unwrap();
+            match create_share_link(&conn, &id, permission, ttl_secs) {
+                Ok(link) => Response::json(&link),
+                Err(err) => Response::text(err.to_string()).with_status_code(500),
+            }
+        },
+
+        // Revoke a share link, disconnecting clients that connected with it.
+        (DELETE) (/admin/shares/{token: String}) => {
+            let conn = db_pool.get().unwrap();
+            match revoke_share_link(&conn, &token) {
+                Ok(Some(page_id)) => {
+                    if registry.lock().unwrap().contains_key(&page_id) {
+                        let _ = tx_master.send(ClientNotify(page_id, ClientUpdate::RevokeShare {
+                            token,
+                        }));
+                    }
+                    Response::json(&json!({ "ok": true }))
+                }
+                Ok(None) => Response::empty_404(),
+                Err(err) => Response::text(err.to_string()).with_status_code(500),
+            }
+        },
+
+        _ => rouille::Response::empty_404()
+    ))
+}
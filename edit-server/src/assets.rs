@@ -0,0 +1,91 @@
+//! Storage for files uploaded to documents, like pasted images. Assets are
+//! named by the hash of their contents, so uploading the same file twice
+//! stores it once.
+
+use failure::Error;
+use md5;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Largest upload we accept, in bytes.
+pub const MAX_ASSET_SIZE: usize = 10 * 1024 * 1024;
+
+/// Types of assets served to be shown inline. These can't carry scripts, so
+/// unlike SVG or HTML they're safe to show from our origin.
+pub const RASTER_IMAGE_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "image/bmp",
+    "image/x-icon",
+];
+
+/// A place to keep uploaded files. Names returned by `put` are safe to use
+/// in URLs.
+pub trait AssetStore: Send + Sync {
+    /// Store `data`, returning the name to retrieve it by.
+    fn put(&self, data: &[u8], extension: &str) -> Result<String, Error>;
+
+    fn get(&self, name: &str) -> Option<Vec<u8>>;
+}
+
+/// Name for an asset with the given contents and extension.
+pub fn asset_name(data: &[u8], extension: &str) -> String {
+    let extension = extension
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase();
+    if extension.is_empty() {
+        format!("{:x}", md5::compute(data))
+    } else {
+        format!("{:x}.{}", md5::compute(data), extension)
+    }
+}
+
+// Names we hand out are hex digits and an optional extension, which keeps
+// requests for them inside the store.
+fn valid_asset_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '.')
+        && !name.starts_with('.')
+}
+
+/// Keeps assets as files in a directory.
+pub struct FsAssetStore(pub PathBuf);
+
+impl AssetStore for FsAssetStore {
+    fn put(&self, data: &[u8], extension: &str) -> Result<String, Error> {
+        if data.len() > MAX_ASSET_SIZE {
+            bail!("asset is larger than {} bytes", MAX_ASSET_SIZE);
+        }
+        let name = asset_name(data, extension);
+        fs::create_dir_all(&self.0)?;
+        let path = self.0.join(&name);
+        if !path.exists() {
+            fs::write(&path, data)?;
+        }
+        Ok(name)
+    }
+
+    fn get(&self, name: &str) -> Option<Vec<u8>> {
+        if !valid_asset_name(name) {
+            return None;
+        }
+        fs::read(self.0.join(name)).ok()
+    }
+}
+
+/// The store configured by EDIT_ASSET_DIR, or an "assets" directory beside
+/// the database.
+pub fn default_asset_store() -> Box<AssetStore> {
+    let dir = env::var("EDIT_ASSET_DIR").unwrap_or_else(|_| "../assets".to_string());
+    Box::new(FsAssetStore(PathBuf::from(dir)))
+}
+
+/// URL the HTTP server serves an asset at.
+pub fn asset_url(name: &str) -> String {
+    format!("/$/assets/{}", name)
+}
@@ -33,21 +33,33 @@ extern crate serde_json;
 
 use edit_common::{
     doc_as_html,
+    identity::SESSION_COOKIE,
     markdown::{
         doc_to_markdown,
         markdown_to_doc,
     },
+    pagination::PageLayout,
 };
 use extern::edit_server::{
+    assets::*,
+    db::db_pool_create,
     graphql::client::*,
+    pdf::doc_to_pdf,
+    share::client_permission,
     sync::*,
 };
 use handlebars::Handlebars;
 use include_dir_macro::include_dir;
-use mime_guess::guess_mime_type;
+use mime_guess::{
+    get_mime_extensions_str,
+    guess_mime_type,
+};
 use oatie::doc::*;
 use oatie::validate::validate_doc;
-use rand::thread_rng;
+use rand::{
+    thread_rng,
+    Rng,
+};
 use rouille::Response;
 use std::{
     env,
@@ -181,6 +193,8 @@ fn run_http_server(port: u16, client_proxy: bool) {
     println!("Listening on http://0.0.0.0:{}/", port);
 
     let reg = Handlebars::new();
+    let asset_store = default_asset_store();
+    let db_pool = db_pool_create();
 
     #[allow(unused)]
     #[allow(unreachable_code)]
@@ -195,6 +209,8 @@ fn run_http_server(port: u16, client_proxy: bool) {
                 &format!("CONFIG = {}", serde_json::to_string(&json!({
                     "configured": true,
                     "wasm": !client_proxy,
+                    // Run the wasm client in a Web Worker.
+                    "worker": env::var("EDIT_WASM_WORKER").map(|x| x == "1").unwrap_or(false),
                     "title": &edit_title,
                 })).unwrap()),
             );
@@ -254,6 +270,32 @@ fn run_http_server(port: u16, client_proxy: bool) {
 
                 return Response::redirect_302(format!("/{}", id));
             },
+            // Create a randomly-named page from a template, /$/new?template={id}.
+            // It's a POST, so links and prefetching can't create pages.
+            (POST) ["/$/new"] => {
+                let template = match request.get_param("template") {
+                    Some(template) => template,
+                    None => return Response::text("No template given.").with_status_code(400),
+                };
+                let id = random_id();
+                return match create_page_from_template_graphql(&id, &template) {
+                    Ok(_) => Response::redirect_303(format!("/{}", id)),
+                    Err(err) => Response::text(err.to_string()).with_status_code(404),
+                };
+            },
+
+            // Search the pages this browser may open, /$/search?q={query},
+            // with the share link it has in &share= if any.
+            (GET) ["/$/search"] => {
+                let query = request.get_param("q").unwrap_or_default();
+                let share_token = request.get_param("share");
+                let share_token = share_token.as_ref().map(|x| x.as_str());
+                return match search_graphql(&query, share_token, request.header("Cookie")) {
+                    Ok(results) => Response::json(&results),
+                    Err(err) => Response::text(err.to_string()).with_status_code(500),
+                };
+            },
+
             (GET) ["/index.html"] => {
                 return Response::redirect_302("/");
             },
@@ -326,6 +368,94 @@ fn run_http_server(port: u16, client_proxy: bool) {
             //     return Response::redirect_302("/$/list");
             // },
 
+            // Upload an image, or any file given its name with ?filename=,
+            // responding with the URL it's served at. Uploads are for the
+            // page in ?page=, by a browser that loaded it and may edit it,
+            // with the share link it was opened with in ?share= if any.
+            (POST) ["/$/upload"] => {
+                let page_id = request.get_param("page").unwrap_or_default();
+                let share_token = request.get_param("share");
+                let has_session = rouille::input::cookies(request)
+                    .any(|(name, _)| name == SESSION_COOKIE);
+                let permission = db_pool
+                    .get()
+                    .ok()
+                    .and_then(|conn| {
+                        let token = share_token.as_ref().map(|x| x.as_str());
+                        client_permission(&conn, token, &page_id).ok()
+                    })
+                    .and_then(|permission| permission);
+                let can_edit = has_session
+                    && valid_page_id(&page_id)
+                    && permission.map(|x| x.can_edit()).unwrap_or(false);
+                if !can_edit {
+                    return Response::text("Only editors of a page may upload to it.")
+                        .with_status_code(403);
+                }
+
+                let content_type = request.header("Content-Type").unwrap_or("").to_string();
+                let filename = request.get_param("filename");
+                let extension = if let Some(ref filename) = filename {
+                    Path::new(filename)
+                        .extension()
+                        .and_then(|ext| ext.to_str())
+                        .unwrap_or("")
+                        .to_string()
+                } else if content_type.starts_with("image/") {
+                    get_mime_extensions_str(&content_type)
+                        .and_then(|exts| exts.first())
+                        .cloned()
+                        .unwrap_or("")
+                        .to_string()
+                } else {
+                    return Response::text("Files other than images need a filename.")
+                        .with_status_code(415);
+                };
+                // SVG images can carry scripts.
+                if extension.eq_ignore_ascii_case("svg") || content_type.starts_with("image/svg") {
+                    return Response::text("SVG images can't be uploaded.").with_status_code(415);
+                }
+
+                let mut data = vec![];
+                if let Some(body) = request.data() {
+                    if body.take(MAX_ASSET_SIZE as u64 + 1).read_to_end(&mut data).is_err() {
+                        return Response::text("Could not read upload.").with_status_code(400);
+                    }
+                }
+                return match asset_store.put(&data, &extension) {
+                    Ok(name) => Response::json(&json!({
+                        "url": asset_url(&name),
+                        "filename": filename,
+                        "size": data.len(),
+                    })),
+                    Err(err) => Response::text(err.to_string()).with_status_code(413),
+                };
+            },
+
+            (GET) ["/$/assets/{name}", name: String] => {
+                if let Some(data) = asset_store.get(&name) {
+                    // Assets never change, since they're named by content.
+                    let mime = guess_mime_type(&name).to_string();
+                    let inline = RASTER_IMAGE_TYPES.contains(&mime.as_str());
+                    // Uploads are whatever users sent us, so browsers are told
+                    // not to guess at their type, and anything that's opened
+                    // runs sandboxed in a unique origin rather than ours.
+                    let res = Response::from_data(mime, data)
+                        .with_public_cache(365 * 24 * 60 * 60)
+                        .with_unique_header("X-Content-Type-Options", "nosniff")
+                        .with_unique_header("Content-Security-Policy", "sandbox");
+                    // Only raster images are shown inline. Everything else,
+                    // SVG included, is offered for download.
+                    return if inline {
+                        res
+                    } else {
+                        res.with_unique_header("Content-Disposition", "attachment")
+                    };
+                } else {
+                    return Response::empty_404();
+                }
+            },
+
             // static_dir
             (GET) ["/$/static/{target}", target: String] => {
                 if let Some(data) = static_dir.get(Path::new(&target)) {
@@ -386,6 +516,41 @@ fn run_http_server(port: u16, client_proxy: bool) {
                 return Response::redirect_302(format!("/{}/presentation", id));
             },
 
+            (GET) ["/{id}/pdf", id: String] => {
+                // Break pages like the editor does, so the PDF has the pages
+                // it showed. The layout can be given as ?lines=46&chars=80.
+                let default_layout = PageLayout::default();
+                let layout = PageLayout {
+                    lines_per_page: request.get_param("lines")
+                        .and_then(|x| x.parse::<usize>().ok())
+                        .filter(|x| *x > 0)
+                        .unwrap_or(default_layout.lines_per_page),
+                    chars_per_line: request.get_param("chars")
+                        .and_then(|x| x.parse::<usize>().ok())
+                        .filter(|x| *x > 0)
+                        .unwrap_or(default_layout.chars_per_line),
+                };
+
+                // Only browsers that may open the page get it, with the
+                // share link they have in &share= if any.
+                let share_token = request.get_param("share");
+                let share_token = share_token.as_ref().map(|x| x.as_str());
+                let cookie = request.header("Cookie");
+                let doc = match get_shared_page_graphql(&id, share_token, cookie) {
+                    Ok(Some(doc)) => doc,
+                    Ok(None) => return Response::empty_404(),
+                    Err(err) => return Response::text(err.to_string()).with_status_code(403),
+                };
+                let filename = id
+                    .chars()
+                    .filter(|c| c.is_alphanumeric() || *c == '-' || *c == '_')
+                    .collect::<String>();
+                return Response::from_data("application/pdf", doc_to_pdf(&doc, &layout))
+                    .with_unique_header(
+                        "Content-Disposition",
+                        format!("inline; filename=\"{}.pdf\"", filename),
+                    );
+            },
             (GET) ["/{id}", id: String] => {
                 // Inline the stylesheet.
                 let stylesheet = dist_dir.get(Path::new("edit.css")).unwrap();
@@ -412,10 +577,22 @@ fn run_http_server(port: u16, client_proxy: bool) {
                     "title": &edit_title,
                 })).unwrap();
 
-                return Response::from_data(
+                let res = Response::from_data(
                     "text/html",
                     payload.into_bytes(),
                 );
+
+                // Guests are named after their session, so start one.
+                let has_session = rouille::input::cookies(request).any(|(name, _)| name == SESSION_COOKIE);
+                return if has_session {
+                    res
+                } else {
+                    let session = thread_rng().gen_ascii_chars().take(24).collect::<String>();
+                    res.with_additional_header(
+                        "Set-Cookie",
+                        format!("{}={}; Path=/; HttpOnly", SESSION_COOKIE, session),
+                    )
+                };
             },
             (GET) ["/{id}/", id: String] => {
                 return Response::redirect_302(format!("/{}", id));
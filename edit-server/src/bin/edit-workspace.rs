@@ -0,0 +1,83 @@
+#![feature(extern_in_paths)]
+
+#[macro_use]
+extern crate quicli;
+extern crate edit_server;
+
+use extern::{
+    edit_server::{
+        db::*,
+        export::*,
+        import::*,
+    },
+    quicli::prelude::*,
+    std::fs,
+    std::path::Path,
+};
+
+#[derive(Debug, StructOpt)]
+enum Cli {
+    /// Export pages as a zip of markdown files.
+    #[structopt(name = "export")]
+    Export {
+        #[structopt(long = "output", short = "o", default_value = "export.zip")]
+        output: String,
+
+        /// Pages to export. Exports every page if none are given.
+        ids: Vec<String>,
+    },
+
+    /// Create a page from each markdown file in a directory.
+    #[structopt(name = "import")]
+    Import {
+        /// Replace existing pages with the same ids.
+        #[structopt(long = "overwrite")]
+        overwrite: bool,
+
+        dir: String,
+    },
+}
+
+// Paths of the markdown files under `dir`, relative to `root` and joined
+// with slashes, and their contents.
+fn read_markdown_files(
+    root: &Path,
+    dir: &Path,
+    out: &mut Vec<(String, String)>,
+) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            read_markdown_files(root, &path, out)?;
+        } else if path.extension().map(|ext| ext == "md").unwrap_or(false) {
+            let relative = path
+                .strip_prefix(root)?
+                .components()
+                .map(|part| part.as_os_str().to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+                .join("/");
+            out.push((relative, fs::read_to_string(&path)?));
+        }
+    }
+    Ok(())
+}
+
+main!(|args: Cli| {
+    let db = db_connection();
+
+    match args {
+        Cli::Export { output, ids } => {
+            let ids = if ids.is_empty() { None } else { Some(ids.as_slice()) };
+            let data = export_zip(&db, ids)?;
+            fs::write(&output, data)?;
+            eprintln!("exported to {}.", output);
+        }
+        Cli::Import { overwrite, dir } => {
+            let mut files = vec![];
+            read_markdown_files(Path::new(&dir), Path::new(&dir), &mut files)?;
+            for (path, id) in import_markdown(&db, &files, overwrite)? {
+                println!("{} -> {}", path, id);
+            }
+        }
+    }
+});
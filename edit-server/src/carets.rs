@@ -59,3 +59,69 @@ pub fn remove_carets_op(doc: &Doc, filter: Vec<String>) -> Result<Op, Error> {
     remove_carets_op_span(&mut writer, &doc.0, &filter)?;
     Ok((writer.result(), vec![]))
 }
+
+// Placeholder client ID for carets that aren't owned by a connected client.
+const SAVED_CARET_CLIENT: &str = "$saved";
+
+fn save_carets_op_span(
+    writer: &mut AddWriter,
+    span: &DocSpan,
+    client_id: &str,
+    found: &mut bool,
+) {
+    for elem in span {
+        match *elem {
+            DocGroup(ref attrs, ref span) => {
+                if attrs["tag"] == "caret" && attrs["client"] == client_id {
+                    let mut attrs = attrs.clone();
+                    attrs.insert("client".to_string(), SAVED_CARET_CLIENT.to_string());
+                    writer.begin();
+                    writer.close(attrs);
+                    *found = true;
+                } else {
+                    writer.begin();
+                    save_carets_op_span(writer, span, client_id, found);
+                    writer.exit();
+                }
+            }
+            DocChars(ref text) => {
+                writer.place(&AddSkip(text.char_len()));
+            }
+        }
+    }
+}
+
+/// Creates an operation that reinserts a client's carets into the document
+/// produced by `remove_carets_op` for that client. None if the client has
+/// no carets.
+pub fn save_carets_op(doc: &Doc, client_id: &str) -> Option<Op> {
+    let mut writer = AddWriter::new();
+    let mut found = false;
+    save_carets_op_span(&mut writer, &doc.0, client_id, &mut found);
+    if found {
+        Some((vec![], writer.result()))
+    } else {
+        None
+    }
+}
+
+fn assign_carets_span(span: &AddSpan, client_id: &str) -> AddSpan {
+    span.iter()
+        .map(|elem| match *elem {
+            AddGroup(ref attrs, ref span) => {
+                let mut attrs = attrs.clone();
+                if attrs["tag"] == "caret" && attrs["client"] == SAVED_CARET_CLIENT {
+                    attrs.insert("client".to_string(), client_id.to_string());
+                }
+                AddGroup(attrs, assign_carets_span(span, client_id))
+            }
+            AddWithGroup(ref span) => AddWithGroup(assign_carets_span(span, client_id)),
+            ref elem => elem.clone(),
+        })
+        .collect()
+}
+
+/// Assigns the carets inserted by a `save_carets_op` operation to a client.
+pub fn assign_carets_op(op: &Op, client_id: &str) -> Op {
+    (op.0.clone(), assign_carets_span(&op.1, client_id))
+}
@@ -0,0 +1,105 @@
+//! Comment threads of pages. Clients that may comment send a CommentAction,
+//! which the page's sync thread applies here before sending every client the
+//! page's threads again.
+
+use crate::{
+    db::*,
+    playback::now_millis,
+};
+
+use extern::{
+    diesel::sqlite::SqliteConnection,
+    edit_common::comments::*,
+    failure::Error,
+    rand::{
+        thread_rng,
+        Rng,
+    },
+    std::collections::HashMap,
+};
+
+const THREAD_ID_LEN: usize = 12;
+
+/// Apply a comment action from `author` to the threads of a page.
+pub fn apply_comment_action(
+    db: &SqliteConnection,
+    page_id: &str,
+    author: &str,
+    action: &CommentAction,
+) -> Result<(), Error> {
+    let created = now_millis();
+    let body = match action.body() {
+        Some(body) => Some(match clean_comment(body) {
+            Some(body) => body,
+            None => bail!("comments can't be empty"),
+        }),
+        None => None,
+    };
+
+    let thread_id = match action {
+        CommentAction::Start(anchor, _) => {
+            let thread_id = thread_rng()
+                .gen_ascii_chars()
+                .take(THREAD_ID_LEN)
+                .collect::<String>();
+            create_comment_thread(db, &thread_id, page_id, anchor, created)?;
+            thread_id
+        }
+        CommentAction::Reply(thread_id, _)
+        | CommentAction::Resolve(thread_id)
+        | CommentAction::Reopen(thread_id) => {
+            match select_comment_thread(db, thread_id)? {
+                Some(ref thread) if thread.page_id == page_id => {}
+                _ => bail!("no comment thread {:?} on page {:?}", thread_id, page_id),
+            }
+            thread_id.clone()
+        }
+    };
+
+    match action {
+        CommentAction::Resolve(_) => {
+            set_comment_thread_resolved(db, &thread_id, true)?;
+        }
+        CommentAction::Reopen(_) => {
+            set_comment_thread_resolved(db, &thread_id, false)?;
+        }
+        CommentAction::Start(..) | CommentAction::Reply(..) => {}
+    }
+    if let Some(body) = body {
+        create_comment(db, &thread_id, author, &body, created)?;
+    }
+    Ok(())
+}
+
+/// Every comment thread of a page, oldest first.
+pub fn page_comment_threads(
+    db: &SqliteConnection,
+    page_id: &str,
+) -> Result<Vec<CommentThread>, Error> {
+    let mut threads = select_comment_threads(db, page_id)?
+        .into_iter()
+        .map(|row| CommentThread {
+            id: row.id,
+            anchor: row.anchor,
+            resolved: row.resolved,
+            comments: vec![],
+        })
+        .collect::<Vec<_>>();
+
+    let ids = threads.iter().map(|thread| thread.id.clone()).collect::<Vec<_>>();
+    let mut comments = HashMap::new();
+    for row in select_thread_comments(db, &ids)? {
+        comments
+            .entry(row.thread_id)
+            .or_insert_with(Vec::new)
+            .push(Comment {
+                author: row.author,
+                body: row.body,
+                created: row.created,
+            });
+    }
+    for thread in &mut threads {
+        thread.comments = comments.remove(&thread.id).unwrap_or_default();
+    }
+    Ok(threads)
+}
@@ -4,6 +4,7 @@ use diesel::{
     self,
     sqlite::SqliteConnection,
 };
+use edit_common::title::doc_title;
 use failure::Error;
 use std::collections::HashMap;
 
@@ -30,10 +31,12 @@ pub fn create_page<'a>(conn: &SqliteConnection, id: &'a str, doc: &Doc) -> usize
     use super::schema::posts;
 
     let body = ::ron::ser::to_string(&doc.0).unwrap();
+    let title = doc_title(&doc.0).unwrap_or_default();
 
     let new_post = NewPost {
         id: id,
         body: &body,
+        title: &title,
     };
 
     lock_retry(|| {
@@ -55,6 +58,15 @@ pub fn all_posts(db: &SqliteConnection) -> HashMap<String, String> {
     ret
 }
 
+pub fn all_post_titles(db: &SqliteConnection) -> HashMap<String, String> {
+    use super::schema::posts::dsl::*;
+
+    let results = lock_retry(|| posts.select((id, title)).load::<(String, String)>(db))
+        .expect("Error loading post titles");
+
+    results.into_iter().collect()
+}
+
 pub fn get_single_page(db: &SqliteConnection, input_id: &str) -> Option<Doc> {
     use super::schema::posts::dsl::*;
 
@@ -124,3 +136,529 @@ pub fn clear_all_logs(db: &SqliteConnection) -> Result<usize, Error> {
 
     Ok(lock_retry(|| diesel::delete(logs).execute(db))?)
 }
+
+// Page records
+
+pub fn create_page_record<'a>(
+    conn: &SqliteConnection,
+    page_id: &'a str,
+    created: i64,
+    body: &'a str,
+) -> Result<usize, Error> {
+    use super::schema::page_records;
+
+    let new_record = NewPageRecordRow {
+        page_id,
+        created,
+        body,
+    };
+
+    Ok(lock_retry(|| {
+        diesel::insert_into(page_records::table)
+            .values(&new_record)
+            .execute(conn)
+    })?)
+}
+
+pub fn select_page_records(
+    db: &SqliteConnection,
+    input_page_id: &str,
+) -> Result<Vec<PageRecordRow>, Error> {
+    use super::schema::page_records::dsl::*;
+
+    Ok(lock_retry(|| {
+        page_records
+            .filter(page_id.eq(input_page_id))
+            .order(rowid.asc())
+            .load(db)
+    })?)
+}
+
+/// The rowid, creation time, and body of a page's records whose bodies
+/// start with `prefix`, oldest first. Bodies are cut to their first `len`
+/// characters if given, so that only what's needed of them is loaded.
+pub fn select_page_record_heads(
+    db: &SqliteConnection,
+    input_page_id: &str,
+    prefix: &str,
+    len: Option<usize>,
+) -> Result<Vec<(i32, i64, String)>, Error> {
+    use super::schema::page_records::dsl::*;
+    use diesel::dsl::sql;
+    use diesel::sql_types::Text;
+
+    let head = match len {
+        Some(len) => format!("substr(body, 1, {})", len),
+        None => "body".to_string(),
+    };
+    let pattern = format!("{}%", prefix);
+    Ok(lock_retry(|| {
+        page_records
+            .select((rowid, created, sql::<Text>(&head)))
+            .filter(page_id.eq(input_page_id))
+            .filter(body.like(pattern.as_str()))
+            .order(rowid.asc())
+            .load(db)
+    })?)
+}
+
+pub fn select_recorded_page_ids(db: &SqliteConnection) -> Result<Vec<String>, Error> {
+    use super::schema::page_records::dsl::*;
+
+    Ok(lock_retry(|| {
+        page_records
+            .select(page_id)
+            .distinct()
+            .load::<String>(db)
+    })?)
+}
+
+pub fn update_page_record_body(
+    db: &SqliteConnection,
+    input_rowid: i32,
+    input_body: &str,
+) -> Result<usize, Error> {
+    use super::schema::page_records::dsl::*;
+
+    Ok(lock_retry(|| {
+        diesel::update(page_records.filter(rowid.eq(input_rowid)))
+            .set(body.eq(input_body))
+            .execute(db)
+    })?)
+}
+
+pub fn delete_page_records(db: &SqliteConnection, input_rowids: &[i32]) -> Result<usize, Error> {
+    use super::schema::page_records::dsl::*;
+
+    Ok(lock_retry(|| {
+        diesel::delete(page_records.filter(rowid.eq_any(input_rowids))).execute(db)
+    })?)
+}
+
+// Saved carets
+
+pub fn upsert_saved_caret<'a>(
+    conn: &SqliteConnection,
+    page_id: &'a str,
+    user_id: &'a str,
+    body: &'a str,
+) -> Result<usize, Error> {
+    use super::schema::saved_carets;
+
+    let new_caret = NewSavedCaretRow {
+        page_id,
+        user_id,
+        body,
+    };
+
+    Ok(lock_retry(|| {
+        diesel::replace_into(saved_carets::table)
+            .values(&new_caret)
+            .execute(conn)
+    })?)
+}
+
+pub fn select_saved_carets(
+    db: &SqliteConnection,
+    input_page_id: &str,
+) -> Result<Vec<SavedCaretRow>, Error> {
+    use super::schema::saved_carets::dsl::*;
+
+    Ok(lock_retry(|| {
+        saved_carets.filter(page_id.eq(input_page_id)).load(db)
+    })?)
+}
+
+pub fn delete_saved_caret(
+    db: &SqliteConnection,
+    input_page_id: &str,
+    input_user_id: &str,
+) -> Result<usize, Error> {
+    use super::schema::saved_carets::dsl::*;
+
+    Ok(lock_retry(|| {
+        diesel::delete(
+            saved_carets
+                .filter(page_id.eq(input_page_id))
+                .filter(user_id.eq(input_user_id)),
+        ).execute(db)
+    })?)
+}
+
+pub fn clear_saved_carets(db: &SqliteConnection, input_page_id: &str) -> Result<usize, Error> {
+    use super::schema::saved_carets::dsl::*;
+
+    Ok(lock_retry(|| {
+        diesel::delete(saved_carets.filter(page_id.eq(input_page_id))).execute(db)
+    })?)
+}
+
+// Templates
+
+pub fn set_template(
+    conn: &SqliteConnection,
+    input_page_id: &str,
+    template: bool,
+) -> Result<usize, Error> {
+    use super::schema::templates::dsl::*;
+
+    Ok(lock_retry(|| {
+        if template {
+            diesel::replace_into(templates)
+                .values(&NewTemplateRow {
+                    page_id: input_page_id,
+                })
+                .execute(conn)
+        } else {
+            diesel::delete(templates.filter(page_id.eq(input_page_id))).execute(conn)
+        }
+    })?)
+}
+
+pub fn all_templates(db: &SqliteConnection) -> Result<Vec<String>, Error> {
+    use super::schema::templates::dsl::*;
+
+    Ok(lock_retry(|| templates.select(page_id).load::<String>(db))?)
+}
+
+pub fn is_template(db: &SqliteConnection, input_page_id: &str) -> Result<bool, Error> {
+    use super::schema::templates::dsl::*;
+
+    Ok(lock_retry(|| {
+        templates
+            .filter(page_id.eq(input_page_id))
+            .count()
+            .get_result::<i64>(db)
+    })? > 0)
+}
+
+// Forks
+
+pub fn upsert_fork<'a>(
+    conn: &SqliteConnection,
+    page_id: &'a str,
+    source_id: &'a str,
+    ancestor: &Doc,
+) -> Result<usize, Error> {
+    use super::schema::forks;
+
+    let ancestor = ::ron::ser::to_string(&ancestor.0)?;
+    let new_fork = NewForkRow {
+        page_id,
+        source_id,
+        ancestor: &ancestor,
+    };
+
+    Ok(lock_retry(|| {
+        diesel::replace_into(forks::table)
+            .values(&new_fork)
+            .execute(conn)
+    })?)
+}
+
+pub fn select_fork(db: &SqliteConnection, input_page_id: &str) -> Result<Option<ForkRow>, Error> {
+    use super::schema::forks::dsl::*;
+
+    Ok(lock_retry(|| {
+        forks
+            .filter(page_id.eq(input_page_id))
+            .first::<ForkRow>(db)
+            .optional()
+    })?)
+}
+
+// Encrypted operations
+
+pub fn create_encrypted_op<'a>(
+    conn: &SqliteConnection,
+    page_id: &'a str,
+    version: usize,
+    client_id: &'a str,
+    body: &'a str,
+) -> Result<usize, Error> {
+    use super::schema::encrypted_ops;
+
+    let new_op = NewEncryptedOpRow {
+        page_id,
+        version: version as i32,
+        client_id,
+        body,
+    };
+
+    Ok(lock_retry(|| {
+        diesel::insert_into(encrypted_ops::table)
+            .values(&new_op)
+            .execute(conn)
+    })?)
+}
+
+pub fn select_encrypted_ops(
+    db: &SqliteConnection,
+    input_page_id: &str,
+) -> Result<Vec<EncryptedOpRow>, Error> {
+    use super::schema::encrypted_ops::dsl::*;
+
+    Ok(lock_retry(|| {
+        encrypted_ops
+            .filter(page_id.eq(input_page_id))
+            .order(version.asc())
+            .load(db)
+    })?)
+}
+
+// Operations on peer pages
+
+pub fn create_peer_op<'a>(
+    conn: &SqliteConnection,
+    page_id: &'a str,
+    seq: usize,
+    client_id: &'a str,
+    body: &'a str,
+) -> Result<usize, Error> {
+    use super::schema::peer_ops;
+
+    let new_op = NewPeerOpRow {
+        page_id,
+        seq: seq as i32,
+        client_id,
+        body,
+    };
+
+    Ok(lock_retry(|| {
+        diesel::insert_into(peer_ops::table)
+            .values(&new_op)
+            .execute(conn)
+    })?)
+}
+
+pub fn select_peer_ops(db: &SqliteConnection, input_page_id: &str) -> Result<Vec<PeerOpRow>, Error> {
+    use super::schema::peer_ops::dsl::*;
+
+    Ok(lock_retry(|| {
+        peer_ops
+            .filter(page_id.eq(input_page_id))
+            .order(seq.asc())
+            .load(db)
+    })?)
+}
+
+// Share tokens
+
+pub fn create_share_token<'a>(
+    conn: &SqliteConnection,
+    token: &'a str,
+    page_id: &'a str,
+    permission: &'a str,
+    expires: Option<i64>,
+) -> Result<usize, Error> {
+    use super::schema::share_tokens;
+
+    let new_token = NewShareTokenRow {
+        token,
+        page_id,
+        permission,
+        expires,
+    };
+
+    Ok(lock_retry(|| {
+        diesel::insert_into(share_tokens::table)
+            .values(&new_token)
+            .execute(conn)
+    })?)
+}
+
+pub fn select_share_token(
+    db: &SqliteConnection,
+    input_token: &str,
+) -> Result<Option<ShareTokenRow>, Error> {
+    use super::schema::share_tokens::dsl::*;
+
+    Ok(lock_retry(|| {
+        share_tokens
+            .filter(token.eq(input_token))
+            .first::<ShareTokenRow>(db)
+            .optional()
+    })?)
+}
+
+pub fn select_share_tokens(
+    db: &SqliteConnection,
+    input_page_id: &str,
+) -> Result<Vec<ShareTokenRow>, Error> {
+    use super::schema::share_tokens::dsl::*;
+
+    Ok(lock_retry(|| {
+        share_tokens.filter(page_id.eq(input_page_id)).load(db)
+    })?)
+}
+
+pub fn delete_share_token(db: &SqliteConnection, input_token: &str) -> Result<usize, Error> {
+    use super::schema::share_tokens::dsl::*;
+
+    Ok(lock_retry(|| {
+        diesel::delete(share_tokens.filter(token.eq(input_token))).execute(db)
+    })?)
+}
+
+// Mentions
+
+pub fn create_mention<'a>(
+    conn: &SqliteConnection,
+    user_id: &'a str,
+    page_id: &'a str,
+    anchor: Option<&'a str>,
+    author: &'a str,
+    created: i64,
+) -> Result<usize, Error> {
+    use super::schema::mentions;
+
+    let new_mention = NewMentionRow {
+        user_id,
+        page_id,
+        anchor,
+        author,
+        created,
+    };
+
+    Ok(lock_retry(|| {
+        diesel::insert_into(mentions::table)
+            .values(&new_mention)
+            .execute(conn)
+    })?)
+}
+
+pub fn select_unseen_mentions(
+    db: &SqliteConnection,
+    input_user_id: &str,
+) -> Result<Vec<MentionRow>, Error> {
+    use super::schema::mentions::dsl::*;
+
+    Ok(lock_retry(|| {
+        mentions
+            .filter(user_id.eq(input_user_id))
+            .filter(seen.eq(false))
+            .order(rowid.asc())
+            .load(db)
+    })?)
+}
+
+pub fn mark_mentions_seen(
+    db: &SqliteConnection,
+    input_user_id: &str,
+    ids: &[i32],
+) -> Result<usize, Error> {
+    use super::schema::mentions::dsl::*;
+
+    Ok(lock_retry(|| {
+        diesel::update(
+            mentions
+                .filter(user_id.eq(input_user_id))
+                .filter(rowid.eq_any(ids)),
+        ).set(seen.eq(true))
+            .execute(db)
+    })?)
+}
+
+// Comments
+
+pub fn create_comment_thread<'a>(
+    conn: &SqliteConnection,
+    id: &'a str,
+    page_id: &'a str,
+    anchor: &'a str,
+    created: i64,
+) -> Result<usize, Error> {
+    use super::schema::comment_threads;
+
+    let new_thread = NewCommentThreadRow {
+        id,
+        page_id,
+        anchor,
+        created,
+    };
+
+    Ok(lock_retry(|| {
+        diesel::insert_into(comment_threads::table)
+            .values(&new_thread)
+            .execute(conn)
+    })?)
+}
+
+pub fn create_comment<'a>(
+    conn: &SqliteConnection,
+    thread_id: &'a str,
+    author: &'a str,
+    body: &'a str,
+    created: i64,
+) -> Result<usize, Error> {
+    use super::schema::comments;
+
+    let new_comment = NewCommentRow {
+        thread_id,
+        author,
+        body,
+        created,
+    };
+
+    Ok(lock_retry(|| {
+        diesel::insert_into(comments::table)
+            .values(&new_comment)
+            .execute(conn)
+    })?)
+}
+
+pub fn select_comment_thread(
+    db: &SqliteConnection,
+    input_id: &str,
+) -> Result<Option<CommentThreadRow>, Error> {
+    use super::schema::comment_threads::dsl::*;
+
+    Ok(lock_retry(|| {
+        comment_threads
+            .filter(id.eq(input_id))
+            .first(db)
+            .optional()
+    })?)
+}
+
+pub fn select_comment_threads(
+    db: &SqliteConnection,
+    input_page_id: &str,
+) -> Result<Vec<CommentThreadRow>, Error> {
+    use super::schema::comment_threads::dsl::*;
+
+    Ok(lock_retry(|| {
+        comment_threads
+            .filter(page_id.eq(input_page_id))
+            .order(created.asc())
+            .load(db)
+    })?)
+}
+
+pub fn select_thread_comments(
+    db: &SqliteConnection,
+    input_thread_ids: &[String],
+) -> Result<Vec<CommentRow>, Error> {
+    use super::schema::comments::dsl::*;
+
+    Ok(lock_retry(|| {
+        comments
+            .filter(thread_id.eq_any(input_thread_ids))
+            .order(rowid.asc())
+            .load(db)
+    })?)
+}
+
+pub fn set_comment_thread_resolved(
+    db: &SqliteConnection,
+    input_id: &str,
+    input_resolved: bool,
+) -> Result<usize, Error> {
+    use super::schema::comment_threads::dsl::*;
+
+    Ok(lock_retry(|| {
+        diesel::update(comment_threads.filter(id.eq(input_id)))
+            .set(resolved.eq(input_resolved))
+            .execute(db)
+    })?)
+}
@@ -1,3 +1,40 @@
+table! {
+    comment_threads (id) {
+        id -> Text,
+        page_id -> Text,
+        anchor -> Text,
+        resolved -> Bool,
+        created -> BigInt,
+    }
+}
+
+table! {
+    comments (rowid) {
+        rowid -> Integer,
+        thread_id -> Text,
+        author -> Text,
+        body -> Text,
+        created -> BigInt,
+    }
+}
+
+table! {
+    encrypted_ops (page_id, version) {
+        page_id -> Text,
+        version -> Integer,
+        client_id -> Text,
+        body -> Text,
+    }
+}
+
+table! {
+    forks (page_id) {
+        page_id -> Text,
+        source_id -> Text,
+        ancestor -> Text,
+    }
+}
+
 table! {
     logs (rowid) {
         rowid -> Integer,
@@ -6,11 +43,65 @@ table! {
     }
 }
 
+table! {
+    mentions (rowid) {
+        rowid -> Integer,
+        user_id -> Text,
+        page_id -> Text,
+        anchor -> Nullable<Text>,
+        author -> Text,
+        created -> BigInt,
+        seen -> Bool,
+    }
+}
+
+table! {
+    page_records (rowid) {
+        rowid -> Integer,
+        page_id -> Text,
+        created -> BigInt,
+        body -> Text,
+    }
+}
+
+table! {
+    peer_ops (page_id, seq) {
+        page_id -> Text,
+        seq -> Integer,
+        client_id -> Text,
+        body -> Text,
+    }
+}
+
+table! {
+    saved_carets (page_id, user_id) {
+        page_id -> Text,
+        user_id -> Text,
+        body -> Text,
+    }
+}
+
+table! {
+    share_tokens (token) {
+        token -> Text,
+        page_id -> Text,
+        permission -> Text,
+        expires -> Nullable<BigInt>,
+    }
+}
+
+table! {
+    templates (page_id) {
+        page_id -> Text,
+    }
+}
+
 table! {
     posts (id) {
         id -> Text,
         body -> Text,
+        title -> Text,
     }
 }
 
-allow_tables_to_appear_in_same_query!(logs, posts,);
+allow_tables_to_appear_in_same_query!(comment_threads, comments, encrypted_ops, forks, logs, mentions, page_records, peer_ops, posts, saved_carets, share_tokens, templates,);
@@ -2,6 +2,7 @@
 pub struct Post {
     pub id: String,
     pub body: String,
+    pub title: String,
 }
 
 use super::schema::posts;
@@ -11,6 +12,7 @@ use super::schema::posts;
 pub struct NewPost<'a> {
     pub id: &'a str,
     pub body: &'a str,
+    pub title: &'a str,
 }
 
 #[derive(Queryable, Debug, Serialize, Deserialize)]
@@ -28,3 +30,184 @@ pub struct NewLog<'a> {
     pub source: &'a str,
     pub body: &'a str,
 }
+
+#[derive(Queryable, Debug)]
+pub struct PageRecordRow {
+    pub rowid: i32,
+    pub page_id: String,
+    pub created: i64,
+    pub body: String,
+}
+
+use super::schema::page_records;
+
+#[derive(Insertable)]
+#[table_name = "page_records"]
+pub struct NewPageRecordRow<'a> {
+    pub page_id: &'a str,
+    pub created: i64,
+    pub body: &'a str,
+}
+
+#[derive(Queryable, Debug)]
+pub struct SavedCaretRow {
+    pub page_id: String,
+    pub user_id: String,
+    pub body: String,
+}
+
+use super::schema::saved_carets;
+
+#[derive(Insertable)]
+#[table_name = "saved_carets"]
+pub struct NewSavedCaretRow<'a> {
+    pub page_id: &'a str,
+    pub user_id: &'a str,
+    pub body: &'a str,
+}
+
+use super::schema::templates;
+
+#[derive(Insertable)]
+#[table_name = "templates"]
+pub struct NewTemplateRow<'a> {
+    pub page_id: &'a str,
+}
+
+#[derive(Queryable, Debug)]
+pub struct ForkRow {
+    pub page_id: String,
+    pub source_id: String,
+    pub ancestor: String,
+}
+
+use super::schema::forks;
+
+#[derive(Insertable)]
+#[table_name = "forks"]
+pub struct NewForkRow<'a> {
+    pub page_id: &'a str,
+    pub source_id: &'a str,
+    pub ancestor: &'a str,
+}
+
+#[derive(Queryable, Debug)]
+pub struct EncryptedOpRow {
+    pub page_id: String,
+    pub version: i32,
+    pub client_id: String,
+    pub body: String,
+}
+
+use super::schema::encrypted_ops;
+
+#[derive(Insertable)]
+#[table_name = "encrypted_ops"]
+pub struct NewEncryptedOpRow<'a> {
+    pub page_id: &'a str,
+    pub version: i32,
+    pub client_id: &'a str,
+    pub body: &'a str,
+}
+
+#[derive(Queryable, Debug)]
+pub struct PeerOpRow {
+    pub page_id: String,
+    pub seq: i32,
+    pub client_id: String,
+    pub body: String,
+}
+
+use super::schema::peer_ops;
+
+#[derive(Insertable)]
+#[table_name = "peer_ops"]
+pub struct NewPeerOpRow<'a> {
+    pub page_id: &'a str,
+    pub seq: i32,
+    pub client_id: &'a str,
+    pub body: &'a str,
+}
+
+#[derive(Queryable, Debug)]
+pub struct ShareTokenRow {
+    pub token: String,
+    pub page_id: String,
+    pub permission: String,
+    // Unix time in seconds, or None if the token doesn't expire.
+    pub expires: Option<i64>,
+}
+
+use super::schema::share_tokens;
+
+#[derive(Insertable)]
+#[table_name = "share_tokens"]
+pub struct NewShareTokenRow<'a> {
+    pub token: &'a str,
+    pub page_id: &'a str,
+    pub permission: &'a str,
+    pub expires: Option<i64>,
+}
+
+#[derive(Queryable, Debug)]
+pub struct MentionRow {
+    pub rowid: i32,
+    pub user_id: String,
+    pub page_id: String,
+    pub anchor: Option<String>,
+    pub author: String,
+    pub created: i64,
+    pub seen: bool,
+}
+
+use super::schema::mentions;
+
+#[derive(Insertable)]
+#[table_name = "mentions"]
+pub struct NewMentionRow<'a> {
+    pub user_id: &'a str,
+    pub page_id: &'a str,
+    pub anchor: Option<&'a str>,
+    pub author: &'a str,
+    pub created: i64,
+}
+
+#[derive(Queryable, Debug)]
+pub struct CommentThreadRow {
+    pub id: String,
+    pub page_id: String,
+    pub anchor: String,
+    pub resolved: bool,
+    pub created: i64,
+}
+
+use super::schema::comment_threads;
+
+#[derive(Insertable)]
+#[table_name = "comment_threads"]
+pub struct NewCommentThreadRow<'a> {
+    pub id: &'a str,
+    pub page_id: &'a str,
+    pub anchor: &'a str,
+    pub created: i64,
+}
+
+#[derive(Queryable, Debug)]
+pub struct CommentRow {
+    pub rowid: i32,
+    pub thread_id: String,
+    pub author: String,
+    pub body: String,
+    pub created: i64,
+}
+
+use super::schema::comments;
+
+#[derive(Insertable)]
+#[table_name = "comments"]
+pub struct NewCommentRow<'a> {
+    pub thread_id: &'a str,
+    pub author: &'a str,
+    pub body: &'a str,
+    pub created: i64,
+}
@@ -0,0 +1,121 @@
+//! Relay for end-to-end encrypted documents, which clients connect to at
+//! /$/ws/{page}/encrypted. Clients encrypt their operations with a key the
+//! server never sees, so the server can't transform or apply them. It only
+//! orders them: an operation is accepted if it was written against the
+//! latest version, and refused otherwise, for the client to transform
+//! against what it missed and send again. Accepted operations are stored
+//! and relayed to every client, and new clients replay them all.
+//!
+//! Nothing that needs to read the document works for these pages: search,
+//! titles, exports, history, and removing the carets of clients who leave.
+
+use crate::db::*;
+
+use extern::{
+    edit_common::commands::*,
+    edit_common::e2ee::is_sealed,
+    edit_common::simple_ws,
+    failure::Error,
+    serde_json,
+    std::{
+        collections::HashMap,
+        sync::Mutex,
+    },
+};
+
+struct EncryptedPage {
+    version: usize,
+    clients: HashMap<String, simple_ws::Sender>,
+}
+
+lazy_static! {
+    static ref ENCRYPTED_PAGES: Mutex<HashMap<String, EncryptedPage>> = Mutex::new(HashMap::new());
+}
+
+fn send_command(client: &simple_ws::Sender, command: &ClientCommand) -> Result<(), Error> {
+    let json = serde_json::to_string(command)?;
+    Ok(client.send(json)?)
+}
+
+/// Send a client every operation of the page, and relay it new ones.
+pub fn encrypted_connect(
+    db_pool: &DbPool,
+    page_id: &str,
+    client_id: &str,
+    out: simple_ws::Sender,
+) -> Result<(), Error> {
+    let conn = db_pool.get()?;
+    let mut pages = ENCRYPTED_PAGES.lock().unwrap();
+
+    let rows = select_encrypted_ops(&conn, page_id)?;
+    let page = pages
+        .entry(page_id.to_string())
+        .or_insert_with(|| EncryptedPage {
+            version: rows.len(),
+            clients: HashMap::new(),
+        });
+
+    let ops = rows
+        .into_iter()
+        .map(|row| (row.client_id, row.body))
+        .collect::<Vec<_>>();
+    send_command(
+        &out,
+        &ClientCommand::EncryptedInit(client_id.to_string(), ops, page.version),
+    )?;
+    page.clients.insert(client_id.to_string(), out);
+    Ok(())
+}
+
+/// Store and relay an operation written against `version`, or refuse it if
+/// that isn't the latest version, or it isn't a sealed value at all.
+pub fn encrypted_commit(
+    db_pool: &DbPool,
+    page_id: &str,
+    client_id: &str,
+    body: &str,
+    version: usize,
+) -> Result<(), Error> {
+    let conn = db_pool.get().map_err(simple_ws::CloseError::unavailable)?;
+    let mut pages = ENCRYPTED_PAGES.lock().unwrap();
+    let page = pages
+        .get_mut(page_id)
+        .ok_or_else(|| format_err!("encrypted page {:?} isn't loaded", page_id))?;
+
+    let refusal = if !is_sealed(body) {
+        Some(RefusalReason::MalformedOp("not a sealed operation".to_string()))
+    } else if version != page.version {
+        Some(RefusalReason::UnknownVersion(version))
+    } else {
+        None
+    };
+    if let Some(reason) = refusal {
+        if let Some(client) = page.clients.get(client_id) {
+            send_command(client, &ClientCommand::Refuse(reason))?;
+        }
+        return Ok(());
+    }
+
+    create_encrypted_op(&conn, page_id, version, client_id, body)?;
+    page.version += 1;
+
+    let command = ClientCommand::EncryptedUpdate(page.version, client_id.to_string(), body.to_string());
+    for client in page.clients.values() {
+        let _ = send_command(client, &command);
+    }
+    Ok(())
+}
+
+pub fn encrypted_disconnect(page_id: &str, client_id: &str) {
+    let mut pages = ENCRYPTED_PAGES.lock().unwrap();
+    let empty = match pages.get_mut(page_id) {
+        Some(page) => {
+            page.clients.remove(client_id);
+            page.clients.is_empty()
+        }
+        None => false,
+    };
+    if empty {
+        pages.remove(page_id);
+    }
+}
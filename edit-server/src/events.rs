@@ -0,0 +1,28 @@
+//! Events of a document, for integrations which only want to know what
+//! happens to it and not to edit it: CI bots, publishing pipelines, and the
+//! like. Tools connect to /$/ws/{page}/events, and are sent each event as
+//! JSON. Anything they send is ignored.
+
+use extern::{
+    edit_common::identity::UserIdentity,
+    oatie::doc::*,
+};
+
+#[derive(Serialize, Debug, Clone)]
+pub enum DocumentEvent {
+    // Sent first, with the document's current version and title.
+    Watching { version: usize, title: String },
+    // An operation was applied by a client, or by the server for it, and
+    // made this version.
+    OpApplied {
+        version: usize,
+        client_id: String,
+        op: Op,
+    },
+    UserJoined {
+        client_id: String,
+        user: UserIdentity,
+    },
+    UserLeft { client_id: String },
+    TitleChanged { title: String },
+}
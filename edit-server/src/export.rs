@@ -0,0 +1,71 @@
+//! Exports pages as markdown files inside a zip archive, for backups and
+//! moving documents between servers. Each file starts with frontmatter
+//! describing its page.
+
+use crate::db::*;
+
+use diesel::sqlite::SqliteConnection;
+use edit_common::markdown::doc_to_markdown;
+use edit_common::title::doc_title;
+use failure::Error;
+use serde_json;
+use std::io::{
+    Cursor,
+    Write,
+};
+use zip::{
+    write::FileOptions,
+    ZipWriter,
+};
+
+/// Frontmatter block for a page. Values are JSON strings, which are also
+/// valid YAML.
+pub fn page_frontmatter(id: &str, title: &str, template: bool) -> String {
+    let mut out = String::from("---\n");
+    out.push_str(&format!("id: {}\n", serde_json::to_string(id).unwrap()));
+    out.push_str(&format!("title: {}\n", serde_json::to_string(title).unwrap()));
+    if template {
+        out.push_str("template: true\n");
+    }
+    out.push_str("---\n\n");
+    out
+}
+
+/// Filename and contents of the markdown export of each page, or of just
+/// the pages in `ids`. Missing pages are skipped.
+pub fn export_markdown(
+    db: &SqliteConnection,
+    ids: Option<&[String]>,
+) -> Result<Vec<(String, String)>, Error> {
+    let mut ids = match ids {
+        Some(ids) => ids.to_vec(),
+        None => all_post_titles(db).into_iter().map(|(id, _)| id).collect(),
+    };
+    ids.sort();
+    ids.dedup();
+
+    let templates = all_templates(db)?;
+    let mut files = vec![];
+    for id in ids {
+        let doc = match get_single_page(db, &id) {
+            Some(doc) => doc,
+            None => continue,
+        };
+        let title = doc_title(&doc.0).unwrap_or_default();
+        let mut contents = page_frontmatter(&id, &title, templates.contains(&id));
+        contents.push_str(&doc_to_markdown(&doc.0)?);
+        files.push((format!("{}.md", id), contents));
+    }
+    Ok(files)
+}
+
+/// A zip archive of the markdown export of each page, or of the pages in
+/// `ids`.
+pub fn export_zip(db: &SqliteConnection, ids: Option<&[String]>) -> Result<Vec<u8>, Error> {
+    let mut zip = ZipWriter::new(Cursor::new(vec![]));
+    for (filename, contents) in export_markdown(db, ids)? {
+        zip.start_file(filename, FileOptions::default())?;
+        zip.write_all(contents.as_bytes())?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
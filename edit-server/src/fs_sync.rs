@@ -0,0 +1,190 @@
+//! Two-way sync between pages and a directory of markdown files, so a page
+//! can be edited in a local text editor as well as in the browser. Set
+//! EDIT_SYNC_DIR to turn it on. Each page is saved to "{id}.md" in the
+//! directory, and edits to those files are applied to their pages as
+//! operations, merging with edits made in the meantime.
+
+use crate::sync::{
+    valid_page_id,
+    ClientNotify,
+    ClientUpdate,
+};
+
+use extern::{
+    crossbeam_channel::Sender as CCSender,
+    edit_common::markdown::{
+        doc_to_markdown,
+        markdown_to_doc,
+    },
+    failure::Error,
+    oatie::{
+        diff::diff,
+        doc::*,
+        schema::RtfSchema,
+        validate::{
+            validate_doc,
+            validate_op,
+        },
+        OT,
+    },
+    std::{
+        collections::HashMap,
+        env,
+        fs,
+        path::PathBuf,
+        sync::Mutex,
+        thread,
+        time::{
+            Duration,
+            SystemTime,
+        },
+    },
+};
+
+const FS_POLL_INTERVAL: u64 = 1_000;
+
+pub struct FsSync {
+    dir: PathBuf,
+    // The markdown each file last held, as written by us or read from disk,
+    // so neither side echoes the other's changes back.
+    known: Mutex<HashMap<String, String>>,
+}
+
+impl FsSync {
+    fn from_env() -> Option<FsSync> {
+        let dir = PathBuf::from(env::var("EDIT_SYNC_DIR").ok()?);
+        if let Err(err) = fs::create_dir_all(&dir) {
+            eprintln!("(!) could not create sync directory {:?}: {:?}", dir, err);
+            return None;
+        }
+        eprintln!("(!) syncing pages with directory {:?}", dir);
+        Some(FsSync {
+            dir,
+            known: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Write a saved page to its file, unless it's unchanged.
+    pub fn write_page(&self, page_id: &str, doc: &Doc) {
+        let markdown = match doc_to_markdown(&doc.0) {
+            Ok(markdown) => markdown,
+            Err(_) => return,
+        };
+        let mut known = self.known.lock().unwrap();
+        if known.get(page_id) == Some(&markdown) {
+            return;
+        }
+        let path = self.dir.join(format!("{}.md", page_id));
+        match fs::write(&path, &markdown) {
+            Ok(_) => {
+                known.insert(page_id.to_string(), markdown);
+            }
+            Err(err) => eprintln!("(!) could not write {:?}: {:?}", path, err),
+        }
+    }
+
+    // Pages whose files changed since they were last read or written, and
+    // their new contents.
+    fn changed_files(&self, mtimes: &mut HashMap<PathBuf, SystemTime>) -> Vec<(String, String)> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(_) => return vec![],
+        };
+
+        let mut changed = vec![];
+        for path in entries.filter_map(|entry| Some(entry.ok()?.path())) {
+            let page_id = match path.file_name().and_then(|x| x.to_str()) {
+                Some(name) if name.ends_with(".md") => name[..name.len() - 3].to_string(),
+                _ => continue,
+            };
+            if !valid_page_id(&page_id) {
+                continue;
+            }
+
+            let mtime = match fs::metadata(&path).and_then(|x| x.modified()) {
+                Ok(mtime) => mtime,
+                Err(_) => continue,
+            };
+            if mtimes.get(&path) == Some(&mtime) {
+                continue;
+            }
+            mtimes.insert(path.clone(), mtime);
+
+            let contents = match fs::read_to_string(&path) {
+                Ok(contents) => contents,
+                Err(_) => continue,
+            };
+            if self.known.lock().unwrap().get(&page_id) != Some(&contents) {
+                changed.push((page_id, contents));
+            }
+        }
+        changed
+    }
+
+    // The document a page's file held before its latest change, if known.
+    fn known_doc(&self, page_id: &str) -> Option<Doc> {
+        let markdown = self.known.lock().unwrap().get(page_id).cloned()?;
+        markdown_to_doc(&markdown).ok().map(Doc)
+    }
+
+    // Files are remembered as the markdown their page would be saved as, so
+    // a page that ends up matching its file isn't written back over it.
+    fn remember(&self, page_id: &str, doc: &Doc) {
+        if let Ok(markdown) = doc_to_markdown(&doc.0) {
+            self.known.lock().unwrap().insert(page_id.to_string(), markdown);
+        }
+    }
+}
+
+lazy_static! {
+    pub static ref FS_SYNC: Option<FsSync> = FsSync::from_env();
+}
+
+/// Write a saved page to the sync directory, if there is one.
+pub fn fs_sync_page(page_id: &str, doc: &Doc) {
+    if let Some(ref fs_sync) = *FS_SYNC {
+        fs_sync.write_page(page_id, doc);
+    }
+}
+
+/// The operation merging an edit made to a page's file into the page as it
+/// is now. The file's changes since `base`, what the file held before, are
+/// transformed over the page's own changes since then, so edits made in the
+/// browser in the meantime are kept.
+pub fn merge_external_edit(base: &Doc, live: &Doc, file: &Doc) -> Result<Op, Error> {
+    validate_doc(file)?;
+    let ours = diff(base, live);
+    let theirs = diff(base, file);
+    let (merged, _) = Op::transform::<RtfSchema>(&ours, &theirs);
+    validate_op(live, &merged)?;
+    Ok(merged)
+}
+
+/// Watch the sync directory, if there is one, sending edits made to its
+/// files to their pages.
+pub fn spawn_fs_watcher(tx_master: CCSender<ClientNotify>) {
+    if FS_SYNC.is_none() {
+        return;
+    }
+    thread::spawn(move || {
+        let fs_sync = FS_SYNC.as_ref().unwrap();
+        let mut mtimes = HashMap::new();
+        loop {
+            for (page_id, contents) in fs_sync.changed_files(&mut mtimes) {
+                match markdown_to_doc(&contents) {
+                    Ok(span) => {
+                        let doc = Doc(span);
+                        let base = fs_sync.known_doc(&page_id);
+                        fs_sync.remember(&page_id, &doc);
+                        let _ = tx_master.send(ClientNotify(
+                            page_id,
+                            ClientUpdate::ExternalEdit { base, doc },
+                        ));
+                    }
+                    Err(err) => eprintln!("(!) could not read {}.md: {:?}", page_id, err),
+                }
+            }
+            thread::sleep(Duration::from_millis(FS_POLL_INTERVAL));
+        }
+    });
+}
@@ -1,6 +1,7 @@
 //! GraphQL code.
 
 pub mod client;
+pub mod reporting;
 pub mod server;
 
 pub use self::client::*;
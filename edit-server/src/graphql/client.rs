@@ -65,6 +65,51 @@ query ($id: String!) {
     Some(Doc(body))
 }
 
+/// A page, if the browser with the given share token and cookies may open
+/// it. Errs if it may not.
+pub fn get_shared_page_graphql(
+    input_id: &str,
+    share: Option<&str>,
+    cookie: Option<&str>,
+) -> Result<Option<Doc>, Error> {
+    let mut headers = reqwest::header::Headers::new();
+    if let Some(cookie) = cookie {
+        headers.set_raw("Cookie", cookie.to_string());
+    }
+
+    let client = reqwest::Client::new();
+    let text = client
+        .post("http://127.0.0.1:8003/graphql/")
+        .headers(headers)
+        .json(&json!({
+            "query": r#"
+
+query ($id: String!, $share: String) {
+    sharedPage(id: $id, share: $share) {
+        doc
+    }
+}
+
+"#,
+            "variables": {
+                "id": input_id,
+                "share": share,
+            },
+        }))
+        .send()?
+        .text()?;
+
+    let ret: serde_json::Value = serde_json::from_str(&text)?;
+    if let Some(message) = ret.pointer("/errors/0/message").and_then(|x| x.as_str()) {
+        bail!("{}", message);
+    }
+    let node = match ret.pointer("/data/sharedPage/doc").and_then(|x| x.as_str()) {
+        Some(node) => node,
+        None => return Ok(None),
+    };
+    Ok(Some(Doc(::ron::de::from_str(node)?)))
+}
+
 pub fn graphql_request(
     query: &str,
     variables: &serde_json::Value,
@@ -141,3 +186,76 @@ mutation ($id: String!, $doc: String!) {
     let body = ::ron::de::from_str(&ron).ok()?;
     Some(Doc(body))
 }
+
+pub fn create_page_from_template_graphql(input_id: &str, template: &str) -> Result<Doc, Error> {
+    let ret = graphql_request(
+        r#"
+
+mutation ($id: String!, $template: String!) {
+    createPageFromTemplate(id: $id, template: $template) {
+        doc
+    }
+}
+
+"#,
+        &json!({
+            "id": input_id,
+            "template": template,
+        }),
+    )?;
+
+    let doc_string = ret
+        .pointer("/data/createPageFromTemplate/doc")
+        .ok_or(format_err!("could not create page from template"))?
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    Ok(Doc(::ron::de::from_str(&doc_string)?))
+}
+
+/// Search the pages a browser may open, given the share token and cookies
+/// it came with, returning each result's page id, title, block anchor, and
+/// snippet.
+pub fn search_graphql(
+    query: &str,
+    share: Option<&str>,
+    cookie: Option<&str>,
+) -> Result<serde_json::Value, Error> {
+    // The cookie has the browser's session, which may be banned.
+    let mut headers = reqwest::header::Headers::new();
+    if let Some(cookie) = cookie {
+        headers.set_raw("Cookie", cookie.to_string());
+    }
+
+    let client = reqwest::Client::new();
+    let text = client
+        .post("http://127.0.0.1:8003/graphql/")
+        .headers(headers)
+        .json(&json!({
+            "query": r#"
+
+query ($query: String!, $share: String) {
+    search(query: $query, share: $share) {
+        pageId
+        title
+        anchor
+        snippet
+    }
+}
+
+"#,
+            "variables": {
+                "query": query,
+                "share": share,
+            },
+        }))
+        .send()?
+        .text()?;
+    let ret: serde_json::Value = serde_json::from_str(&text)?;
+
+    Ok(ret
+        .pointer("/data/search")
+        .ok_or(format_err!("could not search pages"))?
+        .clone())
+}
@@ -0,0 +1,210 @@
+//! Read-only views of documents for dashboards and reporting: their
+//! revisions, who took part, and their comments, a page at a time. Served
+//! only when EDIT_GRAPHQL_API is set, as they expose every document's
+//! history to anyone who can reach the GraphQL port.
+
+use crate::playback::HistoryRecord;
+
+use extern::{
+    edit_common::comments::CommentThread,
+    juniper::{
+        FieldError,
+        FieldResult,
+        Value,
+    },
+    std::collections::HashMap,
+    std::env,
+};
+
+pub const GRAPHQL_API_VAR: &str = "EDIT_GRAPHQL_API";
+
+const DEFAULT_PAGE_SIZE: usize = 20;
+const MAX_PAGE_SIZE: usize = 100;
+
+/// Fails unless the reporting API was enabled with EDIT_GRAPHQL_API.
+pub fn reporting_enabled() -> FieldResult<()> {
+    match env::var(GRAPHQL_API_VAR) {
+        Ok(ref value) if !value.is_empty() && value != "0" => Ok(()),
+        _ => Err(FieldError::new(
+            format!("The reporting API is disabled. Set {} to enable it.", GRAPHQL_API_VAR),
+            Value::null(),
+        )),
+    }
+}
+
+#[derive(Clone, Debug, GraphQLObject)]
+pub struct PageInfo {
+    // Cursor to pass as `after` for the next page.
+    pub end_cursor: Option<String>,
+    pub has_next_page: bool,
+}
+
+/// The `first` items after the cursor `after`, the offset of the last item
+/// of the previous page, along with the total count.
+pub fn paginate<T>(
+    items: Vec<T>,
+    first: Option<i32>,
+    after: Option<String>,
+) -> FieldResult<(Vec<T>, PageInfo, i32)> {
+    let total = items.len();
+    let start = match after {
+        Some(cursor) => {
+            cursor
+                .parse::<usize>()
+                .ok()
+                .and_then(|offset| offset.checked_add(1))
+                .ok_or_else(|| FieldError::new("Invalid cursor", Value::null()))?
+        }
+        None => 0,
+    };
+    let size = match first {
+        Some(first) if first < 0 => {
+            return Err(FieldError::new("first must not be negative", Value::null()));
+        }
+        Some(first) => (first as usize).min(MAX_PAGE_SIZE),
+        None => DEFAULT_PAGE_SIZE,
+    };
+
+    let nodes = items.into_iter().skip(start).take(size).collect::<Vec<_>>();
+    let end = start + nodes.len();
+    let page_info = PageInfo {
+        end_cursor: if nodes.is_empty() {
+            None
+        } else {
+            Some((end - 1).to_string())
+        },
+        has_next_page: end < total,
+    };
+    Ok((nodes, page_info, total as i32))
+}
+
+/// An operation committed to a document.
+#[derive(Clone, Debug, GraphQLObject)]
+pub struct Revision {
+    // Counts commits since the document's recording began, from 1.
+    pub number: i32,
+    pub client_id: String,
+    // Id of the user who made it, if they joined while it was recorded.
+    pub user_id: Option<String>,
+    // Unix time in milliseconds.
+    pub created: f64,
+}
+
+/// A user who joined a document.
+#[derive(Clone, Debug, GraphQLObject)]
+pub struct Participant {
+    pub id: String,
+    pub name: String,
+    pub color: String,
+    pub guest: bool,
+    // Unix time in milliseconds of when they first and last joined.
+    pub first_joined: f64,
+    pub last_joined: f64,
+    // Revisions they made.
+    pub revisions: i32,
+}
+
+/// A document's revisions, oldest first, and its participants in the order
+/// they first joined.
+pub fn page_history(records: &[(i64, HistoryRecord)]) -> (Vec<Revision>, Vec<Participant>) {
+    let mut revisions = vec![];
+    let mut participants: Vec<Participant> = vec![];
+    let mut client_users: HashMap<String, String> = HashMap::new();
+
+    for (created, record) in records {
+        match record {
+            HistoryRecord::Join(client_id, identity) => {
+                client_users.insert(client_id.clone(), identity.id.clone());
+                match participants.iter_mut().find(|x| x.id == identity.id) {
+                    Some(participant) => {
+                        participant.name = identity.name.clone();
+                        participant.color = identity.color.clone();
+                        participant.last_joined = *created as f64;
+                    }
+                    None => participants.push(Participant {
+                        id: identity.id.clone(),
+                        name: identity.name.clone(),
+                        color: identity.color.clone(),
+                        guest: identity.guest,
+                        first_joined: *created as f64,
+                        last_joined: *created as f64,
+                        revisions: 0,
+                    }),
+                }
+            }
+            HistoryRecord::Commit(client_id) => {
+                let user_id = client_users.get(client_id).cloned();
+                if let Some(ref user_id) = user_id {
+                    if let Some(participant) = participants.iter_mut().find(|x| &x.id == user_id) {
+                        participant.revisions += 1;
+                    }
+                }
+                revisions.push(Revision {
+                    number: revisions.len() as i32 + 1,
+                    client_id: client_id.clone(),
+                    user_id,
+                    created: *created as f64,
+                });
+            }
+        }
+    }
+
+    (revisions, participants)
+}
+
+#[derive(Clone, Debug, GraphQLObject)]
+pub struct CommentNode {
+    pub author: String,
+    pub body: String,
+    // Unix time in milliseconds.
+    pub created: f64,
+}
+
+#[derive(Clone, Debug, GraphQLObject)]
+pub struct CommentThreadNode {
+    pub id: String,
+    // Id of the block the thread is about.
+    pub anchor: String,
+    pub resolved: bool,
+    pub comments: Vec<CommentNode>,
+}
+
+impl From<CommentThread> for CommentThreadNode {
+    fn from(thread: CommentThread) -> CommentThreadNode {
+        CommentThreadNode {
+            id: thread.id,
+            anchor: thread.anchor,
+            resolved: thread.resolved,
+            comments: thread
+                .comments
+                .into_iter()
+                .map(|comment| CommentNode {
+                    author: comment.author,
+                    body: comment.body,
+                    created: comment.created as f64,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, GraphQLObject)]
+pub struct RevisionConnection {
+    pub nodes: Vec<Revision>,
+    pub page_info: PageInfo,
+    pub total_count: i32,
+}
+
+#[derive(Clone, Debug, GraphQLObject)]
+pub struct ParticipantConnection {
+    pub nodes: Vec<Participant>,
+    pub page_info: PageInfo,
+    pub total_count: i32,
+}
+
+#[derive(Clone, Debug, GraphQLObject)]
+pub struct CommentThreadConnection {
+    pub nodes: Vec<CommentThreadNode>,
+    pub page_info: PageInfo,
+    pub total_count: i32,
+}
@@ -1,16 +1,26 @@
 //! GraphQL server.
 
+use super::reporting::*;
+
 use crate::{
+    admin::*,
+    comments::page_comment_threads,
     db::*,
+    mentions::*,
+    playback::load_page_history,
+    search::*,
+    share::client_permission,
     sync::{
         ClientNotify,
         ClientUpdate,
     },
+    templates::*,
 };
 
 use extern::{
     crossbeam_channel::Sender as CCSender,
     diesel::sqlite::SqliteConnection,
+    edit_common::identity::session_id,
     edit_common::markdown::*,
     juniper::{
         self,
@@ -35,6 +45,18 @@ struct Page {
 #[derive(GraphQLObject)]
 struct PageId {
     id: String,
+    title: String,
+}
+
+// A mention of a user they haven't seen yet.
+#[derive(GraphQLObject)]
+struct MentionNotice {
+    id: i32,
+    page_id: String,
+    anchor: Option<String>,
+    author: String,
+    // Unix time in milliseconds.
+    created: f64,
 }
 
 graphql_object!(Page: () |&self| {
@@ -48,6 +70,79 @@ graphql_object!(Page: () |&self| {
     }
 });
 
+// A document, for reporting.
+#[derive(Clone)]
+struct Document {
+    id: String,
+    title: String,
+}
+
+graphql_object!(Document: Ctx |&self| {
+    field id() -> &str {
+        self.id.as_str()
+    }
+
+    field title() -> &str {
+        self.title.as_str()
+    }
+
+    field markdown(&executor) -> FieldResult<Option<String>> {
+        let conn = executor.context().db_pool.get()?;
+
+        match get_single_page(&conn, &self.id) {
+            Some(doc) => Ok(Some(doc_to_markdown(&doc.0)?)),
+            None => Ok(None),
+        }
+    }
+
+    field revisions(&executor, first: Option<i32>, after: Option<String>) -> FieldResult<RevisionConnection> {
+        let records = load_page_history(&executor.context().db_pool, &self.id)?;
+        let (revisions, _) = page_history(&records);
+
+        let (nodes, page_info, total_count) = paginate(revisions, first, after)?;
+        Ok(RevisionConnection { nodes, page_info, total_count })
+    }
+
+    field participants(&executor, first: Option<i32>, after: Option<String>) -> FieldResult<ParticipantConnection> {
+        let records = load_page_history(&executor.context().db_pool, &self.id)?;
+        let (_, participants) = page_history(&records);
+
+        let (nodes, page_info, total_count) = paginate(participants, first, after)?;
+        Ok(ParticipantConnection { nodes, page_info, total_count })
+    }
+
+    field comments(&executor, first: Option<i32>, after: Option<String>) -> FieldResult<CommentThreadConnection> {
+        let conn = executor.context().db_pool.get()?;
+        let threads = page_comment_threads(&conn, &self.id)?
+            .into_iter()
+            .map(CommentThreadNode::from)
+            .collect::<Vec<_>>();
+
+        let (nodes, page_info, total_count) = paginate(threads, first, after)?;
+        Ok(CommentThreadConnection { nodes, page_info, total_count })
+    }
+});
+
+struct DocumentConnection {
+    nodes: Vec<Document>,
+    page_info: PageInfo,
+    total_count: i32,
+}
+
+graphql_object!(DocumentConnection: Ctx |&self| {
+    field nodes() -> Vec<Document> {
+        self.nodes.clone()
+    }
+
+    field page_info() -> PageInfo {
+        self.page_info.clone()
+    }
+
+    field total_count() -> i32 {
+        self.total_count
+    }
+});
+
 struct Query;
 
 graphql_object!(Query: Ctx |&self| {
@@ -61,15 +156,106 @@ graphql_object!(Query: Ctx |&self| {
         }))
     }
 
+    // A page, for a browser that may open it with the share link `share`,
+    // if it has one.
+    field sharedPage(&executor, id: String, share: Option<String>) -> FieldResult<Option<Page>> {
+        let share = share.as_ref().map(|x| x.as_str());
+        executor.context().refuse_banned(share)?;
+        let conn = executor.context().db_pool.get().unwrap();
+
+        if client_permission(&conn, share, &id)?.is_none() {
+            return Err(FieldError::new(
+                "Opening this page needs a share link.",
+                juniper::Value::null(),
+            ));
+        }
+
+        let page = get_single_page_raw(&conn, &id);
+
+        Ok(page.map(|x| Page {
+            doc: x.body
+        }))
+    }
+
     field pages(&executor) -> FieldResult<Vec<PageId>> {
         let conn = executor.context().db_pool.get().unwrap();
 
-        let posts = all_posts(&conn);
-        let mut post_ids: Vec<String> = posts.keys().cloned().collect();
-        post_ids.sort();
+        let mut posts: Vec<(String, String)> = all_post_titles(&conn).into_iter().collect();
+        posts.sort();
+
+        Ok(posts.into_iter().map(|(id, title)| PageId {
+            id,
+            title,
+        }).collect::<Vec<_>>())
+    }
+
+    field templates(&executor) -> FieldResult<Vec<PageId>> {
+        let conn = executor.context().db_pool.get().unwrap();
+
+        let titles = all_post_titles(&conn);
+        let mut ids = all_templates(&conn)?;
+        ids.sort();
+
+        Ok(ids.into_iter().map(|id| PageId {
+            title: titles.get(&id).cloned().unwrap_or_default(),
+            id,
+        }).collect::<Vec<_>>())
+    }
+
+    // Blocks containing every word of the query, in the pages the browser
+    // may open with the share link `share`, if it has one.
+    field search(&executor, query: String, share: Option<String>) -> FieldResult<Vec<SearchHit>> {
+        let share = share.as_ref().map(|x| x.as_str());
+        executor.context().refuse_banned(share)?;
+        let conn = executor.context().db_pool.get().unwrap();
+
+        Ok(search(&query).into_iter().filter(|hit| {
+            client_permission(&conn, share, &hit.page_id)
+                .ok()
+                .and_then(|permission| permission)
+                .is_some()
+        }).collect::<Vec<_>>())
+    }
+
+    // Documents by id, a page at a time. Needs EDIT_GRAPHQL_API.
+    field documents(&executor, first: Option<i32>, after: Option<String>) -> FieldResult<DocumentConnection> {
+        reporting_enabled()?;
+        let conn = executor.context().db_pool.get().unwrap();
+
+        let mut posts: Vec<(String, String)> = all_post_titles(&conn).into_iter().collect();
+        posts.sort();
+        let documents = posts.into_iter().map(|(id, title)| Document {
+            id,
+            title,
+        }).collect::<Vec<_>>();
+
+        let (nodes, page_info, total_count) = paginate(documents, first, after)?;
+        Ok(DocumentConnection { nodes, page_info, total_count })
+    }
+
+    // A document, with its history and comments. Needs EDIT_GRAPHQL_API.
+    field document(&executor, id: String) -> FieldResult<Option<Document>> {
+        reporting_enabled()?;
+        let conn = executor.context().db_pool.get().unwrap();
+
+        Ok(get_single_page_raw(&conn, &id).map(|post| Document {
+            id: post.id,
+            title: post.title,
+        }))
+    }
 
-        Ok(post_ids.into_iter().map(|x| PageId {
-            id: x.to_string()
+    // Mentions of a user they haven't marked as seen. Only for requests
+    // from a browser connected as the user.
+    field mentions(&executor, user: String) -> FieldResult<Vec<MentionNotice>> {
+        executor.context().authorize_user(&user)?;
+        let conn = executor.context().db_pool.get().unwrap();
+
+        Ok(unseen_mentions(&conn, &user)?.into_iter().map(|mention| MentionNotice {
+            id: mention.id,
+            page_id: mention.page_id,
+            anchor: mention.anchor,
+            author: mention.author,
+            created: mention.created as f64,
         }).collect::<Vec<_>>())
     }
 });
@@ -133,6 +319,67 @@ graphql_object!(Mutations: Ctx |&self| {
         }).unwrap())
     }
 
+    field setTemplate(
+        &executor,
+        id: String,
+        template: bool,
+    ) -> FieldResult<bool> {
+        let conn = executor.context().db_pool.get().unwrap();
+
+        if template && get_single_page_raw(&conn, &id).is_none() {
+            return Err(FieldError::new(
+                "No such page",
+                juniper::Value::null(),
+            ));
+        }
+        set_template(&conn, &id, template)?;
+
+        Ok(template)
+    }
+
+    // Create the page `id` from a copy of a template, with its variables
+    // filled in. Existing pages aren't overwritten.
+    field createPageFromTemplate(
+        &executor,
+        id: String,
+        template: String,
+    ) -> FieldResult<Page> {
+        let conn = executor.context().db_pool.get().unwrap();
+
+        if get_single_page_raw(&conn, &id).is_some() {
+            return Err(FieldError::new(
+                "Page already exists",
+                juniper::Value::null(),
+            ));
+        }
+        let source = match get_single_page(&conn, &template) {
+            Some(doc) => doc,
+            None => {
+                return Err(FieldError::new(
+                    "No such template",
+                    juniper::Value::null(),
+                ));
+            }
+        };
+        if !is_template(&conn, &template)? {
+            return Err(FieldError::new(
+                "Page is not a template",
+                juniper::Value::null(),
+            ));
+        }
+
+        let doc = Doc(substitute_variables(&source.0, &template_variables(&id)));
+        create_page(&conn, &id, &doc);
+
+        let _ = executor.context().tx_master.send(ClientNotify(id.clone(), ClientUpdate::Overwrite {
+            doc,
+        }));
+
+        Ok(get_single_page_raw(&conn, &id).map(|x| Page {
+            doc: x.body
+        }).unwrap())
+    }
+
     field getOrCreatePage(
         &executor,
         id: String,
@@ -157,6 +404,19 @@ graphql_object!(Mutations: Ctx |&self| {
             doc
         })
     }
+
+    // Mark a user's mentions as seen, returning how many were. Only for
+    // requests from a browser connected as the user.
+    field markMentionsSeen(
+        &executor,
+        user: String,
+        ids: Vec<i32>,
+    ) -> FieldResult<i32> {
+        executor.context().authorize_user(&user)?;
+        let conn = executor.context().db_pool.get().unwrap();
+
+        Ok(mark_mentions_seen(&conn, &user, &ids)? as i32)
+    }
 });
 
 // Arbitrary context data.
@@ -164,6 +424,33 @@ graphql_object!(Mutations: Ctx |&self| {
 struct Ctx {
     db_pool: r2d2::Pool<ConnectionManager<SqliteConnection>>,
     tx_master: CCSender<ClientNotify>,
+    registry: PageRegistry,
+    bans: BanList,
+    // Session cookie of the browser making the request, if it sent one.
+    session: Option<String>,
+}
+
+impl Ctx {
+    fn authorize_user(&self, user_id: &str) -> FieldResult<()> {
+        match self.session {
+            Some(ref session) if session_is_user(&self.registry, session, user_id) => Ok(()),
+            _ => Err(FieldError::new(
+                format!("Not connected as {:?}", user_id),
+                juniper::Value::null(),
+            )),
+        }
+    }
+
+    fn refuse_banned(&self, share: Option<&str>) -> FieldResult<()> {
+        let session = self.session.as_ref().map(|x| x.as_str());
+        if is_banned(&self.bans, &[session, share]) {
+            return Err(FieldError::new(
+                "You were banned from this server.",
+                juniper::Value::null(),
+            ));
+        }
+        Ok(())
+    }
 }
 
 // A root schema consists of a query and a mutation.
@@ -173,13 +460,26 @@ type Schema = juniper::RootNode<'static, Query, Mutations>;
 pub fn sync_graphql_server(
     db_pool: r2d2::Pool<ConnectionManager<SqliteConnection>>,
     tx_master: CCSender<ClientNotify>,
+    registry: PageRegistry,
+    bans: BanList,
 ) {
     // Create a context object.
-    let ctx = Ctx { db_pool, tx_master };
+    let ctx = Ctx {
+        db_pool,
+        tx_master,
+        registry: registry.clone(),
+        bans: bans.clone(),
+        session: None,
+    };
 
     eprintln!("Graphql served on http://0.0.0.0:8003");
     rouille::start_server("0.0.0.0:8003", move |request| {
-        let ctx = ctx.clone();
+        let mut ctx = ctx.clone();
+        ctx.session = request.header("Cookie").and_then(session_id);
+
+        if let Some(response) = admin_request(request, &registry, &bans, &ctx.tx_master, &ctx.db_pool) {
+            return response;
+        }
 
         router!(request,
             (OPTIONS) (/graphql/) => {
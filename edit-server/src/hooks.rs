@@ -0,0 +1,239 @@
+//! Hooks which transform pages as they're edited, to enforce policies like
+//! filtering words, rewriting links, or expanding macros without changing
+//! the sync server. A hook sees each operation sync accepts, and each
+//! document as it's saved, and can answer with an operation of its own. Its
+//! operations are committed like a client's, under the client ID
+//! "$hook-{name}", and sent to every client.
+//!
+//! Operations made by hooks aren't passed to hooks again, so a hook can't
+//! set off another, or itself, without end. Sync tracks which operations
+//! those are itself, rather than going by their client IDs.
+
+use extern::{
+    edit_common::blocks::map_top_level,
+    oatie::{
+        diff::diff,
+        doc::*,
+    },
+    std::collections::HashSet,
+    std::env,
+    std::sync::{
+        Arc,
+        RwLock,
+    },
+};
+
+pub const HOOK_CLIENT_PREFIX: &str = "$hook-";
+
+pub trait ContentHook: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// An operation to follow one sync accepted, given the document after
+    /// it. The operation applies to that document.
+    fn on_commit(&self, _page_id: &str, _doc: &Doc, _op: &Op) -> Option<Op> {
+        None
+    }
+
+    /// An operation to apply to a document before it's saved.
+    fn on_save(&self, _page_id: &str, _doc: &Doc) -> Option<Op> {
+        None
+    }
+}
+
+lazy_static! {
+    static ref CONTENT_HOOKS: RwLock<Vec<Arc<ContentHook>>> = RwLock::new(vec![]);
+}
+
+/// Run a hook on every page from now on, after those already registered.
+pub fn register_content_hook(hook: Arc<ContentHook>) {
+    eprintln!("(!) content hook {:?} registered", hook.name());
+    CONTENT_HOOKS.write().unwrap().push(hook);
+}
+
+pub fn content_hooks() -> Vec<Arc<ContentHook>> {
+    CONTENT_HOOKS.read().unwrap().clone()
+}
+
+/// Register the hooks which come with the server and are configured.
+pub fn register_default_hooks() {
+    if let Some(filter) = WordFilter::from_env() {
+        register_content_hook(Arc::new(filter));
+    }
+}
+
+pub const FILTERED_WORDS_VAR: &str = "EDIT_FILTERED_WORDS";
+
+/// Replaces each of a list of words with asterisks wherever it's typed,
+/// ignoring case. Configured as EDIT_FILTERED_WORDS=word,word.
+pub struct WordFilter {
+    words: HashSet<String>,
+}
+
+impl WordFilter {
+    pub fn new(words: &[&str]) -> WordFilter {
+        WordFilter {
+            words: words.iter().map(|word| word.to_lowercase()).collect(),
+        }
+    }
+
+    pub fn from_env() -> Option<WordFilter> {
+        let words = env::var(FILTERED_WORDS_VAR).ok()?;
+        let words = words
+            .split(',')
+            .map(|word| word.trim())
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>();
+        if words.is_empty() {
+            None
+        } else {
+            Some(WordFilter::new(&words))
+        }
+    }
+
+    /// `text` with each filtered word replaced, or None if it has none.
+    /// Unless `trailing` is set, a word which ends the text is left alone,
+    /// as it may be the start of a longer word still being typed.
+    pub fn filter_text(&self, text: &str, trailing: bool) -> Option<String> {
+        let pieces = split_words(text);
+        let mut filtered = String::with_capacity(text.len());
+        let mut changed = false;
+        for (i, piece) in pieces.iter().enumerate() {
+            let complete = trailing || i + 1 < pieces.len();
+            if complete && self.words.contains(&piece.to_lowercase()) {
+                filtered.extend(piece.chars().map(|_| '*'));
+                changed = true;
+            } else {
+                filtered.push_str(piece);
+            }
+        }
+        if changed {
+            Some(filtered)
+        } else {
+            None
+        }
+    }
+
+    fn filter_span(&self, span: &[DocElement], trailing: bool) -> DocSpan {
+        let mut filtered = vec![];
+        let mut i = 0;
+        while i < span.len() {
+            if let DocGroup(ref attrs, ref inner) = span[i] {
+                filtered.push(DocGroup(attrs.clone(), self.filter_span(inner, trailing)));
+                i += 1;
+                continue;
+            }
+
+            // Filter neighbouring runs of text together, so a word styled
+            // in parts is still found.
+            let start = i;
+            while let Some(DocChars(_)) = span.get(i) {
+                i += 1;
+            }
+            filtered.extend(self.filter_chars(&span[start..i], trailing));
+        }
+        filtered
+    }
+
+    fn filter_chars(&self, runs: &[DocElement], trailing: bool) -> Vec<DocElement> {
+        let text = runs
+            .iter()
+            .map(|elem| match *elem {
+                DocChars(ref text) => text.as_str(),
+                DocGroup(..) => "",
+            })
+            .collect::<String>();
+        let filtered = match self.filter_text(&text, trailing) {
+            Some(filtered) => filtered,
+            None => return runs.to_vec(),
+        };
+
+        // Filtering keeps the number of characters, so each run takes as
+        // many of them as it had.
+        let mut chars = filtered.chars();
+        runs.iter()
+            .map(|elem| match *elem {
+                DocChars(ref text) => {
+                    let filtered = chars.by_ref().take(text.char_len()).collect::<String>();
+                    DocChars(match text.styles() {
+                        Some(styles) => DocString::from_string_styled(filtered, (*styles).clone()),
+                        None => DocString::from_string(filtered),
+                    })
+                }
+                DocGroup(..) => elem.clone(),
+            })
+            .collect()
+    }
+}
+
+// The operation to make `doc` into `filtered`, if they differ.
+fn filter_op(doc: &Doc, filtered: Doc) -> Option<Op> {
+    if filtered == *doc {
+        None
+    } else {
+        Some(diff(doc, &filtered))
+    }
+}
+
+impl ContentHook for WordFilter {
+    fn name(&self) -> &str {
+        "filter"
+    }
+
+    // Only the top-level elements the operation touched are filtered, and
+    // only their completed words, so a word isn't censored halfway through
+    // being typed.
+    fn on_commit(&self, _page_id: &str, doc: &Doc, op: &Op) -> Option<Op> {
+        // Enough entries to cover the elements before the operation: those
+        // its deletion walks over, and at most every element after it.
+        let walked = op
+            .0
+            .iter()
+            .map(|elem| match *elem {
+                DelSkip(n) => n,
+                _ => 1,
+            })
+            .sum::<usize>();
+        let touched = match map_top_level(&vec![Some(()); doc.0.len() + walked], op) {
+            Some(entries) => entries,
+            // The operation deleted or joined elements; look at all of them.
+            None => return filter_op(doc, Doc(self.filter_span(&doc.0, false))),
+        };
+
+        let filtered = Doc(doc
+            .0
+            .iter()
+            .zip(touched)
+            .map(|(elem, entry)| match entry {
+                Some(()) => elem.clone(),
+                None => self.filter_span(&[elem.clone()], false).remove(0),
+            })
+            .collect());
+        filter_op(doc, filtered)
+    }
+
+    // Words ending a block are complete by the time the page is saved.
+    fn on_save(&self, _page_id: &str, doc: &Doc) -> Option<Op> {
+        filter_op(doc, Doc(self.filter_span(&doc.0, true)))
+    }
+}
+
+/// Split text into runs of word characters and runs of anything else.
+pub fn split_words(text: &str) -> Vec<&str> {
+    let mut pieces = vec![];
+    let mut start = 0;
+    let mut in_word = None;
+    for (i, c) in text.char_indices() {
+        let is_word = c.is_alphanumeric() || c == '\'';
+        if in_word != Some(is_word) {
+            if i > start {
+                pieces.push(&text[start..i]);
+            }
+            start = i;
+            in_word = Some(is_word);
+        }
+    }
+    if start < text.len() {
+        pieces.push(&text[start..]);
+    }
+    pieces
+}
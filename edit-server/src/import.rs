@@ -0,0 +1,212 @@
+//! Imports a set of markdown files as pages, as exported by the `export`
+//! module or from any folder of notes. Relative links between the files
+//! become links between the pages they were imported as.
+
+use crate::db::*;
+use crate::search::search_index_page;
+use crate::sync::valid_page_id;
+
+use diesel::sqlite::SqliteConnection;
+use edit_common::link::Link;
+use edit_common::markdown::markdown_to_doc;
+use edit_common::title::doc_title;
+use failure::Error;
+use oatie::doc::*;
+use oatie::validate::validate_doc;
+use serde_json;
+use std::collections::HashMap;
+use std::io::{
+    Cursor,
+    Read,
+};
+use zip::ZipArchive;
+
+const PAGE_ID_LEN: usize = 100;
+
+/// Split `key: value` frontmatter from the start of a markdown file. Values
+/// can be JSON strings or bare text.
+pub fn split_frontmatter(input: &str) -> (HashMap<String, String>, &str) {
+    let mut fields = HashMap::new();
+    if !input.starts_with("---\n") {
+        return (fields, input);
+    }
+    let end = match input[4..].find("\n---") {
+        Some(end) => 4 + end,
+        None => return (fields, input),
+    };
+    for line in input[4..end].lines() {
+        if let Some(colon) = line.find(':') {
+            let key = line[..colon].trim().to_string();
+            let value = line[colon + 1..].trim();
+            let value = serde_json::from_str::<String>(value).unwrap_or_else(|_| value.to_string());
+            fields.insert(key, value);
+        }
+    }
+    let body = &input[end + 4..];
+    let body = body.find('\n').map(|i| &body[i + 1..]).unwrap_or("");
+    (fields, body)
+}
+
+// A page id from a file's path, like "Notes/Meeting 1.md" to
+// "notes-meeting-1".
+fn page_id_from_path(path: &str) -> String {
+    let stem = path.trim_right_matches(".md");
+    let id = stem
+        .to_lowercase()
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    if id.is_empty() {
+        "page".to_string()
+    } else {
+        id.chars().take(PAGE_ID_LEN).collect()
+    }
+}
+
+// Resolve a relative link against the directory of the file it's in.
+fn resolve_path(base: &str, link: &str) -> String {
+    let mut parts = base.split('/').collect::<Vec<_>>();
+    parts.pop();
+    for part in link.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+fn is_relative_link(link: &str) -> bool {
+    !(link.contains("://") || link.starts_with('/') || link.starts_with('#')
+        || link.starts_with("mailto:"))
+}
+
+/// Point relative links to imported files at the pages they became, given
+/// the path of the file the links are in and the page of each file.
+pub fn rewrite_links(span: &DocSpan, path: &str, pages: &HashMap<String, String>) -> DocSpan {
+    span.iter()
+        .map(|elem| match elem {
+            DocGroup(attrs, inner) => DocGroup(attrs.clone(), rewrite_links(inner, path, pages)),
+            DocChars(text) => {
+                let mut styles = match text.styles() {
+                    Some(styles) => (*styles).clone(),
+                    None => return elem.clone(),
+                };
+                // Links with a title or new tab flag are JSON, which keeps
+                // those as the URL is rewritten.
+                let mut link = match styles.get(&Style::Link) {
+                    Some(Some(value)) => Link::from_style_value(value),
+                    _ => return elem.clone(),
+                };
+                let target = if is_relative_link(&link.href) {
+                    let (file, fragment) = match link.href.find('#') {
+                        Some(i) => (&link.href[..i], &link.href[i..]),
+                        None => (link.href.as_str(), ""),
+                    };
+                    let resolved = resolve_path(path, file);
+                    pages
+                        .get(&resolved)
+                        .or_else(|| pages.get(&format!("{}.md", resolved)))
+                        .map(|id| format!("/{}{}", id, fragment))
+                } else {
+                    None
+                };
+                match target {
+                    Some(target) => {
+                        link.href = target;
+                        styles.insert(Style::Link, Some(link.to_style_value()));
+                        DocChars(DocString::from_str_styled(text.as_str(), styles))
+                    }
+                    None => elem.clone(),
+                }
+            }
+        })
+        .collect()
+}
+
+/// Create a page for each markdown file, given its path and contents. Ids
+/// come from the file's frontmatter or its path, and pages without a heading
+/// get one from the frontmatter title or filename. Existing pages are only
+/// replaced with `overwrite`. Returns the path and id of each imported page.
+pub fn import_markdown(
+    db: &SqliteConnection,
+    files: &[(String, String)],
+    overwrite: bool,
+) -> Result<Vec<(String, String)>, Error> {
+    // Pick every page's id first, so links can point at pages not yet
+    // imported.
+    let mut pages = HashMap::new();
+    let mut taken = vec![];
+    let mut parsed = vec![];
+    for (path, contents) in files {
+        let (fields, body) = split_frontmatter(contents);
+        let base = fields
+            .get("id")
+            .filter(|id| valid_page_id(id))
+            .cloned()
+            .unwrap_or_else(|| page_id_from_path(path));
+        let mut id = base.clone();
+        let mut n = 2;
+        while taken.contains(&id) || (!overwrite && get_single_page_raw(db, &id).is_some()) {
+            id = format!("{}-{}", base, n);
+            n += 1;
+        }
+        taken.push(id.clone());
+        pages.insert(path.clone(), id.clone());
+        parsed.push((path, id, fields, body));
+    }
+
+    let mut imported = vec![];
+    for (path, id, fields, body) in parsed {
+        let mut span = rewrite_links(&markdown_to_doc(body)?, path, &pages);
+        if doc_title(&span).is_none() {
+            let filename = path.rsplit('/').next().unwrap_or("").trim_right_matches(".md");
+            let title = fields.get("title").map(|x| x.as_str()).unwrap_or(filename);
+            if !title.is_empty() {
+                let text = DocString::from_str_styled(title, style_map! { Style::Normie => None });
+                span.insert(
+                    0,
+                    DocGroup(hashmap! { "tag".into() => "h1".into() }, vec![DocChars(text)]),
+                );
+            }
+        }
+
+        let doc = Doc(span);
+        if let Err(err) = validate_doc(&doc) {
+            eprintln!("(!) skipping {:?}, which imported as an invalid document: {:?}", path, err);
+            continue;
+        }
+        create_page(db, &id, &doc);
+        search_index_page(&id, &doc);
+        if fields.get("template").map(|x| x == "true").unwrap_or(false) {
+            set_template(db, &id, true)?;
+        }
+        imported.push((path.clone(), id));
+    }
+    Ok(imported)
+}
+
+/// Import the markdown files inside a zip archive.
+pub fn import_zip(
+    db: &SqliteConnection,
+    data: &[u8],
+    overwrite: bool,
+) -> Result<Vec<(String, String)>, Error> {
+    let mut zip = ZipArchive::new(Cursor::new(data))?;
+    let mut files = vec![];
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i)?;
+        let path = file.name().to_string();
+        if !path.ends_with(".md") {
+            continue;
+        }
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        files.push((path, contents));
+    }
+    import_markdown(db, &files, overwrite)
+}
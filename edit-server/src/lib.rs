@@ -31,10 +31,12 @@ extern crate take_mut;
 extern crate taken;
 extern crate url;
 extern crate ws;
+extern crate zip;
 #[macro_use]
 extern crate rouille;
 #[macro_use]
 extern crate juniper;
+extern crate md5;
 extern crate r2d2;
 extern crate r2d2_diesel;
 
@@ -42,8 +44,28 @@ extern crate r2d2_diesel;
 pub mod log;
 
 // Macros can only be used after they are defined
+pub mod admin;
+pub mod assets;
 pub mod carets;
+pub mod comments;
 pub mod db;
+pub mod e2ee;
+pub mod events;
+pub mod export;
+pub mod fs_sync;
 pub mod graphql;
+pub mod hooks;
+pub mod import;
+pub mod limits;
+pub mod memory;
+pub mod mentions;
+pub mod mirror;
+pub mod pdf;
+pub mod peer;
+pub mod playback;
+pub mod retention;
+pub mod search;
+pub mod share;
 pub mod state;
 pub mod sync;
+pub mod templates;
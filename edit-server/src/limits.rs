@@ -0,0 +1,113 @@
+//! Size limits of documents. Operations that would grow a document past its
+//! limits, or that add too much at once, are refused with
+//! RefusalReason::TooLarge, and the client resynchronizes.
+//!
+//! Limits are set with EDIT_MAX_DOC_CHARS, EDIT_MAX_DOC_BLOCKS, and
+//! EDIT_MAX_OP_SIZE, where 0 means no limit.
+
+use extern::{
+    edit_common::commands::{
+        RefusalReason,
+        SizeLimit,
+    },
+    oatie::{
+        doc::*,
+        schema::{
+            RtfSchema,
+            RtfTrack,
+        },
+        Schema,
+    },
+    std::env,
+};
+
+const DEFAULT_MAX_DOC_CHARS: usize = 1_000_000;
+const DEFAULT_MAX_DOC_BLOCKS: usize = 50_000;
+const DEFAULT_MAX_OP_SIZE: usize = 100_000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DocLimits {
+    pub max_chars: Option<usize>,
+    pub max_blocks: Option<usize>,
+    // Most characters and groups a single operation may add.
+    pub max_op_size: Option<usize>,
+}
+
+fn env_limit(name: &str, default: usize) -> Option<usize> {
+    let limit = env::var(name)
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(default);
+    if limit == 0 {
+        None
+    } else {
+        Some(limit)
+    }
+}
+
+fn doc_size(span: &DocSpan, chars: &mut usize, blocks: &mut usize) {
+    for elem in span {
+        match elem {
+            DocChars(text) => *chars += text.char_len(),
+            DocGroup(attrs, inner) => {
+                if RtfSchema::track_type_from_attrs(attrs) == Some(RtfTrack::Blocks) {
+                    *blocks += 1;
+                }
+                doc_size(inner, chars, blocks);
+            }
+        }
+    }
+}
+
+fn add_size(span: &AddSpan) -> usize {
+    span.iter()
+        .map(|elem| match elem {
+            AddChars(text) => text.char_len(),
+            AddGroup(_, inner) => 1 + add_size(inner),
+            AddWithGroup(inner) => add_size(inner),
+            AddSkip(_) | AddStyles(..) => 0,
+        })
+        .sum()
+}
+
+impl DocLimits {
+    pub fn from_env() -> DocLimits {
+        DocLimits {
+            max_chars: env_limit("EDIT_MAX_DOC_CHARS", DEFAULT_MAX_DOC_CHARS),
+            max_blocks: env_limit("EDIT_MAX_DOC_BLOCKS", DEFAULT_MAX_DOC_BLOCKS),
+            max_op_size: env_limit("EDIT_MAX_OP_SIZE", DEFAULT_MAX_OP_SIZE),
+        }
+    }
+
+    /// Check how much an operation adds, before it's applied.
+    pub fn check_op(&self, op: &Op) -> Result<(), RefusalReason> {
+        if let Some(max) = self.max_op_size {
+            if add_size(&op.1) > max {
+                return Err(RefusalReason::TooLarge(SizeLimit::OpSize, max));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check a document an operation produced against the one it was
+    /// applied to. Documents already over a limit, as when it was lowered,
+    /// may still shrink.
+    pub fn check_growth(&self, before: &Doc, after: &Doc) -> Result<(), RefusalReason> {
+        let (mut chars_before, mut blocks_before) = (0, 0);
+        doc_size(&before.0, &mut chars_before, &mut blocks_before);
+        let (mut chars_after, mut blocks_after) = (0, 0);
+        doc_size(&after.0, &mut chars_after, &mut blocks_after);
+
+        if let Some(max) = self.max_chars {
+            if chars_after > max && chars_after > chars_before {
+                return Err(RefusalReason::TooLarge(SizeLimit::Chars, max));
+            }
+        }
+        if let Some(max) = self.max_blocks {
+            if blocks_after > max && blocks_after > blocks_before {
+                return Err(RefusalReason::TooLarge(SizeLimit::Blocks, max));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+//! Memory budget of the sync server. Each page's sync thread reports an
+//! estimate of the memory it holds. When their total is over the budget set
+//! in EDIT_MEMORY_BUDGET_MB, pages without clients or watchers are evicted,
+//! least recently active first. Evicted pages are saved, and load again from the
+//! database the next time they're opened.
+//!
+//! The registry is only a snapshot, so the page master has the last word:
+//! it skips pages that gained a client or were used after the check.
+
+use crate::{
+    admin::PageRegistry,
+    sync::{
+        ClientNotify,
+        ClientUpdate,
+    },
+};
+
+use extern::{
+    crossbeam_channel::Sender as CCSender,
+    std::{
+        env,
+        thread,
+        time::{
+            Duration,
+            Instant,
+        },
+    },
+};
+
+// Milliseconds between checks of the budget.
+const MEMORY_POLL_INTERVAL: u64 = 30_000;
+
+/// The memory budget in bytes, if there is one.
+pub fn memory_budget() -> Option<usize> {
+    env::var("EDIT_MEMORY_BUDGET_MB")
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|mb| *mb > 0)
+        .map(|mb| mb * 1024 * 1024)
+}
+
+/// Ask to evict idle pages until the pages in memory fit in the budget,
+/// returning the ids of the pages asked for.
+pub fn evict_over_budget(
+    registry: &PageRegistry,
+    tx_master: &CCSender<ClientNotify>,
+    budget: usize,
+) -> Vec<String> {
+    let checked = Instant::now();
+    let mut idle = vec![];
+    let mut total = 0;
+    for (page_id, status) in registry.lock().unwrap().iter() {
+        total += status.memory;
+        if status.clients.is_empty() && status.watchers == 0 {
+            idle.push((status.last_active, status.memory, page_id.clone()));
+        }
+    }
+    idle.sort();
+
+    let mut evicted = vec![];
+    for (_, memory, page_id) in idle {
+        if total <= budget {
+            break;
+        }
+        println!("(%) evicting idle page {:?} to stay within the memory budget", page_id);
+        let _ = tx_master.send(ClientNotify(page_id.clone(), ClientUpdate::EvictIdle { checked }));
+        total -= memory;
+        evicted.push(page_id);
+    }
+    evicted
+}
+
+pub fn spawn_memory_watcher(registry: PageRegistry, tx_master: CCSender<ClientNotify>) {
+    let budget = match memory_budget() {
+        Some(budget) => budget,
+        None => return,
+    };
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_millis(MEMORY_POLL_INTERVAL));
+        evict_over_budget(&registry, &tx_master, budget);
+    });
+}
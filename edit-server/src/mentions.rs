@@ -0,0 +1,79 @@
+//! Notifications of mentions. When a mention of a user is added to a page,
+//! it's stored for them until they've seen it, and pushed to any client
+//! they have open.
+
+use crate::{
+    admin::PageRegistry,
+    db::*,
+    playback::now_millis,
+    sync::ClientUpdate,
+};
+
+use extern::{
+    diesel::sqlite::SqliteConnection,
+    edit_common::mention::Mention,
+    failure::Error,
+    std::collections::BTreeSet,
+};
+
+fn from_row(row: MentionRow) -> Mention {
+    Mention {
+        id: row.rowid,
+        page_id: row.page_id,
+        anchor: row.anchor,
+        author: row.author,
+        created: row.created,
+    }
+}
+
+/// Store a notification for each new mention, and tell the clients of the
+/// users mentioned.
+pub fn notify_mentions(
+    db: &SqliteConnection,
+    registry: &PageRegistry,
+    page_id: &str,
+    author: &str,
+    added: &BTreeSet<(String, Option<String>)>,
+) -> Result<(), Error> {
+    let created = now_millis();
+    let mut users = BTreeSet::new();
+    for (user_id, anchor) in added {
+        create_mention(db, user_id, page_id, anchor.as_ref().map(|x| x.as_str()), author, created)?;
+        users.insert(user_id.clone());
+    }
+
+    // Pages the users have open send the news on to their clients.
+    for status in registry.lock().unwrap().values() {
+        for user_id in &users {
+            if status
+                .clients
+                .iter()
+                .any(|client| client.user_id.as_ref() == Some(user_id))
+            {
+                let _ = status.queue.send(ClientUpdate::Mentioned {
+                    user_id: user_id.clone(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether a browser session has a client connected as a user. Clients
+/// claim their user ids, so that's what vouches for a session's user.
+pub fn session_is_user(registry: &PageRegistry, session: &str, user_id: &str) -> bool {
+    registry.lock().unwrap().values().any(|status| {
+        status.clients.iter().any(|client| {
+            client.session.as_ref().map(|x| x.as_str()) == Some(session)
+                && client.user_id.as_ref().map(|x| x.as_str()) == Some(user_id)
+        })
+    })
+}
+
+/// Mentions of a user they haven't seen yet, oldest first.
+pub fn unseen_mentions(db: &SqliteConnection, user_id: &str) -> Result<Vec<Mention>, Error> {
+    Ok(select_unseen_mentions(db, user_id)?
+        .into_iter()
+        .map(from_row)
+        .collect())
+}
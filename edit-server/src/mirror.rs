@@ -0,0 +1,175 @@
+//! Mirrors pages into a git repository as markdown files, for an audit trail
+//! that can be browsed and diffed outside of the server. Set EDIT_GIT_MIRROR
+//! to the path of a git checkout to turn it on. Changes to a page are
+//! committed once it's gone EDIT_GIT_MIRROR_DELAY seconds (default 30)
+//! without another, or at the latest ten delays after the first.
+
+use crate::export::page_frontmatter;
+
+use extern::{
+    edit_common::markdown::doc_to_markdown,
+    edit_common::title::doc_title,
+    failure::Error,
+    oatie::doc::*,
+    std::{
+        collections::HashMap,
+        env,
+        fs,
+        path::PathBuf,
+        process::Command,
+        sync::mpsc::{
+            channel,
+            RecvTimeoutError,
+            Sender,
+        },
+        sync::Mutex,
+        thread,
+        time::{
+            Duration,
+            Instant,
+        },
+    },
+};
+
+const DEFAULT_MIRROR_DELAY: u64 = 30;
+
+// A page edited without pause is still committed after this many delays.
+const MAX_DELAY_FACTOR: u32 = 10;
+
+pub struct GitMirror {
+    sender: Mutex<Sender<(String, Doc)>>,
+}
+
+fn git(repo: &PathBuf, args: &[&str]) -> Result<(), Error> {
+    let status = Command::new("git").current_dir(repo).args(args).status()?;
+    if !status.success() {
+        bail!("git {} failed with {}", args.join(" "), status);
+    }
+    Ok(())
+}
+
+// Write each page's markdown into the repository and commit them together.
+fn commit_pages(repo: &PathBuf, pages: Vec<(String, Doc)>) -> Result<(), Error> {
+    let mut files = vec![];
+    for (page_id, doc) in &pages {
+        let title = doc_title(&doc.0).unwrap_or_default();
+        let mut contents = page_frontmatter(page_id, &title, false);
+        contents.push_str(&doc_to_markdown(&doc.0)?);
+
+        let filename = format!("{}.md", page_id);
+        fs::write(repo.join(&filename), contents)?;
+        files.push(filename);
+    }
+
+    let mut add = vec!["add", "--"];
+    add.extend(files.iter().map(|x| x.as_str()));
+    git(repo, &add)?;
+
+    // Nothing to commit if the pages are unchanged since their last commit.
+    if git(repo, &["diff", "--cached", "--quiet"]).is_ok() {
+        return Ok(());
+    }
+    let ids = pages.iter().map(|(id, _)| id.as_str()).collect::<Vec<_>>();
+    git(repo, &["commit", "--quiet", "-m", &format!("Update {}", ids.join(", "))])
+}
+
+impl GitMirror {
+    fn spawn(repo: PathBuf, delay: Duration) -> GitMirror {
+        let (tx, rx) = channel::<(String, Doc)>();
+        let max_delay = delay * MAX_DELAY_FACTOR;
+        thread::spawn(move || {
+            // Latest unmirrored version of each page, when it last changed,
+            // and when it first changed since its last commit.
+            let mut pending: HashMap<String, (Doc, Instant, Instant)> = HashMap::new();
+            loop {
+                let now = Instant::now();
+                let timeout = pending
+                    .values()
+                    .map(|(_, changed, first)| {
+                        let due = ::std::cmp::min(*changed + delay, *first + max_delay);
+                        if due > now {
+                            due - now
+                        } else {
+                            Duration::from_secs(0)
+                        }
+                    })
+                    .min()
+                    .unwrap_or(Duration::from_secs(60 * 60));
+
+                let disconnected = match rx.recv_timeout(timeout) {
+                    Ok((page_id, doc)) => {
+                        let now = Instant::now();
+                        let first = pending
+                            .get(&page_id)
+                            .map(|(_, _, first)| *first)
+                            .unwrap_or(now);
+                        pending.insert(page_id, (doc, now, first));
+                        false
+                    }
+                    Err(RecvTimeoutError::Timeout) => false,
+                    Err(RecvTimeoutError::Disconnected) => true,
+                };
+
+                // Pages are committed once they've been quiet for the delay, or
+                // once they've waited too long, so a page saved constantly
+                // doesn't keep putting off its commit.
+                let due = pending
+                    .iter()
+                    .filter(|(_, (_, changed, first))| {
+                        disconnected || changed.elapsed() >= delay || first.elapsed() >= max_delay
+                    })
+                    .map(|(page_id, _)| page_id.clone())
+                    .collect::<Vec<_>>();
+                let pages = due
+                    .into_iter()
+                    .filter_map(|page_id| {
+                        let (doc, _, _) = pending.remove(&page_id)?;
+                        Some((page_id, doc))
+                    })
+                    .collect::<Vec<_>>();
+                if !pages.is_empty() {
+                    if let Err(err) = commit_pages(&repo, pages) {
+                        eprintln!("(!) could not mirror pages to git: {:?}", err);
+                    }
+                }
+
+                if disconnected {
+                    break;
+                }
+            }
+        });
+
+        GitMirror {
+            sender: Mutex::new(tx),
+        }
+    }
+
+    fn from_env() -> Option<GitMirror> {
+        let repo = PathBuf::from(env::var("EDIT_GIT_MIRROR").ok()?);
+        let delay = env::var("EDIT_GIT_MIRROR_DELAY")
+            .ok()
+            .and_then(|x| x.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_MIRROR_DELAY);
+        eprintln!("(!) mirroring pages to git repository {:?}", repo);
+        Some(GitMirror::spawn(repo, Duration::from_secs(delay)))
+    }
+
+    pub fn mirror(&self, page_id: &str, doc: &Doc) {
+        let _ = self
+            .sender
+            .lock()
+            .unwrap()
+            .send((page_id.to_string(), doc.clone()));
+    }
+}
+
+lazy_static! {
+    pub static ref GIT_MIRROR: Option<GitMirror> = GitMirror::from_env();
+}
+
+/// Queue a saved page to be committed to the git mirror, if there is one.
+pub fn mirror_page(page_id: &str, doc: &Doc) {
+    if let Some(ref mirror) = *GIT_MIRROR {
+        mirror.mirror(page_id, doc);
+    }
+}
@@ -0,0 +1,195 @@
+//! Exports pages as PDF, set in the layout the editor paginates them in, so
+//! pages break where its page indicators showed. Text is set in the fonts
+//! every PDF reader has, so none are embedded, and characters they can't
+//! show are replaced with question marks.
+
+use edit_common::pagination::*;
+use oatie::doc::Doc;
+use std::io::Write;
+
+// A US Letter page and its margins, in points.
+const PAGE_WIDTH: f64 = 612.0;
+const PAGE_HEIGHT: f64 = 792.0;
+const MARGIN: f64 = 72.0;
+
+// Average width of a character, as a part of the font size.
+const HELVETICA_WIDTH: f64 = 0.5;
+const COURIER_WIDTH: f64 = 0.6;
+
+// Fonts, by their resource names.
+const FONTS: &[(&str, &str)] = &[
+    ("F1", "Helvetica"),
+    ("F2", "Helvetica-Bold"),
+    ("F3", "Courier"),
+];
+
+// A character in the WinAnsi encoding of the standard fonts.
+fn win_ansi(c: char) -> u8 {
+    match c {
+        ' '..='~' | '\u{A0}'..='\u{FF}' => c as u8,
+        '\u{20AC}' => 0x80,
+        '\u{2026}' => 0x85,
+        '\u{2018}' => 0x91,
+        '\u{2019}' => 0x92,
+        '\u{201C}' => 0x93,
+        '\u{201D}' => 0x94,
+        '\u{2022}' => 0x95,
+        '\u{2013}' => 0x96,
+        '\u{2014}' => 0x97,
+        _ => b'?',
+    }
+}
+
+// A PDF string of `text`, with its parentheses.
+fn pdf_string(text: &str) -> Vec<u8> {
+    let mut out = vec![b'('];
+    for byte in text.chars().map(win_ansi) {
+        if byte == b'(' || byte == b')' || byte == b'\\' {
+            out.push(b'\\');
+        }
+        out.push(byte);
+    }
+    out.push(b')');
+    out
+}
+
+// The content stream of each page, drawn from the blocks of `doc`.
+fn draw_pages(doc: &Doc, layout: &PageLayout) -> Vec<Vec<u8>> {
+    let blocks = layout_blocks(doc, layout);
+    let breaks = paginate(doc, layout);
+
+    let line = (PAGE_HEIGHT - 2.0 * MARGIN) / layout.lines_per_page.max(1) as f64;
+    let char_width = (PAGE_WIDTH - 2.0 * MARGIN) / layout.chars_per_line.max(1) as f64;
+    let size = (char_width / HELVETICA_WIDTH).min(line * 0.8);
+    let top = PAGE_HEIGHT - MARGIN;
+
+    let mut pages = vec![vec![]];
+    let mut y = top;
+    for (i, block) in blocks.iter().enumerate() {
+        if breaks.iter().any(|x| x.block == i) {
+            pages.push(vec![]);
+            y = top;
+        }
+
+        let scale = block.scale as f64 / 100.0;
+        let (font, font_size) = match block.tag.as_str() {
+            "pre" => ("F3", size.min(char_width / COURIER_WIDTH)),
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => ("F2", size * scale),
+            _ => ("F1", size),
+        };
+        let x = MARGIN + (block.indent * INDENT_CHARS) as f64 * char_width;
+        for text in &block.lines {
+            // Blocks taller than a page run on into the next.
+            if y < top && y - line * scale < MARGIN - 0.01 {
+                pages.push(vec![]);
+                y = top;
+            }
+            y -= line * scale;
+
+            let page = pages.last_mut().unwrap();
+            if block.tag == "hr" {
+                let rule = y + line / 2.0;
+                let right = PAGE_WIDTH - MARGIN;
+                let _ = writeln!(page, "{:.2} {:.2} m {:.2} {:.2} l S", x, rule, right, rule);
+            } else if !text.is_empty() {
+                let baseline = y + line * scale * 0.25;
+                let _ = write!(page, "BT /{} {:.2} Tf ", font, font_size);
+                let _ = write!(page, "{:.2} {:.2} Td ", x, baseline);
+                page.extend(pdf_string(text));
+                page.extend(b" Tj ET\n");
+            }
+        }
+        y -= line * BLOCK_SPACING as f64;
+    }
+    pages
+}
+
+// Objects of a PDF file, numbered from 1 in the order they're added.
+struct PdfWriter {
+    out: Vec<u8>,
+    offsets: Vec<usize>,
+}
+
+impl PdfWriter {
+    fn new() -> PdfWriter {
+        PdfWriter {
+            out: b"%PDF-1.4\n".to_vec(),
+            offsets: vec![],
+        }
+    }
+
+    fn object(&mut self, body: &[u8]) {
+        self.offsets.push(self.out.len());
+        let _ = writeln!(self.out, "{} 0 obj", self.offsets.len());
+        self.out.extend(body);
+        self.out.extend(b"\nendobj\n");
+    }
+
+    // The file, with the cross-reference table of its objects.
+    fn finish(mut self) -> Vec<u8> {
+        let xref = self.out.len();
+        let _ = write!(self.out, "xref\n0 {}\n0000000000 65535 f \n", self.offsets.len() + 1);
+        for offset in &self.offsets {
+            let _ = write!(self.out, "{:010} 00000 n \n", offset);
+        }
+        let _ = write!(
+            self.out,
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            self.offsets.len() + 1,
+            xref
+        );
+        self.out
+    }
+}
+
+/// `doc` as a PDF file, with its pages broken as in `layout`.
+pub fn doc_to_pdf(doc: &Doc, layout: &PageLayout) -> Vec<u8> {
+    let pages = draw_pages(doc, layout);
+
+    // The catalog and the page tree come first, then the fonts, then each
+    // page and its contents.
+    let first_page = 3 + FONTS.len();
+    let kids = (0..pages.len())
+        .map(|i| format!("{} 0 R", first_page + 2 * i))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let fonts = FONTS
+        .iter()
+        .enumerate()
+        .map(|(i, (name, _))| format!("/{} {} 0 R", name, 3 + i))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let mut pdf = PdfWriter::new();
+    pdf.object(b"<< /Type /Catalog /Pages 2 0 R >>");
+    pdf.object(format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids, pages.len()).as_bytes());
+    for (_, font) in FONTS {
+        pdf.object(
+            format!(
+                "<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>",
+                font
+            )
+            .as_bytes(),
+        );
+    }
+    for (i, content) in pages.into_iter().enumerate() {
+        pdf.object(
+            format!(
+                concat!(
+                    "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] ",
+                    "/Resources << /Font << {} >> >> /Contents {} 0 R >>"
+                ),
+                PAGE_WIDTH,
+                PAGE_HEIGHT,
+                fonts,
+                first_page + 2 * i + 1
+            )
+            .as_bytes(),
+        );
+        let mut stream = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+        stream.extend(content);
+        stream.extend(b"\nendstream");
+        pdf.object(&stream);
+    }
+    pdf.finish()
+}
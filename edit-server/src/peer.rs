@@ -0,0 +1,108 @@
+//! Relay for peer pages, which clients connect to at /$/ws/{page}/peer and
+//! edit as a CRDT (see `edit_common::crdt`). Peers apply each other's
+//! operations in whatever order they arrive, so the server doesn't order,
+//! transform, or refuse them; it only stands in for the network between
+//! peers. Operations are stored and relayed to every other client, and new
+//! clients replay them all.
+//!
+//! As with encrypted pages, nothing that needs to read the document works
+//! for these pages: search, titles, exports, and history.
+
+use crate::db::*;
+
+use extern::{
+    edit_common::commands::*,
+    edit_common::crdt::CrdtOp,
+    edit_common::simple_ws,
+    failure::Error,
+    serde_json,
+    std::{
+        collections::HashMap,
+        sync::Mutex,
+    },
+};
+
+struct PeerPage {
+    // Number of stored batches of operations.
+    count: usize,
+    clients: HashMap<String, simple_ws::Sender>,
+}
+
+lazy_static! {
+    static ref PEER_PAGES: Mutex<HashMap<String, PeerPage>> = Mutex::new(HashMap::new());
+}
+
+fn send_command(client: &simple_ws::Sender, command: &ClientCommand) -> Result<(), Error> {
+    let json = serde_json::to_string(command)?;
+    Ok(client.send(json)?)
+}
+
+/// Send a client every operation of the page, and relay it new ones.
+pub fn peer_connect(
+    db_pool: &DbPool,
+    page_id: &str,
+    client_id: &str,
+    out: simple_ws::Sender,
+) -> Result<(), Error> {
+    let conn = db_pool.get()?;
+    let mut pages = PEER_PAGES.lock().unwrap();
+
+    let rows = select_peer_ops(&conn, page_id)?;
+    let page = pages
+        .entry(page_id.to_string())
+        .or_insert_with(|| PeerPage {
+            count: rows.len(),
+            clients: HashMap::new(),
+        });
+
+    let mut ops = vec![];
+    for row in rows {
+        ops.extend(serde_json::from_str::<Vec<CrdtOp>>(&row.body)?);
+    }
+    send_command(&out, &ClientCommand::PeerInit(client_id.to_string(), ops))?;
+    page.clients.insert(client_id.to_string(), out);
+    Ok(())
+}
+
+/// Store and relay operations a client made.
+pub fn peer_commit(
+    db_pool: &DbPool,
+    page_id: &str,
+    client_id: &str,
+    ops: Vec<CrdtOp>,
+) -> Result<(), Error> {
+    if ops.is_empty() {
+        return Ok(());
+    }
+
+    let conn = db_pool.get().map_err(simple_ws::CloseError::unavailable)?;
+    let mut pages = PEER_PAGES.lock().unwrap();
+    let page = pages
+        .get_mut(page_id)
+        .ok_or_else(|| format_err!("peer page {:?} isn't loaded", page_id))?;
+
+    create_peer_op(&conn, page_id, page.count, client_id, &serde_json::to_string(&ops)?)?;
+    page.count += 1;
+
+    let command = ClientCommand::PeerOps(client_id.to_string(), ops);
+    for (id, client) in &page.clients {
+        if id != client_id {
+            let _ = send_command(client, &command);
+        }
+    }
+    Ok(())
+}
+
+pub fn peer_disconnect(page_id: &str, client_id: &str) {
+    let mut pages = PEER_PAGES.lock().unwrap();
+    let empty = match pages.get_mut(page_id) {
+        Some(page) => {
+            page.clients.remove(client_id);
+            page.clients.is_empty()
+        }
+        None => false,
+    };
+    if empty {
+        pages.remove(page_id);
+    }
+}
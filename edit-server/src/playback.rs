@@ -0,0 +1,214 @@
+//! Records each page's stream of committed operations, and plays a recording
+//! back to a client so a document can be watched as it was written.
+
+use crate::db::*;
+
+use extern::{
+    edit_common::{
+        commands::*,
+        identity::UserIdentity,
+        simple_ws,
+    },
+    failure::Error,
+    oatie::doc::*,
+    serde_json,
+    std::{
+        sync::atomic::{
+            AtomicBool,
+            Ordering,
+        },
+        sync::mpsc::{
+            channel,
+            Sender,
+        },
+        sync::{
+            Arc,
+            Mutex,
+        },
+        thread,
+        time::{
+            Duration,
+            SystemTime,
+            UNIX_EPOCH,
+        },
+    },
+};
+
+// Gaps between recorded operations are capped so that idle periods in a
+// recording don't stall playback.
+const PLAYBACK_MAX_DELAY: u64 = 2_000;
+const PLAYBACK_VERSION: usize = 100;
+const PLAYBACK_CLIENT_ID: &str = "$playback";
+
+// Characters of a commit's record read to learn its author, which leaves
+// out its operation.
+const COMMIT_HEAD_LEN: usize = 128;
+
+/// An entry in a page's recording.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum PageRecord {
+    // The sync thread started (or was overwritten) with this document.
+    Start(DocSpan),
+    // Author client id, committed operation.
+    Commit(String, Op),
+    // A client connected, as this user.
+    Join(String, UserIdentity),
+}
+
+pub fn now_millis() -> i64 {
+    let elapsed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::from_secs(0));
+    (elapsed.as_secs() * 1_000 + (elapsed.subsec_nanos() / 1_000_000) as u64) as i64
+}
+
+// Database, page id, time recorded, and serialized entry.
+type PendingRecord = (DbPool, String, i64, String);
+
+// Recordings are written on their own thread, in the order entries were
+// recorded, so that sync threads don't wait on the database for every
+// operation.
+fn spawn_recorder() -> Mutex<Sender<PendingRecord>> {
+    let (tx, rx) = channel::<PendingRecord>();
+    thread::spawn(move || {
+        while let Ok((db_pool, page_id, created, body)) = rx.recv() {
+            let written = db_pool
+                .get()
+                .map_err(Error::from)
+                .and_then(|conn| create_page_record(&conn, &page_id, created, &body));
+            if let Err(err) = written {
+                eprintln!("(!) could not record page {:?}: {:?}", page_id, err);
+            }
+        }
+    });
+    Mutex::new(tx)
+}
+
+lazy_static! {
+    static ref RECORDER: Mutex<Sender<PendingRecord>> = spawn_recorder();
+}
+
+/// Append an entry to a page's recording. The entry is timestamped now, and
+/// written in the background.
+pub fn record_page(db_pool: &DbPool, page_id: &str, record: &PageRecord) -> Result<(), Error> {
+    let body = ::ron::ser::to_string(record)?;
+    RECORDER
+        .lock()
+        .unwrap()
+        .send((db_pool.clone(), page_id.to_string(), now_millis(), body))
+        .map_err(|_| format_err!("the recorder thread stopped"))
+}
+
+/// Load a page's recording along with the time each entry was recorded.
+pub fn load_page_records(db_pool: &DbPool, page_id: &str) -> Result<Vec<(i64, PageRecord)>, Error> {
+    let conn = db_pool.get()?;
+    let mut records = vec![];
+    for row in select_page_records(&conn, page_id)? {
+        records.push((row.created, ::ron::de::from_str(&row.body)?));
+    }
+    Ok(records)
+}
+
+/// What a page's recording says of who edited it, without the documents and
+/// operations it recorded.
+#[derive(Debug, Clone)]
+pub enum HistoryRecord {
+    // A client connected, as this user.
+    Join(String, UserIdentity),
+    // A client committed an operation.
+    Commit(String),
+}
+
+/// Load a page's history along with the time each entry was recorded. Only
+/// the start of each commit is loaded, however large its operation.
+pub fn load_page_history(
+    db_pool: &DbPool,
+    page_id: &str,
+) -> Result<Vec<(i64, HistoryRecord)>, Error> {
+    let conn = db_pool.get()?;
+    let mut records = vec![];
+    for (rowid, created, body) in select_page_record_heads(&conn, page_id, "Join(", None)? {
+        if let PageRecord::Join(client_id, identity) = ::ron::de::from_str(&body)? {
+            records.push((rowid, created, HistoryRecord::Join(client_id, identity)));
+        }
+    }
+    let commits = select_page_record_heads(&conn, page_id, "Commit(", Some(COMMIT_HEAD_LEN))?;
+    for (rowid, created, head) in commits {
+        let client_id =
+            commit_client_id(&head).ok_or_else(|| format_err!("unreadable commit {:?}", rowid))?;
+        records.push((rowid, created, HistoryRecord::Commit(client_id)));
+    }
+    records.sort_by_key(|(rowid, _, _)| *rowid);
+    Ok(records
+        .into_iter()
+        .map(|(_, created, record)| (created, record))
+        .collect())
+}
+
+/// The author of a recorded commit, from the start of its record.
+pub fn commit_client_id(head: &str) -> Option<String> {
+    let prefix = "Commit(\"";
+    if !head.starts_with(prefix) {
+        return None;
+    }
+    let rest = &head[prefix.len()..];
+    let end = rest.find('"')?;
+    Some(rest[..end].to_string())
+}
+
+fn send_command(out: &simple_ws::Sender, command: &ClientCommand) -> Result<(), Error> {
+    let json = serde_json::to_string(command)?;
+    Ok(out.send(json)?)
+}
+
+/// Stream a page's recording to a client as ordinary Init and Update
+/// commands. `speed` scales the recorded delays between operations.
+pub fn spawn_playback(
+    db_pool: DbPool,
+    page_id: String,
+    out: simple_ws::Sender,
+    speed: f64,
+    alive: Arc<AtomicBool>,
+) {
+    thread::spawn(move || -> Result<(), Error> {
+        let records = load_page_records(&db_pool, &page_id)?;
+        eprintln!("(!) playing back {} records for {:?}", records.len(), page_id);
+
+        let mut version = PLAYBACK_VERSION;
+        let mut users = vec![];
+        let mut last_created = None;
+        for (created, record) in records {
+            // Wait out the (scaled) time between this entry and the last.
+            if let Some(last_created) = last_created {
+                let delay = ((created - last_created).max(0) as f64 / speed) as u64;
+                thread::sleep(Duration::from_millis(delay.min(PLAYBACK_MAX_DELAY)));
+            }
+            last_created = Some(created);
+
+            if !alive.load(Ordering::Relaxed) {
+                break;
+            }
+
+            match record {
+                PageRecord::Start(span) => {
+                    version = PLAYBACK_VERSION;
+                    send_command(
+                        &out,
+                        &ClientCommand::Init(PLAYBACK_CLIENT_ID.to_string(), span, version),
+                    )?;
+                }
+                PageRecord::Commit(client_id, op) => {
+                    version += 1;
+                    send_command(&out, &ClientCommand::Update(version, client_id, op))?;
+                }
+                // Playback shows everyone who ever joined the recording.
+                PageRecord::Join(client_id, identity) => {
+                    users.push((client_id, identity));
+                    send_command(&out, &ClientCommand::Users(users.clone()))?;
+                }
+            }
+        }
+
+        Ok(())
+    });
+}
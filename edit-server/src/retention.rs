@@ -0,0 +1,188 @@
+//! Retention of page recordings. Operations older than the retention policy
+//! are folded into a snapshot of the document as of the oldest operation
+//! kept, so playback still starts from the right document while storage
+//! stays bounded.
+//!
+//! Operations are kept if they're younger than EDIT_OPLOG_RETENTION_DAYS, or
+//! among the last EDIT_OPLOG_RETENTION_REVISIONS of their page. Without
+//! either, recordings are kept forever. Encrypted pages aren't recorded, and
+//! their operations are always kept, since the server can't read them.
+
+use crate::{
+    db::*,
+    playback::{
+        now_millis,
+        PageRecord,
+    },
+};
+
+use extern::{
+    diesel::sqlite::SqliteConnection,
+    failure::Error,
+    oatie::{
+        doc::*,
+        OT,
+    },
+    std::{
+        collections::HashSet,
+        env,
+        thread,
+        time::Duration,
+    },
+};
+
+// Milliseconds between collections in the background.
+const RETENTION_GC_INTERVAL: u64 = 60 * 60 * 1_000;
+
+const MILLIS_PER_DAY: i64 = 24 * 60 * 60 * 1_000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetentionPolicy {
+    // Operations younger than this are kept.
+    pub max_age_days: Option<u64>,
+    // The last this many operations of a page are kept.
+    pub min_revisions: Option<usize>,
+}
+
+impl RetentionPolicy {
+    /// The configured policy, or None if recordings are kept forever.
+    pub fn from_env() -> Option<RetentionPolicy> {
+        let policy = RetentionPolicy {
+            max_age_days: env::var("EDIT_OPLOG_RETENTION_DAYS")
+                .ok()
+                .and_then(|x| x.parse().ok()),
+            min_revisions: env::var("EDIT_OPLOG_RETENTION_REVISIONS")
+                .ok()
+                .and_then(|x| x.parse().ok()),
+        };
+        if policy.max_age_days.is_none() && policy.min_revisions.is_none() {
+            None
+        } else {
+            Some(policy)
+        }
+    }
+
+    fn keeps(&self, created: i64, revision: usize, revisions: usize, now: i64) -> bool {
+        let recent = self
+            .max_age_days
+            .map(|days| created >= now - days as i64 * MILLIS_PER_DAY)
+            .unwrap_or(false);
+        let latest = self
+            .min_revisions
+            .map(|count| revision + count >= revisions)
+            .unwrap_or(false);
+        recent || latest
+    }
+}
+
+/// Fold the records of a page the policy doesn't keep into a snapshot.
+/// Returns how many records were removed.
+pub fn compact_page_records(
+    db: &SqliteConnection,
+    page_id: &str,
+    policy: &RetentionPolicy,
+) -> Result<usize, Error> {
+    let now = now_millis();
+    let mut rows = vec![];
+    for row in select_page_records(db, page_id)? {
+        let record: PageRecord = ::ron::de::from_str(&row.body)?;
+        rows.push((row.rowid, row.created, record));
+    }
+
+    // Records before the first operation kept are folded.
+    let revisions = rows
+        .iter()
+        .filter(|(_, _, record)| match record {
+            PageRecord::Commit(..) => true,
+            _ => false,
+        })
+        .count();
+    let mut revision = 0;
+    let mut cut = rows.len();
+    for (i, (_, created, record)) in rows.iter().enumerate() {
+        if let PageRecord::Commit(..) = record {
+            if policy.keeps(*created, revision, revisions, now) {
+                cut = i;
+                break;
+            }
+            revision += 1;
+        }
+    }
+
+    // Replay from the last snapshot before the cut.
+    let start = match rows[..cut].iter().rposition(|(_, _, record)| match record {
+        PageRecord::Start(..) => true,
+        _ => false,
+    }) {
+        Some(start) => start,
+        None => return Ok(0),
+    };
+    let mut doc = match rows[start].2 {
+        PageRecord::Start(ref span) => Doc(span.clone()),
+        _ => unreachable!(),
+    };
+    for (_, _, record) in &rows[start + 1..cut] {
+        if let PageRecord::Commit(_, op) = record {
+            doc = Op::apply(&doc, op);
+        }
+    }
+
+    // Clients that joined are kept while their operations are.
+    let authors = rows[cut..]
+        .iter()
+        .filter_map(|(_, _, record)| match record {
+            PageRecord::Commit(client_id, _) => Some(client_id.clone()),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+    let folded = rows[..cut]
+        .iter()
+        .filter(|(_, _, record)| match record {
+            PageRecord::Join(client_id, _) => !authors.contains(client_id),
+            _ => true,
+        })
+        .map(|(rowid, _, _)| *rowid)
+        .collect::<Vec<_>>();
+    if folded.len() <= 1 {
+        return Ok(0);
+    }
+
+    // The last record folded becomes the snapshot, so it stays in order
+    // with the records kept.
+    let (snapshot, removed) = folded.split_last().unwrap();
+    update_page_record_body(db, *snapshot, &::ron::ser::to_string(&PageRecord::Start(doc.0))?)?;
+    delete_page_records(db, removed)?;
+    Ok(removed.len())
+}
+
+/// Compact the recordings of every page, returning how many records were
+/// removed.
+pub fn compact_all_page_records(
+    db: &SqliteConnection,
+    policy: &RetentionPolicy,
+) -> Result<usize, Error> {
+    let mut removed = 0;
+    for page_id in select_recorded_page_ids(db)? {
+        match compact_page_records(db, &page_id, policy) {
+            Ok(count) => removed += count,
+            Err(err) => eprintln!("(!) could not compact the recording of {:?}: {:?}", page_id, err),
+        }
+    }
+    Ok(removed)
+}
+
+pub fn spawn_retention_gc(db_pool: DbPool) {
+    let policy = match RetentionPolicy::from_env() {
+        Some(policy) => policy,
+        None => return,
+    };
+    thread::spawn(move || loop {
+        if let Ok(conn) = db_pool.get() {
+            match compact_all_page_records(&conn, &policy) {
+                Ok(removed) => println!("(%) removed {} records outside the retention policy", removed),
+                Err(err) => eprintln!("(!) could not compact recordings: {:?}", err),
+            }
+        }
+        thread::sleep(Duration::from_millis(RETENTION_GC_INTERVAL));
+    });
+}
@@ -0,0 +1,296 @@
+//! Full-text search across every page. The index keeps the words of each
+//! page's top-level blocks, and follows the operations committed to a page
+//! so only the blocks an operation touched are read again.
+
+use crate::db::*;
+
+use extern::{
+    diesel::sqlite::SqliteConnection,
+    edit_common::block_ids::block_id,
+    edit_common::blocks::map_top_level,
+    edit_common::title::doc_title,
+    oatie::doc::*,
+    std::{
+        collections::{
+            HashMap,
+            HashSet,
+        },
+        sync::RwLock,
+    },
+};
+
+/// Most results returned for a search.
+pub const MAX_SEARCH_RESULTS: usize = 50;
+
+// Characters of context shown on each side of a match.
+const SNIPPET_CONTEXT: usize = 40;
+
+#[derive(Clone, Debug, GraphQLObject)]
+pub struct SearchHit {
+    pub page_id: String,
+    pub title: String,
+    // Id of the block the match is in, to link to it.
+    pub anchor: Option<String>,
+    pub snippet: String,
+}
+
+#[derive(Clone, Debug)]
+struct IndexedBlock {
+    anchor: Option<String>,
+    text: String,
+    words: HashSet<String>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct IndexedPage {
+    title: String,
+    // One entry per top-level element, or None when it needs reading again.
+    blocks: Vec<Option<IndexedBlock>>,
+    words: HashSet<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct SearchIndex {
+    pages: HashMap<String, IndexedPage>,
+    // Pages containing each word.
+    postings: HashMap<String, HashSet<String>>,
+}
+
+/// The lowercased words of some text, each once, in the order they appear.
+pub fn words(text: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut out = vec![];
+    for word in text.split(|c: char| !c.is_alphanumeric()) {
+        let word = word.to_lowercase();
+        if !word.is_empty() && seen.insert(word.clone()) {
+            out.push(word);
+        }
+    }
+    out
+}
+
+fn element_text(elem: &DocElement, out: &mut String) {
+    match elem {
+        DocChars(text) => out.push_str(text.as_str()),
+        DocGroup(_, inner) => {
+            for child in inner {
+                element_text(child, out);
+            }
+            out.push(' ');
+        }
+    }
+}
+
+// The first block id inside an element, to anchor its results to.
+fn element_anchor(elem: &DocElement) -> Option<String> {
+    match elem {
+        DocGroup(attrs, inner) => block_id(attrs)
+            .map(|id| id.to_string())
+            .or_else(|| inner.iter().filter_map(element_anchor).next()),
+        DocChars(_) => None,
+    }
+}
+
+fn index_element(elem: &DocElement) -> IndexedBlock {
+    let mut text = String::new();
+    element_text(elem, &mut text);
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    IndexedBlock {
+        anchor: element_anchor(elem),
+        words: words(&text).into_iter().collect(),
+        text,
+    }
+}
+
+// A window of text around the first match of any of `terms`.
+fn snippet(text: &str, terms: &[String]) -> String {
+    let chars = text.chars().collect::<Vec<_>>();
+    let lower = chars
+        .iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect::<Vec<_>>();
+
+    let terms = terms
+        .iter()
+        .map(|term| term.chars().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let mut found = None;
+    for i in 0..lower.len() {
+        if let Some(term) = terms.iter().find(|term| lower[i..].starts_with(term)) {
+            found = Some((i, term.len()));
+            break;
+        }
+    }
+    let (pos, len) = found.unwrap_or((0, 0));
+
+    let start = pos.saturating_sub(SNIPPET_CONTEXT);
+    let end = (pos + len + SNIPPET_CONTEXT).min(chars.len());
+    let mut out = String::new();
+    if start > 0 {
+        out.push('…');
+    }
+    out.extend(&chars[start..end]);
+    if end < chars.len() {
+        out.push('…');
+    }
+    out
+}
+
+impl SearchIndex {
+    pub fn new() -> SearchIndex {
+        SearchIndex::default()
+    }
+
+    // Read blocks the page needs read again, and update the postings for
+    // words the page gained or lost.
+    fn refresh_page(&mut self, page_id: &str, doc: &Doc, mut page: IndexedPage) {
+        if page.blocks.len() != doc.0.len() {
+            page.blocks = doc.0.iter().map(|_| None).collect();
+        }
+        for (entry, elem) in page.blocks.iter_mut().zip(&doc.0) {
+            if entry.is_none() {
+                *entry = Some(index_element(elem));
+            }
+        }
+        page.title = doc_title(&doc.0).unwrap_or_default();
+
+        let words = page
+            .blocks
+            .iter()
+            .flat_map(|block| block.as_ref().unwrap().words.iter().cloned())
+            .collect::<HashSet<_>>();
+        for word in page.words.difference(&words) {
+            if let Some(pages) = self.postings.get_mut(word) {
+                pages.remove(page_id);
+                if pages.is_empty() {
+                    self.postings.remove(word);
+                }
+            }
+        }
+        for word in words.difference(&page.words) {
+            self.postings
+                .entry(word.clone())
+                .or_insert_with(HashSet::new)
+                .insert(page_id.to_string());
+        }
+        page.words = words;
+
+        // Emptied pages aren't kept, the same as pages that are removed.
+        if page.words.is_empty() {
+            return;
+        }
+        self.pages.insert(page_id.to_string(), page);
+    }
+
+    /// Index the whole of a page, replacing what was indexed for it before.
+    pub fn index_page(&mut self, page_id: &str, doc: &Doc) {
+        let page = self.pages.remove(page_id).unwrap_or_default();
+        let page = IndexedPage {
+            blocks: vec![],
+            ..page
+        };
+        self.refresh_page(page_id, doc, page);
+    }
+
+    /// Update a page after `op` was applied to it, producing `doc`.
+    pub fn apply(&mut self, page_id: &str, doc: &Doc, op: &Op) {
+        let mut page = self.pages.remove(page_id).unwrap_or_default();
+        page.blocks = map_top_level(&page.blocks, op).unwrap_or_default();
+        self.refresh_page(page_id, doc, page);
+    }
+
+    /// Drop a page and its words from the index.
+    pub fn remove_page(&mut self, page_id: &str) {
+        if let Some(page) = self.pages.remove(page_id) {
+            for word in page.words {
+                if let Some(pages) = self.postings.get_mut(&word) {
+                    pages.remove(page_id);
+                    if pages.is_empty() {
+                        self.postings.remove(&word);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Blocks containing every word of `query`, in page order. Pages whose
+    /// words are only spread across several blocks match on their first
+    /// block with any of them.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+        let terms = words(query);
+        if terms.is_empty() {
+            return vec![];
+        }
+
+        let mut page_ids = match self.postings.get(&terms[0]) {
+            Some(pages) => pages.iter().cloned().collect::<Vec<_>>(),
+            None => return vec![],
+        };
+        page_ids.retain(|page_id| {
+            terms[1..]
+                .iter()
+                .all(|term| self.postings.get(term).map(|x| x.contains(page_id)).unwrap_or(false))
+        });
+        page_ids.sort();
+
+        let mut hits = vec![];
+        for page_id in page_ids {
+            let page = &self.pages[&page_id];
+            let blocks = page.blocks.iter().filter_map(|x| x.as_ref()).collect::<Vec<_>>();
+
+            let mut matched = blocks
+                .iter()
+                .filter(|block| terms.iter().all(|term| block.words.contains(term)))
+                .collect::<Vec<_>>();
+            if matched.is_empty() {
+                matched.extend(
+                    blocks
+                        .iter()
+                        .find(|block| terms.iter().any(|term| block.words.contains(term))),
+                );
+            }
+
+            for block in matched {
+                if hits.len() >= limit {
+                    return hits;
+                }
+                hits.push(SearchHit {
+                    page_id: page_id.clone(),
+                    title: page.title.clone(),
+                    anchor: block.anchor.clone(),
+                    snippet: snippet(&block.text, &terms),
+                });
+            }
+        }
+        hits
+    }
+}
+
+lazy_static! {
+    pub static ref SEARCH_INDEX: RwLock<SearchIndex> = RwLock::new(SearchIndex::new());
+}
+
+/// Index every page in the database.
+pub fn build_search_index(db: &SqliteConnection) {
+    let pages = all_posts(db);
+    let mut index = SEARCH_INDEX.write().unwrap();
+    for (page_id, body) in pages {
+        match ::ron::de::from_str(&body) {
+            Ok(span) => index.index_page(&page_id, &Doc(span)),
+            Err(err) => eprintln!("(!) could not index page {:?}: {:?}", page_id, err),
+        }
+    }
+    eprintln!("(!) indexed {} pages for search", index.pages.len());
+}
+
+pub fn search_index_page(page_id: &str, doc: &Doc) {
+    SEARCH_INDEX.write().unwrap().index_page(page_id, doc);
+}
+
+pub fn search_index_op(page_id: &str, doc: &Doc, op: &Op) {
+    SEARCH_INDEX.write().unwrap().apply(page_id, doc, op);
+}
+
+pub fn search(query: &str) -> Vec<SearchHit> {
+    SEARCH_INDEX.read().unwrap().search(query, MAX_SEARCH_RESULTS)
+}
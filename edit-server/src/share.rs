@@ -0,0 +1,129 @@
+//! Share links. A share token grants access to one page, to view, comment,
+//! or edit it, until it expires or is revoked through the admin API.
+//! Clients present it when connecting, as /$/ws/{page}?share={token}.
+//!
+//! Clients without a token may edit, unless EDIT_REQUIRE_SHARE_LINK is set.
+
+use crate::db::*;
+
+use extern::{
+    diesel::sqlite::SqliteConnection,
+    edit_common::commands::SharePermission,
+    failure::Error,
+    rand::{
+        thread_rng,
+        Rng,
+    },
+    std::{
+        env,
+        time::{
+            SystemTime,
+            UNIX_EPOCH,
+        },
+    },
+};
+
+const SHARE_TOKEN_LEN: usize = 24;
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ShareLink {
+    pub token: String,
+    pub page_id: String,
+    pub permission: String,
+    // Unix time in seconds, or None if the link doesn't expire.
+    pub expires: Option<i64>,
+}
+
+impl ShareLink {
+    fn from_row(row: ShareTokenRow) -> ShareLink {
+        ShareLink {
+            token: row.token,
+            page_id: row.page_id,
+            permission: row.permission,
+            expires: row.expires,
+        }
+    }
+}
+
+/// Whether clients need a share link to connect.
+pub fn share_link_required() -> bool {
+    env::var("EDIT_REQUIRE_SHARE_LINK")
+        .map(|x| x == "1" || x == "true")
+        .unwrap_or(false)
+}
+
+fn now_secs() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Create a share link for a page, expiring after `ttl_secs` if given.
+pub fn create_share_link(
+    db: &SqliteConnection,
+    page_id: &str,
+    permission: SharePermission,
+    ttl_secs: Option<u64>,
+) -> Result<ShareLink, Error> {
+    let token = thread_rng()
+        .gen_ascii_chars()
+        .take(SHARE_TOKEN_LEN)
+        .collect::<String>();
+    let expires = ttl_secs.map(|ttl| now_secs() + ttl as i64);
+    create_share_token(db, &token, page_id, permission.as_str(), expires)?;
+    Ok(ShareLink {
+        token,
+        page_id: page_id.to_string(),
+        permission: permission.as_str().to_string(),
+        expires,
+    })
+}
+
+pub fn page_share_links(db: &SqliteConnection, page_id: &str) -> Result<Vec<ShareLink>, Error> {
+    Ok(select_share_tokens(db, page_id)?
+        .into_iter()
+        .map(ShareLink::from_row)
+        .collect())
+}
+
+/// Revoke a share link, returning the page it was for.
+pub fn revoke_share_link(db: &SqliteConnection, token: &str) -> Result<Option<String>, Error> {
+    let row = select_share_token(db, token)?;
+    delete_share_token(db, token)?;
+    Ok(row.map(|row| row.page_id))
+}
+
+/// What the holder of a share token may do with a page, or None if the
+/// token doesn't grant access to it.
+pub fn check_share_token(
+    db: &SqliteConnection,
+    token: &str,
+    page_id: &str,
+) -> Result<Option<SharePermission>, Error> {
+    let row = match select_share_token(db, token)? {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    if row.page_id != page_id {
+        return Ok(None);
+    }
+    if row.expires.map(|expires| expires <= now_secs()).unwrap_or(false) {
+        return Ok(None);
+    }
+    Ok(SharePermission::from_str(&row.permission))
+}
+
+/// What a client may do with a page, given the share token it came with, if
+/// any. None if it may not open the page at all.
+pub fn client_permission(
+    db: &SqliteConnection,
+    token: Option<&str>,
+    page_id: &str,
+) -> Result<Option<SharePermission>, Error> {
+    match token {
+        Some(token) => check_share_token(db, token, page_id),
+        None if share_link_required() => Ok(None),
+        None => Ok(Some(SharePermission::Edit)),
+    }
+}
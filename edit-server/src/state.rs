@@ -1,21 +1,96 @@
 //! Sync state. This is a candidate file to be moved into Oatie.
 
+use crate::limits::DocLimits;
+
 use extern::{
+    edit_common::commands::RefusalReason,
     failure::Error,
     oatie::{
         doc::*,
+        intern::{
+            intern_doc,
+            intern_op,
+            Interner,
+        },
         schema::RtfSchema,
-        validate::validate_doc,
+        validate::{
+            validate_doc,
+            validate_op,
+        },
         OT,
     },
-    std::collections::HashMap,
+    std::{
+        collections::{
+            HashMap,
+            VecDeque,
+        },
+        mem::size_of,
+    },
 };
 
+// Bytes of bookkeeping for each entry of an attribute map.
+const ATTR_OVERHEAD: usize = 64;
+
+// Number of operation ids remembered, to recognize operations resent after
+// they were applied.
+const APPLIED_OPS_LEN: usize = 1024;
+
+fn attrs_memory(attrs: &Attrs) -> usize {
+    attrs
+        .iter()
+        .map(|(key, value)| ATTR_OVERHEAD + key.len() + value.len())
+        .sum()
+}
+
+fn span_memory(span: &DocSpan) -> usize {
+    span.iter()
+        .map(|elem| {
+            size_of::<DocElement>() + match elem {
+                DocChars(text) => text.as_str().len(),
+                DocGroup(attrs, inner) => attrs_memory(attrs) + span_memory(inner),
+            }
+        })
+        .sum()
+}
+
+fn del_memory(span: &DelSpan) -> usize {
+    span.iter()
+        .map(|elem| {
+            size_of::<DelElement>() + match elem {
+                DelWithGroup(inner) | DelGroup(inner) => del_memory(inner),
+                DelSkip(_) | DelChars(_) | DelStyles(..) => 0,
+            }
+        })
+        .sum()
+}
+
+fn add_memory(span: &AddSpan) -> usize {
+    span.iter()
+        .map(|elem| {
+            size_of::<AddElement>() + match elem {
+                AddChars(text) => text.as_str().len(),
+                AddGroup(attrs, inner) => attrs_memory(attrs) + add_memory(inner),
+                AddWithGroup(inner) => add_memory(inner),
+                AddSkip(_) | AddStyles(..) => 0,
+            }
+        })
+        .sum()
+}
+
 pub struct SyncState {
     pub version: usize,
     pub clients: HashMap<String, usize>, // client_id -> client_version
     pub history: HashMap<usize, Op>,     // version -> op
+    pub snapshots: HashMap<usize, Doc>,  // version -> doc before that version's op
     pub doc: Doc,
+    pub limits: DocLimits,
+    // (client_id, op_id) -> the version the operation made, for the most
+    // recently applied operations, oldest first in `applied_order`.
+    applied: HashMap<(String, u64), usize>,
+    applied_order: VecDeque<(String, u64)>,
+    // Styles shared by the text of the document, its snapshots, and the
+    // operations in its history.
+    interner: Interner,
 }
 
 impl SyncState {
@@ -25,8 +100,10 @@ impl SyncState {
                 if k < min_version {
                     // eprintln!("(^) evicted document version {}", k);
                     self.history.remove(&k);
+                    self.snapshots.remove(&k);
                 }
             }
+            self.interner.prune();
         }
     }
 
@@ -52,11 +129,50 @@ impl SyncState {
         Ok(op)
     }
 
+    /// Check an incoming operation against the document version it was
+    /// written for, before it is transformed or applied.
+    pub fn validate_incoming(&self, op: &Op, input_version: usize) -> Result<(), RefusalReason> {
+        let base_doc = if input_version == self.version {
+            &self.doc
+        } else {
+            self.snapshots
+                .get(&input_version)
+                .ok_or(RefusalReason::UnknownVersion(input_version))?
+        };
+
+        validate_op(base_doc, op).map_err(|err| RefusalReason::MalformedOp(err.to_string()))?;
+        let new_doc = Op::apply(base_doc, op);
+        validate_doc(&new_doc).map_err(|err| RefusalReason::InvalidDocument(err.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Transform a validated operation to the current version and check it
+    /// against the size limits. Transforming can change what an operation
+    /// adds, and operations committed since it was written count toward the
+    /// document's size, so the limits are checked on the result.
+    pub fn transform_incoming(&self, op: Op, input_version: usize) -> Result<Op, RefusalReason> {
+        let op = self
+            .update_operation_to_current(op, input_version, self.version)
+            .map_err(|err| RefusalReason::MalformedOp(err.to_string()))?;
+        validate_op(&self.doc, &op).map_err(|err| RefusalReason::MalformedOp(err.to_string()))?;
+        self.limits.check_op(&op)?;
+        let new_doc = Op::apply(&self.doc, &op);
+        self.limits.check_growth(&self.doc, &new_doc)?;
+
+        Ok(op)
+    }
+
     pub fn commit(&mut self, client_id: &str, op: Op, input_version: usize) -> Result<Op, Error> {
         let target_version = self.version;
 
-        // Update the operation so we can apply it to the document.
+        // Update the operation so we can apply it to the document, with
+        // its text sharing the styles already in the document.
         let op = self.update_operation_to_current(op, input_version, target_version)?;
+        let op = intern_op(&op, &mut self.interner);
+
+        // The transformed operation must still fit the current document.
+        validate_op(&self.doc, &op)?;
 
         if let Some(version) = self.clients.get_mut(client_id) {
             *version = target_version;
@@ -67,13 +183,15 @@ impl SyncState {
 
         // Prune history entries.
         self.prune_history();
-        self.history.insert(target_version, op.clone());
 
         // Update the document with this operation.
         let new_doc = Op::apply(&self.doc, &op);
 
         // Gut check.
-        validate_doc(&self.doc).map_err(|_| format_err!("Validation error"))?;
+        validate_doc(&new_doc).map_err(|_| format_err!("Validation error"))?;
+
+        self.history.insert(target_version, op.clone());
+        self.snapshots.insert(target_version, self.doc.clone());
 
         // Commit chhanges.
         self.doc = new_doc;
@@ -82,12 +200,59 @@ impl SyncState {
         Ok(op)
     }
 
+    /// The version a client's operation made, if it was applied recently.
+    pub fn applied_version(&self, client_id: &str, op_id: u64) -> Option<usize> {
+        self.applied.get(&(client_id.to_string(), op_id)).cloned()
+    }
+
+    /// The id of a client's operation applied last, if it was applied
+    /// recently, and the version it made.
+    pub fn last_applied(&self, client_id: &str) -> Option<(u64, usize)> {
+        self.applied_order
+            .iter()
+            .rev()
+            .find(|(applied_id, _)| applied_id == client_id)
+            .map(|(_, op_id)| (*op_id, self.applied[&(client_id.to_string(), *op_id)]))
+    }
+
+    /// Remember that a client's operation was applied, making `version`.
+    pub fn record_applied(&mut self, client_id: &str, op_id: u64, version: usize) {
+        let key = (client_id.to_string(), op_id);
+        if self.applied.insert(key.clone(), version).is_none() {
+            self.applied_order.push_back(key);
+        }
+        while self.applied_order.len() > APPLIED_OPS_LEN {
+            if let Some(key) = self.applied_order.pop_front() {
+                self.applied.remove(&key);
+            }
+        }
+    }
+
+    /// Rough estimate of the bytes held for the page: its document, the
+    /// snapshots kept to check operations written against older versions,
+    /// and the history of operations.
+    pub fn memory_estimate(&self) -> usize {
+        let doc = span_memory(&self.doc.0);
+        let history = self
+            .history
+            .values()
+            .map(|op| del_memory(&op.0) + add_memory(&op.1))
+            .sum::<usize>();
+        doc * (1 + self.snapshots.len()) + history
+    }
+
     pub fn new(doc: Doc, version: usize) -> SyncState {
+        let mut interner = Interner::new();
         SyncState {
-            doc,
+            doc: intern_doc(&doc, &mut interner),
             version,
             clients: hashmap![],
             history: hashmap![],
+            snapshots: hashmap![],
+            limits: DocLimits::from_env(),
+            applied: hashmap![],
+            applied_order: VecDeque::new(),
+            interner,
         }
     }
 }
@@ -1,10 +1,24 @@
 //! Synchronization server. Threads for websockets and graphql.
 
 use crate::{
+    admin::*,
     carets::*,
+    comments::*,
     db::*,
+    e2ee::*,
+    peer::*,
+    events::*,
+    fs_sync::*,
     graphql::sync_graphql_server,
+    hooks::*,
     log::log_sync_init,
+    memory::spawn_memory_watcher,
+    mentions::*,
+    mirror::mirror_page,
+    playback::*,
+    retention::spawn_retention_gc,
+    search::*,
+    share::*,
     state::*,
 };
 
@@ -14,9 +28,44 @@ use extern::{
         Receiver as CCReceiver,
         Sender as CCSender,
     },
+    edit_common::block_ids::assign_block_ids,
     edit_common::commands::*,
+    edit_common::protocol::{
+        is_supported,
+        parse_server_command,
+        requested_resume,
+        requested_version,
+        CHUNKED_SYNC_VERSION,
+        OLDEST_PROTOCOL_VERSION,
+        PEER_SYNC_VERSION,
+        TEXT_SYNC_VERSION,
+    },
+    edit_common::comments::{
+        CommentAction,
+        CommentThread,
+    },
+    edit_common::doclink::linked_pages,
+    edit_common::find::find_markers_op,
+    edit_common::mention::mentioned_users,
+    edit_common::identity::{
+        session_id,
+        UserIdentity,
+    },
+    edit_common::signing::{
+        needs_signature,
+        signing_time,
+        SeenNonces,
+        SigningKeys,
+        SIGNING_KEYS_VAR,
+    },
+    edit_common::title::doc_title,
     failure::Error,
-    oatie::doc::*,
+    oatie::{
+        diff::diff,
+        doc::*,
+        schema::RtfSchema,
+        OT,
+    },
     rand::{
         thread_rng,
         Rng,
@@ -26,14 +75,92 @@ use extern::{
     edit_common::simple_ws,
     std::env,
     std::{
-        collections::HashMap,
-        thread,
-        time::Duration,
+        collections::{
+            BTreeSet,
+            HashMap,
+            VecDeque,
+        },
+        sync::atomic::{
+            AtomicBool,
+            AtomicUsize,
+            Ordering,
+        },
+        sync::Arc,
+        sync::Mutex,
+        thread::{
+            self,
+            JoinHandle,
+        },
+        time::{
+            Duration,
+            Instant,
+        },
     },
     url::Url,
     ws,
 };
 
+/// Whether only one client may edit a document at a time.
+fn single_writer_mode() -> bool {
+    env::var("EDIT_SINGLE_WRITER")
+        .map(|x| x == "1" || x == "true")
+        .unwrap_or(false)
+}
+
+/// Whether operations must be signed, for deployments where clients only
+/// connect through a trusted proxy.
+fn op_signing_required() -> bool {
+    env::var("EDIT_OP_SIGNING_REQUIRED")
+        .map(|x| x == "1" || x == "true")
+        .unwrap_or(false)
+}
+
+// Signed commands remembered, so none is accepted twice.
+const MAX_SEEN_NONCES: usize = 100_000;
+
+// Client ids remembered with the session they were issued to.
+const MAX_CLIENT_SESSIONS: usize = 100_000;
+
+// Numbers the sockets of clients, to tell a client's socket from the one it
+// had before reconnecting with the same id.
+static CONNECTION_COUNTER: AtomicUsize = AtomicUsize::new(1);
+
+lazy_static! {
+    // Started with the server, so commands signed before it are refused.
+    static ref SEEN_NONCES: Mutex<SeenNonces> =
+        Mutex::new(SeenNonces::new(MAX_SEEN_NONCES, signing_time()));
+    static ref CLIENT_SESSIONS: Mutex<ClientSessions> = Mutex::new(ClientSessions::default());
+}
+
+// The session each client id was issued to. The session cookie is a secret
+// only its browser knows, so it's what lets a client reconnect as itself.
+#[derive(Default)]
+struct ClientSessions {
+    sessions: HashMap<String, String>,
+    order: VecDeque<String>,
+}
+
+impl ClientSessions {
+    fn issue(&mut self, client_id: &str, session: &str) {
+        if self
+            .sessions
+            .insert(client_id.to_string(), session.to_string())
+            .is_none()
+        {
+            self.order.push_back(client_id.to_string());
+        }
+        while self.order.len() > MAX_CLIENT_SESSIONS {
+            if let Some(oldest) = self.order.pop_front() {
+                self.sessions.remove(&oldest);
+            }
+        }
+    }
+
+    fn is_issued(&self, client_id: &str, session: &str) -> bool {
+        self.sessions.get(client_id).map(|x| x == session).unwrap_or(false)
+    }
+}
+
 fn debug_sync_delay() -> Option<u64> {
     env::var("EDIT_DEBUG_SYNC_DELAY")
         .ok()
@@ -41,7 +168,10 @@ fn debug_sync_delay() -> Option<u64> {
 }
 
 const INITIAL_SYNC_VERSION: usize = 100; // Arbitrarily select version 100
+const DEFAULT_PLAYBACK_SPEED: f64 = 1.0;
 const PAGE_TITLE_LEN: usize = 100; // 100 chars is the limit
+pub const INIT_CHUNK_LEN: usize = 16 * 1024; // Serialized bytes per InitChunk
+const MEMORY_REPORT_INTERVAL: u64 = 10; // Seconds between memory estimates
 
 pub fn default_new_doc(id: &str) -> Doc {
     Doc(doc_span![
@@ -64,6 +194,80 @@ fn generate_random_page_id() -> String {
     thread_rng().gen_ascii_chars().take(6).collect()
 }
 
+// A document's top-level elements as JSON, each serialized once for every
+// client initializing with it. Keys are sorted, so the same version of a
+// document always serializes the same way, and a client can resume its
+// initial sync even from a server that restarted in between.
+pub fn init_elements(doc: &DocSpan) -> Vec<String> {
+    match serde_json::to_value(doc) {
+        Ok(serde_json::Value::Array(elements)) => {
+            elements.iter().map(|elem| elem.to_string()).collect()
+        }
+        _ => vec![],
+    }
+}
+
+// Split a document's serialized elements into chunks, each a JSON array of
+// about INIT_CHUNK_LEN bytes. An element longer than that is a chunk of its
+// own; clients that can take InitText are sent that instead.
+pub fn init_chunks(elements: &[String]) -> Vec<String> {
+    let mut chunks = vec![];
+    let mut chunk = String::new();
+    for elem in elements {
+        if !chunk.is_empty() && chunk.len() + elem.len() + 1 > INIT_CHUNK_LEN {
+            chunk.push(']');
+            chunks.push(chunk);
+            chunk = String::new();
+        }
+        chunk.push(if chunk.is_empty() { '[' } else { ',' });
+        chunk.push_str(elem);
+    }
+    // Even an empty document is sent as one chunk.
+    if !chunk.is_empty() {
+        chunk.push(']');
+        chunks.push(chunk);
+    } else if chunks.is_empty() {
+        chunks.push("[]".to_string());
+    }
+    chunks
+}
+
+// Split a document's serialized elements into pieces of its JSON of at
+// most INIT_CHUNK_LEN bytes, however long its elements are.
+pub fn init_texts(elements: &[String]) -> Vec<String> {
+    let json = format!("[{}]", elements.join(","));
+    let mut texts = vec![];
+    let mut rest = json.as_str();
+    while !rest.is_empty() {
+        let mut len = rest.len().min(INIT_CHUNK_LEN);
+        while !rest.is_char_boundary(len) {
+            len -= 1;
+        }
+        texts.push(rest[..len].to_string());
+        rest = &rest[len..];
+    }
+    texts
+}
+
+// InitChunk with a chunk from `init_chunks`, without serializing its
+// elements again.
+pub fn init_chunk_json(
+    client_id: &str,
+    version: usize,
+    index: usize,
+    count: usize,
+    chunk: &str,
+) -> String {
+    format!(
+        "{{\"InitChunk\":[{},{},{},{},{}]}}",
+        serde_json::to_string(client_id).unwrap(),
+        version,
+        index,
+        count,
+        chunk
+    )
+}
+
 // Target Page ID, ClientUpdate
 pub struct ClientNotify(pub String, pub ClientUpdate);
 
@@ -71,19 +275,105 @@ pub struct ClientNotify(pub String, pub ClientUpdate);
 pub enum ClientUpdate {
     Connect {
         client_id: String,
+        // Stable ID of the user behind this client, if it sent one.
+        user_id: Option<String>,
+        remote_addr: Option<String>,
+        // Session cookie of the client's browser, if it has one.
+        session: Option<String>,
+        // Share token the client connected with, if any.
+        share_token: Option<String>,
+        // What the client may do, as its share link allows.
+        permission: SharePermission,
+        identity: UserIdentity,
+        // Version of the protocol the client speaks, and the initial sync
+        // it asked to resume, as a version and the chunk to resume from.
+        protocol: u32,
+        resume: Option<(usize, usize)>,
+        // Number of the client's socket, as `Disconnect` gives it.
+        connection: usize,
         out: simple_ws::Sender,
     },
     Commit {
         client_id: String,
+        // Id the client gave the operation, to be acknowledged with.
+        op_id: Option<u64>,
         op: Op,
         version: usize,
     },
     Disconnect {
         client_id: String,
+        connection: usize,
     },
     Overwrite {
         doc: Doc,
     },
+    // Save the document to the database now.
+    Save,
+    // Save the document, disconnect its clients, and stop its sync thread.
+    Evict,
+    // Evict the page only if it's had no clients and no notifications since
+    // `checked`. The page master decides, so pages that were idle when the
+    // memory budget was checked, but aren't anymore, stay loaded.
+    EvictIdle {
+        checked: Instant,
+    },
+    // Give a client the write lock, in single-writer mode.
+    TakeOver {
+        client_id: String,
+    },
+    // Copy the document into a new page, with its recording if `history`
+    // is set, and tell the client the new page's id.
+    Duplicate {
+        client_id: String,
+        history: bool,
+    },
+    // The page's file in the sync directory was edited from `base`, if it's
+    // known, and now reads as `doc`.
+    ExternalEdit {
+        base: Option<Doc>,
+        doc: Doc,
+    },
+    // Merge the changes made in a copy of this page back into it.
+    MergeFork {
+        fork_id: String,
+    },
+    // Find a query in the document as of `version`, for a client.
+    Find {
+        client_id: String,
+        query: String,
+        version: usize,
+    },
+    // Another page was loaded or its title changed, for pages linking to it.
+    PageTitle {
+        page_id: String,
+        title: String,
+    },
+    // Start, reply to, resolve, or reopen a comment thread, for a client.
+    Comment {
+        client_id: String,
+        action: CommentAction,
+    },
+    // A user was mentioned, for pages they have open.
+    Mentioned {
+        user_id: String,
+    },
+    // Send the page's events to a socket, or stop.
+    Watch {
+        watcher_id: String,
+        out: simple_ws::Sender,
+    },
+    Unwatch {
+        watcher_id: String,
+    },
+    // Forcibly disconnect a client, telling it why.
+    Kick {
+        client_id: String,
+        reason: String,
+    },
+    // A share link was revoked, so disconnect the clients using it.
+    RevokeShare {
+        token: String,
+    },
 }
 
 /// Websocket handler for an individual user.
@@ -91,24 +381,99 @@ struct ClientSocket {
     page_id: String,
     client_id: String,
     tx_master: CCSender<ClientNotify>,
+    db_pool: DbPool,
+    // Set when this socket is watching a recording instead of editing.
+    playback: Option<Arc<AtomicBool>>,
+    // Set when this socket edits an end-to-end encrypted page, through the
+    // encrypted relay instead of the page's sync thread.
+    encrypted: bool,
+    // Set when this socket edits a peer page, through the peer relay.
+    peer: bool,
+    // What the client may do with the page.
+    permission: SharePermission,
+    // Version of the protocol the client speaks, which its commands are
+    // parsed as.
+    protocol: u32,
+    // Number of this socket, among every client's.
+    connection: usize,
+    // Whether the page's sync thread knows about this client.
+    connected: bool,
+    // Set when this socket only watches the page's events.
+    watching: bool,
 }
 
 /// Websocket implementation.
 impl SimpleSocket for ClientSocket {
-    type Args = (String, CCSender<ClientNotify>);
+    type Args = (String, CCSender<ClientNotify>, DbPool, BanList);
 
     fn initialize(
-        (client_id, tx_master): Self::Args,
+        (client_id, tx_master, db_pool, bans): Self::Args,
         url: &str,
+        remote_addr: Option<String>,
+        cookie: Option<String>,
         out: simple_ws::Sender,
     ) -> Result<ClientSocket, Error> {
         let url = Url::parse("http://localhost/").unwrap().join(url).unwrap();
         let mut path = url.path().to_owned();
 
+        // A client reconnecting gives the id it had, so that operations it
+        // sends again are recognized as the ones it sent before. Only the
+        // session the id was issued to may reconnect as it, and anyone else
+        // is issued a new id.
+        let session = cookie.as_ref().and_then(|x| session_id(x));
+        let reconnect_id = url
+            .query_pairs()
+            .find(|(key, _)| key == "client")
+            .map(|(_, value)| value.to_string())
+            .filter(|value| valid_page_id(value));
+        let client_id = {
+            let mut client_sessions = CLIENT_SESSIONS.lock().unwrap();
+            let reconnects = match (&reconnect_id, &session) {
+                (&Some(ref id), &Some(ref session)) => client_sessions.is_issued(id, session),
+                _ => false,
+            };
+            if reconnects {
+                reconnect_id.unwrap()
+            } else {
+                if let Some(ref session) = session {
+                    client_sessions.issue(&client_id, session);
+                }
+                client_id
+            }
+        };
+        let connection = CONNECTION_COUNTER.fetch_add(1, Ordering::SeqCst);
+
         if path.starts_with("/$/ws/") {
             path = path["/$/ws".len()..].to_string();
         }
 
+        // Sockets connecting to /$/ws/{page}/playback watch the page's recording.
+        let is_playback = path.ends_with("/playback");
+        if is_playback {
+            path = path[..path.len() - "/playback".len()].to_string();
+        }
+
+        // Sockets connecting to /$/ws/{page}/events are sent the page's
+        // events, without editing it.
+        let is_events = path.ends_with("/events");
+        if is_events {
+            path = path[..path.len() - "/events".len()].to_string();
+        }
+
+        // Sockets connecting to /$/ws/{page}/encrypted edit the page's end-to-end
+        // encrypted version.
+        let is_encrypted = path.ends_with("/encrypted");
+        if is_encrypted {
+            path = path[..path.len() - "/encrypted".len()].to_string();
+        }
+
+        // Sockets connecting to /$/ws/{page}/peer edit the page as a CRDT,
+        // relaying operations between peers.
+        let is_peer = path.ends_with("/peer");
+        if is_peer {
+            path = path[..path.len() - "/peer".len()].to_string();
+        }
+
         let page_id = if valid_page_id(&path[1..]) {
             path[1..].to_string()
         } else {
@@ -116,13 +481,233 @@ impl SimpleSocket for ClientSocket {
             "home".to_string()
         };
 
-        eprintln!("(!) Client {:?} connected to {:?}", client_id, page_id);
+        // Clients speaking a version of the protocol we don't, or one too
+        // old for peer pages, are refused before they're sent anything.
+        let version = requested_version(
+            url.query_pairs()
+                .find(|(key, _)| key == "protocol")
+                .map(|(_, value)| value.to_string())
+                .as_ref()
+                .map(|x| x.as_str()),
+        );
+        let supported = version
+            .map(|version| is_supported(version) && (!is_peer || version >= PEER_SYNC_VERSION))
+            .unwrap_or(false);
+        let protocol = match version {
+            Some(version) if supported => version,
+            _ => {
+                eprintln!("(!) Refused client {:?} speaking protocol {:?}", client_id, version);
+                close_client(
+                    &out,
+                    CloseReason::UnsupportedVersion,
+                    "This editor is out of date. Reload the page to update it.",
+                );
+                return Ok(ClientSocket {
+                    page_id,
+                    client_id,
+                    tx_master,
+                    db_pool,
+                    playback: None,
+                    encrypted: false,
+                    peer: false,
+                    permission: SharePermission::View,
+                    protocol: OLDEST_PROTOCOL_VERSION,
+                    connection,
+                    connected: false,
+                    watching: false,
+                });
+            }
+        };
+
+        let user_id = url
+            .query_pairs()
+            .find(|(key, _)| key == "user")
+            .map(|(_, value)| value.to_string())
+            .filter(|value| valid_page_id(value));
+
+        let share_token = url
+            .query_pairs()
+            .find(|(key, _)| key == "share")
+            .map(|(_, value)| value.to_string());
+
+        let ban_keys = [
+            session.as_ref().map(|x| x.as_str()),
+            share_token.as_ref().map(|x| x.as_str()),
+            remote_addr.as_ref().map(|x| x.as_str()),
+        ];
+        if is_banned(&bans, &ban_keys) {
+            eprintln!("(!) Refused banned client {:?} ({:?}, {:?})", client_id, session, remote_addr);
+            close_client(&out, CloseReason::Banned, "You were banned from this server.");
+            return Ok(ClientSocket {
+                page_id,
+                client_id,
+                tx_master,
+                db_pool,
+                playback: None,
+                encrypted: false,
+                peer: false,
+                permission: SharePermission::View,
+                protocol,
+                connection,
+                connected: false,
+                watching: false,
+            });
+        }
+
+        // Clients with a share link may only do what it allows.
+        let permission = {
+            let conn = db_pool.get()?;
+            client_permission(&conn, share_token.as_ref().map(|x| x.as_str()), &page_id)?
+        };
+        let permission = match permission {
+            Some(permission) => permission,
+            None => {
+                eprintln!("(!) Refused client {:?} without a valid share link", client_id);
+                close_client(
+                    &out,
+                    CloseReason::Unauthorized,
+                    "This share link has expired or was revoked.",
+                );
+                return Ok(ClientSocket {
+                    page_id,
+                    client_id,
+                    tx_master,
+                    db_pool,
+                    playback: None,
+                    encrypted: false,
+                    peer: false,
+                    permission: SharePermission::View,
+                    protocol,
+                    connection,
+                    connected: false,
+                    watching: false,
+                });
+            }
+        };
+
+        // Banned clients and those without a share link can't watch the
+        // page's history either.
+        if is_playback {
+            let speed = url
+                .query_pairs()
+                .find(|(key, _)| key == "speed")
+                .and_then(|(_, value)| value.parse::<f64>().ok())
+                .filter(|speed| *speed > 0.0)
+                .unwrap_or(DEFAULT_PLAYBACK_SPEED);
+
+            eprintln!("(!) Client {:?} watching {:?} at {}x", client_id, page_id, speed);
+
+            let alive = Arc::new(AtomicBool::new(true));
+            spawn_playback(db_pool.clone(), page_id.clone(), out, speed, alive.clone());
+
+            return Ok(ClientSocket {
+                page_id,
+                client_id,
+                tx_master,
+                db_pool,
+                playback: Some(alive),
+                encrypted: false,
+                peer: false,
+                permission: SharePermission::View,
+                protocol,
+                connection,
+                connected: false,
+                watching: false,
+            });
+        }
+
+        if is_events {
+            eprintln!("(!) Client {:?} watching events of {:?}", client_id, page_id);
+            let _ = tx_master.send(ClientNotify(
+                page_id.to_string(),
+                ClientUpdate::Watch {
+                    watcher_id: client_id.to_string(),
+                    out,
+                },
+            ));
+            return Ok(ClientSocket {
+                page_id,
+                client_id,
+                tx_master,
+                db_pool,
+                playback: None,
+                encrypted: false,
+                peer: false,
+                permission,
+                protocol,
+                connection,
+                connected: false,
+                watching: true,
+            });
+        }
+
+        // Users describe themselves with ?name=...&color=...&avatar=...,
+        // or else are named as guests of their session.
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.to_string())
+        };
+        let identity = UserIdentity::new(
+            user_id.as_ref().unwrap_or(&client_id),
+            param("name").as_ref().map(|x| x.as_str()),
+            param("color").as_ref().map(|x| x.as_str()),
+            param("avatar").as_ref().map(|x| x.as_str()),
+            session.as_ref().map(|x| x.as_str()),
+        );
+
+        eprintln!("(!) Client {:?} ({:?}) connected to {:?}", client_id, user_id, page_id);
+
+        if is_encrypted {
+            encrypted_connect(&db_pool, &page_id, &client_id, out)?;
+            return Ok(ClientSocket {
+                page_id,
+                client_id,
+                tx_master,
+                db_pool,
+                playback: None,
+                encrypted: true,
+                peer: false,
+                permission,
+                protocol,
+                connection,
+                connected: true,
+                watching: false,
+            });
+        }
+
+        if is_peer {
+            peer_connect(&db_pool, &page_id, &client_id, out)?;
+            return Ok(ClientSocket {
+                page_id,
+                client_id,
+                tx_master,
+                db_pool,
+                playback: None,
+                encrypted: false,
+                peer: true,
+                permission,
+                protocol,
+                connection,
+                connected: true,
+                watching: false,
+            });
+        }
 
         // Notify sync thread of our having connected.
         let _ = tx_master.send(ClientNotify(
             page_id.to_string(),
             ClientUpdate::Connect {
                 client_id: client_id.to_string(),
+                user_id,
+                remote_addr,
+                session,
+                share_token,
+                permission,
+                identity,
+                protocol,
+                resume: requested_resume(param("resume").as_ref().map(|x| x.as_str())),
+                connection,
                 out: out,
             },
         ));
@@ -132,21 +717,136 @@ impl SimpleSocket for ClientSocket {
             page_id: page_id.to_string(),
             client_id: client_id.to_string(),
             tx_master,
+            db_pool,
+            playback: None,
+            encrypted: false,
+            peer: false,
+            permission,
+            protocol,
+            connection,
+            connected: true,
+            watching: false,
         })
     }
 
     fn handle_message(&mut self, data: &[u8]) -> Result<(), Error> {
-        let command: ServerCommand = serde_json::from_slice(&data)?;
+        // Watchers only listen.
+        if self.watching {
+            return Ok(());
+        }
+
+        let command = parse_server_command(self.protocol, data)?;
+
+        // Check signed commands, which must change this page, for this
+        // client, and not have been sent before.
+        let command = match command {
+            ServerCommand::Signed(key_id, signature, payload) => {
+                let keys = match SigningKeys::from_env() {
+                    Some(keys) => keys,
+                    None => bail!("signed commands need {} to be set", SIGNING_KEYS_VAR),
+                };
+                let (command, signed) =
+                    keys.verify_command(&key_id, &signature, &payload, &self.page_id)?;
+                let client_id = match command {
+                    ServerCommand::Commit(ref client_id, ..)
+                    | ServerCommand::CommitOp(ref client_id, ..)
+                    | ServerCommand::EncryptedCommit(ref client_id, ..)
+                    | ServerCommand::PeerOps(ref client_id, ..)
+                    | ServerCommand::Duplicate(ref client_id, ..)
+                    | ServerCommand::MergeFork(ref client_id) => client_id.to_string(),
+                    _ => unreachable!(),
+                };
+                if client_id != self.client_id {
+                    bail!("signed command from {:?} was sent by {:?}", client_id, self.client_id);
+                }
+                if !SEEN_NONCES.lock().unwrap().insert(&signed.nonce, signed.signed_at) {
+                    bail!("refusing a signed command {:?} sent again", self.client_id);
+                }
+                command
+            }
+            ref command if needs_signature(command) && op_signing_required() => {
+                bail!("refusing an unsigned command from {:?}", self.client_id);
+            }
+            command => command,
+        };
+
+        // Refused sockets can't send anything.
+        if !self.connected && self.playback.is_none() {
+            return Ok(());
+        }
+
+        // Playback is read-only, so drop any operations or comments the
+        // client sends.
+        if self.playback.is_some() {
+            match command {
+                ServerCommand::Commit(..) | ServerCommand::CommitOp(..) | ServerCommand::Comment(..) => {
+                    return Ok(())
+                }
+                _ => {}
+            }
+        }
+
+        // Clients with a view-only share link can't comment.
+        if !self.permission.can_comment() {
+            if let ServerCommand::Comment(..) = command {
+                return Ok(());
+            }
+        }
+
+        // Clients that can't edit can't take over, merge, or duplicate the
+        // page either. Their operations are refused by the page's sync thread.
+        if !self.permission.can_edit() {
+            match command {
+                ServerCommand::TakeOver(..)
+                | ServerCommand::MergeFork(..)
+                | ServerCommand::Duplicate(..)
+                | ServerCommand::EncryptedCommit(..)
+                | ServerCommand::PeerOps(..) => return Ok(()),
+                _ => {}
+            }
+        }
+
+        // Encrypted pages only take encrypted operations, which are relayed
+        // without going through the page's sync thread.
+        if self.encrypted {
+            match command {
+                ServerCommand::EncryptedCommit(_, body, version) => {
+                    encrypted_commit(&self.db_pool, &self.page_id, &self.client_id, &body, version)?;
+                }
+                ServerCommand::Log(log) => {
+                    log_raw!(self.client_id, log);
+                }
+                _ => {}
+            }
+            return Ok(());
+        }
+
+        // Likewise peer pages only take peer operations.
+        if self.peer {
+            match command {
+                ServerCommand::PeerOps(_, ops) => {
+                    peer_commit(&self.db_pool, &self.page_id, &self.client_id, ops)?;
+                }
+                ServerCommand::Log(log) => {
+                    log_raw!(self.client_id, log);
+                }
+                _ => {}
+            }
+            return Ok(());
+        }
 
         // TODO don't log client Log(...)
         // log_sync!("SERVER", ClientPacket(command.clone()));
 
         match command {
-            ServerCommand::Commit(client_id, op, version) => {
+            // Operations are committed as this socket's client, whichever
+            // client the command names.
+            ServerCommand::Commit(_, op, version) => {
                 let _ = self.tx_master.send(ClientNotify(
                     self.page_id.to_string(),
                     ClientUpdate::Commit {
-                        client_id,
+                        client_id: self.client_id.to_string(),
+                        op_id: None,
                         op,
                         version,
                     },
@@ -154,6 +854,80 @@ impl SimpleSocket for ClientSocket {
                 // let mut sync_state = self.sync_state_mutex.lock().unwrap();
                 // sync_state.ops.push_back((client_id.clone(), version, op.clone()));
             }
+            ServerCommand::CommitOp(_, op_id, op, version) => {
+                let _ = self.tx_master.send(ClientNotify(
+                    self.page_id.to_string(),
+                    ClientUpdate::Commit {
+                        client_id: self.client_id.to_string(),
+                        op_id: Some(op_id),
+                        op,
+                        version,
+                    },
+                ));
+            }
+            ServerCommand::TakeOver(_) => {
+                let _ = self.tx_master.send(ClientNotify(
+                    self.page_id.to_string(),
+                    ClientUpdate::TakeOver {
+                        client_id: self.client_id.to_string(),
+                    },
+                ));
+            }
+            // Copies are made for this socket's client, whichever client
+            // the command names.
+            ServerCommand::Duplicate(_, history) => {
+                let _ = self.tx_master.send(ClientNotify(
+                    self.page_id.to_string(),
+                    ClientUpdate::Duplicate {
+                        client_id: self.client_id.to_string(),
+                        history,
+                    },
+                ));
+            }
+            // Results go back to this socket's client.
+            ServerCommand::Find(_, query, version) => {
+                let _ = self.tx_master.send(ClientNotify(
+                    self.page_id.to_string(),
+                    ClientUpdate::Find {
+                        client_id: self.client_id.to_string(),
+                        query,
+                        version,
+                    },
+                ));
+            }
+            // The original document does the merge, so it's sent there.
+            ServerCommand::MergeFork(_) => {
+                let conn = self.db_pool.get().map_err(CloseError::unavailable)?;
+                match select_fork(&conn, &self.page_id)? {
+                    Some(fork) => {
+                        let _ = self.tx_master.send(ClientNotify(
+                            fork.source_id,
+                            ClientUpdate::MergeFork {
+                                fork_id: self.page_id.to_string(),
+                            },
+                        ));
+                    }
+                    None => bail!("page {:?} is not a copy of another", self.page_id),
+                }
+            }
+            // Comments are attributed to this socket's client, whichever
+            // client the command names.
+            ServerCommand::Comment(_, action) => {
+                let _ = self.tx_master.send(ClientNotify(
+                    self.page_id.to_string(),
+                    ClientUpdate::Comment {
+                        client_id: self.client_id.to_string(),
+                        action,
+                    },
+                ));
+            }
+            ServerCommand::EncryptedCommit(..) => {
+                bail!("page {:?} isn't encrypted", self.page_id);
+            }
+            ServerCommand::PeerOps(..) => {
+                bail!("page {:?} isn't a peer page", self.page_id);
+            }
+            ServerCommand::Signed(..) => unreachable!(),
             ServerCommand::TerminateProxy => {
                 // NOTE we ignore this, it's only used for user proxy
             }
@@ -166,10 +940,36 @@ impl SimpleSocket for ClientSocket {
     }
 
     fn cleanup(&mut self) -> Result<(), Error> {
+        if let Some(ref alive) = self.playback {
+            alive.store(false, Ordering::Relaxed);
+            return Ok(());
+        }
+        if self.watching {
+            let _ = self.tx_master.send(ClientNotify(
+                self.page_id.to_owned(),
+                ClientUpdate::Unwatch {
+                    watcher_id: self.client_id.to_owned(),
+                },
+            ));
+            return Ok(());
+        }
+        if !self.connected {
+            return Ok(());
+        }
+        if self.encrypted {
+            encrypted_disconnect(&self.page_id, &self.client_id);
+            return Ok(());
+        }
+        if self.peer {
+            peer_disconnect(&self.page_id, &self.client_id);
+            return Ok(());
+        }
+
         self.tx_master.send(ClientNotify(
             self.page_id.to_owned(),
             ClientUpdate::Disconnect {
                 client_id: self.client_id.to_owned(),
+                connection: self.connection,
             },
         ));
 
@@ -177,41 +977,650 @@ impl SimpleSocket for ClientSocket {
     }
 }
 
+// Sockets connecting to this path carry several pages at once, as
+// MuxMessages.
+const MUX_PATH: &str = "/$/ws/$mux";
+
+/// Websocket handler for several pages over one connection. Each page is
+/// connected to as if by its own socket, with the same query string.
+struct MuxSocket {
+    tx_master: CCSender<ClientNotify>,
+    db_pool: DbPool,
+    bans: BanList,
+    query: String,
+    remote_addr: Option<String>,
+    cookie: Option<String>,
+    out: simple_ws::Sender,
+    pages: HashMap<String, ClientSocket>,
+}
+
+impl MuxSocket {
+    fn open(&mut self, page_id: String) -> Result<(), Error> {
+        if self.pages.contains_key(&page_id) {
+            return Ok(());
+        }
+        let socket = ClientSocket::initialize(
+            (
+                generate_random_page_id(),
+                self.tx_master.clone(),
+                self.db_pool.clone(),
+                self.bans.clone(),
+            ),
+            &format!("/$/ws/{}?{}", page_id, self.query),
+            self.remote_addr.clone(),
+            self.cookie.clone(),
+            self.out.muxed(&page_id),
+        )?;
+        self.pages.insert(page_id, socket);
+        Ok(())
+    }
+
+    fn close(&mut self, page_id: &str) -> Result<(), Error> {
+        match self.pages.remove(page_id) {
+            Some(mut socket) => socket.cleanup(),
+            None => Ok(()),
+        }
+    }
+
+    fn handle_message(&mut self, data: &[u8]) -> Result<(), Error> {
+        match serde_json::from_slice::<MuxMessage<serde_json::Value>>(data)? {
+            MuxMessage::Open(page_id) => self.open(page_id),
+            MuxMessage::Frame(page_id, command) => {
+                let handled = match self.pages.get_mut(&page_id) {
+                    Some(socket) => socket.handle_message(&serde_json::to_vec(&command)?),
+                    None => bail!("page {:?} isn't open on this connection", page_id),
+                };
+                // Only the page whose message couldn't be handled is closed.
+                if let Err(err) = handled {
+                    let (reason, message) = close_reason_for(&err);
+                    eprintln!(
+                        "(!) Closing page {:?} after a message it couldn't handle ({:?}): {:?}",
+                        page_id, reason, err
+                    );
+                    close_client(&self.out.muxed(&page_id), reason, &message);
+                    self.close(&page_id)?;
+                }
+                Ok(())
+            }
+            MuxMessage::Close(page_id, _) => self.close(&page_id),
+        }
+    }
+
+    fn cleanup(&mut self) -> Result<(), Error> {
+        for (_, mut socket) in self.pages.drain() {
+            socket.cleanup()?;
+        }
+        Ok(())
+    }
+}
+
+/// Websocket handler for a page, or for several on a multiplexed connection.
+enum SyncSocket {
+    Page(ClientSocket),
+    Mux(MuxSocket),
+}
+
+impl SimpleSocket for SyncSocket {
+    type Args = <ClientSocket as SimpleSocket>::Args;
+
+    fn initialize(
+        (client_id, tx_master, db_pool, bans): Self::Args,
+        url: &str,
+        remote_addr: Option<String>,
+        cookie: Option<String>,
+        out: simple_ws::Sender,
+    ) -> Result<SyncSocket, Error> {
+        let parsed = Url::parse("http://localhost/").unwrap().join(url).unwrap();
+        if parsed.path() != MUX_PATH {
+            let socket = ClientSocket::initialize(
+                (client_id, tx_master, db_pool, bans),
+                url,
+                remote_addr,
+                cookie,
+                out,
+            )?;
+            return Ok(SyncSocket::Page(socket));
+        }
+
+        eprintln!("(!) Client {:?} multiplexing pages", client_id);
+        Ok(SyncSocket::Mux(MuxSocket {
+            tx_master,
+            db_pool,
+            bans,
+            query: parsed.query().unwrap_or("").to_string(),
+            remote_addr,
+            cookie,
+            out,
+            pages: HashMap::new(),
+        }))
+    }
+
+    fn handle_message(&mut self, data: &[u8]) -> Result<(), Error> {
+        match self {
+            SyncSocket::Page(socket) => socket.handle_message(data),
+            SyncSocket::Mux(socket) => socket.handle_message(data),
+        }
+    }
+
+    fn cleanup(&mut self) -> Result<(), Error> {
+        match self {
+            SyncSocket::Page(socket) => socket.cleanup(),
+            SyncSocket::Mux(socket) => socket.cleanup(),
+        }
+    }
+}
+
 pub struct PageController {
     page_id: String,
     db_pool: DbPool,
     state: SyncState,
     clients: HashMap<String, simple_ws::Sender>,
+    users: HashMap<String, String>,   // client_id -> user_id
+    saved_carets: HashMap<String, Op>, // user_id -> op restoring their carets
+    // Users whose saved carets moved since they were last written.
+    unwritten_carets: BTreeSet<String>,
+    remote_addrs: HashMap<String, String>, // client_id -> remote address
+    sessions: HashMap<String, String>,     // client_id -> session cookie
+    // Client holding the write lock, in single-writer mode.
+    writer: Option<String>,
+    // Share token of clients that connected with a link.
+    shares: HashMap<String, String>,
+    // What each client may do.
+    permissions: HashMap<String, SharePermission>,
+    identities: HashMap<String, UserIdentity>, // client_id -> user
+    registry: PageRegistry,
+    // Our title, as last announced to other pages.
+    title: String,
+    // Titles of the pages we link to, or None for pages that don't exist.
+    link_titles: HashMap<String, Option<String>>,
+    // Users mentioned in the document, and the blocks they're mentioned in.
+    mentions: BTreeSet<(String, Option<String>)>,
+    // Number of each client's socket.
+    connections: HashMap<String, usize>,
+    // Sockets watching the page's events.
+    watchers: HashMap<String, simple_ws::Sender>,
+    // The version of the document last sent to a connecting client, and
+    // its elements, serialized by `init_elements`.
+    init_elements: Option<(usize, Vec<String>)>,
+    // Whether the operation being committed was made by a hook.
+    committing_hook: bool,
+    // When the memory estimate was last reported to the registry.
+    memory_reported: Option<Instant>,
 }
 
 impl PageController {
     // This is just a commit across all operations, and forwarding it to
     // all listening clients. It also is the commit point for all new
-    // operations.
-    fn sync_commit(&mut self, client_id: &str, op: Op, input_version: usize) {
-        // TODO we should evict the client if this fails.
+    // operations. Operations a client gave an id are acknowledged to it
+    // by that id.
+    fn sync_commit(
+        &mut self,
+        client_id: &str,
+        op_id: Option<u64>,
+        op: Op,
+        input_version: usize,
+    ) -> Result<(), Error> {
+        let op = self.state.commit(&client_id, op, input_version)?;
+        if let Some(op_id) = op_id {
+            let version = self.state.version;
+            self.state.record_applied(client_id, op_id, version);
+        }
+
+        // Append the operation to this page's recording.
+        if let Err(err) = record_page(
+            &self.db_pool,
+            &self.page_id,
+            &PageRecord::Commit(client_id.to_string(), op.clone()),
+        ) {
+            eprintln!("(!) could not record operation: {:?}", err);
+        }
+
+        // Keep the carets of users who left in step with the document.
+        self.transform_saved_carets(&op);
+
+        self.publish_event(&DocumentEvent::OpApplied {
+            version: self.state.version,
+            client_id: client_id.to_string(),
+            op: op.clone(),
+        });
+
+        search_index_op(&self.page_id, &self.state.doc, &op);
+
+        // Updates the database with the new document version.
+        self.save_page();
+
+        // Broadcast this operation to all connected websockets, except the
+        // one it came from if it's to be acknowledged.
+        let command = ClientCommand::Update(self.state.version, client_id.to_owned(), op.clone());
+        match op_id {
+            Some(op_id) => {
+                let json = serde_json::to_string(&command).unwrap();
+                for (id, client) in &self.clients {
+                    if id != client_id {
+                        let _ = client.send(json.clone());
+                    }
+                }
+                if let Some(client) = self.clients.get(client_id) {
+                    let ack = ClientCommand::Ack(op_id, self.state.version);
+                    let _ = self.send_client_command(client, &ack);
+                }
+            }
+            None => self.broadcast_client_command(&command),
+        }
+
+        // Keep document links and pages linking to us up to date.
+        if let Some(titles) = self.refresh_link_titles() {
+            self.broadcast_client_command(&ClientCommand::PageTitles(titles));
+        }
+        self.publish_title();
+
+        // Let users know they were mentioned.
+        self.refresh_mentions(client_id);
+
+        if !self.committing_hook {
+            let doc = self.state.doc.clone();
+            let follow_ups = content_hooks()
+                .into_iter()
+                .filter_map(|hook| {
+                    let op = hook.on_commit(&self.page_id, &doc, &op)?;
+                    Some((hook, op))
+                })
+                .collect::<Vec<_>>();
+            self.commit_hook_ops(follow_ups);
+        }
+
+        Ok(())
+    }
+
+    /// Commit the operations hooks answered with, each transformed past
+    /// those committed before it.
+    fn commit_hook_ops(&mut self, follow_ups: Vec<(Arc<ContentHook>, Op)>) {
+        let version = self.state.version;
+        for (hook, op) in follow_ups {
+            let client_id = format!("{}{}", HOOK_CLIENT_PREFIX, hook.name());
+            self.committing_hook = true;
+            let result = self.sync_commit(&client_id, None, op, version);
+            self.committing_hook = false;
+            if let Err(err) = result {
+                eprintln!("(!) could not apply content hook {:?}: {:?}", hook.name(), err);
+            }
+        }
+    }
+
+    /// Give hooks a chance to change the document before it's saved.
+    fn run_save_hooks(&mut self) {
+        let doc = self.state.doc.clone();
+        let follow_ups = content_hooks()
+            .into_iter()
+            .filter_map(|hook| {
+                let op = hook.on_save(&self.page_id, &doc)?;
+                Some((hook, op))
+            })
+            .collect::<Vec<_>>();
+        self.commit_hook_ops(follow_ups);
+    }
+
+    /// Notify users of mentions of them added since last time.
+    fn refresh_mentions(&mut self, client_id: &str) {
+        let mentions = mentioned_users(&self.state.doc.0);
+        let added = mentions
+            .difference(&self.mentions)
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        self.mentions = mentions;
+        if added.is_empty() {
+            return;
+        }
+
+        let author = self.author_name(client_id);
+        let notified = self
+            .db_pool
+            .get()
+            .map_err(Error::from)
+            .and_then(|conn| notify_mentions(&conn, &self.registry, &self.page_id, &author, &added));
+        if let Err(err) = notified {
+            eprintln!("(!) could not record mentions: {:?}", err);
+        }
+    }
+
+    /// Send a user's unseen mentions to the given clients.
+    fn send_mentions(&self, user_id: &str, client_ids: &[String]) {
+        let mentions = match self
+            .db_pool
+            .get()
+            .map_err(Error::from)
+            .and_then(|conn| unseen_mentions(&conn, user_id))
+        {
+            Ok(mentions) => mentions,
+            Err(err) => {
+                eprintln!("(!) could not load mentions of {:?}: {:?}", user_id, err);
+                return;
+            }
+        };
+        if mentions.is_empty() {
+            return;
+        }
+        let command = ClientCommand::Mentions(mentions);
+        for client_id in client_ids {
+            if let Some(client) = self.clients.get(client_id) {
+                let _ = self.send_client_command(client, &command);
+            }
+        }
+    }
+
+    /// Copy the current document, and optionally its recording, into a new
+    /// page. Returns the new page's id.
+    fn duplicate_page(&self, history: bool) -> Result<String, Error> {
+        let doc = remove_carets(&self.state.doc)?;
+        let conn = self.db_pool.get()?;
+
+        let mut page_id = generate_random_page_id();
+        while get_single_page_raw(&conn, &page_id).is_some() {
+            page_id = generate_random_page_id();
+        }
+
+        // The copy's recording starts with the original's, and continues
+        // from the document we copied when it's first loaded.
+        if history {
+            for row in select_page_records(&conn, &self.page_id)? {
+                create_page_record(&conn, &page_id, row.created, &row.body)?;
+            }
+        }
+
+        create_page(&conn, &page_id, &doc);
+        upsert_fork(&conn, &page_id, &self.page_id, &doc)?;
+        Ok(page_id)
+    }
+
+    /// Three-way merge of a copy of this page. The copy's changes since it
+    /// was made (or last merged) are transformed against our changes since
+    /// then, and committed as one operation.
+    fn merge_fork(&mut self, fork_id: &str) -> Result<(), Error> {
+        let conn = self.db_pool.get()?;
+        let fork = select_fork(&conn, fork_id)?
+            .filter(|fork| fork.source_id == self.page_id)
+            .ok_or_else(|| format_err!("page {:?} is not a copy of {:?}", fork_id, self.page_id))?;
+        let ancestor = Doc(::ron::de::from_str(&fork.ancestor)?);
+        let forked = get_single_page(&conn, fork_id)
+            .ok_or_else(|| format_err!("copy {:?} has no document", fork_id))?;
+
+        let ours = diff(&ancestor, &self.state.doc);
+        let theirs = diff(&ancestor, &forked);
+        let (merged, _) = Op::transform::<RtfSchema>(&ours, &theirs);
+
+        let version = self.state.version;
+        self.sync_commit(&format!("$merge-{}", fork_id), None, merged, version)?;
+
+        // Later merges only bring over what changed after this one.
+        upsert_fork(&conn, fork_id, &self.page_id, &forked)?;
+        Ok(())
+    }
+
+    /// Apply an edit made to the page's file, merged with the changes made to
+    /// the page since the file last held `base`. Without a base, the page as
+    /// it is stands in for one, so only the carets are transformed through.
+    fn apply_external_edit(&mut self, base: Option<&Doc>, doc: &Doc) -> Result<(), Error> {
+        let base = match base {
+            Some(base) => base.clone(),
+            None => remove_carets(&self.state.doc)?,
+        };
+        let edit = merge_external_edit(&base, &self.state.doc, doc)?;
+
+        let version = self.state.version;
+        self.sync_commit("$fs", None, edit, version)
+    }
+
+    /// Find a query in the document as of `version`, marking the matches
+    /// with an operation transformed to apply to the current version.
+    /// Returns the match count and the operation.
+    fn find(&self, query: &str, version: usize) -> Result<(usize, Op), Error> {
+        let doc = if version == self.state.version {
+            &self.state.doc
+        } else {
+            self.state
+                .snapshots
+                .get(&version)
+                .ok_or_else(|| format_err!("version {} is no longer available", version))?
+        };
+        let (op, count) = find_markers_op(doc, query);
         let op = self
             .state
-            .commit(&client_id, op, input_version)
-            .expect("Could not commit client operation.");
+            .update_operation_to_current(op, version, self.state.version)?;
+        Ok((count, op))
+    }
 
-        // Updates the database with the new document version.
+    fn save_page(&self) {
         if let Ok(doc) = remove_carets(&self.state.doc) {
             let conn = self.db_pool.get().unwrap();
             // TODO why is this "create" page
             create_page(&conn, &self.page_id, &doc);
+            mirror_page(&self.page_id, &doc);
+            fs_sync_page(&self.page_id, &doc);
+        }
+    }
+
+    /// Resolve the titles of pages we've started linking to since last time,
+    /// and forget pages we no longer link to. Returns the new titles, if
+    /// there are any.
+    fn refresh_link_titles(&mut self) -> Option<Vec<(String, Option<String>)>> {
+        let targets = linked_pages(&self.state.doc.0);
+        self.link_titles.retain(|page_id, _| targets.contains(page_id));
+
+        let conn = self.db_pool.get().ok()?;
+        let mut added = vec![];
+        for page_id in targets {
+            if !self.link_titles.contains_key(&page_id) {
+                let title = get_single_page_raw(&conn, &page_id).map(|page| page.title);
+                self.link_titles.insert(page_id.clone(), title.clone());
+                added.push((page_id, title));
+            }
+        }
+        if added.is_empty() {
+            None
+        } else {
+            Some(added)
+        }
+    }
+
+    /// Tell every loaded page our title, so their links to us show it.
+    fn announce_title(&self) {
+        let registry = self.registry.lock().unwrap();
+        for (page_id, status) in registry.iter() {
+            if page_id != &self.page_id {
+                let _ = status.queue.send(ClientUpdate::PageTitle {
+                    page_id: self.page_id.clone(),
+                    title: self.title.clone(),
+                });
+            }
         }
+    }
+
+    fn publish_title(&mut self) {
+        let title = doc_title(&self.state.doc.0).unwrap_or_default();
+        if title != self.title {
+            self.title = title;
+            self.announce_title();
+            self.publish_event(&DocumentEvent::TitleChanged {
+                title: self.title.clone(),
+            });
+        }
+    }
 
-        // Broadcast this operation to all connected websockets.
-        let command = ClientCommand::Update(self.state.version, client_id.to_owned(), op);
-        self.broadcast_client_command(&command);
+    /// Send an event to every socket watching the page.
+    fn publish_event(&self, event: &DocumentEvent) {
+        if self.watchers.is_empty() {
+            return;
+        }
+        let json = serde_json::to_string(event).unwrap();
+        for (_, watcher) in &self.watchers {
+            let _ = watcher.send(json.clone());
+        }
+    }
+
+    /// Publish this page's state to the admin API.
+    fn report_status(&mut self) {
+        let mut registry = self.registry.lock().unwrap();
+        if let Some(status) = registry.get_mut(&self.page_id) {
+            status.version = self.state.version;
+            status.last_active = Instant::now();
+
+            // Estimating memory walks the whole page, so it's done at most
+            // once an interval.
+            let interval = Duration::from_secs(MEMORY_REPORT_INTERVAL);
+            if self.memory_reported.map(|at| at.elapsed() >= interval).unwrap_or(true) {
+                status.memory = self.state.memory_estimate();
+                self.memory_reported = Some(Instant::now());
+            }
+            status.clients = self
+                .clients
+                .keys()
+                .map(|client_id| ClientStatus {
+                    id: client_id.clone(),
+                    user_id: self.users.get(client_id).cloned(),
+                    name: self.identities.get(client_id).map(|x| x.name.clone()),
+                    remote_addr: self.remote_addrs.get(client_id).cloned(),
+                    session: self.sessions.get(client_id).cloned(),
+                    share_token: self.shares.get(client_id).cloned(),
+                })
+                .collect();
+            status.clients.sort_by(|a, b| a.id.cmp(&b.id));
+            status.watchers = self.watchers.len();
+        }
+    }
+
+    /// Keep saved carets in step with an operation. They're written to the
+    /// database when the page is next saved by `write_saved_carets`.
+    fn transform_saved_carets(&mut self, op: &Op) {
+        for (user_id, saved) in self.saved_carets.iter_mut() {
+            let (next, _) = Op::transform::<RtfSchema>(op, saved);
+            if next != *saved {
+                *saved = next;
+                self.unwritten_carets.insert(user_id.clone());
+            }
+        }
+    }
+
+    fn write_saved_carets(&mut self) {
+        if self.unwritten_carets.is_empty() {
+            return;
+        }
+        let conn = self.db_pool.get().unwrap();
+        for user_id in ::std::mem::replace(&mut self.unwritten_carets, BTreeSet::new()) {
+            if let Some(saved) = self.saved_carets.get(&user_id) {
+                if let Ok(body) = ::ron::ser::to_string(saved) {
+                    let _ = upsert_saved_caret(&conn, &self.page_id, &user_id, &body);
+                }
+            }
+        }
+    }
+
+    /// Remember where a departing client's carets were, so they can be
+    /// restored when its user reconnects. `op` applies to the document
+    /// after the client's carets were removed.
+    fn save_carets(&mut self, user_id: String, op: Op) {
+        let conn = self.db_pool.get().unwrap();
+        if let Ok(body) = ::ron::ser::to_string(&op) {
+            let _ = upsert_saved_caret(&conn, &self.page_id, &user_id, &body);
+        }
+        self.unwritten_carets.remove(&user_id);
+        self.saved_carets.insert(user_id, op);
+    }
+
+    /// Reinsert a reconnecting user's carets for their new client.
+    fn restore_carets(&mut self, client_id: &str, user_id: &str) {
+        let op = match self.saved_carets.remove(user_id) {
+            Some(op) => assign_carets_op(&op, client_id),
+            None => return,
+        };
+        self.unwritten_carets.remove(user_id);
+        let conn = self.db_pool.get().unwrap();
+        let _ = delete_saved_caret(&conn, &self.page_id, user_id);
+
+        // Saved carets can go stale if the server restarted before they
+        // were transformed against the last operations, so check first.
+        let version = self.state.version;
+        if self.state.validate_incoming(&op, version).is_err() {
+            eprintln!("(!) discarded stale caret for user {:?}", user_id);
+            return;
+        }
+        if let Err(err) = self.sync_commit(client_id, None, op, version) {
+            eprintln!("(!) could not restore caret for {:?}: {:?}", user_id, err);
+        }
+    }
+
+    /// Tell every client who is editing the document.
+    fn broadcast_users(&self) {
+        let mut users = self
+            .identities
+            .iter()
+            .map(|(client_id, identity)| (client_id.clone(), identity.clone()))
+            .collect::<Vec<_>>();
+        users.sort_by(|a, b| a.0.cmp(&b.0));
+        self.broadcast_client_command(&ClientCommand::Users(users));
+    }
+
+    /// The name a client's changes are attributed to.
+    fn author_name(&self, client_id: &str) -> String {
+        self.identities
+            .get(client_id)
+            .map(|identity| identity.name.clone())
+            .unwrap_or_else(|| client_id.to_string())
+    }
+
+    /// The page's comment threads, or None if they couldn't be loaded.
+    fn comment_threads(&self) -> Option<Vec<CommentThread>> {
+        let threads = self
+            .db_pool
+            .get()
+            .map_err(Error::from)
+            .and_then(|conn| page_comment_threads(&conn, &self.page_id));
+        match threads {
+            Ok(threads) => Some(threads),
+            Err(err) => {
+                eprintln!("(!) could not load comments of {:?}: {:?}", self.page_id, err);
+                None
+            }
+        }
+    }
+
+    /// Whether a client may edit. Clients we don't know of can't.
+    fn can_edit(&self, client_id: &str) -> bool {
+        self.permissions
+            .get(client_id)
+            .map(|permission| permission.can_edit())
+            .unwrap_or(false)
+    }
+
+    /// Quarantine a client's operation: tell the client why it was refused,
+    /// then resynchronize it with the current document.
+    fn refuse_client(&mut self, client_id: &str, reason: RefusalReason) {
+        eprintln!("(!) refused operation from {:?}: {:?}", client_id, reason);
+
+        if let Some(status) = self.registry.lock().unwrap().get_mut(&self.page_id) {
+            status.record_error();
+        }
+
+        let version = self.state.version;
+        if let Some(client) = self.clients.get(client_id) {
+            let _ = self.send_client_command(client, &ClientCommand::Refuse(reason));
+            let _ = self.send_client_command(
+                client,
+                &ClientCommand::Init(client_id.to_string(), self.state.doc.0.clone(), version),
+            );
+        }
+
+        // The client has discarded its pending operations and now starts
+        // from the current version.
+        if let Some(client_version) = self.state.clients.get_mut(client_id) {
+            *client_version = version;
+        }
     }
 
     /// Forward command to everyone in our client set.
     fn broadcast_client_command(&self, command: &ClientCommand) {
         let json = serde_json::to_string(&command).unwrap();
         for (_, client) in &self.clients {
-            let _ = client.lock().unwrap().send(json.clone());
+            let _ = client.send(json.clone());
         }
     }
 
@@ -221,27 +1630,39 @@ impl PageController {
         command: &ClientCommand,
     ) -> Result<(), Error> {
         let json = serde_json::to_string(&command).unwrap();
-        Ok(client.lock().unwrap().send(json.clone())?)
+        Ok(client.send(json.clone())?)
+    }
+
+    // The current version of the document as sent to connecting clients,
+    // serialized once for all of them.
+    fn init_elements(&mut self) -> &[String] {
+        let version = self.state.version;
+        let stale = match self.init_elements {
+            Some((cached, _)) => cached != version,
+            None => true,
+        };
+        if stale {
+            self.init_elements = Some((version, init_elements(&self.state.doc.0)));
+        }
+        &self.init_elements.as_ref().unwrap().1
     }
 
     fn send_client_restart(&self, client_id: &str) -> Result<(), Error> {
-        let code = ws::CloseCode::Restart;
         let reason = "Server received an updated version of the document.";
 
         // TODO abort if client doesn't exist, or move the client_id referencing
         // to its own function
         self.clients.get(client_id).map(|client| {
-            let _ = client.lock().unwrap().close_with_reason(code, reason);
+            close_client(client, CloseReason::Reloaded, reason);
         });
         Ok(())
     }
 
     /// Forward restart code to everyone in our client set.
     fn broadcast_restart(&self) -> Result<(), Error> {
-        let code = ws::CloseCode::Restart;
         let reason = "Server received an updated version of the document.";
         for (_, client) in &self.clients {
-            let _ = client.lock().unwrap().close_with_reason(code, reason);
+            close_client(client, CloseReason::Reloaded, reason);
         }
         Ok(())
     }
@@ -249,37 +1670,221 @@ impl PageController {
     // Handle a client's update.
     fn handle(&mut self, notification: ClientUpdate) {
         match notification {
-            ClientUpdate::Connect { client_id, out } => {
-                let version = self.state.version;
+            ClientUpdate::Connect {
+                client_id,
+                user_id,
+                remote_addr,
+                session,
+                share_token,
+                permission,
+                identity,
+                protocol,
+                resume,
+                connection,
+                out,
+            } => {
+                // A client reconnecting with its id may still have its old
+                // socket here, if we didn't notice it close.
+                if let Some(old) = self.clients.get(&client_id) {
+                    close_client(old, CloseReason::Kicked, "You connected again elsewhere.");
+                }
+                self.connections.insert(client_id.to_string(), connection);
 
-                // Initialize client state on outgoing websocket.
-                let command = ClientCommand::Init(
-                    client_id.to_string(),
-                    self.state.doc.0.clone(),
-                    version,
-                );
-                let _ = self.send_client_command(&out, &command);
+                if let Some(remote_addr) = remote_addr {
+                    self.remote_addrs.insert(client_id.to_string(), remote_addr);
+                }
+                if let Some(session) = session {
+                    self.sessions.insert(client_id.to_string(), session);
+                }
 
                 // Register with clients list.
+                self.state
+                    .clients
+                    .insert(client_id.to_string(), self.state.version);
+
+                // Restore the user's caret, so that it's in the document the
+                // client initializes with.
+                if let Some(user_id) = user_id {
+                    self.restore_carets(&client_id, &user_id);
+                    self.users.insert(client_id.to_string(), user_id);
+                }
+
+                // Clients with a share link learn what it allows before their
+                // Init, like the writer below.
+                self.permissions.insert(client_id.to_string(), permission);
+                if let Some(token) = share_token {
+                    let _ = self.send_client_command(&out, &ClientCommand::Permission(permission));
+                    self.shares.insert(client_id.to_string(), token);
+                }
+
+                // In single-writer mode, the first client to arrive at an
+                // unlocked document takes the lock. Clients learn who holds
+                // it before their Init, so that read-only clients don't try
+                // to add a caret.
+                if single_writer_mode() {
+                    if self.writer.is_none() && self.can_edit(&client_id) {
+                        self.writer = Some(client_id.to_string());
+                    }
+                    let _ = self.send_client_command(&out, &ClientCommand::Writer(self.writer.clone()));
+                }
+
+                // A client reconnecting with its id learns which of its
+                // operations we applied before Init, so that it doesn't apply
+                // them again over the document.
+                if let Some((op_id, version)) = self.state.last_applied(&client_id) {
+                    let _ = self.send_client_command(&out, &ClientCommand::Ack(op_id, version));
+                }
+
+                let version = self.state.version;
+
+                // Initialize client state on outgoing websocket. Newer
+                // clients get the document in chunks, and may already have
+                // the first few of them from a connection that dropped.
+                if protocol >= CHUNKED_SYNC_VERSION {
+                    let chunks = {
+                        let elements = self.init_elements();
+                        if protocol >= TEXT_SYNC_VERSION {
+                            init_texts(elements)
+                        } else {
+                            init_chunks(elements)
+                        }
+                    };
+                    let first = match resume {
+                        Some((resume_version, index))
+                            if resume_version == version && index < chunks.len() =>
+                        {
+                            index
+                        }
+                        _ => 0,
+                    };
+                    let count = chunks.len();
+                    for (index, chunk) in chunks.into_iter().enumerate().skip(first) {
+                        let _ = if protocol >= TEXT_SYNC_VERSION {
+                            let command = ClientCommand::InitText(
+                                client_id.to_string(),
+                                version,
+                                index,
+                                count,
+                                chunk,
+                            );
+                            self.send_client_command(&out, &command)
+                        } else {
+                            let json = init_chunk_json(&client_id, version, index, count, &chunk);
+                            out.send(json).map_err(Error::from)
+                        };
+                    }
+                } else {
+                    let command = ClientCommand::Init(
+                        client_id.to_string(),
+                        self.state.doc.0.clone(),
+                        version,
+                    );
+                    let _ = self.send_client_command(&out, &command);
+                }
+
+                // Followed by the titles of the pages we link to.
+                self.refresh_link_titles();
+                if !self.link_titles.is_empty() {
+                    let titles = self
+                        .link_titles
+                        .iter()
+                        .map(|(page_id, title)| (page_id.clone(), title.clone()))
+                        .collect();
+                    let _ = self.send_client_command(&out, &ClientCommand::PageTitles(titles));
+                }
+
                 self.state.clients.insert(client_id.to_string(), version);
 
                 // Forward to all in our client set.
                 self.clients.insert(client_id.to_string(), out);
+
+                // Attribute the client's operations in the recording.
+                if let Err(err) = record_page(
+                    &self.db_pool,
+                    &self.page_id,
+                    &PageRecord::Join(client_id.to_string(), identity.clone()),
+                ) {
+                    eprintln!("(!) could not record client: {:?}", err);
+                }
+
+                // Everyone, including the new client, learns who's here.
+                self.publish_event(&DocumentEvent::UserJoined {
+                    client_id: client_id.to_string(),
+                    user: identity.clone(),
+                });
+                self.identities.insert(client_id.to_string(), identity);
+                self.broadcast_users();
+
+                // Followed by the page's comments.
+                if let Some(threads) = self.comment_threads() {
+                    if !threads.is_empty() {
+                        if let Some(client) = self.clients.get(&client_id) {
+                            let _ = self.send_client_command(client, &ClientCommand::Comments(threads));
+                        }
+                    }
+                }
+
+                // Followed by the mentions the user hasn't seen.
+                if let Some(user_id) = self.users.get(&client_id) {
+                    self.send_mentions(user_id, &[client_id.clone()]);
+                }
             }
 
-            ClientUpdate::Disconnect { client_id } => {
+            ClientUpdate::Disconnect {
+                client_id,
+                connection,
+            } => {
+                // A client that reconnected with its id before we noticed
+                // its old socket close keeps its place.
+                if self.connections.get(&client_id) != Some(&connection) {
+                    return;
+                }
+                self.connections.remove(&client_id);
+
+                let saved = self
+                    .users
+                    .remove(&client_id)
+                    .and_then(|user_id| Some((user_id, save_carets_op(&self.state.doc, &client_id)?)));
+
                 // Remove our caret from document.
                 let op = remove_carets_op(&self.state.doc, vec![client_id.clone()]).unwrap();
                 let version = self.state.version;
-                self.sync_commit(&client_id, op, version);
+                if let Err(err) = self.sync_commit(&client_id, None, op, version) {
+                    eprintln!("(!) could not remove carets for {:?}: {:?}", client_id, err);
+                }
+
+                if let Some((user_id, op)) = saved {
+                    self.save_carets(user_id, op);
+                }
 
                 // Remove from our client set.
                 self.state.clients.remove(&client_id);
                 self.clients.remove(&client_id);
+                self.remote_addrs.remove(&client_id);
+                self.sessions.remove(&client_id);
+                self.shares.remove(&client_id);
+                self.permissions.remove(&client_id);
+                self.identities.remove(&client_id);
+                self.broadcast_users();
+                self.publish_event(&DocumentEvent::UserLeft {
+                    client_id: client_id.to_string(),
+                });
+
+                // Release the write lock until someone takes it over.
+                if self.writer.as_ref() == Some(&client_id) {
+                    self.writer = None;
+                    self.broadcast_client_command(&ClientCommand::Writer(None));
+                }
+
+                // Nobody is left to move the carets of those who left.
+                if self.clients.is_empty() {
+                    self.write_saved_carets();
+                }
             }
 
             ClientUpdate::Commit {
                 client_id,
+                op_id,
                 op,
                 version,
             } => {
@@ -290,29 +1895,249 @@ impl PageController {
                     thread::sleep(Duration::from_millis(delay));
                 }
 
+                // An operation resent after we applied it, as when the ack
+                // was lost in a reconnect race, is a no-op answered with
+                // its original ack.
+                if let Some(version) = op_id.and_then(|op_id| self.state.applied_version(&client_id, op_id)) {
+                    eprintln!("(!) ignored resent operation {:?} from {:?}", op_id, client_id);
+                    if let Some(client) = self.clients.get(&client_id) {
+                        let _ = self.send_client_command(client, &ClientCommand::Ack(op_id.unwrap(), version));
+                    }
+                    return;
+                }
+
+                if single_writer_mode() && self.writer.as_ref() != Some(&client_id) {
+                    self.refuse_client(&client_id, RefusalReason::ReadOnly);
+                    return;
+                }
+                if !self.can_edit(&client_id) {
+                    self.refuse_client(&client_id, RefusalReason::ReadOnly);
+                    return;
+                }
+
+                // Validate the operation before it touches the document.
+                if let Err(reason) = self.state.validate_incoming(&op, version) {
+                    self.refuse_client(&client_id, reason);
+                    return;
+                }
+
                 // Commit the operation.
                 // TODO remove this AssertUnwindSafe, since it's probably not safe.
                 let sync = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
-                    self.sync_commit(&client_id, op, version);
+                    // Size limits apply to the operation as it's committed.
+                    let op = self.state.transform_incoming(op, version)?;
+                    let current = self.state.version;
+                    self.sync_commit(&client_id, op_id, op, current)
+                        .map_err(|err| RefusalReason::MalformedOp(err.to_string()))
                 }));
 
-                if let Err(err) = sync {
-                    eprintln!(
-                        "received invalid packet from client: {:?} - {:?}",
-                        client_id, err
-                    );
-                    // let _ = self.send_client_restart(&client_id);
+                match sync {
+                    Ok(Ok(())) => {}
+                    Ok(Err(reason)) => {
+                        self.refuse_client(&client_id, reason);
+                    }
+                    Err(err) => {
+                        eprintln!(
+                            "received invalid packet from client: {:?} - {:?}",
+                            client_id, err
+                        );
+                        self.refuse_client(
+                            &client_id,
+                            RefusalReason::MalformedOp("operation could not be transformed".to_string()),
+                        );
+                    }
                 }
             }
 
             ClientUpdate::Overwrite { doc } => {
                 let _ = self.broadcast_restart();
 
+                let _ = record_page(&self.db_pool, &self.page_id, &PageRecord::Start(doc.0.clone()));
+
+                // Saved carets don't apply to the new document.
+                let conn = self.db_pool.get().unwrap();
+                let _ = clear_saved_carets(&conn, &self.page_id);
+
                 // Rewrite our state.
                 self.state = SyncState::new(doc, INITIAL_SYNC_VERSION);
                 self.clients = HashMap::new();
+                self.users = HashMap::new();
+                self.saved_carets = HashMap::new();
+                self.unwritten_carets = BTreeSet::new();
+                self.writer = None;
+                self.link_titles = HashMap::new();
+                self.mentions = mentioned_users(&self.state.doc.0);
+                self.publish_title();
+                search_index_page(&self.page_id, &self.state.doc);
+            }
+
+            ClientUpdate::Save => {
+                self.run_save_hooks();
+                self.save_page();
+                self.write_saved_carets();
+            }
+
+            // The page master turns these into Evict, or drops them.
+            ClientUpdate::EvictIdle { .. } => {}
+
+            ClientUpdate::Evict => {
+                self.run_save_hooks();
+                self.save_page();
+                self.write_saved_carets();
+
+                let reason = "The server unloaded this document.";
+                for (_, client) in self.clients.iter().chain(&self.watchers) {
+                    close_client(client, CloseReason::Unloaded, reason);
+                }
+                self.clients = HashMap::new();
+                self.watchers = HashMap::new();
+            }
+
+            // The previous writer's operations still in flight are refused,
+            // so it's resynchronized as a read-only client.
+            ClientUpdate::TakeOver { client_id } => {
+                if single_writer_mode()
+                    && self.clients.contains_key(&client_id)
+                    && self.can_edit(&client_id)
+                {
+                    eprintln!("(!) client {:?} took over editing", client_id);
+                    self.writer = Some(client_id);
+                    self.broadcast_client_command(&ClientCommand::Writer(self.writer.clone()));
+                }
+            }
+
+            ClientUpdate::Duplicate { client_id, history } => {
+                if !self.can_edit(&client_id) {
+                    eprintln!("(!) refused duplicate from {:?}, who can't edit", client_id);
+                    return;
+                }
+                match self.duplicate_page(history) {
+                    Ok(page_id) => {
+                        eprintln!("(!) duplicated {:?} as {:?}", self.page_id, page_id);
+                        if let Some(client) = self.clients.get(&client_id) {
+                            let _ = self.send_client_command(client, &ClientCommand::Duplicated(page_id));
+                        }
+                    }
+                    Err(err) => {
+                        eprintln!("(!) could not duplicate {:?}: {:?}", self.page_id, err);
+                    }
+                }
+            }
+
+            ClientUpdate::ExternalEdit { base, doc } => {
+                let edit = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+                    self.apply_external_edit(base.as_ref(), &doc)
+                }));
+                match edit {
+                    Ok(Ok(())) => {}
+                    Ok(Err(err)) => eprintln!("(!) could not apply edit to {}.md: {:?}", self.page_id, err),
+                    Err(_) => eprintln!("(!) edit to {}.md could not be transformed", self.page_id),
+                }
+            }
+
+            ClientUpdate::MergeFork { fork_id } => {
+                let merge = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+                    self.merge_fork(&fork_id)
+                }));
+                match merge {
+                    Ok(Ok(())) => eprintln!("(!) merged {:?} into {:?}", fork_id, self.page_id),
+                    Ok(Err(err)) => eprintln!("(!) could not merge {:?}: {:?}", fork_id, err),
+                    Err(_) => eprintln!("(!) merging {:?} could not be transformed", fork_id),
+                }
+            }
+
+            ClientUpdate::Find {
+                client_id,
+                query,
+                version,
+            } => {
+                let found = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+                    self.find(&query, version)
+                }));
+                match found {
+                    Ok(Ok((count, op))) => {
+                        if let Some(client) = self.clients.get(&client_id) {
+                            let command = ClientCommand::FindResults(query, self.state.version, count, op);
+                            let _ = self.send_client_command(client, &command);
+                        }
+                    }
+                    Ok(Err(err)) => eprintln!("(!) could not find {:?}: {:?}", query, err),
+                    Err(_) => eprintln!("(!) matches for {:?} could not be transformed", query),
+                }
+            }
+
+            ClientUpdate::PageTitle { page_id, title } => {
+                if let Some(known) = self.link_titles.get_mut(&page_id) {
+                    if known.as_ref() != Some(&title) {
+                        *known = Some(title.clone());
+                        let titles = vec![(page_id, Some(title))];
+                        self.broadcast_client_command(&ClientCommand::PageTitles(titles));
+                    }
+                }
+            }
+
+            ClientUpdate::Comment { client_id, action } => {
+                let author = self.author_name(&client_id);
+                let applied = self
+                    .db_pool
+                    .get()
+                    .map_err(Error::from)
+                    .and_then(|conn| apply_comment_action(&conn, &self.page_id, &author, &action));
+                if let Err(err) = applied {
+                    eprintln!("(!) could not comment for {:?}: {:?}", client_id, err);
+                    return;
+                }
+                if let Some(threads) = self.comment_threads() {
+                    self.broadcast_client_command(&ClientCommand::Comments(threads));
+                }
+            }
+
+            ClientUpdate::Mentioned { user_id } => {
+                let client_ids = self
+                    .users
+                    .iter()
+                    .filter(|(_, id)| **id == user_id)
+                    .map(|(client_id, _)| client_id.clone())
+                    .collect::<Vec<_>>();
+                self.send_mentions(&user_id, &client_ids);
+            }
+
+            ClientUpdate::Watch { watcher_id, out } => {
+                let event = DocumentEvent::Watching {
+                    version: self.state.version,
+                    title: self.title.clone(),
+                };
+                let _ = out.send(serde_json::to_string(&event).unwrap());
+                self.watchers.insert(watcher_id, out);
+            }
+
+            ClientUpdate::Unwatch { watcher_id } => {
+                self.watchers.remove(&watcher_id);
+            }
+
+            ClientUpdate::Kick { client_id, reason } => {
+                eprintln!("(!) kicking client {:?}: {:?}", client_id, reason);
+
+                // The socket's cleanup sends the Disconnect that removes it.
+                if let Some(client) = self.clients.get(&client_id) {
+                    close_client(client, CloseReason::Kicked, &reason);
+                }
+            }
+
+            ClientUpdate::RevokeShare { token } => {
+                for (client_id, client_token) in &self.shares {
+                    if *client_token != token {
+                        continue;
+                    }
+                    eprintln!("(!) disconnecting client {:?}, its share link was revoked", client_id);
+                    if let Some(client) = self.clients.get(client_id) {
+                        close_client(client, CloseReason::Unauthorized, "This share link was revoked.");
+                    }
+                }
             }
         }
+
+        self.report_status();
     }
 }
 
@@ -322,8 +2147,24 @@ pub fn spawn_sync_thread(
     rx_notify: CCReceiver<ClientUpdate>,
     inner_doc: Doc,
     db_pool: DbPool,
-) -> Result<(), Error> {
-    thread::spawn(move || {
+    registry: PageRegistry,
+) -> Result<JoinHandle<()>, Error> {
+    Ok(thread::spawn(move || {
+        // Each sync thread starts a new section of the page's recording.
+        let _ = record_page(&db_pool, &page_id, &PageRecord::Start(inner_doc.0.clone()));
+
+        // Carets of users who left this page.
+        let saved_carets = db_pool
+            .get()
+            .map_err(Error::from)
+            .and_then(|conn| select_saved_carets(&conn, &page_id))
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|row| Some((row.user_id, ::ron::de::from_str(&row.body).ok()?)))
+            .collect();
+
+        let title = doc_title(&inner_doc.0).unwrap_or_default();
+
         // This page ID's state.
         // TODO make this a ::new(...) statement
         let mut sync = PageController {
@@ -331,32 +2172,138 @@ pub fn spawn_sync_thread(
             db_pool,
             state: SyncState::new(inner_doc, INITIAL_SYNC_VERSION),
             clients: HashMap::new(),
+            users: HashMap::new(),
+            saved_carets,
+            unwritten_carets: BTreeSet::new(),
+            remote_addrs: HashMap::new(),
+            sessions: HashMap::new(),
+            writer: None,
+            shares: HashMap::new(),
+            permissions: HashMap::new(),
+            identities: HashMap::new(),
+            registry,
+            title,
+            link_titles: HashMap::new(),
+            mentions: mentioned_users(&inner_doc.0),
+            connections: HashMap::new(),
+            watchers: HashMap::new(),
+            init_elements: None,
+            committing_hook: false,
+            memory_reported: None,
         };
 
+        // Pages that link to us may have thought we didn't exist.
+        sync.announce_title();
+        search_index_page(&sync.page_id, &sync.state.doc);
+
         while let Some(notification) = rx_notify.recv() {
             // let now = Instant::now()
 
+            let evict = match notification {
+                ClientUpdate::Evict => true,
+                _ => false,
+            };
+
             // TODO with need to listen for errors and break the loop if erorrs occurr
             // (killin the sync thread).
             sync.handle(notification);
 
+            if evict {
+                println!("(%) evicted page {:?}", sync.page_id);
+                break;
+            }
+
             // let elapsed = now.elapsed();
             // println!("sync duration: {}s, {}us", elapsed.as_secs(), elapsed.subsec_nanos()/1_000);
         }
-    });
-    Ok(())
+    }))
+}
+
+// What the page master knows of a loaded page's use, from the notifications
+// it forwarded.
+struct PageActivity {
+    last_active: Instant,
+    clients: usize,
 }
 
 struct PageMaster {
     db_pool: DbPool,
     pages: HashMap<String, CCSender<ClientUpdate>>,
+    activity: HashMap<String, PageActivity>,
+    threads: HashMap<String, JoinHandle<()>>,
+    // Sync threads of evicted pages, which may still be saving them.
+    evicting: HashMap<String, JoinHandle<()>>,
+    registry: PageRegistry,
 }
 
 impl PageMaster {
-    fn new(db_pool: DbPool) -> PageMaster {
+    fn new(db_pool: DbPool, registry: PageRegistry) -> PageMaster {
         PageMaster {
             db_pool,
             pages: hashmap![],
+            activity: hashmap![],
+            threads: hashmap![],
+            evicting: hashmap![],
+            registry,
+        }
+    }
+
+    fn notify(&mut self, page_id: &str, notification: ClientUpdate) {
+        // Watchers keep a page loaded, like clients.
+        let clients_change = match notification {
+            ClientUpdate::Connect { .. } | ClientUpdate::Watch { .. } => 1,
+            ClientUpdate::Disconnect { .. } | ClientUpdate::Unwatch { .. } => -1,
+            _ => 0,
+        };
+
+        match notification {
+            // Admin commands, disconnects, and unwatches only apply to pages
+            // already in memory. (Clients of an evicted page disconnect after
+            // it's gone.)
+            ClientUpdate::Save | ClientUpdate::Disconnect { .. } | ClientUpdate::Unwatch { .. } => {
+                if let Some(page) = self.pages.get(page_id) {
+                    let _ = page.send(notification);
+                }
+            }
+            ClientUpdate::Evict => {
+                self.evict(page_id);
+                return;
+            }
+            ClientUpdate::EvictIdle { checked } => {
+                let idle = self
+                    .activity
+                    .get(page_id)
+                    .map(|activity| activity.clients == 0 && activity.last_active <= checked)
+                    .unwrap_or(false);
+                if idle {
+                    self.evict(page_id);
+                }
+                return;
+            }
+            _ => {
+                let _ = self.acquire_page(page_id).send(notification);
+            }
+        }
+
+        if let Some(activity) = self.activity.get_mut(page_id) {
+            activity.last_active = Instant::now();
+            activity.clients = if clients_change < 0 {
+                activity.clients.saturating_sub(1)
+            } else {
+                activity.clients + clients_change as usize
+            };
+        }
+    }
+
+    /// Save the page, disconnect its clients, and unload it.
+    fn evict(&mut self, page_id: &str) {
+        self.registry.lock().unwrap().remove(page_id);
+        self.activity.remove(page_id);
+        if let Some(page) = self.pages.remove(page_id) {
+            let _ = page.send(ClientUpdate::Evict);
+        }
+        if let Some(thread) = self.threads.remove(page_id) {
+            self.evicting.insert(page_id.to_string(), thread);
         }
     }
 
@@ -365,6 +2312,13 @@ impl PageMaster {
     fn acquire_page(&mut self, page_id: &str) -> CCSender<ClientUpdate> {
         // If this page doesn't exist, let's allocate a new thread for it.
         if self.pages.get(page_id).is_none() {
+            // Notifications are handled one at a time, so an evicted page's
+            // thread gets no more of them. Wait for it to save the page
+            // before loading it again.
+            if let Some(thread) = self.evicting.remove(page_id) {
+                let _ = thread.join();
+            }
+
             println!("(%) loading new page for {:?}", page_id);
 
             // Retrieve from database, or use a default generic document.
@@ -372,16 +2326,33 @@ impl PageMaster {
             let inner_doc =
                 get_single_page(&conn, page_id).unwrap_or_else(|| default_new_doc(page_id));
 
+            // Give blocks from older documents ids to link to.
+            let inner_doc = Doc(assign_block_ids(&inner_doc.0));
+
             let (tx_notify, rx_notify) = unbounded();
             self.pages.insert(page_id.to_string(), tx_notify.clone());
+            self.activity.insert(
+                page_id.to_string(),
+                PageActivity {
+                    last_active: Instant::now(),
+                    clients: 0,
+                },
+            );
+            self.registry
+                .lock()
+                .unwrap()
+                .insert(page_id.to_string(), PageStatus::new(tx_notify.clone()));
 
             // We ignore all errors from the sync thread, and thus the whole thread.
-            let _ = spawn_sync_thread(
+            if let Ok(thread) = spawn_sync_thread(
                 page_id.to_owned(),
                 rx_notify,
                 inner_doc,
                 self.db_pool.clone(),
-            );
+                self.registry.clone(),
+            ) {
+                self.threads.insert(page_id.to_string(), thread);
+            }
             tx_notify
         } else {
             self.pages.get(page_id).map(|x| x.clone()).unwrap()
@@ -390,12 +2361,16 @@ impl PageMaster {
 }
 
 // TODO make this coordinate properly with
-fn spawn_page_master(db_pool: DbPool, rx_master: CCReceiver<ClientNotify>) {
+fn spawn_page_master(
+    db_pool: DbPool,
+    registry: PageRegistry,
+    rx_master: CCReceiver<ClientNotify>,
+) {
     thread::spawn(move || {
-        let mut page_map = PageMaster::new(db_pool);
+        let mut page_map = PageMaster::new(db_pool, registry);
 
         while let Some(ClientNotify(page_id, notification)) = rx_master.recv() {
-            let _ = page_map.acquire_page(&page_id).send(notification);
+            page_map.notify(&page_id, notification);
         }
     });
 }
@@ -409,15 +2384,36 @@ pub fn sync_socket_server(port: u16) {
 
     log_sync!("SERVER", Spawn);
 
+    // Signed commands are only accepted from when the server started.
+    ::lazy_static::initialize(&SEEN_NONCES);
+
+    // Index existing pages for search before any page is loaded, so pages
+    // being edited aren't indexed from the database again over their edits.
+    build_search_index(&db_pool.get().unwrap());
+
     // Spawn master coordination thread.
+    let registry = page_registry_create();
+    let bans = ban_list_create();
     let (tx_master, rx_master) = unbounded::<ClientNotify>();
-    spawn_page_master(db_pool.clone(), rx_master);
+    spawn_page_master(db_pool.clone(), registry.clone(), rx_master);
+
+    // Transform pages as they're edited, if hooks are configured.
+    register_default_hooks();
+
+    // Watch the sync directory for edits, if there is one.
+    spawn_fs_watcher(tx_master.clone());
+
+    // Unload idle pages when over the memory budget, if there is one.
+    spawn_memory_watcher(registry.clone(), tx_master.clone());
+
+    // Compact recordings to the retention policy, if there is one.
+    spawn_retention_gc(db_pool.clone());
 
     // Start the GraphQL server.
     ::std::thread::spawn({
-        take!(=db_pool, =tx_master);
+        take!(=db_pool, =tx_master, =registry, =bans);
         move || {
-            sync_graphql_server(db_pool, tx_master);
+            sync_graphql_server(db_pool, tx_master, registry, bans);
         }
     });
 
@@ -430,20 +2426,22 @@ pub fn sync_socket_server(port: u16) {
 
     // Start the WebSocket listener.
     let _ = ws::listen(url, {
-        take!(=tx_master);
+        take!(=tx_master, =db_pool, =bans);
         move |out| {
             log_sync!("SERVER", ClientConnect);
 
             eprintln!("Client connected.");
 
             // Listen to commands from the clients and submit to sync server.
-            SocketHandler::<ClientSocket>::new(
+            MaybeDeflate::new(SocketHandler::<SyncSocket>::new(
                 (
                     generate_random_page_id(), // TODO can we select from unused client IDs?
                     tx_master.clone(),
+                    db_pool.clone(),
+                    bans.clone(),
                 ),
                 out,
-            )
+            ))
         }
     });
 }
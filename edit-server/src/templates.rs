@@ -0,0 +1,85 @@
+//! Document templates. Any page can be marked as a template, and new pages
+//! created from it start as a copy with its variables, like "{{date}}",
+//! filled in.
+
+use oatie::doc::*;
+use std::collections::HashMap;
+use std::time::{
+    SystemTime,
+    UNIX_EPOCH,
+};
+
+// Year, month, and day of a count of days since 1970-01-01, in the
+// proleptic Gregorian calendar.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Variables available to templates when creating the page `id`. Times are
+/// in UTC.
+pub fn template_variables(id: &str) -> HashMap<String, String> {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs() as i64)
+        .unwrap_or(0);
+    let (year, month, day) = civil_from_days(secs / 86400);
+    let time = secs % 86400;
+
+    hashmap! {
+        "date".to_string() => format!("{:04}-{:02}-{:02}", year, month, day),
+        "time".to_string() => format!("{:02}:{:02}", time / 3600, (time / 60) % 60),
+        "year".to_string() => year.to_string(),
+        "id".to_string() => id.to_string(),
+    }
+}
+
+// Replace "{{name}}" with its variable. Unknown variables are left as they
+// are, so templates can show literal braces.
+fn substitute_text(text: &str, vars: &HashMap<String, String>) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let end = match rest[start + 2..].find("}}") {
+            Some(len) => start + 2 + len,
+            None => break,
+        };
+        out.push_str(&rest[..start]);
+        match vars.get(rest[start + 2..end].trim()) {
+            Some(value) => out.push_str(value),
+            None => out.push_str(&rest[start..end + 2]),
+        }
+        rest = &rest[end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// A copy of a template's content with its variables filled in. Variables
+/// are only found within runs of identically styled text.
+pub fn substitute_variables(span: &DocSpan, vars: &HashMap<String, String>) -> DocSpan {
+    span.iter()
+        .map(|elem| match elem {
+            DocGroup(attrs, inner) => DocGroup(attrs.clone(), substitute_variables(inner, vars)),
+            DocChars(text) => {
+                let replaced = substitute_text(text.as_str(), vars);
+                match text.styles() {
+                    Some(styles) => DocChars(DocString::from_str_styled(&replaced, (*styles).clone())),
+                    None => DocChars(DocString::from_str(&replaced)),
+                }
+            }
+        })
+        .filter(|elem| match elem {
+            DocChars(text) => !text.as_str().is_empty(),
+            _ => true,
+        })
+        .collect()
+}
@@ -0,0 +1,39 @@
+#[macro_use]
+extern crate oatie;
+extern crate edit_server;
+
+use edit_server::state::SyncState;
+use oatie::doc::*;
+
+fn state() -> SyncState {
+    SyncState::new(Doc(doc_span![DocGroup({"tag": "p"}, [DocChars("ab")])]), 100)
+}
+
+#[test]
+fn applied_operations_are_remembered_by_client() {
+    let mut state = state();
+    state.record_applied("one", 1, 101);
+    state.record_applied("two", 1, 102);
+    state.record_applied("one", 2, 103);
+
+    assert_eq!(state.applied_version("one", 1), Some(101));
+    assert_eq!(state.applied_version("two", 1), Some(102));
+    assert_eq!(state.applied_version("two", 2), None);
+
+    // A client reconnecting learns the last of its operations we applied.
+    assert_eq!(state.last_applied("one"), Some((2, 103)));
+    assert_eq!(state.last_applied("two"), Some((1, 102)));
+    assert_eq!(state.last_applied("three"), None);
+}
+
+#[test]
+fn only_recent_operations_are_remembered() {
+    let mut state = state();
+    state.record_applied("one", 0, 100);
+    for op_id in 1..2000 {
+        state.record_applied("two", op_id, 100 + op_id as usize);
+    }
+    assert_eq!(state.applied_version("one", 0), None);
+    assert_eq!(state.last_applied("one"), None);
+    assert_eq!(state.last_applied("two"), Some((1999, 2099)));
+}
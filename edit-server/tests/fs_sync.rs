@@ -0,0 +1,48 @@
+extern crate edit_server;
+#[macro_use]
+extern crate oatie;
+
+use edit_server::fs_sync::merge_external_edit;
+use oatie::doc::*;
+use oatie::OT;
+
+fn doc(texts: &[&str]) -> Doc {
+    Doc(texts
+        .iter()
+        .flat_map(|text| doc_span![DocGroup({"tag": "p"}, [DocChars(text)])])
+        .collect())
+}
+
+#[test]
+fn external_edit_keeps_concurrent_edits() {
+    let base = doc(&["one", "two", "three"]);
+    // Typed in the browser since the file was last written.
+    let live = doc(&["one!", "two", "three"]);
+    // Saved from a text editor, which never saw the browser's edit.
+    let file = doc(&["one", "two", "3"]);
+
+    let edit = merge_external_edit(&base, &live, &file).unwrap();
+    assert_eq!(Op::apply(&live, &edit), doc(&["one!", "two", "3"]));
+}
+
+#[test]
+fn external_edit_with_new_blocks_on_both_sides() {
+    let base = doc(&["one", "two"]);
+    let live = doc(&["zero", "one", "two"]);
+    let file = doc(&["one", "two", "three"]);
+
+    let edit = merge_external_edit(&base, &live, &file).unwrap();
+    assert_eq!(
+        Op::apply(&live, &edit),
+        doc(&["zero", "one", "two", "three"])
+    );
+}
+
+#[test]
+fn unchanged_file_is_no_edit() {
+    let base = doc(&["one", "two"]);
+    let live = doc(&["one", "two", "three"]);
+
+    let edit = merge_external_edit(&base, &live, &base).unwrap();
+    assert_eq!(Op::apply(&live, &edit), live);
+}
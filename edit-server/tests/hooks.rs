@@ -0,0 +1,81 @@
+#[macro_use]
+extern crate oatie;
+extern crate edit_server;
+
+use edit_server::hooks::*;
+use oatie::doc::*;
+use oatie::OT;
+
+fn filter() -> WordFilter {
+    WordFilter::new(&["Bad"])
+}
+
+#[test]
+fn split_words_keeps_every_character() {
+    let text = "don't stop, go!";
+    let pieces = split_words(text);
+    assert_eq!(pieces, vec!["don't", " ", "stop", ", ", "go", "!"]);
+    assert_eq!(pieces.concat(), text);
+    assert!(split_words("").is_empty());
+}
+
+#[test]
+fn filter_text_replaces_whole_words() {
+    let filter = filter();
+    assert_eq!(filter.filter_text("so bad here", false), Some("so *** here".to_string()));
+    assert_eq!(filter.filter_text("BAD. Bad!", false), Some("***. ***!".to_string()));
+    assert_eq!(filter.filter_text("badminton is good", false), None);
+
+    // A word ending the text may still be being typed.
+    assert_eq!(filter.filter_text("so bad", false), None);
+    assert_eq!(filter.filter_text("so bad", true), Some("so ***".to_string()));
+}
+
+#[test]
+fn commits_filter_only_touched_blocks() {
+    let doc = Doc(doc_span![
+        DocGroup({"tag": "p"}, [DocChars("bad one")]),
+        DocGroup({"tag": "p"}, [DocChars("bad two")]),
+    ]);
+    let op = op_span!([], [AddSkip(1), AddWithGroup([AddSkip(7), AddChars("!")])]);
+    let doc = Op::apply(&doc, &op);
+
+    let filtered = filter().on_commit("page", &doc, &op).unwrap();
+    assert_eq!(
+        Op::apply(&doc, &filtered),
+        Doc(doc_span![
+            DocGroup({"tag": "p"}, [DocChars("bad one")]),
+            DocGroup({"tag": "p"}, [DocChars("*** two!")]),
+        ])
+    );
+}
+
+#[test]
+fn commits_leave_words_being_typed() {
+    let doc = Doc(doc_span![DocGroup({"tag": "p"}, [DocChars("ba")])]);
+    let op = op_span!([], [AddWithGroup([AddSkip(2), AddChars("d")])]);
+    let doc = Op::apply(&doc, &op);
+    assert!(filter().on_commit("page", &doc, &op).is_none());
+
+    // Finishing the word filters it.
+    let op = op_span!([], [AddWithGroup([AddSkip(3), AddChars(" ")])]);
+    let doc = Op::apply(&doc, &op);
+    let filtered = filter().on_commit("page", &doc, &op).unwrap();
+    assert_eq!(
+        Op::apply(&doc, &filtered),
+        Doc(doc_span![DocGroup({"tag": "p"}, [DocChars("*** ")])])
+    );
+}
+
+#[test]
+fn saves_filter_words_styled_in_parts() {
+    let doc = Doc(doc_span![DocGroup({"tag": "p"}, [
+        DocChars("ba", {Style::Bold => None}),
+        DocChars("d"),
+    ])]);
+    let filtered = filter().on_save("page", &doc).unwrap();
+    assert_eq!(
+        Op::apply(&doc, &filtered),
+        Doc(doc_span![DocGroup({"tag": "p"}, [DocChars("***")])])
+    );
+}
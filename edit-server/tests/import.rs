@@ -0,0 +1,93 @@
+extern crate edit_common;
+extern crate edit_server;
+#[macro_use]
+extern crate maplit;
+#[macro_use]
+extern crate oatie;
+
+use edit_common::link::Link;
+use edit_server::import::*;
+use oatie::doc::*;
+use std::collections::HashMap;
+
+fn linked(text: &str, link: &Link) -> DocSpan {
+    doc_span![
+        DocGroup({"tag": "p"}, [
+            DocChars(text, {Style::Normie => None, Style::Link => Some(link.to_style_value())}),
+        ]),
+    ]
+}
+
+fn link_of(span: &DocSpan) -> Link {
+    match span[0] {
+        DocGroup(_, ref inner) => match inner[0] {
+            DocChars(ref text) => match text.styles().unwrap().get(&Style::Link) {
+                Some(Some(value)) => Link::from_style_value(value),
+                other => panic!("expected a link, got {:?}", other),
+            },
+            ref other => panic!("expected text, got {:?}", other),
+        },
+        ref other => panic!("expected a paragraph, got {:?}", other),
+    }
+}
+
+fn pages() -> HashMap<String, String> {
+    hashmap! {
+        "notes/meeting.md".to_string() => "notes-meeting".to_string(),
+        "index.md".to_string() => "index".to_string(),
+    }
+}
+
+#[test]
+fn relative_links_point_at_pages() {
+    let span = linked("meeting", &Link::new("meeting.md#agenda"));
+    let span = rewrite_links(&span, "notes/todo.md", &pages());
+    assert_eq!(link_of(&span), Link::new("/notes-meeting#agenda"));
+
+    let span = linked("home", &Link::new("../index"));
+    let span = rewrite_links(&span, "notes/todo.md", &pages());
+    assert_eq!(link_of(&span), Link::new("/index"));
+}
+
+#[test]
+fn links_with_titles_keep_them() {
+    let link = Link {
+        href: "../index.md".to_string(),
+        title: Some("Home".to_string()),
+        new_tab: true,
+    };
+    let span = rewrite_links(&linked("home", &link), "notes/todo.md", &pages());
+    assert_eq!(
+        link_of(&span),
+        Link {
+            href: "/index".to_string(),
+            title: Some("Home".to_string()),
+            new_tab: true,
+        }
+    );
+}
+
+#[test]
+fn other_links_stay() {
+    for href in &["https://example.com/a.md", "/elsewhere", "#top", "missing.md"] {
+        let link = Link {
+            href: href.to_string(),
+            title: Some("Title".to_string()),
+            new_tab: false,
+        };
+        let span = rewrite_links(&linked("text", &link), "notes/todo.md", &pages());
+        assert_eq!(link_of(&span), link);
+    }
+}
+
+#[test]
+fn frontmatter() {
+    let (fields, body) = split_frontmatter("---\nid: notes\ntitle: \"A: B\"\n---\n# Hi\n");
+    assert_eq!(fields["id"], "notes");
+    assert_eq!(fields["title"], "A: B");
+    assert_eq!(body, "# Hi\n");
+
+    let (fields, body) = split_frontmatter("# No frontmatter");
+    assert!(fields.is_empty());
+    assert_eq!(body, "# No frontmatter");
+}
@@ -0,0 +1,83 @@
+#[macro_use]
+extern crate oatie;
+extern crate edit_common;
+extern crate edit_server;
+extern crate serde_json;
+
+use edit_common::commands::*;
+use edit_server::sync::*;
+use oatie::doc::*;
+
+fn paragraph(text: &str) -> DocSpan {
+    doc_span![DocGroup({"tag": "p"}, [DocChars(text)])]
+}
+
+fn paragraphs(count: usize, len: usize) -> DocSpan {
+    (0..count).flat_map(|_| paragraph(&"a".repeat(len))).collect()
+}
+
+#[test]
+fn chunks_join_into_the_document() {
+    let doc = paragraphs(20, 2000);
+    let elements = init_elements(&doc);
+    assert_eq!(elements.len(), 20);
+
+    let chunks = init_chunks(&elements);
+    assert!(chunks.len() > 1);
+    let mut joined: DocSpan = vec![];
+    for chunk in &chunks {
+        assert!(chunk.len() <= INIT_CHUNK_LEN);
+        joined.extend(serde_json::from_str::<DocSpan>(chunk).unwrap());
+    }
+    assert_eq!(joined, doc);
+
+    // Each chunk is sent as an InitChunk without being parsed again.
+    let json = init_chunk_json("client", 100, 1, chunks.len(), &chunks[1]);
+    match serde_json::from_str(&json).unwrap() {
+        ClientCommand::InitChunk(client_id, 100, 1, count, span) => {
+            assert_eq!(client_id, "client");
+            assert_eq!(count, chunks.len());
+            assert_eq!(span, serde_json::from_str::<DocSpan>(&chunks[1]).unwrap());
+        }
+        other => panic!("expected a chunk, got {:?}", other),
+    }
+}
+
+#[test]
+fn empty_documents_are_one_chunk() {
+    assert_eq!(init_chunks(&init_elements(&vec![])), vec!["[]".to_string()]);
+    assert_eq!(init_texts(&init_elements(&vec![])), vec!["[]".to_string()]);
+}
+
+#[test]
+fn long_elements_are_split_into_texts() {
+    // One paragraph longer than a chunk, with characters longer than a byte.
+    let mut doc = paragraphs(1, 3 * INIT_CHUNK_LEN);
+    doc.extend(paragraph(&"é".repeat(INIT_CHUNK_LEN)));
+    let elements = init_elements(&doc);
+
+    // It's a chunk of its own.
+    let chunks = init_chunks(&elements);
+    assert_eq!(chunks.len(), 2);
+    assert!(chunks[0].len() > INIT_CHUNK_LEN);
+
+    // Texts aren't longer than a chunk, however long the elements are.
+    let texts = init_texts(&elements);
+    assert!(texts.len() > 4);
+    assert!(texts.iter().all(|text| text.len() <= INIT_CHUNK_LEN));
+    let joined: DocSpan = serde_json::from_str(&texts.concat()).unwrap();
+    assert_eq!(joined, doc);
+}
+
+#[test]
+fn documents_serialize_the_same_way() {
+    let first = doc_span![
+        DocGroup({"tag": "p", "class": "note", "lang": "en", "id": "b1"}, [DocChars("ab")]),
+    ];
+    let second = doc_span![
+        DocGroup({"id": "b1", "lang": "en", "class": "note", "tag": "p"}, [DocChars("ab")]),
+    ];
+
+    // So a client resuming an initial sync gets the pieces it's missing.
+    assert_eq!(init_texts(&init_elements(&first)), init_texts(&init_elements(&second)));
+}
@@ -0,0 +1,56 @@
+extern crate edit_common;
+extern crate edit_server;
+#[macro_use]
+extern crate oatie;
+
+use edit_common::commands::*;
+use edit_server::limits::DocLimits;
+use edit_server::state::SyncState;
+use oatie::doc::*;
+
+fn state(max_chars: usize) -> SyncState {
+    let mut state = SyncState::new(Doc(doc_span![DocGroup({"tag": "p"}, [DocChars("ab")])]), 0);
+    state.limits = DocLimits {
+        max_chars: Some(max_chars),
+        max_blocks: None,
+        max_op_size: None,
+    };
+    state
+}
+
+fn insert(text: &str) -> Op {
+    op_span!([], [AddWithGroup([AddChars(text)])])
+}
+
+#[test]
+fn limits_count_operations_committed_since() {
+    let mut state = state(7);
+    state.commit("a", insert("cde"), 0).unwrap();
+
+    // Against the version it was written for, the operation fits.
+    let op = insert("fgh");
+    assert_eq!(state.validate_incoming(&op, 0), Ok(()));
+    assert_eq!(
+        state.transform_incoming(op, 0),
+        Err(RefusalReason::TooLarge(SizeLimit::Chars, 7))
+    );
+}
+
+#[test]
+fn transformed_operation_within_limits() {
+    let mut state = state(8);
+    state.commit("a", insert("cde"), 0).unwrap();
+
+    let op = state.transform_incoming(insert("fgh"), 0).unwrap();
+    let version = state.version;
+    state.commit("b", op, version).unwrap();
+    assert_eq!(state.doc.0.len(), 1);
+    assert_eq!(state.version, 2);
+}
+
+#[test]
+fn shrinking_stays_allowed_over_limit() {
+    let state = state(1);
+    let op = op_span!([DelWithGroup([DelChars(1)])], []);
+    assert!(state.transform_incoming(op, 0).is_ok());
+}
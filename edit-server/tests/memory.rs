@@ -0,0 +1,85 @@
+extern crate crossbeam_channel;
+extern crate edit_server;
+
+use crossbeam_channel::unbounded;
+use edit_server::admin::*;
+use edit_server::memory::evict_over_budget;
+use edit_server::sync::*;
+use std::collections::HashMap;
+use std::sync::{
+    Arc,
+    Mutex,
+};
+use std::time::{
+    Duration,
+    Instant,
+};
+
+fn page(memory: usize, idle_secs: u64, clients: usize) -> PageStatus {
+    let (queue, _) = unbounded();
+    let mut status = PageStatus::new(queue);
+    status.memory = memory;
+    status.last_active = Instant::now() - Duration::from_secs(idle_secs);
+    status.clients = (0..clients)
+        .map(|i| ClientStatus {
+            id: i.to_string(),
+            user_id: None,
+            name: None,
+            remote_addr: None,
+            session: None,
+            share_token: None,
+        })
+        .collect();
+    status
+}
+
+fn registry(pages: Vec<(&str, PageStatus)>) -> PageRegistry {
+    let mut map = HashMap::new();
+    for (page_id, status) in pages {
+        map.insert(page_id.to_string(), status);
+    }
+    Arc::new(Mutex::new(map))
+}
+
+#[test]
+fn evicts_least_recently_active_idle_pages() {
+    let registry = registry(vec![
+        ("recent", page(100, 10, 0)),
+        ("old", page(100, 100, 0)),
+        ("open", page(100, 1000, 1)),
+    ]);
+    let (tx_master, rx_master) = unbounded();
+    assert_eq!(evict_over_budget(&registry, &tx_master, 250), vec!["old"]);
+
+    // Only idle evictions are asked for, for the page master to confirm.
+    match rx_master.try_recv() {
+        Some(ClientNotify(ref page_id, ClientUpdate::EvictIdle { .. })) => {
+            assert_eq!(page_id, "old")
+        }
+        _ => panic!("expected an idle eviction"),
+    }
+    assert!(rx_master.try_recv().is_none());
+}
+
+#[test]
+fn pages_with_clients_stay_over_budget() {
+    let registry = registry(vec![("open", page(1000, 1000, 2)), ("idle", page(10, 0, 0))]);
+    let (tx_master, _rx_master) = unbounded();
+    assert_eq!(evict_over_budget(&registry, &tx_master, 100), vec!["idle"]);
+}
+
+#[test]
+fn within_budget_evicts_nothing() {
+    let registry = registry(vec![("idle", page(10, 1000, 0))]);
+    let (tx_master, _rx_master) = unbounded();
+    assert!(evict_over_budget(&registry, &tx_master, 100).is_empty());
+}
+
+#[test]
+fn watched_pages_stay_over_budget() {
+    let mut watched = page(1000, 1000, 0);
+    watched.watchers = 1;
+    let registry = registry(vec![("watched", watched), ("idle", page(10, 0, 0))]);
+    let (tx_master, _rx_master) = unbounded();
+    assert_eq!(evict_over_budget(&registry, &tx_master, 100), vec!["idle"]);
+}
@@ -0,0 +1,66 @@
+#[macro_use]
+extern crate oatie;
+extern crate edit_common;
+extern crate edit_server;
+
+use edit_common::pagination::*;
+use edit_server::pdf::*;
+use oatie::doc::*;
+
+fn count(haystack: &[u8], needle: &[u8]) -> usize {
+    haystack.windows(needle.len()).filter(|x| *x == needle).count()
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|x| x == needle)
+}
+
+fn paragraphs(count: usize) -> Doc {
+    Doc((0..count)
+        .flat_map(|i| doc_span![DocGroup({"tag": "p"}, [DocChars(&format!("paragraph {}", i))])])
+        .collect())
+}
+
+#[test]
+fn pages_break_as_paginated() {
+    let doc = paragraphs(5);
+    let layout = PageLayout {
+        lines_per_page: 4,
+        chars_per_line: 80,
+    };
+    assert_eq!(paginate(&doc, &layout).len(), 2);
+
+    let pdf = doc_to_pdf(&doc, &layout);
+    assert!(pdf.starts_with(b"%PDF-1.4\n"));
+    assert_eq!(count(&pdf, b"/Type /Page /Parent"), 3);
+    assert_eq!(count(&pdf, b"(paragraph "), 5);
+}
+
+#[test]
+fn cross_references_point_at_objects() {
+    let pdf = doc_to_pdf(&paragraphs(2), &PageLayout::default());
+    let text = String::from_utf8_lossy(&pdf).to_string();
+
+    let start = text.rfind("startxref\n").unwrap() + "startxref\n".len();
+    let xref = text[start..].lines().next().unwrap().parse::<usize>().unwrap();
+    assert!(text[xref..].starts_with("xref\n"));
+
+    // Entries after the free one give each object's offset, in order.
+    let entries = text[xref..].lines().skip(3).take_while(|x| x.ends_with(" n "));
+    let mut objects = 0;
+    for (i, entry) in entries.enumerate() {
+        let offset = entry[..10].parse::<usize>().unwrap();
+        assert!(text[offset..].starts_with(&format!("{} 0 obj\n", i + 1)));
+        objects += 1;
+    }
+    assert!(text.contains(&format!("/Size {}", objects + 1)));
+}
+
+#[test]
+fn text_is_escaped_and_encoded() {
+    let doc = Doc(doc_span![DocGroup({"tag": "p"}, [
+        DocChars("a (b) \\ \u{201C}c\u{201D} \u{4E2D}"),
+    ])]);
+    let pdf = doc_to_pdf(&doc, &PageLayout::default());
+    assert!(find(&pdf, b"(a \\(b\\) \\\\ \x93c\x94 ?) Tj").is_some());
+}
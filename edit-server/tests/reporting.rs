@@ -0,0 +1,82 @@
+extern crate edit_common;
+extern crate edit_server;
+extern crate oatie;
+extern crate ron;
+
+use edit_common::identity::UserIdentity;
+use edit_server::graphql::reporting::*;
+use edit_server::playback::*;
+use oatie::doc::*;
+use oatie::OT;
+
+fn user(id: &str, name: &str) -> UserIdentity {
+    UserIdentity::new(id, Some(name), None, None, None)
+}
+
+#[test]
+fn paginate_pages_through_items() {
+    let items = (0..45).collect::<Vec<_>>();
+    let (nodes, page_info, total) = paginate(items.clone(), None, None).unwrap();
+    assert_eq!(nodes, (0..20).collect::<Vec<_>>());
+    assert_eq!(page_info.end_cursor, Some("19".to_string()));
+    assert!(page_info.has_next_page);
+    assert_eq!(total, 45);
+
+    let (nodes, page_info, _) = paginate(items.clone(), Some(30), page_info.end_cursor).unwrap();
+    assert_eq!(nodes, (20..45).collect::<Vec<_>>());
+    assert!(!page_info.has_next_page);
+
+    // Past the end, there's nothing left.
+    let (nodes, page_info, _) = paginate(items, None, Some("44".to_string())).unwrap();
+    assert!(nodes.is_empty());
+    assert_eq!(page_info.end_cursor, None);
+}
+
+#[test]
+fn paginate_refuses_bad_arguments() {
+    let items = vec![1, 2, 3];
+    assert!(paginate(items.clone(), Some(-1), None).is_err());
+    assert!(paginate(items.clone(), None, Some("first".to_string())).is_err());
+    assert!(paginate(items.clone(), None, Some(usize::max_value().to_string())).is_err());
+
+    // Pages are no larger than the largest allowed.
+    let items = (0..500).collect::<Vec<_>>();
+    assert_eq!(paginate(items, Some(1000), None).unwrap().0.len(), 100);
+}
+
+#[test]
+fn page_history_credits_users() {
+    let records = vec![
+        (1, HistoryRecord::Join("a".to_string(), user("ann", "Ann"))),
+        (2, HistoryRecord::Commit("a".to_string())),
+        (3, HistoryRecord::Join("b".to_string(), user("bo", "Bo"))),
+        (4, HistoryRecord::Commit("b".to_string())),
+        (5, HistoryRecord::Join("c".to_string(), user("ann", "Annie"))),
+        (6, HistoryRecord::Commit("c".to_string())),
+        (7, HistoryRecord::Commit("unknown".to_string())),
+    ];
+    let (revisions, participants) = page_history(&records);
+
+    assert_eq!(revisions.len(), 4);
+    assert_eq!(revisions[0].number, 1);
+    assert_eq!(revisions[2].user_id, Some("ann".to_string()));
+    assert_eq!(revisions[3].user_id, None);
+
+    assert_eq!(participants.len(), 2);
+    assert_eq!(participants[0].name, "Annie");
+    assert_eq!(participants[0].revisions, 2);
+    assert_eq!((participants[0].first_joined, participants[0].last_joined), (1.0, 5.0));
+    assert_eq!(participants[1].revisions, 1);
+}
+
+#[test]
+fn commit_client_id_reads_the_start_of_a_record() {
+    let record = PageRecord::Commit("abc123".to_string(), Op::empty());
+    let body = ron::ser::to_string(&record).unwrap();
+    assert_eq!(commit_client_id(&body[..15]), Some("abc123".to_string()));
+    assert_eq!(commit_client_id(&body), Some("abc123".to_string()));
+
+    let record = PageRecord::Start(vec![]);
+    assert_eq!(commit_client_id(&ron::ser::to_string(&record).unwrap()), None);
+    assert_eq!(commit_client_id("Commit(\"abc"), None);
+}
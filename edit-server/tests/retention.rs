@@ -0,0 +1,134 @@
+extern crate diesel;
+extern crate edit_common;
+extern crate edit_server;
+#[macro_use]
+extern crate oatie;
+extern crate ron;
+
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use edit_common::identity::UserIdentity;
+use edit_server::db::*;
+use edit_server::playback::*;
+use edit_server::retention::*;
+use oatie::doc::*;
+use oatie::OT;
+
+fn db() -> SqliteConnection {
+    let db = SqliteConnection::establish(":memory:").unwrap();
+    db.execute(include_str!("../migrations/2018-08-28-193000_page_records/up.sql"))
+        .unwrap();
+    db
+}
+
+fn record(db: &SqliteConnection, page_id: &str, record: PageRecord) {
+    let body = ::ron::ser::to_string(&record).unwrap();
+    create_page_record(db, page_id, now_millis(), &body).unwrap();
+}
+
+fn records(db: &SqliteConnection, page_id: &str) -> Vec<PageRecord> {
+    select_page_records(db, page_id)
+        .unwrap()
+        .into_iter()
+        .map(|row| ::ron::de::from_str(&row.body).unwrap())
+        .collect()
+}
+
+fn insert(text: &str) -> Op {
+    op_span!([], [AddWithGroup([AddChars(text)])])
+}
+
+fn paragraph(text: &str) -> DocSpan {
+    doc_span![DocGroup({"tag": "p"}, [DocChars(text)])]
+}
+
+fn identity(id: &str) -> UserIdentity {
+    UserIdentity {
+        id: id.to_string(),
+        name: id.to_string(),
+        color: "#000000".to_string(),
+        avatar_url: None,
+        guest: false,
+    }
+}
+
+// Replay a recording to the document it ends with.
+fn replay(records: &[PageRecord]) -> Doc {
+    let mut doc = Doc(vec![]);
+    for record in records {
+        match record {
+            PageRecord::Start(span) => doc = Doc(span.clone()),
+            PageRecord::Commit(_, op) => doc = Op::apply(&doc, op),
+            PageRecord::Join(..) => {}
+        }
+    }
+    doc
+}
+
+fn recorded_page(db: &SqliteConnection, page_id: &str) {
+    record(db, page_id, PageRecord::Start(paragraph("a")));
+    record(db, page_id, PageRecord::Commit("x".to_string(), insert("b")));
+    record(db, page_id, PageRecord::Join("y".to_string(), identity("y")));
+    record(db, page_id, PageRecord::Commit("y".to_string(), insert("c")));
+    record(db, page_id, PageRecord::Commit("y".to_string(), insert("d")));
+}
+
+#[test]
+fn compact_keeps_latest_revisions() {
+    let db = db();
+    recorded_page(&db, "notes");
+    let before = replay(&records(&db, "notes"));
+
+    let policy = RetentionPolicy {
+        max_age_days: None,
+        min_revisions: Some(1),
+    };
+    assert_eq!(compact_page_records(&db, "notes", &policy).unwrap(), 2);
+
+    // The join of the author of a kept operation stays, and the operations
+    // folded become a snapshot before the kept one.
+    let after = records(&db, "notes");
+    assert_eq!(after.len(), 3);
+    match after[0] {
+        PageRecord::Join(ref client_id, _) => assert_eq!(client_id, "y"),
+        _ => panic!("expected a join"),
+    }
+    match after[1] {
+        PageRecord::Start(ref span) => assert_eq!(span, &paragraph("cba")),
+        _ => panic!("expected a snapshot"),
+    }
+    assert_eq!(replay(&after), before);
+
+    // Compacting again has nothing left to fold.
+    assert_eq!(compact_page_records(&db, "notes", &policy).unwrap(), 0);
+}
+
+#[test]
+fn compact_keeps_recent_operations() {
+    let db = db();
+    recorded_page(&db, "notes");
+    let policy = RetentionPolicy {
+        max_age_days: Some(1),
+        min_revisions: None,
+    };
+    assert_eq!(compact_page_records(&db, "notes", &policy).unwrap(), 0);
+    assert_eq!(records(&db, "notes").len(), 5);
+}
+
+#[test]
+fn compact_all_pages() {
+    let db = db();
+    recorded_page(&db, "one");
+    recorded_page(&db, "two");
+    let policy = RetentionPolicy {
+        max_age_days: None,
+        min_revisions: Some(0),
+    };
+    // Without operations to keep, each recording becomes a single snapshot.
+    assert_eq!(compact_all_page_records(&db, &policy).unwrap(), 8);
+    for page_id in &["one", "two"] {
+        let after = records(&db, page_id);
+        assert_eq!(after.len(), 1);
+        assert_eq!(replay(&after), Doc(paragraph("dcba")));
+    }
+}
@@ -0,0 +1,83 @@
+extern crate edit_server;
+#[macro_use]
+extern crate oatie;
+
+use edit_server::search::*;
+use oatie::doc::*;
+use oatie::OT;
+
+fn page_ids(hits: &[SearchHit]) -> Vec<&str> {
+    hits.iter().map(|hit| hit.page_id.as_str()).collect()
+}
+
+#[test]
+fn words_are_lowercased_once_each() {
+    assert_eq!(
+        words("The cat, the HAT... and the cat's hat"),
+        vec!["the", "cat", "hat", "and", "s"]
+    );
+    assert!(words(" -- ").is_empty());
+}
+
+#[test]
+fn search_finds_every_word_in_a_block() {
+    let mut index = SearchIndex::new();
+    index.index_page(
+        "animals",
+        &Doc(doc_span![
+            DocGroup({"tag": "h1"}, [DocChars("Animals")]),
+            DocGroup({"tag": "p", "id": "cats"}, [DocChars("Cats chase mice.")]),
+            DocGroup({"tag": "p"}, [DocChars("Dogs chase cats.")]),
+        ]),
+    );
+    index.index_page(
+        "food",
+        &Doc(doc_span![DocGroup({"tag": "p"}, [DocChars("Mice eat cheese.")])]),
+    );
+
+    let hits = index.search("mice", 10);
+    assert_eq!(page_ids(&hits), vec!["animals", "food"]);
+    assert_eq!(hits[0].title, "Animals");
+    assert_eq!(hits[0].snippet, "Cats chase mice.");
+
+    let hits = index.search("chase CATS", 10);
+    assert_eq!(page_ids(&hits), vec!["animals", "animals"]);
+    assert_eq!(index.search("chase cheese", 10).len(), 0);
+    assert_eq!(index.search("chase", 1).len(), 1);
+    assert_eq!(index.search("", 10).len(), 0);
+}
+
+#[test]
+fn search_follows_operations() {
+    let mut index = SearchIndex::new();
+    let doc = Doc(doc_span![
+        DocGroup({"tag": "p"}, [DocChars("first")]),
+        DocGroup({"tag": "p"}, [DocChars("second")]),
+    ]);
+    index.index_page("page", &doc);
+
+    let op = op_span!(
+        [DelSkip(1), DelWithGroup([DelChars(6)])],
+        [AddSkip(1), AddWithGroup([AddChars("third")])],
+    );
+    let doc = Op::apply(&doc, &op);
+    index.apply("page", &doc, &op);
+
+    assert_eq!(page_ids(&index.search("third", 10)), vec!["page"]);
+    assert_eq!(index.search("second", 10).len(), 0);
+    assert_eq!(page_ids(&index.search("first", 10)), vec!["page"]);
+}
+
+#[test]
+fn removed_and_emptied_pages_are_not_found() {
+    let mut index = SearchIndex::new();
+    let doc = Doc(doc_span![DocGroup({"tag": "p"}, [DocChars("hello")])]);
+    index.index_page("one", &doc);
+    index.index_page("two", &doc);
+
+    index.remove_page("one");
+    assert_eq!(page_ids(&index.search("hello", 10)), vec!["two"]);
+
+    index.index_page("two", &Doc(doc_span![DocGroup({"tag": "p"}, [])]));
+    assert_eq!(index.search("hello", 10).len(), 0);
+}
@@ -0,0 +1,57 @@
+extern crate edit_server;
+#[macro_use]
+extern crate maplit;
+#[macro_use]
+extern crate oatie;
+
+use edit_server::templates::*;
+use oatie::doc::*;
+
+#[test]
+fn substitutes_variables() {
+    let vars = hashmap! {
+        "date".to_string() => "2018-07-01".to_string(),
+        "id".to_string() => "notes".to_string(),
+    };
+    let template = doc_span![
+        DocGroup({"tag": "h1"}, [DocChars("Notes for {{date}}")]),
+        DocGroup({"tag": "p"}, [DocChars("Page {{ id }}, not {{unknown}} or {{open")]),
+    ];
+    assert_eq!(
+        substitute_variables(&template, &vars),
+        doc_span![
+            DocGroup({"tag": "h1"}, [DocChars("Notes for 2018-07-01")]),
+            DocGroup({"tag": "p"}, [DocChars("Page notes, not {{unknown}} or {{open")]),
+        ]
+    );
+}
+
+#[test]
+fn substitution_drops_emptied_text() {
+    let vars = hashmap! { "empty".to_string() => "".to_string() };
+    let template = doc_span![
+        DocGroup({"tag": "p"}, [DocChars("{{empty}}")]),
+    ];
+    assert_eq!(
+        substitute_variables(&template, &vars),
+        doc_span![DocGroup({"tag": "p"}, [])]
+    );
+}
+
+#[test]
+fn variables_for_page() {
+    let vars = template_variables("notes");
+    assert_eq!(vars["id"], "notes");
+
+    let date = &vars["date"];
+    assert_eq!(date.len(), 10);
+    assert_eq!(&date[..4], vars["year"].as_str());
+    assert!(date
+        .chars()
+        .enumerate()
+        .all(|(i, c)| if i == 4 || i == 7 { c == '-' } else { c.is_digit(10) }));
+
+    let time = &vars["time"];
+    assert_eq!(time.len(), 5);
+    assert_eq!(&time[2..3], ":");
+}
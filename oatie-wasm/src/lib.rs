@@ -0,0 +1,78 @@
+//! WebAssembly bindings for oatie, for JavaScript editors that want its
+//! operational transform without the rest of the edit-text client.
+//!
+//! Documents and operations are passed as JSON strings, in the same format
+//! the edit-text server and client exchange them. Every function takes the
+//! document its operations apply to, and checks them against it before
+//! using them, so malformed operations are thrown as JavaScript exceptions
+//! rather than aborting the module.
+
+#![feature(use_extern_macros)]
+
+extern crate oatie;
+extern crate serde;
+extern crate serde_json;
+extern crate wasm_bindgen;
+
+use oatie::prelude::{
+    validate_doc,
+    validate_op,
+    Doc,
+    Op,
+    RtfSchema,
+    OT,
+};
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+fn parse_op(input: &str) -> Result<Op, JsValue> {
+    serde_json::from_str(input).map_err(|err| JsValue::from_str(&format!("invalid operation: {}", err)))
+}
+
+fn parse_doc(input: &str) -> Result<Doc, JsValue> {
+    let span = serde_json::from_str(input)
+        .map_err(|err| JsValue::from_str(&format!("invalid document: {}", err)))?;
+    let doc = Doc(span);
+    validate_doc(&doc).map_err(|err| JsValue::from_str(&format!("invalid document: {}", err)))?;
+    Ok(doc)
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, JsValue> {
+    serde_json::to_string(value).map_err(|err| JsValue::from_str(&err.to_string()))
+}
+
+// An operation that can be applied to `doc`.
+fn valid_op(doc: &Doc, input: &str) -> Result<Op, JsValue> {
+    let op = parse_op(input)?;
+    validate_op(doc, &op)
+        .map_err(|err| JsValue::from_str(&format!("invalid operation: {}", err)))?;
+    Ok(op)
+}
+
+/// Transform two concurrent operations on `doc` against each other. Returns
+/// a JSON array of `[a', b']`, where `a'` is `b` rewritten to follow `a`, and
+/// `b'` is `a` rewritten to follow `b`.
+#[wasm_bindgen]
+pub fn transform(doc: &str, a: &str, b: &str) -> Result<String, JsValue> {
+    let doc = parse_doc(doc)?;
+    let (a, b) = (valid_op(&doc, a)?, valid_op(&doc, b)?);
+    to_json(&Op::transform::<RtfSchema>(&a, &b))
+}
+
+/// Compose two operations into one that does both, `a` on `doc` and then
+/// `b` on the result.
+#[wasm_bindgen]
+pub fn compose(doc: &str, a: &str, b: &str) -> Result<String, JsValue> {
+    let doc = parse_doc(doc)?;
+    let a = valid_op(&doc, a)?;
+    let b = valid_op(&Op::apply(&doc, &a), b)?;
+    to_json(&Op::compose(&a, &b))
+}
+
+/// Apply an operation to a document, returning the new document.
+#[wasm_bindgen]
+pub fn apply(doc: &str, op: &str) -> Result<String, JsValue> {
+    let doc = parse_doc(doc)?;
+    let op = valid_op(&doc, op)?;
+    to_json(&Op::apply(&doc, &op).0)
+}
@@ -0,0 +1,137 @@
+//! Benchmarks for the operational transform hot paths. Run with `cargo bench`.
+
+#[macro_use]
+extern crate criterion;
+#[macro_use]
+extern crate maplit;
+#[macro_use]
+extern crate oatie;
+
+use criterion::Criterion;
+use oatie::doc::*;
+use oatie::schema::RtfSchema;
+use oatie::OT;
+
+const DOC_SIZES: &[usize] = &[10, 100, 1_000];
+
+const PARAGRAPH: &str = "Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do \
+                         eiusmod tempor incididunt ut labore et dolore magna aliqua.";
+
+// A document of `size` paragraphs.
+fn bench_doc(size: usize) -> Doc {
+    Doc((0..size)
+        .map(|_| {
+            DocGroup(
+                hashmap! { "tag".to_string() => "p".to_string() },
+                vec![DocChars(DocString::from_str(PARAGRAPH))],
+            )
+        })
+        .collect())
+}
+
+// Inserts text at the start of the given paragraph.
+fn insert_op(paragraph: usize, text: &str) -> Op {
+    (
+        vec![],
+        vec![
+            AddSkip(paragraph),
+            AddWithGroup(vec![AddChars(DocString::from_str(text))]),
+        ],
+    )
+}
+
+// Deletes the first characters of the given paragraph.
+fn delete_op(paragraph: usize, count: usize) -> Op {
+    (
+        vec![DelSkip(paragraph), DelWithGroup(vec![DelChars(count)])],
+        vec![],
+    )
+}
+
+fn bench_apply(c: &mut Criterion) {
+    c.bench_function_over_inputs(
+        "apply",
+        |b, &&size| {
+            let doc = bench_doc(size);
+            let op = insert_op(size / 2, "hello");
+            b.iter(|| Op::apply(&doc, &op))
+        },
+        DOC_SIZES,
+    );
+}
+
+fn bench_compose(c: &mut Criterion) {
+    c.bench_function_over_inputs(
+        "compose",
+        |b, &&size| {
+            let a = insert_op(size / 2, "hello");
+            let b_op = delete_op(size - 1, 5);
+            b.iter(|| Op::compose(&a, &b_op))
+        },
+        DOC_SIZES,
+    );
+}
+
+fn bench_transform(c: &mut Criterion) {
+    c.bench_function_over_inputs(
+        "transform",
+        |b, &&size| {
+            let a = insert_op(size / 2, "hello");
+            let b_op = delete_op(size - 1, 5);
+            b.iter(|| Op::transform::<RtfSchema>(&a, &b_op))
+        },
+        DOC_SIZES,
+    );
+}
+
+fn bench_docstring_split(c: &mut Criterion) {
+    c.bench_function_over_inputs(
+        "docstring_split",
+        |b, &&size| {
+            let text = DocString::from_string(PARAGRAPH.repeat(size));
+            let middle = text.char_len() / 2;
+            b.iter(|| text.split_at(middle))
+        },
+        DOC_SIZES,
+    );
+}
+
+// Bolds, then unbolds, every paragraph.
+fn toggle_bold_ops(size: usize) -> (Op, Op) {
+    let chars = PARAGRAPH.chars().count();
+    let bold = (
+        vec![],
+        (0..size)
+            .map(|_| AddWithGroup(vec![AddStyles(chars, style_map! { Style::Bold => None })]))
+            .collect(),
+    );
+    let unbold = (
+        (0..size)
+            .map(|_| DelWithGroup(vec![DelStyles(chars, hashset![Style::Bold])]))
+            .collect(),
+        vec![],
+    );
+    (bold, unbold)
+}
+
+fn bench_style_toggle(c: &mut Criterion) {
+    c.bench_function_over_inputs(
+        "style_toggle",
+        |b, &&size| {
+            let doc = bench_doc(size);
+            let (bold, unbold) = toggle_bold_ops(size);
+            b.iter(|| Op::apply(&Op::apply(&doc, &bold), &unbold))
+        },
+        DOC_SIZES,
+    );
+}
+
+criterion_group!(
+    benches,
+    bench_apply,
+    bench_compose,
+    bench_transform,
+    bench_docstring_split,
+    bench_style_toggle
+);
+criterion_main!(benches);
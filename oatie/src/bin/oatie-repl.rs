@@ -0,0 +1,35 @@
+//! An interactive shell for operations. Load a document, name operations
+//! written in the notation of oatie::terse, and apply, compose, or transform
+//! them to see what comes out. Type `help` for the list of commands.
+
+extern crate oatie;
+
+use oatie::repl::Repl;
+use std::io;
+use std::io::prelude::*;
+
+fn main() {
+    let mut repl = Repl::new();
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("oatie> ");
+        io::stdout().flush().expect("Could not write to stdout");
+
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line == "quit" || line == "exit" {
+            break;
+        }
+        match repl.run(line) {
+            Ok(output) => print!("{}", output),
+            Err(err) => println!("error: {}", err),
+        }
+    }
+}
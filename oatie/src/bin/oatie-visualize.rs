@@ -0,0 +1,32 @@
+//! Reads a transform test from stdin, in any format of the tests in
+//! edit-common/tests/transform, and writes an HTML page visualizing it.
+
+extern crate oatie;
+
+use oatie::schema::RtfSchema;
+use oatie::transform_test::*;
+use oatie::visualize::transform_html;
+use std::io;
+use std::io::prelude::*;
+
+fn main() {
+    let mut input = String::new();
+    let stdin = io::stdin();
+    stdin
+        .lock()
+        .read_to_string(&mut input)
+        .expect("Could not read stdin");
+
+    match parse_transform_test(&input) {
+        Ok(test) => {
+            print!(
+                "{}",
+                transform_html::<RtfSchema>(test.doc.as_ref(), &test.a, &test.b)
+            );
+        }
+        Err(err) => {
+            eprintln!("transform test error: {:?}", err);
+            ::std::process::exit(1);
+        }
+    }
+}
@@ -529,7 +529,7 @@ fn compose_add_del_inner(
                     // a_styles - b_styles
                     let combined_styles = a_styles
                         .clone()
-                        .drain()
+                        .into_iter()
                         .filter(|(k, _)| !b_styles.contains(k))
                         .collect();
 
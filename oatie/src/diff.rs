@@ -0,0 +1,374 @@
+//! Computes an operation turning one document into another. Elements the
+//! two documents share are skipped rather than rewritten, so the operation
+//! transforms cleanly against concurrent edits elsewhere in the document.
+
+use doc::*;
+use std::cmp;
+use std::iter;
+use std::sync::Arc;
+
+// A single character or group, the unit a span is compared in.
+#[derive(Clone)]
+enum Unit<'a> {
+    Char(char, Option<Arc<StyleMap>>),
+    Group(&'a Attrs, &'a DocSpan),
+}
+
+// Groups are compared by their units, since comparing their spans would
+// ignore the styles of their text.
+impl<'a> PartialEq for Unit<'a> {
+    fn eq(&self, other: &Unit<'a>) -> bool {
+        match (self, other) {
+            (Unit::Char(a, a_styles), Unit::Char(b, b_styles)) => a == b && a_styles == b_styles,
+            (Unit::Group(a_attrs, a_inner), Unit::Group(b_attrs, b_inner)) => {
+                a_attrs == b_attrs && units(a_inner) == units(b_inner)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn units(span: &DocSpan) -> Vec<Unit> {
+    let mut out = vec![];
+    for elem in span {
+        match elem {
+            DocChars(text) => {
+                let styles = text.styles();
+                out.extend(text.as_str().chars().map(|c| Unit::Char(c, styles.clone())));
+            }
+            DocGroup(attrs, inner) => out.push(Unit::Group(attrs, inner)),
+        }
+    }
+    out
+}
+
+fn char_string(c: char, styles: &Option<Arc<StyleMap>>) -> DocString {
+    match styles {
+        Some(styles) => DocString::from_str_styled(&c.to_string(), (**styles).clone()),
+        None => DocString::from_str(&c.to_string()),
+    }
+}
+
+fn delete_unit(del: &mut DelSpan, unit: &Unit) {
+    match unit {
+        Unit::Char(..) => del.place(&DelChars(1)),
+        Unit::Group(_, inner) => {
+            let mut inner_del = vec![];
+            for unit in units(inner) {
+                delete_unit(&mut inner_del, &unit);
+            }
+            del.place(&DelGroup(inner_del));
+        }
+    }
+}
+
+fn add_unit(add: &mut AddSpan, unit: &Unit) {
+    match unit {
+        Unit::Char(c, styles) => add.place(&AddChars(char_string(*c, styles))),
+        Unit::Group(attrs, inner) => {
+            let mut inner_add = vec![];
+            for unit in units(inner) {
+                add_unit(&mut inner_add, &unit);
+            }
+            add.place(&AddGroup((*attrs).clone(), inner_add));
+        }
+    }
+}
+
+// Trailing skips don't change anything, so they're left off.
+fn trim_del(mut del: DelSpan) -> DelSpan {
+    if let Some(DelSkip(_)) = del.last() {
+        del.pop();
+    }
+    del
+}
+
+fn trim_add(mut add: AddSpan) -> AddSpan {
+    if let Some(AddSkip(_)) = add.last() {
+        add.pop();
+    }
+    add
+}
+
+fn diff_span(a: &DocSpan, b: &DocSpan) -> Op {
+    diff_units(&units(a), &units(b), 0, 0)
+}
+
+// Most edits searched for when matching units. Spans that differ by more
+// have nothing matched between them.
+const MAX_EDITS: isize = 1000;
+
+// Indexes of the pairs of units of `a` and `b` that `matches` accepts, in
+// order, as many as there can be, or None if too many units differ. This is
+// Myers' algorithm: it finds the fewest units to delete and add, and keeps
+// the rest.
+fn matching_units<F>(a: &[Unit], b: &[Unit], matches: F) -> Option<Vec<(usize, usize)>>
+where
+    F: Fn(&Unit, &Unit) -> bool,
+{
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = cmp::min(n + m, MAX_EDITS);
+    let offset = max + 1;
+
+    // The furthest reaching path along each diagonal, for each number of
+    // edits, which the pairs are traced back from.
+    let mut v = vec![0isize; 2 * offset as usize + 1];
+    let mut trace = vec![];
+    for d in 0..(max + 1) {
+        trace.push(v.clone());
+        for k in (-d..(d + 1)).step_by(2) {
+            let index = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[index - 1] < v[index + 1]) {
+                v[index + 1]
+            } else {
+                v[index - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && matches(&a[x as usize], &b[y as usize]) {
+                x += 1;
+                y += 1;
+            }
+            v[index] = x;
+
+            if x >= n && y >= m {
+                let mut pairs = vec![];
+                let (mut x, mut y) = (n, m);
+                for d in (1..(d + 1)).rev() {
+                    let v = &trace[d as usize];
+                    let k = x - y;
+                    let index = (k + offset) as usize;
+                    let previous_k = if k == -d || (k != d && v[index - 1] < v[index + 1]) {
+                        k + 1
+                    } else {
+                        k - 1
+                    };
+                    let previous_x = v[(previous_k + offset) as usize];
+                    let previous_y = previous_x - previous_k;
+                    while x > previous_x && y > previous_y {
+                        x -= 1;
+                        y -= 1;
+                        pairs.push((x as usize, y as usize));
+                    }
+                    x = previous_x;
+                    y = previous_y;
+                }
+                while x > 0 && y > 0 {
+                    x -= 1;
+                    y -= 1;
+                    pairs.push((x as usize, y as usize));
+                }
+                pairs.reverse();
+                return Some(pairs);
+            }
+        }
+    }
+    None
+}
+
+// Most pairs of units weighed against each other when pairing groups.
+// Bigger hunks pair groups by their attributes alone.
+const MAX_WEIGHED_PAIRS: usize = 100_000;
+
+// How alike two groups are, given their attributes and units, if one can be
+// edited into the other: one, and how many of their units are the same at
+// their start and end.
+fn likeness(x: &Option<(&Attrs, Vec<Unit>)>, y: &Option<(&Attrs, Vec<Unit>)>) -> Option<usize> {
+    match (x, y) {
+        (Some((x_attrs, x_units)), Some((y_attrs, y_units))) if x_attrs == y_attrs => {
+            let head = x_units.iter().zip(y_units).take_while(|(x, y)| x == y).count();
+            let tail = x_units[head..]
+                .iter()
+                .rev()
+                .zip(y_units[head..].iter().rev())
+                .take_while(|(x, y)| x == y)
+                .count();
+            Some(1 + head + tail)
+        }
+        _ => None,
+    }
+}
+
+// The attributes and units of each group in `span`.
+fn groups<'a>(span: &[Unit<'a>]) -> Vec<Option<(&'a Attrs, Vec<Unit<'a>>)>> {
+    span.iter()
+        .map(|unit| match *unit {
+            Unit::Group(attrs, inner) => Some((attrs, units(inner))),
+            Unit::Char(..) => None,
+        })
+        .collect()
+}
+
+// Pairs of groups of `a` and `b` to edit into one another, in order. Of the
+// ways to pair them, it's the one whose pairs are most alike, so a group is
+// edited into the one it became rather than into the next with the same
+// attributes.
+fn pair_groups(a: &[Unit], b: &[Unit]) -> Vec<(usize, usize)> {
+    if a.len() * b.len() > MAX_WEIGHED_PAIRS {
+        let same_kind = |x: &Unit, y: &Unit| match (x, y) {
+            (Unit::Group(x_attrs, _), Unit::Group(y_attrs, _)) => x_attrs == y_attrs,
+            _ => false,
+        };
+        return matching_units(a, b, same_kind).unwrap_or_default();
+    }
+
+    let (a_groups, b_groups) = (groups(a), groups(b));
+
+    // The most likeness pairing the units from `i` in `a` and from `j` in
+    // `b` can have.
+    let mut best = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            let paired = likeness(&a_groups[i], &b_groups[j])
+                .map(|x| x + best[i + 1][j + 1])
+                .unwrap_or(0);
+            best[i][j] = cmp::max(paired, cmp::max(best[i + 1][j], best[i][j + 1]));
+        }
+    }
+
+    let mut pairs = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if best[i][j] == best[i + 1][j] {
+            i += 1;
+        } else if best[i][j] == best[i][j + 1] {
+            j += 1;
+        } else {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        }
+    }
+    pairs
+}
+
+// Add to `del` and `add` the edits turning the units `a` into `b`, which
+// have none in common. Paired groups are edited in place, and everything
+// else is deleted and added again.
+fn diff_hunk(del: &mut DelSpan, add: &mut AddSpan, a: &[Unit], b: &[Unit]) {
+    let has_group = |units: &[Unit]| units.iter().any(|x| match x {
+        Unit::Group(..) => true,
+        _ => false,
+    });
+    let pairs = if has_group(a) && has_group(b) {
+        pair_groups(a, b)
+    } else {
+        vec![]
+    };
+
+    let (mut i, mut j) = (0, 0);
+    for (x, y) in pairs.into_iter().chain(iter::once((a.len(), b.len()))) {
+        for unit in &a[i..x] {
+            delete_unit(del, unit);
+        }
+        for unit in &b[j..y] {
+            add_unit(add, unit);
+        }
+        if let (Some(Unit::Group(_, a_inner)), Some(Unit::Group(_, b_inner))) =
+            (a.get(x), b.get(y))
+        {
+            let (inner_del, inner_add) = diff_span(a_inner, b_inner);
+            if inner_del.is_empty() {
+                del.place(&DelSkip(1));
+            } else {
+                del.place(&DelWithGroup(inner_del));
+            }
+            if inner_add.is_empty() {
+                add.place(&AddSkip(1));
+            } else {
+                add.place(&AddWithGroup(inner_add));
+            }
+        }
+        i = x + 1;
+        j = y + 1;
+    }
+}
+
+// An operation turning units `a` into `b`, whose first `head` and last
+// `tail` units are already known to be the same.
+fn diff_units(a: &[Unit], b: &[Unit], head: usize, tail: usize) -> Op {
+    // Identical units at the start and end are kept as they are.
+    let prefix = head
+        + a[head..a.len() - tail]
+            .iter()
+            .zip(&b[head..b.len() - tail])
+            .take_while(|(x, y)| x == y)
+            .count();
+    let suffix = tail
+        + a[prefix..a.len() - tail]
+            .iter()
+            .rev()
+            .zip(b[prefix..b.len() - tail].iter().rev())
+            .take_while(|(x, y)| x == y)
+            .count();
+    let a_mid = &a[prefix..a.len() - suffix];
+    let b_mid = &b[prefix..b.len() - suffix];
+
+    let mut del = vec![];
+    let mut add = vec![];
+    if prefix > 0 {
+        del.place(&DelSkip(prefix));
+        add.place(&AddSkip(prefix));
+    }
+
+    // Units in the middle that are the same on both sides are kept, as many
+    // of them as can be in order. The hunks between them are edited.
+    let matches = matching_units(a_mid, b_mid, |x, y| x == y).unwrap_or_default();
+    let (mut i, mut j) = (0, 0);
+    for (x, y) in matches.into_iter().chain(iter::once((a_mid.len(), b_mid.len()))) {
+        diff_hunk(&mut del, &mut add, &a_mid[i..x], &b_mid[j..y]);
+        if x < a_mid.len() {
+            del.place(&DelSkip(1));
+            add.place(&AddSkip(1));
+        }
+        i = x + 1;
+        j = y + 1;
+    }
+
+    if suffix > 0 {
+        del.place(&DelSkip(suffix));
+        add.place(&AddSkip(suffix));
+    }
+
+    (trim_del(del), trim_add(add))
+}
+
+/// An operation that, applied to `a`, produces `b`.
+pub fn diff(a: &Doc, b: &Doc) -> Op {
+    diff_span(&a.0, &b.0)
+}
+
+/// How many top-level units at the start and at the end of `before` are
+/// left as they are in `after` by `op`, which turned one into the other.
+/// Units count as skips do: a group, or a character.
+pub fn untouched_edges(before: &Doc, after: &Doc, op: &Op) -> (usize, usize) {
+    let (ref del, ref add) = *op;
+    let before_len = before.0.skip_len();
+    let after_len = after.0.skip_len();
+
+    let del_head = match del.first() {
+        None => before_len,
+        Some(&DelSkip(n)) => n,
+        Some(_) => 0,
+    };
+    let add_head = match add.first() {
+        None => after_len,
+        Some(&AddSkip(n)) => n,
+        Some(_) => 0,
+    };
+    let tail = cmp::min(
+        before_len.saturating_sub(del.skip_pre_len()),
+        after_len.saturating_sub(add.skip_post_len()),
+    );
+    let head = cmp::min(
+        cmp::min(del_head, add_head),
+        cmp::min(before_len, after_len) - tail,
+    );
+    (head, tail)
+}
+
+/// An operation that, applied to `a`, produces `b`, given that their first
+/// `head` and last `tail` top-level units are the same. Only the units in
+/// between are compared.
+pub fn diff_between(a: &Doc, b: &Doc, head: usize, tail: usize) -> Op {
+    diff_units(&units(&a.0), &units(&b.0), head, tail)
+}
@@ -0,0 +1,89 @@
+//! Interning of the styles a document repeats. Text with the same styles,
+//! like every run linking to the same URL, then shares one StyleMap instead
+//! of each holding its own copy, and comparing their styles is a pointer
+//! comparison.
+//!
+//! A document's interner lives as long as the document does: operations
+//! are interned as they're applied, so the text they add shares the styles
+//! already in the document, and `prune` forgets styles no text has anymore.
+//!
+//! Group attributes aren't interned, as they're plain owned maps
+//! throughout.
+
+use super::doc::*;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// The styles seen in a document so far.
+#[derive(Debug, Default)]
+pub struct Interner {
+    styles: HashSet<Arc<StyleMap>>,
+}
+
+impl Interner {
+    pub fn new() -> Interner {
+        Interner::default()
+    }
+
+    /// The shared copy of these styles, which they become if they're new.
+    pub fn intern_styles(&mut self, styles: &Arc<StyleMap>) -> Arc<StyleMap> {
+        if let Some(shared) = self.styles.get(&**styles) {
+            return shared.clone();
+        }
+        self.styles.insert(styles.clone());
+        styles.clone()
+    }
+
+    /// Forget the styles only the interner still holds.
+    pub fn prune(&mut self) {
+        self.styles.retain(|styles| Arc::strong_count(styles) > 1);
+    }
+
+    /// Number of distinct styles interned.
+    pub fn len(&self) -> usize {
+        self.styles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.styles.is_empty()
+    }
+}
+
+fn intern_string(text: &DocString, interner: &mut Interner) -> DocString {
+    let mut text = text.clone();
+    if let Some(styles) = text.styles() {
+        text.share_styles(interner.intern_styles(&styles));
+    }
+    text
+}
+
+fn intern_span(span: &DocSpan, interner: &mut Interner) -> DocSpan {
+    span.iter()
+        .map(|elem| match elem {
+            DocGroup(attrs, inner) => DocGroup(attrs.clone(), intern_span(inner, interner)),
+            DocChars(text) => DocChars(intern_string(text, interner)),
+        })
+        .collect()
+}
+
+fn intern_add_span(span: &AddSpan, interner: &mut Interner) -> AddSpan {
+    span.iter()
+        .map(|elem| match elem {
+            AddChars(text) => AddChars(intern_string(text, interner)),
+            AddGroup(attrs, inner) => AddGroup(attrs.clone(), intern_add_span(inner, interner)),
+            AddWithGroup(inner) => AddWithGroup(intern_add_span(inner, interner)),
+            AddSkip(_) | AddStyles(..) => elem.clone(),
+        })
+        .collect()
+}
+
+/// A copy of a document whose text shares each distinct set of styles.
+pub fn intern_doc(doc: &Doc, interner: &mut Interner) -> Doc {
+    Doc(intern_span(&doc.0, interner))
+}
+
+/// A copy of an operation whose added text shares the interned styles, to
+/// apply to a document interned with the same interner.
+pub fn intern_op(op: &Op, interner: &mut Interner) -> Op {
+    (op.0.clone(), intern_add_span(&op.1, interner))
+}
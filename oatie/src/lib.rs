@@ -46,20 +46,35 @@ macro_rules! log_compose {
 
 /* /logging */
 
+// Internal modules, which are public for edit-text's own crates. Projects
+// using oatie on its own should stick to `prelude`.
+#[doc(hidden)]
+pub mod apply;
+#[doc(hidden)]
 pub mod compose;
+#[doc(hidden)]
+pub mod stepper;
+#[doc(hidden)]
+pub mod transform_test;
+#[doc(hidden)]
+pub mod writer;
+
+pub mod diff;
 pub mod doc;
+pub mod intern;
 //pub mod random;
-pub mod apply;
 pub mod macros;
 mod parse;
 mod place;
+pub mod prelude;
+pub mod repl;
 pub mod schema;
-pub mod stepper;
 mod string;
+pub mod style_spans;
+pub mod terse;
 pub mod transform;
-pub mod transform_test;
 pub mod validate;
-pub mod writer;
+pub mod visualize;
 
 use apply::*;
 use compose::*;
@@ -8,7 +8,7 @@ macro_rules! doc_span {
     };
     ( @kind DocChars $b:expr , { $( $e:expr => $c:expr ),+  $(,)* } $(,)* ) => {
         {
-            let mut map = ::std::collections::HashMap::<Style, Option<String>>::new();
+            let mut map = $crate::doc::StyleMap::new();
             $(
                 map.insert($e, $c);
             )*
@@ -42,7 +42,7 @@ macro_rules! add_span {
     };
     ( @kind AddChars $b:expr , { $( $e:expr => $c:expr ),+  $(,)* } $(,)* ) => {
         {
-            let mut map = ::std::collections::HashMap::<Style, Option<String>>::new();
+            let mut map = $crate::doc::StyleMap::new();
             $(
                 map.insert($e, $c);
             )*
@@ -116,3 +116,17 @@ macro_rules! op_span {
         )
     };
 }
+
+/// Build a StyleMap, like `style_map! { Style::Bold => None }`.
+#[macro_export]
+macro_rules! style_map {
+    ( $( $style:expr => $value:expr ),* $(,)* ) => {
+        {
+            let mut map = $crate::doc::StyleMap::new();
+            $(
+                map.insert($style, $value);
+            )*
+            map
+        }
+    };
+}
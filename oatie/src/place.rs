@@ -16,7 +16,7 @@ impl DocPlaceable for DocSpan {
                 // append our text to it to cut down on new elements.
                 if let Some(&mut DocChars(ref mut prefix)) = self.last_mut() {
                     // Check if they're equal and we can push it directly.
-                    if prefix.styles() == text.styles() {
+                    if prefix.same_styles(text) {
                         prefix.push_str(text.as_str());
                         return;
                     }
@@ -180,7 +180,7 @@ impl AddPlaceable for AddSpan {
                 // append our text to it to cut down on new elements.
                 if let Some(&mut AddChars(ref mut prefix)) = self.last_mut() {
                     // Check if they're equal and we can push it directly.
-                    if prefix.styles() == text.styles() {
+                    if prefix.same_styles(text) {
                         prefix.push_str(text.as_str());
                         return;
                     }
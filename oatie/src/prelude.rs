@@ -0,0 +1,64 @@
+//! The stable interface of oatie, for projects that use it as an OT engine
+//! outside of edit-text:
+//!
+//! ```
+//! use oatie::prelude::*;
+//! ```
+//!
+//! Everything here keeps its signature across releases. The other modules
+//! are the engine's internals, which edit-text changes as it needs to.
+
+pub use super::diff::diff;
+pub use super::doc::{
+    AddElement,
+    AddElement::*,
+    AddSpan,
+    Attrs,
+    DelElement,
+    DelElement::*,
+    DelSpan,
+    DividedString,
+    Doc,
+    DocElement,
+    DocElement::*,
+    DocSpan,
+    DocString,
+    Op,
+    Style,
+    StyleMap,
+    StyleSet,
+};
+pub use super::schema::RtfSchema;
+pub use super::transform::Schema;
+pub use super::validate::{
+    validate_doc,
+    validate_op,
+};
+pub use super::OT;
+
+/// Apply an operation to a document. The operation should be checked with
+/// `validate_op` first, as applying one that doesn't fit the document panics.
+pub fn apply(doc: &Doc, op: &Op) -> Doc {
+    Op::apply(doc, op)
+}
+
+/// Compose two operations into one that does `a` and then `b`.
+pub fn compose(a: &Op, b: &Op) -> Op {
+    Op::compose(a, b)
+}
+
+/// Transform two concurrent operations on the same document against each
+/// other with the rich text schema. Returns `(a', b')`, where `a'` is `b`
+/// rewritten to follow `a`, and `b'` is `a` rewritten to follow `b`, so that
+/// `compose(a, a')` and `compose(b, b')` are the same.
+pub fn transform(a: &Op, b: &Op) -> (Op, Op) {
+    Op::transform::<RtfSchema>(a, b)
+}
+
+/// An operation that undoes `op`, given the document it was applied to.
+/// Only the part of the document `op` changed is compared.
+pub fn invert(doc: &Doc, op: &Op) -> Op {
+    let after = apply(doc, op);
+    let (head, tail) = super::diff::untouched_edges(doc, &after, op);
+    super::diff::diff_between(&after, doc, head, tail)
+}
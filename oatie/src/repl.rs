@@ -0,0 +1,207 @@
+//! An interactive shell for operations, run by the `oatie-repl` binary. Load
+//! a document, name operations written in the notation of `terse`, and apply,
+//! compose, or transform them to see what comes out.
+
+use crate::doc::*;
+use crate::schema::RtfSchema;
+use crate::terse::*;
+use crate::validate::{
+    validate_doc,
+    validate_op,
+};
+use crate::OT;
+use ron;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::panic::{
+    catch_unwind,
+    AssertUnwindSafe,
+};
+
+// Writes a line of output. Writing to a String can't fail.
+macro_rules! outln {
+    ($out:expr, $($arg:tt)*) => {
+        writeln!($out, $($arg)*).unwrap()
+    };
+}
+
+pub const HELP: &str = r#"commands:
+  doc <doc>              load a document, e.g. doc <p>("hello")
+  let <name> = <op>      name an operation, e.g. let a = w(s1 d2) | w(s1 "x")
+  apply <name | op>      apply an operation to the document
+  compose <a> <b>        compose two named operations, saved as _
+  transform <a> <b>      transform two named operations, saved as a' and b'
+  show [name]            print the document, or a named operation
+  ops                    list the named operations
+  ron [name]             print the document, or an operation, as RON
+  undo                   revert the last operation applied
+  help                   print this message
+  quit                   exit
+"#;
+
+/// Run a closure, turning a panic into an error with the panic's message.
+fn attempt<T, F: FnOnce() -> T>(f: F) -> Result<T, String> {
+    catch_unwind(AssertUnwindSafe(f)).map_err(|err| {
+        err.downcast_ref::<String>()
+            .cloned()
+            .or_else(|| err.downcast_ref::<&str>().map(|x| x.to_string()))
+            .unwrap_or_else(|| "panicked".to_string())
+    })
+}
+
+/// The state of a session: the document, its history, and named operations.
+pub struct Repl {
+    doc: Doc,
+    history: Vec<Doc>,
+    ops: BTreeMap<String, Op>,
+}
+
+impl Default for Repl {
+    fn default() -> Repl {
+        Repl::new()
+    }
+}
+
+impl Repl {
+    pub fn new() -> Repl {
+        Repl {
+            doc: Doc(vec![]),
+            history: vec![],
+            ops: BTreeMap::new(),
+        }
+    }
+
+    fn op(&self, name: &str) -> Result<Op, String> {
+        self.ops
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("no operation named {:?}", name))
+    }
+
+    // A named operation, or one written inline.
+    fn op_or_inline(&self, input: &str) -> Result<Op, String> {
+        match self.ops.get(input) {
+            Some(op) => Ok(op.clone()),
+            None => parse_op(input).map_err(|err| err.to_string()),
+        }
+    }
+
+    fn two_ops(&self, args: &str) -> Result<(String, Op, String, Op), String> {
+        let names = args.split_whitespace().collect::<Vec<_>>();
+        if names.len() != 2 {
+            return Err("expected the names of two operations".to_string());
+        }
+        Ok((
+            names[0].to_string(),
+            self.op(names[0])?,
+            names[1].to_string(),
+            self.op(names[1])?,
+        ))
+    }
+
+    /// Run one command, returning what it prints.
+    pub fn run(&mut self, line: &str) -> Result<String, String> {
+        let mut out = String::new();
+        let (command, args) = match line.find(char::is_whitespace) {
+            Some(index) => (&line[..index], line[index..].trim()),
+            None => (line, ""),
+        };
+
+        match command {
+            "doc" => {
+                let doc = parse_doc(args).map_err(|err| err.to_string())?;
+                if let Err(err) = validate_doc(&doc) {
+                    outln!(out, "warning: the document isn't valid: {}", err);
+                }
+                self.doc = doc;
+                self.history.clear();
+                outln!(out, "{}", format_doc(&self.doc.0));
+            }
+            "let" => {
+                let index = args
+                    .find('=')
+                    .ok_or_else(|| "expected let <name> = <op>".to_string())?;
+                let name = args[..index].trim();
+                if name.is_empty() || name.contains(char::is_whitespace) {
+                    return Err(format!("invalid name {:?}", name));
+                }
+                let op = parse_op(&args[index + 1..]).map_err(|err| err.to_string())?;
+                outln!(out, "{} = {}", name, format_op(&op));
+                self.ops.insert(name.to_string(), op);
+            }
+            "apply" => {
+                let op = self.op_or_inline(args)?;
+                validate_op(&self.doc, &op).map_err(|err| format!("can't apply: {}", err))?;
+                let doc = attempt(|| Op::apply(&self.doc, &op))?;
+                self.history.push(::std::mem::replace(&mut self.doc, doc));
+                outln!(out, "{}", format_doc(&self.doc.0));
+            }
+            "compose" => {
+                let (_, a, _, b) = self.two_ops(args)?;
+                let result = attempt(|| Op::compose(&a, &b))?;
+                outln!(out, "_ = {}", format_op(&result));
+                self.ops.insert("_".to_string(), result);
+            }
+            "transform" => {
+                let (a_name, a, b_name, b) = self.two_ops(args)?;
+                let (a_, b_) = attempt(|| Op::transform::<RtfSchema>(&a, &b))?;
+                outln!(out, "{}' = {}", a_name, format_op(&a_));
+                outln!(out, "{}' = {}", b_name, format_op(&b_));
+
+                // Both paths should end up at the same operation.
+                let converged = attempt(|| Op::compose(&a, &a_) == Op::compose(&b, &b_));
+                match converged {
+                    Ok(true) => outln!(
+                        out,
+                        "{} : {}' and {} : {}' converge",
+                        a_name,
+                        a_name,
+                        b_name,
+                        b_name
+                    ),
+                    Ok(false) => outln!(
+                        out,
+                        "{} : {}' and {} : {}' DIVERGE",
+                        a_name,
+                        a_name,
+                        b_name,
+                        b_name
+                    ),
+                    Err(err) => outln!(out, "could not compose the results: {}", err),
+                }
+                self.ops.insert(format!("{}'", a_name), a_);
+                self.ops.insert(format!("{}'", b_name), b_);
+            }
+            "show" => {
+                if args.is_empty() {
+                    outln!(out, "{}", format_doc(&self.doc.0));
+                } else {
+                    outln!(out, "{} = {}", args, format_op(&self.op(args)?));
+                }
+            }
+            "ops" => {
+                for (name, op) in &self.ops {
+                    outln!(out, "{} = {}", name, format_op(op));
+                }
+            }
+            "ron" => {
+                let output = if args.is_empty() {
+                    ron::ser::to_string(&self.doc.0)
+                } else {
+                    ron::ser::to_string(&self.op_or_inline(args)?)
+                };
+                outln!(out, "{}", output.map_err(|err| err.to_string())?);
+            }
+            "undo" => {
+                self.doc = self
+                    .history
+                    .pop()
+                    .ok_or_else(|| "nothing to undo".to_string())?;
+                outln!(out, "{}", format_doc(&self.doc.0));
+            }
+            "help" => out.push_str(HELP),
+            _ => return Err(format!("unknown command {:?}, try help", command)),
+        }
+        Ok(out)
+    }
+}
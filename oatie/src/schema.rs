@@ -40,9 +40,9 @@ pub enum RtfTrack {
     ListItems,     // bullet
     BlockQuotes,   // blockquote
     Blocks,        // h1, h2, h3, h4, h5, h6, p, pre
-    BlockObjects,  // hr
+    BlockObjects,  // hr, embed, attachment
     Inlines,       // span
-    InlineObjects, // caret
+    InlineObjects, // caret, math, img
 }
 
 impl Track for RtfTrack {
@@ -74,7 +74,7 @@ impl Track for RtfTrack {
     fn allowed_in_root(&self) -> bool {
         use self::RtfTrack::*;
         match *self {
-            Blocks | ListItems | BlockObjects => true,
+            Blocks | ListItems | BlockQuotes | BlockObjects => true,
             _ => false,
         }
     }
@@ -107,7 +107,10 @@ impl Track for RtfTrack {
         match *self {
             ListItems => vec![ListItems, BlockQuotes],
             BlockQuotes => vec![ListItems, BlockQuotes],
-            Blocks => vec![ListItems, BlockObjects],
+            // Blocks and block objects sit in the same containers, so
+            // wrapping or unwrapping one regenerates the same ancestors as
+            // the other.
+            Blocks => vec![ListItems, BlockQuotes],
             BlockObjects => vec![ListItems, BlockQuotes],
             Inlines | InlineObjects => vec![ListItems, BlockQuotes, Blocks],
         }
@@ -137,6 +140,18 @@ impl Schema for RtfSchema {
                 "tag".to_string() => "span".to_string(),
                 "class".to_string() => format_classes(&c),
             })
+        } else if a.get("align") != b.get("align")
+            && Self::track_type_from_attrs(a) == Some(RtfTrack::Blocks)
+            && Self::track_type_from_attrs(b) == Some(RtfTrack::Blocks)
+        {
+            // Concurrent changes to the same block keep the winning side's
+            // attributes, but an alignment set by only the other side is
+            // kept, so retagging a block doesn't undo its alignment.
+            let mut c = a.clone();
+            if let (None, Some(align)) = (a.get("align"), b.get("align")) {
+                c.insert("align".to_string(), align.clone());
+            }
+            Some(c)
         } else {
             None
         }
@@ -145,12 +160,13 @@ impl Schema for RtfSchema {
     fn track_type_from_attrs(attrs: &Attrs) -> Option<Self::Track> {
         match &*attrs["tag"] {
             "bullet" => Some(RtfTrack::ListItems),
-            "p" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "pre" | "html" => {
+            "blockquote" => Some(RtfTrack::BlockQuotes),
+            "p" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "pre" | "html" | "dt" | "dd" => {
                 Some(RtfTrack::Blocks)
             }
             "span" => Some(RtfTrack::Inlines),
-            "caret" => Some(RtfTrack::InlineObjects),
-            "hr" => Some(RtfTrack::BlockObjects),
+            "caret" | "math" | "img" | "doclink" | "mention" => Some(RtfTrack::InlineObjects),
+            "hr" | "embed" | "attachment" => Some(RtfTrack::BlockObjects),
             _ => None,
         }
     }
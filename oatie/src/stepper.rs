@@ -258,8 +258,8 @@ impl DocStepper {
     pub fn head(&self) -> Option<DocElement> {
         match self.rest.get(self.head as usize) {
             Some(&DocChars(ref text)) => {
-                let (_, right) = text.clone().split_at(self.char_debt);
-                Some(DocChars(right))
+                let divided = DividedString::new(text.clone(), self.char_debt);
+                Some(DocChars(divided.right().clone()))
             }
             Some(value) => Some(value.clone()),
             None => None,
@@ -269,8 +269,8 @@ impl DocStepper {
     pub fn unhead(&self) -> Option<DocElement> {
         if self.char_debt > 0 {
             if let Some(&DocChars(ref text)) = self.rest.get(self.head as usize) {
-                let (left, right) = text.clone().split_at(self.char_debt);
-                return Some(DocChars(left));
+                let divided = DividedString::new(text.clone(), self.char_debt);
+                return Some(DocChars(divided.left().clone()));
             } else {
                 unreachable!();
             }
@@ -1,13 +1,16 @@
 use serde::{
     de::{
         self,
+        DeserializeOwned,
         SeqAccess,
         Visitor,
     },
     ser::SerializeSeq,
     Deserialize, Deserializer, Serialize, Serializer,
 };
+use serde_json;
 use std::{
+    cell::RefCell,
     collections::{
         HashMap,
         HashSet,
@@ -21,13 +24,15 @@ use std::{
 };
 
 #[repr(u8)]
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum Style {
     Normie,   // sentinel
     Selected, // never used except on the client
     Bold,
     Italic,
     Link,
+    FontSize,   // a CSS length, like "14px"
+    FontFamily, // a list of font names, like "Georgia, serif"
 }
 
 impl fmt::Display for Style {
@@ -36,9 +41,175 @@ impl fmt::Display for Style {
     }
 }
 
-pub type StyleMap = HashMap<Style, Option<String>>;
 pub type StyleSet = HashSet<Style>;
 
+/// Styles and their values. Text rarely has more than a couple of styles,
+/// so they're kept in a vector sorted by style rather than a hash map,
+/// which is cheaper to clone, compare, and look up in at these sizes. It
+/// has the parts of the HashMap interface the rest of the code uses, and
+/// serializes the same way.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct StyleMap(Vec<(Style, Option<String>)>);
+
+impl StyleMap {
+    pub fn new() -> StyleMap {
+        StyleMap(vec![])
+    }
+
+    fn position(&self, style: &Style) -> Result<usize, usize> {
+        self.0.binary_search_by(|(key, _)| key.cmp(style))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn get(&self, style: &Style) -> Option<&Option<String>> {
+        self.position(style).ok().map(|index| &self.0[index].1)
+    }
+
+    pub fn contains_key(&self, style: &Style) -> bool {
+        self.position(style).is_ok()
+    }
+
+    /// Set a style's value, returning the value it had.
+    pub fn insert(&mut self, style: Style, value: Option<String>) -> Option<Option<String>> {
+        match self.position(&style) {
+            Ok(index) => Some(::std::mem::replace(&mut self.0[index].1, value)),
+            Err(index) => {
+                self.0.insert(index, (style, value));
+                None
+            }
+        }
+    }
+
+    pub fn remove(&mut self, style: &Style) -> Option<Option<String>> {
+        match self.position(style) {
+            Ok(index) => Some(self.0.remove(index).1),
+            Err(_) => None,
+        }
+    }
+
+    pub fn retain<F: FnMut(&Style, &Option<String>) -> bool>(&mut self, mut f: F) {
+        self.0.retain(|(style, value)| f(style, value))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Style, &Option<String>)> {
+        self.0.iter().map(|(style, value)| (style, value))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Style> {
+        self.0.iter().map(|(style, _)| style)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Option<String>> {
+        self.0.iter().map(|(_, value)| value)
+    }
+}
+
+impl From<HashMap<Style, Option<String>>> for StyleMap {
+    fn from(map: HashMap<Style, Option<String>>) -> StyleMap {
+        map.into_iter().collect()
+    }
+}
+
+impl ::std::iter::FromIterator<(Style, Option<String>)> for StyleMap {
+    fn from_iter<I: IntoIterator<Item = (Style, Option<String>)>>(iter: I) -> StyleMap {
+        let mut map = StyleMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl Extend<(Style, Option<String>)> for StyleMap {
+    fn extend<I: IntoIterator<Item = (Style, Option<String>)>>(&mut self, iter: I) {
+        for (style, value) in iter {
+            self.insert(style, value);
+        }
+    }
+}
+
+impl IntoIterator for StyleMap {
+    type Item = (Style, Option<String>);
+    type IntoIter = ::std::vec::IntoIter<(Style, Option<String>)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl Serialize for StyleMap {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_map(self.iter())
+    }
+}
+
+impl<'de> Deserialize<'de> for StyleMap {
+    fn deserialize<D>(deserializer: D) -> Result<StyleMap, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let map: HashMap<Style, Option<String>> = Deserialize::deserialize(deserializer)?;
+        Ok(map.into())
+    }
+}
+
+thread_local! {
+    // The buffer `from_json_shared` is deserializing, which strings
+    // deserialized from it refer into.
+    static SHARED_BUFFER: RefCell<Option<Arc<String>>> = RefCell::new(None);
+}
+
+// Inputs smaller than this are deserialized as usual. Referring into them
+// saves little, and would keep each message alive as long as any string
+// from it is.
+const SHARED_MIN_INPUT: usize = 64 * 1024;
+
+// Strings shorter than this are copied even from a shared buffer, so the
+// short runs of text between styles don't keep the whole input alive.
+const SHARED_MIN_STRING: usize = 256;
+
+// Sets the shared buffer for as long as it lives, then restores the one set
+// before, even if deserializing panics.
+struct SharedBufferGuard(Option<Arc<String>>);
+
+impl SharedBufferGuard {
+    fn set(buffer: Arc<String>) -> SharedBufferGuard {
+        SharedBufferGuard(SHARED_BUFFER.with(|shared| shared.replace(Some(buffer))))
+    }
+}
+
+impl Drop for SharedBufferGuard {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        SHARED_BUFFER.with(|shared| *shared.borrow_mut() = previous);
+    }
+}
+
+/// Deserialize JSON leaving the text of its documents in the input buffer.
+/// Each long DocString refers to its text in the buffer instead of copying
+/// it into a String of its own, until the string is changed. Short strings
+/// and text containing escapes are copied as usual.
+///
+/// The buffer lives as long as any string referring into it, so this only
+/// shares text out of large inputs, like a client's initial sync. Smaller
+/// inputs are deserialized as `serde_json::from_str` would.
+pub fn from_json_shared<T: DeserializeOwned>(input: String) -> Result<T, serde_json::Error> {
+    if input.len() < SHARED_MIN_INPUT {
+        return serde_json::from_str(&input);
+    }
+    let buffer = Arc::new(input);
+    let _guard = SharedBufferGuard::set(buffer.clone());
+    serde_json::from_str(&buffer)
+}
+
 /// Abstraction for String that allows a limited set of operations
 /// with good optimization. (Or that's the idea.)
 #[derive(Clone, Debug)]
@@ -61,6 +232,25 @@ impl DocString {
         DocString(Arc::new(input.to_owned()), None, Some(Arc::new(styles)))
     }
 
+    // Text borrowed from the input during deserialization, which refers
+    // into the shared buffer if it came from there.
+    fn from_borrowed_str(input: &str) -> DocString {
+        if input.len() < SHARED_MIN_STRING {
+            return DocString::from_str(input);
+        }
+        SHARED_BUFFER.with(|shared| {
+            if let Some(ref buffer) = *shared.borrow() {
+                let base = buffer.as_ptr() as usize;
+                let start = input.as_ptr() as usize;
+                if start >= base && start + input.len() <= base + buffer.len() {
+                    let start = start - base;
+                    return DocString(buffer.clone(), Some(start..(start + input.len())), None);
+                }
+            }
+            DocString::from_str(input)
+        })
+    }
+
     // TODO audit use of this
     pub fn as_str(&self) -> &str {
         if let Some(ref range) = self.1 {
@@ -70,27 +260,50 @@ impl DocString {
         }
     }
 
+    /// Whether this string is a view of part of a larger buffer, as text
+    /// split from another string or shared by `from_json_shared` is.
+    pub fn is_view(&self) -> bool {
+        self.1.is_some()
+    }
+
     pub fn styles(&self) -> Option<Arc<StyleMap>> {
         self.2.clone()
     }
 
+    /// Whether two strings have the same styles. Interned styles compare by
+    /// pointer.
+    pub fn same_styles(&self, other: &DocString) -> bool {
+        match (&self.2, &other.2) {
+            (&Some(ref a), &Some(ref b)) => Arc::ptr_eq(a, b) || a == b,
+            (&None, &None) => true,
+            _ => false,
+        }
+    }
+
+    /// Use a shared copy of this string's styles, as from an Interner.
+    pub fn share_styles(&mut self, styles: Arc<StyleMap>) {
+        debug_assert!(self.2.as_ref().map(|x| **x == *styles).unwrap_or(false));
+        self.2 = Some(styles);
+    }
+
+    // Styles are shared between the strings split from one another, and
+    // only copied when a change leaves them different.
     pub fn remove_styles(&mut self, styles: &StyleSet) {
         if let &mut Some(ref mut self_styles) = &mut self.2 {
-            let mut new_styles: StyleMap = (**self_styles).clone();
-            *self_styles = Arc::new(new_styles
-                .drain()
-                .filter(|(ref x, _)| !styles.contains(x))
-                .collect());
+            if self_styles.keys().any(|style| styles.contains(style)) {
+                Arc::make_mut(self_styles).retain(|style, _| !styles.contains(style));
+            }
         } else {
             // no-op
         }
     }
 
     pub fn extend_styles(&mut self, styles: &StyleMap) {
-        if let &mut Some(ref self_styles) = &mut self.2 {
-            let mut new_styles: StyleMap = (**self_styles).clone();
-            new_styles.extend(styles.iter().map(|(a, b)| (a.to_owned(), b.to_owned())));
-            self.2 = Some(Arc::new(new_styles));
+        if let &mut Some(ref mut self_styles) = &mut self.2 {
+            if styles.iter().any(|(style, value)| self_styles.get(style) != Some(value)) {
+                Arc::make_mut(self_styles)
+                    .extend(styles.iter().map(|(a, b)| (a.to_owned(), b.to_owned())));
+            }
         } else {
             self.2 = Some(Arc::new(styles.to_owned()));
         }
@@ -104,14 +317,26 @@ impl DocString {
         self.1 = None;
     }
 
+    // Byte index of a character offset, or the length for the offset past
+    // the last character.
+    fn byte_index(&self, char_boundary: usize) -> usize {
+        let value = self.as_str();
+        value
+            .char_indices()
+            .nth(char_boundary)
+            .map(|(index, _)| index)
+            .unwrap_or_else(|| {
+                assert!(
+                    char_boundary <= value.chars().count(),
+                    "split past the end of a string"
+                );
+                value.len()
+            })
+    }
+
     // TODO consume self?
     pub fn split_at(&self, char_boundary: usize) -> (DocString, DocString) {
-        let (byte_index, _) = self
-            .as_str()
-            .char_indices()
-            .skip(char_boundary)
-            .next()
-            .unwrap();
+        let byte_index = self.byte_index(char_boundary);
         let mut start = 0;
         let mut end = self.0.len();
         if let Some(ref range) = self.1 {
@@ -150,6 +375,99 @@ impl DocString {
     }
 }
 
+/// A DocString divided at a character offset, as text is around a caret.
+/// The text on either side is a view sharing one buffer, so moving the
+/// division doesn't copy any text. Each side keeps its own styles, which
+/// differ once a side is replaced with differently styled text.
+#[derive(Clone, Debug)]
+pub struct DividedString {
+    offset: usize,
+    left: DocString,
+    right: DocString,
+}
+
+impl DividedString {
+    /// Divide a string before the character at `offset`, which may be the
+    /// length of the string to divide it at its end.
+    pub fn new(string: DocString, offset: usize) -> DividedString {
+        let (left, right) = string.split_at(offset);
+        DividedString {
+            offset,
+            left,
+            right,
+        }
+    }
+
+    /// Move the division to another character offset. Text can't move from
+    /// one side to the other if they're styled differently, so then this
+    /// leaves the division where it is and returns false.
+    pub fn seek(&mut self, offset: usize) -> bool {
+        if !self.left.same_styles(&self.right) {
+            return false;
+        }
+        let (left, right) = self.joined().split_at(offset);
+        self.offset = offset;
+        self.left = left;
+        self.right = right;
+        true
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The text before the division.
+    pub fn left(&self) -> &DocString {
+        &self.left
+    }
+
+    /// The text after the division.
+    pub fn right(&self) -> &DocString {
+        &self.right
+    }
+
+    /// Replace the text before the division, which stays after it.
+    pub fn update_left(&mut self, left: DocString) {
+        self.offset = left.char_len();
+        self.left = left;
+        self.rejoin();
+    }
+
+    /// Replace the text after the division.
+    pub fn update_right(&mut self, right: DocString) {
+        self.right = right;
+        self.rejoin();
+    }
+
+    // The text of both sides, with the left side's styles.
+    fn joined(&self) -> DocString {
+        let mut string = self.left.clone();
+        string.push_str(self.right.as_str());
+        string
+    }
+
+    // Put both sides back in one buffer, each with its own styles.
+    fn rejoin(&mut self) {
+        let (left, mut right) = self.joined().split_at(self.offset);
+        right.2 = self.right.2.clone();
+        self.left = left;
+        self.right = right;
+    }
+
+    /// The divided text: one string if both sides have the same styles, or
+    /// else a string for each side that isn't empty.
+    pub fn into_strings(self) -> Vec<DocString> {
+        if self.left.same_styles(&self.right) {
+            vec![self.joined()]
+        } else {
+            vec![self.left, self.right]
+                .into_iter()
+                .filter(|string| !string.is_empty())
+                .collect()
+        }
+    }
+}
+
 impl PartialEq for DocString {
     fn eq(&self, other: &DocString) -> bool {
         self.as_str() == other.as_str()
@@ -195,13 +513,21 @@ impl<'de> Deserialize<'de> for DocString {
                 Ok(DocString::from_str(value))
             }
 
+            fn visit_borrowed_str<E>(self, value: &'de str) -> Result<DocString, E>
+            where
+                E: de::Error,
+            {
+                Ok(DocString::from_borrowed_str(value))
+            }
+
             fn visit_seq<A>(self, mut seq: A) -> Result<DocString, A::Error>
             where
                 A: SeqAccess<'de>,
             {
-                if let Some(inner) = seq.next_element::<String>()? {
+                if let Some(inner) = seq.next_element::<UnstyledString>()? {
                     if let Some(styles) = seq.next_element::<StyleMap>()? {
-                        Ok(DocString::from_string_styled(inner, styles))
+                        let DocString(value, range, _) = inner.0;
+                        Ok(DocString(value, range, Some(Arc::new(styles))))
                     } else {
                         Err(de::Error::unknown_field("1", FIELDS))
                     }
@@ -215,3 +541,40 @@ impl<'de> Deserialize<'de> for DocString {
         deserializer.deserialize_any(FieldVisitor)
     }
 }
+
+// The text of a styled DocString, deserialized on its own so it can be
+// borrowed from the input too.
+struct UnstyledString(DocString);
+
+impl<'de> Deserialize<'de> for UnstyledString {
+    fn deserialize<D>(deserializer: D) -> Result<UnstyledString, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct TextVisitor;
+
+        impl<'de> Visitor<'de> for TextVisitor {
+            type Value = UnstyledString;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("string")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<UnstyledString, E>
+            where
+                E: de::Error,
+            {
+                Ok(UnstyledString(DocString::from_str(value)))
+            }
+
+            fn visit_borrowed_str<E>(self, value: &'de str) -> Result<UnstyledString, E>
+            where
+                E: de::Error,
+            {
+                Ok(UnstyledString(DocString::from_borrowed_str(value)))
+            }
+        }
+
+        deserializer.deserialize_str(TextVisitor)
+    }
+}
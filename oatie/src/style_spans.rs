@@ -0,0 +1,255 @@
+//! Inline styles kept apart from the text they style. With styles stored on
+//! each DocString, text is split into a new run wherever its styles change,
+//! and restyling a range touches every run in it. Here each style of a
+//! block is instead a tree of intervals over the block's contents, so its
+//! text stays in one piece between inline objects and styling a range only
+//! replaces the intervals it covers.
+//!
+//! Offsets count characters, and inline objects like carets as one each, the
+//! same as skips in operations.
+
+use super::doc::*;
+use std::collections::{
+    BTreeMap,
+    BTreeSet,
+};
+use std::ops::Range;
+
+// Intervals of one style, keyed by their start, with their end and value.
+// Intervals never overlap, and touching intervals with the same value are
+// joined.
+type Intervals = BTreeMap<usize, (usize, Option<String>)>;
+
+// Remove a range from intervals, trimming those that overlap it.
+fn cut(intervals: &mut Intervals, range: &Range<usize>) {
+    let overlapping = intervals
+        .range(..range.end)
+        .filter(|&(_, &(end, _))| end > range.start)
+        .map(|(&start, _)| start)
+        .collect::<Vec<_>>();
+    for start in overlapping {
+        let (end, value) = intervals.remove(&start).unwrap();
+        if start < range.start {
+            intervals.insert(start, (range.start, value.clone()));
+        }
+        if end > range.end {
+            intervals.insert(range.end, (end, value));
+        }
+    }
+}
+
+// Join the interval starting at `start` with its neighbors, if they touch
+// it and have the same value.
+fn join(intervals: &mut Intervals, start: usize) {
+    let (mut start, mut end, value) = match intervals.get(&start) {
+        Some(&(end, ref value)) => (start, end, value.clone()),
+        None => return,
+    };
+
+    let previous = intervals
+        .range(..start)
+        .next_back()
+        .map(|(&start, &(end, ref value))| (start, end, value.clone()));
+    if let Some((previous_start, previous_end, previous_value)) = previous {
+        if previous_end == start && previous_value == value {
+            intervals.remove(&start);
+            start = previous_start;
+        }
+    }
+
+    let next = intervals.get(&end).cloned();
+    if let Some((next_end, next_value)) = next {
+        if next_value == value {
+            intervals.remove(&end);
+            end = next_end;
+        }
+    }
+
+    intervals.insert(start, (end, value));
+}
+
+/// The styles of a block, as intervals of each style.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StyleSpans {
+    styles: BTreeMap<Style, Intervals>,
+}
+
+impl StyleSpans {
+    pub fn new() -> StyleSpans {
+        StyleSpans::default()
+    }
+
+    /// Style a range, replacing the value the style had in it.
+    pub fn add(&mut self, range: Range<usize>, style: Style, value: Option<String>) {
+        if range.start >= range.end {
+            return;
+        }
+        let intervals = self.styles.entry(style).or_insert_with(BTreeMap::new);
+        cut(intervals, &range);
+        intervals.insert(range.start, (range.end, value));
+        join(intervals, range.start);
+    }
+
+    /// Remove a style from a range.
+    pub fn remove(&mut self, range: Range<usize>, style: &Style) {
+        let now_empty = match self.styles.get_mut(style) {
+            Some(intervals) => {
+                cut(intervals, &range);
+                intervals.is_empty()
+            }
+            None => false,
+        };
+        if now_empty {
+            self.styles.remove(style);
+        }
+    }
+
+    /// Whether a style covers all of a range, with whatever values.
+    pub fn covers(&self, range: Range<usize>, style: &Style) -> bool {
+        let intervals = match self.styles.get(style) {
+            Some(intervals) => intervals,
+            None => return false,
+        };
+        // Intervals with different values touch without being joined, so
+        // follow them on from the one the range starts in.
+        let mut covered = match intervals.range(..=range.start).next_back() {
+            Some((_, &(end, _))) if end > range.start => end,
+            _ => return false,
+        };
+        while covered < range.end {
+            covered = match intervals.get(&covered) {
+                Some(&(end, _)) => end,
+                None => return false,
+            };
+        }
+        true
+    }
+
+    /// Remove a style from a range if it covers all of it, or else add it.
+    pub fn toggle(&mut self, range: Range<usize>, style: Style, value: Option<String>) {
+        if self.covers(range.clone(), &style) {
+            self.remove(range, &style);
+        } else {
+            self.add(range, style, value);
+        }
+    }
+
+    /// The styles at an offset.
+    pub fn styles_at(&self, offset: usize) -> StyleMap {
+        let mut styles = StyleMap::new();
+        for (style, intervals) in &self.styles {
+            if let Some((_, &(end, ref value))) = intervals.range(..=offset).next_back() {
+                if offset < end {
+                    styles.insert(style.clone(), value.clone());
+                }
+            }
+        }
+        styles
+    }
+
+    /// A range divided into runs with the same styles.
+    pub fn runs(&self, range: Range<usize>) -> Vec<(Range<usize>, StyleMap)> {
+        let mut points = BTreeSet::new();
+        points.insert(range.start);
+        points.insert(range.end);
+        for intervals in self.styles.values() {
+            for (&start, &(end, _)) in intervals {
+                for &point in &[start, end] {
+                    if point > range.start && point < range.end {
+                        points.insert(point);
+                    }
+                }
+            }
+        }
+
+        let points = points.into_iter().collect::<Vec<_>>();
+        points
+            .windows(2)
+            .filter(|pair| pair[0] < pair[1])
+            .map(|pair| (pair[0]..pair[1], self.styles_at(pair[0])))
+            .collect()
+    }
+}
+
+/// The contents of a block with its styles kept apart. Its text is
+/// unstyled, in one piece between each inline object.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StyledSpan {
+    pub span: DocSpan,
+    pub styles: StyleSpans,
+}
+
+impl StyledSpan {
+    /// Separate the styles of a block's contents.
+    pub fn from_span(span: &DocSpan) -> StyledSpan {
+        let mut contents: DocSpan = vec![];
+        let mut styles = StyleSpans::new();
+        let mut offset = 0;
+        for elem in span {
+            match elem {
+                DocChars(text) => {
+                    let len = text.char_len();
+                    if let Some(text_styles) = text.styles() {
+                        for (style, value) in text_styles.iter() {
+                            styles.add(offset..offset + len, style.clone(), value.clone());
+                        }
+                    }
+                    let joined = match contents.last_mut() {
+                        Some(&mut DocChars(ref mut previous)) => {
+                            previous.push_str(text.as_str());
+                            true
+                        }
+                        _ => false,
+                    };
+                    if !joined {
+                        contents.push(DocChars(DocString::from_str(text.as_str())));
+                    }
+                    offset += len;
+                }
+                DocGroup(..) => {
+                    contents.push(elem.clone());
+                    offset += 1;
+                }
+            }
+        }
+        StyledSpan {
+            span: contents,
+            styles,
+        }
+    }
+
+    /// The block's contents with styles on its text again, split into a
+    /// run wherever they change.
+    pub fn to_span(&self) -> DocSpan {
+        let mut out = vec![];
+        let mut offset = 0;
+        for elem in &self.span {
+            match elem {
+                DocChars(text) => {
+                    let chars = text.as_str().chars().collect::<Vec<_>>();
+                    for (range, styles) in self.styles.runs(offset..offset + chars.len()) {
+                        let run = chars[(range.start - offset)..(range.end - offset)]
+                            .iter()
+                            .collect::<String>();
+                        out.push(DocChars(if styles.is_empty() {
+                            DocString::from_string(run)
+                        } else {
+                            DocString::from_string_styled(run, styles)
+                        }));
+                    }
+                    offset += chars.len();
+                }
+                DocGroup(..) => {
+                    out.push(elem.clone());
+                    offset += 1;
+                }
+            }
+        }
+        out
+    }
+
+    /// Length of the contents, in the units of the style intervals.
+    pub fn len(&self) -> usize {
+        self.span.skip_len()
+    }
+}
@@ -0,0 +1,383 @@
+//! A terse notation for documents and operations, short enough to type by
+//! hand. An operation is its deletion and its addition separated by `|`:
+//!
+//! ```text
+//! s2 d3 w(s1) | s2 "new" <p>("text") w(s1 "x"{Bold})
+//! ```
+//!
+//! In both halves, `sN` skips N elements and `w(..)` steps into a group.
+//! Deletions use `dN` to delete N characters, `g(..)` to delete a group, and
+//! `uN{Bold}` to remove styles from N characters. Additions use `"text"` to
+//! add text, styled as `"text"{Bold,Link=url}`, `<tag key=value>(..)` to add
+//! a group, and `tN{Bold}` to style N characters.
+//!
+//! Documents are written like additions of only text and groups.
+
+use super::doc::*;
+use failure::Error;
+use std::collections::HashMap;
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || "_-./:#".contains(c)
+}
+
+fn parse_style(name: &str) -> Result<Style, Error> {
+    Ok(match name {
+        "Normie" => Style::Normie,
+        "Selected" => Style::Selected,
+        "Bold" => Style::Bold,
+        "Italic" => Style::Italic,
+        "Link" => Style::Link,
+        "FontSize" => Style::FontSize,
+        "FontFamily" => Style::FontFamily,
+        _ => bail!("unknown style {:?}", name),
+    })
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn new(input: &str) -> Parser {
+        Parser {
+            chars: input.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).cloned()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        if c.is_some() {
+            self.pos += 1;
+        }
+        c
+    }
+
+    // Elements are separated by whitespace or commas.
+    fn skip_sep(&mut self) {
+        while let Some(c) = self.peek() {
+            if !(c.is_whitespace() || c == ',') {
+                break;
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), Error> {
+        self.skip_sep();
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => bail!("expected {:?} at {}, found {:?}", expected, self.pos - 1, c),
+            None => bail!("expected {:?}, found the end of input", expected),
+        }
+    }
+
+    fn number(&mut self) -> Result<usize, Error> {
+        let start = self.pos;
+        while self.peek().map(|c| c.is_ascii_digit()).unwrap_or(false) {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            bail!("expected a number at {}", start);
+        }
+        Ok(self.chars[start..self.pos].iter().collect::<String>().parse()?)
+    }
+
+    fn word(&mut self) -> Result<String, Error> {
+        let start = self.pos;
+        while self.peek().map(is_word_char).unwrap_or(false) {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            bail!("expected a name at {}", start);
+        }
+        Ok(self.chars[start..self.pos].iter().collect())
+    }
+
+    // Called after the opening quote.
+    fn string(&mut self) -> Result<String, Error> {
+        let mut out = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(out),
+                Some('\\') => match self.next() {
+                    Some('n') => out.push('\n'),
+                    Some('t') => out.push('\t'),
+                    Some(c) => out.push(c),
+                    None => bail!("unterminated string"),
+                },
+                Some(c) => out.push(c),
+                None => bail!("unterminated string"),
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<String, Error> {
+        if self.peek() == Some('"') {
+            self.next();
+            self.string()
+        } else {
+            self.word()
+        }
+    }
+
+    // Called after the opening brace.
+    fn style_map(&mut self) -> Result<StyleMap, Error> {
+        let mut styles = StyleMap::new();
+        loop {
+            self.skip_sep();
+            if self.peek() == Some('}') {
+                self.next();
+                return Ok(styles);
+            }
+            let style = parse_style(&self.word()?)?;
+            let value = if self.peek() == Some('=') {
+                self.next();
+                Some(self.value()?)
+            } else {
+                None
+            };
+            styles.insert(style, value);
+        }
+    }
+
+    fn style_set(&mut self) -> Result<StyleSet, Error> {
+        self.expect('{')?;
+        Ok(self.style_map()?.into_iter().map(|(style, _)| style).collect())
+    }
+
+    fn del_span(&mut self) -> Result<DelSpan, Error> {
+        let mut span = vec![];
+        loop {
+            self.skip_sep();
+            let c = match self.peek() {
+                None | Some('|') | Some(')') => return Ok(span),
+                Some(c) => c,
+            };
+            self.next();
+            span.push(match c {
+                's' => DelSkip(self.number()?),
+                'd' => DelChars(self.number()?),
+                'u' => {
+                    let count = self.number()?;
+                    DelStyles(count, self.style_set()?)
+                }
+                'w' => DelWithGroup(self.del_group()?),
+                'g' => DelGroup(self.del_group()?),
+                _ => bail!("unexpected {:?} in deletion at {}", c, self.pos - 1),
+            });
+        }
+    }
+
+    fn del_group(&mut self) -> Result<DelSpan, Error> {
+        self.expect('(')?;
+        let inner = self.del_span()?;
+        self.expect(')')?;
+        Ok(inner)
+    }
+
+    fn add_span(&mut self) -> Result<AddSpan, Error> {
+        let mut span = vec![];
+        loop {
+            self.skip_sep();
+            let c = match self.peek() {
+                None | Some(')') => return Ok(span),
+                Some(c) => c,
+            };
+            self.next();
+            span.push(match c {
+                's' => AddSkip(self.number()?),
+                't' => {
+                    let count = self.number()?;
+                    self.expect('{')?;
+                    AddStyles(count, self.style_map()?)
+                }
+                'w' => AddWithGroup(self.add_group()?),
+                '"' => {
+                    let text = self.string()?;
+                    if self.peek() == Some('{') {
+                        self.next();
+                        AddChars(DocString::from_string_styled(text, self.style_map()?))
+                    } else {
+                        AddChars(DocString::from_string(text))
+                    }
+                }
+                '<' => {
+                    let mut attrs = HashMap::new();
+                    attrs.insert("tag".to_string(), self.word()?);
+                    loop {
+                        self.skip_sep();
+                        if self.peek() == Some('>') {
+                            self.next();
+                            break;
+                        }
+                        let key = self.word()?;
+                        self.expect('=')?;
+                        attrs.insert(key, self.value()?);
+                    }
+                    AddGroup(attrs, self.add_group()?)
+                }
+                _ => bail!("unexpected {:?} in addition at {}", c, self.pos - 1),
+            });
+        }
+    }
+
+    fn add_group(&mut self) -> Result<AddSpan, Error> {
+        self.expect('(')?;
+        let inner = self.add_span()?;
+        self.expect(')')?;
+        Ok(inner)
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.skip_sep();
+        match self.peek() {
+            Some(c) => bail!("unexpected {:?} at {}", c, self.pos),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Parse an operation. Either half may be left out, so `s1 d2` only
+/// deletes, and `| "x"` only adds.
+pub fn parse_op(input: &str) -> Result<Op, Error> {
+    let mut parser = Parser::new(input);
+    let del = parser.del_span()?;
+    parser.skip_sep();
+    let add = if parser.peek() == Some('|') {
+        parser.next();
+        parser.add_span()?
+    } else {
+        vec![]
+    };
+    parser.finish()?;
+    Ok((del, add))
+}
+
+fn add_to_doc_span(span: AddSpan) -> Result<DocSpan, Error> {
+    span.into_iter()
+        .map(|elem| {
+            Ok(match elem {
+                AddChars(text) => DocChars(text),
+                AddGroup(attrs, inner) => DocGroup(attrs, add_to_doc_span(inner)?),
+                _ => bail!("documents can only contain text and groups"),
+            })
+        })
+        .collect()
+}
+
+/// Parse a document.
+pub fn parse_doc(input: &str) -> Result<Doc, Error> {
+    let mut parser = Parser::new(input);
+    let span = parser.add_span()?;
+    parser.finish()?;
+    Ok(Doc(add_to_doc_span(span)?))
+}
+
+fn quote(input: &str) -> String {
+    let mut out = String::from("\"");
+    for c in input.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            _ => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn format_value(value: &str) -> String {
+    if !value.is_empty() && value.chars().all(is_word_char) {
+        value.to_string()
+    } else {
+        quote(value)
+    }
+}
+
+fn format_style_map(styles: &StyleMap) -> String {
+    let mut styles = styles
+        .iter()
+        .map(|(style, value)| match value {
+            Some(value) => format!("{}={}", style, format_value(value)),
+            None => style.to_string(),
+        })
+        .collect::<Vec<_>>();
+    styles.sort();
+    format!("{{{}}}", styles.join(","))
+}
+
+fn format_style_set(styles: &StyleSet) -> String {
+    let mut styles = styles.iter().map(|style| style.to_string()).collect::<Vec<_>>();
+    styles.sort();
+    format!("{{{}}}", styles.join(","))
+}
+
+fn format_text(text: &DocString) -> String {
+    match text.styles() {
+        Some(styles) => format!("{}{}", quote(text.as_str()), format_style_map(&styles)),
+        None => quote(text.as_str()),
+    }
+}
+
+// The tag comes first, then the other attributes in order.
+fn format_tag(attrs: &Attrs) -> String {
+    let mut rest = attrs
+        .iter()
+        .filter(|(key, _)| *key != "tag")
+        .map(|(key, value)| format!(" {}={}", key, format_value(value)))
+        .collect::<Vec<_>>();
+    rest.sort();
+    let tag = attrs.get("tag").map(|x| x.as_str()).unwrap_or("");
+    format!("<{}{}>", format_value(tag), rest.concat())
+}
+
+pub fn format_del_span(span: &DelSpan) -> String {
+    span.iter()
+        .map(|elem| match elem {
+            DelSkip(n) => format!("s{}", n),
+            DelChars(n) => format!("d{}", n),
+            DelStyles(n, styles) => format!("u{}{}", n, format_style_set(styles)),
+            DelWithGroup(inner) => format!("w({})", format_del_span(inner)),
+            DelGroup(inner) => format!("g({})", format_del_span(inner)),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn format_add_span(span: &AddSpan) -> String {
+    span.iter()
+        .map(|elem| match elem {
+            AddSkip(n) => format!("s{}", n),
+            AddChars(text) => format_text(text),
+            AddStyles(n, styles) => format!("t{}{}", n, format_style_map(styles)),
+            AddWithGroup(inner) => format!("w({})", format_add_span(inner)),
+            AddGroup(attrs, inner) => format!("{}({})", format_tag(attrs), format_add_span(inner)),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn format_op(op: &Op) -> String {
+    format!("{} | {}", format_del_span(&op.0), format_add_span(&op.1))
+        .trim()
+        .to_string()
+}
+
+pub fn format_doc(span: &DocSpan) -> String {
+    span.iter()
+        .map(|elem| match elem {
+            DocChars(text) => format_text(text),
+            DocGroup(attrs, inner) => format!("{}({})", format_tag(attrs), format_doc(inner)),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
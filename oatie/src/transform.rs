@@ -46,7 +46,8 @@ pub trait Schema: Clone + Debug {
     /// Get the track type from this Attrs.
     fn track_type_from_attrs(attrs: &Attrs) -> Option<Self::Track>;
 
-    /// Combine two Attrs into a new definition.
+    /// Combine two Attrs into a new definition, keeping `a`'s attributes
+    /// where the two conflict.
     fn merge_attrs(a: &Attrs, b: &Attrs) -> Option<Attrs>;
 }
 
@@ -169,7 +170,7 @@ where
 
         // TODO merge this functionality elsewhere
         let real = if let Some(ref a) = a {
-            Some(S::merge_attrs(a, &b).unwrap_or_else(|| b.clone()))
+            Some(S::merge_attrs(b, a).unwrap_or_else(|| b.clone()))
         } else {
             Some(b.clone())
         };
@@ -1721,7 +1722,7 @@ pub fn transform_add_del_inner(
                     // Remove styles from A that were present in B.
                     let combined_styles: StyleMap = a_styles
                         .clone()
-                        .drain()
+                        .into_iter()
                         .filter(|(ref k, _)| b_styles.contains(k))
                         .collect();
 
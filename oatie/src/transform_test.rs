@@ -55,7 +55,18 @@ enum TestSpec {
     TransformTest { doc: DocSpan, a: Op, b: Op },
 }
 
-pub fn run_transform_test<T: Schema>(input: &str) -> Result<(), Error> {
+/// A document, if given, and two concurrent operations on it. The check
+/// operation, if given, is what `a` composed with its transform should be.
+pub struct TransformTest {
+    pub doc: Option<Doc>,
+    pub a: Op,
+    pub b: Op,
+    pub check: Option<Op>,
+}
+
+/// Parse a transform test, in any of the formats of the tests in
+/// edit-common/tests/transform.
+pub fn parse_transform_test(input: &str) -> Result<TransformTest, Error> {
     let mut test: HashMap<String, String> = HashMap::new();
 
     // ron-defined test specs
@@ -108,8 +119,6 @@ pub fn run_transform_test<T: Schema>(input: &str) -> Result<(), Error> {
         }
     }
 
-    println!("entries {:?}", test.keys().collect::<Vec<_>>());
-
     // Extract test entries.
     let a = (
         ron::de::from_str::<DelSpan>(&test["a_del"])?,
@@ -128,6 +137,17 @@ pub fn run_transform_test<T: Schema>(input: &str) -> Result<(), Error> {
         None
     };
 
+    let doc = match test.get("doc") {
+        Some(doc) => Some(Doc(ron::de::from_str::<DocSpan>(doc)?)),
+        None => None,
+    };
+
+    Ok(TransformTest { doc, a, b, check })
+}
+
+pub fn run_transform_test<T: Schema>(input: &str) -> Result<(), Error> {
+    let TransformTest { doc, a, b, check } = parse_transform_test(input)?;
+
     // Check that transforms produce identical operations when composed.
     println!(
         "{}",
@@ -149,10 +169,9 @@ pub fn run_transform_test<T: Schema>(input: &str) -> Result<(), Error> {
     }
 
     // Check against provided document.
-    if let Some(doc) = test.get("doc") {
+    if let Some(doc) = doc {
         println!("{}", Paint::red("(!) validating docs..."));
 
-        let doc = Doc(ron::de::from_str::<DocSpan>(doc)?);
         println!("original document: {:?}", doc);
         validate_doc_span(&mut ValidateContext::new(), &doc.0)?;
         println!();
@@ -1,5 +1,6 @@
 //! Validates a document matches a given Schema.
 
+use super::apply::apply_delete;
 use super::compose;
 use super::doc::*;
 use super::normalize;
@@ -51,6 +52,9 @@ pub fn validate_doc_span(ctx: &mut ValidateContext, span: &DocSpan) -> Result<()
                 if attrs["tag"] == "bullet" {
                     ensure!(!span.is_empty(), "Expected non-empty bullet");
                 }
+                if attrs["tag"] == "blockquote" {
+                    ensure!(!span.is_empty(), "Expected non-empty blockquote");
+                }
 
                 ctx.stack.push(attrs.clone());
                 validate_doc_span(ctx, span)?;
@@ -97,3 +101,139 @@ pub fn validate_doc(doc: &Doc) -> Result<(), Error> {
     let mut ctx = ValidateContext::new();
     validate_doc_span(&mut ctx, &doc.0)
 }
+
+/// Tracks a position inside of a span while checking an operation against it.
+struct SpanCursor<'a> {
+    span: &'a [DocElement],
+    index: usize,
+    offset: usize,
+}
+
+impl<'a> SpanCursor<'a> {
+    fn new(span: &'a [DocElement]) -> SpanCursor<'a> {
+        SpanCursor {
+            span,
+            index: 0,
+            offset: 0,
+        }
+    }
+
+    fn take_group(&mut self) -> Result<&'a DocSpan, Error> {
+        match self.span.get(self.index) {
+            Some(&DocGroup(_, ref span)) => {
+                self.index += 1;
+                Ok(span)
+            }
+            Some(&DocChars(..)) => bail!("Expected group, found chars"),
+            None => bail!("Expected group, found end of span"),
+        }
+    }
+
+    fn take(&mut self, mut count: usize, allow_groups: bool) -> Result<(), Error> {
+        while count > 0 {
+            match self.span.get(self.index) {
+                Some(&DocChars(ref text)) => {
+                    let remaining = text.char_len() - self.offset;
+                    if count < remaining {
+                        self.offset += count;
+                        count = 0;
+                    } else {
+                        self.index += 1;
+                        self.offset = 0;
+                        count -= remaining;
+                    }
+                }
+                Some(&DocGroup(..)) => {
+                    ensure!(allow_groups, "Expected chars, found group");
+                    self.index += 1;
+                    count -= 1;
+                }
+                None => bail!("Operation is longer than the span it modifies"),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn validate_op_attrs(attrs: &Attrs) -> Result<(), Error> {
+    ensure!(attrs.contains_key("tag"), "Group is missing a tag");
+    ensure!(
+        RtfSchema::track_type_from_attrs(attrs).is_some(),
+        "Unknown group tag {:?}",
+        attrs["tag"]
+    );
+    if attrs["tag"] == "caret" {
+        ensure!(attrs.contains_key("client"), "Caret is missing a client");
+    }
+    Ok(())
+}
+
+fn validate_op_text(text: &DocString) -> Result<(), Error> {
+    ensure!(!text.is_empty(), "Empty char string");
+    ensure!(
+        text.as_str().chars().all(|c| c.is_whitespace() || !c.is_control()),
+        "Char string contains control characters"
+    );
+    Ok(())
+}
+
+fn validate_del_span(cursor: &mut SpanCursor, span: &DelSpan) -> Result<(), Error> {
+    for elem in span {
+        match *elem {
+            DelSkip(count) => {
+                ensure!(count > 0, "Empty DelSkip");
+                cursor.take(count, true)?;
+            }
+            DelChars(count) | DelStyles(count, _) => {
+                ensure!(count > 0, "Empty DelChars or DelStyles");
+                cursor.take(count, false)?;
+            }
+            DelWithGroup(ref inner) | DelGroup(ref inner) => {
+                let group = cursor.take_group()?;
+                validate_del_span(&mut SpanCursor::new(group), inner)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn validate_add_span(cursor: &mut SpanCursor, span: &AddSpan) -> Result<(), Error> {
+    for elem in span {
+        match *elem {
+            AddSkip(count) => {
+                ensure!(count > 0, "Empty AddSkip");
+                cursor.take(count, true)?;
+            }
+            AddStyles(count, _) => {
+                ensure!(count > 0, "Empty AddStyles");
+                cursor.take(count, false)?;
+            }
+            AddChars(ref text) => {
+                validate_op_text(text)?;
+            }
+            AddWithGroup(ref inner) => {
+                let group = cursor.take_group()?;
+                validate_add_span(&mut SpanCursor::new(group), inner)?;
+            }
+            AddGroup(ref attrs, ref inner) => {
+                validate_op_attrs(attrs)?;
+                // An added group wraps the elements that follow it.
+                validate_add_span(cursor, inner)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks that an operation fits the document it is applied to: every skip,
+/// deletion, and restyle lands on content that exists, and every inserted
+/// group and string is well-formed. An operation that passes this check can
+/// be applied to `doc` without panicking, though the result should still be
+/// checked with `validate_doc`.
+pub fn validate_op(doc: &Doc, op: &Op) -> Result<(), Error> {
+    let &(ref del, ref add) = op;
+    validate_del_span(&mut SpanCursor::new(&doc.0), del)?;
+    let postdel = apply_delete(&doc.0, del);
+    validate_add_span(&mut SpanCursor::new(&postdel), add)?;
+    Ok(())
+}
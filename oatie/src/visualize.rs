@@ -0,0 +1,265 @@
+//! Renders a transform as an HTML page: a document, two concurrent
+//! operations on it, both of their transforms, and the documents each path
+//! leads to, side by side, so it's clear where the paths diverge.
+//!
+//! Panics while transforming or applying are caught and shown in place of
+//! their results, since those are the cases worth looking at.
+
+use super::doc::*;
+use super::transform::Schema;
+use super::OT;
+use std::panic::{
+    catch_unwind,
+    AssertUnwindSafe,
+};
+
+const STYLE: &str = r#"
+body { font-family: sans-serif; margin: 2em; color: #222; }
+h1 { font-size: 1.4em; }
+h2 { font-size: 1em; margin: 0 0 0.5em; }
+.paths { display: grid; grid-template-columns: 1fr 1fr; grid-gap: 1em; }
+.panel { border: 1px solid #ccc; border-radius: 4px; padding: 0.8em; overflow-x: auto; }
+.wide { grid-column: 1 / 3; }
+.group { display: inline-block; border: 1px solid #99b; border-radius: 3px; margin: 2px; padding: 2px 4px; vertical-align: top; }
+.group > .tag { font-size: 0.75em; color: #557; display: block; }
+.group.block { display: block; }
+.chars { background: #f3f3f3; white-space: pre; font-family: monospace; }
+.styles { font-size: 0.7em; color: #a60; }
+.el { display: inline-block; margin: 1px; padding: 0 3px; border-radius: 3px; font-family: monospace; font-size: 0.85em; }
+.skip { background: #eee; color: #666; }
+.del { background: #fdd; color: #900; }
+.add { background: #dfd; color: #060; }
+.style { background: #ffd; color: #960; }
+.nest { border: 1px dashed #aaa; }
+.error { color: #c00; font-weight: bold; white-space: pre-wrap; }
+.same { color: #080; font-weight: bold; }
+.differ { color: #c00; font-weight: bold; }
+"#;
+
+fn escape(input: &str) -> String {
+    input
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn format_attrs(attrs: &Attrs) -> String {
+    let mut attrs = attrs
+        .iter()
+        .map(|(key, value)| format!("{}={:?}", key, value))
+        .collect::<Vec<_>>();
+    attrs.sort();
+    escape(&attrs.join(" "))
+}
+
+fn format_styles(styles: &StyleMap) -> String {
+    let mut styles = styles
+        .iter()
+        .filter(|(style, _)| **style != Style::Normie)
+        .map(|(style, value)| match value {
+            Some(value) => format!("{}={:?}", style, value),
+            None => style.to_string(),
+        })
+        .collect::<Vec<_>>();
+    styles.sort();
+    escape(&styles.join(" "))
+}
+
+fn format_style_set(styles: &StyleSet) -> String {
+    let mut styles = styles.iter().map(|style| style.to_string()).collect::<Vec<_>>();
+    styles.sort();
+    escape(&styles.join(" "))
+}
+
+fn text_html(text: &DocString) -> String {
+    let styles = text
+        .styles()
+        .map(|styles| format_styles(&styles))
+        .unwrap_or_default();
+    format!(
+        r#"<span class="chars">{}</span><span class="styles">{}</span>"#,
+        escape(text.as_str()),
+        styles,
+    )
+}
+
+fn doc_html(span: &DocSpan) -> String {
+    span.iter()
+        .map(|elem| match elem {
+            DocChars(text) => text_html(text),
+            DocGroup(attrs, inner) => {
+                let tag = attrs.get("tag").map(|x| x.as_str()).unwrap_or("");
+                let block = match tag {
+                    "caret" | "mention" | "math" | "doclink" | "img" => "",
+                    _ => " block",
+                };
+                format!(
+                    r#"<div class="group{}"><span class="tag">{}</span>{}</div>"#,
+                    block,
+                    format_attrs(attrs),
+                    doc_html(inner),
+                )
+            }
+        })
+        .collect()
+}
+
+fn del_html(span: &DelSpan) -> String {
+    span.iter()
+        .map(|elem| match elem {
+            DelSkip(n) => format!(r#"<span class="el skip">skip {}</span>"#, n),
+            DelChars(n) => format!(r#"<span class="el del">chars {}</span>"#, n),
+            DelStyles(n, styles) => format!(
+                r#"<span class="el style">unstyle {} {}</span>"#,
+                n,
+                format_style_set(styles),
+            ),
+            DelWithGroup(inner) => format!(
+                r#"<span class="el skip nest">with [{}]</span>"#,
+                del_html(inner),
+            ),
+            DelGroup(inner) => format!(
+                r#"<span class="el del nest">group [{}]</span>"#,
+                del_html(inner),
+            ),
+        })
+        .collect()
+}
+
+fn add_html(span: &AddSpan) -> String {
+    span.iter()
+        .map(|elem| match elem {
+            AddSkip(n) => format!(r#"<span class="el skip">skip {}</span>"#, n),
+            AddChars(text) => format!(r#"<span class="el add">{}</span>"#, text_html(text)),
+            AddStyles(n, styles) => format!(
+                r#"<span class="el style">style {} {}</span>"#,
+                n,
+                format_styles(styles),
+            ),
+            AddWithGroup(inner) => format!(
+                r#"<span class="el skip nest">with [{}]</span>"#,
+                add_html(inner),
+            ),
+            AddGroup(attrs, inner) => format!(
+                r#"<span class="el add nest">{} [{}]</span>"#,
+                format_attrs(attrs),
+                add_html(inner),
+            ),
+        })
+        .collect()
+}
+
+fn op_html(op: &Op) -> String {
+    format!(
+        "<div>del: {}</div><div>add: {}</div>",
+        del_html(&op.0),
+        add_html(&op.1),
+    )
+}
+
+fn panel(title: &str, body: &str, class: &str) -> String {
+    format!(
+        r#"<div class="panel {}"><h2>{}</h2>{}</div>"#,
+        class,
+        escape(title),
+        body,
+    )
+}
+
+fn error_html(err: &str) -> String {
+    format!(r#"<div class="error">{}</div>"#, escape(err))
+}
+
+/// Run a closure, turning a panic into an error with the panic's message.
+fn attempt<T, F: FnOnce() -> T>(f: F) -> Result<T, String> {
+    catch_unwind(AssertUnwindSafe(f)).map_err(|err| {
+        err.downcast_ref::<String>()
+            .cloned()
+            .or_else(|| err.downcast_ref::<&str>().map(|x| x.to_string()))
+            .unwrap_or_else(|| "panicked".to_string())
+    })
+}
+
+fn result_html<T, F: Fn(&T) -> String>(result: &Result<T, String>, render: F) -> String {
+    match result {
+        Ok(value) => render(value),
+        Err(err) => error_html(err),
+    }
+}
+
+fn convergence_html<T: PartialEq>(a: &Result<T, String>, b: &Result<T, String>) -> String {
+    match (a, b) {
+        (Ok(a), Ok(b)) if a == b => r#"<span class="same">identical</span>"#.to_string(),
+        (Ok(_), Ok(_)) => r#"<span class="differ">different</span>"#.to_string(),
+        _ => r#"<span class="differ">not computed</span>"#.to_string(),
+    }
+}
+
+/// An HTML page showing `a` and `b` transformed against each other, and
+/// applied to `doc` if given.
+pub fn transform_html<S: Schema>(doc: Option<&Doc>, a: &Op, b: &Op) -> String {
+    let mut panels = vec![];
+    panels.push(panel("a", &op_html(a), ""));
+    panels.push(panel("b", &op_html(b), ""));
+
+    // a' follows a, b' follows b, and both paths should compose to the same
+    // operation.
+    let transformed = attempt(|| Op::transform::<S>(a, b));
+    panels.push(panel(
+        "a' (b, after a)",
+        &result_html(&transformed, |(a_, _)| op_html(a_)),
+        "",
+    ));
+    panels.push(panel(
+        "b' (a, after b)",
+        &result_html(&transformed, |(_, b_)| op_html(b_)),
+        "",
+    ));
+
+    let composed_a = transformed
+        .clone()
+        .and_then(|(a_, _)| attempt(|| Op::compose(a, &a_)));
+    let composed_b = transformed
+        .clone()
+        .and_then(|(_, b_)| attempt(|| Op::compose(b, &b_)));
+    panels.push(panel("a : a'", &result_html(&composed_a, op_html), ""));
+    panels.push(panel("b : b'", &result_html(&composed_b, op_html), ""));
+    panels.push(panel(
+        "Composed operations",
+        &convergence_html(&composed_a, &composed_b),
+        "wide",
+    ));
+
+    if let Some(doc) = doc {
+        panels.push(panel("Document", &doc_html(&doc.0), "wide"));
+
+        let doc_a = attempt(|| Op::apply(doc, a));
+        let doc_b = attempt(|| Op::apply(doc, b));
+        panels.push(panel("doc : a", &result_html(&doc_a, |doc| doc_html(&doc.0)), ""));
+        panels.push(panel("doc : b", &result_html(&doc_b, |doc| doc_html(&doc.0)), ""));
+
+        let doc_a_ = match (&doc_a, &transformed) {
+            (Ok(doc_a), Ok((a_, _))) => attempt(|| Op::apply(doc_a, a_)),
+            (Err(err), _) | (_, Err(err)) => Err(err.clone()),
+        };
+        let doc_b_ = match (&doc_b, &transformed) {
+            (Ok(doc_b), Ok((_, b_))) => attempt(|| Op::apply(doc_b, b_)),
+            (Err(err), _) | (_, Err(err)) => Err(err.clone()),
+        };
+        panels.push(panel("doc : a : a'", &result_html(&doc_a_, |doc| doc_html(&doc.0)), ""));
+        panels.push(panel("doc : b : b'", &result_html(&doc_b_, |doc| doc_html(&doc.0)), ""));
+        panels.push(panel(
+            "Converged documents",
+            &convergence_html(&doc_a_, &doc_b_),
+            "wide",
+        ));
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Transform</title><style>{}</style></head>\
+         <body><h1>Transform</h1><div class=\"paths\">{}</div></body></html>\n",
+        STYLE,
+        panels.concat(),
+    )
+}
@@ -0,0 +1,212 @@
+#[macro_use]
+extern crate oatie;
+extern crate serde_json;
+
+use oatie::diff::{
+    diff,
+    untouched_edges,
+};
+use oatie::doc::*;
+use oatie::prelude::invert;
+use oatie::schema::RtfSchema;
+use oatie::validate::validate_op;
+use oatie::*;
+
+fn paragraphs(texts: &[&str]) -> Doc {
+    Doc(texts
+        .iter()
+        .flat_map(|text| doc_span![DocGroup({"tag": "p"}, [DocChars(text)])])
+        .collect())
+}
+
+// Documents compared along with the styles of their text, which their own
+// equality ignores.
+fn styled(doc: &Doc) -> serde_json::Value {
+    serde_json::to_value(doc).unwrap()
+}
+
+fn assert_diff(a: &Doc, b: &Doc) -> Op {
+    let op = diff(a, b);
+    assert!(validate_op(a, &op).is_ok());
+    assert_eq!(styled(&Op::apply(a, &op)), styled(b));
+    op
+}
+
+#[test]
+fn diff_identical_is_empty() {
+    let doc = paragraphs(&["one", "two"]);
+    assert_eq!(diff(&doc, &doc), Op::empty());
+}
+
+#[test]
+fn diff_text_edit_touches_only_its_block() {
+    let a = paragraphs(&["hello", "world"]);
+    let b = paragraphs(&["help", "world"]);
+    assert_eq!(
+        assert_diff(&a, &b),
+        op_span!(
+            [DelWithGroup([DelSkip(3), DelChars(2)])],
+            [AddWithGroup([AddSkip(3), AddChars("p")])],
+        )
+    );
+}
+
+#[test]
+fn diff_inserted_and_removed_blocks() {
+    let a = paragraphs(&["one", "two", "three"]);
+    let b = paragraphs(&["one", "new", "two", "three"]);
+    assert_eq!(
+        assert_diff(&a, &b),
+        op_span!([], [AddSkip(1), AddGroup({"tag": "p"}, [AddChars("new")])])
+    );
+
+    assert_diff(&b, &a);
+    assert_diff(&a, &paragraphs(&[]));
+    assert_diff(&paragraphs(&[]), &a);
+}
+
+#[test]
+fn diff_changed_attributes_replace_the_block() {
+    let a = paragraphs(&["title", "body"]);
+    let b = Doc(doc_span![
+        DocGroup({"tag": "h1"}, [DocChars("title")]),
+        DocGroup({"tag": "p"}, [DocChars("body")]),
+    ]);
+    assert_eq!(
+        assert_diff(&a, &b),
+        op_span!(
+            [DelGroup([DelChars(5)])],
+            [AddGroup({"tag": "h1"}, [AddChars("title")])],
+        )
+    );
+}
+
+#[test]
+fn diff_nested_groups() {
+    let a = Doc(doc_span![
+        DocGroup({"tag": "bullet"}, [
+            DocGroup({"tag": "p"}, [DocChars("item")]),
+        ]),
+    ]);
+    let b = Doc(doc_span![
+        DocGroup({"tag": "bullet"}, [
+            DocGroup({"tag": "p"}, [DocChars("items")]),
+            DocGroup({"tag": "p"}, [DocChars("more")]),
+        ]),
+    ]);
+    assert_diff(&a, &b);
+    assert_diff(&b, &a);
+}
+
+#[test]
+fn diffs_merge_three_ways() {
+    let ancestor = paragraphs(&["one", "two", "three"]);
+    let ours = paragraphs(&["one a", "two", "three"]);
+    let theirs = paragraphs(&["one", "two", "three b", "four"]);
+
+    let ours_op = diff(&ancestor, &ours);
+    let theirs_op = diff(&ancestor, &theirs);
+    let (after_ours, after_theirs) = Op::transform::<RtfSchema>(&ours_op, &theirs_op);
+
+    let merged = Op::apply(&ours, &after_ours);
+    assert_eq!(merged, paragraphs(&["one a", "two", "three b", "four"]));
+    assert_eq!(Op::apply(&theirs, &after_theirs), merged);
+}
+
+#[test]
+fn diff_keeps_blocks_after_a_changed_one() {
+    let a = paragraphs(&["one", "two", "three"]);
+    let b = Doc(doc_span![
+        DocGroup({"tag": "p"}, [DocChars("one a")]),
+        DocGroup({"tag": "h1"}, [DocChars("two")]),
+        DocGroup({"tag": "p"}, [DocChars("three a")]),
+    ]);
+    assert_eq!(
+        assert_diff(&a, &b),
+        op_span!(
+            [DelSkip(1), DelGroup([DelChars(3)])],
+            [
+                AddWithGroup([AddSkip(3), AddChars(" a")]),
+                AddGroup({"tag": "h1"}, [AddChars("two")]),
+                AddWithGroup([AddSkip(5), AddChars(" a")]),
+            ],
+        )
+    );
+    assert_diff(&b, &a);
+}
+
+#[test]
+fn diffs_merge_around_a_changed_block() {
+    let ancestor = paragraphs(&["one", "two", "three"]);
+    let ours = Doc(doc_span![
+        DocGroup({"tag": "p"}, [DocChars("one a")]),
+        DocGroup({"tag": "h1"}, [DocChars("two")]),
+        DocGroup({"tag": "p"}, [DocChars("three a")]),
+    ]);
+    let theirs = paragraphs(&["b one", "two", "b three"]);
+
+    let ours_op = diff(&ancestor, &ours);
+    let theirs_op = diff(&ancestor, &theirs);
+    let (after_ours, after_theirs) = Op::transform::<RtfSchema>(&ours_op, &theirs_op);
+
+    let merged = Op::apply(&ours, &after_ours);
+    assert_eq!(
+        merged,
+        Doc(doc_span![
+            DocGroup({"tag": "p"}, [DocChars("b one a")]),
+            DocGroup({"tag": "h1"}, [DocChars("two")]),
+            DocGroup({"tag": "p"}, [DocChars("b three a")]),
+        ])
+    );
+    assert_eq!(Op::apply(&theirs, &after_theirs), merged);
+}
+
+#[test]
+fn diff_restyled_text_inside_a_block() {
+    let a = paragraphs(&["one", "two"]);
+    let b = Doc(doc_span![
+        DocGroup({"tag": "p"}, [
+            DocChars("o"),
+            DocChars("ne", {Style::Normie => None, Style::Bold => None}),
+        ]),
+        DocGroup({"tag": "p"}, [DocChars("two")]),
+    ]);
+    let op = assert_diff(&a, &b);
+    assert_ne!(op, Op::empty());
+    assert_diff(&b, &a);
+}
+
+#[test]
+fn invert_undoes_an_operation() {
+    let doc = paragraphs(&["one", "two"]);
+    let ops = vec![
+        op_span!([], [AddWithGroup([AddSkip(1), AddChars("x")])]),
+        op_span!([DelSkip(1), DelGroup([DelChars(3)])], []),
+        op_span!(
+            [DelWithGroup([DelChars(3)])],
+            [AddWithGroup([AddChars("one", {Style::Normie => None, Style::Italic => None})])],
+        ),
+    ];
+    for op in ops {
+        let applied = Op::apply(&doc, &op);
+        let undo = invert(&doc, &op);
+        assert!(validate_op(&applied, &undo).is_ok());
+        assert_eq!(styled(&Op::apply(&applied, &undo)), styled(&doc));
+    }
+}
+
+#[test]
+fn invert_compares_only_what_changed() {
+    let doc = paragraphs(&["one", "two", "three", "four"]);
+    let op = op_span!(
+        [DelSkip(1), DelWithGroup([DelChars(1)])],
+        [AddSkip(2), AddGroup({"tag": "p"}, [AddChars("new")])],
+    );
+    let applied = Op::apply(&doc, &op);
+    assert_eq!(applied, paragraphs(&["one", "wo", "new", "three", "four"]));
+    assert_eq!(untouched_edges(&doc, &applied, &op), (1, 2));
+
+    let undo = invert(&doc, &op);
+    assert_eq!(Op::apply(&applied, &undo), doc);
+    assert_eq!(untouched_edges(&doc, &doc, &Op::empty()), (0, 4));
+}
@@ -0,0 +1,91 @@
+//! Styles shared between the text of a document and the operations on it.
+
+#[macro_use]
+extern crate oatie;
+
+use oatie::doc::*;
+use oatie::intern::*;
+use oatie::OT;
+use std::sync::Arc;
+
+fn styles_of(span: &DocSpan) -> Vec<Arc<StyleMap>> {
+    let mut out = vec![];
+    for elem in span {
+        match elem {
+            DocChars(text) => out.extend(text.styles()),
+            DocGroup(_, inner) => out.extend(styles_of(inner)),
+        }
+    }
+    out
+}
+
+fn doc() -> Doc {
+    Doc(doc_span![
+        DocGroup({"tag": "p"}, [
+            DocChars("one", {Style::Normie => None, Style::Bold => None}),
+            DocChars(" two "),
+        ]),
+        DocGroup({"tag": "p"}, [
+            DocChars("three", {Style::Normie => None, Style::Bold => None}),
+        ]),
+    ])
+}
+
+#[test]
+fn intern_doc_shares_styles() {
+    let mut interner = Interner::new();
+    let interned = intern_doc(&doc(), &mut interner);
+    assert_eq!(interned, doc());
+    assert_eq!(interner.len(), 1);
+
+    let styles = styles_of(&interned.0);
+    assert_eq!(styles.len(), 2);
+    assert!(Arc::ptr_eq(&styles[0], &styles[1]));
+
+    // Without interning, each run has its own copy.
+    let styles = styles_of(&doc().0);
+    assert!(!Arc::ptr_eq(&styles[0], &styles[1]));
+}
+
+#[test]
+fn intern_op_shares_the_documents_styles() {
+    let mut interner = Interner::new();
+    let interned = intern_doc(&doc(), &mut interner);
+    let op = op_span!([], [
+        AddWithGroup([
+            AddSkip(8),
+            AddChars("new", {Style::Normie => None, Style::Bold => None}),
+        ]),
+    ]);
+    let op = intern_op(&op, &mut interner);
+    assert_eq!(interner.len(), 1);
+
+    let applied = Op::apply(&interned, &op);
+    let styles = styles_of(&applied.0);
+    assert_eq!(styles.len(), 3);
+    assert!(Arc::ptr_eq(&styles[0], &styles[1]));
+    assert!(Arc::ptr_eq(&styles[1], &styles[2]));
+}
+
+#[test]
+fn prune_forgets_unused_styles() {
+    let mut interner = Interner::new();
+    let interned = intern_doc(&doc(), &mut interner);
+    let op = op_span!([], [
+        AddWithGroup([AddChars("new", {Style::Italic => None})]),
+    ]);
+    let op = intern_op(&op, &mut interner);
+    assert_eq!(interner.len(), 2);
+
+    // The italic styles are still held by the operation.
+    interner.prune();
+    assert_eq!(interner.len(), 2);
+
+    drop(op);
+    interner.prune();
+    assert_eq!(interner.len(), 1);
+
+    drop(interned);
+    interner.prune();
+    assert!(interner.is_empty());
+}
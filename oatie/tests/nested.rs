@@ -0,0 +1,125 @@
+//! Every pair of concurrent wrap, unwrap, split, and typing operations on
+//! paragraphs nested in lists and quotes must transform into operations
+//! that converge on the same valid document.
+
+extern crate oatie;
+
+use oatie::doc::*;
+use oatie::schema::RtfSchema;
+use oatie::validate::validate_doc;
+use oatie::OT;
+use std::collections::HashMap;
+
+const CONTAINERS: &[&str] = &["bullet", "blockquote"];
+
+fn attrs(tag: &str) -> Attrs {
+    let mut attrs = HashMap::new();
+    attrs.insert("tag".to_string(), tag.to_string());
+    attrs
+}
+
+fn paragraph(text: &str) -> DocElement {
+    DocGroup(attrs("p"), vec![DocChars(DocString::from_str(text))])
+}
+
+// Every chain of containers up to two deep, outermost first.
+fn chains() -> Vec<Vec<&'static str>> {
+    let mut chains = vec![vec![]];
+    for outer in CONTAINERS {
+        chains.push(vec![*outer]);
+        for inner in CONTAINERS {
+            chains.push(vec![*outer, *inner]);
+        }
+    }
+    chains
+}
+
+// Two paragraphs inside the chain of containers.
+fn nested_doc(chain: &[&str]) -> Doc {
+    let mut span = vec![paragraph("ab"), paragraph("cd")];
+    for tag in chain.iter().rev() {
+        span = vec![DocGroup(attrs(tag), span)];
+    }
+    Doc(span)
+}
+
+// An operation on the contents of the container `depth` levels down.
+fn at_depth(depth: usize, del: DelSpan, add: AddSpan) -> Op {
+    let (mut del, mut add) = (del, add);
+    for _ in 0..depth {
+        del = vec![DelWithGroup(del)];
+        add = vec![AddWithGroup(add)];
+    }
+    (del, add)
+}
+
+// The operations to try on a document nested in `chain`, with their names.
+fn nested_ops(chain: &[&str]) -> Vec<(String, Op)> {
+    let depth = chain.len();
+    let mut ops = vec![];
+
+    // Wrap the first paragraph in each kind of container.
+    for tag in CONTAINERS {
+        ops.push((
+            format!("wrap in {}", tag),
+            at_depth(depth, vec![], vec![AddGroup(attrs(tag), vec![AddSkip(1)])]),
+        ));
+    }
+
+    // Unwrap the innermost container.
+    if depth > 0 {
+        ops.push((
+            format!("unwrap {}", chain[depth - 1]),
+            at_depth(depth - 1, vec![DelGroup(vec![DelSkip(2)])], vec![]),
+        ));
+    }
+
+    // Split the first paragraph.
+    ops.push((
+        "split".to_string(),
+        at_depth(
+            depth,
+            vec![DelGroup(vec![DelSkip(2)])],
+            vec![
+                AddGroup(attrs("p"), vec![AddSkip(1)]),
+                AddGroup(attrs("p"), vec![AddSkip(1)]),
+            ],
+        ),
+    ));
+
+    // Type into the first paragraph.
+    ops.push((
+        "type".to_string(),
+        at_depth(
+            depth,
+            vec![],
+            vec![AddWithGroup(vec![AddSkip(1), AddChars(DocString::from_str("x"))])],
+        ),
+    ));
+
+    ops
+}
+
+#[test]
+fn nested_transforms_converge() {
+    for chain in chains() {
+        let doc = nested_doc(&chain);
+        validate_doc(&doc).unwrap();
+
+        let ops = nested_ops(&chain);
+        for &(ref a_name, ref a) in &ops {
+            for &(ref b_name, ref b) in &ops {
+                let context = format!("{:?}: {} against {}", chain, a_name, b_name);
+
+                let (a_, b_) = Op::transform::<RtfSchema>(a, b);
+                let doc_a = Op::apply(&Op::apply(&doc, a), &a_);
+                let doc_b = Op::apply(&Op::apply(&doc, b), &b_);
+
+                assert_eq!(doc_a, doc_b, "diverged in {}", context);
+                if let Err(err) = validate_doc(&doc_a) {
+                    panic!("invalid document in {}: {:?}\n{:?}", context, err, doc_a);
+                }
+            }
+        }
+    }
+}
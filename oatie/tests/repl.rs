@@ -0,0 +1,64 @@
+extern crate oatie;
+
+use oatie::repl::Repl;
+
+fn run(repl: &mut Repl, line: &str) -> String {
+    repl.run(line).unwrap()
+}
+
+#[test]
+fn apply_and_undo() {
+    let mut repl = Repl::new();
+    assert_eq!(run(&mut repl, r#"doc <p>("abc")"#), "<p>(\"abc\")\n");
+    assert_eq!(run(&mut repl, r#"apply | w("x")"#), "<p>(\"xabc\")\n");
+
+    run(&mut repl, "let del = w(d1)");
+    assert_eq!(run(&mut repl, "apply del"), "<p>(\"abc\")\n");
+
+    assert_eq!(run(&mut repl, "undo"), "<p>(\"xabc\")\n");
+    assert_eq!(run(&mut repl, "undo"), "<p>(\"abc\")\n");
+    assert!(repl.run("undo").is_err());
+}
+
+#[test]
+fn apply_refuses_invalid_ops() {
+    let mut repl = Repl::new();
+    run(&mut repl, r#"doc <p>("abc")"#);
+    let err = repl.run("apply w(d5)").unwrap_err();
+    assert!(err.starts_with("can't apply"), err);
+    assert_eq!(run(&mut repl, "show"), "<p>(\"abc\")\n");
+}
+
+#[test]
+fn compose_and_transform() {
+    let mut repl = Repl::new();
+    run(&mut repl, r#"doc <p>("abc")"#);
+    run(&mut repl, r#"let a = | w("x")"#);
+    run(&mut repl, r#"let b = | w(s3 "y")"#);
+
+    assert!(run(&mut repl, "compose a b").starts_with("_ = "));
+    assert_eq!(run(&mut repl, "apply _"), "<p>(\"xabyc\")\n");
+    run(&mut repl, "undo");
+
+    let output = run(&mut repl, "transform a b");
+    assert!(output.contains("converge"), output);
+
+    // The results are saved under primed names.
+    let ops = run(&mut repl, "ops");
+    assert!(ops.contains("a' = "), ops);
+    assert!(ops.contains("b' = "), ops);
+    run(&mut repl, "apply a");
+    assert_eq!(run(&mut repl, "apply a'"), "<p>(\"xabcy\")\n");
+}
+
+#[test]
+fn reports_bad_input() {
+    let mut repl = Repl::new();
+    assert!(repl.run("frobnicate").is_err());
+    assert!(repl.run("let = s1").is_err());
+    assert!(repl.run("let a b = s1").is_err());
+    assert!(repl.run("compose a").is_err());
+    assert!(repl.run("show missing").is_err());
+    assert!(repl.run("doc <p>(").is_err());
+    assert!(run(&mut repl, "help").contains("transform"));
+}
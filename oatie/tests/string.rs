@@ -0,0 +1,157 @@
+//! DocStrings and the views into them.
+
+#[macro_use]
+extern crate oatie;
+extern crate serde_json;
+
+use oatie::doc::*;
+
+fn styled(text: &str, style: Style) -> DocString {
+    DocString::from_str_styled(text, style_map! { style => None })
+}
+
+fn texts(strings: &[DocString]) -> Vec<&str> {
+    strings.iter().map(|string| string.as_str()).collect()
+}
+
+#[test]
+fn divide_and_seek() {
+    let mut divided = DividedString::new(styled("hello", Style::Bold), 2);
+    assert_eq!(divided.left().as_str(), "he");
+    assert_eq!(divided.right().as_str(), "llo");
+
+    // Dividing at either end leaves one side empty.
+    assert!(divided.seek(5));
+    assert_eq!(divided.left().as_str(), "hello");
+    assert_eq!(divided.right().as_str(), "");
+    assert!(divided.seek(0));
+    assert_eq!(divided.offset(), 0);
+    assert_eq!(divided.right().as_str(), "hello");
+}
+
+#[test]
+fn divide_multibyte() {
+    let divided = DividedString::new(DocString::from_str("añb😀c"), 4);
+    assert_eq!(divided.left().as_str(), "añb😀");
+    assert_eq!(divided.right().as_str(), "c");
+}
+
+#[test]
+fn update_sides_with_the_same_styles() {
+    let mut divided = DividedString::new(styled("hello", Style::Bold), 2);
+    divided.update_left(styled("HE", Style::Bold));
+    divided.update_right(styled("LLO!", Style::Bold));
+    assert_eq!(divided.offset(), 2);
+    let strings = divided.into_strings();
+    assert_eq!(texts(&strings), vec!["HELLO!"]);
+    assert!(strings[0].same_styles(&styled("", Style::Bold)));
+}
+
+#[test]
+fn update_right_keeps_its_styles() {
+    let mut divided = DividedString::new(styled("hello", Style::Bold), 2);
+    divided.update_right(styled("LLO", Style::Italic));
+    assert_eq!(divided.left().as_str(), "he");
+    assert!(divided.left().same_styles(&styled("", Style::Bold)));
+    assert_eq!(divided.right().as_str(), "LLO");
+    assert!(divided.right().same_styles(&styled("", Style::Italic)));
+
+    // Text can't cross between differently styled sides.
+    assert!(!divided.seek(4));
+    assert_eq!(divided.offset(), 2);
+
+    let strings = divided.into_strings();
+    assert_eq!(texts(&strings), vec!["he", "LLO"]);
+    assert!(strings[1].same_styles(&styled("", Style::Italic)));
+}
+
+#[test]
+fn update_left_keeps_both_styles() {
+    let mut divided = DividedString::new(styled("hello", Style::Bold), 2);
+    divided.update_left(styled("abc", Style::Italic));
+    assert_eq!(divided.offset(), 3);
+    assert!(divided.right().same_styles(&styled("", Style::Bold)));
+    assert_eq!(texts(&divided.into_strings()), vec!["abc", "llo"]);
+
+    // An empty side with other styles is left out.
+    let mut divided = DividedString::new(styled("hello", Style::Bold), 5);
+    divided.update_right(styled("", Style::Italic));
+    assert_eq!(texts(&divided.into_strings()), vec!["hello"]);
+}
+
+#[test]
+#[should_panic]
+fn divide_past_the_end() {
+    DividedString::new(DocString::from_str("abc"), 4);
+}
+
+// JSON of `count` strings of `len` characters, each styled bold if `bold`.
+fn strings_json(count: usize, len: usize, bold: bool) -> (Vec<DocString>, String) {
+    let strings = (0..count)
+        .map(|n| {
+            let text = format!("{:0width$}", n, width = len);
+            if bold {
+                styled(&text, Style::Bold)
+            } else {
+                DocString::from_string(text)
+            }
+        })
+        .collect::<Vec<_>>();
+    let json = serde_json::to_string(&strings).unwrap();
+    (strings, json)
+}
+
+#[test]
+fn json_shared_refers_into_large_inputs() {
+    for &bold in &[false, true] {
+        let (strings, json) = strings_json(300, 300, bold);
+        assert!(json.len() > 64 * 1024);
+        let shared: Vec<DocString> = from_json_shared(json).unwrap();
+        assert_eq!(shared, strings);
+        assert!(shared.iter().all(|string| string.is_view()));
+        if bold {
+            assert!(shared[0].same_styles(&styled("", Style::Bold)));
+        }
+    }
+}
+
+#[test]
+fn json_shared_copies_small_inputs() {
+    let (strings, json) = strings_json(10, 300, false);
+    let shared: Vec<DocString> = from_json_shared(json).unwrap();
+    assert_eq!(shared, strings);
+    assert!(shared.iter().all(|string| !string.is_view()));
+}
+
+#[test]
+fn json_shared_copies_short_and_escaped_strings() {
+    let (mut strings, _) = strings_json(300, 300, false);
+    strings.push(DocString::from_str("short"));
+    strings.push(DocString::from_string(format!("{}\n\"quoted\"", "x".repeat(300))));
+    let json = serde_json::to_string(&strings).unwrap();
+
+    let shared: Vec<DocString> = from_json_shared(json).unwrap();
+    assert_eq!(shared, strings);
+    assert!(shared[0].is_view());
+    assert!(!shared[300].is_view());
+    assert!(!shared[301].is_view());
+    assert!(shared[301].as_str().ends_with("\n\"quoted\""));
+
+    // Changing a shared string gives it text of its own.
+    let mut changed = shared[0].clone();
+    changed.push_str("!");
+    assert!(!changed.is_view());
+}
+
+#[test]
+fn json_shared_reports_errors() {
+    let (_, json) = strings_json(300, 300, false);
+    let broken = json[..json.len() - 1].to_string();
+    assert!(from_json_shared::<Vec<DocString>>(broken).is_err());
+
+    // Strings deserialized afterward don't refer into the broken input.
+    let (strings, json) = strings_json(1, 300, false);
+    let copied: Vec<DocString> = serde_json::from_str(&json).unwrap();
+    assert_eq!(copied, strings);
+    assert!(!copied[0].is_view());
+}
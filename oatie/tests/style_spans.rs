@@ -0,0 +1,112 @@
+//! Styles kept as intervals over a block's contents.
+
+#[macro_use]
+extern crate oatie;
+
+use oatie::doc::*;
+use oatie::style_spans::*;
+
+fn link(url: &str) -> Option<String> {
+    Some(url.to_string())
+}
+
+#[test]
+fn add_joins_touching_intervals() {
+    let mut spans = StyleSpans::new();
+    spans.add(0..3, Style::Bold, None);
+    spans.add(3..6, Style::Bold, None);
+    assert_eq!(
+        spans.runs(0..8),
+        vec![
+            (0..6, style_map! { Style::Bold => None }),
+            (6..8, StyleMap::new()),
+        ]
+    );
+
+    // Adding a style to touching ranges is the same as adding it to both.
+    let mut whole = StyleSpans::new();
+    whole.add(0..6, Style::Bold, None);
+    assert_eq!(spans, whole);
+}
+
+#[test]
+fn add_replaces_values() {
+    let mut spans = StyleSpans::new();
+    spans.add(0..6, Style::Link, link("a"));
+    spans.add(2..4, Style::Link, link("b"));
+    assert_eq!(spans.styles_at(1).get(&Style::Link), Some(&link("a")));
+    assert_eq!(spans.styles_at(2).get(&Style::Link), Some(&link("b")));
+    assert_eq!(spans.styles_at(4).get(&Style::Link), Some(&link("a")));
+    assert_eq!(spans.styles_at(6).get(&Style::Link), None);
+}
+
+#[test]
+fn remove_trims_intervals() {
+    let mut spans = StyleSpans::new();
+    spans.add(0..6, Style::Italic, None);
+    spans.remove(2..4, &Style::Italic);
+    assert_eq!(
+        spans.runs(0..6),
+        vec![
+            (0..2, style_map! { Style::Italic => None }),
+            (2..4, StyleMap::new()),
+            (4..6, style_map! { Style::Italic => None }),
+        ]
+    );
+
+    // Removing every interval leaves no trace of the style.
+    spans.remove(0..6, &Style::Italic);
+    assert_eq!(spans, StyleSpans::new());
+}
+
+#[test]
+fn covers_touching_intervals_with_different_values() {
+    let mut spans = StyleSpans::new();
+    spans.add(0..3, Style::Link, link("a"));
+    spans.add(3..6, Style::Link, link("b"));
+    assert!(spans.covers(0..6, &Style::Link));
+    assert!(spans.covers(1..5, &Style::Link));
+    assert!(!spans.covers(1..7, &Style::Link));
+    assert!(!spans.covers(0..2, &Style::Bold));
+
+    // A gap between intervals isn't covered.
+    spans.add(7..9, Style::Link, link("c"));
+    assert!(!spans.covers(5..8, &Style::Link));
+    assert!(!spans.covers(6..8, &Style::Link));
+}
+
+#[test]
+fn toggle_adds_then_removes() {
+    let mut spans = StyleSpans::new();
+    spans.add(0..2, Style::Bold, None);
+    spans.toggle(0..4, Style::Bold, None);
+    assert!(spans.covers(0..4, &Style::Bold));
+    spans.toggle(0..4, Style::Bold, None);
+    assert_eq!(spans, StyleSpans::new());
+}
+
+#[test]
+fn styled_span_roundtrip() {
+    let span = doc_span![
+        DocChars("plain "),
+        DocChars("bold", {Style::Bold => None}),
+        DocGroup({"tag": "caret", "client": "a"}, []),
+        DocChars(" more", {Style::Bold => None}),
+    ];
+    let styled = StyledSpan::from_span(&span);
+    assert_eq!(styled.len(), 16);
+    assert_eq!(styled.span.len(), 3);
+    assert!(styled.styles.covers(6..10, &Style::Bold));
+    // The caret between the bold runs isn't bold.
+    assert!(!styled.styles.covers(6..16, &Style::Bold));
+
+    let out = styled.to_span();
+    assert_eq!(out, span);
+    match out[1] {
+        DocChars(ref text) => assert!(text.same_styles(&DocString::from_str_styled(
+            "",
+            style_map! { Style::Bold => None },
+        ))),
+        ref other => panic!("expected bold text, got {:?}", other),
+    }
+}
@@ -0,0 +1,105 @@
+#[macro_use]
+extern crate maplit;
+#[macro_use]
+extern crate oatie;
+
+use oatie::doc::*;
+use oatie::terse::*;
+
+fn bold() -> StyleMap {
+    let mut styles = StyleMap::new();
+    styles.insert(Style::Bold, None);
+    styles
+}
+
+#[test]
+fn parse_op_example() {
+    let op = parse_op(r#"s2 d3 w(s1) | s2 "new" <p>("text") w(s1 "x"{Bold})"#).unwrap();
+    assert_eq!(
+        op,
+        op_span!(
+            [DelSkip(2), DelChars(3), DelWithGroup([DelSkip(1)])],
+            [
+                AddSkip(2),
+                AddChars("new"),
+                AddGroup({"tag": "p"}, [AddChars("text")]),
+                AddWithGroup([AddSkip(1), AddChars("x")]),
+            ],
+        )
+    );
+
+    // Text compares without its styles, so check them on their own.
+    match op.1[3] {
+        AddWithGroup(ref inner) => match inner[1] {
+            AddChars(ref text) => assert_eq!(text.styles().map(|x| (*x).clone()), Some(bold())),
+            ref other => panic!("expected text, got {:?}", other),
+        },
+        ref other => panic!("expected a group, got {:?}", other),
+    }
+}
+
+#[test]
+fn parse_op_halves() {
+    assert_eq!(parse_op("s1 d2").unwrap(), op_span!([DelSkip(1), DelChars(2)], []));
+    assert_eq!(parse_op(r#"| "x""#).unwrap(), op_span!([], [AddChars("x")]));
+    assert_eq!(parse_op("").unwrap(), Op::empty());
+}
+
+#[test]
+fn format_op_roundtrip() {
+    let mut link = bold();
+    link.insert(Style::Link, Some("http://example.com/a b".to_string()));
+    let op: Op = (
+        vec![
+            DelSkip(1),
+            DelGroup(vec![DelChars(2)]),
+            DelStyles(3, vec![Style::Bold, Style::Italic].into_iter().collect()),
+        ],
+        vec![
+            AddChars(DocString::from_string_styled("say \"hi\"\n".to_string(), link)),
+            AddGroup(
+                hashmap! {
+                    "tag".to_string() => "h1".to_string(),
+                    "class".to_string() => "two words".to_string(),
+                },
+                vec![AddChars(DocString::from_str("title"))],
+            ),
+            AddStyles(4, bold()),
+        ],
+    );
+
+    let text = format_op(&op);
+    assert_eq!(
+        text,
+        r#"s1 g(d2) u3{Bold,Italic} | "say \"hi\"\n"{Bold,Link="http://example.com/a b"} <h1 class="two words">("title") t4{Bold}"#
+    );
+    let parsed = parse_op(&text).unwrap();
+    assert_eq!(parsed, op);
+    assert_eq!(format_op(&parsed), text);
+}
+
+#[test]
+fn parse_doc_roundtrip() {
+    let text = r#"<p>("one" "two"{Italic}) <bullet>(<p>("three"))"#;
+    let doc = parse_doc(text).unwrap();
+    assert_eq!(
+        doc.0,
+        doc_span![
+            DocGroup({"tag": "p"}, [DocChars("one"), DocChars("two")]),
+            DocGroup({"tag": "bullet"}, [DocGroup({"tag": "p"}, [DocChars("three")])]),
+        ]
+    );
+    assert_eq!(format_doc(&doc.0), text);
+}
+
+#[test]
+fn parse_errors() {
+    // Documents hold only text and groups.
+    assert!(parse_doc(r#"<p>(s1 "x")"#).is_err());
+    assert!(parse_op(r#"| "unterminated"#).is_err());
+    assert!(parse_op(r#"| "x"{Underline}"#).is_err());
+    assert!(parse_op("w(s1").is_err());
+    assert!(parse_op("s1 | s1 )").is_err());
+    assert!(parse_op("x1").is_err());
+    assert!(parse_op("s").is_err());
+}
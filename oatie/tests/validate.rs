@@ -0,0 +1,62 @@
+#![allow(unused_imports)]
+
+#[macro_use]
+extern crate oatie;
+
+use oatie::doc::*;
+use oatie::validate::*;
+use oatie::*;
+
+fn test_doc() -> Doc {
+    Doc(doc_span![
+        DocGroup({"tag": "h1"}, [
+            DocChars("Hello"),
+        ]),
+        DocGroup({"tag": "p"}, [
+            DocChars("world"),
+        ]),
+    ])
+}
+
+#[test]
+fn test_validate_op_accepts_fitting_ops() {
+    let doc = test_doc();
+
+    let op = op_span!(
+        [DelWithGroup([DelSkip(1), DelChars(2)])],
+        [AddWithGroup([AddSkip(1), AddChars("ey")])],
+    );
+    assert!(validate_op(&doc, &op).is_ok());
+    assert!(validate_doc(&Op::apply(&doc, &op)).is_ok());
+
+    let op = op_span!(
+        [],
+        [AddSkip(1), AddGroup({"tag": "p"}, [AddChars("new")])],
+    );
+    assert!(validate_op(&doc, &op).is_ok());
+}
+
+#[test]
+fn test_validate_op_rejects_malformed_ops() {
+    let doc = test_doc();
+
+    // Skips past the end of the document.
+    let op = op_span!([DelSkip(3)], []);
+    assert!(validate_op(&doc, &op).is_err());
+
+    // Deletes characters inside a group that doesn't have enough.
+    let op = op_span!([DelWithGroup([DelChars(10)])], []);
+    assert!(validate_op(&doc, &op).is_err());
+
+    // Deletes chars where a group is.
+    let op = op_span!([DelChars(1)], []);
+    assert!(validate_op(&doc, &op).is_err());
+
+    // Adds a group with an unknown tag.
+    let op = op_span!([], [AddGroup({"tag": "marquee"}, [AddSkip(1)])]);
+    assert!(validate_op(&doc, &op).is_err());
+
+    // Adds a string containing a NUL character.
+    let op = op_span!([], [AddWithGroup([AddChars("\u{0}")])]);
+    assert!(validate_op(&doc, &op).is_err());
+}
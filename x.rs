@@ -57,6 +57,10 @@ enum Cli {
     Wasm {
         #[structopt(name = "no-vendor")]
         no_vendor: bool,
+
+        /// Build the smaller production bundle, without logging or debugging.
+        #[structopt(long = "minimal")]
+        minimal: bool,
     },
 
     #[structopt(name = "wasm-watch", about = "Watch the WebAssembly bundle.")]
@@ -74,6 +78,13 @@ enum Cli {
     #[structopt(name = "oatie-build", about = "Build the operational transform library.")]
     OatieBuild { args: Vec<String> },
 
+    #[structopt(name = "oatie-wasm-build", about = "Compile oatie's standalone WebAssembly module.")]
+    OatieWasmBuild {
+        /// Where wasm-bindgen writes the module and its JavaScript bindings.
+        #[structopt(long = "out-dir", default_value = "./dist/oatie-wasm")]
+        out_dir: String,
+    },
+
     #[structopt(name = "server", about = "Run the edit-text server.")]
     MercutioServerRun {
         #[structopt(long = "log", help = "Export a log")]
@@ -160,9 +171,16 @@ fn run() -> Result<(), Error> {
             )?;
         },
 
-        Cli::Wasm { no_vendor } => {
+        Cli::Wasm { no_vendor, minimal } => {
             // wasm must always be --release
             let release_flag = Some("--release");
+            let features_flag = if minimal {
+                // Abort on panic and optimize for size.
+                ::std::env::set_var("RUSTFLAGS", "-C panic=abort -C opt-level=z");
+                Some("--features=minimal")
+            } else {
+                None
+            };
 
             execute!(
                 "
@@ -174,9 +192,10 @@ fn run() -> Result<(), Error> {
             execute!(
                 r"
                     cd edit-client
-                    cargo build {release_flag} --lib --target wasm32-unknown-unknown
+                    cargo build {release_flag} {features_flag} --lib --target wasm32-unknown-unknown
                 ",
                 release_flag = release_flag,
+                features_flag = features_flag,
             )?;
 
             if !no_vendor {
@@ -249,6 +268,33 @@ fn run() -> Result<(), Error> {
             )?;
         }
 
+        Cli::OatieWasmBuild { out_dir } => {
+            execute!(
+                "
+                    rustup target add wasm32-unknown-unknown
+                "
+            )?;
+
+            eprintln!("Building...");
+            execute!(
+                r"
+                    cd oatie-wasm
+                    cargo build --release --lib --target wasm32-unknown-unknown
+                ",
+            )?;
+
+            ::std::fs::create_dir_all(&out_dir)?;
+            execute!(
+                r"
+                    wasm-bindgen ./target/wasm32-unknown-unknown/release/oatie_wasm.wasm \
+                        --out-dir {out_dir} \
+                        --typescript
+                ",
+                out_dir = out_dir,
+            )?;
+            eprintln!("Done.");
+        }
+
         Cli::MercutioServerRun { log, args } => {
             if release {
                 eprintln!("Building and running edit-text server (release mode)...");